@@ -22,6 +22,15 @@ struct VersionRange {
     serialized_type: String,
 }
 
+impl VersionRange {
+    /// The type the field is deserialized as in this range, which may be generic, such as `Vec<bool>`
+    fn parse_serialized_type(&self) -> syn::Type {
+        syn::parse_str(&self.serialized_type).unwrap_or_else(|_| {
+            panic!("savefile_versions_as must name a type, not {:?}", self.serialized_type)
+        })
+    }
+}
+
 #[derive(Debug)]
 struct AttrsResult {
     version_from: u32,
@@ -32,6 +41,7 @@ struct AttrsResult {
     deserialize_types: Vec<VersionRange>,
     introspect_key: bool,
     introspect_ignore: bool,
+    packed: bool,
 }
 
 fn check_is_remove(field_type: &syn::Type) -> bool {
@@ -40,7 +50,8 @@ fn check_is_remove(field_type: &syn::Type) -> bool {
     let mut tokens = TokenStream::new();
     field_type.to_tokens(&mut tokens);
     for tok in tokens.into_iter() {
-        if tok.to_string() == "Removed" {
+        let tok = tok.to_string();
+        if tok == "Removed" || tok == "RemovedOpaque" {
             //TODO: This is not robust, since it's based on text matching
             is_remove = true;
         }
@@ -79,6 +90,7 @@ fn parse_attr_tag2(attrs: &Vec<syn::Attribute>, is_string_default_val: bool) ->
     let mut ignore = false;
     let mut introspect_ignore = false;
     let mut introspect_key = false;
+    let mut packed = false;
     let mut deser_types = Vec::new();
     for attr in attrs.iter() {
         if let Some(ref meta) = attr.interpret_meta() {
@@ -93,6 +105,9 @@ fn parse_attr_tag2(attrs: &Vec<syn::Attribute>, is_string_default_val: bool) ->
                     if x.to_string() == "savefile_introspect_ignore" {
                         introspect_ignore = true;
                     }
+                    if x.to_string() == "savefile_packed" {
+                        packed = true;
+                    }
                 }
                 &syn::Meta::List(ref _x) => {}
                 &syn::Meta::NameValue(ref x) => {
@@ -243,6 +258,9 @@ fn parse_attr_tag2(attrs: &Vec<syn::Attribute>, is_string_default_val: bool) ->
             panic!("The version ranges of #version_as attributes must be lower than those of the #savefile_versions attribute.");
         }
     }
+    if packed && (field_from_version.is_some() || field_to_version.is_some() || !deser_types.is_empty()) {
+        panic!("The savefile_packed attribute can't be combined with savefile_versions or savefile_versions_as");
+    }
 
     AttrsResult {
         version_from: field_from_version.unwrap_or(0),
@@ -253,6 +271,33 @@ fn parse_attr_tag2(attrs: &Vec<syn::Attribute>, is_string_default_val: bool) ->
         deserialize_types: deser_types,
         introspect_key,
         introspect_ignore,
+        packed,
+    }
+}
+
+/// The number of bytes a field of the given type is serialized as, if it is a primitive type
+/// of fixed size, such as u32 or f64. Runs of such fields are written together. The types are
+/// recognized by their names, so types named like primitive types are not supported.
+/// The generated code is placed where the fields may be bound to variables named by the user,
+/// so its own variables have reserved names.
+fn fixed_primitive_size(field_type: &syn::Type) -> Option<usize> {
+    let path = match field_type {
+        syn::Type::Path(type_path) if type_path.qself.is_none() => &type_path.path,
+        _ => return None,
+    };
+    if path.leading_colon.is_some() || path.segments.len() != 1 {
+        return None;
+    }
+    let segment = &path.segments[0];
+    if !matches!(segment.arguments, syn::PathArguments::None) {
+        return None;
+    }
+    match segment.ident.to_string().as_str() {
+        "bool" | "u8" | "i8" => Some(1),
+        "u16" | "i16" => Some(2),
+        "u32" | "i32" | "f32" => Some(4),
+        "u64" | "i64" | "f64" => Some(8),
+        _ => None,
     }
 }
 
@@ -266,6 +311,7 @@ fn implement_fields_serialize<'a>(
     field_infos: Vec<FieldInfo<'a>>,
     implicit_self: bool,
     index: bool,
+    unnamed: bool,
 ) -> (TokenStream, Vec<TokenStream>) {
     let mut min_safe_version = 0;
     let mut output = Vec::new();
@@ -274,13 +320,81 @@ fn implement_fields_serialize<'a>(
     let span = proc_macro2::Span::call_site();
     let local_serializer = quote_spanned! { defspan => local_serializer};
     let mut index_number = 0;
-    for ref field in &field_infos {
+    // Consecutive bit-packed fields are written together, when the next field is not bit-packed
+    let mut packed_run = Vec::new();
+    let flush_packed_run = |packed_run: &mut Vec<TokenStream>, output: &mut Vec<TokenStream>| {
+        if !packed_run.is_empty() {
+            let pushes = packed_run.iter();
+            output.push(quote! {
+                {
+                    let mut __savefile_packed_bits = _savefile::prelude::PackedBits::new();
+                    #(#pushes)*
+                    #local_serializer.write_packed(&__savefile_packed_bits)?;
+                }
+            });
+            packed_run.clear();
+        }
+    };
+    // Consecutive primitive fields of fixed size are copied to a buffer, which is written in
+    // one go, instead of making a write for each of them. The run is entered as one field,
+    // named after its first field, just before the buffer is written, so that progress is
+    // reported, and a failed write attributed, as for a single field.
+    let mut burst: Vec<(TokenStream, &syn::Type, String, usize)> = Vec::new();
+    let flush_burst = |burst: &mut Vec<(TokenStream, &syn::Type, String, usize)>, output: &mut Vec<TokenStream>| {
+        if burst.len() == 1 {
+            let (objid, _, path_segment, _) = &burst[0];
+            output.push(quote!(
+                #local_serializer.enter_field(#path_segment)?;
+                <_ as _savefile::prelude::Serialize>::serialize(#objid, #local_serializer)?;
+                #local_serializer.leave_field();
+            ));
+        } else if !burst.is_empty() {
+            let total: usize = burst.iter().map(|(_, _, _, size)| size).sum();
+            let first_segment = &burst[0].2;
+            let mut offset = 0;
+            let mut copies = Vec::new();
+            for (objid, ty, _, size) in burst.iter() {
+                let (start, end) = (offset, offset + size);
+                copies.push(if *size == 1 {
+                    quote!( __savefile_burst[#start] = *#objid as u8; )
+                } else {
+                    quote!(
+                        __savefile_burst[#start..#end].copy_from_slice(&if __savefile_big_endian {
+                            <#ty>::to_be_bytes(*#objid)
+                        } else {
+                            <#ty>::to_le_bytes(*#objid)
+                        });
+                    )
+                });
+                offset = end;
+            }
+            output.push(quote! {
+                {
+                    #[allow(unused_variables)]
+                    let __savefile_big_endian = #local_serializer.encoding.endianness == _savefile::prelude::Endianness::Big;
+                    let mut __savefile_burst = [0u8; #total];
+                    #(#copies)*
+                    #local_serializer.enter_field(#first_segment)?;
+                    #local_serializer.write_buf(&__savefile_burst)?;
+                    #local_serializer.leave_field();
+                }
+            });
+        }
+        burst.clear();
+    };
+    for (field_index, ref field) in field_infos.iter().enumerate() {
         {
             let verinfo = parse_attr_tag(&field.attrs, &field.ty);
 
             if verinfo.ignore {
                 continue;
             }
+            let path_segment = if unnamed {
+                field_index.to_string()
+            } else {
+                field.ident.clone().unwrap().to_string()
+            };
+            let with_path = quote! { .map_err(|err| err.with_validation_path_segment(#path_segment)) };
             let (field_from_version, field_to_version) = (verinfo.version_from, verinfo.version_to);
 
             let removed = check_is_remove(&field.ty);
@@ -302,6 +416,21 @@ fn implement_fields_serialize<'a>(
                 }
             };
 
+            if verinfo.packed {
+                flush_burst(&mut burst, &mut output);
+                packed_run.push(quote!(
+                    __savefile_packed_bits.push(#objid, #local_serializer.version)?;
+                ));
+                continue;
+            }
+            flush_packed_run(&mut packed_run, &mut output);
+            if field_from_version == 0 && field_to_version == u32::MAX {
+                if let Some(size) = fixed_primitive_size(field.ty) {
+                    burst.push((objid, field.ty, path_segment, size));
+                    continue;
+                }
+            }
+            flush_burst(&mut burst, &mut output);
             if field_from_version == 0 && field_to_version == std::u32::MAX {
                 if removed {
                     panic!(
@@ -309,7 +438,9 @@ fn implement_fields_serialize<'a>(
                     );
                 }
                 output.push(quote!(
-                <_ as _savefile::prelude::Serialize>::serialize(#objid, #local_serializer)?;
+                #local_serializer.enter_field(#path_segment)?;
+                <_ as _savefile::prelude::Serialize>::serialize(#objid, #local_serializer)#with_path?;
+                #local_serializer.leave_field();
                 ));
             } else {
                 if field_to_version < std::u32::MAX {
@@ -321,11 +452,15 @@ fn implement_fields_serialize<'a>(
                 }
                 output.push(quote!(
                 if #local_serializer.version >= #field_from_version && #local_serializer.version <= #field_to_version {
-                    <_ as _savefile::prelude::Serialize>::serialize(#objid, #local_serializer)?;
+                    #local_serializer.enter_field(#path_segment)?;
+                    <_ as _savefile::prelude::Serialize>::serialize(#objid, #local_serializer)#with_path?;
+                    #local_serializer.leave_field();
                 }));
             }
         }
     }
+    flush_packed_run(&mut packed_run, &mut output);
+    flush_burst(&mut burst, &mut output);
     let serialize2 = quote! {
         let local_serializer = serializer;
         if #min_safe_version > local_serializer.version {
@@ -348,6 +483,21 @@ fn implement_fields_serialize<'a>(
 
 fn savefile_derive_crate_serialize(input: DeriveInput) -> TokenStream {
     let name = input.ident;
+    let validate = implement_validate(&input.attrs, quote! { self });
+    // The fields of types in a namespace are serialized with the version of the namespace
+    let namespace = get_namespace(&input.attrs);
+    let in_namespace = |serialized: TokenStream| match namespace {
+        Some(ref namespace) => quote! {
+            _savefile::serialize_in_namespace(&#namespace, serializer, |serializer| {
+                #serialized
+                Ok(())
+            })
+        },
+        None => quote! {
+            #serialized
+            Ok(())
+        },
+    };
 
     let generics = input.generics;
 
@@ -370,19 +520,15 @@ fn savefile_derive_crate_serialize(input: DeriveInput) -> TokenStream {
     let saveerr = quote_spanned! {defspan=>
         Result<(),_savefile::prelude::SavefileError>
     };
-    let magic = format!("_IMPL_SAVEFILE_SERIALIZE_FOR_{}", &name).to_string();
-    let dummy_const = syn::Ident::new(&magic, proc_macro2::Span::call_site());
 
     let expanded = match &input.data {
         &syn::Data::Enum(ref enum1) => {
             let mut output = Vec::new();
-            let variant_count = enum1.variants.len();
-            if variant_count > 255 {
-                panic!("This library is not capable of serializing enums with more than 255 variants. Our deepest apologies, we thought no-one would ever create such an enum!");
-            }
+            let discriminators = enum_discriminators(&name, enum1, &input.attrs);
+            let write_discriminator = &discriminators.write;
 
             for (var_idx, ref variant) in enum1.variants.iter().enumerate() {
-                let var_idx = var_idx as u8;
+                let var_idx = &discriminators.variants[var_idx];
                 let var_ident = (variant.ident).clone();
                 let variant_name = quote! { #name::#var_ident };
                 let variant_name_spanned = quote_spanned! { span => &#variant_name};
@@ -398,9 +544,9 @@ fn savefile_derive_crate_serialize(input: DeriveInput) -> TokenStream {
                             })
                             .collect();
 
-                        let (fields_serialized, fields_names) = implement_fields_serialize(field_infos, false, false);
+                        let (fields_serialized, fields_names) = implement_fields_serialize(field_infos, false, false, false);
                         output.push(quote!( #variant_name_spanned{#(#fields_names,)*} => { 
-                                serializer.write_u8(#var_idx)?; 
+                                serializer.#write_discriminator(#var_idx)?; 
                                 #fields_serialized 
                             } ));
                     }
@@ -419,30 +565,33 @@ fn savefile_derive_crate_serialize(input: DeriveInput) -> TokenStream {
                             })
                             .collect();
 
-                        let (fields_serialized, fields_names) = implement_fields_serialize(field_infos, false, false);
+                        let (fields_serialized, fields_names) = implement_fields_serialize(field_infos, false, false, true);
 
                         output.push(
-                            quote!( #variant_name_spanned(#(#fields_names,)*) => { serializer.write_u8(#var_idx)?; #fields_serialized  } ),
+                            quote!( #variant_name_spanned(#(#fields_names,)*) => { serializer.#write_discriminator(#var_idx)?; #fields_serialized  } ),
                         );
                     }
                     &syn::Fields::Unit => {
-                        output.push(quote!( #variant_name_spanned => { serializer.write_u8(#var_idx)? } ));
+                        output.push(quote!( #variant_name_spanned => { serializer.#write_discriminator(#var_idx)? } ));
                     }
                 }
             }
+            let serialized = in_namespace(quote! {
+                match self {
+                    #(#output,)*
+                }
+            });
             quote! {
                 #[allow(non_upper_case_globals)]
-                const #dummy_const: () = {
+                const _: () = {
                     #uses
 
                     impl #impl_generics #serialize for #name #ty_generics #where_clause {
 
                         #[allow(unused_comparisons, unused_variables)]
-                        fn serialize(&self, serializer: &mut #serializer) -> #saveerr {
-                            match self {
-                                #(#output,)*
-                            }
-                            Ok(())
+                        fn serialize(&self, serializer: &mut #serializer<impl ::std::io::Write>) -> #saveerr {
+                            #validate
+                            #serialized
                         }
                     }
                 };
@@ -463,7 +612,7 @@ fn savefile_derive_crate_serialize(input: DeriveInput) -> TokenStream {
                         })
                         .collect();
 
-                    let t = implement_fields_serialize(field_infos, true, false);
+                    let t = implement_fields_serialize(field_infos, true, false, false);
                     fields_serialize = t.0;
                     _field_names = t.1;
                 }
@@ -478,7 +627,7 @@ fn savefile_derive_crate_serialize(input: DeriveInput) -> TokenStream {
                         })
                         .collect();
 
-                    let t = implement_fields_serialize(field_infos, true, true);
+                    let t = implement_fields_serialize(field_infos, true, true, true);
                     fields_serialize = t.0;
                     _field_names = t.1;
                 }
@@ -487,16 +636,17 @@ fn savefile_derive_crate_serialize(input: DeriveInput) -> TokenStream {
                     fields_serialize = quote! { {} };
                 }
             }
+            let serialized = in_namespace(quote! { #(#fields_serialize)* });
             quote! {
                 #[allow(non_upper_case_globals)]
-                const #dummy_const: () = {
+                const _: () = {
                     #uses
 
                     impl #impl_generics #serialize for #name #ty_generics #where_clause {
                         #[allow(unused_comparisons, unused_variables)]
-                        fn serialize(&self, serializer: &mut #serializer)  -> #saveerr {
-                            #(#fields_serialize)*
-                            Ok(())
+                        fn serialize(&self, serializer: &mut #serializer<impl ::std::io::Write>)  -> #saveerr {
+                            #validate
+                            #serialized
                         }
                     }
                 };
@@ -510,16 +660,96 @@ fn savefile_derive_crate_serialize(input: DeriveInput) -> TokenStream {
     expanded
 }
 
-fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
+/// Deserializes each field. If `borrowed_lifetime` is given, fields are deserialized
+/// using DeserializeBorrowed with that lifetime, instead of using Deserialize.
+/// Returns the declarations of local variables, which must precede the fields, and the fields.
+fn implement_deserialize(
+    field_infos: Vec<FieldInfo>,
+    borrowed_lifetime: Option<&syn::Lifetime>,
+) -> (Vec<TokenStream>, Vec<TokenStream>) {
     let span = proc_macro2::Span::call_site();
     let defspan = proc_macro2::Span::call_site();
-    let removeddef = quote_spanned! { defspan => _savefile::prelude::Removed };
     let local_deserializer = quote_spanned! { defspan => deserializer};
+    let deserialize_fn = match borrowed_lifetime {
+        Some(lifetime) => quote_spanned! { defspan => _savefile::prelude::DeserializeBorrowed<#lifetime>>::deserialize_borrowed },
+        None => quote_spanned! { defspan => _savefile::prelude::Deserialize>::deserialize },
+    };
+
+    // Each run of consecutive bit-packed fields is read when its first field is reached,
+    // into a local variable, from which the fields of the run are taken.
+    let mut declarations = Vec::new();
+    let mut packed_runs: Vec<Vec<&syn::Type>> = Vec::new();
+    let mut field_runs = Vec::new();
+    let mut in_run = false;
+    // Whether the current run is separated from the previous one by a field present in all versions.
+    // If it isn't, the runs would be adjacent in the schema of some version.
+    let mut separated = true;
+    for field in field_infos.iter() {
+        let verinfo = parse_attr_tag(field.attrs, field.ty);
+        if verinfo.ignore {
+            field_runs.push(None);
+        } else if verinfo.packed {
+            if !in_run {
+                if !separated {
+                    panic!("Bit-packed fields must not be separated only by fields which don't exist in all versions");
+                }
+                packed_runs.push(Vec::new());
+            }
+            in_run = true;
+            separated = packed_runs.is_empty();
+            packed_runs.last_mut().unwrap().push(field.ty);
+            field_runs.push(Some(packed_runs.len() - 1));
+        } else {
+            in_run = false;
+            if verinfo.version_from == 0 && verinfo.version_to == u32::MAX {
+                separated = true;
+            }
+            field_runs.push(None);
+        }
+    }
+    for run_index in 0..packed_runs.len() {
+        let run_var = syn::Ident::new(&format!("__savefile_packed_bits{}", run_index), span);
+        declarations.push(quote! { let mut #run_var: _savefile::prelude::PackedBits; });
+    }
+    let mut started_runs = Vec::new();
 
     let mut output = Vec::new();
     let mut min_safe_version = 0;
-    for ref field in &field_infos {
+    for (field_index, ref field) in field_infos.iter().enumerate() {
         let field_type = &field.ty;
+        let path_segment = match field.ident {
+            Some(ref id) => id.to_string(),
+            None => field_index.to_string(),
+        };
+        let with_path = quote! { .map_err(|err| err.with_path_segment(#path_segment)) };
+
+        if let Some(run_index) = field_runs[field_index] {
+            let run_var = syn::Ident::new(&format!("__savefile_packed_bits{}", run_index), span);
+            let read_run = if started_runs.contains(&run_index) {
+                quote! {}
+            } else {
+                started_runs.push(run_index);
+                let widths = packed_runs[run_index].iter().map(|run_type| {
+                    quote! { <#run_type as _savefile::prelude::BitPacked>::bit_width(#local_deserializer.file_version) }
+                });
+                quote! {
+                    #run_var = #local_deserializer.read_packed(0 #(+ #widths)*)#with_path?;
+                }
+            };
+            let src = quote_spanned! { span =>
+                {
+                    #read_run
+                    #run_var.pop::<#field_type>(#local_deserializer.file_version)#with_path?
+                }
+            };
+            if let Some(ref id) = field.ident {
+                let id_spanned = quote_spanned! { span => #id};
+                output.push(quote!(#id_spanned : #src ));
+            } else {
+                output.push(quote!( #src ));
+            }
+            continue;
+        }
 
         let is_removed = check_is_remove(field_type);
 
@@ -542,7 +772,7 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
         }
 
         let effective_default_val = if is_removed {
-            quote! { #removeddef::new() }
+            quote! { <#field_type>::new() }
         } else if let Some(defval) = default_val {
             quote! { str::parse(#defval).unwrap() }
         } else if let Some(default_fn) = default_fn {
@@ -563,7 +793,7 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
                 //TODO: Better message, tell user how to do this annotation
             };
             quote_spanned! { span =>
-                <#field_type as _savefile::prelude::Deserialize>::deserialize(#local_deserializer)?
+                <#field_type as #deserialize_fn(#local_deserializer)#with_path?
             }
         } else if verinfo.ignore {
             quote_spanned! { span =>
@@ -582,7 +812,7 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
             for dt in verinfo.deserialize_types.iter() {
                 let dt_from = dt.from;
                 let dt_to = dt.to;
-                let dt_field_type = syn::Ident::new(&dt.serialized_type, span);
+                let dt_field_type = dt.parse_serialized_type();
                 let dt_convert_fun = if dt.convert_fun.len() > 0 {
                     let dt_conv_fun = syn::Ident::new(&dt.convert_fun, span);
                     quote! { #dt_conv_fun }
@@ -592,7 +822,7 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
 
                 version_mappings.push(quote!{
                     if #local_deserializer.file_version >= #dt_from && #local_deserializer.file_version <= #dt_to {
-                        let temp : #dt_field_type = <#dt_field_type as _savefile::prelude::Deserialize>::deserialize(#local_deserializer)?;
+                        let temp : #dt_field_type = <#dt_field_type as #deserialize_fn(#local_deserializer)#with_path?;
                         #dt_convert_fun(temp)
                     } else 
                 });
@@ -601,12 +831,25 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
             quote_spanned! { span =>
                 #(#version_mappings)*
                 if #local_deserializer.file_version >= #field_from_version && #local_deserializer.file_version <= #field_to_version {
-                    <#field_type as _savefile::prelude::Deserialize>::deserialize(#local_deserializer)?
+                    <#field_type as #deserialize_fn(#local_deserializer)#with_path?
                 } else {
                     #effective_default_val
                 }
             }
         };
+        // Borrowed deserializers don't report progress
+        let src = if verinfo.ignore || borrowed_lifetime.is_some() {
+            src
+        } else {
+            quote_spanned! { span =>
+                {
+                    #local_deserializer.enter_field(#path_segment)?;
+                    let value = #src;
+                    #local_deserializer.leave_field();
+                    value
+                }
+            }
+        };
 
         if let Some(ref id) = field.ident {
             let id_spanned = quote_spanned! { span => #id};
@@ -615,7 +858,7 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
             output.push(quote!( #src ));
         }
     }
-    output
+    (declarations, output)
 }
 
 #[proc_macro_derive(
@@ -627,7 +870,14 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
         savefile_introspect_key,
         savefile_ignore,
         savefile_default_val,
-        savefile_default_fn
+        savefile_default_fn,
+        savefile_packed,
+        savefile_post_deserialize,
+        savefile_validate,
+        savefile_max_version,
+        savefile_enum_repr,
+        savefile_enum_as_int,
+        savefile_namespace
     )
 )]
 pub fn savefile(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -639,6 +889,10 @@ pub fn savefile(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 
     let w = savefile_derive_crate_withschema(input.clone());
 
+    let b = savefile_derive_crate_bitpacked(input.clone());
+
+    let v = implement_max_version(&input);
+
     let i = savefile_derive_crate_introspect(input);
 
     let expanded = quote! {
@@ -648,6 +902,10 @@ pub fn savefile(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 
         #w
 
+        #b
+
+        #v
+
         #i
     };
 
@@ -660,7 +918,9 @@ pub fn savefile(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
         savefile_versions_as,
         savefile_ignore,
         savefile_default_val,
-        savefile_default_fn
+        savefile_default_fn,
+        savefile_enum_repr,
+        savefile_enum_as_int
     )
 )]
 pub fn savefile_introspect_only(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -675,6 +935,214 @@ pub fn savefile_introspect_only(input: proc_macro::TokenStream) -> proc_macro::T
     expanded.into()
 }
 
+#[proc_macro_derive(
+    SavefileBorrowed,
+    attributes(
+        savefile_versions,
+        savefile_versions_as,
+        savefile_introspect_ignore,
+        savefile_introspect_key,
+        savefile_ignore,
+        savefile_default_val,
+        savefile_default_fn,
+        savefile_packed,
+        savefile_max_version
+    )
+)]
+pub fn savefile_borrowed(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input: DeriveInput = syn::parse(input).unwrap();
+
+    let s = savefile_derive_crate_serialize(input.clone());
+
+    let d = savefile_derive_crate_deserialize_borrowed(input.clone());
+
+    let w = savefile_derive_crate_withschema(input.clone());
+
+    let v = implement_max_version(&input);
+
+    let i = savefile_derive_crate_introspect(input);
+
+    let expanded = quote! {
+        #s
+
+        #d
+
+        #w
+
+        #v
+
+        #i
+    };
+
+    expanded.into()
+}
+
+/// Implements BitPacked for enums without fields, so that they can be used
+/// in bit-packed fields. For other types, nothing is generated.
+fn savefile_derive_crate_bitpacked(input: DeriveInput) -> TokenStream {
+    let enum1 = match input.data {
+        syn::Data::Enum(ref enum1) => enum1,
+        _ => return quote! {},
+    };
+    if enum1.variants.is_empty() || enum1.variants.iter().any(|variant| !matches!(variant.fields, syn::Fields::Unit)) {
+        return quote! {};
+    }
+    let name = input.ident;
+    let generics = input.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let span = proc_macro2::Span::call_site();
+    let defspan = proc_macro2::Span::call_site();
+    let bitpacked = quote_spanned! { defspan => _savefile::prelude::BitPacked };
+    let uses = quote_spanned! { defspan =>
+        extern crate savefile as _savefile;
+    };
+
+    let discriminators = enum_discriminators(&name, enum1, &input.attrs);
+    let version = local_version(&get_namespace(&input.attrs), quote! { version });
+    let mut from_versions = Vec::new();
+    let mut to_bits = Vec::new();
+    let mut from_bits = Vec::new();
+    let mut discriminator_names = Vec::new();
+    for (var_idx, variant) in enum1.variants.iter().enumerate() {
+        let discriminator = &discriminators.variants[var_idx];
+        let discriminator_name = syn::Ident::new(&format!("DISCRIMINATOR_{}", var_idx), Span::call_site());
+        let var_ident = variant.ident.clone();
+        let variant_name_spanned = quote_spanned! { span => #name::#var_ident };
+        let verinfo = parse_attr_tag2(&variant.attrs, false);
+        let field_from_version = verinfo.version_from;
+        from_versions.push(quote! { (#field_from_version, #discriminator as u32) });
+        to_bits.push(quote! { #variant_name_spanned => #discriminator as u64 });
+        from_bits.push(quote! { #discriminator_name => #variant_name_spanned });
+        discriminator_names.push(quote! { const #discriminator_name: u64 = #discriminator as u64; });
+    }
+
+    quote! {
+        #[allow(non_upper_case_globals)]
+        const _: () = {
+            #uses
+
+            impl #impl_generics #bitpacked for #name #ty_generics #where_clause {
+                #[allow(unused_comparisons)]
+                fn bit_width(version: u32) -> u32 {
+                    let version = #version;
+                    let max_discriminator = [#(#from_versions),*]
+                        .iter()
+                        .filter(|&&(from_version, _)| version >= from_version)
+                        .map(|&(_, discriminator)| discriminator)
+                        .max()
+                        .unwrap_or(0);
+                    32 - max_discriminator.leading_zeros()
+                }
+                fn to_bits(&self) -> u64 {
+                    match self {
+                        #(#to_bits,)*
+                    }
+                }
+                fn from_bits(bits: u64) -> Result<Self, _savefile::prelude::SavefileError> {
+                    #(#discriminator_names)*
+                    Ok(match bits {
+                        #(#from_bits,)*
+                        _ => {
+                            return Err(_savefile::prelude::SavefileError::GeneralError {
+                                msg: format!("Corrupt file - unknown enum variant {} detected in bit-packed {}.", bits, stringify!(#name)),
+                            });
+                        }
+                    })
+                }
+            }
+        };
+    }
+}
+
+fn savefile_derive_crate_deserialize_borrowed(input: DeriveInput) -> TokenStream {
+    let defspan = proc_macro2::Span::call_site();
+
+    let name = input.ident;
+
+    let generics = input.generics;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let lifetime = match generics.lifetimes().next() {
+        Some(lifetime_def) => lifetime_def.lifetime.clone(),
+        None => panic!("SavefileBorrowed can only be derived for structs with a lifetime parameter, like 'a, which borrowed fields use"),
+    };
+
+    let uses = quote_spanned! { defspan =>
+        extern crate savefile as _savefile;
+    };
+
+    let deserializer = quote_spanned! {defspan=>
+        _savefile::prelude::BorrowedDeserializer
+    };
+
+    let saveerr = quote_spanned! {defspan=>
+        _savefile::prelude::SavefileError
+    };
+
+
+    let output = match &input.data {
+        &syn::Data::Struct(ref struc) => match &struc.fields {
+            &syn::Fields::Named(ref namedfields) => {
+                let field_infos: Vec<FieldInfo> = namedfields
+                    .named
+                    .iter()
+                    .map(|field| FieldInfo {
+                        ident: Some(field.ident.clone().unwrap()),
+                        ty: &field.ty,
+                        attrs: &field.attrs,
+                    })
+                    .collect();
+
+                let (declarations, output1) = implement_deserialize(field_infos, Some(&lifetime));
+                quote! {
+                    #(#declarations)*
+                    Ok(#name {
+                        #(#output1,)*
+                    })
+                }
+            }
+            &syn::Fields::Unnamed(ref fields_unnamed) => {
+                let field_infos: Vec<FieldInfo> = fields_unnamed
+                    .unnamed
+                    .iter()
+                    .map(|field| FieldInfo {
+                        ident: None,
+                        ty: &field.ty,
+                        attrs: &field.attrs,
+                    })
+                    .collect();
+                let (declarations, output1) = implement_deserialize(field_infos, Some(&lifetime));
+
+                quote! {
+                    #(#declarations)*
+                    Ok(#name (
+                        #(#output1,)*
+                    ))
+                }
+            }
+            &syn::Fields::Unit => {
+                panic!("SavefileBorrowed can't be derived for unit structs, since they have nothing to borrow");
+            }
+        },
+        _ => {
+            panic!("SavefileBorrowed can only be derived for structs");
+        }
+    };
+
+    quote! {
+        #[allow(non_upper_case_globals)]
+        const _: () = {
+            #uses
+            impl #impl_generics _savefile::prelude::DeserializeBorrowed<#lifetime> for #name #ty_generics #where_clause {
+                #[allow(unused_comparisons, unused_variables)]
+                fn deserialize_borrowed(deserializer: &mut #deserializer<#lifetime>) -> Result<Self,#saveerr> {
+                    #output
+                }
+            }
+        };
+    }
+}
+
 fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
     let span = proc_macro2::Span::call_site();
     let defspan = proc_macro2::Span::call_site();
@@ -700,19 +1168,60 @@ fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
         _savefile::prelude::SavefileError
     };
 
-    let magic = format!("_IMPL_SAVEFILE_DESERIALIZE_FOR_{}", &name).to_string();
-    let dummy_const = syn::Ident::new(&magic, proc_macro2::Span::call_site());
+
+    let post_deserialize = has_post_deserialize(&input.attrs);
+    let validate = implement_validate(&input.attrs, quote! { &value });
+    let has_hooks = post_deserialize || !validate.is_empty();
+    let namespace = get_namespace(&input.attrs);
+    // With a post_deserialize hook or validation, the value is constructed first, and then
+    // passed to the hook, before being validated. The fields of types in a namespace are
+    // deserialized with the versions of the namespace.
+    let finish = |deserialized: TokenStream| {
+        let deserialized = if has_hooks {
+            let post = if post_deserialize {
+                quote! { _savefile::prelude::PostDeserialize::post_deserialize(&mut value, deserializer.file_version); }
+            } else {
+                quote! {}
+            };
+            quote_spanned! { defspan =>
+                let result: Result<Self, #saveerr> = { #deserialized };
+                let mut value = result?;
+                #post
+                #validate
+                Ok(value)
+            }
+        } else {
+            deserialized
+        };
+        match namespace {
+            Some(ref namespace) => quote! {
+                _savefile::deserialize_in_namespace(&#namespace, deserializer, |deserializer| {
+                    #deserialized
+                })
+            },
+            None => deserialized,
+        }
+    };
 
     let expanded = match &input.data {
         &syn::Data::Enum(ref enum1) => {
             let mut output = Vec::new();
-            let variant_count = enum1.variants.len();
-            if variant_count > 255 {
-                panic!("This library is not capable of deserializing enums with more than 255 variants. Our deepest apologies, we thought no-one would ever use more than 255 variants!");
-            }
+            let discriminators = enum_discriminators(&name, enum1, &input.attrs);
+            let read_discriminator = &discriminators.read;
+            let discriminator_type = &discriminators.ty;
+            // The discriminators are constants, so that they can be matched also when
+            // they are the values of the variants
+            let discriminator_names: Vec<_> = (0..enum1.variants.len())
+                .map(|var_idx| syn::Ident::new(&format!("DISCRIMINATOR_{}", var_idx), Span::call_site()))
+                .collect();
+            let discriminator_consts: Vec<_> = discriminator_names
+                .iter()
+                .zip(&discriminators.variants)
+                .map(|(name, value)| quote! { const #name: #discriminator_type = #value; })
+                .collect();
 
             for (var_idx, ref variant) in enum1.variants.iter().enumerate() {
-                let var_idx = var_idx as u8;
+                let var_idx = &discriminator_names[var_idx];
                 let var_ident = variant.ident.clone();
                 let variant_name = quote! { #name::#var_ident };
                 let variant_name_spanned = quote_spanned! { span => #variant_name};
@@ -728,9 +1237,12 @@ fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                             })
                             .collect();
 
-                        let fields_deserialized = implement_deserialize(field_infos);
+                        let (declarations, fields_deserialized) = implement_deserialize(field_infos, None);
 
-                        output.push(quote!( #var_idx => #variant_name_spanned{ #(#fields_deserialized,)* } ));
+                        output.push(quote!( #var_idx => {
+                            #(#declarations)*
+                            #variant_name_spanned{ #(#fields_deserialized,)* }
+                        } ));
                     }
                     &syn::Fields::Unnamed(ref fields_unnamed) => {
                         let field_infos: Vec<FieldInfo> = fields_unnamed
@@ -742,9 +1254,12 @@ fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                                 attrs: &field.attrs,
                             })
                             .collect();
-                        let fields_deserialized = implement_deserialize(field_infos);
+                        let (declarations, fields_deserialized) = implement_deserialize(field_infos, None);
 
-                        output.push(quote!( #var_idx => #variant_name_spanned( #(#fields_deserialized,)*) ));
+                        output.push(quote!( #var_idx => {
+                            #(#declarations)*
+                            #variant_name_spanned( #(#fields_deserialized,)*)
+                        } ));
                     }
                     &syn::Fields::Unit => {
                         output.push(quote!( #var_idx => #variant_name_spanned ));
@@ -752,24 +1267,34 @@ fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                 }
             }
 
+            let output = finish(quote! {
+                #(#discriminator_consts)*
+                Ok(match deserializer.#read_discriminator()? {
+                    #(#output,)*
+                    _ => return Err(_savefile::prelude::SavefileError::GeneralError{msg:format!("Corrupt file - unknown enum variant detected.")})
+                })
+            });
             quote! {
                 #[allow(non_upper_case_globals)]
-                const #dummy_const: () = {
+                const _: () = {
                     #uses
                     impl #impl_generics #deserialize for #name #ty_generics #where_clause {
                         #[allow(unused_comparisons, unused_variables)]
-                        fn deserialize(deserializer: &mut #deserializer) -> Result<Self,#saveerr> {
-
-                            Ok(match deserializer.read_u8()? {
-                                #(#output,)*
-                                _ => return Err(_savefile::prelude::SavefileError::GeneralError{msg:format!("Corrupt file - unknown enum variant detected.")})
-                            })
+                        fn deserialize(deserializer: &mut #deserializer<impl ::std::io::Read>) -> Result<Self,#saveerr> {
+                            #output
                         }
                     }
                 };
             }
         }
         &syn::Data::Struct(ref struc) => {
+            // The default deserialize_in_place calls deserialize, and thereby the hooks and
+            // the namespace
+            let in_place = if has_hooks || namespace.is_some() {
+                quote! {}
+            } else {
+                implement_deserialize_in_place(&struc.fields)
+            };
             let output = match &struc.fields {
                 &syn::Fields::Named(ref namedfields) => {
                     let field_infos: Vec<FieldInfo> = namedfields
@@ -782,10 +1307,13 @@ fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                         })
                         .collect();
 
-                    let output1 = implement_deserialize(field_infos);
-                    quote! {Ok(#name {
-                        #(#output1,)*
-                    })}
+                    let (declarations, output1) = implement_deserialize(field_infos, None);
+                    quote! {
+                        #(#declarations)*
+                        Ok(#name {
+                            #(#output1,)*
+                        })
+                    }
                 }
                 &syn::Fields::Unnamed(ref fields_unnamed) => {
                     let field_infos: Vec<FieldInfo> = fields_unnamed
@@ -797,29 +1325,34 @@ fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                             attrs: &field.attrs,
                         })
                         .collect();
-                    let output1 = implement_deserialize(field_infos);
+                    let (declarations, output1) = implement_deserialize(field_infos, None);
 
-                    quote! {Ok(#name (
-                        #(#output1,)*
-                    ))}
+                    quote! {
+                        #(#declarations)*
+                        Ok(#name (
+                            #(#output1,)*
+                        ))
+                    }
                 }
                 &syn::Fields::Unit => {
-                    let output1 = implement_deserialize(Vec::new());
+                    let (_, output1) = implement_deserialize(Vec::new(), None);
 
                     quote! {Ok(#name (
                         #(#output1,)*
                     ))}
                 } //_ => panic!("Only regular structs supported, not tuple structs."),
             };
+            let output = finish(output);
             quote! {
                 #[allow(non_upper_case_globals)]
-                const #dummy_const: () = {
+                const _: () = {
                         #uses
                         impl #impl_generics #deserialize for #name #ty_generics #where_clause {
                         #[allow(unused_comparisons, unused_variables)]
-                        fn deserialize(deserializer: &mut #deserializer) -> Result<Self,#saveerr> {
+                        fn deserialize(deserializer: &mut #deserializer<impl ::std::io::Read>) -> Result<Self,#saveerr> {
                             #output
                         }
+                        #in_place
                     }
                 };
             }
@@ -832,8 +1365,50 @@ fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
     expanded
 }
 
+/// Implements Deserialize::deserialize_in_place for a struct, deserializing each field in place.
+/// This is only done if no field has versions or is ignored, since such fields need the
+/// regular deserialize to handle them. Otherwise, the default implementation is used.
+fn implement_deserialize_in_place(fields: &syn::Fields) -> TokenStream {
+    let defspan = proc_macro2::Span::call_site();
+    let mut output = Vec::new();
+    for (field_index, field) in fields.iter().enumerate() {
+        let verinfo = parse_attr_tag(&field.attrs, &field.ty);
+        if verinfo.ignore || verinfo.packed || verinfo.version_from != 0 || verinfo.version_to != u32::MAX {
+            return quote! {};
+        }
+        let (member, path_segment) = match field.ident {
+            Some(ref id) => (quote! { #id }, id.to_string()),
+            None => {
+                let index = syn::Index::from(field_index);
+                (quote! { #index }, field_index.to_string())
+            }
+        };
+        output.push(quote_spanned! { defspan =>
+            _savefile::prelude::Deserialize::deserialize_in_place(&mut self.#member, deserializer)
+                .map_err(|err| err.with_path_segment(#path_segment))?;
+        });
+    }
+    if output.is_empty() {
+        return quote! {};
+    }
+    quote! {
+        fn deserialize_in_place(&mut self, deserializer: &mut _savefile::prelude::Deserializer<impl ::std::io::Read>) -> Result<(), _savefile::prelude::SavefileError> {
+            #(#output)*
+            Ok(())
+        }
+    }
+}
+
+/// Implements ReprC for a struct with the given fields, or for an enum without fields, given
+/// its variants, which are then the only valid values of the enum.
 #[allow(non_snake_case)]
-fn implement_reprc(field_infos: Vec<FieldInfo>, generics: syn::Generics, name: syn::Ident) -> TokenStream {
+fn implement_reprc(
+    field_infos: Vec<FieldInfo>,
+    enum_variants: Option<Vec<TokenStream>>,
+    generics: syn::Generics,
+    name: syn::Ident,
+    namespace: Option<syn::Path>,
+) -> TokenStream {
     let generics = generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     let span = proc_macro2::Span::call_site();
@@ -845,21 +1420,39 @@ fn implement_reprc(field_infos: Vec<FieldInfo>, generics: syn::Generics, name: s
     let WithSchema = quote_spanned! { defspan => _savefile::prelude::WithSchema};
     let mut min_safe_version = 0;
     let mut optsafe_outputs = Vec::new();
+    let mut validated_fields = Vec::new();
     let uses = quote_spanned! { defspan =>
         extern crate savefile as _savefile;
     };
-    let magic = format!("_IMPL_SAVEFILE_REPRC_FOR_{}", &name).to_string();
-    let dummy_const = syn::Ident::new(&magic, proc_macro2::Span::call_site());
+    let file_version = local_version(&namespace, quote! { file_version });
 
-    for ref field in &field_infos {
+    for (field_index, ref field) in field_infos.iter().enumerate() {
         let verinfo = parse_attr_tag(&field.attrs, &field.ty);
         if verinfo.ignore {
             panic!("The ReprC attribute cannot be derived for structures containing ignored fields");
         }
+        if verinfo.packed {
+            panic!("The ReprC attribute cannot be derived for structures containing bit-packed fields");
+        }
         let (field_from_version, field_to_version) = (verinfo.version_from, verinfo.version_to);
 
         let removed = check_is_remove(&field.ty);
         let field_type = &field.ty;
+        if !removed {
+            let member = match field.ident {
+                Some(ref ident) => quote! { #ident },
+                None => {
+                    let index = syn::Index::from(field_index);
+                    quote! { #index }
+                }
+            };
+            validated_fields.push(quote! {
+                if <#field_type as #reprc>::repr_c_needs_validation() {
+                    let offset = std::mem::offset_of!(Self, #member);
+                    <#field_type as #reprc>::repr_c_validate(&value[offset..offset + std::mem::size_of::<#field_type>()])?;
+                }
+            });
+        }
         if field_from_version == 0 && field_to_version == std::u32::MAX {
             if removed {
                 panic!("The Removed type can only be used for removed fields. Use the savefile_version attribute to mark a field as only existing in previous versions.");
@@ -880,15 +1473,50 @@ fn implement_reprc(field_infos: Vec<FieldInfo>, generics: syn::Generics, name: s
             }
         }
     }
+    let saveerr = quote_spanned! { defspan => _savefile::prelude::SavefileError };
+    let validation = match enum_variants {
+        Some(variants) => quote! {
+            fn repr_c_needs_validation() -> bool {
+                true
+            }
+            fn repr_c_validate(bytes: &[u8]) -> Result<(), #saveerr> {
+                _savefile::validate_repr_c_enum(bytes, &[#(#variants),*], stringify!(#name))
+            }
+        },
+        None if validated_fields.is_empty() => quote! {},
+        None => {
+            let field_needs_validation: Vec<_> = field_infos
+                .iter()
+                .filter(|field| !check_is_remove(field.ty))
+                .map(|field| {
+                    let field_type = field.ty;
+                    quote! { <#field_type as #reprc>::repr_c_needs_validation() }
+                })
+                .collect();
+            quote! {
+                fn repr_c_needs_validation() -> bool {
+                    false #(|| #field_needs_validation)*
+                }
+                fn repr_c_validate(bytes: &[u8]) -> Result<(), #saveerr> {
+                    // Each field of each value is validated like the field's type
+                    for value in bytes.chunks_exact(std::mem::size_of::<Self>().max(1)) {
+                        #(#validated_fields)*
+                    }
+                    Ok(())
+                }
+            }
+        }
+    };
     quote! {
 
         #[allow(non_upper_case_globals)]
-        const #dummy_const: () = {
+        const _: () = {
             extern crate std;
             #uses
             unsafe impl #impl_generics #reprc for #name #ty_generics #where_clause {
                 #[allow(unused_comparisons,unused_variables, unused_variables)]
                 fn repr_c_optimization_safe(file_version:u32) -> bool {
+                    let file_version = #file_version;
                     // The following is a debug_assert because it is slightly expensive, and the entire
                     // point of the ReprC trait is to speed things up.
                     if cfg!(debug_assertions) {
@@ -903,14 +1531,344 @@ fn implement_reprc(field_infos: Vec<FieldInfo>, generics: syn::Generics, name: s
                     file_version >= #min_safe_version
                     #( && #optsafe_outputs)*
                 }
+                #validation
             }
         };
     }
 }
 
+/// True if the type has the savefile_post_deserialize attribute
+fn has_post_deserialize(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| match attr.interpret_meta() {
+        Some(syn::Meta::Word(ref x)) => x == "savefile_post_deserialize",
+        _ => false,
+    })
+}
+
+/// The function given by the savefile_validate attribute of the type, if any
+fn get_validate_fn(attrs: &[syn::Attribute]) -> Option<syn::Path> {
+    for attr in attrs.iter() {
+        if let Some(syn::Meta::NameValue(ref x)) = attr.interpret_meta() {
+            if x.ident == "savefile_validate" {
+                let validate_fn = match x.lit {
+                    syn::Lit::Str(ref litstr) => litstr.value(),
+                    _ => panic!("Unexpected attribute value, please specify the savefile_validate function within quotes."),
+                };
+                return Some(syn::parse_str(&validate_fn).unwrap_or_else(|_| {
+                    panic!("savefile_validate must name a function, not {:?}", validate_fn)
+                }));
+            }
+        }
+    }
+    None
+}
+
+/// The VersionNamespace given by the savefile_namespace attribute of the type, if any
+fn get_namespace(attrs: &[syn::Attribute]) -> Option<syn::Path> {
+    for attr in attrs.iter() {
+        if let Some(syn::Meta::NameValue(ref x)) = attr.interpret_meta() {
+            if x.ident == "savefile_namespace" {
+                let namespace = match x.lit {
+                    syn::Lit::Str(ref litstr) => litstr.value(),
+                    _ => panic!("Unexpected attribute value, please specify the savefile_namespace constant within quotes."),
+                };
+                return Some(syn::parse_str(&namespace).unwrap_or_else(|_| {
+                    panic!("savefile_namespace must name a constant, not {:?}", namespace)
+                }));
+            }
+        }
+    }
+    None
+}
+
+/// The version the type is in, which is that of its namespace, if it has one
+fn local_version(namespace: &Option<syn::Path>, version: TokenStream) -> TokenStream {
+    match namespace {
+        Some(namespace) => quote! { #namespace.active_version() },
+        None => version,
+    }
+}
+
+/// The version given by the savefile_max_version attribute of the type, if any
+fn get_max_version(attrs: &[syn::Attribute]) -> Option<u32> {
+    for attr in attrs.iter() {
+        if let Some(syn::Meta::NameValue(ref x)) = attr.interpret_meta() {
+            if x.ident == "savefile_max_version" {
+                let version = match x.lit {
+                    syn::Lit::Str(ref litstr) => litstr.value(),
+                    syn::Lit::Int(ref litint) => litint.value().to_string(),
+                    _ => panic!("Unexpected attribute value, please specify the savefile_max_version as an integer, like #[savefile_max_version = 3]"),
+                };
+                return Some(version.trim().parse().unwrap_or_else(|_| {
+                    panic!("savefile_max_version must be an integer, not {:?}", version)
+                }));
+            }
+        }
+    }
+    None
+}
+
+/// The number of bytes of the discriminators of an enum, 1, 2 or 4, given by the
+/// savefile_enum_repr attribute. Enums without the attribute have u8 discriminators.
+fn get_enum_repr(attrs: &[syn::Attribute]) -> u8 {
+    for attr in attrs.iter() {
+        if let Some(syn::Meta::List(ref metalist)) = attr.interpret_meta() {
+            if metalist.ident == "savefile_enum_repr" {
+                let repr = match metalist.nested.iter().next() {
+                    Some(syn::NestedMeta::Meta(syn::Meta::Word(ref word))) if metalist.nested.len() == 1 => {
+                        word.to_string()
+                    }
+                    _ => panic!("Unexpected attribute value, please specify the savefile_enum_repr as u8, u16 or u32, like #[savefile_enum_repr(u16)]"),
+                };
+                return match repr.as_ref() {
+                    "u8" => 1,
+                    "u16" => 2,
+                    "u32" => 4,
+                    _ => panic!("savefile_enum_repr must be u8, u16 or u32, not {}", repr),
+                };
+            }
+        }
+    }
+    1
+}
+
+/// True if the enum has the savefile_enum_as_int attribute
+fn has_enum_as_int(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| match attr.interpret_meta() {
+        Some(syn::Meta::Word(ref x)) => x == "savefile_enum_as_int",
+        _ => false,
+    })
+}
+
+/// How the discriminators of an enum are saved
+struct EnumDiscriminators {
+    /// The number of bytes of each discriminator
+    size: u8,
+    /// The unsigned integer type of the discriminators
+    ty: syn::Ident,
+    /// The Serializer method writing a discriminator
+    write: syn::Ident,
+    /// The Deserializer method reading a discriminator
+    read: syn::Ident,
+    /// The discriminator of each variant, as an expression of type `ty`. It is the index of the
+    /// variant, or with the savefile_enum_as_int attribute, the value of the variant.
+    variants: Vec<TokenStream>,
+}
+
+/// The discriminators of the variants of an enum, given by its savefile_enum_repr or
+/// savefile_enum_as_int attribute. Panics if the enum has more variants than its
+/// discriminators can tell apart, or if the attributes are used incorrectly.
+fn enum_discriminators(name: &syn::Ident, enum1: &syn::DataEnum, attrs: &[syn::Attribute]) -> EnumDiscriminators {
+    let span = Span::call_site();
+    let as_int = has_enum_as_int(attrs);
+    let size = if as_int {
+        if attrs.iter().any(|attr| match attr.interpret_meta() {
+            Some(syn::Meta::List(ref metalist)) => metalist.ident == "savefile_enum_repr",
+            _ => false,
+        }) {
+            panic!("The savefile_enum_as_int attribute saves enums as their repr, so it can't be combined with savefile_enum_repr.");
+        }
+        if enum1.variants.iter().any(|variant| !matches!(variant.fields, syn::Fields::Unit)) {
+            panic!("The savefile_enum_as_int attribute can only be used on enums without fields.");
+        }
+        match get_enum_size(&attrs.to_vec()) {
+            Some(size) if size <= 4 => size as u8,
+            Some(_) => panic!("Enums with the savefile_enum_as_int attribute must have a repr of at most 32 bits, like #[repr(u32)]."),
+            None => panic!("Enums with the savefile_enum_as_int attribute must specify their size using the repr-attribute, like #[repr(u8)]."),
+        }
+    } else {
+        get_enum_repr(attrs)
+    };
+    let (max_variants, ty): (u64, &str) = match size {
+        1 => (255, "u8"),
+        2 => (65535, "u16"),
+        _ => (u32::MAX as u64, "u32"),
+    };
+    if enum1.variants.len() as u64 > max_variants {
+        panic!(
+            "Savefile does not support enums with more than {} variants with discriminators of {} bytes. Use a wider discriminator, like #[savefile_enum_repr(u32)].",
+            max_variants, size
+        );
+    }
+    let ty = syn::Ident::new(ty, span);
+    let variants = enum1
+        .variants
+        .iter()
+        .enumerate()
+        .map(|(var_idx, variant)| {
+            if as_int {
+                // Signed values are saved as the unsigned integer with the same bits
+                let repr = syn::Ident::new(&get_enum_repr_type(&attrs.to_vec()).unwrap(), span);
+                let var_ident = &variant.ident;
+                quote! { (#name::#var_ident as #repr as #ty) }
+            } else {
+                let literal = match size {
+                    1 => proc_macro2::Literal::u8_suffixed(var_idx as u8),
+                    2 => proc_macro2::Literal::u16_suffixed(var_idx as u16),
+                    _ => proc_macro2::Literal::u32_suffixed(var_idx as u32),
+                };
+                quote! { #literal }
+            }
+        })
+        .collect();
+    EnumDiscriminators {
+        size,
+        write: syn::Ident::new(&format!("write_{}", ty), span),
+        read: syn::Ident::new(&format!("read_{}", ty), span),
+        ty,
+        variants,
+    }
+}
+
+/// The lowest version which can be the current version of a field, given its version attributes.
+/// A field which has been removed was removed by the version after its last one.
+fn implied_version(verinfo: &AttrsResult) -> u32 {
+    if verinfo.version_to == u32::MAX {
+        verinfo.version_from
+    } else {
+        verinfo.version_from.max(verinfo.version_to + 1)
+    }
+}
+
+/// True if the tokens of a type contain the given type, or Self
+fn mentions_type(tokens: TokenStream, name: &syn::Ident) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(ref ident) => ident == name || ident == "Self",
+        proc_macro2::TokenTree::Group(ref group) => mentions_type(group.stream(), name),
+        _ => false,
+    })
+}
+
+/// Implements CurrentVersion, whose SAVEFILE_MAX_VERSION is the current version of the type.
+/// It is given by the savefile_max_version attribute, or else it is the lowest version which
+/// fits the version attributes of the fields and variants, and the current versions of the
+/// types of the fields which are still saved. Types in a namespace have version 0, since
+/// their versions are those of the namespace, not of the files they are saved in.
+fn implement_max_version(input: &DeriveInput) -> TokenStream {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let defspan = proc_macro2::Span::call_site();
+    let current_version = quote_spanned! { defspan => _savefile::prelude::CurrentVersion };
+    let uses = quote_spanned! { defspan =>
+        extern crate savefile as _savefile;
+    };
+
+    let mut versioned: Vec<(String, AttrsResult)> = Vec::new();
+    let mut current_field_types: Vec<syn::Type> = Vec::new();
+    let mut add_fields = |prefix: String, fields: &syn::Fields| {
+        for (field_index, field) in fields.iter().enumerate() {
+            let field_name = match field.ident {
+                Some(ref ident) => ident.to_string(),
+                None => field_index.to_string(),
+            };
+            let verinfo = parse_attr_tag(&field.attrs, &field.ty);
+            if !verinfo.ignore && verinfo.version_to == u32::MAX && !check_is_remove(&field.ty) {
+                current_field_types.push(field.ty.clone());
+            }
+            versioned.push((format!("{}{}", prefix, field_name), verinfo));
+        }
+    };
+    match input.data {
+        syn::Data::Struct(ref struc) => add_fields(String::new(), &struc.fields),
+        syn::Data::Enum(ref enum1) => {
+            for variant in enum1.variants.iter() {
+                add_fields(format!("{}.", variant.ident), &variant.fields);
+            }
+            for variant in enum1.variants.iter() {
+                versioned.push((variant.ident.to_string(), parse_attr_tag2(&variant.attrs, false)));
+            }
+        }
+        _ => panic!("Unsupported data type"),
+    }
+
+    let implied = versioned
+        .iter()
+        .map(|(_, verinfo)| implied_version(verinfo))
+        .max()
+        .unwrap_or(0);
+    let given_max_version = get_max_version(&input.attrs);
+    let max_version = match given_max_version {
+        Some(max_version) => {
+            for (item_name, verinfo) in versioned.iter() {
+                if implied_version(verinfo) > max_version {
+                    panic!(
+                        "{}: {} has a savefile_versions attribute which needs version {}, but the savefile_max_version of {} is {}",
+                        name,
+                        item_name,
+                        implied_version(verinfo),
+                        name,
+                        max_version
+                    );
+                }
+            }
+            max_version
+        }
+        None => implied,
+    };
+
+    let max_version = if get_namespace(&input.attrs).is_some() {
+        quote! { 0 }
+    } else if given_max_version.is_some() {
+        quote! { #max_version }
+    } else {
+        // Fields containing the type itself would make the constant depend on itself
+        let field_versions = current_field_types
+            .iter()
+            .filter(|field_type| !mentions_type(quote! { #field_type }, name))
+            .map(|field_type| quote! { _savefile::current_version_probe!(#field_type) });
+        quote! {
+            {
+                let version = #max_version;
+                #(let version = _savefile::max_version(version, #field_versions);)*
+                version
+            }
+        }
+    };
+
+    quote! {
+        #[allow(non_upper_case_globals)]
+        const _: () = {
+            #uses
+
+            impl #impl_generics #current_version for #name #ty_generics #where_clause {
+                const SAVEFILE_MAX_VERSION: u32 = #max_version;
+            }
+        };
+    }
+}
+
+/// Call the validation function of the type, if it has one, on `value`
+fn implement_validate(attrs: &[syn::Attribute], value: TokenStream) -> TokenStream {
+    match get_validate_fn(attrs) {
+        Some(validate_fn) => quote! {
+            #validate_fn(#value).map_err(|message| _savefile::prelude::SavefileError::Validation {
+                path: String::new(),
+                message,
+            })?;
+        },
+        None => quote! {},
+    }
+}
+
 fn get_enum_size(attrs: &Vec<syn::Attribute>) -> Option<u32> {
+    get_enum_repr_type(attrs).map(|repr| match repr.as_ref() {
+        "u8" => 1,
+        "i8" => 1,
+        "u16" => 2,
+        "i16" => 2,
+        "u32" => 4,
+        "i32" => 4,
+        "u64" => 8,
+        "i64" => 8,
+        _ => panic!("Unsupported repr(X) attribute on enum: {}", repr),
+    })
+}
+
+/// The integer type given by the repr-attribute of an enum, like "u8"
+fn get_enum_repr_type(attrs: &Vec<syn::Attribute>) -> Option<String> {
     use quote::ToTokens;
-    let mut size_u32: Option<u32> = None;
+    let mut repr: Option<String> = None;
     for attr in attrs.iter() {
         if let Some(ref meta) = attr.interpret_meta() {
             match meta {
@@ -936,24 +1894,14 @@ fn get_enum_size(attrs: &Vec<syn::Attribute>) -> Option<u32> {
                                     }
                                 },
                             };
-                            size_u32 = match size_str.as_ref() {
-                                "u8" => Some(1),
-                                "i8" => Some(1),
-                                "u16" => Some(2),
-                                "i16" => Some(2),
-                                "u32" => Some(4),
-                                "i32" => Some(4),
-                                "u64" => Some(8),
-                                "i64" => Some(8),
-                                _ => panic!("Unsupported repr(X) attribute on enum: {}", size_str),
-                            }
+                            repr = Some(size_str);
                         }
                     }
                 }
             }
         }
     }
-    size_u32
+    repr
 }
 #[proc_macro_derive(
     ReprC,
@@ -962,25 +1910,36 @@ fn get_enum_size(attrs: &Vec<syn::Attribute>) -> Option<u32> {
         savefile_versions_as,
         savefile_ignore,
         savefile_default_val,
-        savefile_default_fn
+        savefile_default_fn,
+        savefile_enum_repr,
+        savefile_enum_as_int,
+        savefile_namespace
     )
 )]
 pub fn reprc(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input: DeriveInput = syn::parse(input).unwrap();
 
     let name = input.ident;
+    let namespace = get_namespace(&input.attrs);
 
     let expanded = match &input.data {
         &syn::Data::Enum(ref enum1) => {
             let enum_size = get_enum_size(&input.attrs);
+            let as_int = has_enum_as_int(&input.attrs);
             if let Some(enum_size) = enum_size {
-                if enum_size != 1 {
-                    panic!("The ReprC trait assumes that the enum representation is u8 or i8. Savefile does not support enums with more than 255 variants. Sorry.");
+                if !as_int && enum_size != get_enum_repr(&input.attrs) as u32 {
+                    panic!("The ReprC trait assumes that the enum representation has the size of the discriminators saved by Savefile, which is 1 byte (u8 or i8) unless another size is given by the savefile_enum_repr attribute.");
                 }
             }
 
             let field_infos = Vec::<FieldInfo>::new();
+            let mut variants = Vec::new();
             for ref variant in enum1.variants.iter() {
+                if variant.discriminant.is_some() && !as_int {
+                    panic!("The ReprC trait can only be derived for enums with explicit discriminants if they have the savefile_enum_as_int attribute, since their values are otherwise not what Savefile saves.");
+                }
+                let var_ident = &variant.ident;
+                variants.push(quote! { #name::#var_ident });
                 match &variant.fields {
                     &syn::Fields::Named(ref _fields_named) => {
                         panic!("The ReprC trait cannot be derived for enums with fields.");
@@ -995,7 +1954,7 @@ pub fn reprc(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                     }
                 }
             }
-            implement_reprc(field_infos, input.generics, name)
+            implement_reprc(field_infos, Some(variants), input.generics, name, namespace)
         }
         &syn::Data::Struct(ref struc) => match &struc.fields {
             &syn::Fields::Named(ref namedfields) => {
@@ -1009,26 +1968,22 @@ pub fn reprc(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                     })
                     .collect();
 
-                implement_reprc(field_infos, input.generics, name)
+                implement_reprc(field_infos, None, input.generics, name, namespace)
             }
             &syn::Fields::Unnamed(ref fields_unnamed) => {
                 let field_infos: Vec<FieldInfo> = fields_unnamed
                     .unnamed
                     .iter()
-                    .enumerate()
-                    .map(|(idx, field)| FieldInfo {
-                        ident: Some(syn::Ident::new(
-                            &("x".to_string() + &idx.to_string()),
-                            Span::call_site(),
-                        )),
+                    .map(|field| FieldInfo {
+                        ident: None,
                         ty: &field.ty,
                         attrs: &field.attrs,
                     })
                     .collect();
 
-                implement_reprc(field_infos, input.generics, name)
+                implement_reprc(field_infos, None, input.generics, name, namespace)
             }
-            &syn::Fields::Unit => implement_reprc(Vec::new(), input.generics, name),
+            &syn::Fields::Unit => implement_reprc(Vec::new(), None, input.generics, name, namespace),
         },
         _ => {
             panic!("Unsupported data type");
@@ -1142,19 +2097,14 @@ fn savefile_derive_crate_introspect(input: DeriveInput) -> TokenStream {
     //let Field = quote_spanned! { defspan => _savefile::prelude::Field };
     //let Variant = quote_spanned! { defspan => _savefile::prelude::Variant };
 
-    let magic = format!("_IMPL_SAVEFILE_INTROSPECT_FOR_{}", &name).to_string();
-    let dummy_const = syn::Ident::new(&magic, proc_macro2::Span::call_site());
 
     let expanded = match &input.data {
         &syn::Data::Enum(ref enum1) => {
             let mut variants = Vec::new();
             let mut value_variants = Vec::new();
             let mut len_variants = Vec::new();
-            for (var_idx, ref variant) in enum1.variants.iter().enumerate() {
-                if var_idx > 255 {
-                    panic!("Savefile does not support enums with more than 255 total variants. Sorry.");
-                }
-                //let var_idx = var_idx as u8;
+            enum_discriminators(&name, enum1, &input.attrs);
+            for ref variant in enum1.variants.iter() {
                 let var_ident = variant.ident.clone();
                 let variant_name = quote! { #var_ident };
                 let variant_name_spanned = quote_spanned! { span => #variant_name};
@@ -1237,7 +2187,7 @@ fn savefile_derive_crate_introspect(input: DeriveInput) -> TokenStream {
             }
             quote! {
                 #[allow(non_upper_case_globals)]
-                const #dummy_const: () = {
+                const _: () = {
                     #uses
 
                     impl #impl_generics #introspect for #name #ty_generics #where_clause {
@@ -1313,7 +2263,7 @@ fn savefile_derive_crate_introspect(input: DeriveInput) -> TokenStream {
             }
             quote! {
                 #[allow(non_upper_case_globals)]
-                const #dummy_const: () = {
+                const _: () = {
                     #uses
 
                     impl #impl_generics #introspect for #name #ty_generics #where_clause {
@@ -1349,7 +2299,7 @@ fn implement_withschema(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
     let defspan = proc_macro2::Span::call_site();
     let local_version = quote_spanned! { defspan => local_version};
     let Field = quote_spanned! { defspan => _savefile::prelude::Field };
-    let WithSchema = quote_spanned! { defspan => _savefile::prelude::WithSchema };
+    let schema_of = quote_spanned! { defspan => _savefile::prelude::schema_of };
     let fields1 = quote_spanned! { defspan => fields1 };
 
     let mut fields = Vec::new();
@@ -1367,20 +2317,26 @@ fn implement_withschema(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
         };
         let removed = check_is_remove(&field.ty);
         let field_type = &field.ty;
-        if field_from_version == 0 && field_to_version == std::u32::MAX {
+        if verinfo.packed {
+            let BitPacked = quote_spanned! { defspan => _savefile::prelude::BitPacked };
+            let Schema = quote_spanned! { defspan => _savefile::prelude::Schema };
+            fields.push(quote_spanned!( span => #fields1.push(#Field { name:#name.to_string(), value: ::std::sync::Arc::new(#Schema::BitPacked(#schema_of::<#field_type>(#local_version)))})));
+            // Make sure the type can be packed
+            fields.push(quote_spanned!( span => let _ = <#field_type as #BitPacked>::bit_width));
+        } else if field_from_version == 0 && field_to_version == std::u32::MAX {
             if removed {
                 panic!("The Removed type can only be used for removed fields. Use the savefile_version attribute.");
             }
-            fields.push(quote_spanned!( span => #fields1.push(#Field { name:#name.to_string(), value:Box::new(<#field_type as #WithSchema>::schema(#local_version))})));
+            fields.push(quote_spanned!( span => #fields1.push(#Field { name:#name.to_string(), value:#schema_of::<#field_type>(#local_version)})));
         } else {
             let mut version_mappings = Vec::new();
             for dt in verinfo.deserialize_types.iter() {
                 let dt_from = dt.from;
                 let dt_to = dt.to;
-                let dt_field_type = syn::Ident::new(&dt.serialized_type, span);
+                let dt_field_type = dt.parse_serialized_type();
                 version_mappings.push(quote!{
                     if #local_version >= #dt_from && local_version <= #dt_to {
-                        #fields1.push(#Field { name:#name.to_string(), value:Box::new(<#dt_field_type as #WithSchema>::schema(#local_version))});
+                        #fields1.push(#Field { name:#name.to_string(), value:#schema_of::<#dt_field_type>(#local_version)});
                     }
                 });
             }
@@ -1389,7 +2345,7 @@ fn implement_withschema(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
                 #(#version_mappings)*
 
                 if #local_version >= #field_from_version && #local_version <= #field_to_version {
-                    #fields1.push(#Field { name:#name.to_string(), value:Box::new(<#field_type as #WithSchema>::schema(#local_version))});
+                    #fields1.push(#Field { name:#name.to_string(), value:#schema_of::<#field_type>(#local_version)});
                 }
                 ));
         }
@@ -1400,6 +2356,7 @@ fn implement_withschema(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
 #[allow(non_snake_case)]
 fn savefile_derive_crate_withschema(input: DeriveInput) -> TokenStream {
     let name = input.ident;
+    let local_version = local_version(&get_namespace(&input.attrs), quote! { version });
 
     let generics = input.generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
@@ -1419,17 +2376,37 @@ fn savefile_derive_crate_withschema(input: DeriveInput) -> TokenStream {
     let Field = quote_spanned! { defspan => _savefile::prelude::Field };
     let Variant = quote_spanned! { defspan => _savefile::prelude::Variant };
 
-    let magic = format!("_IMPL_SAVEFILE_WITHSCHEMA_FOR_{}", &name).to_string();
-    let dummy_const = syn::Ident::new(&magic, proc_macro2::Span::call_site());
+    let IsReprC = quote_spanned! { defspan => _savefile::prelude::IsReprC };
+    // Picks up a ReprC-implementation of the type, if there is one. This only works
+    // for non-generic types, for generic types the probe always says 'no'.
+    let repr_c_layout = quote! {
+        fn repr_c_layout(version:u32) -> #IsReprC<Self> {
+            _savefile::repr_c_probe!(Self, version)
+        }
+    };
+
+    let SchemaCache = quote_spanned! { defspan => _savefile::prelude::SchemaCache };
+    // A static in a generic impl would be shared between all instantiations,
+    // so only non-generic types get a schema cache.
+    let schema_cache = if generics.params.is_empty() {
+        quote! {
+            fn schema_cache() -> Option<&'static #SchemaCache> {
+                static CACHE: #SchemaCache = #SchemaCache::new();
+                Some(&CACHE)
+            }
+        }
+    } else {
+        quote! {}
+    };
+
 
     let expanded = match &input.data {
         &syn::Data::Enum(ref enum1) => {
             let mut variants = Vec::new();
+            let discriminators = enum_discriminators(&name, enum1, &input.attrs);
+            let discriminant_size = discriminators.size;
             for (var_idx, ref variant) in enum1.variants.iter().enumerate() {
-                if var_idx > 255 {
-                    panic!("Savefile does not support enums with more than 255 total variants. Sorry.");
-                }
-                let var_idx = var_idx as u8;
+                let var_idx = &discriminators.variants[var_idx];
                 let var_ident = variant.ident.clone();
                 let variant_name = quote! { #var_ident };
                 let variant_name_spanned = quote_spanned! { span => stringify!(#variant_name).to_string()};
@@ -1472,7 +2449,7 @@ fn savefile_derive_crate_withschema(input: DeriveInput) -> TokenStream {
                 variants.push(quote! {
                 (#field_from_version,
                  #field_to_version,
-                 #Variant { name: #variant_name_spanned, discriminator: #var_idx, fields:
+                 #Variant { name: #variant_name_spanned, discriminator: #var_idx as u32, fields:
                     {
                         let mut fields1 = Vec::<#Field>::new();
                         #(#fields;)*
@@ -1482,15 +2459,18 @@ fn savefile_derive_crate_withschema(input: DeriveInput) -> TokenStream {
             }
             quote! {
                 #[allow(non_upper_case_globals)]
-                const #dummy_const: () = {
+                const _: () = {
                     #uses
 
                     impl #impl_generics #withschema for #name #ty_generics #where_clause {
+                        #repr_c_layout
+
+                        #schema_cache
 
                         #[allow(unused_mut)]
                         #[allow(unused_comparisons, unused_variables)]
                         fn schema(version:u32) -> #Schema {
-                            let local_version = version;
+                            let local_version = #local_version;
                             #Schema::Enum (
                                 #SchemaEnum {
                                     dbg_name : stringify!(#name).to_string(),
@@ -1500,7 +2480,8 @@ fn savefile_derive_crate_withschema(input: DeriveInput) -> TokenStream {
                                         } else {
                                             None
                                         }
-                                    }).collect()
+                                    }).collect(),
+                                    discriminant_size: #discriminant_size,
                                 }
                             )
                         }
@@ -1542,14 +2523,18 @@ fn savefile_derive_crate_withschema(input: DeriveInput) -> TokenStream {
             }
             quote! {
                 #[allow(non_upper_case_globals)]
-                const #dummy_const: () = {
+                const _: () = {
                     #uses
 
                     impl #impl_generics #withschema for #name #ty_generics #where_clause {
+                        #repr_c_layout
+
+                        #schema_cache
+
                         #[allow(unused_comparisons)]
                         #[allow(unused_mut, unused_variables)]
                         fn schema(version:u32) -> #Schema {
-                            let local_version = version;
+                            let local_version = #local_version;
                             let mut fields1 = Vec::new();
                             #(#fields;)* ;
                             #Schema::Struct(#SchemaStruct{
@@ -2,6 +2,8 @@
 #![allow(unused_imports)]
 #![cfg_attr(feature="nightly", feature(test))]
 #![deny(warnings)]
+// Many types only exist to have their schemas or derived code checked
+#![allow(dead_code)]
 
 extern crate serde;
 #[macro_use]
@@ -17,6 +19,13 @@ extern crate bit_vec;
 extern crate smallvec;
 extern crate byteorder;
 extern crate rand;
+extern crate tokio;
+extern crate proptest;
+extern crate quickcheck;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[macro_use]
+extern crate bitflags;
 
 use std::fmt::Debug;
 use std::io::Write;
@@ -29,6 +38,8 @@ mod test_introspect;
 mod test_nested_non_repr_c;
 mod test_nested_repr_c;
 mod test_arrayvec;
+#[cfg(feature = "python")]
+mod test_python;
 
 #[cfg(feature = "external_benchmarks")]
 mod ext_benchmark;
@@ -464,7 +475,6 @@ pub fn test_string_leak_test() {
 }
 
 
-#[cfg(feature="nightly")]
 #[test]
 pub fn test_long_array() {
     let arr=[47;32];
@@ -472,7 +482,6 @@ pub fn test_long_array() {
 }
 
 
-#[cfg(feature="nightly")]
 #[test]
 pub fn test_very_long_array() {
     #[derive(Savefile)]
@@ -1161,3 +1170,4135 @@ pub fn test_pathbuf2() {
     assert_roundtrip(x);
 
 }
+
+#[test]
+pub fn test_error_source() {
+    use std::error::Error;
+    let err = load_file::<u32>("this_file_does_not_exist.bin", 0).unwrap_err();
+    let source = err.source().expect("IO errors should carry their cause");
+    assert!(source.downcast_ref::<std::io::Error>().is_some());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct ErrorPathInner {
+    name: String,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct ErrorPathOuter {
+    id: u32,
+    items: Vec<ErrorPathInner>,
+}
+
+#[test]
+pub fn test_error_path_and_offset() {
+    let x = ErrorPathOuter {
+        id: 42,
+        items: vec![ErrorPathInner { name: "hello".to_string() }],
+    };
+    let mut data = Vec::new();
+    save_noschema(&mut data, 0, &x).unwrap();
+    data.pop();
+    let err = load_noschema::<ErrorPathOuter>(&mut &data[..], 0).unwrap_err();
+    match err {
+        SavefileError::DeserializationFailed { offset, path, .. } => {
+            assert_eq!(path, "items/*/name");
+            assert!(offset.is_some());
+        }
+        err => panic!("Unexpected error: {:?}", err),
+    }
+}
+
+#[test]
+pub fn test_save_to_vec_load_from_slice() {
+    let data = vec!["hello".to_string(), "world".to_string()];
+    let encoded = save_to_vec(1, &data).unwrap();
+    let decoded: Vec<String> = load_from_slice(&encoded, 1).unwrap();
+    assert_eq!(decoded, data);
+}
+
+#[test]
+pub fn test_type_erased_writer_and_reader() {
+    let mut buf = Vec::new();
+    {
+        let mut writer: &mut dyn Write = &mut buf;
+        save(&mut writer, 0, &vec![1u32, 2, 3]).unwrap();
+    }
+    let mut slice = &buf[..];
+    let mut reader: &mut dyn std::io::Read = &mut slice;
+    let loaded: Vec<u32> = load(&mut reader, 0).unwrap();
+    assert_eq!(loaded, vec![1, 2, 3]);
+
+    let values: Vec<Box<dyn SerializeDyn>> = vec![Box::new(7u8), Box::new("text".to_string()), Box::new(vec![1u32])];
+    let mut raw = Vec::new();
+    {
+        let mut writer: &mut dyn Write = &mut raw;
+        let mut serializer = DynSerializer::new_raw(&mut writer);
+        for value in &values {
+            value.serialize_dyn(&mut serializer).unwrap();
+        }
+    }
+    let mut number = 0u8;
+    let mut text = String::new();
+    let mut numbers: Vec<u32> = Vec::new();
+    {
+        let targets: Vec<&mut dyn DeserializeDyn> = vec![&mut number, &mut text, &mut numbers];
+        let mut slice = &raw[..];
+        let mut reader: &mut dyn std::io::Read = &mut slice;
+        let mut deserializer = DynDeserializer::new_raw(&mut reader);
+        for target in targets {
+            target.deserialize_in_place_dyn(&mut deserializer).unwrap();
+        }
+    }
+    assert_eq!((number, text.as_str(), numbers), (7, "text", vec![1]));
+}
+
+#[test]
+pub fn test_buffered_file_roundtrip() {
+    let data: Vec<u64> = (0..10_000).collect();
+    save_file_with_capacity("test4.bin", 0, &data, 16).unwrap();
+    let loaded: Vec<u64> = load_file("test4.bin", 0).unwrap();
+    assert_eq!(loaded, data);
+    let loaded: Vec<u64> = load_file_with_capacity("test4.bin", 0, 3).unwrap();
+    assert_eq!(loaded, data);
+}
+
+#[test]
+pub fn test_save_file_atomic_with_backups() {
+    let _ = std::fs::remove_file("test5.bin");
+    for i in 0..4u32 {
+        save_file_atomic_with_backups("test5.bin", 0, &i, 2).unwrap();
+    }
+    assert_eq!(load_file::<u32>("test5.bin", 0).unwrap(), 3);
+    assert_eq!(load_file::<u32>("test5.bin.1", 0).unwrap(), 2);
+    assert_eq!(load_file::<u32>("test5.bin.2", 0).unwrap(), 1);
+    assert!(!std::path::Path::new("test5.bin.3").exists());
+
+    save_file_atomic("test5.bin", 0, &47u32).unwrap();
+    assert_eq!(load_file::<u32>("test5.bin", 0).unwrap(), 47);
+    assert_eq!(load_file::<u32>("test5.bin.1", 0).unwrap(), 2);
+    std::fs::remove_file("test5.bin.1").unwrap();
+    std::fs::remove_file("test5.bin.2").unwrap();
+}
+
+#[test]
+pub fn test_save_file_atomic_from_several_threads() {
+    let threads: Vec<_> = (0..8u32)
+        .map(|i| std::thread::spawn(move || save_file_atomic("test5b.bin", 0, &vec![i; 100_000]).unwrap()))
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let loaded: Vec<u32> = load_file("test5b.bin", 0).unwrap();
+    assert_eq!(loaded.len(), 100_000);
+    assert!(loaded.iter().all(|&x| x == loaded[0]));
+    std::fs::remove_file("test5b.bin").unwrap();
+}
+
+#[test]
+pub fn test_file_helpers_with_paths_and_files() {
+    let path = PathBuf::from("test6.bin");
+    save_file(&path, 0, &"hello".to_string()).unwrap();
+    assert_eq!(load_file::<String>(&path, 0).unwrap(), "hello");
+
+    {
+        let mut f = std::fs::File::create(&path).unwrap();
+        save_to(&mut f, 0, &47u16).unwrap();
+    }
+    let mut f = std::fs::File::open(&path).unwrap();
+    assert_eq!(load_from::<u16>(&mut f, 0).unwrap(), 47);
+}
+
+#[test]
+pub fn test_read_header() {
+    let encoded = save_to_vec(3, &vec![1u8, 2, 3]).unwrap();
+    let header = read_header(&mut &encoded[..]).unwrap();
+    assert_eq!(header.version, 3);
+    assert_eq!(header.compression, CompressionMethod::None);
+    assert_eq!(header.has_schema, Some(true));
+    assert_eq!(header.payload_length, Some(encoded.len() as u64 - 33));
+    // In-memory helpers don't record a creation time, so their output is deterministic
+    assert!(header.created.is_none());
+
+    let mut streamed = Vec::new();
+    save_compressed(&mut streamed, 3, &vec![1u8, 2, 3]).unwrap();
+    let header = read_header(&mut &streamed[..]).unwrap();
+    assert_eq!(header.compression, CompressionMethod::Bzip2);
+    assert_eq!(header.payload_length, None);
+
+    save_file("test7.bin", 1, &47u32).unwrap();
+    let header = read_header(&mut std::fs::File::open("test7.bin").unwrap()).unwrap();
+    assert_eq!(header.payload_length, Some(std::fs::metadata("test7.bin").unwrap().len() - 33));
+    assert!(header.created.is_some());
+
+    assert!(read_header(&mut &b"garbage, not a savefile"[..]).is_err());
+}
+
+#[test]
+pub fn test_save_deterministic() {
+    let data = vec!["same".to_string(), "bytes".to_string()];
+    let mut first = Vec::new();
+    save_deterministic(&mut first, 0, &data).unwrap();
+    let mut second = Vec::new();
+    save_deterministic(&mut second, 0, &data).unwrap();
+    assert_eq!(first, second);
+    assert_eq!(&first[25..33], &[0; 8]);
+    assert!(read_header(&mut &first[..]).unwrap().created.is_none());
+    assert_eq!(load::<Vec<String>>(&mut &first[..], 0).unwrap(), data);
+
+    let mut timed = Vec::new();
+    save(&mut timed, 0, &data).unwrap();
+    assert!(read_header(&mut &timed[..]).unwrap().created.is_some());
+}
+
+#[test]
+pub fn test_load_format_version_0() {
+    // Header as written by earlier versions of savefile, without schema
+    let mut data = b"savefile\0".to_vec();
+    data.extend_from_slice(&[0, 0, 1, 0, 0, 0, 0]);
+    data.extend_from_slice(&[0x2a, 0, 0, 0]);
+    let header = read_header(&mut &data[..]).unwrap();
+    assert_eq!(header.format_version, 0);
+    assert_eq!(header.has_schema, None);
+    assert_eq!(load_noschema::<u32>(&mut &data[..], 1).unwrap(), 42);
+}
+
+#[test]
+pub fn test_compression_methods() {
+    let data: Vec<String> = (0..1000).map(|x| format!("item {}", x % 10)).collect();
+    let uncompressed = save_to_vec(0, &data).unwrap();
+    for method in [
+        CompressionMethod::Bzip2,
+        CompressionMethod::Gzip,
+        CompressionMethod::Zstd,
+        CompressionMethod::Lz4,
+    ].iter() {
+        let mut encoded = Vec::new();
+        save_compressed_with(&mut encoded, 0, &data, *method).unwrap();
+        assert!(encoded.len() < uncompressed.len() / 4);
+        assert_eq!(read_header(&mut &encoded[..]).unwrap().compression, *method);
+        let decoded: Vec<String> = load(&mut &encoded[..], 0).unwrap();
+        assert_eq!(decoded, data);
+    }
+}
+
+#[test]
+pub fn test_checksum_detects_corruption() {
+    let data = vec![1u32, 2, 3, 4];
+    let mut encoded = Vec::new();
+    save_checksummed(&mut encoded, 0, &data).unwrap();
+    assert!(read_header(&mut &encoded[..]).unwrap().has_checksum);
+    let decoded: Vec<u32> = load(&mut &encoded[..], 0).unwrap();
+    assert_eq!(decoded, data);
+
+    // Flip a bit in the last element of the vector
+    let last_element = encoded.len() - 4 - 4;
+    encoded[last_element] ^= 0x10;
+    match load::<Vec<u32>>(&mut &encoded[..], 0) {
+        Err(SavefileError::ChecksumMismatch { .. }) => {}
+        other => panic!("Expected a checksum mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+pub fn test_checksum_is_verified_before_deserializing() {
+    let data = vec![1u32, 2, 3, 4];
+    let mut encoded = Vec::new();
+    save_checksummed(&mut encoded, 0, &data).unwrap();
+    assert_eq!(
+        read_header(&mut &encoded[..]).unwrap().payload_length,
+        Some(encoded.len() as u64 - 33)
+    );
+
+    // A corrupt length would make deserialization fail, or allocate a lot of memory
+    let mut corrupt = encoded.clone();
+    let length = corrupt.len() - 4 - 16 - 8;
+    corrupt[length + 7] = 0x7f;
+    match load::<Vec<u32>>(&mut &corrupt[..], 0) {
+        Err(SavefileError::ChecksumMismatch { .. }) => {}
+        other => panic!("Expected a checksum mismatch, got {:?}", other),
+    }
+
+    // The header is covered too. This flag would make the data be read as big endian.
+    let mut corrupt = encoded.clone();
+    corrupt[16] ^= 64;
+    match load::<Vec<u32>>(&mut &corrupt[..], 0) {
+        Err(SavefileError::ChecksumMismatch { .. }) => {}
+        other => panic!("Expected a checksum mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+pub fn test_encrypted_roundtrip_and_tampering() {
+    let key = [42u8; 32];
+    // Large enough to span several encrypted chunks
+    let data: Vec<u32> = (0..50_000).collect();
+    let mut encoded = Vec::new();
+    save_encrypted(&mut encoded, 0, &data, &key).unwrap();
+    assert!(read_header(&mut &encoded[..]).unwrap().encrypted);
+    let decoded: Vec<u32> = load_encrypted(&mut &encoded[..], 0, &key).unwrap();
+    assert_eq!(decoded, data);
+
+    assert!(load::<Vec<u32>>(&mut &encoded[..], 0).is_err());
+    assert!(load_encrypted::<Vec<u32>>(&mut &encoded[..], 0, &[43u8; 32]).is_err());
+
+    let mut plain = Vec::new();
+    save(&mut plain, 0, &data).unwrap();
+    assert!(load_encrypted::<Vec<u32>>(&mut &plain[..], 0, &key).is_err());
+
+    let mut tampered = encoded.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    assert!(load_encrypted::<Vec<u32>>(&mut &tampered[..], 0, &key).is_err());
+
+    // Clearing the schema flag of the header is detected, since the header is authenticated
+    let mut tampered = encoded.clone();
+    tampered[16] &= !1;
+    assert!(load_encrypted::<Vec<u32>>(&mut &tampered[..], 0, &key).is_err());
+
+    assert!(load_encrypted::<Vec<u32>>(&mut &encoded[..encoded.len() - 100], 0, &key).is_err());
+
+    // Every file gets its own salt, and so its own key
+    let mut again = Vec::new();
+    save_encrypted(&mut again, 0, &data, &key).unwrap();
+    assert_ne!(again[33..65], encoded[33..65]);
+    assert_ne!(again[65..], encoded[65..]);
+    assert_eq!(load_encrypted::<Vec<u32>>(&mut &again[..], 0, &key).unwrap(), data);
+
+    let mut tampered = encoded.clone();
+    tampered[40] ^= 1;
+    assert!(load_encrypted::<Vec<u32>>(&mut &tampered[..], 0, &key).is_err());
+}
+
+#[test]
+pub fn test_schema_hash() {
+    let data = vec![(1u32, "one".to_string()), (2, "two".to_string())];
+    let mut encoded = Vec::new();
+    save_with_schema_hash(&mut encoded, 0, &data).unwrap();
+    let header = read_header(&mut &encoded[..]).unwrap();
+    assert!(header.has_schema_hash);
+    assert_eq!(header.has_schema, Some(false));
+    let mut full = Vec::new();
+    save(&mut full, 0, &data).unwrap();
+    assert!(encoded.len() < full.len());
+
+    let decoded: Vec<(u32, String)> = load(&mut &encoded[..], 0).unwrap();
+    assert_eq!(decoded, data);
+    match load::<Vec<(i32, String)>>(&mut &encoded[..], 0) {
+        Err(SavefileError::IncompatibleSchema { .. }) => {}
+        other => panic!("Expected an incompatible schema, got {:?}", other),
+    }
+    assert!(load_noschema::<Vec<(i32, String)>>(&mut &encoded[..], 0).is_err());
+    let decoded: Vec<(i32, String)> = load_noschema_unverified(&mut &encoded[..], 0).unwrap();
+    assert_eq!(decoded[1], (2, "two".to_string()));
+}
+
+#[test]
+pub fn test_schema_hash_is_stable() {
+    assert_eq!(<Vec<u32>>::schema(0).stable_hash(), 12455683848108913979);
+    assert_eq!(<Vec<u32>>::schema(0).stable_hash(), <Vec<u32>>::schema(1).stable_hash());
+    assert_ne!(<Vec<u32>>::schema(0).stable_hash(), <Vec<u64>>::schema(0).stable_hash());
+}
+
+#[test]
+pub fn test_read_schema() {
+    let data = vec![1u32, 2, 3];
+    let mut encoded = Vec::new();
+    save(&mut encoded, 3, &data).unwrap();
+    let (version, schema) = read_schema(&mut &encoded[..]).unwrap();
+    assert_eq!(version, 3);
+    assert_eq!(schema, <Vec<u32>>::schema(3));
+
+    let mut compressed = Vec::new();
+    save_compressed(&mut compressed, 3, &data).unwrap();
+    assert_eq!(read_schema(&mut &compressed[..]).unwrap().1, <Vec<u32>>::schema(3));
+
+    let mut noschema = Vec::new();
+    save_noschema(&mut noschema, 3, &data).unwrap();
+    assert!(read_schema(&mut &noschema[..]).is_err());
+}
+
+#[derive(Savefile)]
+pub enum DisplayedEnum {
+    A,
+    B { x: u8 },
+}
+
+#[test]
+pub fn test_schema_display() {
+    assert_eq!(
+        <Vec<(u32, Option<String>)>>::schema(0).to_string(),
+        "Vec<struct 2-Tuple {\n    0: u32\n    1: Option<String>\n}>"
+    );
+    assert_eq!(
+        DisplayedEnum::schema(0).to_string(),
+        "enum DisplayedEnum {\n    A = 0\n    B = 1 {\n        x: u8\n    }\n}"
+    );
+    assert_eq!(<[u8; 4]>::schema(0).to_string(), "[u8; 4]");
+}
+
+#[test]
+pub fn test_incompatible_schema_shows_subtrees() {
+    let saved = save_to_mem(0, &(1u8, vec![DisplayedEnum::A])).unwrap();
+    let err = load_from_mem::<(u8, Vec<Option<u8>>)>(&saved, 0).unwrap_err();
+    assert!(err.to_string().ends_with(
+        "\nIn memory:\nOption<u8>\nIn file:\nenum DisplayedEnum {\n    A = 0\n    B = 1 {\n        x: u8\n    }\n}"
+    ));
+}
+
+#[test]
+pub fn test_schema_to_json() {
+    assert_eq!(
+        <Vec<(u8, Option<String>)>>::schema(0).to_json(),
+        "{\"kind\":\"vector\",\"item\":{\"kind\":\"struct\",\"name\":\"2-Tuple\",\"fields\":[\
+         {\"name\":\"0\",\"schema\":{\"kind\":\"primitive\",\"type\":\"u8\"}},\
+         {\"name\":\"1\",\"schema\":{\"kind\":\"option\",\"item\":{\"kind\":\"primitive\",\"type\":\"String\"}}}]}}"
+    );
+    assert_eq!(
+        DisplayedEnum::schema(0).to_json_schema(),
+        "{\"$schema\":\"https://json-schema.org/draft/2020-12/schema\",\"allOf\":[{\"oneOf\":[{\"const\":\"A\"},\
+         {\"type\":\"object\",\"properties\":{\"B\":{\"type\":\"object\",\"properties\":\
+         {\"x\":{\"type\":\"integer\",\"minimum\":0,\"maximum\":255}},\"required\":[\"x\"],\"additionalProperties\":false}},\
+         \"required\":[\"B\"],\"additionalProperties\":false}]}]}"
+    );
+}
+
+#[test]
+pub fn test_schema_differences() {
+    let memory = <(u32, Vec<u8>)>::schema(0);
+    assert!(schema_differences(&memory, &<(u32, Vec<u8>)>::schema(0)).is_empty());
+
+    let differences = schema_differences(&memory, &<(u16, Vec<u16>)>::schema(0));
+    assert_eq!(
+        differences,
+        vec![
+            SchemaDifference {
+                path: "./2-Tuple/0".to_string(),
+                kind: SchemaDifferenceKind::PrimitiveMismatch {
+                    memory: SchemaPrimitive::schema_u32,
+                    file: SchemaPrimitive::schema_u16,
+                },
+            },
+            SchemaDifference {
+                path: "./2-Tuple/1/*".to_string(),
+                kind: SchemaDifferenceKind::PrimitiveMismatch {
+                    memory: SchemaPrimitive::schema_u8,
+                    file: SchemaPrimitive::schema_u16,
+                },
+            },
+        ]
+    );
+    assert_eq!(
+        differences[0].to_string(),
+        "At location [./2-Tuple/0]: Application protocol has datatype u32, but disk format has u16"
+    );
+
+    let differences = schema_differences(&memory, &<Vec<u8>>::schema(0));
+    assert_eq!(
+        differences[0].kind,
+        SchemaDifferenceKind::KindMismatch {
+            memory: "struct",
+            file: "vector"
+        }
+    );
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct DynPlayer {
+    name: String,
+    position: (i32, i32),
+    inventory: Vec<Option<String>>,
+    state: DisplayedEnum,
+    scores: [u16; 2],
+}
+
+impl PartialEq for DisplayedEnum {
+    fn eq(&self, other: &DisplayedEnum) -> bool {
+        match (self, other) {
+            (DisplayedEnum::A, DisplayedEnum::A) => true,
+            (DisplayedEnum::B { x: a }, DisplayedEnum::B { x: b }) => a == b,
+            _ => false,
+        }
+    }
+}
+impl std::fmt::Debug for DisplayedEnum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisplayedEnum::A => write!(f, "A"),
+            DisplayedEnum::B { x } => write!(f, "B {{ x: {} }}", x),
+        }
+    }
+}
+
+#[test]
+pub fn test_dynamic_document() {
+    let player = DynPlayer {
+        name: "Bob".to_string(),
+        position: (-3, 4),
+        inventory: vec![Some("sword".to_string()), None],
+        state: DisplayedEnum::B { x: 7 },
+        scores: [10, 20],
+    };
+    let mut encoded = Vec::new();
+    save(&mut encoded, 2, &player).unwrap();
+
+    let mut document = Document::load(&mut &encoded[..]).unwrap();
+    assert_eq!(document.version, 2);
+    assert_eq!(document.get("name"), Some(&DynValue::String("Bob".to_string())));
+    assert_eq!(document.get("position/1"), Some(&DynValue::I32(4)));
+    assert_eq!(document.get("inventory/0/").unwrap(), &DynValue::Option(Some(Box::new(DynValue::String("sword".to_string())))));
+    assert_eq!(document.get("inventory/1"), Some(&DynValue::Option(None)));
+    assert_eq!(document.get("state/x"), Some(&DynValue::U8(7)));
+    assert_eq!(document.get("scores/1"), Some(&DynValue::U16(20)));
+    assert_eq!(document.get("missing"), None);
+
+    *document.get_mut("name").unwrap() = DynValue::String("Alice".to_string());
+    *document.get_mut("state").unwrap() = DynValue::Enum {
+        variant: "A".to_string(),
+        discriminator: 0,
+        fields: Default::default(),
+    };
+    let mut modified = Vec::new();
+    document.save(&mut modified).unwrap();
+    let loaded: DynPlayer = load(&mut &modified[..], 2).unwrap();
+    assert_eq!(
+        loaded,
+        DynPlayer {
+            name: "Alice".to_string(),
+            state: DisplayedEnum::A,
+            ..player
+        }
+    );
+
+    *document.get_mut("name").unwrap() = DynValue::U8(1);
+    assert!(document.save(&mut Vec::new()).is_err());
+}
+
+#[test]
+pub fn test_dyn_value_to_json() {
+    let player = DynPlayer {
+        name: "Bob \"the\" builder".to_string(),
+        position: (-3, 4),
+        inventory: vec![Some("sword".to_string()), None],
+        state: DisplayedEnum::B { x: 7 },
+        scores: [10, 20],
+    };
+    let document = Document::load(&mut &save_to_vec(0, &player).unwrap()[..]).unwrap();
+    assert_eq!(
+        document.value.to_json(),
+        "{\"name\":\"Bob \\\"the\\\" builder\",\"position\":{\"0\":-3,\"1\":4},\"inventory\":[\"sword\",null],\
+         \"state\":{\"B\":{\"x\":7}},\"scores\":[10,20]}"
+    );
+    assert_eq!(DynValue::F64(1.5).to_json(), "1.5");
+    assert_eq!(DynValue::F32(std::f32::NAN).to_json(), "null");
+}
+
+#[test]
+pub fn test_async_save_and_load() {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let mut stream = Vec::new();
+    runtime.block_on(save_async(&mut stream, 0, &vec![1u32, 2])).unwrap();
+    runtime.block_on(save_async(&mut stream, 0, &"second".to_string())).unwrap();
+
+    // Both values can be read back one at a time from the same stream
+    let mut reader = &stream[..];
+    let first: Vec<u32> = runtime.block_on(load_async(&mut reader, 0)).unwrap();
+    let second: String = runtime.block_on(load_async(&mut reader, 0)).unwrap();
+    assert_eq!(first, vec![1, 2]);
+    assert_eq!(second, "second");
+    assert!(reader.is_empty());
+
+    runtime.block_on(save_file_async("test_async.bin", 0, &first)).unwrap();
+    let loaded: Vec<u32> = runtime.block_on(load_file_async("test_async.bin", 0)).unwrap();
+    assert_eq!(loaded, first);
+}
+
+#[test]
+pub fn test_stream_messages() {
+    use savefile::stream::{recv_message, send_message, send_message_checksummed};
+    let mut stream = Vec::new();
+    send_message(&mut stream, 0, &vec![1u32, 2]).unwrap();
+    send_message_checksummed(&mut stream, 0, &"second".to_string()).unwrap();
+
+    let mut reader = &stream[..];
+    let first: Vec<u32> = recv_message(&mut reader, 0).unwrap();
+    let second: String = recv_message(&mut reader, 0).unwrap();
+    assert_eq!(first, vec![1, 2]);
+    assert_eq!(second, "second");
+    assert!(reader.is_empty());
+
+    // Corruption of a checksummed message is detected
+    let mut corrupt = Vec::new();
+    send_message_checksummed(&mut corrupt, 0, &"message".to_string()).unwrap();
+    let last = corrupt.len() - 5;
+    corrupt[last] ^= 1;
+    match recv_message::<String>(&mut &corrupt[..], 0) {
+        Err(SavefileError::ChecksumMismatch { .. }) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // A truncated message is an error
+    let mut truncated = Vec::new();
+    send_message(&mut truncated, 0, &"message".to_string()).unwrap();
+    truncated.pop();
+    assert!(recv_message::<String>(&mut &truncated[..], 0).is_err());
+}
+
+fn run_handshake_peers<A: WithSchema, B: WithSchema>(a_range: (u32, u32), b_range: (u32, u32)) -> (Result<u32, SavefileError>, Result<u32, SavefileError>) {
+    use savefile::stream::handshake;
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = std::thread::spawn(move || {
+        let mut stream = std::net::TcpStream::connect(address).unwrap();
+        handshake::<B>(&mut stream, b_range.0, b_range.1)
+    });
+    let mut stream = listener.accept().unwrap().0;
+    let result = handshake::<A>(&mut stream, a_range.0, a_range.1);
+    (result, peer.join().unwrap())
+}
+
+#[test]
+pub fn test_stream_handshake() {
+    let (a, b) = run_handshake_peers::<u32, u32>((0, 2), (1, 3));
+    assert_eq!(a.unwrap(), 2);
+    assert_eq!(b.unwrap(), 2);
+
+    let (a, b) = run_handshake_peers::<u32, u32>((2, 2), (0, 1));
+    assert!(a.is_err());
+    assert!(b.is_err());
+
+    let (a, b) = run_handshake_peers::<u32, String>((0, 0), (0, 0));
+    assert!(a.is_err());
+    assert!(b.is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct LogEvent {
+    id: u32,
+    description: String,
+}
+
+fn log_event(id: u32) -> LogEvent {
+    LogEvent { id, description: format!("event {}", id) }
+}
+
+fn read_log_events(path: &str) -> Vec<LogEvent> {
+    SaveLog::<LogEvent>::read(path, 0).unwrap().map(|event| event.unwrap()).collect()
+}
+
+#[test]
+pub fn test_save_log() {
+    let path = "test_save_log.bin";
+    {
+        let mut log = SaveLog::<LogEvent>::create(path, 0).unwrap();
+        log.append(&log_event(1)).unwrap();
+        log.append(&log_event(2)).unwrap();
+    }
+    {
+        let mut log = SaveLog::<LogEvent>::open(path, 0).unwrap();
+        log.append(&log_event(3)).unwrap();
+        log.sync().unwrap();
+    }
+    assert_eq!(read_log_events(path), vec![log_event(1), log_event(2), log_event(3)]);
+
+    // Simulate a torn write of the last record
+    let complete = std::fs::read(path).unwrap();
+    std::fs::write(path, &complete[..complete.len() - 3]).unwrap();
+    assert_eq!(read_log_events(path), vec![log_event(1), log_event(2)]);
+
+    // The torn record is removed before appending
+    SaveLog::<LogEvent>::open(path, 0).unwrap().append(&log_event(4)).unwrap();
+    assert_eq!(read_log_events(path), vec![log_event(1), log_event(2), log_event(4)]);
+
+    // Corruption before the last record is reported
+    let mut corrupt = std::fs::read(path).unwrap();
+    let first_record_end = corrupt.len() - 2 * (8 + 4 + 8 + 7);
+    corrupt[first_record_end - 1] ^= 1;
+    std::fs::write(path, &corrupt).unwrap();
+    let events: Vec<_> = SaveLog::<LogEvent>::read(path, 0).unwrap().collect();
+    assert_eq!(events.len(), 1);
+    match events[0] {
+        Err(SavefileError::ChecksumMismatch { .. }) => {}
+        ref other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // Records can't be appended by a different version
+    assert!(SaveLog::<LogEvent>::open(path, 1).is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct PersistentCounter {
+    total: u64,
+    history: Vec<u32>,
+}
+
+#[derive(Savefile, Debug)]
+pub enum CounterCommand {
+    Add(u32),
+    Reset,
+}
+
+impl PersistentCommand<PersistentCounter> for CounterCommand {
+    fn apply(&self, state: &mut PersistentCounter) {
+        match *self {
+            CounterCommand::Add(x) => {
+                state.total += x as u64;
+                state.history.push(x);
+            }
+            CounterCommand::Reset => {
+                state.total = 0;
+            }
+        }
+    }
+}
+
+fn open_counter(path: &str) -> Persistent<PersistentCounter, CounterCommand> {
+    Persistent::open(path, 0, || PersistentCounter { total: 0, history: Vec::new() }).unwrap()
+}
+
+#[test]
+pub fn test_persistent() {
+    let path = "test_persistent.bin";
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_file("test_persistent.bin.journal");
+    {
+        let mut counter = open_counter(path);
+        counter.set_compact_after(3);
+        counter.execute(CounterCommand::Add(1)).unwrap();
+        counter.execute(CounterCommand::Add(2)).unwrap();
+        counter.execute(CounterCommand::Reset).unwrap();
+        // Compacted here
+        counter.execute(CounterCommand::Add(5)).unwrap();
+        counter.sync().unwrap();
+        assert_eq!(counter.state(), &PersistentCounter { total: 5, history: vec![1, 2, 5] });
+    }
+    let journal_before_reopen = std::fs::read("test_persistent.bin.journal").unwrap();
+    {
+        // The snapshot is loaded, and the journaled command applied to it
+        let mut counter = open_counter(path);
+        assert_eq!(counter.state(), &PersistentCounter { total: 5, history: vec![1, 2, 5] });
+        counter.execute(CounterCommand::Add(1)).unwrap();
+    }
+
+    // Commands in the journal which are already part of the snapshot are not applied again,
+    // as happens when crashing between saving the snapshot and emptying the journal.
+    std::fs::write("test_persistent.bin.journal", &journal_before_reopen).unwrap();
+    let counter = open_counter(path);
+    assert_eq!(counter.state(), &PersistentCounter { total: 5, history: vec![1, 2, 5] });
+
+    // Emptied journals replace the old ones atomically, through temporary files
+    let temp_files = std::fs::read_dir(".")
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_string_lossy().starts_with(".test_persistent.bin")
+        })
+        .count();
+    assert_eq!(temp_files, 0);
+}
+
+#[test]
+pub fn test_save_archive() {
+    let mut archive = SaveArchive::new();
+    archive.insert("world", 2, &vec![1u32, 2, 3]).unwrap();
+    archive.insert("player", 0, &"Bob".to_string()).unwrap();
+    archive.insert("settings", 1, &(true, 7u8)).unwrap();
+    archive.insert("player", 0, &"Alice".to_string()).unwrap();
+    assert!(archive.remove("settings"));
+    assert!(!archive.remove("settings"));
+    archive.save_file("test_archive.bin").unwrap();
+
+    let mut reader = ArchiveReader::open_file("test_archive.bin").unwrap();
+    assert_eq!(reader.names().collect::<Vec<_>>(), vec!["world", "player"]);
+    assert!(reader.contains("player"));
+    assert!(!reader.contains("settings"));
+    let player: String = reader.load("player", 0).unwrap();
+    assert_eq!(player, "Alice");
+    let world: Vec<u32> = reader.load("world", 2).unwrap();
+    assert_eq!(world, vec![1, 2, 3]);
+    assert!(reader.load::<String>("settings", 0).is_err());
+    // Each entry has its own version and schema
+    assert!(reader.load::<Vec<u32>>("world", 1).is_err());
+    assert!(reader.load::<u32>("player", 0).is_err());
+
+    assert!(ArchiveReader::new(Cursor::new(save_to_mem(0, &1u32).unwrap())).is_err());
+}
+
+#[test]
+pub fn test_archive_writer_sections() {
+    let file = std::fs::File::create("test_archive_sections.bin").unwrap();
+    let mut writer = ArchiveWriter::new(BufWriter::new(file)).unwrap();
+    writer.add("header", 0, &"My world".to_string()).unwrap();
+    for x in 0..3u32 {
+        for y in 0..3u32 {
+            writer.add(&format!("chunks/{}_{}", x, y), 0, &vec![x * 10 + y; 100]).unwrap();
+        }
+    }
+    assert!(writer.add("header", 0, &"Duplicate".to_string()).is_err());
+    writer.finish().unwrap();
+
+    let mut reader = ArchiveReader::open_file("test_archive_sections.bin").unwrap();
+    assert_eq!(reader.names_under("chunks/").count(), 9);
+    let chunk: Vec<u32> = reader.load("chunks/2_1", 0).unwrap();
+    assert_eq!(chunk, vec![21; 100]);
+    let header: String = reader.load("header", 0).unwrap();
+    assert_eq!(header, "My world");
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct LazyGameSave {
+    name: String,
+    world: Vec<(u32, String)>,
+    heights: Vec<u16>,
+    timestamp: u64,
+}
+
+#[derive(Savefile)]
+pub struct LazyGameSaveSummary {
+    name: String,
+    world: Lazy<Vec<(u32, String)>>,
+    heights: Lazy<Vec<u16>>,
+    timestamp: u64,
+}
+
+#[test]
+pub fn test_lazy_fields() {
+    let save = LazyGameSave {
+        name: "My game".to_string(),
+        world: vec![(1, "forest".to_string()), (2, "lake".to_string())],
+        heights: vec![3, 1, 4, 1, 5],
+        timestamp: 12345,
+    };
+    let bytes = save_to_mem(0, &save).unwrap();
+
+    let mut summary: LazyGameSaveSummary = load_from_mem(&bytes, 0).unwrap();
+    assert_eq!(summary.name, "My game");
+    assert_eq!(summary.timestamp, 12345);
+    assert!(!summary.world.is_loaded());
+    assert!(!summary.heights.is_loaded());
+    assert_eq!(summary.heights.get().unwrap(), &vec![3, 1, 4, 1, 5]);
+    assert!(summary.heights.is_loaded());
+    summary.heights.get().unwrap().push(9);
+
+    // The world was never loaded, so its retained bytes are saved as is
+    let resaved = save_to_mem(0, &summary).unwrap();
+    let reloaded: LazyGameSave = load_from_mem(&resaved, 0).unwrap();
+    assert_eq!(reloaded.world, save.world);
+    assert_eq!(reloaded.heights, vec![3, 1, 4, 1, 5, 9]);
+    assert_eq!(summary.world.into_inner().unwrap(), save.world);
+}
+
+#[test]
+pub fn test_vec_writer_and_reader() {
+    let file = std::fs::File::create("test_vec_stream.bin").unwrap();
+    let mut writer = VecWriter::<_, (u32, String)>::new(BufWriter::new(file), 0).unwrap();
+    for i in 0..1000u32 {
+        writer.push(&(i, format!("item {}", i))).unwrap();
+    }
+    assert_eq!(writer.len(), 1000);
+    writer.finish().unwrap();
+
+    // The file is a regular savefile file of a vector
+    let items: Vec<(u32, String)> = load_file("test_vec_stream.bin", 0).unwrap();
+    assert_eq!(items.len(), 1000);
+    assert_eq!(items[999], (999, "item 999".to_string()));
+    let header = read_header(&mut std::fs::File::open("test_vec_stream.bin").unwrap()).unwrap();
+    assert_eq!(header.payload_length, Some(std::fs::metadata("test_vec_stream.bin").unwrap().len() - 33));
+
+    let reader = VecReader::<_, (u32, String)>::new(std::io::BufReader::new(std::fs::File::open("test_vec_stream.bin").unwrap()), 0).unwrap();
+    assert_eq!(reader.remaining(), Some(1000));
+    let mut count = 0;
+    for (i, item) in reader.enumerate() {
+        assert_eq!(item.unwrap(), (i as u32, format!("item {}", i)));
+        count += 1;
+    }
+    assert_eq!(count, 1000);
+
+    // Files written by save can be read one item at a time too
+    let data = save_to_mem(0, &vec![1u16, 2, 3]).unwrap();
+    let read: Vec<u16> = VecReader::<_, u16>::new(&data[..], 0).unwrap().map(|item| item.unwrap()).collect();
+    assert_eq!(read, vec![1, 2, 3]);
+    assert!(VecReader::<_, u32>::new(&data[..], 0).is_err());
+}
+
+/// A writer which can't seek, like a pipe
+struct PipeWriter(Vec<u8>);
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct ChunkedQueryResult {
+    query: String,
+    rows: Vec<(u32, String)>,
+    values: Vec<u64>,
+}
+
+pub struct ChunkedQueryResultWriter {
+    rows: u32,
+}
+
+impl WithSchema for ChunkedQueryResultWriter {
+    fn schema(version: u32) -> Schema {
+        ChunkedQueryResult::schema(version)
+    }
+}
+
+impl Serialize for ChunkedQueryResultWriter {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_string("all rows")?;
+        serializer.write_chunked((0..self.rows).map(|i| (i, i.to_string())))?;
+        serializer.write_chunked((0..self.rows as u64).filter(|x| x % 2 == 0))
+    }
+}
+
+#[test]
+pub fn test_chunked_vec_encoding() {
+    let bytes = save_to_mem(0, &ChunkedQueryResultWriter { rows: 20000 }).unwrap();
+    let loaded: ChunkedQueryResult = load_from_mem(&bytes, 0).unwrap();
+    assert_eq!(loaded.query, "all rows");
+    assert_eq!(loaded.rows.len(), 20000);
+    assert_eq!(loaded.rows[12345], (12345, "12345".to_string()));
+    assert_eq!(loaded.values, (0..20000u64).filter(|x| x % 2 == 0).collect::<Vec<_>>());
+
+    // The chunked encoding also works without the types
+    let document = Document::load(&mut &bytes[..]).unwrap();
+    assert_eq!(document.get("rows/19999/1"), Some(&DynValue::String("19999".to_string())));
+
+    let mut writer = ChunkedVecWriter::<_, u32>::new(PipeWriter(Vec::new()), 0).unwrap();
+    for i in 0..50000 {
+        writer.push(&i).unwrap();
+    }
+    let bytes = writer.finish().unwrap().0;
+    let loaded: Vec<u32> = load_from_mem(&bytes, 0).unwrap();
+    assert_eq!(loaded, (0..50000).collect::<Vec<_>>());
+
+    let reader = VecReader::<_, u32>::new(&bytes[..], 0).unwrap();
+    assert_eq!(reader.remaining(), None);
+    assert_eq!(reader.map(|item| item.unwrap()).collect::<Vec<_>>(), loaded);
+
+    let empty = ChunkedVecWriter::<_, u32>::new(PipeWriter(Vec::new()), 0).unwrap().finish().unwrap().0;
+    assert_eq!(load_from_mem::<Vec<u32>>(&empty, 0).unwrap(), Vec::<u32>::new());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct OwnedMessage {
+    sender: String,
+    payload: Vec<u8>,
+    tags: Vec<String>,
+}
+
+#[derive(SavefileBorrowed, Debug, PartialEq)]
+pub struct BorrowedMessage<'a> {
+    sender: &'a str,
+    payload: &'a [u8],
+    tags: Vec<String>,
+}
+
+#[test]
+pub fn test_load_borrowed() {
+    let message = OwnedMessage {
+        sender: "Alice".to_string(),
+        payload: vec![1, 2, 3, 4, 5],
+        tags: vec!["urgent".to_string()],
+    };
+    let bytes = save_to_mem(0, &message).unwrap();
+    let borrowed: BorrowedMessage = load_borrowed(&bytes, 0).unwrap();
+    assert_eq!(borrowed.sender, "Alice");
+    assert_eq!(borrowed.payload, &[1, 2, 3, 4, 5]);
+    assert_eq!(borrowed.tags, vec!["urgent".to_string()]);
+    let payload_start = borrowed.payload.as_ptr() as usize - bytes.as_ptr() as usize;
+    assert!(payload_start < bytes.len());
+
+    // The borrowed struct has the same schema, so it can be saved just like the owned struct
+    let resaved = save_to_mem(0, &borrowed).unwrap();
+    let reloaded: OwnedMessage = load_from_mem(&resaved, 0).unwrap();
+    assert_eq!(reloaded, message);
+
+    let mut checksummed = Vec::new();
+    save_checksummed(&mut checksummed, 0, &message).unwrap();
+    let borrowed: BorrowedMessage = load_borrowed(&checksummed, 0).unwrap();
+    assert_eq!(borrowed.sender, "Alice");
+    let mut corrupt = checksummed.clone();
+    let last = corrupt.len() - 6;
+    corrupt[last] ^= 1;
+    assert!(load_borrowed::<BorrowedMessage>(&corrupt, 0).is_err());
+}
+
+#[test]
+pub fn test_load_file_mmap() {
+    let message = OwnedMessage {
+        sender: "Bob".to_string(),
+        payload: vec![42; 10000],
+        tags: vec!["large".to_string(), "mapped".to_string()],
+    };
+    save_file("test_mmap.bin", 0, &message).unwrap();
+
+    let loaded: OwnedMessage = load_file_mmap("test_mmap.bin", 0).unwrap();
+    assert_eq!(loaded, message);
+
+    let mapped = MappedFile::open("test_mmap.bin").unwrap();
+    let borrowed: BorrowedMessage = mapped.load(0).unwrap();
+    assert_eq!(borrowed.sender, "Bob");
+    assert_eq!(borrowed.payload.len(), 10000);
+    assert_eq!(borrowed.tags, message.tags);
+    let bytes = mapped.bytes().as_ptr() as usize..mapped.bytes().as_ptr() as usize + mapped.bytes().len();
+    assert!(bytes.contains(&(borrowed.payload.as_ptr() as usize)));
+}
+
+#[derive(Savefile, Debug, PartialEq, Default)]
+pub struct FrameState {
+    names: Vec<String>,
+    positions: Vec<(u32, u32)>,
+    scores: std::collections::HashMap<u32, u64>,
+    title: String,
+}
+
+#[test]
+pub fn test_load_in_place() {
+    let mut first = FrameState::default();
+    first.names = vec!["alpha".to_string(), "beta".to_string(), "gamma".to_string()];
+    first.positions = vec![(1, 2); 100];
+    first.scores.insert(1, 100);
+    first.title = "Frame number one".to_string();
+    let mut second = FrameState::default();
+    second.names = vec!["delta".to_string(), "epsilon".to_string()];
+    second.positions = vec![(3, 4); 50];
+    second.scores.insert(2, 200);
+    second.title = "Frame two".to_string();
+
+    let mut state: FrameState = load_from_mem(&save_to_mem(0, &first).unwrap(), 0).unwrap();
+    let positions_ptr = state.positions.as_ptr();
+    let name_ptr = state.names[0].as_ptr();
+    let title_ptr = state.title.as_ptr();
+
+    let bytes = save_to_mem(0, &second).unwrap();
+    load_in_place(&mut &bytes[..], 0, &mut state).unwrap();
+    assert_eq!(state, second);
+    assert_eq!(state.positions.as_ptr(), positions_ptr);
+    assert_eq!(state.names[0].as_ptr(), name_ptr);
+    assert_eq!(state.title.as_ptr(), title_ptr);
+
+    load_in_place(&mut &save_to_mem(0, &first).unwrap()[..], 0, &mut state).unwrap();
+    assert_eq!(state, first);
+}
+
+#[test]
+pub fn test_serialized_size() {
+    let values = vec![
+        OwnedMessage {
+            sender: "Carol".to_string(),
+            payload: vec![7; 1000],
+            tags: vec![],
+        },
+        OwnedMessage {
+            sender: "".to_string(),
+            payload: vec![],
+            tags: vec!["a".to_string(); 10],
+        },
+    ];
+    for value in &values {
+        assert_eq!(serialized_size(0, value).unwrap(), save_to_mem(0, value).unwrap().len() as u64);
+    }
+    assert_eq!(serialized_size(0, &values).unwrap(), save_to_mem(0, &values).unwrap().len() as u64);
+
+    let mut counter = SizeCounter::new();
+    values[0].serialize(&mut Serializer::new_raw(&mut counter)).unwrap();
+    // Length and bytes of the string and the vector, and the length of the empty vector
+    assert_eq!(counter.count(), 8 + 5 + 8 + 1000 + 8);
+}
+
+#[test]
+pub fn test_hash_value() {
+    let message = OwnedMessage {
+        sender: "Dave".to_string(),
+        payload: vec![1, 2, 3],
+        tags: vec!["x".to_string()],
+    };
+    let hash = hash_value(0, &message).unwrap();
+    assert_eq!(hash, hash_value(0, &message).unwrap());
+    let mut changed = OwnedMessage {
+        sender: "Dave".to_string(),
+        payload: vec![1, 2, 3],
+        tags: vec!["x".to_string()],
+    };
+    assert_eq!(hash_value(0, &changed).unwrap(), hash);
+    changed.payload[2] = 4;
+    assert_ne!(hash_value(0, &changed).unwrap(), hash);
+
+    // SHA-256 of the serialized u64, which is just its 8 little endian bytes
+    assert_eq!(hash_value(0, &0u64).unwrap()[..4], [0xaf, 0x55, 0x70, 0xf5]);
+}
+
+#[derive(Savefile, Debug, PartialEq, Clone)]
+pub enum SimulationPhase {
+    Idle,
+    Moving { speed: u32 },
+}
+
+#[derive(Savefile, Debug, PartialEq, Clone)]
+pub struct SimulationSnapshot {
+    tick: u64,
+    bodies: Vec<(f32, f32)>,
+    phase: SimulationPhase,
+    label: Option<String>,
+}
+
+#[test]
+pub fn test_diff_values() {
+    let a = SimulationSnapshot {
+        tick: 10,
+        bodies: vec![(0.0, 1.0), (2.0, 3.0)],
+        phase: SimulationPhase::Moving { speed: 5 },
+        label: Some("start".to_string()),
+    };
+    let mut b = a.clone();
+    assert!(diff_values(0, &a, &b).unwrap().is_empty());
+
+    b.bodies[1].0 = 2.5;
+    b.phase = SimulationPhase::Moving { speed: 6 };
+    b.label = Some("end".to_string());
+    let differences = diff_values(0, &a, &b).unwrap();
+    let paths: Vec<&str> = differences.iter().map(|difference| difference.path.as_str()).collect();
+    assert_eq!(paths, vec!["/bodies/1/0", "/phase/speed", "/label"]);
+    assert_eq!(differences[0].old, DynValue::F32(2.0));
+    assert_eq!(differences[0].new, DynValue::F32(2.5));
+    assert_eq!(differences[2].to_string(), "/label: \"start\" -> \"end\"");
+
+    b.phase = SimulationPhase::Idle;
+    let serialized = diff_serialized(&save_to_mem(0, &a).unwrap(), &save_to_mem(0, &b).unwrap()).unwrap();
+    assert_eq!(serialized[1].path, "/phase");
+    assert_eq!(serialized.len(), 3);
+
+    assert!(diff_serialized(&save_to_mem(0, &a).unwrap(), &save_to_mem(0, &1u32).unwrap()).is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq, Clone)]
+pub struct DeltaWorld {
+    name: String,
+    tiles: Vec<u8>,
+    entities: Vec<SimulationSnapshot>,
+    spawn: Option<(i32, i32)>,
+    phase: SimulationPhase,
+}
+
+#[test]
+pub fn test_save_and_apply_delta() {
+    let previous = DeltaWorld {
+        name: "World".to_string(),
+        tiles: vec![0; 10000],
+        entities: vec![
+            SimulationSnapshot {
+                tick: 1,
+                bodies: vec![(1.0, 1.0)],
+                phase: SimulationPhase::Idle,
+                label: None,
+            };
+            3
+        ],
+        spawn: Some((5, 5)),
+        phase: SimulationPhase::Moving { speed: 1 },
+    };
+    let mut current = previous.clone();
+    current.tiles[1234] = 7;
+    current.entities[1].bodies.push((2.0, 2.0));
+    current.entities[2].label = Some("boss".to_string());
+    current.entities.truncate(2);
+    current.spawn = Some((5, 6));
+    current.phase = SimulationPhase::Idle;
+
+    let delta = save_delta(0, &previous, &current).unwrap();
+    assert!(delta.len() < 200);
+    let mut base = previous.clone();
+    apply_delta(0, &mut base, &delta).unwrap();
+    assert_eq!(base, current);
+
+    let mut grown = current.clone();
+    grown.entities.push(previous.entities[0].clone());
+    grown.spawn = None;
+    let delta = save_delta(0, &current, &grown).unwrap();
+    apply_delta(0, &mut base, &delta).unwrap();
+    assert_eq!(base, grown);
+
+    let unchanged = save_delta(0, &grown, &grown).unwrap();
+    assert_eq!(unchanged.len(), 12);
+    assert!(apply_delta(1, &mut base, &unchanged).is_err());
+}
+
+#[test]
+pub fn test_save_with_varint_lengths() {
+    let varint = Encoding {
+        lengths: LengthEncoding::Varint,
+        ..Encoding::default()
+    };
+    let save = LazyGameSave {
+        name: "My game".to_string(),
+        world: (0..1000).map(|i| (i, i.to_string())).collect(),
+        heights: vec![3, 1, 4, 1, 5],
+        timestamp: 12345,
+    };
+    let mut bytes = Vec::new();
+    save_with_encoding(&mut bytes, 0, &save, varint).unwrap();
+    assert!(bytes.len() + 7000 < save_to_mem(0, &save).unwrap().len());
+    assert_eq!(read_header(&mut &bytes[..]).unwrap().encoding, varint);
+
+    let loaded: LazyGameSave = load_from_mem(&bytes, 0).unwrap();
+    assert_eq!(loaded.world, save.world);
+    assert_eq!(loaded.heights, save.heights);
+
+    // Retained bytes of lazy fields are converted when saved with another encoding
+    let summary: LazyGameSaveSummary = load_from_mem(&bytes, 0).unwrap();
+    let resaved: LazyGameSave = load_from_mem(&save_to_mem(0, &summary).unwrap(), 0).unwrap();
+    assert_eq!(resaved.world, save.world);
+
+    let document = Document::load(&mut &bytes[..]).unwrap();
+    assert_eq!(document.get("world/999/1"), Some(&DynValue::String("999".to_string())));
+
+    let mut chunked = Vec::new();
+    save_with_encoding(&mut chunked, 0, &ChunkedQueryResultWriter { rows: 20000 }, varint).unwrap();
+    let loaded: ChunkedQueryResult = load_from_mem(&chunked, 0).unwrap();
+    assert_eq!(loaded.rows[12345], (12345, "12345".to_string()));
+    assert_eq!(loaded.values.len(), 10000);
+
+    let mut strings = Vec::new();
+    save_with_encoding(&mut strings, 0, &vec!["a".to_string(); 300], varint).unwrap();
+    let reader = VecReader::<_, String>::new(&strings[..], 0).unwrap();
+    assert_eq!(reader.remaining(), Some(300));
+    assert!(reader.map(|item| item.unwrap()).all(|item| item == "a"));
+
+    // Values of type usize are still 8 bytes, as their schema says, so they can be read
+    // without knowing their type
+    let counted = VarintCountedV0 { count: 300, tag: 7 };
+    let mut counted_bytes = Vec::new();
+    save_with_encoding(&mut counted_bytes, 0, &counted, varint).unwrap();
+    assert_eq!(counted_bytes.len(), save_to_mem(0, &counted).unwrap().len());
+    assert_eq!(load_from_mem::<VarintCountedV0>(&counted_bytes, 0).unwrap(), counted);
+    let removed: VarintCountedV1 = load_from_mem(&counted_bytes, 1).unwrap();
+    assert_eq!(removed.tag, 7);
+    let document = Document::load(&mut &counted_bytes[..]).unwrap();
+    assert_eq!(document.get("count"), Some(&DynValue::U64(300)));
+    assert_eq!(document.get("tag"), Some(&DynValue::U8(7)));
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct VarintCountedV0 {
+    count: usize,
+    tag: u8,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct VarintCountedV1 {
+    #[savefile_versions = "..0"]
+    count: RemovedOpaque<usize>,
+    tag: u8,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct PortableCounters {
+    names: Vec<String>,
+    counts: Vec<usize>,
+    offset: isize,
+}
+
+#[test]
+pub fn test_save_with_32_bit_lengths() {
+    let fixed32 = Encoding {
+        lengths: LengthEncoding::Fixed32,
+        ..Encoding::default()
+    };
+    let counters = PortableCounters {
+        names: vec!["a".to_string(), "b".to_string()],
+        counts: vec![1, 2, usize::MAX],
+        offset: -7,
+    };
+    let mut bytes = Vec::new();
+    save_with_encoding(&mut bytes, 0, &counters, fixed32).unwrap();
+    assert_eq!(bytes.len() + 16, save_to_mem(0, &counters).unwrap().len());
+    assert_eq!(read_header(&mut &bytes[..]).unwrap().encoding, fixed32);
+    assert_eq!(load_from_mem::<PortableCounters>(&bytes, 0).unwrap(), counters);
+
+    // usize and isize are saved as 64 bits on all machines
+    assert_eq!(usize::schema(0), u64::schema(0));
+    assert_eq!(isize::schema(0), i64::schema(0));
+
+    let mut chunked = Vec::new();
+    save_with_encoding(&mut chunked, 0, &ChunkedQueryResultWriter { rows: 5000 }, fixed32).unwrap();
+    let loaded: ChunkedQueryResult = load_from_mem(&chunked, 0).unwrap();
+    assert_eq!(loaded.rows[4321], (4321, "4321".to_string()));
+    assert_eq!(loaded.values.len(), 2500);
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct SensorPacket {
+    id: u32,
+    samples: Vec<u16>,
+    calibration: [f32; 3],
+    offset: isize,
+    name: String,
+}
+
+#[test]
+pub fn test_save_big_endian() {
+    let big_endian = Encoding {
+        endianness: Endianness::Big,
+        ..Encoding::default()
+    };
+    let mut raw = Vec::new();
+    {
+        let mut serializer = Serializer::new_raw(&mut raw);
+        serializer.encoding = big_endian;
+        serializer.write_u32(0x01020304).unwrap();
+        serializer.write_usize(5).unwrap();
+    }
+    assert_eq!(raw, vec![1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 5]);
+
+    let packet = SensorPacket {
+        id: 0xdeadbeef,
+        samples: (0..1000).collect(),
+        calibration: [1.5, -2.0, 0.25],
+        offset: -3,
+        name: "thermometer".to_string(),
+    };
+    let mut bytes = Vec::new();
+    save_with_encoding(&mut bytes, 0, &packet, big_endian).unwrap();
+    assert_eq!(read_header(&mut &bytes[..]).unwrap().encoding, big_endian);
+    assert_eq!(load_from_mem::<SensorPacket>(&bytes, 0).unwrap(), packet);
+    assert_ne!(bytes, save_to_mem(0, &packet).unwrap());
+
+    let document = Document::load(&mut &bytes[..]).unwrap();
+    assert_eq!(document.get("samples/999"), Some(&DynValue::U16(999)));
+    assert_eq!(document.get("id"), Some(&DynValue::U32(0xdeadbeef)));
+}
+
+#[derive(Savefile, Debug, PartialEq, Clone, Copy)]
+pub enum Terrain {
+    Grass,
+    Sand,
+    Water,
+    Rock,
+    #[savefile_versions = "1.."]
+    Lava,
+}
+
+#[derive(Savefile, Debug, PartialEq, Clone)]
+pub struct PathCell {
+    x: u16,
+    #[savefile_packed]
+    walkable: bool,
+    #[savefile_packed]
+    visible: bool,
+    #[savefile_packed]
+    terrain: Terrain,
+    height: u8,
+    #[savefile_packed]
+    explored: bool,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub enum Marker {
+    Door {
+        #[savefile_packed]
+        open: bool,
+        #[savefile_packed]
+        locked: bool,
+    },
+    Wall,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct PathMap {
+    cells: Vec<PathCell>,
+    markers: Vec<Marker>,
+}
+
+#[derive(Savefile)]
+pub struct LazyPathMap {
+    cells: Lazy<Vec<PathCell>>,
+    markers: Vec<Marker>,
+}
+
+#[test]
+pub fn test_bit_packed_fields() {
+    assert_eq!(<Terrain as BitPacked>::bit_width(0), 2);
+    assert_eq!(<Terrain as BitPacked>::bit_width(1), 3);
+    // The first three packed fields share a byte, and the last one has its own
+    assert_eq!(PathCell::schema(1).serialized_size(), Some(5));
+
+    let map = PathMap {
+        cells: (0..100)
+            .map(|x| PathCell {
+                x,
+                walkable: x % 2 == 0,
+                visible: x % 3 == 0,
+                terrain: [Terrain::Grass, Terrain::Sand, Terrain::Water, Terrain::Rock, Terrain::Lava][x as usize % 5],
+                height: x as u8,
+                explored: x % 7 == 0,
+            })
+            .collect(),
+        markers: vec![
+            Marker::Door {
+                open: true,
+                locked: false,
+            },
+            Marker::Wall,
+            Marker::Door {
+                open: false,
+                locked: true,
+            },
+        ],
+    };
+    let bytes = save_to_mem(1, &map).unwrap();
+    assert_eq!(load_from_mem::<PathMap>(&bytes, 1).unwrap(), map);
+
+    // Lava doesn't exist in version 0, and doesn't fit in its bits
+    assert!(save_to_mem(0, &map).is_err());
+
+    let document = Document::load(&mut &bytes[..]).unwrap();
+    assert_eq!(document.get("cells/42/walkable"), Some(&DynValue::Bool(true)));
+    assert_eq!(document.get("cells/42/explored"), Some(&DynValue::Bool(true)));
+    assert_eq!(document.get("cells/43/visible"), Some(&DynValue::Bool(false)));
+    assert_eq!(document.get("markers/2/locked"), Some(&DynValue::Bool(true)));
+    match document.get("cells/44/terrain") {
+        Some(&DynValue::Enum { ref variant, .. }) => assert_eq!(variant, "Lava"),
+        other => panic!("Unexpected terrain {:?}", other),
+    }
+    let mut resaved = Vec::new();
+    document.save(&mut resaved).unwrap();
+    assert_eq!(load_from_mem::<PathMap>(&resaved, 1).unwrap(), map);
+
+    let mut lazy: LazyPathMap = load_from_mem(&bytes, 1).unwrap();
+    assert_eq!(lazy.markers, map.markers);
+    assert_eq!(lazy.cells.get().unwrap()[99], map.cells[99]);
+
+    let mut changed = map.cells.clone();
+    changed[5].visible = true;
+    let delta = save_delta(1, &map.cells, &changed).unwrap();
+    let mut patched = map.cells.clone();
+    apply_delta(1, &mut patched, &delta).unwrap();
+    assert_eq!(patched, changed);
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct ItemStack {
+    item_id: String,
+    count: u32,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct Inventory {
+    owner: String,
+    items: Vec<ItemStack>,
+    tags: Vec<String>,
+}
+
+#[derive(Savefile)]
+pub struct LazyInventory {
+    owner: String,
+    items: Lazy<Vec<ItemStack>>,
+    tags: Vec<String>,
+}
+
+#[test]
+pub fn test_save_with_interned_strings() {
+    let interned = Encoding {
+        intern_strings: true,
+        ..Encoding::default()
+    };
+    let ids = ["minecraft:cobblestone", "minecraft:oak_planks", "minecraft:torch"];
+    let inventory = Inventory {
+        owner: "minecraft:torch".to_string(),
+        items: (0..1000)
+            .map(|i| ItemStack {
+                item_id: ids[i % 3].to_string(),
+                count: i as u32,
+            })
+            .collect(),
+        tags: vec!["sorted".to_string(), "minecraft:oak_planks".to_string(), "sorted".to_string()],
+    };
+    let mut bytes = Vec::new();
+    save_with_encoding(&mut bytes, 0, &inventory, interned).unwrap();
+    let plain = save_to_mem(0, &inventory).unwrap();
+    assert!(bytes.len() * 2 < plain.len());
+    assert_eq!(read_header(&mut &bytes[..]).unwrap().encoding, interned);
+    assert_eq!(load_from_mem::<Inventory>(&bytes, 0).unwrap(), inventory);
+
+    let document = Document::load(&mut &bytes[..]).unwrap();
+    assert_eq!(
+        document.get("items/998/item_id"),
+        Some(&DynValue::String("minecraft:torch".to_string()))
+    );
+    assert_eq!(document.get("tags/2"), Some(&DynValue::String("sorted".to_string())));
+
+    // Lazy values are loaded immediately, since their strings refer to earlier strings
+    let mut lazy: LazyInventory = load_from_mem(&bytes, 0).unwrap();
+    assert!(lazy.items.is_loaded());
+    assert_eq!(lazy.tags, inventory.tags);
+    assert_eq!(lazy.items.get().unwrap()[500], inventory.items[500]);
+
+    assert!(load_borrowed::<Inventory>(&bytes, 0).is_err());
+}
+
+#[repr(C)]
+#[derive(ReprC, Savefile, Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    position: [f32; 3],
+    generation: std::num::Wrapping<u32>,
+}
+
+#[test]
+pub fn test_repr_c_arrays_tuples_and_wrapping() {
+    use std::num::Wrapping;
+    assert!(<[f32; 3]>::repr_c_layout(0).is_yes());
+    assert!(<(u32, u32)>::repr_c_layout(0).is_yes());
+    assert!(<Wrapping<u64>>::repr_c_layout(0).is_yes());
+    assert!(Vertex::repr_c_layout(0).is_yes());
+    // Padding and non-raw-copy fields prevent the optimization
+    assert!(!<(u8, u32)>::repr_c_layout(0).is_yes());
+    assert!(!<(u32, String)>::repr_c_layout(0).is_yes());
+    assert!(!<[String; 2]>::repr_c_layout(0).is_yes());
+
+    assert_roundtrip((0..100).map(|i| [i as f32, -(i as f32), 0.5]).collect::<Vec<_>>());
+    assert_roundtrip((0..100u32).map(|i| (i, i * i)).collect::<Vec<_>>());
+    assert_roundtrip((0..100u8).map(|i| (i, i as u32)).collect::<Vec<_>>());
+    assert_roundtrip((0..100).map(Wrapping).collect::<Vec<Wrapping<i16>>>());
+    let vertices: Vec<Vertex> = (0..100)
+        .map(|i| Vertex {
+            position: [i as f32, 1.0, 2.0],
+            generation: Wrapping(u32::MAX) + Wrapping(i),
+        })
+        .collect();
+    let bytes = save_to_mem(0, &vertices).unwrap();
+    assert_eq!(load_from_mem::<Vec<Vertex>>(&bytes, 0).unwrap(), vertices);
+
+    // The raw bytes are the same as when the fields are written one by one
+    let tuples: Vec<(u32, u32)> = (0..10).map(|i| (i, i + 1)).collect();
+    let mut raw = Vec::new();
+    tuples.serialize(&mut Serializer::new_raw(&mut raw)).unwrap();
+    let mut expected = Vec::new();
+    {
+        let mut serializer = Serializer::new_raw(&mut expected);
+        serializer.write_usize(tuples.len()).unwrap();
+        for &(a, b) in &tuples {
+            serializer.write_u32(a).unwrap();
+            serializer.write_u32(b).unwrap();
+        }
+    }
+    assert_eq!(raw, expected);
+}
+
+#[test]
+pub fn test_repr_c_map_pairs() {
+    use std::collections::HashMap;
+    let index: HashMap<u32, u64> = (0..20000u32).map(|i| (i * 7, (i as u64) << 33)).collect();
+    let bytes = save_to_mem(0, &index).unwrap();
+    assert_eq!(load_from_mem::<HashMap<u32, u64>>(&bytes, 0).unwrap(), index);
+
+    // The pairs are written exactly like when serialized one by one
+    let mut raw = Vec::new();
+    index.serialize(&mut Serializer::new_raw(&mut raw)).unwrap();
+    let mut expected = Vec::new();
+    {
+        let mut serializer = Serializer::new_raw(&mut expected);
+        serializer.write_usize(index.len()).unwrap();
+        for (k, v) in &index {
+            serializer.write_u32(*k).unwrap();
+            serializer.write_u64(*v).unwrap();
+        }
+    }
+    assert_eq!(raw, expected);
+
+    let ranges: BTreeMap<u16, [f32; 2]> = (0..1000u16).map(|i| (i, [i as f32, 0.5])).collect();
+    assert_roundtrip(ranges.clone());
+    let big_endian = Encoding {
+        endianness: Endianness::Big,
+        ..Encoding::default()
+    };
+    let mut swapped = Vec::new();
+    save_with_encoding(&mut swapped, 0, &ranges, big_endian).unwrap();
+    assert_eq!(load_from_mem::<BTreeMap<u16, [f32; 2]>>(&swapped, 0).unwrap(), ranges);
+}
+
+#[test]
+pub fn test_archive_load_parallel() {
+    let mut archive = SaveArchive::new();
+    let mut names = Vec::new();
+    for chunk in 0..40u32 {
+        let name = format!("chunks/{}", chunk);
+        archive
+            .insert(&name, 1, &(0..5000).map(|i| (i * chunk, i.to_string())).collect::<Vec<_>>())
+            .unwrap();
+        names.push(name);
+    }
+    archive.insert("header", 0, &"Not a chunk".to_string()).unwrap();
+    let mut data = Vec::new();
+    archive.save(&mut data).unwrap();
+
+    let mut reader = ArchiveReader::new(Cursor::new(data)).unwrap();
+    let names: Vec<&str> = names.iter().map(|name| name.as_str()).rev().collect();
+    let chunks: Vec<Vec<(u32, String)>> = reader.load_parallel(&names, 1).unwrap();
+    assert_eq!(chunks.len(), 40);
+    assert_eq!(chunks[0][4999], (4999 * 39, "4999".to_string()));
+    assert_eq!(chunks[39][7], (0, "7".to_string()));
+    assert!(reader.load_parallel::<Vec<(u32, String)>>(&[], 1).unwrap().is_empty());
+    // A single entry is decoded without spawning threads
+    let single: Vec<Vec<(u32, String)>> = reader.load_parallel(&["chunks/5"], 1).unwrap();
+    assert_eq!(single[0][2], (10, "2".to_string()));
+
+    assert!(reader.load_parallel::<Vec<(u32, String)>>(&["chunks/3", "header"], 1).is_err());
+    assert!(reader.load_parallel::<Vec<(u32, String)>>(&["chunks/3", "missing"], 1).is_err());
+}
+
+#[test]
+pub fn test_read_string_into() {
+    let large = "x".repeat(100_000);
+    let mut raw = Vec::new();
+    {
+        let mut serializer = Serializer::new_raw(&mut raw);
+        serializer.write_string("first").unwrap();
+        serializer.write_string("second, a bit longer").unwrap();
+        serializer.write_string(&large).unwrap();
+        serializer.write_usize(2).unwrap();
+        serializer.write_bytes(&[0xff, 0xfe]).unwrap();
+    }
+    let mut reader = &raw[..];
+    let mut deserializer = Deserializer::new_raw(&mut reader);
+    let mut string = String::with_capacity(100);
+    let capacity = string.capacity();
+    deserializer.read_string_into(&mut string).unwrap();
+    assert_eq!(string, "first");
+    deserializer.read_string_into(&mut string).unwrap();
+    assert_eq!(string, "second, a bit longer");
+    assert_eq!(string.capacity(), capacity);
+    assert_eq!(deserializer.read_string().unwrap(), large);
+    assert!(deserializer.read_string_into(&mut string).is_err());
+    assert!(string.is_empty());
+
+    let interned = Encoding {
+        intern_strings: true,
+        ..Encoding::default()
+    };
+    let names = vec!["a".to_string(), "b".to_string(), "a".to_string(), large.clone()];
+    let mut bytes = Vec::new();
+    save_with_encoding(&mut bytes, 0, &names, interned).unwrap();
+    let mut loaded = vec![String::new(); 4];
+    let mut reader = &bytes[..];
+    let header = read_header(&mut reader).unwrap();
+    Schema::deserialize(&mut Deserializer::new_raw(&mut reader)).unwrap();
+    let mut deserializer = Deserializer::new_raw(&mut reader);
+    deserializer.encoding = header.encoding;
+    loaded.deserialize_in_place(&mut deserializer).unwrap();
+    assert_eq!(loaded, names);
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct DialogueLine {
+    speaker: String,
+    text: String,
+}
+
+#[test]
+pub fn test_load_unchecked_utf8() {
+    let lines: Vec<DialogueLine> = (0..1000)
+        .map(|i| DialogueLine {
+            speaker: format!("Сказочник {}", i % 7),
+            text: format!("Line {} — «{}»", i, "ünïcödé ".repeat(i % 20)),
+        })
+        .collect();
+    let mut data = Vec::new();
+    save_checksummed(&mut data, 0, &lines).unwrap();
+    let loaded: Vec<DialogueLine> = unsafe { load_unchecked_utf8(&mut &data[..], 0) }.unwrap();
+    assert_eq!(loaded, lines);
+
+    let mut raw = Vec::new();
+    Serializer::new_raw(&mut raw).write_string("trusted ✓").unwrap();
+    let mut reader = &raw[..];
+    let mut deserializer = Deserializer::new_raw(&mut reader);
+    unsafe { deserializer.set_unchecked_utf8(true) };
+    assert_eq!(deserializer.read_string().unwrap(), "trusted ✓");
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct ChatMessage {
+    #[savefile_versions = "1.."]
+    channel: u32,
+    body: String,
+}
+
+#[test]
+pub fn test_schema_cache() {
+    let first = schema_of::<ChatMessage>(1);
+    let second = schema_of::<ChatMessage>(1);
+    assert!(std::sync::Arc::ptr_eq(&first, &second));
+    assert_eq!(*first, ChatMessage::schema(1));
+
+    let old = schema_of::<ChatMessage>(0);
+    assert!(!std::sync::Arc::ptr_eq(&first, &old));
+    assert_eq!(*old, ChatMessage::schema(0));
+
+    // Generic types have no cache, their schema is built each time
+    assert!(<Vec<ChatMessage>>::schema_cache().is_none());
+    assert_eq!(*schema_of::<Vec<ChatMessage>>(1), <Vec<ChatMessage>>::schema(1));
+
+    for i in 0..100 {
+        let message = ChatMessage {
+            channel: i % 3,
+            body: format!("message {}", i),
+        };
+        let mut data = Vec::new();
+        save(&mut data, 1, &message).unwrap();
+        let loaded: ChatMessage = load(&mut &data[..], 1).unwrap();
+        assert_eq!(loaded, message);
+    }
+}
+
+#[derive(Savefile)]
+pub struct ChatLog {
+    pinned: ChatMessage,
+    messages: Vec<ChatMessage>,
+}
+
+#[test]
+pub fn test_schema_shares_subtrees() {
+    let message_schema = schema_of::<ChatMessage>(1);
+    match ChatLog::schema(1) {
+        Schema::Struct(log) => {
+            assert!(std::sync::Arc::ptr_eq(&log.fields[0].value, &message_schema));
+            match &*log.fields[1].value {
+                Schema::Vector(item) => assert!(std::sync::Arc::ptr_eq(item, &message_schema)),
+                other => panic!("Unexpected schema {:?}", other),
+            }
+            let copy = log.clone();
+            assert!(std::sync::Arc::ptr_eq(&copy.fields[0].value, &log.fields[0].value));
+        }
+        other => panic!("Unexpected schema {:?}", other),
+    }
+}
+
+struct Calculator {
+    calls: u32,
+}
+
+impl AbiProvider for Calculator {
+    fn version() -> u32 {
+        0
+    }
+    fn methods(version: u32) -> Vec<AbiMethod> {
+        vec![
+            AbiMethod::new::<(u32, u32), u32>("add", version),
+            AbiMethod::new::<(String,), String>("greet", version),
+            AbiMethod::new::<(), u32>("calls", version),
+            AbiMethod::new::<(), ()>("explode", version),
+        ]
+    }
+    fn call(
+        &mut self,
+        method: u32,
+        arguments: &mut Deserializer<impl std::io::Read>,
+        result: &mut Serializer<impl std::io::Write>,
+    ) -> Result<(), SavefileError> {
+        self.calls += 1;
+        match method {
+            0 => {
+                let (a, b) = <(u32, u32)>::deserialize(arguments)?;
+                a.checked_add(b)
+                    .ok_or_else(|| SavefileError::GeneralError { msg: "Overflow".to_string() })?
+                    .serialize(result)
+            }
+            1 => {
+                let (name,) = <(String,)>::deserialize(arguments)?;
+                format!("Hello, {}!", name).serialize(result)
+            }
+            2 => self.calls.serialize(result),
+            _ => panic!("Kaboom"),
+        }
+    }
+}
+
+fn calculator_methods(version: u32) -> Vec<AbiMethod> {
+    vec![
+        AbiMethod::new::<(u32, u32), u32>("add", version),
+        AbiMethod::new::<(String,), String>("greet", version),
+        AbiMethod::new::<(), u32>("calls", version),
+        AbiMethod::new::<(), ()>("explode", version),
+        AbiMethod::new::<(), ()>("newer_method", version),
+    ]
+}
+
+#[test]
+pub fn test_abi_connection() {
+    let connection = unsafe { AbiConnection::new(AbiExport::new(Calculator { calls: 0 }), 1, calculator_methods) }.unwrap();
+    assert_eq!(connection.version(), 0);
+    assert_eq!(connection.call::<_, u32>("add", &(40u32, 2u32)).unwrap(), 42);
+    assert_eq!(
+        connection.call::<_, String>("greet", &("plugin".to_string(),)).unwrap(),
+        "Hello, plugin!"
+    );
+    let overflow = connection.call::<_, u32>("add", &(u32::MAX, 1u32)).unwrap_err();
+    assert!(overflow.to_string().contains("Overflow"));
+    let panicked = connection.call::<_, ()>("explode", &()).unwrap_err();
+    assert!(panicked.to_string().contains("Kaboom"));
+    assert!(!connection.has_method("newer_method"));
+    assert!(connection.call::<_, ()>("newer_method", &()).is_err());
+    assert_eq!(connection.call::<_, u32>("calls", &()).unwrap(), 5);
+
+    let incompatible = unsafe {
+        AbiConnection::new(AbiExport::new(Calculator { calls: 0 }), 0, |version| {
+            vec![AbiMethod::new::<(u32, u64), u32>("add", version)]
+        })
+    };
+    match incompatible {
+        Err(SavefileError::IncompatibleSchema { message }) => assert!(message.contains("add")),
+        _ => panic!("Expected an incompatible schema error"),
+    }
+}
+
+#[test]
+pub fn test_rpc_client_and_server() {
+    use savefile::stream::{serve, RpcClient};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut calculator = Calculator { calls: 0 };
+        serve(&mut stream, &mut calculator).unwrap();
+        calculator.calls
+    });
+
+    let stream = std::net::TcpStream::connect(address).unwrap();
+    let mut client = RpcClient::connect(stream, 1, calculator_methods).unwrap();
+    assert_eq!(client.version(), 0);
+    assert_eq!(client.call::<_, u32>("add", &(40u32, 2u32)).unwrap(), 42);
+    assert_eq!(
+        client.call::<_, String>("greet", &("network".to_string(),)).unwrap(),
+        "Hello, network!"
+    );
+    assert!(client.call::<_, ()>("explode", &()).unwrap_err().to_string().contains("Kaboom"));
+    assert!(client.call::<_, ()>("newer_method", &()).is_err());
+    assert_eq!(client.call::<_, u32>("calls", &()).unwrap(), 4);
+    drop(client);
+    assert_eq!(server.join().unwrap(), 4);
+}
+
+#[derive(Savefile)]
+pub struct SensorReading {
+    sensor: u32,
+    label: String,
+    samples: Vec<i16>,
+    calibration: Option<f64>,
+}
+
+#[test]
+pub fn test_ffi_reader() {
+    use savefile::ffi::*;
+    use std::ffi::CStr;
+    let reading = SensorReading {
+        sensor: 7,
+        label: "thermometer".to_string(),
+        samples: vec![-3, 12, 400],
+        calibration: Some(0.5),
+    };
+    let mut data = Vec::new();
+    save_checksummed(&mut data, 2, &reading).unwrap();
+
+    unsafe {
+        let reader = savefile_reader_new(data.as_ptr(), data.len());
+        let mut header = SavefileHeaderInfo {
+            version: 0,
+            has_schema: false,
+            has_schema_hash: false,
+            has_checksum: false,
+        };
+        assert_eq!(savefile_read_header(reader, &mut header), 0);
+        assert_eq!(header.version, 2);
+        assert!(header.has_schema && header.has_checksum && !header.has_schema_hash);
+
+        let schema = savefile_read_schema(reader);
+        assert!(!schema.is_null());
+        assert_eq!(savefile_schema_kind(schema), SAVEFILE_SCHEMA_STRUCT);
+        assert_eq!(CStr::from_ptr(savefile_schema_name(schema)).to_str().unwrap(), "SensorReading");
+        assert_eq!(savefile_schema_child_count(schema), 4);
+        assert_eq!(CStr::from_ptr(savefile_schema_child_name(schema, 2)).to_str().unwrap(), "samples");
+        let samples = savefile_schema_child(schema, 2);
+        assert_eq!(savefile_schema_kind(samples), SAVEFILE_SCHEMA_VECTOR);
+        assert_eq!(savefile_schema_primitive(savefile_schema_child(samples, 0)), 3);
+        assert_eq!(savefile_schema_kind(savefile_schema_child(schema, 3)), SAVEFILE_SCHEMA_OPTION);
+        assert!(savefile_schema_child(schema, 4).is_null());
+        savefile_schema_free(schema);
+
+        let mut sensor = 0u32;
+        assert_eq!(savefile_read_u32(reader, &mut sensor), 0);
+        assert_eq!(sensor, 7);
+        let mut label = std::ptr::null();
+        let mut label_len = 0;
+        assert_eq!(savefile_read_string(reader, &mut label, &mut label_len), 0);
+        assert_eq!(std::slice::from_raw_parts(label, label_len), b"thermometer");
+        let mut length = 0u64;
+        assert_eq!(savefile_read_length(reader, &mut length), 0);
+        let mut samples = Vec::new();
+        for _ in 0..length {
+            let mut sample = 0i16;
+            assert_eq!(savefile_read_i16(reader, &mut sample), 0);
+            samples.push(sample);
+        }
+        assert_eq!(samples, vec![-3, 12, 400]);
+        let mut present = 0u8;
+        assert_eq!(savefile_read_u8(reader, &mut present), 0);
+        let mut calibration = 0.0f64;
+        assert_eq!(savefile_read_f64(reader, &mut calibration), 0);
+        assert_eq!((present, calibration), (1, 0.5));
+
+        // Only the checksum of the data remains
+        assert_eq!(savefile_reader_position(reader), data.len() - 4);
+        assert_eq!(savefile_read_u64(reader, &mut length), -1);
+        assert!(!CStr::from_ptr(savefile_reader_error(reader)).to_bytes().is_empty());
+        savefile_reader_free(reader);
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub enum QuestState {
+    Hidden,
+    Active { step: u16, notes: Vec<String> },
+    Done(Option<i64>),
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct QuestLog {
+    quests: std::collections::HashMap<String, QuestState>,
+    #[savefile_packed]
+    tracked: bool,
+    #[savefile_packed]
+    hardcore: bool,
+    position: [f32; 3],
+    #[savefile_versions = "1.."]
+    title: String,
+}
+
+/// Serializes only half of its value, which roundtrip_check should notice
+#[derive(Debug, PartialEq)]
+pub struct Halving(u32);
+
+impl WithSchema for Halving {
+    fn schema(version: u32) -> Schema {
+        u32::schema(version)
+    }
+}
+impl Serialize for Halving {
+    fn serialize(&self, serializer: &mut Serializer<impl std::io::Write>) -> Result<(), SavefileError> {
+        serializer.write_u32(self.0 / 2)
+    }
+}
+impl Deserialize for Halving {
+    fn deserialize(deserializer: &mut Deserializer<impl std::io::Read>) -> Result<Self, SavefileError> {
+        Ok(Halving(deserializer.read_u32()?))
+    }
+}
+
+#[test]
+pub fn test_roundtrip_check() {
+    roundtrip_check::<QuestLog>(1, 200).unwrap();
+    roundtrip_check::<(u8, Vec<Option<String>>, std::collections::BTreeMap<u64, bool>)>(0, 100).unwrap();
+
+    let err = roundtrip_check::<Halving>(0, 100).unwrap_err();
+    assert!(err.to_string().contains("changed when saved and loaded again"));
+
+    let schema = QuestState::schema(0);
+    for seed in 0..50 {
+        let value = random_value(&schema, seed).unwrap();
+        assert_eq!(value, random_value(&schema, seed).unwrap());
+        match value {
+            DynValue::Enum { ref variant, .. } => assert!(["Hidden", "Active", "Done"].contains(&variant.as_str())),
+            _ => panic!("Expected an enum value"),
+        }
+    }
+    assert!(random_value(&Schema::Undefined, 0).is_err());
+}
+
+#[test]
+pub fn test_roundtrip_check_skips_rejected_values() {
+    // Values with unsorted or mismatched keys are skipped, but the rest are checked
+    roundtrip_check::<DenseMap<std::collections::BTreeMap<u8, u16>>>(0, 200).unwrap();
+    // Random lengths almost never match the bits, so nothing can be checked
+    let err = roundtrip_check::<PackedBools<Vec<bool>>>(0, 20).unwrap_err();
+    assert!(err.to_string().contains("couldn't be loaded"));
+}
+
+#[test]
+pub fn test_type_strategy() {
+    use proptest::strategy::{Strategy, ValueTree};
+    use proptest::test_runner::TestRunner;
+    let mut runner = TestRunner::deterministic();
+    let strategy = type_strategy::<QuestLog>(1).unwrap();
+    for _ in 0..50 {
+        let quest_log = strategy.new_tree(&mut runner).unwrap().current();
+        let bytes = save_to_mem(1, &quest_log).unwrap();
+        assert_eq!(load_from_mem::<QuestLog>(&bytes, 1).unwrap(), quest_log);
+    }
+    let keys = type_strategy::<DenseMap<std::collections::BTreeMap<u8, u16>>>(0).unwrap();
+    for _ in 0..50 {
+        keys.new_tree(&mut runner).unwrap();
+    }
+    assert!(value_strategy(&Schema::Undefined).is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq, Clone)]
+pub struct Waypoint {
+    name: String,
+    position: (i32, i32),
+    visited: Option<u64>,
+}
+
+#[test]
+pub fn test_arbitrary_value() {
+    fn roundtrips(waypoint: ArbitraryValue<Waypoint>) -> bool {
+        let bytes = save_to_mem(0, &waypoint.0).unwrap();
+        load_from_mem::<Waypoint>(&bytes, 0).unwrap() == waypoint.0
+    }
+    quickcheck::QuickCheck::new().quickcheck(roundtrips as fn(ArbitraryValue<Waypoint>) -> bool);
+}
+
+#[test]
+pub fn test_fuzz_corpus() {
+    let schema = QuestLog::schema(1);
+    let corpus = fuzz_corpus(&schema, 1, 40, 100).unwrap();
+    assert_eq!(corpus.len(), 80);
+    assert_eq!(corpus, fuzz_corpus(&schema, 1, 40, 100).unwrap());
+    // Seeds wrap around instead of overflowing
+    assert_eq!(fuzz_corpus(&schema, 1, 4, u64::MAX - 1).unwrap().len(), 8);
+    for file in &corpus[..40] {
+        load_from_mem::<QuestLog>(file, 1).unwrap();
+    }
+    let mut rejected = 0;
+    for file in &corpus[40..] {
+        if load_from_mem::<QuestLog>(file, 1).is_err() {
+            rejected += 1;
+        }
+    }
+    assert!(rejected > 0);
+    for seed in 0..400 {
+        let _ = load_from_mem::<QuestLog>(&mutate_bytes(&corpus[(seed % 40) as usize], seed), 1);
+    }
+    assert!(mutate_bytes(&[], 0).is_empty());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct GoldenPlayerV0 {
+    name: String,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct GoldenPlayer {
+    name: String,
+    #[savefile_versions = "1.."]
+    level: u32,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct GoldenPlayerBroken {
+    name: String,
+    level: u32,
+}
+
+#[test]
+pub fn test_check_golden() {
+    let dir = "test_golden";
+    let _ = std::fs::remove_dir_all(dir);
+    let player_v0 = GoldenPlayerV0 { name: "Ada".into() };
+    check_golden(dir, "player", 0, &player_v0).unwrap();
+    check_golden(dir, "player", 0, &player_v0).unwrap();
+
+    let player = GoldenPlayer {
+        name: "Ada".into(),
+        level: 7,
+    };
+    check_golden(dir, "player", 1, &player).unwrap();
+    assert!(std::path::Path::new(dir).join("player_v1.savefile").exists());
+    check_golden(dir, "player", 1, &player).unwrap();
+
+    let err = check_golden(
+        dir,
+        "player",
+        1,
+        &GoldenPlayer {
+            name: "Ada".into(),
+            level: 8,
+        },
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("player_v1.savefile"));
+    assert!(err.to_string().contains("Delete the file"));
+
+    let err = check_golden(
+        dir,
+        "player",
+        1,
+        &GoldenPlayerBroken {
+            name: "Ada".into(),
+            level: 7,
+        },
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("player_v0.savefile"));
+
+    check_golden(dir, "player_stats", 1, &player).unwrap();
+    assert!(std::path::Path::new(dir).join("player_stats_v1.savefile").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub enum JsonItem {
+    Empty,
+    Potion { strength: u8 },
+    Note(String),
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct JsonPlayerSave {
+    name: String,
+    gold: i64,
+    speed: f32,
+    items: Vec<JsonItem>,
+    companion: Option<String>,
+    position: (u16, u16),
+}
+
+#[test]
+pub fn test_json_conversion() {
+    let save = JsonPlayerSave {
+        name: "Ada \"the brave\" \u{1f600}\n".into(),
+        gold: -12,
+        speed: 1.5,
+        items: vec![
+            JsonItem::Empty,
+            JsonItem::Potion { strength: 3 },
+            JsonItem::Note("x".into()),
+        ],
+        companion: None,
+        position: (3, 4),
+    };
+    let bytes = save_to_mem(1, &save).unwrap();
+    let json = to_json(&mut &bytes[..]).unwrap();
+    assert!(json.contains("\"gold\":-12"));
+    assert!(json.contains("{\"Potion\":{\"strength\":3}}"));
+
+    let schema = schema_of::<JsonPlayerSave>(1);
+    let restored: JsonPlayerSave = load_from_mem(&from_json(&schema, 1, &json).unwrap(), 1).unwrap();
+    assert_eq!(restored, save);
+
+    let edited = json
+        .replace("\"gold\":-12", "\"gold\": 100")
+        .replace("\"companion\":null", "\"companion\" : \"Bob\\u00e9\\ud83d\\ude00\"");
+    let restored: JsonPlayerSave = load_from_mem(&from_json(&schema, 1, &edited).unwrap(), 1).unwrap();
+    assert_eq!(restored.gold, 100);
+    assert_eq!(restored.companion, Some("Bob\u{e9}\u{1f600}".to_string()));
+
+    let err = from_json(&schema, 1, &json.replace("\"strength\":3", "\"strength\":300")).unwrap_err();
+    assert!(err.to_string().contains("items/1/strength"));
+    assert!(from_json(&schema, 1, &json.replace("\"gold\":-12,", "")).is_err());
+    assert!(from_json(&schema, 1, &json.replace("\"gold\":-12", "\"gold\":-12,\"silver\":1")).is_err());
+    assert!(from_json(&schema, 1, &json.replace("\"Empty\"", "\"Broken\"")).is_err());
+    assert!(from_json(&schema, 1, &format!("{} x", json)).is_err());
+    assert!(from_json(&schema, 1, &"[".repeat(100000)).is_err());
+
+    let unpaired = json.replace("\"companion\":null", "\"companion\":\"\\ud83d\\u0041\"");
+    let err = from_json(&schema, 1, &unpaired).unwrap_err();
+    assert!(err.to_string().contains("Unpaired surrogate"));
+    let duplicated = json.replace("\"gold\":-12", "\"gold\":-12,\"gold\":5");
+    let err = from_json(&schema, 1, &duplicated).unwrap_err();
+    assert!(err.to_string().contains("Duplicate key \"gold\""));
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub enum ProtoShape {
+    Point,
+    Circle { radius: f32 },
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct ProtoDrawing {
+    id: u32,
+    offset: i16,
+    label: String,
+    shapes: Vec<ProtoShape>,
+    layers: Vec<Vec<u8>>,
+    note: Option<String>,
+}
+
+#[test]
+pub fn test_proto_export() {
+    let schema = schema_of::<ProtoDrawing>(0);
+    let proto = schema.to_proto("drawings").unwrap();
+    assert!(proto.starts_with("syntax = \"proto3\";\n\npackage drawings;\n"));
+    assert!(proto.contains("message ProtoDrawing {\n  uint32 id = 1;\n  sint32 offset = 2;\n  string label = 3;\n  repeated ProtoShape shapes = 4;\n  repeated ProtoDrawing_layers_Value layers = 5;\n  optional string note = 6;\n}\n"));
+    assert!(proto.contains("message ProtoShape {\n  oneof variant {\n    ProtoShape_Point Point = 1;\n    ProtoShape_Circle Circle = 2;\n  }\n}\n"));
+    assert!(proto.contains("message ProtoShape_Circle {\n  float radius = 1;\n}\n"));
+    assert!(proto.contains("message ProtoDrawing_layers_Value {\n  repeated uint32 value = 1;\n}\n"));
+    assert!(proto.trim_end().ends_with("optional string note = 6;\n}"));
+
+    let drawing = ProtoDrawing {
+        id: 150,
+        offset: -2,
+        label: "ab".into(),
+        shapes: vec![ProtoShape::Point, ProtoShape::Circle { radius: 1.0 }],
+        layers: vec![vec![1, 2], vec![]],
+        note: None,
+    };
+    let bytes = save_to_mem(0, &drawing).unwrap();
+    let document = Document::load(&mut &bytes[..]).unwrap();
+    assert_eq!(
+        document.value.to_protobuf(&document.schema).unwrap(),
+        vec![
+            0x08, 0x96, 0x01, // id
+            0x10, 0x03, // offset
+            0x1a, 0x02, b'a', b'b', // label
+            0x22, 0x02, 0x0a, 0x00, // Point
+            0x22, 0x07, 0x12, 0x05, 0x0d, 0x00, 0x00, 0x80, 0x3f, // Circle
+            0x2a, 0x04, 0x08, 0x01, 0x08, 0x02, // first layer
+            0x2a, 0x00, // second layer
+        ]
+    );
+
+    let root = schema_of::<Vec<Option<u64>>>(0);
+    assert!(root
+        .to_proto("p")
+        .unwrap()
+        .contains("message Root {\n  repeated Root_value_Value value = 1;\n}\n"));
+    assert_eq!(
+        DynValue::Vector(vec![DynValue::Option(Some(Box::new(DynValue::U64(5)))), DynValue::Option(None)])
+            .to_protobuf(&root)
+            .unwrap(),
+        vec![0x0a, 0x02, 0x08, 0x05, 0x0a, 0x00]
+    );
+    assert!(DynValue::U8(1).to_protobuf(&schema).is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct HistoryPlayer {
+    #[savefile_versions = "..0"]
+    level: Removed<u32>,
+    name: String,
+    #[savefile_versions = "1.."]
+    score: u64,
+    #[savefile_versions = "2.."]
+    tags: Vec<String>,
+}
+
+#[test]
+pub fn test_schema_history() {
+    let history = SchemaHistory::of::<HistoryPlayer>(2);
+    assert_eq!(history.current_version, 2);
+    assert_eq!(history.root.len(), 1);
+    let fields = match &history.root[0].value {
+        HistoryNode::Struct { fields, .. } => fields,
+        other => panic!("Expected a struct, got {:?}", other),
+    };
+    let ranges: Vec<_> = fields
+        .iter()
+        .map(|field| (field.name.as_str(), field.first_version, field.last_version))
+        .collect();
+    assert_eq!(
+        ranges,
+        vec![("level", 0, 0), ("name", 0, 2), ("score", 1, 2), ("tags", 2, 2)]
+    );
+    for version in 0..=2 {
+        assert_eq!(history.schema(version).unwrap(), *schema_of::<HistoryPlayer>(version));
+    }
+    assert!(history.schema(3).is_err());
+
+    let player = HistoryPlayer {
+        level: Removed::new(),
+        name: "Ann".into(),
+        score: 12,
+        tags: vec!["new".into()],
+    };
+    let mut bytes = Vec::new();
+    save_with_schema_history(&mut bytes, 2, &player).unwrap();
+    assert!(read_header(&mut &bytes[..]).unwrap().has_schema_history);
+    assert_eq!(read_schema_history(&mut &bytes[..]).unwrap(), Some(history));
+    let (version, schema) = read_schema(&mut &bytes[..]).unwrap();
+    assert_eq!((version, &schema), (2, &*schema_of::<HistoryPlayer>(2)));
+    assert_eq!(load_from_mem::<HistoryPlayer>(&bytes, 2).unwrap(), player);
+    assert_eq!(Document::load(&mut &bytes[..]).unwrap().schema, *schema_of::<HistoryPlayer>(2));
+
+    let plain = save_to_mem(2, &player).unwrap();
+    assert_eq!(read_schema_history(&mut &plain[..]).unwrap(), None);
+
+    let mut changed = SchemaHistory::of::<HistoryPlayer>(1);
+    changed.push(2, &schema_of::<u8>(0));
+    assert_eq!(changed.root.len(), 2);
+    assert_eq!(changed.schema(1).unwrap(), *schema_of::<HistoryPlayer>(1));
+    assert_eq!(changed.schema(2).unwrap(), *schema_of::<u8>(0));
+}
+
+#[test]
+pub fn test_skip_value() {
+    let lines = vec![
+        DialogueLine {
+            speaker: "Ann".to_string(),
+            text: "Hello".to_string(),
+        },
+        DialogueLine {
+            speaker: "Bob".to_string(),
+            text: "Goodbye".to_string(),
+        },
+    ];
+    let value = (lines, Some(7u64), "end".to_string());
+    let varint = Encoding {
+        lengths: LengthEncoding::Varint,
+        ..Encoding::default()
+    };
+    for encoding in [Encoding::default(), varint].iter() {
+        let mut bytes = Vec::new();
+        save_with_encoding(&mut bytes, 0, &value, *encoding).unwrap();
+        let mut reader = &bytes[..];
+        let header = read_header(&mut reader).unwrap();
+        let fields = match Schema::deserialize(&mut Deserializer::new_raw(&mut reader)).unwrap() {
+            Schema::Struct(schema_struct) => schema_struct.fields,
+            other => panic!("Expected a tuple, got {:?}", other),
+        };
+        let mut deserializer = Deserializer::new_raw(&mut reader);
+        deserializer.encoding = header.encoding;
+        deserializer.skip_value(&fields[0].value).unwrap();
+        deserializer.skip_value(&fields[1].value).unwrap();
+        assert_eq!(deserializer.read_string().unwrap(), "end");
+        assert!(deserializer.skip_value(&fields[1].value).is_err());
+    }
+    let mut reader = &[0u8][..];
+    assert!(Deserializer::new_raw(&mut reader).skip_value(&Schema::Undefined).is_err());
+}
+
+/// Textures loaded by the application, which sprites refer to by handle
+#[derive(Default)]
+pub struct TextureRegistry {
+    names: Vec<String>,
+}
+
+impl TextureRegistry {
+    fn handle(&mut self, name: &str) -> TextureHandle {
+        match self.names.iter().position(|existing| existing == name) {
+            Some(index) => TextureHandle(index),
+            None => {
+                self.names.push(name.to_string());
+                TextureHandle(self.names.len() - 1)
+            }
+        }
+    }
+}
+
+/// Saved as the name of the texture, since handles differ between runs
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TextureHandle(usize);
+
+impl WithSchema for TextureHandle {
+    fn schema(version: u32) -> Schema {
+        String::schema(version)
+    }
+}
+impl Serialize for TextureHandle {
+    fn serialize(&self, serializer: &mut Serializer<impl std::io::Write>) -> Result<(), SavefileError> {
+        let name = match serializer.context::<TextureRegistry>() {
+            Some(registry) => registry.names[self.0].clone(),
+            None => {
+                return Err(SavefileError::GeneralError {
+                    msg: "Textures can only be saved with a registry".to_string(),
+                })
+            }
+        };
+        serializer.write_string(&name)
+    }
+}
+impl Deserialize for TextureHandle {
+    fn deserialize(deserializer: &mut Deserializer<impl std::io::Read>) -> Result<Self, SavefileError> {
+        let name = deserializer.read_string()?;
+        match deserializer.context::<TextureRegistry>() {
+            Some(registry) => Ok(registry.handle(&name)),
+            None => Err(SavefileError::GeneralError {
+                msg: "Textures can only be loaded with a registry".to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct Sprite {
+    x: i32,
+    #[savefile_introspect_ignore]
+    texture: TextureHandle,
+}
+
+#[test]
+pub fn test_user_context() {
+    let mut registry = TextureRegistry::default();
+    let sprites = vec![
+        Sprite {
+            x: 1,
+            texture: registry.handle("grass"),
+        },
+        Sprite {
+            x: 2,
+            texture: registry.handle("stone"),
+        },
+    ];
+    let mut bytes = Vec::new();
+    save_with_context(&mut bytes, 0, &sprites, &mut registry).unwrap();
+    assert!(save_to_mem(0, &sprites).is_err());
+
+    let mut fresh = TextureRegistry::default();
+    fresh.handle("stone");
+    let loaded: Vec<Sprite> = load_with_context(&mut &bytes[..], 0, &mut fresh).unwrap();
+    assert_eq!(fresh.names, vec!["stone", "grass"]);
+    assert_eq!(loaded[0].texture, TextureHandle(1));
+    assert_eq!(loaded[1].texture, TextureHandle(0));
+    assert!(load_from_mem::<Vec<Sprite>>(&bytes, 0).is_err());
+    assert!(load_with_context::<Vec<Sprite>, _>(&mut &bytes[..], 0, &mut 0u32).is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+#[savefile_post_deserialize]
+pub struct Invoice {
+    customer: String,
+    lines: Vec<u32>,
+    #[savefile_ignore]
+    total: u32,
+}
+
+impl PostDeserialize for Invoice {
+    fn post_deserialize(&mut self, file_version: u32) {
+        self.total = self.lines.iter().sum();
+        // Version 0 stored customer names in upper case
+        if file_version == 0 {
+            self.customer = self.customer.to_lowercase();
+        }
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+#[savefile_post_deserialize]
+pub enum Discount {
+    Percent(u32),
+    Fixed { amount: u32 },
+}
+
+impl PostDeserialize for Discount {
+    fn post_deserialize(&mut self, _file_version: u32) {
+        if let Discount::Percent(ref mut percent) = *self {
+            *percent = (*percent).min(100);
+        }
+    }
+}
+
+#[test]
+pub fn test_post_deserialize() {
+    let invoice = Invoice {
+        customer: "ACME".to_string(),
+        lines: vec![3, 4],
+        total: 0,
+    };
+    let bytes = save_to_mem(0, &invoice).unwrap();
+    let expected = Invoice {
+        customer: "acme".to_string(),
+        lines: vec![3, 4],
+        total: 7,
+    };
+    assert_eq!(load_from_mem::<Invoice>(&bytes, 1).unwrap(), expected);
+    let bytes = save_to_mem(1, &invoice).unwrap();
+    assert_eq!(load_from_mem::<Invoice>(&bytes, 1).unwrap().customer, "ACME");
+
+    let mut reused = Invoice {
+        customer: String::new(),
+        lines: vec![],
+        total: 0,
+    };
+    load_in_place(&mut &bytes[..], 1, &mut reused).unwrap();
+    assert_eq!(reused.total, 7);
+
+    let discounts = vec![Discount::Percent(150), Discount::Fixed { amount: 150 }];
+    let loaded: Vec<Discount> = load_from_mem(&save_to_mem(0, &discounts).unwrap(), 0).unwrap();
+    assert_eq!(loaded, vec![Discount::Percent(100), Discount::Fixed { amount: 150 }]);
+}
+
+fn check_order_line(line: &OrderLine) -> Result<(), String> {
+    if line.quantity == 0 {
+        Err(format!("No items of {}", line.product))
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+#[savefile_validate = "check_order_line"]
+pub struct OrderLine {
+    product: String,
+    quantity: u32,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct Shipment {
+    destination: String,
+    lines: Vec<OrderLine>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct UncheckedOrderLine {
+    product: String,
+    quantity: u32,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct UncheckedShipment {
+    destination: String,
+    lines: Vec<UncheckedOrderLine>,
+}
+
+#[test]
+pub fn test_validate() {
+    let shipment = Shipment {
+        destination: "Oslo".to_string(),
+        lines: vec![
+            OrderLine {
+                product: "nails".to_string(),
+                quantity: 100,
+            },
+            OrderLine {
+                product: "hammers".to_string(),
+                quantity: 0,
+            },
+        ],
+    };
+    let err = save_to_mem(0, &shipment).unwrap_err();
+    assert_eq!(err.to_string(), "Validation failed in field [lines/*]: No items of hammers");
+    match err {
+        SavefileError::Validation { path, message } => {
+            assert_eq!(path, "lines/*");
+            assert_eq!(message, "No items of hammers");
+        }
+        err => panic!("Unexpected error {:?}", err),
+    }
+
+    let unchecked = UncheckedShipment {
+        destination: "Oslo".to_string(),
+        lines: vec![UncheckedOrderLine {
+            product: "saws".to_string(),
+            quantity: 0,
+        }],
+    };
+    let bytes = save_to_mem(0, &unchecked).unwrap();
+    match load_from_mem::<Shipment>(&bytes, 0).unwrap_err() {
+        SavefileError::Validation { path, message } => {
+            assert_eq!(path, "lines/*");
+            assert_eq!(message, "No items of saws");
+        }
+        err => panic!("Unexpected error {:?}", err),
+    }
+    let line = OrderLine {
+        product: "saws".to_string(),
+        quantity: 0,
+    };
+    match load_from_mem::<OrderLine>(&save_to_mem(0, &unchecked.lines[0]).unwrap(), 0).unwrap_err() {
+        SavefileError::Validation { path, .. } => assert_eq!(path, ""),
+        err => panic!("Unexpected error {:?}", err),
+    }
+    assert!(save_to_mem(0, &line).is_err());
+
+    let valid = Shipment {
+        destination: "Oslo".to_string(),
+        lines: vec![],
+    };
+    assert_eq!(load_from_mem::<Shipment>(&save_to_mem(0, &valid).unwrap(), 0).unwrap(), valid);
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct WorldChunk {
+    x: u32,
+    tiles: Vec<u8>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct World {
+    name: String,
+    chunks: Vec<WorldChunk>,
+    seed: u64,
+}
+
+#[test]
+pub fn test_progress_and_cancellation() {
+    let world = World {
+        name: "Overworld".to_string(),
+        chunks: (0..64)
+            .map(|x| WorldChunk {
+                x,
+                tiles: vec![x as u8; 4096],
+            })
+            .collect(),
+        seed: 42,
+    };
+    let mut reports = Vec::new();
+    let mut bytes = Vec::new();
+    {
+        let mut progress = Progress::new().with_callback(|report: ProgressReport| {
+            reports.push((report.field.to_string(), report.bytes))
+        });
+        save_with_progress(&mut bytes, 0, &world, &mut progress).unwrap();
+    }
+    let fields: Vec<&str> = reports.iter().map(|(field, _)| field.as_str()).collect();
+    assert_eq!(fields.first(), Some(&"name"));
+    assert_eq!(fields.last(), Some(&""));
+    assert!(fields.iter().filter(|field| **field == "chunks").count() > 2);
+    assert!(fields.contains(&"seed"));
+    assert!(reports.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    let total = reports.last().unwrap().1;
+    assert!(total > 64 * 4096);
+
+    let mut loaded_reports = Vec::new();
+    {
+        let mut progress = Progress::new().with_callback(|report: ProgressReport| {
+            loaded_reports.push((report.field.to_string(), report.bytes))
+        });
+        let loaded: World = load_with_progress(&mut &bytes[..], 0, &mut progress).unwrap();
+        assert_eq!(loaded, world);
+    }
+    assert_eq!(loaded_reports.last(), Some(&("".to_string(), total)));
+
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    let mut progress = Progress::new()
+        .with_callback(move |report: ProgressReport| {
+            if report.field == "chunks" {
+                cancel.cancel();
+            }
+        })
+        .with_cancellation(token.clone());
+    match save_with_progress(&mut Vec::new(), 0, &world, &mut progress) {
+        Err(SavefileError::Cancelled) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert!(token.is_cancelled());
+    match load_with_progress::<World>(&mut &bytes[..], 0, &mut progress) {
+        Err(SavefileError::Cancelled) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct PlayerStats {
+    level: u32,
+    gold: u64,
+    name: String,
+}
+
+#[test]
+pub fn test_progress_of_fixed_size_fields() {
+    // The run of fixed size fields is written in one go, and reported once, before it is written
+    let stats = PlayerStats {
+        level: 3,
+        gold: 100,
+        name: "Ada".to_string(),
+    };
+    let mut reports = Vec::new();
+    {
+        let mut progress = Progress::new().with_callback(|report: ProgressReport| {
+            reports.push((report.field.to_string(), report.bytes))
+        });
+        save_with_progress(&mut Vec::new(), 0, &stats, &mut progress).unwrap();
+    }
+    let start = reports[0].1;
+    assert_eq!(
+        reports,
+        vec![
+            ("level".to_string(), start),
+            ("name".to_string(), start + 12),
+            ("".to_string(), start + 12 + 8 + 3),
+        ]
+    );
+}
+
+#[test]
+pub fn test_autosaver() {
+    use std::time::Duration;
+    let mut world = World {
+        name: "Autosaved".to_string(),
+        chunks: vec![WorldChunk {
+            x: 1,
+            tiles: vec![1, 2, 3],
+        }],
+        seed: 7,
+    };
+    {
+        let mut autosaver = Autosaver::new("test_autosave.bin", 0, Duration::from_millis(0));
+        assert!(autosaver.tick(&world).unwrap());
+        autosaver.wait().unwrap();
+        assert_eq!(load_file::<World>("test_autosave.bin", 0).unwrap(), world);
+
+        world.seed = 8;
+        autosaver.save_now(&world).unwrap();
+        world.seed = 9;
+        // The previous snapshot may still be being written
+        autosaver.save_now(&world).unwrap();
+    }
+    assert_eq!(load_file::<World>("test_autosave.bin", 0).unwrap().seed, 9);
+
+    let mut autosaver = Autosaver::new("test_autosave.bin", 0, Duration::from_secs(3600));
+    assert!(!autosaver.tick(&world).unwrap());
+    let mut failing = Autosaver::new("no_such_directory/test_autosave.bin", 0, Duration::from_secs(3600));
+    failing.save_now(&world).unwrap();
+    assert!(failing.wait().is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq, Default)]
+pub struct AudioSettings {
+    volume: u8,
+    muted: bool,
+}
+
+#[test]
+pub fn test_saved() {
+    let _ = std::fs::remove_file("test_saved.bin");
+    let mut settings: Saved<AudioSettings> = Saved::load_or_else("test_saved.bin", 0, AudioSettings::default).unwrap();
+    assert!(settings.is_dirty());
+    assert!(settings.flush_if_dirty("test_saved.bin", 0).unwrap());
+    assert!(!settings.flush_if_dirty("test_saved.bin", 0).unwrap());
+
+    assert_eq!(settings.volume, 0);
+    assert!(!settings.is_dirty());
+    settings.volume = 80;
+    assert!(settings.is_dirty());
+    assert!(settings.flush_if_dirty("test_saved.bin", 0).unwrap());
+
+    let loaded: Saved<AudioSettings> = Saved::load_or_else("test_saved.bin", 0, AudioSettings::default).unwrap();
+    assert!(!loaded.is_dirty());
+    assert_eq!(*loaded, AudioSettings { volume: 80, muted: false });
+
+    let mut unsaved = Saved::new(AudioSettings::default());
+    unsaved.mark_clean();
+    assert!(!unsaved.flush_if_dirty("no_such_directory/test_saved.bin", 0).unwrap());
+    unsaved.mark_dirty();
+    assert!(unsaved.flush_if_dirty("no_such_directory/test_saved.bin", 0).is_err());
+    assert!(unsaved.is_dirty());
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ColorParseError {
+    text: String,
+}
+
+impl std::fmt::Display for ColorParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "'{}' is not a color", self.text)
+    }
+}
+
+impl std::error::Error for ColorParseError {}
+
+/// A color, saved as text like "#ff8000"
+#[derive(Debug, PartialEq)]
+pub struct HexColor(u32);
+
+impl WithSchema for HexColor {
+    fn schema(version: u32) -> Schema {
+        String::schema(version)
+    }
+}
+impl Serialize for HexColor {
+    fn serialize(&self, serializer: &mut Serializer<impl std::io::Write>) -> Result<(), SavefileError> {
+        if self.0 > 0xffffff {
+            return Err(SavefileError::custom("Colors can't have more than 24 bits"));
+        }
+        serializer.write_string(&format!("#{:06x}", self.0))
+    }
+}
+impl Deserialize for HexColor {
+    fn deserialize(deserializer: &mut Deserializer<impl std::io::Read>) -> Result<Self, SavefileError> {
+        let text = deserializer.read_string()?;
+        match text.strip_prefix('#').and_then(|hex| u32::from_str_radix(hex, 16).ok()) {
+            Some(color) => Ok(HexColor(color)),
+            None => Err(SavefileError::custom(ColorParseError { text })),
+        }
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct Theme {
+    #[savefile_introspect_ignore]
+    background: HexColor,
+}
+
+#[test]
+pub fn test_custom_error() {
+    let theme = Theme {
+        background: HexColor(0xff8000),
+    };
+    let bytes = save_to_mem(0, &theme).unwrap();
+    assert_eq!(load_from_mem::<Theme>(&bytes, 0).unwrap(), theme);
+
+    let err = save_to_mem(0, &HexColor(0x1000000)).unwrap_err();
+    assert_eq!(err.to_string(), "Colors can't have more than 24 bits");
+    assert!(err.downcast_custom::<ColorParseError>().is_none());
+
+    #[derive(Savefile)]
+    pub struct TextTheme {
+        background: String,
+    }
+    let bytes = save_to_mem(
+        0,
+        &TextTheme {
+            background: "orange".to_string(),
+        },
+    )
+    .unwrap();
+    let err = load_from_mem::<Theme>(&bytes, 0).unwrap_err();
+    assert_eq!(
+        err.downcast_custom::<ColorParseError>(),
+        Some(&ColorParseError {
+            text: "orange".to_string()
+        })
+    );
+    assert!(err.to_string().ends_with("in field [background]: 'orange' is not a color"));
+    let source = std::error::Error::source(&err).unwrap();
+    assert_eq!(source.to_string(), "'orange' is not a color");
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct Position3 {
+    x: u32,
+    y: u32,
+    z: u32,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct Position2 {
+    x: u32,
+    y: u32,
+}
+
+#[test]
+pub fn test_load_exact() {
+    let position = Position3 { x: 1, y: 2, z: 3 };
+    let mut bytes = save_to_mem(0, &position).unwrap();
+    assert_eq!(load_exact::<Position3>(&mut &bytes[..], 0).unwrap(), position);
+    bytes.extend_from_slice(b"garbage");
+    assert_eq!(load_from_mem::<Position3>(&bytes, 0).unwrap(), position);
+    let err = load_exact::<Position3>(&mut &bytes[..], 0).unwrap_err();
+    assert_eq!(err.to_string(), "General error: There are bytes left after the loaded value");
+
+    // A payload length which doesn't match is caught as well
+    let mut bytes = save_to_mem(0, &position).unwrap();
+    bytes[17] += 1;
+    assert!(load_exact::<Position3>(&mut &bytes[..], 0).is_err());
+
+    // If the schema hash isn't checked, a type with fewer fields can be loaded, except in strict mode
+    let mut bytes = Vec::new();
+    save_noschema(&mut bytes, 0, &position).unwrap();
+    assert_eq!(
+        load_noschema_unverified::<Position2>(&mut &bytes[..], 0).unwrap(),
+        Position2 { x: 1, y: 2 }
+    );
+    assert!(load_exact::<Position2>(&mut &bytes[..], 0).is_err());
+
+    let mut bytes = Vec::new();
+    save_compressed(&mut bytes, 0, &position).unwrap();
+    assert_eq!(load_exact::<Position3>(&mut &bytes[..], 0).unwrap(), position);
+
+    save_file("test_load_exact.bin", 0, &position).unwrap();
+    assert_eq!(load_file_exact::<Position3>("test_load_exact.bin", 0).unwrap(), position);
+    {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new().append(true).open("test_load_exact.bin").unwrap();
+        file.write_all(&[0]).unwrap();
+    }
+    assert!(load_file_exact::<Position3>("test_load_exact.bin", 0).is_err());
+}
+
+#[test]
+pub fn test_noschema_schema_hash() {
+    let position = Position3 { x: 1, y: 2, z: 3 };
+    let mut bytes = Vec::new();
+    save_noschema(&mut bytes, 0, &position).unwrap();
+    assert!(read_header(&mut &bytes[..]).unwrap().has_schema_hash);
+    assert_eq!(load_noschema::<Position3>(&mut &bytes[..], 0).unwrap(), position);
+    match load_noschema::<Position2>(&mut &bytes[..], 0) {
+        Err(SavefileError::IncompatibleSchema { .. }) => {}
+        other => panic!("Expected an incompatible schema, got {:?}", other),
+    }
+    match load_noschema::<Vec<u8>>(&mut &bytes[..], 0) {
+        Err(SavefileError::IncompatibleSchema { .. }) => {}
+        other => panic!("Expected an incompatible schema, got {:?}", other),
+    }
+    assert_eq!(
+        load_noschema_unverified::<(u32, u32, u32)>(&mut &bytes[..], 0).unwrap(),
+        (1, 2, 3)
+    );
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct StationRecord {
+    sensor: String,
+    timestamp: u64,
+    values: Vec<f32>,
+}
+
+#[test]
+pub fn test_schema_file() {
+    let _ = std::fs::remove_file("test_schema_file_schema.bin");
+    let readings: Vec<StationRecord> = (0..3)
+        .map(|i| StationRecord {
+            sensor: format!("sensor{}", i),
+            timestamp: 1000 + i,
+            values: vec![i as f32; 2],
+        })
+        .collect();
+    for (i, reading) in readings.iter().enumerate() {
+        let path = format!("test_schema_file_{}.bin", i);
+        save_with_schema_file(&path, "test_schema_file_schema.bin", 0, reading).unwrap();
+    }
+    let (version, schema) = read_schema(&mut std::fs::File::open("test_schema_file_schema.bin").unwrap()).unwrap();
+    assert_eq!(version, 0);
+    assert_eq!(schema.stable_hash(), schema_of::<StationRecord>(0).stable_hash());
+    for (i, reading) in readings.iter().enumerate() {
+        let path = format!("test_schema_file_{}.bin", i);
+        assert_eq!(
+            &load_with_schema_file::<StationRecord>(&path, "test_schema_file_schema.bin", 0).unwrap(),
+            reading
+        );
+    }
+
+    // The data file doesn't hold the schema
+    let data_len = std::fs::metadata("test_schema_file_0.bin").unwrap().len();
+    assert!((data_len as usize) < save_to_mem(0, &readings[0]).unwrap().len());
+
+    // Loading as another type fails
+    match load_with_schema_file::<Position3>("test_schema_file_0.bin", "test_schema_file_schema.bin", 0) {
+        Err(SavefileError::IncompatibleSchema { .. }) => {}
+        other => panic!("Expected an incompatible schema, got {:?}", other),
+    }
+
+    // The schema file of another type can't be used, neither for saving nor for loading
+    let _ = std::fs::remove_file("test_schema_file_position_schema.bin");
+    save_with_schema_file(
+        "test_schema_file_position.bin",
+        "test_schema_file_position_schema.bin",
+        0,
+        &Position3 { x: 1, y: 2, z: 3 },
+    )
+    .unwrap();
+    assert!(save_with_schema_file("test_schema_file_3.bin", "test_schema_file_position_schema.bin", 0, &readings[0]).is_err());
+    assert!(save_with_schema_file("test_schema_file_3.bin", "test_schema_file_schema.bin", 1, &readings[0]).is_err());
+    match load_with_schema_file::<StationRecord>("test_schema_file_0.bin", "test_schema_file_position_schema.bin", 0) {
+        Err(SavefileError::IncompatibleSchema { .. }) => {}
+        other => panic!("Expected an incompatible schema, got {:?}", other),
+    }
+}
+
+#[test]
+pub fn test_schema_registry() {
+    let record = StationRecord {
+        sensor: "north".to_string(),
+        timestamp: 17,
+        values: vec![1.0, 2.5],
+    };
+    let mut registry = SchemaRegistry::new();
+    let mut bytes = Vec::new();
+    save_with_registry(&mut bytes, 0, &record, &mut registry).unwrap();
+    let fingerprint = schema_of::<StationRecord>(0).stable_hash();
+    assert!(registry.get(fingerprint).is_some());
+    assert_eq!(load_with_registry::<StationRecord>(&mut &bytes[..], 0, &mut registry).unwrap(), record);
+    match load_with_registry::<Position3>(&mut &bytes[..], 0, &mut registry) {
+        Err(SavefileError::IncompatibleSchema { .. }) => {}
+        other => panic!("Expected an incompatible schema, got {:?}", other),
+    }
+    // The registry of another process, which hasn't seen the schema, can't load the data
+    assert!(load_with_registry::<StationRecord>(&mut &bytes[..], 0, &mut SchemaRegistry::new()).is_err());
+
+    // With a cache directory, a new registry finds the schema there
+    let dir = "test_schema_registry_cache";
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir(dir).unwrap();
+    let mut bytes = Vec::new();
+    save_with_registry(&mut bytes, 0, &record, &mut SchemaRegistry::with_cache_dir(dir)).unwrap();
+    let mut registry = SchemaRegistry::with_cache_dir(dir);
+    assert!(registry.get(fingerprint).is_none());
+    assert_eq!(load_with_registry::<StationRecord>(&mut &bytes[..], 0, &mut registry).unwrap(), record);
+    assert!(registry.get(fingerprint).is_some());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub enum PipeCommand {
+    Start,
+    Stop { reason: String },
+}
+
+#[test]
+pub fn test_tagged_objects() {
+    let tags = TypeTags::new()
+        .with_type::<StationRecord>(1, 0)
+        .with_type::<PipeCommand>(2, 0)
+        .with_type::<u64>(3, 0);
+    assert_eq!(tags.tag_of::<PipeCommand>(), Some(2));
+    assert_eq!(tags.tag_of::<u32>(), None);
+
+    let record = StationRecord {
+        sensor: "south".to_string(),
+        timestamp: 3,
+        values: vec![0.5],
+    };
+    let mut pipe = Vec::new();
+    save_tagged(&mut pipe, &tags, &PipeCommand::Start).unwrap();
+    save_tagged(&mut pipe, &tags, &record).unwrap();
+    save_tagged(&mut pipe, &tags, &42u64).unwrap();
+    save_tagged(
+        &mut pipe,
+        &tags,
+        &PipeCommand::Stop {
+            reason: "done".to_string(),
+        },
+    )
+    .unwrap();
+    assert!(save_tagged(&mut pipe.clone(), &tags, &1u32).is_err());
+
+    let mut reader = &pipe[..];
+    let mut commands = Vec::new();
+    let mut records = Vec::new();
+    let mut numbers = Vec::new();
+    while let Some(object) = load_tagged_any(&mut reader, &tags).unwrap() {
+        let object = match object.downcast::<PipeCommand>() {
+            Ok(command) => {
+                commands.push(*command);
+                continue;
+            }
+            Err(object) => object,
+        };
+        let object = match object.downcast::<StationRecord>() {
+            Ok(record) => {
+                records.push(*record);
+                continue;
+            }
+            Err(object) => object,
+        };
+        numbers.push(*object.downcast::<u64>().unwrap());
+    }
+    assert_eq!(
+        commands,
+        vec![
+            PipeCommand::Start,
+            PipeCommand::Stop {
+                reason: "done".to_string()
+            }
+        ]
+    );
+    assert_eq!(records, vec![record]);
+    assert_eq!(numbers, vec![42]);
+
+    // Unknown tags and objects cut off in the middle are errors
+    assert!(load_tagged_any(&mut &[9u8, 0, 0, 0][..], &tags).is_err());
+    assert!(load_tagged_any(&mut &pipe[..2], &tags).is_err());
+    assert!(load_tagged_any(&mut &pipe[..pipe.len() - 1], &TypeTags::new()).is_err());
+}
+
+pub trait EntityComponent: std::any::Any + Debug {
+    fn label(&self) -> String;
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct PositionComponent {
+    x: f32,
+    y: f32,
+}
+
+impl EntityComponent for PositionComponent {
+    fn label(&self) -> String {
+        format!("at {},{}", self.x, self.y)
+    }
+}
+
+#[derive(Savefile, Debug)]
+pub struct ChildrenComponent {
+    children: Vec<Box<dyn EntityComponent>>,
+}
+
+impl EntityComponent for ChildrenComponent {
+    fn label(&self) -> String {
+        format!("{} children", self.children.len())
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct UnregisteredComponent {
+    value: u8,
+}
+
+impl EntityComponent for UnregisteredComponent {
+    fn label(&self) -> String {
+        "unregistered".to_string()
+    }
+}
+
+savefile::savefile_trait_object!(EntityComponent);
+
+#[derive(Savefile, Debug)]
+pub struct GameEntity {
+    name: String,
+    components: Vec<Box<dyn EntityComponent>>,
+}
+
+#[test]
+pub fn test_trait_objects() {
+    savefile::register_savefile_type!(EntityComponent, PositionComponent, "position");
+    savefile::register_savefile_type!(EntityComponent, ChildrenComponent, "children");
+    // Registering again is harmless
+    savefile::register_savefile_type!(EntityComponent, PositionComponent, "position");
+
+    let entity = GameEntity {
+        name: "player".to_string(),
+        components: vec![
+            Box::new(PositionComponent { x: 1.0, y: 2.0 }),
+            Box::new(ChildrenComponent {
+                children: vec![Box::new(PositionComponent { x: 3.0, y: 4.0 })],
+            }),
+        ],
+    };
+    let registry = <dyn EntityComponent as SavefileTraitObject>::savefile_registry();
+    assert_eq!(registry.name_of(&*entity.components[1]), Some("children"));
+
+    let loaded: GameEntity = load_from_mem(&save_to_mem(0, &entity).unwrap(), 0).unwrap();
+    assert_eq!(loaded.name, "player");
+    let labels: Vec<String> = loaded.components.iter().map(|c| c.label()).collect();
+    assert_eq!(labels, vec!["at 1,2".to_string(), "1 children".to_string()]);
+    let position: &dyn EntityComponent = &*loaded.components[0];
+    assert_eq!(registry.introspect(position).unwrap().introspect_len(), 2);
+    match registry.name_of(&*loaded.components[1]) {
+        Some("children") => {}
+        other => panic!("Unexpected type {:?}", other),
+    }
+
+    // Types which haven't been registered can't be saved
+    let unregistered = GameEntity {
+        name: "ghost".to_string(),
+        components: vec![Box::new(UnregisteredComponent { value: 1 })],
+    };
+    assert!(save_to_mem(0, &unregistered).is_err());
+
+    // An object saved with a different schema is rejected
+    let mut bytes = Vec::new();
+    {
+        let mut serializer = Serializer::new_raw(&mut bytes);
+        serializer.write_string("position").unwrap();
+        serializer.write_u64(schema_of::<u32>(0).stable_hash()).unwrap();
+        vec![0u8; 4].serialize(&mut serializer).unwrap();
+    }
+    let mut reader = &bytes[..];
+    match <Box<dyn EntityComponent>>::deserialize(&mut Deserializer::new_raw(&mut reader)) {
+        Err(SavefileError::IncompatibleSchema { .. }) => {}
+        other => panic!("Expected an incompatible schema, got {:?}", other),
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct ParticleStorage {
+    particles: Columns<(Vec<u32>, Vec<f32>, Vec<String>)>,
+}
+
+#[test]
+pub fn test_columns() {
+    let storage = ParticleStorage {
+        particles: Columns((vec![1, 2, 3], vec![0.5, 1.5, 2.5], vec!["a".into(), "b".into(), "c".into()])),
+    };
+    let loaded: ParticleStorage = load_from_mem(&save_to_mem(0, &storage).unwrap(), 0).unwrap();
+    assert_eq!(loaded, storage);
+
+    // Each column is laid out like a vector
+    let ids = [7u32, 8];
+    let weights = [1.0f64, 2.0];
+    let borrowed = Columns((&ids[..], &weights[..]));
+    let mut borrowed_bytes = Vec::new();
+    save_noschema(&mut borrowed_bytes, 0, &borrowed).unwrap();
+    let mut vec_bytes = Vec::new();
+    save_noschema(&mut vec_bytes, 0, &Columns((ids.to_vec(), weights.to_vec()))).unwrap();
+    assert_eq!(borrowed_bytes, vec_bytes);
+    let mut column_data = Vec::new();
+    borrowed.serialize(&mut Serializer::new_raw(&mut column_data)).unwrap();
+    let mut tuple_data = Vec::new();
+    (ids.to_vec(), weights.to_vec())
+        .serialize(&mut Serializer::new_raw(&mut tuple_data))
+        .unwrap();
+    assert_eq!(column_data, tuple_data);
+    let loaded: Columns<(Vec<u32>, Vec<f64>)> = load_noschema(&mut &borrowed_bytes[..], 0).unwrap();
+    assert_eq!(loaded.0, (ids.to_vec(), weights.to_vec()));
+
+    // All columns must have the same length
+    assert!(save_to_mem(0, &Columns((vec![1u8, 2], vec![1u8]))).is_err());
+    let mut bytes = Vec::new();
+    save_noschema(&mut bytes, 0, &(vec![1u8, 2], vec![1u8])).unwrap();
+    assert!(load_noschema_unverified::<Columns<(Vec<u8>, Vec<u8>)>>(&mut &bytes[..], 0).is_err());
+}
+
+#[repr(C)]
+#[derive(ReprC, Savefile, Debug, PartialEq, Clone, Copy)]
+pub struct Velocity {
+    dx: f32,
+    dy: f32,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct EntityTables {
+    velocities: DenseMap<std::collections::HashMap<u64, Velocity>>,
+    names: DenseMap<std::collections::BTreeMap<u32, String>>,
+}
+
+#[test]
+pub fn test_dense_map() {
+    let mut tables = EntityTables {
+        velocities: DenseMap(std::collections::HashMap::new()),
+        names: DenseMap::default(),
+    };
+    for id in 0..100u64 {
+        tables.velocities.insert(id * 3, Velocity { dx: id as f32, dy: -1.0 });
+    }
+    tables.names.insert(5, "five".to_string());
+    tables.names.insert(2, "two".to_string());
+    let loaded: EntityTables = load_from_mem(&save_to_mem(0, &tables).unwrap(), 0).unwrap();
+    assert_eq!(loaded, tables);
+
+    // The keys are sorted, and followed by the values
+    let mut data = Vec::new();
+    tables.names.serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    let mut expected = Vec::new();
+    (vec![2u32, 5], vec!["two".to_string(), "five".to_string()])
+        .serialize(&mut Serializer::new_raw(&mut expected))
+        .unwrap();
+    assert_eq!(data, expected);
+
+    // Unsorted keys, and keys without values, are rejected
+    for (keys, values) in vec![(vec![5u32, 2], vec![1u8, 2]), (vec![2u32, 2], vec![1u8, 2]), (vec![2u32], vec![])] {
+        let mut data = Vec::new();
+        (keys, values).serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+        let mut reader = &data[..];
+        assert!(DenseMap::<std::collections::BTreeMap<u32, u8>>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct UploadRequest {
+    user: String,
+    blobs: Vec<Vec<u8>>,
+}
+
+#[test]
+pub fn test_memory_budget() {
+    let request = UploadRequest {
+        user: "anna".to_string(),
+        blobs: vec![vec![1; 1000], vec![2; 5000]],
+    };
+    let data = save_to_mem(0, &request).unwrap();
+    let loaded: UploadRequest = load_with_memory_budget(&mut &data[..], 0, 10_000).unwrap();
+    assert_eq!(loaded, request);
+
+    match load_with_memory_budget::<UploadRequest>(&mut &data[..], 0, 3000) {
+        Err(SavefileError::DeserializationFailed { path, cause, .. }) => {
+            assert!(path.contains("blobs"), "{}", path);
+            match *cause {
+                SavefileError::MemoryBudgetExceeded { budget, needed } => {
+                    assert_eq!(budget, 3000);
+                    assert!(needed > 3000);
+                }
+                err => panic!("Unexpected error {:?}", err),
+            }
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+
+    // A corrupt length fails before anything is allocated
+    let mut data = save_to_mem(0, &vec![1u64, 2]).unwrap();
+    let length_offset = data.len() - 24;
+    data[length_offset..length_offset + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    let err = load_with_memory_budget::<Vec<u64>>(&mut &data[..], 0, 1_000_000).unwrap_err();
+    assert!(format!("{}", err).contains("Memory budget of 1000000 bytes exceeded"), "{}", err);
+}
+
+#[test]
+pub fn test_schema_stable() {
+    let path = "test_schema_stable_schema.bin";
+    let _ = std::fs::remove_file(path);
+    savefile::assert_schema_stable!(GoldenPlayer, 0, path);
+    assert!(std::path::Path::new(path).exists());
+    savefile::assert_schema_stable!(GoldenPlayer, 0, path);
+    savefile::assert_schema_stable!(GoldenPlayerV0, 0, path);
+
+    let err = check_schema_stable::<GoldenPlayerBroken>(0, path).unwrap_err();
+    assert!(format!("{}", err).contains("has changed since"), "{}", err);
+    assert!(check_schema_stable::<GoldenPlayer>(1, path).is_err());
+
+    let hash = format!("{:016x}", schema_of::<GoldenPlayer>(1).stable_hash());
+    savefile::assert_schema_stable!(GoldenPlayer, 1, &hash);
+    savefile::assert_schema_stable!(GoldenPlayerBroken, 1, &hash.to_uppercase());
+    let err = check_schema_stable::<GoldenPlayer>(0, &hash).unwrap_err();
+    assert!(format!("{}", err).contains("its hash is now"), "{}", err);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+pub fn test_slice_reader() {
+    use std::io::Read;
+    let data = [1u8, 2, 3, 4, 5];
+    let mut reader = SliceReader::new(&data);
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, [1, 2]);
+    let mut large = [0u8; 4];
+    assert!(reader.read_exact(&mut large).is_err());
+    assert_eq!(reader.remaining(), &[3, 4, 5]);
+    assert_eq!(reader.read(&mut large).unwrap(), 3);
+    assert_eq!(&large[..3], &[3, 4, 5]);
+    assert_eq!(reader.read(&mut large).unwrap(), 0);
+    assert!(reader.is_empty());
+
+    let rows: Vec<(u32, i16, String)> = (0..100).map(|i| (i, -(i as i16), i.to_string())).collect();
+    let saved = save_to_mem(0, &rows).unwrap();
+    let mut reader = SliceReader::new(&saved);
+    let loaded: Vec<(u32, i16, String)> = SliceDeserializer::load(&mut reader, 0).unwrap();
+    assert_eq!(loaded, rows);
+    assert!(reader.is_empty());
+    assert_eq!(load_from_mem::<Vec<(u32, i16, String)>>(&saved, 0).unwrap(), rows);
+    assert!(load_from_mem::<Vec<(u32, i16, String)>>(&saved[..saved.len() - 1], 0).is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct Particle {
+    x: u16,
+    y: u16,
+    visible: bool,
+    depth: f32,
+    id: i64,
+    label: String,
+    layer: u8,
+    offset: i8,
+    #[savefile_versions = "1.."]
+    mass: f64,
+    charge: i32,
+}
+
+#[test]
+pub fn test_combined_primitive_writes() {
+    let particle = Particle {
+        x: 1,
+        y: 0x203,
+        visible: true,
+        depth: 0.5,
+        id: -4,
+        label: "p".to_string(),
+        layer: 9,
+        offset: -1,
+        mass: 2.0,
+        charge: -77,
+    };
+    for endianness in vec![Endianness::Little, Endianness::Big] {
+        let mut data = Vec::new();
+        {
+            let mut serializer = Serializer::new_raw(&mut data);
+            serializer.encoding.endianness = endianness;
+            serializer.version = 1;
+            particle.serialize(&mut serializer).unwrap();
+        }
+        let mut expected = Vec::new();
+        {
+            let mut serializer = Serializer::new_raw(&mut expected);
+            serializer.encoding.endianness = endianness;
+            serializer.write_u16(particle.x).unwrap();
+            serializer.write_u16(particle.y).unwrap();
+            serializer.write_bool(particle.visible).unwrap();
+            serializer.write_f32(particle.depth).unwrap();
+            serializer.write_i64(particle.id).unwrap();
+            serializer.write_string(&particle.label).unwrap();
+            serializer.write_u8(particle.layer).unwrap();
+            serializer.write_i8(particle.offset).unwrap();
+            serializer.write_f64(particle.mass).unwrap();
+            serializer.write_i32(particle.charge).unwrap();
+        }
+        assert_eq!(data, expected);
+
+        let mut saved = Vec::new();
+        save_with_encoding(&mut saved, 1, &particle, Encoding { endianness, ..Encoding::default() }).unwrap();
+        assert_eq!(load_from_mem::<Particle>(&saved, 1).unwrap(), particle);
+    }
+}
+
+#[test]
+pub fn test_raw_copy_byte_order() {
+    let velocities: Vec<Velocity> = (0..10).map(|i| Velocity { dx: i as f32, dy: 0.25 }).collect();
+    let foreign = if Endianness::native() == Endianness::Little {
+        Endianness::Big
+    } else {
+        Endianness::Little
+    };
+    for endianness in vec![Endianness::native(), foreign] {
+        let mut data = Vec::new();
+        {
+            let mut serializer = Serializer::new_raw(&mut data);
+            serializer.encoding.endianness = endianness;
+            assert_eq!(serializer.uses_raw_copy::<Velocity>(), endianness == Endianness::native());
+            assert!(!serializer.uses_raw_copy::<String>());
+            velocities.serialize(&mut serializer).unwrap();
+        }
+        // Either way, the data is in the requested byte order
+        let mut expected = Vec::new();
+        {
+            let mut serializer = Serializer::new_raw(&mut expected);
+            serializer.encoding.endianness = endianness;
+            serializer.write_usize(velocities.len()).unwrap();
+            for velocity in &velocities {
+                serializer.write_f32(velocity.dx).unwrap();
+                serializer.write_f32(velocity.dy).unwrap();
+            }
+        }
+        assert_eq!(data, expected);
+
+        let mut reader = &data[..];
+        let mut deserializer = Deserializer::new_raw(&mut reader);
+        deserializer.encoding.endianness = endianness;
+        assert_eq!(deserializer.uses_raw_copy::<Velocity>(), endianness == Endianness::native());
+        assert_eq!(Vec::<Velocity>::deserialize(&mut deserializer).unwrap(), velocities);
+    }
+}
+
+#[test]
+pub fn test_raw_vec_hardening() {
+    // A hostile length fails when the data ends, without allocating memory for all the items
+    let mut data = Vec::new();
+    Serializer::new_raw(&mut data).write_usize(1 << 40).unwrap();
+    data.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+    let mut reader = &data[..];
+    assert!(Deserializer::new_raw(&mut reader).uses_raw_copy::<u32>());
+    assert!(Vec::<u32>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+
+    // The number of bytes of the items overflows
+    let mut data = Vec::new();
+    Serializer::new_raw(&mut data).write_usize(usize::MAX / 2).unwrap();
+    let mut reader = &data[..];
+    match Vec::<u64>::deserialize(&mut Deserializer::new_raw(&mut reader)) {
+        Err(SavefileError::MemoryAllocationLayoutError) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+
+    // The data ends in the middle of an item
+    let mut data = Vec::new();
+    vec![1u32, 2, 3].serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    data.pop();
+    let mut reader = &data[..];
+    assert!(Vec::<u32>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+    let mut reused = vec![7u32; 10];
+    let mut reader = &data[..];
+    assert!(reused
+        .deserialize_in_place(&mut Deserializer::new_raw(&mut reader))
+        .is_err());
+
+    // Zero-sized items
+    let units = vec![(); 1000];
+    assert_eq!(load_from_mem::<Vec<()>>(&save_to_mem(0, &units).unwrap(), 0).unwrap(), units);
+
+    let large: Vec<u16> = (0..50_000).collect();
+    let mut reused = vec![1u16; 3];
+    let saved = save_to_mem(0, &large).unwrap();
+    load_in_place(&mut &saved[..], 0, &mut reused).unwrap();
+    assert_eq!(reused, large);
+}
+
+#[test]
+pub fn test_incremental_vec_allocation() {
+    // A file truncated after the length prefix fails, without reserving memory for all the items
+    let mut data = Vec::new();
+    Serializer::new_raw(&mut data).write_usize(1 << 50).unwrap();
+    "only one".to_string().serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    let mut reader = &data[..];
+    assert!(Vec::<String>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+
+    // The same goes for strings and raw bytes
+    let mut data = Vec::new();
+    Serializer::new_raw(&mut data).write_usize(900_000).unwrap();
+    data.extend_from_slice(b"short");
+    let mut reader = &data[..];
+    assert!(String::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+    let mut reader = &b"short"[..];
+    assert!(Deserializer::new_raw(&mut reader).read_bytes(1 << 50).is_err());
+    let bytes: Vec<u8> = (0..20 << 20).map(|i| i as u8).collect();
+    let mut reader = &bytes[..];
+    assert_eq!(Deserializer::new_raw(&mut reader).read_bytes(bytes.len()).unwrap(), bytes);
+
+    let words: Vec<String> = (0..100_000).map(|i| i.to_string()).collect();
+    let loaded: Vec<String> = load_from_mem(&save_to_mem(0, &words).unwrap(), 0).unwrap();
+    assert_eq!(loaded, words);
+    assert_eq!(loaded.capacity(), words.len());
+
+    let mut reused = vec!["x".to_string(); 10];
+    load_in_place(&mut &save_to_mem(0, &words).unwrap()[..], 0, &mut reused).unwrap();
+    assert_eq!(reused, words);
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct DialogueDatabase {
+    lines: StringBlock<Vec<String>>,
+    audio: StringBlock<Vec<Vec<u8>>>,
+}
+
+#[test]
+pub fn test_string_block() {
+    let database = DialogueDatabase {
+        lines: StringBlock((0..10_000).map(|i| format!("Line {} – «ok»", i)).chain(vec![String::new()]).collect()),
+        audio: StringBlock(vec![vec![1, 2, 3], vec![], vec![255; 1000]]),
+    };
+    let saved = save_to_mem(0, &database).unwrap();
+    assert_eq!(load_from_mem::<DialogueDatabase>(&saved, 0).unwrap(), database);
+    assert_roundtrip(StringBlock(Vec::<String>::new()));
+
+    // Lengths which don't match the data, or which split a character, are rejected
+    for (lengths, data) in vec![(vec![2u64, 2], "abc"), (vec![1], "abc"), (vec![1, 1], "«")] {
+        let mut serialized = Vec::new();
+        {
+            let mut serializer = Serializer::new_raw(&mut serialized);
+            lengths.serialize(&mut serializer).unwrap();
+            data.as_bytes().to_vec().serialize(&mut serializer).unwrap();
+        }
+        let mut reader = &serialized[..];
+        assert!(StringBlock::<Vec<String>>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct CustomerTable {
+    names: Vec<String>,
+    balances: Vec<i64>,
+}
+
+/// Produces the rows of a CustomerTable one at a time, like a database cursor
+pub struct CustomerCursor {
+    rows: usize,
+}
+
+impl WithSchema for CustomerCursor {
+    fn schema(version: u32) -> Schema {
+        CustomerTable::schema(version)
+    }
+}
+
+impl Serialize for CustomerCursor {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_iter(serializer, None, (0..self.rows).map(|i| format!("Customer {}", i)))?;
+        let mut balances = serializer.serialize_seq(Some(self.rows))?;
+        for i in 0..self.rows {
+            balances.push(&(i as i64 - 100))?;
+        }
+        assert_eq!(balances.count(), self.rows);
+        balances.end()
+    }
+}
+
+#[test]
+pub fn test_serialize_iter() {
+    let bytes = save_to_mem(0, &CustomerCursor { rows: 5000 }).unwrap();
+    let loaded: CustomerTable = load_from_mem(&bytes, 0).unwrap();
+    assert_eq!(loaded.names.len(), 5000);
+    assert_eq!(loaded.names[4321], "Customer 4321");
+    assert_eq!(loaded.balances, (-100..4900).collect::<Vec<i64>>());
+
+    // The number of items written in front of them must be right
+    let mut data = Vec::new();
+    let mut serializer = Serializer::new_raw(&mut data);
+    assert!(serialize_iter(&mut serializer, Some(3), vec![1u8, 2]).is_err());
+    assert!(serialize_iter(&mut serializer, Some(1), vec![1u8, 2]).is_err());
+    serialize_iter(&mut serializer, Some(2), vec![1u8, 2]).unwrap();
+}
+
+#[test]
+pub fn test_deserialize_seq() {
+    // One chunked and one plain vector, read one item at a time
+    let mut data = Vec::new();
+    CustomerCursor { rows: 30000 }.serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    let mut reader = &data[..];
+    let mut deserializer = Deserializer::new_raw(&mut reader);
+    let names = deserialize_seq::<String, _>(&mut deserializer).unwrap();
+    assert_eq!(names.remaining(), None);
+    let long_names = names.map(|name| name.unwrap()).filter(|name| name.len() >= 14).count();
+    assert_eq!(long_names, 20000);
+    let mut balances = deserialize_seq::<i64, _>(&mut deserializer).unwrap();
+    assert_eq!(balances.remaining(), Some(30000));
+    assert_eq!(balances.next().unwrap().unwrap(), -100);
+    assert_eq!(balances.remaining(), Some(29999));
+    balances.finish().unwrap();
+    assert!(reader.is_empty());
+
+    // A truncated vector gives one error, and then no more items
+    let mut truncated = &data[..data.len() - 4];
+    let mut deserializer = Deserializer::new_raw(&mut truncated);
+    deserialize_seq::<String, _>(&mut deserializer).unwrap().finish().unwrap();
+    let balances: Vec<_> = deserialize_seq::<i64, _>(&mut deserializer).unwrap().collect();
+    assert_eq!(balances.len(), 30000);
+    assert!(balances.last().unwrap().is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq, Clone)]
+pub struct ItemStats {
+    damage: u32,
+    tags: Vec<String>,
+}
+
+#[test]
+pub fn test_load_into_map() {
+    let stats = |damage: u32, tag: &str| ItemStats {
+        damage,
+        tags: vec![tag.to_string()],
+    };
+    let mut base = std::collections::HashMap::new();
+    base.insert("sword".to_string(), stats(10, "base"));
+    base.insert("shield".to_string(), stats(0, "base"));
+    let mut mod_items = std::collections::BTreeMap::new();
+    mod_items.insert("sword".to_string(), stats(12, "mod"));
+    mod_items.insert("bow".to_string(), stats(7, "mod"));
+    let mod_file = save_to_mem(0, &mod_items).unwrap();
+
+    // Entries are read one at a time, in the order they were saved
+    let reader = MapReader::<_, String, ItemStats>::new(&mod_file[..], 0).unwrap();
+    assert_eq!(reader.remaining(), Some(2));
+    let entries: Vec<(String, ItemStats)> = reader.map(|entry| entry.unwrap()).collect();
+    assert_eq!(entries, mod_items.clone().into_iter().collect::<Vec<_>>());
+
+    // Keep the base values, but merge the tags
+    let mut merged = base.clone();
+    load_into_map(&mut &mod_file[..], 0, &mut merged, |_key, existing, loaded| {
+        existing.tags.extend(loaded.tags);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(merged.len(), 3);
+    assert_eq!(merged["sword"].damage, 10);
+    assert_eq!(merged["sword"].tags, vec!["base", "mod"]);
+    assert_eq!(merged["bow"], stats(7, "mod"));
+    assert_eq!(merged["shield"], base["shield"]);
+
+    // Conflicts can also be errors
+    let mut strict = base.clone();
+    let result = load_into_map(&mut &mod_file[..], 0, &mut strict, |key, _existing, _loaded| {
+        Err(SavefileError::GeneralError {
+            msg: format!("{} is already defined", key),
+        })
+    });
+    assert!(result.is_err());
+    assert_eq!(strict["bow"], stats(7, "mod"));
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct VisibilityMapV0 {
+    visible: Vec<bool>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct VisibilityMapV1 {
+    #[savefile_versions_as = "0..0:Vec<bool>"]
+    #[savefile_versions = "1.."]
+    visible: PackedBools<Vec<bool>>,
+}
+
+#[test]
+pub fn test_packed_bools() {
+    let visible: Vec<bool> = (0..10_001).map(|i| i % 7 == 0 || i % 5 == 1).collect();
+
+    // Files saved with a Vec<bool> can still be loaded
+    let old = save_to_mem(0, &VisibilityMapV0 { visible: visible.clone() }).unwrap();
+    let loaded: VisibilityMapV1 = load_from_mem(&old, 1).unwrap();
+    assert_eq!(*loaded.visible, visible);
+
+    let new = save_to_mem(1, &loaded).unwrap();
+    assert!(new.len() < old.len() / 7);
+    assert_eq!(load_from_mem::<VisibilityMapV1>(&new, 1).unwrap(), loaded);
+
+    assert_roundtrip(PackedBools(Vec::<bool>::new()));
+    assert_roundtrip(PackedBools(vec![true; 8].into_boxed_slice()));
+    let mut slice_data = Vec::new();
+    PackedBools(&visible[..10]).serialize(&mut Serializer::new_raw(&mut slice_data)).unwrap();
+    let mut reader = &slice_data[..];
+    let from_slice = PackedBools::<Vec<bool>>::deserialize(&mut Deserializer::new_raw(&mut reader)).unwrap();
+    assert_eq!(&from_slice[..], &visible[..10]);
+
+    // The number of bytes must match the number of bools, and unused bits must be zero
+    for (len, bits) in vec![(9u64, vec![0u8]), (8, vec![0, 0]), (3, vec![0b1000])] {
+        let mut data = Vec::new();
+        {
+            let mut serializer = Serializer::new_raw(&mut data);
+            serializer.write_u64(len).unwrap();
+            bits.serialize(&mut serializer).unwrap();
+        }
+        let mut reader = &data[..];
+        assert!(PackedBools::<Vec<bool>>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct AttributeColumnsV0 {
+    weights: Vec<Option<f32>>,
+    labels: Vec<Option<String>>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct AttributeColumnsV1 {
+    #[savefile_versions_as = "0..0:Vec<Option<f32>>"]
+    #[savefile_versions = "1.."]
+    weights: PackedOptions<Vec<Option<f32>>>,
+    #[savefile_versions_as = "0..0:Vec<Option<String>>"]
+    #[savefile_versions = "1.."]
+    labels: PackedOptions<Vec<Option<String>>>,
+}
+
+#[test]
+pub fn test_packed_options() {
+    let weights: Vec<Option<f32>> = (0..10_000)
+        .map(|i| if i % 50 == 0 { Some(i as f32 / 2.0) } else { None })
+        .collect();
+    let labels: Vec<Option<String>> = (0..100).map(|i| if i % 3 == 0 { Some(i.to_string()) } else { None }).collect();
+    let old = save_to_mem(0, &AttributeColumnsV0 { weights: weights.clone(), labels: labels.clone() }).unwrap();
+    let loaded: AttributeColumnsV1 = load_from_mem(&old, 1).unwrap();
+    assert_eq!(*loaded.weights, weights);
+    assert_eq!(*loaded.labels, labels);
+
+    let new = save_to_mem(1, &loaded).unwrap();
+    assert!(new.len() < old.len() / 4);
+    assert_eq!(load_from_mem::<AttributeColumnsV1>(&new, 1).unwrap(), loaded);
+    assert_roundtrip(PackedOptions(Vec::<Option<u8>>::new()));
+    assert_roundtrip(PackedOptions(vec![None, Some((1u16, 2u16)), Some((3, 4))]));
+
+    // There must be as many values as present items
+    let mut data = Vec::new();
+    {
+        let mut serializer = Serializer::new_raw(&mut data);
+        PackedBools(vec![true, false, true]).serialize(&mut serializer).unwrap();
+        vec![7u32].serialize(&mut serializer).unwrap();
+    }
+    let mut reader = &data[..];
+    assert!(PackedOptions::<Vec<Option<u32>>>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+#[savefile_enum_repr(u16)]
+pub enum WideOpcode {
+    Nop,
+    Push(u32),
+    Jump { target: u64 },
+}
+
+#[derive(Savefile, ReprC, Clone, Copy, Debug, PartialEq)]
+#[repr(u32)]
+#[savefile_enum_repr(u32)]
+pub enum WideRegister {
+    Accumulator,
+    Counter,
+}
+
+mod narrow {
+    #[derive(Savefile, Debug, PartialEq)]
+    pub enum WideOpcode {
+        Nop,
+        Push(u32),
+        Jump { target: u64 },
+    }
+}
+
+#[test]
+pub fn test_enum_repr() {
+    assert_roundtrip(vec![WideOpcode::Nop, WideOpcode::Push(7), WideOpcode::Jump { target: 3 }]);
+    assert_roundtrip(vec![WideRegister::Counter, WideRegister::Accumulator]);
+
+    let mut data = Vec::new();
+    WideOpcode::Push(7).serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    assert_eq!(data, vec![1, 0, 7, 0, 0, 0]);
+    let mut data = Vec::new();
+    WideRegister::Counter.serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    assert_eq!(data, vec![1, 0, 0, 0]);
+
+    let saved = save_to_mem(0, &WideOpcode::Jump { target: 3 }).unwrap();
+    match read_schema(&mut &saved[..]).unwrap().1 {
+        Schema::Enum(schema_enum) => assert_eq!(schema_enum.discriminant_size, 2),
+        other => panic!("Expected an enum, got {:?}", other),
+    }
+    assert_eq!(
+        load_from_mem::<WideOpcode>(&saved, 0).unwrap(),
+        WideOpcode::Jump { target: 3 }
+    );
+    let history = SchemaHistory::of::<WideOpcode>(0);
+    let mut data = Vec::new();
+    history.serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    let mut reader = &data[..];
+    assert_eq!(SchemaHistory::deserialize(&mut Deserializer::new_raw(&mut reader)).unwrap(), history);
+
+    // The same enum with u8 discriminators has an incompatible schema
+    let narrow = save_to_mem(0, &narrow::WideOpcode::Push(7)).unwrap();
+    match load_from_mem::<WideOpcode>(&narrow, 0) {
+        Err(SavefileError::IncompatibleSchema { message }) => assert!(message.contains("discriminator of 2 bytes")),
+        other => panic!("Expected an incompatible schema, got {:?}", other),
+    }
+    assert_ne!(
+        WideOpcode::schema(0).stable_hash(),
+        narrow::WideOpcode::schema(0).stable_hash()
+    );
+}
+
+#[derive(Savefile, ReprC, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+#[savefile_enum_as_int]
+pub enum TileKind {
+    Empty = 0,
+    Floor = 3,
+    Wall = 7,
+    Door = 200,
+}
+
+#[derive(Savefile, Clone, Copy, Debug, PartialEq)]
+#[repr(i16)]
+#[savefile_enum_as_int]
+pub enum Elevation {
+    Pit = -2,
+    Ground = 0,
+    Hill = 500,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct TileCell {
+    #[savefile_packed]
+    kind: TileKind,
+    #[savefile_packed]
+    lit: bool,
+}
+
+#[test]
+pub fn test_enum_as_int() {
+    let tiles = vec![TileKind::Wall, TileKind::Door, TileKind::Floor, TileKind::Empty];
+    assert_roundtrip(tiles.clone());
+    assert_roundtrip(vec![Elevation::Pit, Elevation::Hill, Elevation::Ground]);
+    assert_roundtrip(TileCell { kind: TileKind::Door, lit: true });
+    assert_eq!(<TileKind as BitPacked>::bit_width(0), 8);
+
+    // The tiles are saved as their values, by copying the whole vector
+    assert!(Deserializer::new_raw(&mut &[][..]).uses_raw_copy::<TileKind>());
+    let mut data = Vec::new();
+    tiles.serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    assert_eq!(&data[8..], &[7, 200, 3, 0]);
+    let mut data = Vec::new();
+    Elevation::Pit.serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    assert_eq!(data, vec![0xfe, 0xff]);
+
+    match TileKind::schema(0) {
+        Schema::Enum(schema_enum) => {
+            assert_eq!(schema_enum.discriminant_size, 1);
+            let discriminators: Vec<u32> = schema_enum.variants.iter().map(|v| v.discriminator).collect();
+            assert_eq!(discriminators, vec![0, 3, 7, 200]);
+        }
+        other => panic!("Expected an enum, got {:?}", other),
+    }
+    match Elevation::schema(0) {
+        Schema::Enum(schema_enum) => {
+            assert_eq!(schema_enum.discriminant_size, 2);
+            assert_eq!(schema_enum.variants[0].discriminator, 0xfffe);
+        }
+        other => panic!("Expected an enum, got {:?}", other),
+    }
+    let saved = save_to_mem(0, &tiles).unwrap();
+    assert_eq!(load_from_mem::<Vec<TileKind>>(&saved, 0).unwrap(), tiles);
+
+    // Values which are not variants are rejected, both one at a time and when copied
+    let mut reader = &[1u8][..];
+    assert!(TileKind::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+    let mut reader = &[1u8, 0][..];
+    assert!(Elevation::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+    let mut data = Vec::new();
+    vec![7u8, 1].serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    let mut reader = &data[..];
+    assert!(Vec::<TileKind>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+    let mut reader = &data[8..];
+    assert!(<[TileKind; 2]>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+    let mut data = Vec::new();
+    vec![1u8, 2].serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    let mut reader = &data[..];
+    assert!(Vec::<bool>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+    let mut data = Vec::new();
+    vec![[9u8, 0, 0, 0]].serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+    let mut reader = &data[..];
+    assert!(Vec::<TerrainTile>::deserialize(&mut Deserializer::new_raw(&mut reader)).is_err());
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Permissions: u32 {
+        const READ = 1;
+        const WRITE = 2;
+        const EXECUTE = 4;
+    }
+}
+savefile::savefile_bitflags!(Permissions);
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Features: u8 {
+        const SOUND = 1;
+        const NETWORK = 0x10;
+    }
+}
+savefile::savefile_bitflags!(Features, UnknownBits::Truncate);
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct FileEntry {
+    name: String,
+    permissions: Permissions,
+    features: Features,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct FileEntryRawPermissions {
+    name: String,
+    permissions: u32,
+    features: u8,
+}
+
+#[test]
+pub fn test_bitflags() {
+    assert_roundtrip(FileEntry {
+        name: "run.sh".to_string(),
+        permissions: Permissions::READ | Permissions::EXECUTE,
+        features: Features::empty(),
+    });
+    assert_roundtrip(vec![Permissions::all(), Permissions::empty(), Permissions::WRITE]);
+
+    // The flags have the schema of their bits, and are saved as them
+    assert_eq!(Permissions::schema(0), u32::schema(0));
+    let entry = FileEntryRawPermissions {
+        name: "notes.txt".to_string(),
+        permissions: 3,
+        features: 0x11,
+    };
+    let saved = save_to_mem(0, &entry).unwrap();
+    let loaded: FileEntry = load_from_mem(&saved, 0).unwrap();
+    assert_eq!(loaded.permissions, Permissions::READ | Permissions::WRITE);
+    assert_eq!(loaded.features, Features::SOUND | Features::NETWORK);
+    assert_eq!(loaded.permissions.introspect_value(), "READ | WRITE");
+
+    // Unknown bits are an error, or removed
+    let entry = FileEntryRawPermissions {
+        name: "notes.txt".to_string(),
+        permissions: 0x41,
+        features: 0,
+    };
+    let saved = save_to_mem(0, &entry).unwrap();
+    match load_from_mem::<FileEntry>(&saved, 0) {
+        Err(SavefileError::DeserializationFailed { path, cause, .. }) => {
+            assert_eq!(path, "permissions");
+            match *cause {
+                SavefileError::GeneralError { msg } => assert!(msg.ends_with("has unknown bits 0x40"), "{}", msg),
+                other => panic!("Unexpected error {:?}", other),
+            }
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+    let entry = FileEntryRawPermissions {
+        name: "notes.txt".to_string(),
+        permissions: 1,
+        features: 0xff,
+    };
+    let saved = save_to_mem(0, &entry).unwrap();
+    let loaded: FileEntry = load_from_mem(&saved, 0).unwrap();
+    assert_eq!(loaded.features, Features::all());
+}
+
+const SHAPES_V0: VersionNamespace = VersionNamespace::new("shapes", 0);
+const SHAPES: VersionNamespace = VersionNamespace::new("shapes", 1);
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct ShapeUnversioned {
+    sides: u32,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+#[savefile_namespace = "SHAPES_V0"]
+pub struct ShapeV0 {
+    sides: u32,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+#[savefile_namespace = "SHAPES"]
+pub struct Shape {
+    sides: u32,
+    #[savefile_versions = "1.."]
+    color: u8,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct SceneUnversioned {
+    name: String,
+    #[savefile_versions = "2.."]
+    scale: u32,
+    shapes: Vec<ShapeUnversioned>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct SceneV0 {
+    name: String,
+    #[savefile_versions = "2.."]
+    scale: u32,
+    shapes: Vec<ShapeV0>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct Scene {
+    name: String,
+    #[savefile_versions = "2.."]
+    scale: u32,
+    shapes: Vec<Shape>,
+}
+
+#[derive(Savefile)]
+pub struct LazyScene {
+    name: String,
+    #[savefile_versions = "2.."]
+    scale: u32,
+    shapes: Lazy<Vec<Shape>>,
+}
+
+#[derive(Savefile)]
+pub struct MixedScene {
+    old: ShapeV0,
+    new: Shape,
+}
+
+#[test]
+pub fn test_version_namespaces() {
+    let scene = Scene {
+        name: "square".to_string(),
+        scale: 3,
+        shapes: vec![Shape { sides: 4, color: 7 }, Shape { sides: 3, color: 9 }],
+    };
+    assert_roundtrip_version(scene, 2);
+
+    // The types in the namespace have its version, whichever version the file has
+    assert_roundtrip_version(vec![Shape { sides: 4, color: 7 }], 0);
+    let scene = Scene {
+        name: "square".to_string(),
+        scale: 3,
+        shapes: vec![Shape { sides: 4, color: 7 }],
+    };
+    let saved = save_to_mem(2, &scene).unwrap();
+    assert_eq!(read_header(&mut &saved[..]).unwrap().format_version, 2);
+    let mut compressed = Vec::new();
+    save_compressed(&mut compressed, 2, &scene).unwrap();
+    assert_eq!(load_from_mem::<Scene>(&compressed, 2).unwrap(), scene);
+    let mut checksummed = Vec::new();
+    save_checksummed(&mut checksummed, 2, &scene).unwrap();
+    assert_eq!(load_from_mem::<Scene>(&checksummed, 2).unwrap(), scene);
+
+    // Files saved with an older version of the namespace, or before the types were placed in
+    // it, are loaded with that version
+    let old_scene = SceneV0 {
+        name: "triangle".to_string(),
+        scale: 5,
+        shapes: vec![ShapeV0 { sides: 3 }],
+    };
+    let saved = save_to_mem(2, &old_scene).unwrap();
+    let loaded: Scene = load_from_mem(&saved, 2).unwrap();
+    assert_eq!(loaded.scale, 5);
+    assert_eq!(loaded.shapes, vec![Shape { sides: 3, color: 0 }]);
+    let unversioned = SceneUnversioned {
+        name: "triangle".to_string(),
+        scale: 5,
+        shapes: vec![ShapeUnversioned { sides: 3 }],
+    };
+    let saved = save_to_mem(2, &unversioned).unwrap();
+    assert_eq!(read_header(&mut &saved[..]).unwrap().format_version, 1);
+    let loaded: Scene = load_from_mem(&saved, 2).unwrap();
+    assert_eq!(loaded.shapes, vec![Shape { sides: 3, color: 0 }]);
+
+    // Lazy values keep the versions of the namespaces in the file they were loaded from
+    let saved = save_to_mem(2, &old_scene).unwrap();
+    let lazy: LazyScene = load_from_mem(&saved, 2).unwrap();
+    let resaved = save_to_mem(2, &lazy).unwrap();
+    let mut lazy: LazyScene = load_from_mem(&saved, 2).unwrap();
+    assert_eq!(*lazy.shapes.get().unwrap(), vec![Shape { sides: 3, color: 0 }]);
+    let loaded: Scene = load_from_mem(&resaved, 2).unwrap();
+    assert_eq!(loaded.shapes, vec![Shape { sides: 3, color: 0 }]);
+
+    // Files with a later version of the namespace than in memory can't be loaded
+    let saved = save_to_mem(2, &scene).unwrap();
+    match load_from_mem::<SceneV0>(&saved, 2) {
+        Err(SavefileError::WrongVersion { msg }) => assert!(msg.contains("namespace shapes"), "{}", msg),
+        other => panic!("Unexpected result {:?}", other),
+    }
+
+    // A namespace must have one version in each file
+    let mixed = MixedScene {
+        old: ShapeV0 { sides: 1 },
+        new: Shape { sides: 2, color: 3 },
+    };
+    assert!(save_to_mem(0, &mixed).is_err());
+
+    // Vectors written one item at a time record the namespaces too
+    let mut writer = VecWriter::<_, Shape>::new(Cursor::new(Vec::new()), 0).unwrap();
+    writer.push(&Shape { sides: 5, color: 1 }).unwrap();
+    let written = writer.finish().unwrap().into_inner();
+    let read: Vec<Shape> = VecReader::<_, Shape>::new(&written[..], 0).unwrap().map(|item| item.unwrap()).collect();
+    assert_eq!(read, vec![Shape { sides: 5, color: 1 }]);
+    let saved = save_to_mem(0, &vec![ShapeV0 { sides: 6 }]).unwrap();
+    let read: Vec<Shape> = VecReader::<_, Shape>::new(&saved[..], 0).unwrap().map(|item| item.unwrap()).collect();
+    assert_eq!(read, vec![Shape { sides: 6, color: 0 }]);
+}
+
+#[test]
+pub fn test_save_log_with_version_namespaces() {
+    let path = "test_save_log_with_version_namespaces.bin";
+    SaveLog::<Shape>::create(path, 0).unwrap().append(&Shape { sides: 4, color: 2 }).unwrap();
+    let read: Vec<Shape> = SaveLog::<Shape>::read(path, 0).unwrap().map(|shape| shape.unwrap()).collect();
+    assert_eq!(read, vec![Shape { sides: 4, color: 2 }]);
+    match SaveLog::<ShapeV0>::read(path, 0) {
+        Err(SavefileError::WrongVersion { msg }) => assert!(msg.contains("namespace shapes"), "{}", msg),
+        Err(err) => panic!("Unexpected error {}", err),
+        Ok(_) => panic!("A log with a later version of a namespace was read"),
+    }
+
+    // Logs of an older version of the namespace are read with that version, but can't be
+    // appended to with the current version
+    SaveLog::<ShapeV0>::create(path, 0).unwrap().append(&ShapeV0 { sides: 3 }).unwrap();
+    let read: Vec<Shape> = SaveLog::<Shape>::read(path, 0).unwrap().map(|shape| shape.unwrap()).collect();
+    assert_eq!(read, vec![Shape { sides: 3, color: 0 }]);
+    assert!(SaveLog::<Shape>::open(path, 0).is_err());
+    SaveLog::<ShapeV0>::open(path, 0).unwrap().append(&ShapeV0 { sides: 5 }).unwrap();
+    assert_eq!(SaveLog::<Shape>::read(path, 0).unwrap().count(), 2);
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub enum BurstMessage {
+    A { burst: u32, other: u16 },
+    B { big_endian: bool, x: u8 },
+}
+
+#[test]
+pub fn test_combined_writes_of_fields_named_like_locals() {
+    assert_roundtrip(vec![
+        BurstMessage::A { burst: 7, other: 300 },
+        BurstMessage::B { big_endian: true, x: 3 },
+    ]);
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub enum PackedMessage {
+    Flags {
+        #[savefile_packed]
+        packed_bits: bool,
+        #[savefile_packed]
+        other: bool,
+    },
+    Empty,
+}
+
+#[test]
+pub fn test_packed_fields_named_like_locals() {
+    assert_roundtrip(vec![
+        PackedMessage::Flags { packed_bits: true, other: false },
+        PackedMessage::Empty,
+    ]);
+}
@@ -1,7 +1,7 @@
 use savefile::prelude::*;
 use savefile::{Introspector, IntrospectorNavCommand, IntrospectedElementKey, IntrospectionError};
 use parking_lot::{RwLock, Mutex};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::sync::Arc;
 use std::rc::Rc;
 use std::collections::BinaryHeap;
@@ -159,6 +159,23 @@ pub fn do_test_refcell() {
 
     assert_eq!(test.introspect_len(), 0);
 }
+
+#[derive(Savefile)]
+pub struct PlayerStats {
+    health: Cell<u32>,
+}
+
+#[test]
+pub fn do_test_cell() {
+    let test = PlayerStats { health: Cell::new(32) };
+    test.health.set(17);
+
+    assert_eq!(test.introspect_len(), 1);
+    let child = test.introspect_child(0).unwrap();
+    assert_eq!(child.key(), "health");
+    assert_eq!(child.val().introspect_value(), "Cell(17 (deep introspect not supported))");
+    assert_eq!(child.val().introspect_len(), 0);
+}
 #[test]
 pub fn do_test_rc() {
     let test = Rc::new(32);
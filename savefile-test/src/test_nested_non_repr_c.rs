@@ -9,7 +9,6 @@ struct Inner {
 
 
 
-#[cfg(feature="nightly")] //The whole system to use a faster serialization/deserialization for Vec<T> where T:ReprC only works on nightly (since it depends on specialisation)
 #[test]
 #[should_panic] //Inner struct is not packed (same in memory as on disk)
 #[cfg(debug_assertions)] //This test only works in debug builds
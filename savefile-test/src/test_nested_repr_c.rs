@@ -40,9 +40,13 @@ fn test_not_raw_memcpy2() {
     let savefile_header=9;
     let savefile_lib_version = 2;
     let is_compressed = 1;
+    let has_schema = 1;
+    let payload_length = 8;
+    let created = 8;
+    let schema_hash = 8;
     let misaligner=1;
     let inner=4;
-    assert_eq!(f_internal_size, version + vec_overhead + misaligner + inner +savefile_header+savefile_lib_version+is_compressed); //3 bytes padding also because of ReprC-optimization
+    assert_eq!(f_internal_size, version + vec_overhead + misaligner + inner +savefile_header+savefile_lib_version+is_compressed+has_schema+payload_length+created+schema_hash); //3 bytes padding also because of ReprC-optimization
 }
 
 
@@ -65,4 +69,26 @@ fn test_various_types_for_reprc() {
     assert_eq!(UnnamedFieldsStruct::repr_c_optimization_safe(0), true);
 
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_repr_c_layout_is_detected() {
+    assert!(<u32 as WithSchema>::repr_c_layout(0).is_yes());
+    assert!(<Inner as WithSchema>::repr_c_layout(0).is_yes());
+    assert!(<TestReprEnum as WithSchema>::repr_c_layout(0).is_yes());
+    assert!(!<Nested as WithSchema>::repr_c_layout(0).is_yes());
+    assert!(!<String as WithSchema>::repr_c_layout(0).is_yes());
+}
+
+#[test]
+fn test_repr_c_vec_is_raw_copy() {
+    use std::io::Cursor;
+    let sample = vec![Inner { x: 1 }, Inner { x: 2 }];
+    let mut f = Cursor::new(Vec::new());
+    Serializer::save_noschema(&mut f, 0, &sample).unwrap();
+    let data = f.into_inner();
+    let payload = &data[data.len() - 8..];
+    assert_eq!(payload, &[1, 0, 0, 0, 2, 0, 0, 0]);
+    let roundtripped: Vec<Inner> = load_noschema(&mut &data[..], 0).unwrap();
+    assert_eq!(roundtripped, sample);
+}
@@ -0,0 +1,71 @@
+use pyo3::exceptions::PyKeyError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use savefile::prelude::*;
+use savefile::python::PyDocument;
+
+#[derive(Savefile, Debug, PartialEq)]
+enum PythonItem {
+    Empty,
+    Potion { strength: u8 },
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct PythonSave {
+    name: String,
+    level: u32,
+    items: Vec<PythonItem>,
+    companion: Option<String>,
+}
+
+#[test]
+fn test_python_document_roundtrip() {
+    let save = PythonSave {
+        name: "Ada".to_string(),
+        level: 3,
+        items: vec![PythonItem::Empty, PythonItem::Potion { strength: 2 }],
+        companion: None,
+    };
+    let bytes = save_to_mem(1, &save).unwrap();
+    let resaved = Python::with_gil(|py| {
+        let document = py
+            .get_type_bound::<PyDocument>()
+            .call_method1("load", (PyBytes::new_bound(py, &bytes),))
+            .unwrap();
+        assert_eq!(document.getattr("version").unwrap().extract::<u32>().unwrap(), 1);
+        let name = document.call_method1("get", ("name",)).unwrap();
+        assert_eq!(name.extract::<String>().unwrap(), "Ada");
+        let strength = document.call_method1("get", ("items/1/strength",)).unwrap();
+        assert_eq!(strength.extract::<u8>().unwrap(), 2);
+        assert!(document.call_method1("get", ("items/1/companion",)).is_err());
+        assert!(document
+            .call_method1("get", ("missing",))
+            .unwrap_err()
+            .is_instance_of::<PyKeyError>(py));
+
+        document.call_method1("set", ("name", "Bob")).unwrap();
+        document.call_method1("set", ("level", 7)).unwrap();
+        document.call_method1("set", ("items/1", "Empty")).unwrap();
+        let potion = py
+            .eval_bound("{'variant': 'Potion', 'fields': {'strength': 5}}", None, None)
+            .unwrap();
+        document.call_method1("set", ("items/0", potion)).unwrap();
+        document.call_method1("set", ("companion", "Cat")).unwrap();
+        // Values which don't match the schema are rejected
+        assert!(document.call_method1("set", ("level", "high")).is_err());
+        assert!(document.call_method1("set", ("items/0", "Broken")).is_err());
+
+        let saved = document.call_method0("save").unwrap();
+        saved.downcast::<PyBytes>().unwrap().as_bytes().to_vec()
+    });
+    let loaded: PythonSave = load_from_mem(&resaved, 1).unwrap();
+    assert_eq!(
+        loaded,
+        PythonSave {
+            name: "Bob".to_string(),
+            level: 7,
+            items: vec![PythonItem::Potion { strength: 5 }, PythonItem::Empty],
+            companion: Some("Cat".to_string()),
+        }
+    );
+}
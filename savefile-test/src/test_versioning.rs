@@ -313,3 +313,349 @@ fn test_change_type_of_field2() {
         1
         );
 }
+
+/// A type whose fields swap places between version 0 and 1, which breaks loading
+struct SwappedFields;
+
+impl WithSchema for SwappedFields {
+	fn schema(version: u32) -> Schema {
+		let mut fields = vec![
+			Field { name: "a".to_string(), value: schema_of::<u32>(version) },
+			Field { name: "b".to_string(), value: schema_of::<String>(version) },
+		];
+		if version >= 1 {
+			fields.reverse();
+		}
+		Schema::Struct(SchemaStruct { dbg_name: "SwappedFields".to_string(), fields })
+	}
+}
+
+#[test]
+fn test_verify_version_compatibility() {
+	verify_version_compatibility::<Version3>(0, 2).unwrap();
+	verify_version_compatibility::<SwappedFields>(0, 0).unwrap();
+	match verify_version_compatibility::<SwappedFields>(0, 1) {
+		Err(SavefileError::IncompatibleSchema { message }) => {
+			assert_eq!(message, "Version 0 to 1: At location [./SwappedFields]: Field a has moved relative to the other fields. Fields present in several versions must keep their order.");
+		}
+		other => panic!("Unexpected result: {:?}", other),
+	}
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct MigratedV0 {
+	name: String,
+	x: u32,
+	y: u32,
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct MigratedV1 {
+	name: String,
+	coordinates: Vec<u32>,
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct MigratedV2 {
+	names: Vec<String>,
+	coordinates: Vec<u32>,
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct MigratedV3 {
+	names: Vec<String>,
+	coordinates: Vec<u32>,
+	#[savefile_versions = "3.."]
+	extra: u8,
+}
+
+fn migrator() -> Migrator<MigratedV3> {
+	Migrator::new(3)
+		.with_upgrade(0, |old: MigratedV0| MigratedV1 { name: old.name, coordinates: vec![old.x, old.y] })
+		.with_upgrade(1, |old: MigratedV1| MigratedV3 { names: vec![old.name], coordinates: old.coordinates, extra: 0 })
+}
+
+#[test]
+fn test_migrator() {
+	let expected = MigratedV3 { names: vec!["a".to_string()], coordinates: vec![1, 2], extra: 0 };
+
+	let mut v0 = Vec::new();
+	save(&mut v0, 0, &MigratedV0 { name: "a".to_string(), x: 1, y: 2 }).unwrap();
+	assert_eq!(migrator().load(&mut &v0[..]).unwrap(), expected);
+
+	let mut v1 = Vec::new();
+	save(&mut v1, 1, &MigratedV1 { name: "a".to_string(), coordinates: vec![1, 2] }).unwrap();
+	assert_eq!(migrator().load(&mut &v1[..]).unwrap(), expected);
+
+	// Version 2 files were saved using the current type, before 'extra' was added
+	let mut v2 = Vec::new();
+	save(&mut v2, 2, &MigratedV2 { names: vec!["a".to_string()], coordinates: vec![1, 2] }).unwrap();
+	assert_eq!(migrator().load(&mut &v2[..]).unwrap(), expected);
+
+	let current = MigratedV3 { extra: 7, ..expected };
+	let mut v3 = Vec::new();
+	save(&mut v3, 3, &current).unwrap();
+	assert_eq!(migrator().load(&mut &v3[..]).unwrap(), current);
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+enum InventoryEventV0 {
+	Dropped { item: u32, count: u16 },
+	Sold(u32, String),
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+enum InventoryEventV1 {
+	Dropped {
+		item: u32,
+		#[savefile_versions_as = "0..0:u16"]
+		#[savefile_versions = "1.."]
+		count: u32,
+		#[savefile_versions = "1.."]
+		#[savefile_default_val = "5"]
+		slot: u8,
+	},
+	Sold(
+		u32,
+		#[savefile_versions = "0..0"]
+		Removed<String>,
+		#[savefile_versions = "1.."]
+		u64
+	),
+}
+
+#[test]
+fn test_versioned_fields_in_variants() {
+	use ::assert_roundtrip_to_new_version;
+	use ::assert_roundtrip_version;
+	assert_roundtrip_to_new_version(
+		InventoryEventV0::Dropped { item: 3, count: 7 },
+		0,
+		InventoryEventV1::Dropped { item: 3, count: 7, slot: 5 },
+		1,
+	);
+	assert_roundtrip_to_new_version(
+		InventoryEventV0::Sold(3, "Bob".to_string()),
+		0,
+		InventoryEventV1::Sold(3, Removed::new(), 0),
+		1,
+	);
+	assert_roundtrip_version(InventoryEventV1::Dropped { item: 3, count: 70000, slot: 2 }, 1);
+	assert_roundtrip_version(InventoryEventV1::Sold(3, Removed::new(), 12), 1);
+
+	match (InventoryEventV0::schema(0), InventoryEventV1::schema(0)) {
+		(Schema::Enum(v0), Schema::Enum(v1)) => assert_eq!(v0.variants, v1.variants),
+		_ => panic!("Expected enum schemas"),
+	}
+	match InventoryEventV1::schema(1) {
+		Schema::Enum(schema_enum) => {
+			let names: Vec<&str> = schema_enum.variants[0].fields.iter().map(|field| field.name.as_str()).collect();
+			assert_eq!(names, ["item", "count", "slot"]);
+			let names: Vec<&str> = schema_enum.variants[1].fields.iter().map(|field| field.name.as_str()).collect();
+			assert_eq!(names, ["0", "2"]);
+		}
+		_ => panic!("Expected an enum schema"),
+	}
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct BagItem {
+	id: u32,
+	label: String,
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct BagV0 {
+	name: String,
+	items: Vec<BagItem>,
+	weight: u16,
+}
+
+/// The schema BagItem had, for loading bags without the BagItem type
+struct OldBagItems;
+
+impl WithSchema for OldBagItems {
+	fn schema(version: u32) -> Schema {
+		Schema::Vector(::std::sync::Arc::new(Schema::Struct(SchemaStruct {
+			dbg_name: "BagItem".to_string(),
+			fields: vec![
+				Field { name: "id".to_string(), value: schema_of::<u32>(version) },
+				Field { name: "label".to_string(), value: schema_of::<String>(version) },
+			],
+		})))
+	}
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct BagV1 {
+	name: String,
+	#[savefile_versions = "..0"]
+	items: RemovedOpaque<OldBagItems>,
+	weight: u16,
+}
+
+#[test]
+fn test_removed_opaque() {
+	use ::assert_roundtrip_version;
+	let bag = BagV0 {
+		name: "Pack".to_string(),
+		items: vec![BagItem { id: 1, label: "rope".to_string() }, BagItem { id: 2, label: "lamp".to_string() }],
+		weight: 9,
+	};
+	let expected = BagV1 { name: "Pack".to_string(), items: RemovedOpaque::new(), weight: 9 };
+	let bytes = save_to_mem(0, &bag).unwrap();
+	assert_eq!(load_from_mem::<BagV1>(&bytes, 1).unwrap(), expected);
+
+	let mut varint = Vec::new();
+	save_with_encoding(&mut varint, 0, &bag, Encoding { lengths: LengthEncoding::Varint, ..Encoding::default() }).unwrap();
+	assert_eq!(load_from_mem::<BagV1>(&varint, 1).unwrap(), expected);
+
+	assert_roundtrip_version(BagV1 { name: "Bag".to_string(), items: RemovedOpaque::new(), weight: 1 }, 1);
+	match (BagV0::schema(0), BagV1::schema(0)) {
+		(Schema::Struct(v0), Schema::Struct(v1)) => assert_eq!(v0.fields, v1.fields),
+		_ => panic!("Expected struct schemas"),
+	}
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct StatsV0 {
+	kills: u32,
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct StatsV1 {
+	kills: u32,
+	#[savefile_versions = "1.."]
+	deaths: u32,
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct StatsV2 {
+	kills: u32,
+	#[savefile_versions = "1.."]
+	deaths: u32,
+	#[savefile_versions = "2.."]
+	title: Option<String>,
+	#[savefile_versions = "2.."]
+	badges: Vec<String>,
+}
+
+#[test]
+fn test_implicit_default_of_added_fields() {
+	use ::assert_roundtrip_to_new_version;
+	assert_roundtrip_to_new_version(StatsV0 { kills: 3 }, 0, StatsV1 { kills: 3, deaths: 0 }, 1);
+	assert_roundtrip_to_new_version(
+		StatsV0 { kills: 3 },
+		0,
+		StatsV2 { kills: 3, deaths: 0, title: None, badges: vec![] },
+		2,
+	);
+	assert_roundtrip_to_new_version(
+		StatsV1 { kills: 3, deaths: 4 },
+		1,
+		StatsV2 { kills: 3, deaths: 4, title: None, badges: vec![] },
+		2,
+	);
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+#[savefile_max_version = 4]
+struct StatsV4 {
+	kills: u32,
+	#[savefile_versions = "2.."]
+	title: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+enum ShapeV2 {
+	Circle(f32),
+	#[savefile_versions = "1.."]
+	Rect {
+		#[savefile_versions = "0..1"]
+		size: Removed<f32>,
+		#[savefile_versions = "2.."]
+		height: f32,
+	},
+}
+
+#[derive(Debug, PartialEq, Default, Savefile)]
+struct InnerV1 {
+	a: u32,
+	#[savefile_versions = "1.."]
+	b: u32,
+}
+
+#[derive(Debug, PartialEq, Default, Savefile)]
+struct OuterV1 {
+	inner: InnerV1,
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct ContainersV3 {
+	inners: Vec<InnerV1>,
+	by_name: std::collections::HashMap<String, Option<Box<InnerV1>>>,
+	#[savefile_versions = "3.."]
+	extra: (u8, OuterV1),
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct RemovedInner {
+	#[savefile_versions = "0..0"]
+	old: Removed<InnerV1>,
+	#[savefile_versions = "0..0"]
+	older: Removed<u32>,
+}
+
+#[derive(Debug, PartialEq, Savefile)]
+struct TreeV1 {
+	value: InnerV1,
+	children: Vec<TreeV1>,
+	pair: Option<Box<[TreeV1; 2]>>,
+}
+
+#[test]
+fn test_savefile_max_version_of_nested_types() {
+	// The current versions of the types of fields are included
+	assert_eq!(InnerV1::SAVEFILE_MAX_VERSION, 1);
+	assert_eq!(OuterV1::SAVEFILE_MAX_VERSION, 1);
+	assert_eq!(ContainersV3::SAVEFILE_MAX_VERSION, 3);
+	assert_eq!(TreeV1::SAVEFILE_MAX_VERSION, 1);
+	// but not those of removed fields
+	assert_eq!(RemovedInner::SAVEFILE_MAX_VERSION, 1);
+}
+
+#[test]
+fn test_savefile_max_version() {
+	use ::assert_roundtrip_version;
+	assert_eq!(Version1::SAVEFILE_MAX_VERSION, 0);
+	assert_eq!(Version2::SAVEFILE_MAX_VERSION, 1);
+	assert_eq!(Version3::SAVEFILE_MAX_VERSION, 2);
+	assert_eq!(EnumVer2::SAVEFILE_MAX_VERSION, 1);
+	assert_eq!(StatsV2::SAVEFILE_MAX_VERSION, 2);
+	assert_eq!(StatsV4::SAVEFILE_MAX_VERSION, 4);
+	assert_eq!(ShapeV2::SAVEFILE_MAX_VERSION, 2);
+	assert_roundtrip_version(StatsV4 { kills: 1, title: None }, StatsV4::SAVEFILE_MAX_VERSION);
+}
+
+#[test]
+fn test_save_current() {
+	let stats = StatsV2 { kills: 3, deaths: 4, title: Some("Ace".to_string()), badges: vec![] };
+	let mut data = Vec::new();
+	save_current(&mut data, &stats).unwrap();
+	assert_eq!(read_header(&mut &data[..]).unwrap().version, 2);
+	assert_eq!(load_current::<StatsV2>(&mut &data[..]).unwrap(), stats);
+
+	// Older files are loaded as the current version
+	let old = save_to_mem(StatsV1::SAVEFILE_MAX_VERSION, &StatsV1 { kills: 5, deaths: 6 }).unwrap();
+	assert_eq!(
+		load_current::<StatsV2>(&mut &old[..]).unwrap(),
+		StatsV2 { kills: 5, deaths: 6, title: None, badges: vec![] }
+	);
+
+	// Types containing versioned types are saved with the version of their contents
+	let outer = OuterV1 { inner: InnerV1 { a: 1, b: 2 } };
+	let mut data = Vec::new();
+	save_current(&mut data, &outer).unwrap();
+	assert_eq!(read_header(&mut &data[..]).unwrap().version, 1);
+	assert_eq!(load_current::<OuterV1>(&mut &data[..]).unwrap(), outer);
+}
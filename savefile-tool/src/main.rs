@@ -0,0 +1,103 @@
+//! Command line tool for inspecting and editing savefile files, without access
+//! to the rust types which wrote them. The files must have been saved with a schema.
+
+extern crate savefile;
+
+use savefile::prelude::*;
+use std::process::exit;
+
+const USAGE: &str = "Usage:
+    savefile-tool dump <file>                 Print the contents of the file as JSON
+    savefile-tool schema <file>               Print the version and schema of the file
+    savefile-tool diff <file1> <file2>        Print the differences between two files
+    savefile-tool get <file> <path>           Print the value at the path as JSON
+    savefile-tool set <file> <path> <value>   Replace the primitive value at the path
+
+Paths consist of field names and indices separated by '/', such as 'players/0/name'.
+Only primitive values can be set. Options can be set to 'null' to make them empty.";
+
+fn load_document(path: &str) -> Result<Document, String> {
+    let mut file = std::io::BufReader::new(std::fs::File::open(path).map_err(|err| format!("{}: {}", path, err))?);
+    Document::load(&mut file).map_err(|err| format!("{}: {}", path, err))
+}
+
+fn value_at<'a>(document: &'a Document, path: &str) -> Result<&'a DynValue, String> {
+    document.get(path).ok_or_else(|| format!("No value at path '{}'", path))
+}
+
+/// Parse `text` as a value of the same type as `current`
+fn parse_like(current: &DynValue, text: &str) -> Result<DynValue, String> {
+    fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+        text.parse::<T>().map_err(|_| format!("Invalid value '{}'", text))
+    }
+    Ok(match current {
+        DynValue::I8(_) => DynValue::I8(parse(text)?),
+        DynValue::U8(_) => DynValue::U8(parse(text)?),
+        DynValue::I16(_) => DynValue::I16(parse(text)?),
+        DynValue::U16(_) => DynValue::U16(parse(text)?),
+        DynValue::I32(_) => DynValue::I32(parse(text)?),
+        DynValue::U32(_) => DynValue::U32(parse(text)?),
+        DynValue::I64(_) => DynValue::I64(parse(text)?),
+        DynValue::U64(_) => DynValue::U64(parse(text)?),
+        DynValue::F32(_) => DynValue::F32(parse(text)?),
+        DynValue::F64(_) => DynValue::F64(parse(text)?),
+        DynValue::Bool(_) => DynValue::Bool(parse(text)?),
+        DynValue::String(_) => DynValue::String(text.to_string()),
+        DynValue::Option(_) if text == "null" => DynValue::Option(None),
+        DynValue::Option(Some(value)) => DynValue::Option(Some(Box::new(parse_like(value, text)?))),
+        _ => return Err("Only primitive values can be set".to_string()),
+    })
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    match args.as_slice() {
+        ["dump", file] => {
+            println!("{}", load_document(file)?.value.to_json());
+        }
+        ["schema", file] => {
+            let document = load_document(file)?;
+            println!("Version: {}", document.version);
+            println!("{}", document.schema);
+        }
+        ["diff", file1, file2] => {
+            let a = load_document(file1)?;
+            let b = load_document(file2)?;
+            if a.version != b.version {
+                println!("Version: {} -> {}", a.version, b.version);
+            }
+            let schema_differences = schema_differences(&b.schema, &a.schema);
+            if !schema_differences.is_empty() {
+                for difference in schema_differences {
+                    println!("Schema: {}", difference);
+                }
+                return Ok(());
+            }
+            for difference in a.value.diff(&b.value) {
+                println!("{}", difference);
+            }
+        }
+        ["get", file, path] => {
+            let document = load_document(file)?;
+            println!("{}", value_at(&document, path)?.to_json());
+        }
+        ["set", file, path, text] => {
+            let mut document = load_document(file)?;
+            let new_value = parse_like(value_at(&document, path)?, text)?;
+            *document.get_mut(path).unwrap() = new_value;
+            let mut data = Vec::new();
+            document.save(&mut data).map_err(|err| err.to_string())?;
+            std::fs::write(file, data).map_err(|err| format!("{}: {}", file, err))?;
+        }
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        exit(1);
+    }
+}
@@ -1,10 +0,0 @@
-extern crate rustc_version;
-use rustc_version::{version_meta, Channel};
-fn main() {
-    match version_meta().unwrap().channel {
-        Channel::Nightly => {
-            println!("cargo:rustc-cfg=feature=\"nightly\"");
-        }
-        _ => {}
-    }
-}
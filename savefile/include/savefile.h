@@ -0,0 +1,78 @@
+/*
+ * C interface for reading savefile data, enabled by the "ffi" feature of the savefile crate.
+ * See the documentation of the savefile::ffi module for details.
+ *
+ * All read functions return 0 on success and -1 on failure, in which case
+ * savefile_reader_error returns the error message.
+ */
+#ifndef SAVEFILE_H
+#define SAVEFILE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SAVEFILE_SCHEMA_STRUCT 1
+#define SAVEFILE_SCHEMA_ENUM 2
+#define SAVEFILE_SCHEMA_PRIMITIVE 3
+#define SAVEFILE_SCHEMA_VECTOR 4
+#define SAVEFILE_SCHEMA_UNDEFINED 5
+#define SAVEFILE_SCHEMA_ZERO_SIZE 6
+#define SAVEFILE_SCHEMA_OPTION 7
+#define SAVEFILE_SCHEMA_ARRAY 8
+#define SAVEFILE_SCHEMA_BIT_PACKED 9
+#define SAVEFILE_SCHEMA_VARIANT 10
+
+typedef struct SavefileReader SavefileReader;
+typedef struct SavefileSchema SavefileSchema;
+
+typedef struct SavefileHeaderInfo {
+    uint32_t version;
+    bool has_schema;
+    bool has_schema_hash;
+    bool has_checksum;
+} SavefileHeaderInfo;
+
+/* The data must stay valid and unchanged until the reader is freed. */
+SavefileReader *savefile_reader_new(const uint8_t *data, size_t len);
+void savefile_reader_free(SavefileReader *reader);
+const char *savefile_reader_error(const SavefileReader *reader);
+size_t savefile_reader_position(const SavefileReader *reader);
+
+int savefile_read_header(SavefileReader *reader, SavefileHeaderInfo *header);
+SavefileSchema *savefile_read_schema(SavefileReader *reader);
+int savefile_read_schema_hash(SavefileReader *reader, uint64_t *hash);
+
+int savefile_read_bool(SavefileReader *reader, bool *value);
+int savefile_read_u8(SavefileReader *reader, uint8_t *value);
+int savefile_read_i8(SavefileReader *reader, int8_t *value);
+int savefile_read_u16(SavefileReader *reader, uint16_t *value);
+int savefile_read_i16(SavefileReader *reader, int16_t *value);
+int savefile_read_u32(SavefileReader *reader, uint32_t *value);
+int savefile_read_i32(SavefileReader *reader, int32_t *value);
+int savefile_read_u64(SavefileReader *reader, uint64_t *value);
+int savefile_read_i64(SavefileReader *reader, int64_t *value);
+int savefile_read_f32(SavefileReader *reader, float *value);
+int savefile_read_f64(SavefileReader *reader, double *value);
+int savefile_read_length(SavefileReader *reader, uint64_t *length);
+/* The string is not null terminated, and is valid until the next call with the reader. */
+int savefile_read_string(SavefileReader *reader, const uint8_t **data, size_t *len);
+
+void savefile_schema_free(SavefileSchema *schema);
+uint32_t savefile_schema_kind(const SavefileSchema *schema);
+uint32_t savefile_schema_primitive(const SavefileSchema *schema);
+const char *savefile_schema_name(const SavefileSchema *schema);
+uint64_t savefile_schema_count(const SavefileSchema *schema);
+size_t savefile_schema_child_count(const SavefileSchema *schema);
+const SavefileSchema *savefile_schema_child(const SavefileSchema *schema, size_t index);
+const char *savefile_schema_child_name(const SavefileSchema *schema, size_t index);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,380 @@
+//! Calling into code in another dynamic library, possibly built by a different compiler,
+//! by serializing the arguments and results of each call.
+//!
+//! Rust has no stable ABI, so the only types which can safely be passed between libraries
+//! built with different compilers are the `#[repr(C)]` types of this module. The arguments
+//! and return values themselves are savefile-serialized, and their schemas are compared when
+//! the connection is made, so a plugin built from an older version of the interface can
+//! still be called, or is rejected with a clear error.
+//!
+//! The library implements [AbiProvider] and exports a function returning an [AbiExport],
+//! made by [AbiExport::new]. The application loads the library, calls that function and
+//! wraps the result in an [AbiConnection], through which it calls the methods by name.
+
+use crate::{
+    diff_schema, schema_of, Deserialize, Deserializer, SavefileError, Schema, Serialize, Serializer, WithSchema,
+};
+use std::io::{Read, Write};
+use std::os::raw::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Method index of the call which returns the version and method descriptions of the provider
+pub(crate) const DESCRIBE_METHOD: u32 = u32::MAX;
+
+/// Status of a call which succeeded. The data is the serialized return value.
+pub(crate) const STATUS_OK: u32 = 0;
+/// Status of a call which returned an error. The data is the error message.
+pub(crate) const STATUS_ERROR: u32 = 1;
+/// Status of a call which panicked. The data is the panic message, if it had one.
+pub(crate) const STATUS_PANIC: u32 = 2;
+
+/// A single call across the library boundary.
+#[repr(C)]
+pub struct AbiCall {
+    /// Index of the method in the list returned by [AbiProvider::methods]
+    pub method: u32,
+    /// The version the arguments were serialized with, and the result must be serialized with
+    pub version: u32,
+    /// The serialized arguments
+    pub arguments: *const u8,
+    /// The length of the serialized arguments
+    pub arguments_len: usize,
+    /// Passed to `receive` unchanged
+    pub sink: *mut c_void,
+    /// Called exactly once by the provider, with the status and data of the call.
+    /// The data is only valid during the call, so the receiver must copy it.
+    pub receive: unsafe extern "C" fn(sink: *mut c_void, status: u32, data: *const u8, len: usize),
+}
+
+/// The entry point of a provider, which executes the call on the given instance.
+pub type AbiEntry = unsafe extern "C" fn(instance: *mut c_void, call: *const AbiCall);
+
+/// An instance of an [AbiProvider], with the functions needed to call and drop it.
+/// This is what a library exports.
+#[repr(C)]
+pub struct AbiExport {
+    /// The provider instance
+    pub instance: *mut c_void,
+    /// Executes calls on the instance
+    pub entry: AbiEntry,
+    /// Drops the instance. This is done by the library, since it allocated it.
+    pub drop: unsafe extern "C" fn(instance: *mut c_void),
+}
+
+/// Description of a method which can be called across the library boundary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbiMethod {
+    /// The name the method is called by
+    pub name: String,
+    /// The schema of the arguments of the method
+    pub arguments: Schema,
+    /// The schema of the return value of the method
+    pub result: Schema,
+}
+
+impl AbiMethod {
+    /// Describe a method taking arguments of type `A` (usually a tuple), and returning `R`,
+    /// using the given version of the types.
+    pub fn new<A: WithSchema, R: WithSchema>(name: &str, version: u32) -> AbiMethod {
+        AbiMethod {
+            name: name.to_string(),
+            arguments: (*schema_of::<A>(version)).clone(),
+            result: (*schema_of::<R>(version)).clone(),
+        }
+    }
+}
+
+/// A type whose methods can be called from another library, through an [AbiConnection].
+pub trait AbiProvider {
+    /// The current version of the arguments and return values of the methods. The caller
+    /// and the provider use the oldest of their versions for the calls.
+    fn version() -> u32;
+
+    /// The methods of the provider, using the given version of the argument and return types.
+    /// The index of a method in this list is the number passed to [AbiProvider::call].
+    fn methods(version: u32) -> Vec<AbiMethod>;
+
+    /// Deserialize the arguments of the method with the given index, execute it, and
+    /// serialize its return value.
+    fn call(
+        &mut self,
+        method: u32,
+        arguments: &mut Deserializer<impl Read>,
+        result: &mut Serializer<impl Write>,
+    ) -> Result<(), SavefileError>;
+}
+
+impl AbiExport {
+    /// Export the provider, so that it can be called through an [AbiConnection].
+    pub fn new<P: AbiProvider>(provider: P) -> AbiExport {
+        AbiExport {
+            instance: Box::into_raw(Box::new(provider)) as *mut c_void,
+            entry: abi_entry::<P>,
+            drop: abi_drop::<P>,
+        }
+    }
+}
+
+unsafe extern "C" fn abi_drop<P: AbiProvider>(instance: *mut c_void) {
+    drop(Box::from_raw(instance as *mut P));
+}
+
+fn execute<P: AbiProvider>(
+    provider: &mut P,
+    method: u32,
+    version: u32,
+    mut arguments: &[u8],
+) -> Result<Vec<u8>, SavefileError> {
+    let version = version.min(P::version());
+    let mut result = Vec::new();
+    let mut serializer = Serializer::new_raw(&mut result);
+    serializer.version = version;
+    if method == DESCRIBE_METHOD {
+        let methods: Vec<(String, Schema, Schema)> = P::methods(version)
+            .into_iter()
+            .map(|method| (method.name, method.arguments, method.result))
+            .collect();
+        P::version().serialize(&mut serializer)?;
+        methods.serialize(&mut serializer)?;
+    } else {
+        let mut deserializer = Deserializer::new_raw(&mut arguments);
+        deserializer.file_version = version;
+        deserializer.memory_version = P::version();
+        provider.call(method, &mut deserializer, &mut serializer)?;
+    }
+    Ok(result)
+}
+
+/// Execute a call of the method, and return the status and data of the result.
+/// Errors and panics are reported as a status.
+pub(crate) fn dispatch<P: AbiProvider>(
+    provider: &mut P,
+    method: u32,
+    version: u32,
+    arguments: &[u8],
+) -> (u32, Vec<u8>) {
+    match catch_unwind(AssertUnwindSafe(|| execute(provider, method, version, arguments))) {
+        Ok(Ok(result)) => (STATUS_OK, result),
+        Ok(Err(err)) => (STATUS_ERROR, err.to_string().into_bytes()),
+        Err(panic) => {
+            let message = if let Some(message) = panic.downcast_ref::<&str>() {
+                message.to_string()
+            } else if let Some(message) = panic.downcast_ref::<String>() {
+                message.clone()
+            } else {
+                "The method panicked".to_string()
+            };
+            (STATUS_PANIC, message.into_bytes())
+        }
+    }
+}
+
+/// The [AbiEntry] of provider P. Panics never unwind across the library boundary.
+unsafe extern "C" fn abi_entry<P: AbiProvider>(instance: *mut c_void, call: *const AbiCall) {
+    let call = &*call;
+    let provider = &mut *(instance as *mut P);
+    let arguments = if call.arguments_len == 0 {
+        &[][..]
+    } else {
+        std::slice::from_raw_parts(call.arguments, call.arguments_len)
+    };
+    let (status, data) = dispatch(provider, call.method, call.version, arguments);
+    (call.receive)(call.sink, status, data.as_ptr(), data.len());
+}
+
+struct Received {
+    status: u32,
+    data: Vec<u8>,
+}
+
+unsafe extern "C" fn receive(sink: *mut c_void, status: u32, data: *const u8, len: usize) {
+    let received = &mut *(sink as *mut Received);
+    received.status = status;
+    if len != 0 {
+        received.data.extend_from_slice(std::slice::from_raw_parts(data, len));
+    }
+}
+
+/// The methods of a provider, as seen by a caller
+pub(crate) struct MethodTable {
+    /// The version of the types of the caller
+    caller_version: u32,
+    /// The version used for calls
+    version: u32,
+    /// The methods of the provider, in index order
+    methods: Vec<AbiMethod>,
+}
+
+impl MethodTable {
+    /// Read the result of a [DESCRIBE_METHOD] call, and check that the methods of the provider
+    /// are compatible with those `expected` by the caller.
+    pub(crate) fn from_description(
+        description: &[u8],
+        caller_version: u32,
+        expected: impl FnOnce(u32) -> Vec<AbiMethod>,
+    ) -> Result<MethodTable, SavefileError> {
+        let mut reader = description;
+        let mut deserializer = Deserializer::new_raw(&mut reader);
+        let provider_version = u32::deserialize(&mut deserializer)?;
+        let version = caller_version.min(provider_version);
+        deserializer.file_version = version;
+        deserializer.memory_version = version;
+        let methods: Vec<AbiMethod> = <Vec<(String, Schema, Schema)>>::deserialize(&mut deserializer)?
+            .into_iter()
+            .map(|(name, arguments, result)| AbiMethod {
+                name,
+                arguments,
+                result,
+            })
+            .collect();
+        for expected in expected(version) {
+            if let Some(method) = methods.iter().find(|method| method.name == expected.name) {
+                let difference = diff_schema(&method.arguments, &expected.arguments, "arguments".to_string())
+                    .or_else(|| diff_schema(&method.result, &expected.result, "result".to_string()));
+                if let Some(difference) = difference {
+                    return Err(SavefileError::IncompatibleSchema {
+                        message: format!(
+                            "Method '{}' of the provider is incompatible with the caller, at version {}: {}",
+                            expected.name, version, difference
+                        ),
+                    });
+                }
+            }
+        }
+        Ok(MethodTable {
+            caller_version,
+            version,
+            methods,
+        })
+    }
+
+    pub(crate) fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub(crate) fn has_method(&self, name: &str) -> bool {
+        self.methods.iter().any(|method| method.name == name)
+    }
+
+    pub(crate) fn index(&self, name: &str) -> Result<u32, SavefileError> {
+        self.methods
+            .iter()
+            .position(|method| method.name == name)
+            .map(|index| index as u32)
+            .ok_or_else(|| SavefileError::GeneralError {
+                msg: format!("The provider has no method named '{}'", name),
+            })
+    }
+
+    pub(crate) fn serialize_arguments<A: Serialize>(&self, arguments: &A) -> Result<Vec<u8>, SavefileError> {
+        let mut data = Vec::new();
+        let mut serializer = Serializer::new_raw(&mut data);
+        serializer.version = self.version;
+        arguments.serialize(&mut serializer)?;
+        Ok(data)
+    }
+
+    pub(crate) fn deserialize_result<R: Deserialize>(&self, result: &[u8]) -> Result<R, SavefileError> {
+        let mut reader = result;
+        let mut deserializer = Deserializer::new_raw(&mut reader);
+        deserializer.file_version = self.version;
+        deserializer.memory_version = self.caller_version;
+        R::deserialize(&mut deserializer)
+    }
+}
+
+/// Turn the status and data of a call into its result
+pub(crate) fn call_result(status: u32, data: Vec<u8>) -> Result<Vec<u8>, SavefileError> {
+    match status {
+        STATUS_OK => Ok(data),
+        STATUS_ERROR => Err(SavefileError::GeneralError {
+            msg: String::from_utf8_lossy(&data).into_owned(),
+        }),
+        _ => Err(SavefileError::GeneralError {
+            msg: format!("The provider panicked: {}", String::from_utf8_lossy(&data)),
+        }),
+    }
+}
+
+/// A connection to an [AbiProvider] in another library.
+pub struct AbiConnection {
+    export: AbiExport,
+    methods: MethodTable,
+}
+
+impl AbiConnection {
+    /// Connect to an exported provider. `version` is the current version of the argument and
+    /// return types of the caller, and `expected` returns the methods the caller wants to call,
+    /// using the given version of their types, like [AbiProvider::methods].
+    ///
+    /// Returns an error if the provider has a method with the same name as an expected method,
+    /// but different argument or return types. Expected methods which the provider doesn't have
+    /// return an error when called.
+    ///
+    /// # Safety
+    /// `export` must have been made by [AbiExport::new], and the library it was made by must
+    /// stay loaded as long as the connection exists. The connection takes ownership of the
+    /// instance, and drops it when dropped.
+    pub unsafe fn new(
+        export: AbiExport,
+        version: u32,
+        expected: impl FnOnce(u32) -> Vec<AbiMethod>,
+    ) -> Result<AbiConnection, SavefileError> {
+        let description = raw_call(&export, version, DESCRIBE_METHOD, &[]);
+        let methods = match description {
+            Ok(description) => MethodTable::from_description(&description, version, expected),
+            Err(err) => Err(err),
+        };
+        match methods {
+            Ok(methods) => Ok(AbiConnection { export, methods }),
+            Err(err) => {
+                (export.drop)(export.instance);
+                Err(err)
+            }
+        }
+    }
+
+    /// The version used for the arguments and return values of calls,
+    /// the oldest of the versions of the caller and the provider.
+    pub fn version(&self) -> u32 {
+        self.methods.version()
+    }
+
+    /// Returns true if the provider has a method with the given name.
+    pub fn has_method(&self, name: &str) -> bool {
+        self.methods.has_method(name)
+    }
+
+    /// Call the method with the given name. `A` are the arguments, usually a tuple,
+    /// and `R` the return value, as for [AbiMethod::new]. The caller's version of
+    /// the types must be the `version` given to [AbiConnection::new].
+    pub fn call<A: Serialize, R: Deserialize>(&self, name: &str, arguments: &A) -> Result<R, SavefileError> {
+        let method = self.methods.index(name)?;
+        let arguments = self.methods.serialize_arguments(arguments)?;
+        let result = raw_call(&self.export, self.methods.version(), method, &arguments)?;
+        self.methods.deserialize_result(&result)
+    }
+}
+
+fn raw_call(export: &AbiExport, version: u32, method: u32, arguments: &[u8]) -> Result<Vec<u8>, SavefileError> {
+    let mut received = Received {
+        status: STATUS_PANIC,
+        data: Vec::new(),
+    };
+    let call = AbiCall {
+        method,
+        version,
+        arguments: arguments.as_ptr(),
+        arguments_len: arguments.len(),
+        sink: &mut received as *mut Received as *mut c_void,
+        receive,
+    };
+    unsafe { (export.entry)(export.instance, &call) };
+    call_result(received.status, received.data)
+}
+
+impl Drop for AbiConnection {
+    fn drop(&mut self) {
+        unsafe { (self.export.drop)(self.export.instance) };
+    }
+}
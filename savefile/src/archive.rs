@@ -0,0 +1,292 @@
+//! Files containing several independently typed and versioned values, stored under names.
+
+#[cfg(feature = "fs")]
+use crate::write_file_atomic;
+use crate::{
+    load_from_mem, save_to_mem, Deserialize, Deserializer, SaveOptions, SavefileError, Serialize, Serializer,
+    WithSchema, HEADER_LENGTH,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use indexmap::IndexMap;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::{BufReader, BufWriter};
+use std::io::{Read, Seek, SeekFrom, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const ARCHIVE_MAGIC: &[u8; 9] = b"savearch\0";
+
+/// The archive format version written by this version of the crate.
+const ARCHIVE_FORMAT_VERSION: u16 = 0;
+
+/// Length of the magic bytes and the format version
+const ARCHIVE_HEADER_LENGTH: u64 = 11;
+
+/// Length of the offset of the table of contents, at the end of the archive
+const ARCHIVE_FOOTER_LENGTH: u64 = 8;
+
+/// Several values of different types, each saved under a name, which are written to a single file.
+///
+/// Every entry is saved as a complete savefile file, with its own version and schema.
+/// The archive ends with a table of contents, holding the offset of each entry, so that
+/// [ArchiveReader] can load individual entries without reading the rest of the archive.
+///
+/// ```
+/// use savefile::prelude::*;
+///
+/// let mut archive = SaveArchive::new();
+/// archive.insert("player", 0, &"Bob".to_string()).unwrap();
+/// archive.insert("settings", 3, &vec![1u8, 2, 3]).unwrap();
+/// let mut data = Vec::new();
+/// archive.save(&mut data).unwrap();
+///
+/// let mut reader = ArchiveReader::new(std::io::Cursor::new(data)).unwrap();
+/// let settings: Vec<u8> = reader.load("settings", 3).unwrap();
+/// assert_eq!(settings, vec![1, 2, 3]);
+/// ```
+#[derive(Default)]
+pub struct SaveArchive {
+    entries: IndexMap<String, Vec<u8>>,
+}
+
+impl SaveArchive {
+    /// Create an empty archive
+    pub fn new() -> SaveArchive {
+        SaveArchive::default()
+    }
+
+    /// Save `data` under the given name, replacing any entry previously saved under it.
+    /// The current version of T in memory must be `version`.
+    pub fn insert<T: WithSchema + Serialize>(
+        &mut self,
+        name: impl Into<String>,
+        version: u32,
+        data: &T,
+    ) -> Result<(), SavefileError> {
+        self.entries.insert(name.into(), save_to_mem(version, data)?);
+        Ok(())
+    }
+
+    /// Remove the entry with the given name. Returns true if there was such an entry.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.shift_remove(name).is_some()
+    }
+
+    /// The names of the entries, in the order they were first inserted
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|name| name.as_str())
+    }
+
+    /// Write the archive
+    pub fn save(&self, writer: &mut impl Write) -> Result<(), SavefileError> {
+        let mut archive_writer = ArchiveWriter::new(writer)?;
+        for (name, bytes) in &self.entries {
+            archive_writer.writer.write_all(bytes)?;
+            archive_writer.add_entry(name, bytes.len() as u64);
+        }
+        archive_writer.finish()?;
+        Ok(())
+    }
+
+    /// Write the archive to the given file. The file is replaced atomically, like
+    /// by [crate::save_file_atomic].
+    #[cfg(feature = "fs")]
+    pub fn save_file(&self, filepath: impl AsRef<Path>) -> Result<(), SavefileError> {
+        write_file_atomic(filepath.as_ref(), 0, |f| self.save(&mut BufWriter::new(f)))
+    }
+}
+
+/// Writes an archive entry by entry, for archives too large to be kept in memory.
+/// The archive can be read by [ArchiveReader], just like archives written by [SaveArchive].
+///
+/// Names of entries can be paths like "chunks/12_7", which makes it possible to find
+/// related entries using [ArchiveReader::names_under].
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    offset: u64,
+    entries: IndexMap<String, (u64, u64)>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Start writing an archive to the writer
+    pub fn new(mut writer: W) -> Result<ArchiveWriter<W>, SavefileError> {
+        writer.write_all(ARCHIVE_MAGIC)?;
+        writer.write_u16::<LittleEndian>(ARCHIVE_FORMAT_VERSION)?;
+        Ok(ArchiveWriter {
+            writer,
+            offset: ARCHIVE_HEADER_LENGTH,
+            entries: IndexMap::new(),
+        })
+    }
+
+    /// Record an entry of `length` bytes, which has just been written
+    fn add_entry(&mut self, name: &str, length: u64) {
+        self.entries.insert(name.to_string(), (self.offset, length));
+        self.offset += length;
+    }
+
+    /// Write `data` as an entry with the given name. Each name can only be used once.
+    /// The current version of T in memory must be `version`.
+    pub fn add<T: WithSchema + Serialize>(&mut self, name: &str, version: u32, data: &T) -> Result<(), SavefileError> {
+        if self.entries.contains_key(name) {
+            return Err(SavefileError::GeneralError {
+                msg: format!("The archive already has an entry named '{}'", name),
+            });
+        }
+        let payload_length = Serializer::save_impl(&mut self.writer, version, data, SaveOptions::new(true))?;
+        self.add_entry(name, HEADER_LENGTH + payload_length);
+        Ok(())
+    }
+
+    /// Write the table of contents, which completes the archive, and return the writer.
+    pub fn finish(mut self) -> Result<W, SavefileError> {
+        let table: Vec<(String, u64, u64)> = self
+            .entries
+            .into_iter()
+            .map(|(name, (offset, length))| (name, offset, length))
+            .collect();
+        table.serialize(&mut Serializer::new_raw(&mut self.writer))?;
+        self.writer.write_u64::<LittleEndian>(self.offset)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Loads individual entries of an archive written by [SaveArchive].
+/// Only the table of contents is read when the archive is opened.
+pub struct ArchiveReader<R> {
+    reader: R,
+    entries: IndexMap<String, (u64, u64)>,
+}
+
+#[cfg(feature = "fs")]
+impl ArchiveReader<BufReader<File>> {
+    /// Open the archive in the given file
+    pub fn open_file(filepath: impl AsRef<Path>) -> Result<ArchiveReader<BufReader<File>>, SavefileError> {
+        ArchiveReader::new(BufReader::new(File::open(filepath)?))
+    }
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    /// Read the table of contents of the archive
+    pub fn new(mut reader: R) -> Result<ArchiveReader<R>, SavefileError> {
+        let not_an_archive = || SavefileError::GeneralError {
+            msg: "File is not a savefile archive".into(),
+        };
+        let mut magic = [0u8; 9];
+        reader.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(not_an_archive());
+        }
+        let format_version = reader.read_u16::<LittleEndian>()?;
+        if format_version > ARCHIVE_FORMAT_VERSION {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "This archive has been created by a later version of the savefile crate (archive format version {}).",
+                    format_version
+                ),
+            });
+        }
+        let length = reader.seek(SeekFrom::End(0))?;
+        if length < ARCHIVE_HEADER_LENGTH + ARCHIVE_FOOTER_LENGTH {
+            return Err(not_an_archive());
+        }
+        reader.seek(SeekFrom::Start(length - ARCHIVE_FOOTER_LENGTH))?;
+        let table_offset = reader.read_u64::<LittleEndian>()?;
+        if table_offset < ARCHIVE_HEADER_LENGTH || table_offset > length - ARCHIVE_FOOTER_LENGTH {
+            return Err(not_an_archive());
+        }
+        reader.seek(SeekFrom::Start(table_offset))?;
+        let table: Vec<(String, u64, u64)> = Deserialize::deserialize(&mut Deserializer::new_raw(
+            &mut (&mut reader).take(length - table_offset),
+        ))?;
+        let mut entries = IndexMap::with_capacity(table.len());
+        for (name, offset, entry_length) in table {
+            if offset < ARCHIVE_HEADER_LENGTH || offset.saturating_add(entry_length) > table_offset {
+                return Err(not_an_archive());
+            }
+            entries.insert(name, (offset, entry_length));
+        }
+        Ok(ArchiveReader { reader, entries })
+    }
+
+    /// The names of the entries, in the order they were first inserted into the archive
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|name| name.as_str())
+    }
+
+    /// The names of the entries starting with `prefix`, such as all entries under "chunks/"
+    pub fn names_under<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.names().filter(move |name| name.starts_with(prefix))
+    }
+
+    /// True if the archive has an entry with the given name
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Load the entry with the given name, by seeking directly to it.
+    /// The current version of T in memory must be `version`.
+    pub fn load<T: WithSchema + Deserialize>(&mut self, name: &str, version: u32) -> Result<T, SavefileError> {
+        let (offset, length) = *self.entries.get(name).ok_or_else(|| SavefileError::GeneralError {
+            msg: format!("The archive has no entry named '{}'", name),
+        })?;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        Deserializer::load::<T>(&mut (&mut self.reader).take(length), version)
+    }
+
+    /// Load the entries with the given names, which must all have type T, using a thread per
+    /// available cpu. The bytes of the entries are read one after the other, and then decoded
+    /// in parallel. The values are returned in the order of the names. If any entry fails to
+    /// load, the error of the first such entry is returned.
+    /// The current version of T in memory must be `version`.
+    pub fn load_parallel<T: WithSchema + Deserialize + Send>(
+        &mut self,
+        names: &[&str],
+        version: u32,
+    ) -> Result<Vec<T>, SavefileError> {
+        let mut entries = Vec::with_capacity(names.len());
+        for name in names {
+            let (offset, length) = *self.entries.get(*name).ok_or_else(|| SavefileError::GeneralError {
+                msg: format!("The archive has no entry named '{}'", name),
+            })?;
+            self.reader.seek(SeekFrom::Start(offset))?;
+            let mut bytes = Vec::with_capacity(length as usize);
+            (&mut self.reader).take(length).read_to_end(&mut bytes)?;
+            entries.push(bytes);
+        }
+        let threads = std::thread::available_parallelism()
+            .map(|threads| threads.get())
+            .unwrap_or(1)
+            .min(entries.len())
+            .max(1);
+        let next = AtomicUsize::new(0);
+        let work = || {
+            let mut loaded = Vec::new();
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                match entries.get(index) {
+                    Some(bytes) => loaded.push((index, load_from_mem::<T>(bytes, version))),
+                    None => return loaded,
+                }
+            }
+        };
+        // With a single worker, the entries are decoded on this thread. Threads can't
+        // be spawned on some targets, such as wasm32-unknown-unknown.
+        if threads == 1 {
+            return work().into_iter().map(|(_, value)| value).collect();
+        }
+        let mut results: Vec<(usize, Result<T, SavefileError>)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|_| scope.spawn(work)).collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                .collect()
+        });
+        results.sort_by_key(|&(index, _)| index);
+        results.into_iter().map(|(_, value)| value).collect()
+    }
+}
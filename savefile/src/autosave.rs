@@ -0,0 +1,140 @@
+//! Periodic saving of state to a file, with the file I/O done on a background thread.
+
+use crate::{save, write_file_atomic, SavefileError, Serialize, WithSchema};
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// A snapshot written by the background thread, and the result of writing the previous one.
+/// The buffer is sent back when it has been written, so it can be reused.
+type Written = (Vec<u8>, Result<(), SavefileError>);
+
+/// Saves state of type T to a file at a fixed interval, without blocking the caller on file I/O.
+///
+/// The state is serialized into a buffer in memory on the caller's thread, which is usually much
+/// faster than writing it to disk. A background thread then writes the buffer to the file, which
+/// is replaced atomically, as by [crate::save_file_atomic]. There are two buffers, so the next
+/// snapshot can be taken while the previous one is being written. The buffers are reused, so
+/// their memory is only allocated once.
+///
+/// Call [Autosaver::tick] regularly, such as once per frame. Errors from writing the file are
+/// returned by the next call to [Autosaver::tick], [Autosaver::save_now] or [Autosaver::wait].
+/// When dropped, the autosaver waits for the snapshot being written, if any.
+pub struct Autosaver<T> {
+    version: u32,
+    interval: Duration,
+    last_snapshot: Instant,
+    snapshots: Option<Sender<Vec<u8>>>,
+    written: Receiver<Written>,
+    /// The buffer the next snapshot is serialized into
+    spare: Vec<u8>,
+    /// Number of snapshots sent to the background thread, but not yet written
+    pending: usize,
+    thread: Option<JoinHandle<()>>,
+    phantom: PhantomData<fn(&T)>,
+}
+
+impl<T: WithSchema + Serialize> Autosaver<T> {
+    /// An autosaver saving to the given path with the given version, at most once per `interval`.
+    /// The first snapshot is taken by the first call to [Autosaver::tick] after the interval
+    /// has passed.
+    pub fn new(path: impl AsRef<Path>, version: u32, interval: Duration) -> Autosaver<T> {
+        let path: PathBuf = path.as_ref().to_path_buf();
+        let (snapshots, snapshot_receiver) = channel::<Vec<u8>>();
+        let (written_sender, written) = channel();
+        let thread = std::thread::spawn(move || {
+            for snapshot in snapshot_receiver {
+                let result = write_file_atomic(&path, 0, |file| Ok(file.write_all(&snapshot)?));
+                if written_sender.send((snapshot, result)).is_err() {
+                    break;
+                }
+            }
+        });
+        Autosaver {
+            version,
+            interval,
+            last_snapshot: Instant::now(),
+            snapshots: Some(snapshots),
+            written,
+            spare: Vec::new(),
+            pending: 0,
+            thread: Some(thread),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Take a snapshot of the state if the interval has passed since the last one, and the
+    /// previous snapshot has been written. Returns true if a snapshot was taken.
+    pub fn tick(&mut self, state: &T) -> Result<bool, SavefileError> {
+        self.collect_written(false)?;
+        if self.pending > 0 || self.last_snapshot.elapsed() < self.interval {
+            return Ok(false);
+        }
+        self.snapshot(state)?;
+        Ok(true)
+    }
+
+    /// Take a snapshot of the state right away. If the previous snapshot is still being written,
+    /// this waits for it, after the state has been serialized. The new snapshot is written in
+    /// the background.
+    pub fn save_now(&mut self, state: &T) -> Result<(), SavefileError> {
+        self.snapshot(state)
+    }
+
+    /// Wait until all snapshots taken so far have been written to the file.
+    pub fn wait(&mut self) -> Result<(), SavefileError> {
+        self.collect_written(true)
+    }
+
+    fn snapshot(&mut self, state: &T) -> Result<(), SavefileError> {
+        let mut buffer = std::mem::take(&mut self.spare);
+        buffer.clear();
+        save(&mut buffer, self.version, state)?;
+        self.last_snapshot = Instant::now();
+        // Only two buffers are used, so the previous one must have been written
+        self.collect_written(true)?;
+        match self.snapshots.as_ref().map(|snapshots| snapshots.send(buffer)) {
+            Some(Ok(())) => {}
+            _ => return Err(background_thread_stopped()),
+        }
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Receive the results of written snapshots, waiting for all of them if `wait` is true.
+    fn collect_written(&mut self, wait: bool) -> Result<(), SavefileError> {
+        while self.pending > 0 {
+            let (buffer, result) = if wait {
+                self.written.recv().map_err(|_| background_thread_stopped())?
+            } else {
+                match self.written.try_recv() {
+                    Ok(written) => written,
+                    Err(_) => return Ok(()),
+                }
+            };
+            self.pending -= 1;
+            self.spare = buffer;
+            result?;
+        }
+        Ok(())
+    }
+}
+
+fn background_thread_stopped() -> SavefileError {
+    SavefileError::GeneralError {
+        msg: "The background thread of the autosaver has stopped".into(),
+    }
+}
+
+impl<T> Drop for Autosaver<T> {
+    fn drop(&mut self) {
+        // Closing the channel makes the thread exit, once it has written what it was sent
+        self.snapshots = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Deserialization of values which borrow strings and slices from the buffer they are
+//! loaded from, instead of allocating copies of them.
+
+use crate::{
+    check_file_schema, introspect_item, schema_of, verify_checksums, ChecksumReader, CompressionMethod, Deserialize,
+    Deserializer, Encoding, Introspect, IntrospectItem, PackedBits, SavefileError, SavefileHeader, Schema,
+    SchemaPrimitive, Serialize, Serializer, WithSchema,
+};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Write;
+
+/// Deserializes values borrowing from an in-memory buffer. See [DeserializeBorrowed].
+pub struct BorrowedDeserializer<'a> {
+    data: &'a [u8],
+    /// The version of the input file
+    pub file_version: u32,
+    /// The version of the data structures in memory
+    pub memory_version: u32,
+    /// The encoding of the input file
+    pub encoding: Encoding,
+    /// The scratch buffer of the ordinary deserializers used for owned values
+    scratch: Vec<u8>,
+}
+
+impl<'a> BorrowedDeserializer<'a> {
+    /// Create a deserializer reading from the start of `data`, which must not contain a header.
+    /// Don't use this method directly, use the [crate::load_borrowed] function instead.
+    pub fn new_raw(data: &'a [u8], file_version: u32, memory_version: u32) -> BorrowedDeserializer<'a> {
+        BorrowedDeserializer {
+            data,
+            file_version,
+            memory_version,
+            encoding: Encoding::default(),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// The part of the buffer which hasn't been read yet
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Read `len` bytes, borrowing them from the buffer
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], SavefileError> {
+        if len > self.data.len() {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Reads a length into an usize, see [Deserializer::read_length]. For 32 bit architectures,
+    /// the function fails on overflow.
+    pub fn read_length_usize(&mut self) -> Result<usize, SavefileError> {
+        let value = self.deserializer(|deserializer| deserializer.read_length())?;
+        usize::try_from(value).map_err(|_| SavefileError::SizeOverflow)
+    }
+
+    /// Reads a group of bit-packed values, see [Deserializer::read_packed]
+    pub fn read_packed(&mut self, width: u32) -> Result<PackedBits, SavefileError> {
+        self.deserializer(|deserializer| deserializer.read_packed(width))
+    }
+
+    /// Run `f` with an ordinary deserializer reading from the remaining buffer
+    fn deserializer<T>(
+        &mut self,
+        f: impl FnOnce(&mut Deserializer<&'a [u8]>) -> Result<T, SavefileError>,
+    ) -> Result<T, SavefileError> {
+        let mut reader = self.data;
+        let mut deserializer = Deserializer {
+            reader: &mut reader,
+            file_version: self.file_version,
+            memory_version: self.memory_version,
+            encoding: self.encoding,
+            ephemeral_state: HashMap::new(),
+            interned_strings: Vec::new(),
+            scratch: std::mem::take(&mut self.scratch),
+            unchecked_utf8: false,
+            context: None,
+            progress: None,
+            memory_budget: None,
+        };
+        let value = f(&mut deserializer);
+        self.scratch = deserializer.scratch;
+        let value = value?;
+        self.data = reader;
+        Ok(value)
+    }
+}
+
+/// Types which can be deserialized from an in-memory buffer with lifetime `'a`,
+/// possibly borrowing parts of the buffer. This is implemented for `&'a str`,
+/// for `&'a [T]` where T has a raw-copy ([crate::ReprC]) layout, and for all types
+/// which implement [Deserialize]. It can be derived for structs using
+/// `#[derive(SavefileBorrowed)]`. Containers of borrowed values, like `Vec<&'a str>`,
+/// are not supported.
+pub trait DeserializeBorrowed<'a>: Sized {
+    /// Deserialize the value, borrowing from the buffer of the deserializer
+    fn deserialize_borrowed(deserializer: &mut BorrowedDeserializer<'a>) -> Result<Self, SavefileError>;
+}
+
+impl<'a, T: Deserialize> DeserializeBorrowed<'a> for T {
+    fn deserialize_borrowed(deserializer: &mut BorrowedDeserializer<'a>) -> Result<Self, SavefileError> {
+        deserializer.deserializer(T::deserialize)
+    }
+}
+
+impl<'a> DeserializeBorrowed<'a> for &'a str {
+    fn deserialize_borrowed(deserializer: &mut BorrowedDeserializer<'a>) -> Result<Self, SavefileError> {
+        if deserializer.encoding.intern_strings {
+            return Err(SavefileError::GeneralError {
+                msg: "Strings can't be borrowed from files with interned strings".into(),
+            });
+        }
+        let len = deserializer.read_length_usize()?;
+        Ok(std::str::from_utf8(deserializer.read_bytes(len)?)?)
+    }
+}
+
+impl<'a, T: WithSchema> DeserializeBorrowed<'a> for &'a [T] {
+    fn deserialize_borrowed(deserializer: &mut BorrowedDeserializer<'a>) -> Result<Self, SavefileError> {
+        if !T::repr_c_layout(deserializer.file_version).is_yes() {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "Slices of {} can't be borrowed, since it doesn't have a raw-copy layout",
+                    std::any::type_name::<T>()
+                ),
+            });
+        }
+        if !deserializer.encoding.allows_raw_copy() {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "Slices of {} can't be borrowed, since the byte order of the file differs from this machine",
+                    std::any::type_name::<T>()
+                ),
+            });
+        }
+        let len = deserializer.read_length_usize()?;
+        let num_bytes = len
+            .checked_mul(std::mem::size_of::<T>())
+            .ok_or(SavefileError::MemoryAllocationLayoutError)?;
+        let bytes = deserializer.read_bytes(num_bytes)?;
+        if bytes.as_ptr().align_offset(std::mem::align_of::<T>()) != 0 {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "Slice of {} in the buffer isn't suitably aligned to be borrowed",
+                    std::any::type_name::<T>()
+                ),
+            });
+        }
+        T::repr_c_layout(deserializer.file_version).validate(bytes)?;
+        // Safety: T is ReprC, the bytes have been validated as values of it, and they are aligned
+        Ok(unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const T, len) })
+    }
+}
+
+impl WithSchema for &str {
+    fn schema(_version: u32) -> Schema {
+        Schema::Primitive(SchemaPrimitive::schema_string)
+    }
+}
+
+impl Serialize for &str {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_string(self)
+    }
+}
+
+impl Introspect for &str {
+    fn introspect_value(&self) -> String {
+        self.to_string()
+    }
+
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        None
+    }
+}
+
+impl<T: WithSchema> WithSchema for &[T] {
+    fn schema(version: u32) -> Schema {
+        Schema::Vector(schema_of::<T>(version))
+    }
+}
+
+impl<T: Serialize> Serialize for &[T] {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        crate::serialize_slice(self, serializer)
+    }
+}
+
+impl<T: Introspect> Introspect for &[T] {
+    fn introspect_value(&self) -> String {
+        "slice[]".to_string()
+    }
+
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        self.get(index).map(|item| introspect_item(index.to_string(), item))
+    }
+
+    fn introspect_len(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Load a value from an in-memory buffer containing a savefile file, like [crate::load_from_mem].
+/// Strings and slices in the value can borrow from the buffer, see [DeserializeBorrowed].
+/// The file must not be compressed or encrypted. The current version of T in memory must be `version`.
+pub fn load_borrowed<'a, T: WithSchema + DeserializeBorrowed<'a>>(
+    data: &'a [u8],
+    version: u32,
+) -> Result<T, SavefileError> {
+    let mut reader = data;
+    let header = SavefileHeader::read(&mut reader)?;
+    if header.version > version {
+        return Err(SavefileError::WrongVersion {
+            msg: format!(
+                "File has later version ({}) than structs in memory ({}).",
+                header.version, version
+            ),
+        });
+    }
+    if header.compression != CompressionMethod::None || header.encrypted || header.encoding.intern_strings {
+        return Err(SavefileError::GeneralError {
+            msg: "Compressed or encrypted files, or files with interned strings, can't be loaded without copying"
+                .into(),
+        });
+    }
+    if let (true, Some(payload_length)) = (header.has_checksum, header.payload_length) {
+        let payload = usize::try_from(payload_length)
+            .ok()
+            .and_then(|payload_length| reader.get(..payload_length))
+            .ok_or(SavefileError::ShortRead)?;
+        verify_checksums(payload, &header)?;
+    }
+    let namespaces = check_file_schema::<T, _>(
+        &mut ChecksumReader::new(&mut reader, &header)?,
+        &header,
+        header.has_schema.unwrap_or(true),
+        true,
+        true,
+        None,
+    )?;
+    let mut deserializer = BorrowedDeserializer::new_raw(reader, header.version, version);
+    deserializer.encoding = header.encoding;
+    let value = crate::namespace::with_file_versions(Some(namespaces), || T::deserialize_borrowed(&mut deserializer))?;
+    if header.has_checksum {
+        let consumed = reader.len() - deserializer.data.len();
+        let actual = crc32fast::hash(&reader[..consumed]);
+        let expected = deserializer.data.read_u32::<LittleEndian>()?;
+        if expected != actual {
+            return Err(SavefileError::ChecksumMismatch { expected, actual });
+        }
+    }
+    Ok(value)
+}
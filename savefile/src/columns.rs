@@ -0,0 +1,114 @@
+//! Tables stored as columns, one vector per field, rather than as a vector of rows.
+
+use crate::{
+    introspect_item, max_version, schema_of, serialize_slice, CurrentVersion, Deserialize, Deserializer, Field, Introspect, IntrospectItem,
+    SavefileError, Schema, SchemaStruct, Serialize, Serializer, WithSchema,
+};
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// A table whose columns are the vectors of the tuple C, such as `(Vec<u32>, Vec<f32>)`, which
+/// must all have the same length. Each column is saved like a vector, so columns of [crate::ReprC]
+/// types are written in one go, without looking at each row. This suits data which is stored in
+/// columns in memory, such as the components of an entity component system, since it doesn't
+/// have to be copied into rows to be saved.
+///
+/// Tables of borrowed columns, such as `Columns<(&[u32], &[f32])>`, can be saved too, and loaded
+/// as tables of vectors. Saving fails if the columns don't all have the same length, and so does
+/// loading a table whose columns have different lengths.
+///
+/// In the schema, a table is a struct with a vector for each column, named by its index.
+/// Tables with 1 to 4 columns are supported.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Columns<C>(pub C);
+
+/// The schema of a table with the given columns
+fn columns_schema(columns: Vec<Arc<Schema>>) -> Schema {
+    Schema::Struct(SchemaStruct {
+        dbg_name: format!("{}-Columns", columns.len()),
+        fields: columns
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| Field {
+                name: index.to_string(),
+                value,
+            })
+            .collect(),
+    })
+}
+
+fn check_column_lengths(lengths: &[usize]) -> Result<(), SavefileError> {
+    if lengths.iter().any(|length| *length != lengths[0]) {
+        return Err(SavefileError::GeneralError {
+            msg: format!("The columns of the table have different lengths: {:?}", lengths),
+        });
+    }
+    Ok(())
+}
+
+macro_rules! impl_columns {
+    ($($t:ident $index:tt),*) => {
+        impl<$($t: CurrentVersion),*> CurrentVersion for Columns<($(Vec<$t>,)*)> {
+            const SAVEFILE_MAX_VERSION: u32 = {
+                let version = 0;
+                $(let version = max_version(version, $t::SAVEFILE_MAX_VERSION);)*
+                version
+            };
+        }
+        impl<'a, $($t: CurrentVersion),*> CurrentVersion for Columns<($(&'a [$t],)*)> {
+            const SAVEFILE_MAX_VERSION: u32 = <Columns<($(Vec<$t>,)*)> as CurrentVersion>::SAVEFILE_MAX_VERSION;
+        }
+        impl<$($t: WithSchema),*> WithSchema for Columns<($(Vec<$t>,)*)> {
+            fn schema(version: u32) -> Schema {
+                columns_schema(vec![$(schema_of::<Vec<$t>>(version)),*])
+            }
+        }
+        impl<'a, $($t: WithSchema),*> WithSchema for Columns<($(&'a [$t],)*)> {
+            fn schema(version: u32) -> Schema {
+                columns_schema(vec![$(schema_of::<Vec<$t>>(version)),*])
+            }
+        }
+        impl<$($t: Serialize),*> Serialize for Columns<($(Vec<$t>,)*)> {
+            fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+                check_column_lengths(&[$(self.0.$index.len()),*])?;
+                $(serialize_slice(&self.0.$index, serializer)?;)*
+                Ok(())
+            }
+        }
+        impl<'a, $($t: Serialize),*> Serialize for Columns<($(&'a [$t],)*)> {
+            fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+                check_column_lengths(&[$(self.0.$index.len()),*])?;
+                $(serialize_slice(self.0.$index, serializer)?;)*
+                Ok(())
+            }
+        }
+        impl<$($t: Deserialize),*> Deserialize for Columns<($(Vec<$t>,)*)> {
+            fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+                let columns = ($(
+                    Vec::<$t>::deserialize(deserializer).map_err(|err| err.with_path_segment(stringify!($index)))?,
+                )*);
+                check_column_lengths(&[$(columns.$index.len()),*])?;
+                Ok(Columns(columns))
+            }
+        }
+        impl<$($t: Introspect),*> Introspect for Columns<($(Vec<$t>,)*)> {
+            fn introspect_value(&self) -> String {
+                "Columns".to_string()
+            }
+            fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+                match index {
+                    $($index => Some(introspect_item(index.to_string(), &self.0.$index)),)*
+                    _ => None,
+                }
+            }
+            fn introspect_len(&self) -> usize {
+                [$(stringify!($index)),*].len()
+            }
+        }
+    };
+}
+
+impl_columns!(T1 0);
+impl_columns!(T1 0, T2 1);
+impl_columns!(T1 0, T2 1, T3 2);
+impl_columns!(T1 0, T2 1, T3 2, T4 3);
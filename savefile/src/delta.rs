@@ -0,0 +1,332 @@
+//! Saving only the parts of a value which changed since a previous save.
+
+use crate::dynamic::to_dyn_value;
+use crate::{Deserialize, Deserializer, DynValue, Encoding, SavefileError, Schema, Serialize, Serializer, WithSchema};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// The value at the path was replaced by the serialized value
+const CHANGE_REPLACE: u8 = 0;
+
+/// The vector at the path was truncated or extended to the given length,
+/// and the serialized items appended, if it was extended.
+const CHANGE_RESIZE: u8 = 1;
+
+/// Path key of the contents of a present option
+const OPTION_KEY: &str = "?";
+
+fn serialize_dyn(value: &DynValue, schema: &Schema, version: u32) -> Result<Vec<u8>, SavefileError> {
+    let mut bytes = Vec::new();
+    value.serialize(
+        schema,
+        &mut Serializer {
+            writer: &mut bytes,
+            version,
+            encoding: Encoding::default(),
+            interned_strings: HashMap::new(),
+            context: None,
+            progress: None,
+        },
+    )?;
+    Ok(bytes)
+}
+
+/// Writes the changes which turn `a` into `b` to the serializer, and returns the number of changes.
+fn write_changes(
+    schema: &Schema,
+    path: &str,
+    a: &DynValue,
+    b: &DynValue,
+    version: u32,
+    serializer: &mut Serializer<impl Write>,
+) -> Result<u64, SavefileError> {
+    let child_path = |key: &str| format!("{}/{}", path, key);
+    let mut count = 0;
+    match (schema, a, b) {
+        (
+            Schema::Struct(schema_struct),
+            DynValue::Struct { fields: a_fields, .. },
+            DynValue::Struct { fields: b_fields, .. },
+        ) => {
+            for field in &schema_struct.fields {
+                if let (Some(a_value), Some(b_value)) = (a_fields.get(&field.name), b_fields.get(&field.name)) {
+                    count += write_changes(
+                        &field.value,
+                        &child_path(&field.name),
+                        a_value,
+                        b_value,
+                        version,
+                        serializer,
+                    )?;
+                }
+            }
+            return Ok(count);
+        }
+        (
+            Schema::Enum(schema_enum),
+            DynValue::Enum {
+                variant: a_variant,
+                fields: a_fields,
+                ..
+            },
+            DynValue::Enum {
+                variant: b_variant,
+                fields: b_fields,
+                ..
+            },
+        ) if a_variant == b_variant => {
+            if let Some(schema_variant) = schema_enum.variants.iter().find(|variant| &variant.name == a_variant) {
+                for field in &schema_variant.fields {
+                    if let (Some(a_value), Some(b_value)) = (a_fields.get(&field.name), b_fields.get(&field.name)) {
+                        count += write_changes(
+                            &field.value,
+                            &child_path(&field.name),
+                            a_value,
+                            b_value,
+                            version,
+                            serializer,
+                        )?;
+                    }
+                }
+                return Ok(count);
+            }
+        }
+        (Schema::Vector(item), DynValue::Vector(a_items), DynValue::Vector(b_items)) => {
+            for (index, (a_item, b_item)) in a_items.iter().zip(b_items.iter()).enumerate() {
+                count += write_changes(
+                    item,
+                    &child_path(&index.to_string()),
+                    a_item,
+                    b_item,
+                    version,
+                    serializer,
+                )?;
+            }
+            if a_items.len() != b_items.len() {
+                let mut appended = Vec::new();
+                for b_item in b_items.iter().skip(a_items.len()) {
+                    appended.extend(serialize_dyn(b_item, item, version)?);
+                }
+                serializer.write_u8(CHANGE_RESIZE)?;
+                serializer.write_string(path)?;
+                serializer.write_length(b_items.len() as u64)?;
+                appended.serialize(serializer)?;
+                count += 1;
+            }
+            return Ok(count);
+        }
+        (Schema::Array(array), DynValue::Array(a_items), DynValue::Array(b_items))
+            if a_items.len() == b_items.len() =>
+        {
+            for (index, (a_item, b_item)) in a_items.iter().zip(b_items.iter()).enumerate() {
+                count += write_changes(
+                    &array.item_type,
+                    &child_path(&index.to_string()),
+                    a_item,
+                    b_item,
+                    version,
+                    serializer,
+                )?;
+            }
+            return Ok(count);
+        }
+        (Schema::SchemaOption(item), DynValue::Option(Some(a_value)), DynValue::Option(Some(b_value))) => {
+            return write_changes(item, &child_path(OPTION_KEY), a_value, b_value, version, serializer);
+        }
+        _ => {}
+    }
+    if a != b {
+        serializer.write_u8(CHANGE_REPLACE)?;
+        serializer.write_string(path)?;
+        serialize_dyn(b, schema, version)?.serialize(serializer)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn bad_path(path: &str) -> SavefileError {
+    SavefileError::GeneralError {
+        msg: format!(
+            "The delta changes the value at '{}', which doesn't exist in the base value",
+            path
+        ),
+    }
+}
+
+/// Find the value at the path, and its schema
+fn resolve<'s, 'v>(
+    mut schema: &'s Schema,
+    mut value: &'v mut DynValue,
+    path: &str,
+) -> Result<(&'s Schema, &'v mut DynValue), SavefileError> {
+    for key in path.split('/').filter(|key| !key.is_empty()) {
+        let (child_schema, child) = match (schema, value) {
+            (Schema::Struct(schema_struct), DynValue::Struct { fields, .. }) => {
+                let field = schema_struct.fields.iter().find(|field| field.name == key);
+                (field.map(|field| &*field.value), fields.get_mut(key))
+            }
+            (Schema::Enum(schema_enum), DynValue::Enum { variant, fields, .. }) => {
+                let field = schema_enum
+                    .variants
+                    .iter()
+                    .find(|candidate| &candidate.name == variant)
+                    .and_then(|variant| variant.fields.iter().find(|field| field.name == key));
+                (field.map(|field| &*field.value), fields.get_mut(key))
+            }
+            (Schema::Vector(item), DynValue::Vector(items)) => (
+                Some(&**item),
+                key.parse::<usize>().ok().and_then(move |index| items.get_mut(index)),
+            ),
+            (Schema::Array(array), DynValue::Array(items)) => (
+                Some(&*array.item_type),
+                key.parse::<usize>().ok().and_then(move |index| items.get_mut(index)),
+            ),
+            (Schema::SchemaOption(item), DynValue::Option(Some(inner))) if key == OPTION_KEY => {
+                (Some(&**item), Some(&mut **inner))
+            }
+            _ => (None, None),
+        };
+        match (child_schema, child) {
+            (Some(child_schema), Some(child)) => {
+                schema = child_schema;
+                value = child;
+            }
+            _ => return Err(bad_path(path)),
+        }
+    }
+    Ok((schema, value))
+}
+
+/// Serialize the differences between `previous` and `current`, which can be applied to a copy
+/// of `previous` to recreate `current`, using [crate::apply_delta]. Only the fields and
+/// items which changed are saved, each with its path in the value. Items added to or
+/// removed from the end of a vector are saved as a change of its length.
+/// The current version of T in memory must be `version`.
+///
+/// The whole value is compared, so this saves space, not time. It is useful for saving
+/// mostly unchanged values often, such as for autosaves.
+pub fn save_delta<T: WithSchema + Serialize>(
+    version: u32,
+    previous: &T,
+    current: &T,
+) -> Result<Vec<u8>, SavefileError> {
+    let schema = T::schema(version);
+    let previous = to_dyn_value(&schema, version, previous)?;
+    let current = to_dyn_value(&schema, version, current)?;
+    let mut changes = Vec::new();
+    let count = write_changes(
+        &schema,
+        "",
+        &previous,
+        &current,
+        version,
+        &mut Serializer {
+            writer: &mut changes,
+            version,
+            encoding: Encoding::default(),
+            interned_strings: HashMap::new(),
+            context: None,
+            progress: None,
+        },
+    )?;
+    let mut delta = Vec::with_capacity(changes.len() + 12);
+    {
+        let mut serializer = Serializer::new_raw(&mut delta);
+        serializer.write_u32(version)?;
+        serializer.write_u64(count)?;
+        serializer.write_bytes(&changes)?;
+    }
+    Ok(delta)
+}
+
+/// Apply a delta created by [crate::save_delta] to `base`, which must be equal to the previous
+/// value the delta was created from. The delta must have been saved with the current version of
+/// T in memory, `version`. If an error is returned, `base` is unchanged.
+pub fn apply_delta<T: WithSchema + Serialize + Deserialize>(
+    version: u32,
+    base: &mut T,
+    delta: &[u8],
+) -> Result<(), SavefileError> {
+    let mut reader = delta;
+    let mut deserializer = Deserializer::new_raw(&mut reader);
+    let delta_version = deserializer.read_u32()?;
+    if delta_version != version {
+        return Err(SavefileError::WrongVersion {
+            msg: format!(
+                "The delta has version {}, but deltas can only be applied with the same version ({})",
+                delta_version, version
+            ),
+        });
+    }
+    deserializer.file_version = version;
+    deserializer.memory_version = version;
+    let schema = T::schema(version);
+    let mut value = to_dyn_value(&schema, version, base)?;
+    let count = deserializer.read_u64()?;
+    for _ in 0..count {
+        let kind = deserializer.read_u8()?;
+        let path = deserializer.read_string()?;
+        let (target_schema, target) = resolve(&schema, &mut value, &path)?;
+        match (kind, target_schema, target) {
+            (CHANGE_REPLACE, _, target) => {
+                let bytes = <Vec<u8>>::deserialize(&mut deserializer)?;
+                *target = DynValue::deserialize(
+                    target_schema,
+                    &mut Deserializer {
+                        reader: &mut &bytes[..],
+                        file_version: version,
+                        memory_version: version,
+                        encoding: Encoding::default(),
+                        ephemeral_state: HashMap::new(),
+                        interned_strings: Vec::new(),
+                        scratch: Vec::new(),
+                        unchecked_utf8: false,
+                        context: None,
+                        progress: None,
+                        memory_budget: None,
+                    },
+                )?;
+            }
+            (CHANGE_RESIZE, Schema::Vector(item), DynValue::Vector(items)) => {
+                let length = deserializer.read_length_usize()?;
+                let bytes = <Vec<u8>>::deserialize(&mut deserializer)?;
+                items.truncate(length);
+                let mut appended = &bytes[..];
+                let mut item_deserializer = Deserializer {
+                    reader: &mut appended,
+                    file_version: version,
+                    memory_version: version,
+                    encoding: Encoding::default(),
+                    ephemeral_state: HashMap::new(),
+                    interned_strings: Vec::new(),
+                    scratch: Vec::new(),
+                    unchecked_utf8: false,
+                    context: None,
+                    progress: None,
+                    memory_budget: None,
+                };
+                while items.len() < length {
+                    items.push(DynValue::deserialize(item, &mut item_deserializer)?);
+                }
+            }
+            _ => return Err(bad_path(&path)),
+        }
+    }
+    let bytes = serialize_dyn(&value, &schema, version)?;
+    let mut tmp_reader = &bytes[..];
+    let mut deserializer = Deserializer {
+        reader: &mut tmp_reader,
+        file_version: version,
+        memory_version: version,
+        encoding: Encoding::default(),
+        ephemeral_state: HashMap::new(),
+        interned_strings: Vec::new(),
+        scratch: Vec::new(),
+        unchecked_utf8: false,
+        context: None,
+        progress: None,
+        memory_budget: None,
+    };
+    *base = T::deserialize(&mut deserializer)?;
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! Maps saved as a run of sorted keys followed by a run of values, instead of as key-value pairs.
+
+use crate::{
+    schema_of, CurrentVersion, Deserialize, Deserializer, Field, Introspect, IntrospectItem, SavefileError, Schema, SchemaStruct,
+    Serialize, Serializer, WithSchema, VEC_CHUNK_BYTES,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+
+/// A map, `HashMap<K, V>` or `BTreeMap<K, V>`, which is saved with its keys sorted, as a vector
+/// of all the keys, followed by a vector of all the values. When K and V are [crate::ReprC],
+/// such as for maps from integer entity ids to plain structs, each vector is written and read
+/// in large chunks, and loading doesn't need to look at each key and value separately.
+///
+/// The map is used through [Deref] and [DerefMut]. In the schema, it is a struct with the
+/// vectors `keys` and `values`. Loading fails if the keys aren't in increasing order, or if
+/// there are not as many values as keys.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DenseMap<M>(pub M);
+
+impl<M> DenseMap<M> {
+    /// The map
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl<M> Deref for DenseMap<M> {
+    type Target = M;
+    fn deref(&self) -> &M {
+        &self.0
+    }
+}
+
+impl<M> DerefMut for DenseMap<M> {
+    fn deref_mut(&mut self) -> &mut M {
+        &mut self.0
+    }
+}
+
+fn dense_map_schema<K: WithSchema, V: WithSchema>(version: u32) -> Schema {
+    Schema::Struct(SchemaStruct {
+        dbg_name: "DenseMap".to_string(),
+        fields: vec![
+            Field {
+                name: "keys".to_string(),
+                value: schema_of::<Vec<K>>(version),
+            },
+            Field {
+                name: "values".to_string(),
+                value: schema_of::<Vec<V>>(version),
+            },
+        ],
+    })
+}
+
+/// Serialize the items like a `Vec<T>` containing them. If T is [crate::ReprC], they are
+/// copied to a buffer, which is written in large chunks.
+pub(crate) fn serialize_run<'a, T: Serialize + 'a>(
+    len: usize,
+    items: impl Iterator<Item = &'a T>,
+    serializer: &mut Serializer<impl Write>,
+) -> Result<(), SavefileError> {
+    serializer.write_length(len as u64)?;
+    if !serializer.uses_raw_copy::<T>() {
+        for item in items {
+            item.serialize(serializer)
+                .map_err(|err| err.with_validation_path_segment("*"))?;
+        }
+        return Ok(());
+    }
+    let mut buffer = Vec::with_capacity(VEC_CHUNK_BYTES.min(len * std::mem::size_of::<T>()));
+    for item in items {
+        // Safety: T is ReprC, so it contains no padding, and all its bytes are initialized
+        buffer.extend_from_slice(unsafe {
+            std::slice::from_raw_parts(item as *const T as *const u8, std::mem::size_of::<T>())
+        });
+        if buffer.len() >= VEC_CHUNK_BYTES {
+            serializer.write_buf(&buffer)?;
+            buffer.clear();
+        }
+    }
+    serializer.write_buf(&buffer)
+}
+
+fn serialize_sorted<K: Serialize + Ord, V: Serialize>(
+    mut entries: Vec<(&K, &V)>,
+    serializer: &mut Serializer<impl Write>,
+) -> Result<(), SavefileError> {
+    entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    serialize_run(entries.len(), entries.iter().map(|entry| entry.0), serializer)?;
+    serialize_run(entries.len(), entries.iter().map(|entry| entry.1), serializer)
+}
+
+/// Deserialize the keys and values saved by [serialize_sorted], and give them to `insert`
+fn deserialize_sorted<K: Deserialize + Ord, V: Deserialize>(
+    deserializer: &mut Deserializer<impl Read>,
+    mut insert: impl FnMut(K, V),
+) -> Result<(), SavefileError> {
+    let keys = Vec::<K>::deserialize(deserializer).map_err(|err| err.with_path_segment("keys"))?;
+    let values = Vec::<V>::deserialize(deserializer).map_err(|err| err.with_path_segment("values"))?;
+    if keys.len() != values.len() {
+        return Err(SavefileError::GeneralError {
+            msg: format!("The map has {} keys, but {} values", keys.len(), values.len()),
+        });
+    }
+    if keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(SavefileError::GeneralError {
+            msg: "The keys of the map are not in increasing order".into(),
+        });
+    }
+    for (key, value) in keys.into_iter().zip(values) {
+        insert(key, value);
+    }
+    Ok(())
+}
+
+impl<K: WithSchema + Eq + Hash, V: WithSchema, S: BuildHasher> WithSchema for DenseMap<HashMap<K, V, S>> {
+    fn schema(version: u32) -> Schema {
+        dense_map_schema::<K, V>(version)
+    }
+}
+
+impl<K: Serialize + Ord + Hash, V: Serialize, S: BuildHasher> Serialize for DenseMap<HashMap<K, V, S>> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_sorted(self.0.iter().collect(), serializer)
+    }
+}
+
+impl<K: Deserialize + Ord + Hash, V: Deserialize, S: BuildHasher + Default> Deserialize for DenseMap<HashMap<K, V, S>> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let mut map = HashMap::default();
+        deserialize_sorted(deserializer, |key, value| {
+            map.insert(key, value);
+        })?;
+        Ok(DenseMap(map))
+    }
+}
+
+impl<K: WithSchema, V: WithSchema> WithSchema for DenseMap<BTreeMap<K, V>> {
+    fn schema(version: u32) -> Schema {
+        dense_map_schema::<K, V>(version)
+    }
+}
+
+impl<K: Serialize + Ord, V: Serialize> Serialize for DenseMap<BTreeMap<K, V>> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_run(self.0.len(), self.0.keys(), serializer)?;
+        serialize_run(self.0.len(), self.0.values(), serializer)
+    }
+}
+
+impl<K: Deserialize + Ord, V: Deserialize> Deserialize for DenseMap<BTreeMap<K, V>> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let mut map = BTreeMap::new();
+        deserialize_sorted(deserializer, |key, value| {
+            map.insert(key, value);
+        })?;
+        Ok(DenseMap(map))
+    }
+}
+
+impl<M: Introspect> Introspect for DenseMap<M> {
+    fn introspect_value(&self) -> String {
+        self.0.introspect_value()
+    }
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        self.0.introspect_child(index)
+    }
+    fn introspect_len(&self) -> usize {
+        self.0.introspect_len()
+    }
+}
+
+impl<M: CurrentVersion> CurrentVersion for DenseMap<M> {
+    const SAVEFILE_MAX_VERSION: u32 = M::SAVEFILE_MAX_VERSION;
+}
@@ -0,0 +1,592 @@
+//! A dynamic representation of savefile data, which can be loaded using only the
+//! schema embedded in a file, without access to the rust types which saved it.
+
+use crate::{
+    decompressing_reader, packed_bit_width, packed_run, read_vec_items, schema_differences, ChecksumReader, CompressionMethod,
+    Deserializer, Encoding, PackedBits, SavefileError, SavefileHeader, Schema, SchemaPrimitive, Serialize, Serializer, WithSchema,
+    SAVEFILE_FORMAT_VERSION,
+};
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+/// A value of any type which can be described by a [Schema].
+/// Structs and enum variants hold their fields by name, in declaration order.
+/// Fields of tuples and tuple structs are named "0", "1" and so on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynValue {
+    /// i8
+    I8(i8),
+    /// u8
+    U8(u8),
+    /// i16
+    I16(i16),
+    /// u16
+    U16(u16),
+    /// i32
+    I32(i32),
+    /// u32 (also used for the canary type)
+    U32(u32),
+    /// i64 (also used for isize)
+    I64(i64),
+    /// u64 (also used for usize)
+    U64(u64),
+    /// f32
+    F32(f32),
+    /// f64
+    F64(f64),
+    /// bool
+    Bool(bool),
+    /// String
+    String(String),
+    /// A struct or tuple
+    Struct {
+        /// Name of the struct
+        name: String,
+        /// The fields, by name
+        fields: IndexMap<String, DynValue>,
+    },
+    /// An enum value
+    Enum {
+        /// Name of the active variant
+        variant: String,
+        /// Discriminator of the active variant
+        discriminator: u32,
+        /// The fields of the active variant, by name
+        fields: IndexMap<String, DynValue>,
+    },
+    /// A vector, or other collection, of values. Maps are vectors of key-value structs.
+    Vector(Vec<DynValue>),
+    /// A fixed size array
+    Array(Vec<DynValue>),
+    /// An optional value
+    Option(Option<Box<DynValue>>),
+    /// A value of a zero-sized type
+    ZeroSize,
+}
+
+fn mismatch(schema: &Schema, value: &DynValue) -> SavefileError {
+    SavefileError::GeneralError {
+        msg: format!("Value {:?} does not match schema {}", value, schema),
+    }
+}
+
+impl DynValue {
+    /// Deserialize a value, as described by the given schema.
+    pub fn deserialize(schema: &Schema, deserializer: &mut Deserializer<impl Read>) -> Result<DynValue, SavefileError> {
+        Ok(match schema {
+            Schema::Struct(schema_struct) => DynValue::Struct {
+                name: schema_struct.dbg_name.clone(),
+                fields: Self::deserialize_fields(&schema_struct.fields, deserializer)?,
+            },
+            Schema::Enum(schema_enum) => {
+                let discriminator = deserializer.read_discriminant(schema_enum.discriminant_size)?;
+                let variant = schema_enum
+                    .variants
+                    .iter()
+                    .find(|variant| variant.discriminator == discriminator)
+                    .ok_or_else(|| SavefileError::GeneralError {
+                        msg: format!(
+                            "Enum {} has no variant with discriminator {}",
+                            schema_enum.dbg_name, discriminator
+                        ),
+                    })?;
+                DynValue::Enum {
+                    variant: variant.name.clone(),
+                    discriminator,
+                    fields: Self::deserialize_fields(&variant.fields, deserializer)?,
+                }
+            }
+            Schema::Primitive(primitive) => match primitive {
+                SchemaPrimitive::schema_i8 => DynValue::I8(deserializer.read_i8()?),
+                SchemaPrimitive::schema_u8 => DynValue::U8(deserializer.read_u8()?),
+                SchemaPrimitive::schema_i16 => DynValue::I16(deserializer.read_i16()?),
+                SchemaPrimitive::schema_u16 => DynValue::U16(deserializer.read_u16()?),
+                SchemaPrimitive::schema_i32 => DynValue::I32(deserializer.read_i32()?),
+                SchemaPrimitive::schema_u32 | SchemaPrimitive::schema_canary1 => {
+                    DynValue::U32(deserializer.read_u32()?)
+                }
+                SchemaPrimitive::schema_i64 => DynValue::I64(deserializer.read_i64()?),
+                SchemaPrimitive::schema_u64 => DynValue::U64(deserializer.read_u64()?),
+                SchemaPrimitive::schema_string => DynValue::String(deserializer.read_string()?),
+                SchemaPrimitive::schema_f32 => DynValue::F32(deserializer.read_f32()?),
+                SchemaPrimitive::schema_f64 => DynValue::F64(deserializer.read_f64()?),
+                SchemaPrimitive::schema_bool => DynValue::Bool(deserializer.read_bool()?),
+            },
+            Schema::Vector(item) => {
+                let mut items = Vec::new();
+                read_vec_items(deserializer, |deserializer| {
+                    items.push(Self::deserialize(item, deserializer)?);
+                    Ok(())
+                })?;
+                DynValue::Vector(items)
+            }
+            Schema::Array(array) => {
+                let mut items = Vec::with_capacity(array.count.min(4096));
+                for _ in 0..array.count {
+                    items.push(Self::deserialize(&array.item_type, deserializer)?);
+                }
+                DynValue::Array(items)
+            }
+            Schema::SchemaOption(item) => {
+                if deserializer.read_u8()? == 0 {
+                    DynValue::Option(None)
+                } else {
+                    DynValue::Option(Some(Box::new(Self::deserialize(item, deserializer)?)))
+                }
+            }
+            Schema::ZeroSize => DynValue::ZeroSize,
+            Schema::BitPacked(item) => {
+                let width = packed_bit_width(item)?;
+                Self::unpack(item, deserializer.read_packed(width)?.pop_bits(width))?
+            }
+            Schema::Undefined => {
+                return Err(SavefileError::GeneralError {
+                    msg: "Values of undefined schema can't be deserialized".into(),
+                })
+            }
+        })
+    }
+
+    fn deserialize_fields(
+        fields: &[crate::Field],
+        deserializer: &mut Deserializer<impl Read>,
+    ) -> Result<IndexMap<String, DynValue>, SavefileError> {
+        let mut values = IndexMap::with_capacity(fields.len());
+        let mut packed = PackedBits::new();
+        let mut packed_remaining = 0;
+        for (index, field) in fields.iter().enumerate() {
+            let value = match *field.value {
+                Schema::BitPacked(ref item) => {
+                    if packed_remaining == 0 {
+                        let (count, width) = packed_run(fields[index..].iter().map(|field| &*field.value))?;
+                        packed = deserializer.read_packed(width)?;
+                        packed_remaining = count;
+                    }
+                    packed_remaining -= 1;
+                    let bits = packed.pop_bits(packed_bit_width(item)?);
+                    Self::unpack(item, bits)
+                }
+                _ => {
+                    packed_remaining = 0;
+                    Self::deserialize(&field.value, deserializer)
+                }
+            }
+            .map_err(|err| err.with_path_segment(&field.name))?;
+            values.insert(field.name.clone(), value);
+        }
+        Ok(values)
+    }
+
+    /// The value of a bit-packed bool or enum, with the schema inside [Schema::BitPacked]
+    fn unpack(schema: &Schema, bits: u64) -> Result<DynValue, SavefileError> {
+        match schema {
+            Schema::Primitive(SchemaPrimitive::schema_bool) => Ok(DynValue::Bool(bits != 0)),
+            Schema::Enum(schema_enum) => {
+                let variant = schema_enum
+                    .variants
+                    .iter()
+                    .find(|variant| u64::from(variant.discriminator) == bits)
+                    .ok_or_else(|| SavefileError::GeneralError {
+                        msg: format!("Enum {} has no variant with discriminator {}", schema_enum.dbg_name, bits),
+                    })?;
+                Ok(DynValue::Enum {
+                    variant: variant.name.clone(),
+                    discriminator: variant.discriminator,
+                    fields: IndexMap::new(),
+                })
+            }
+            _ => Err(packed_bit_width(schema).err().unwrap_or_else(|| mismatch(schema, &DynValue::ZeroSize))),
+        }
+    }
+
+    /// The bits of a bit-packed bool or enum, with the schema inside [Schema::BitPacked]
+    fn pack(&self, schema: &Schema, packed: &mut PackedBits) -> Result<(), SavefileError> {
+        let bits = match (schema, self) {
+            (Schema::Primitive(SchemaPrimitive::schema_bool), DynValue::Bool(x)) => *x as u64,
+            (Schema::Enum(schema_enum), DynValue::Enum { variant, fields, .. }) if fields.is_empty() => {
+                let schema_variant = schema_enum
+                    .variants
+                    .iter()
+                    .find(|candidate| &candidate.name == variant)
+                    .ok_or_else(|| mismatch(schema, self))?;
+                u64::from(schema_variant.discriminator)
+            }
+            _ => return Err(mismatch(schema, self)),
+        };
+        packed.push_bits(bits, packed_bit_width(schema)?)
+    }
+
+    /// Serialize the value, as described by the given schema.
+    /// Fails if the value doesn't match the schema.
+    pub fn serialize(&self, schema: &Schema, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        match (schema, self) {
+            (Schema::Struct(schema_struct), DynValue::Struct { fields, .. }) => {
+                Self::serialize_fields(&schema_struct.fields, fields, serializer)
+            }
+            (Schema::Enum(schema_enum), DynValue::Enum { variant, fields, .. }) => {
+                let schema_variant = schema_enum
+                    .variants
+                    .iter()
+                    .find(|candidate| &candidate.name == variant)
+                    .ok_or_else(|| mismatch(schema, self))?;
+                serializer.write_discriminant(schema_variant.discriminator, schema_enum.discriminant_size)?;
+                Self::serialize_fields(&schema_variant.fields, fields, serializer)
+            }
+            (Schema::Primitive(primitive), value) => match (primitive, value) {
+                (SchemaPrimitive::schema_i8, DynValue::I8(x)) => serializer.write_i8(*x),
+                (SchemaPrimitive::schema_u8, DynValue::U8(x)) => serializer.write_u8(*x),
+                (SchemaPrimitive::schema_i16, DynValue::I16(x)) => serializer.write_i16(*x),
+                (SchemaPrimitive::schema_u16, DynValue::U16(x)) => serializer.write_u16(*x),
+                (SchemaPrimitive::schema_i32, DynValue::I32(x)) => serializer.write_i32(*x),
+                (SchemaPrimitive::schema_u32, DynValue::U32(x)) => serializer.write_u32(*x),
+                (SchemaPrimitive::schema_canary1, DynValue::U32(x)) => serializer.write_u32(*x),
+                (SchemaPrimitive::schema_i64, DynValue::I64(x)) => serializer.write_i64(*x),
+                (SchemaPrimitive::schema_u64, DynValue::U64(x)) => serializer.write_u64(*x),
+                (SchemaPrimitive::schema_string, DynValue::String(x)) => serializer.write_string(x),
+                (SchemaPrimitive::schema_f32, DynValue::F32(x)) => serializer.write_f32(*x),
+                (SchemaPrimitive::schema_f64, DynValue::F64(x)) => serializer.write_f64(*x),
+                (SchemaPrimitive::schema_bool, DynValue::Bool(x)) => serializer.write_bool(*x),
+                _ => Err(mismatch(schema, self)),
+            },
+            (Schema::Vector(item), DynValue::Vector(items)) => {
+                serializer.write_length(items.len() as u64)?;
+                for value in items {
+                    value.serialize(item, serializer)?;
+                }
+                Ok(())
+            }
+            (Schema::Array(array), DynValue::Array(items)) if items.len() == array.count => {
+                for value in items {
+                    value.serialize(&array.item_type, serializer)?;
+                }
+                Ok(())
+            }
+            (Schema::SchemaOption(_), DynValue::Option(None)) => serializer.write_u8(0),
+            (Schema::SchemaOption(item), DynValue::Option(Some(value))) => {
+                serializer.write_u8(1)?;
+                value.serialize(item, serializer)
+            }
+            (Schema::ZeroSize, DynValue::ZeroSize) => Ok(()),
+            (Schema::BitPacked(item), value) => {
+                let mut packed = PackedBits::new();
+                value.pack(item, &mut packed)?;
+                serializer.write_packed(&packed)
+            }
+            _ => Err(mismatch(schema, self)),
+        }
+    }
+
+    fn serialize_fields(
+        schema_fields: &[crate::Field],
+        fields: &IndexMap<String, DynValue>,
+        serializer: &mut Serializer<impl Write>,
+    ) -> Result<(), SavefileError> {
+        if schema_fields.len() != fields.len() {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "Expected {} fields, but the value has {}",
+                    schema_fields.len(),
+                    fields.len()
+                ),
+            });
+        }
+        let mut packed = PackedBits::new();
+        for (index, schema_field) in schema_fields.iter().enumerate() {
+            let value = fields
+                .get(&schema_field.name)
+                .ok_or_else(|| SavefileError::GeneralError {
+                    msg: format!("Missing field {}", schema_field.name),
+                })?;
+            match *schema_field.value {
+                Schema::BitPacked(ref item) => {
+                    value.pack(item, &mut packed)?;
+                    // The group ends at the first field which isn't bit-packed
+                    if !matches!(schema_fields.get(index + 1).map(|next| &*next.value), Some(Schema::BitPacked(_))) {
+                        serializer.write_packed(&packed)?;
+                        packed = PackedBits::new();
+                    }
+                }
+                _ => value.serialize(&schema_field.value, serializer)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The child with the given key: a field of a struct or of the active variant of an enum,
+    /// or an index into a vector or array. The contents of a present option are searched for the key.
+    pub fn child(&self, key: &str) -> Option<&DynValue> {
+        match self {
+            DynValue::Struct { fields, .. } | DynValue::Enum { fields, .. } => fields.get(key),
+            DynValue::Vector(items) | DynValue::Array(items) => items.get(key.parse::<usize>().ok()?),
+            DynValue::Option(Some(value)) => value.child(key),
+            _ => None,
+        }
+    }
+
+    /// Mutable version of [DynValue::child]
+    pub fn child_mut(&mut self, key: &str) -> Option<&mut DynValue> {
+        match self {
+            DynValue::Struct { fields, .. } | DynValue::Enum { fields, .. } => fields.get_mut(key),
+            DynValue::Vector(items) | DynValue::Array(items) => items.get_mut(key.parse::<usize>().ok()?),
+            DynValue::Option(Some(value)) => value.child_mut(key),
+            _ => None,
+        }
+    }
+
+    /// The value at the given path, consisting of keys (see [DynValue::child]) separated by '/'.
+    /// The empty path denotes the value itself.
+    pub fn get(&self, path: &str) -> Option<&DynValue> {
+        path.split('/')
+            .filter(|key| !key.is_empty())
+            .try_fold(self, |value, key| value.child(key))
+    }
+
+    /// Mutable version of [DynValue::get]
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut DynValue> {
+        path.split('/')
+            .filter(|key| !key.is_empty())
+            .try_fold(self, |value, key| value.child_mut(key))
+    }
+}
+
+/// A value which differs between two values being compared, see [DynValue::diff].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueDifference {
+    /// Location of the difference, such as `/players/0/name`, in the format used by [DynValue::get].
+    /// The path of the whole value is `/`.
+    pub path: String,
+    /// The value in the first value compared
+    pub old: DynValue,
+    /// The value in the second value compared
+    pub new: DynValue,
+}
+
+impl Display for ValueDifference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} -> {}", self.path, self.old.to_json(), self.new.to_json())
+    }
+}
+
+impl DynValue {
+    /// Compare with another value of the same schema, and return the innermost values which
+    /// differ, with their paths. Differences in which enum variant is active, or in the length
+    /// of vectors, are reported for the enum or vector as a whole.
+    pub fn diff(&self, other: &DynValue) -> Vec<ValueDifference> {
+        let mut differences = Vec::new();
+        diff_dyn_values("", self, other, &mut differences);
+        differences
+    }
+}
+
+fn diff_dyn_values(path: &str, a: &DynValue, b: &DynValue, differences: &mut Vec<ValueDifference>) {
+    let child_path = |key: &str| format!("{}/{}", path, key);
+    match (a, b) {
+        (DynValue::Struct { fields: a_fields, .. }, DynValue::Struct { fields: b_fields, .. })
+            if a_fields.keys().eq(b_fields.keys()) =>
+        {
+            for ((key, a_value), b_value) in a_fields.iter().zip(b_fields.values()) {
+                diff_dyn_values(&child_path(key), a_value, b_value, differences);
+            }
+        }
+        (
+            DynValue::Enum {
+                variant: a_variant,
+                fields: a_fields,
+                ..
+            },
+            DynValue::Enum {
+                variant: b_variant,
+                fields: b_fields,
+                ..
+            },
+        ) if a_variant == b_variant && a_fields.keys().eq(b_fields.keys()) => {
+            for ((key, a_value), b_value) in a_fields.iter().zip(b_fields.values()) {
+                diff_dyn_values(&child_path(key), a_value, b_value, differences);
+            }
+        }
+        (DynValue::Vector(a_items), DynValue::Vector(b_items)) | (DynValue::Array(a_items), DynValue::Array(b_items))
+            if a_items.len() == b_items.len() =>
+        {
+            for (index, (a_item, b_item)) in a_items.iter().zip(b_items.iter()).enumerate() {
+                diff_dyn_values(&child_path(&index.to_string()), a_item, b_item, differences);
+            }
+        }
+        (DynValue::Option(Some(a_value)), DynValue::Option(Some(b_value))) => {
+            diff_dyn_values(path, a_value, b_value, differences)
+        }
+        _ => {
+            if a != b {
+                differences.push(ValueDifference {
+                    path: if path.is_empty() { "/".to_string() } else { path.to_string() },
+                    old: a.clone(),
+                    new: b.clone(),
+                });
+            }
+        }
+    }
+}
+
+pub(crate) fn to_dyn_value<T: WithSchema + Serialize>(schema: &Schema, version: u32, data: &T) -> Result<DynValue, SavefileError> {
+    let mut bytes = Vec::new();
+    data.serialize(&mut Serializer {
+        writer: &mut bytes,
+        version,
+        encoding: Encoding::default(),
+        interned_strings: HashMap::new(),
+        context: None,
+        progress: None,
+    })?;
+    DynValue::deserialize(
+        schema,
+        &mut Deserializer {
+            reader: &mut &bytes[..],
+            file_version: version,
+            memory_version: version,
+            encoding: Encoding::default(),
+            ephemeral_state: HashMap::new(),
+            interned_strings: Vec::new(),
+            scratch: Vec::new(),
+            unchecked_utf8: false,
+            context: None,
+            progress: None,
+            memory_budget: None,
+        },
+    )
+}
+
+/// Compare two values field by field, and return the innermost values which differ,
+/// with their paths, see [DynValue::diff]. The current version of T in memory must be `version`.
+pub fn diff_values<T: WithSchema + Serialize>(
+    version: u32,
+    a: &T,
+    b: &T,
+) -> Result<Vec<ValueDifference>, SavefileError> {
+    let schema = T::schema(version);
+    Ok(to_dyn_value(&schema, version, a)?.diff(&to_dyn_value(&schema, version, b)?))
+}
+
+/// Compare two files saved with a schema, such as by [crate::save_to_mem], and return the
+/// innermost values which differ, with their paths, see [DynValue::diff].
+/// Fails if the files have different versions or schemas.
+pub fn diff_serialized(a: &[u8], b: &[u8]) -> Result<Vec<ValueDifference>, SavefileError> {
+    let a = Document::load(&mut &a[..])?;
+    let b = Document::load(&mut &b[..])?;
+    if a.version != b.version {
+        return Err(SavefileError::WrongVersion {
+            msg: format!("The files have different versions ({} and {})", a.version, b.version),
+        });
+    }
+    let differences = schema_differences(&b.schema, &a.schema);
+    if !differences.is_empty() {
+        return Err(SavefileError::IncompatibleSchema {
+            message: differences
+                .iter()
+                .map(|difference| difference.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        });
+    }
+    Ok(a.value.diff(&b.value))
+}
+
+/// The contents of a savefile file, loaded without knowing the rust types which saved it.
+/// The file must have been saved with a schema. The value can be inspected and modified,
+/// and saved again. The saved file can be loaded as usual, as long as the value still
+/// matches the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    /// The version of the data
+    pub version: u32,
+    /// The schema of the data
+    pub schema: Schema,
+    /// The data
+    pub value: DynValue,
+}
+
+impl Document {
+    /// Load a document from a file saved with a schema, such as by [crate::save].
+    /// Compressed files are supported, if the compression method is enabled.
+    pub fn load(reader: &mut impl Read) -> Result<Document, SavefileError> {
+        let header = SavefileHeader::read(reader)?;
+        if header.encrypted {
+            return Err(SavefileError::GeneralError {
+                msg: "Encrypted files can't be loaded as documents.".into(),
+            });
+        }
+        if header.has_schema != Some(true) {
+            return Err(SavefileError::GeneralError {
+                msg: "Only files with a schema can be loaded as documents.".into(),
+            });
+        }
+        let mut decompressed = decompressing_reader(reader, header.compression)?;
+        let mut reader = ChecksumReader::new(&mut decompressed, &header)?;
+        crate::namespace::read_namespace_versions(&mut reader, &header)?;
+        let schema = crate::read_stored_schema(&mut reader, header.version, header.has_schema_history)?;
+        reader.verify()?;
+        let value = {
+            let mut deserializer = Deserializer {
+                reader: &mut reader,
+                file_version: header.version,
+                memory_version: header.version,
+                encoding: header.encoding,
+                ephemeral_state: HashMap::new(),
+                interned_strings: Vec::new(),
+                scratch: Vec::new(),
+                unchecked_utf8: false,
+                context: None,
+                progress: None,
+                memory_budget: None,
+            };
+            DynValue::deserialize(&schema, &mut deserializer)?
+        };
+        reader.verify()?;
+        Ok(Document {
+            version: header.version,
+            schema,
+            value,
+        })
+    }
+
+    /// Save the document, with its schema and without compression. No creation time is
+    /// recorded, so the same document always gives the same bytes.
+    pub fn save(&self, writer: &mut impl Write) -> Result<(), SavefileError> {
+        SavefileHeader {
+            format_version: SAVEFILE_FORMAT_VERSION,
+            version: self.version,
+            compression: CompressionMethod::None,
+            has_schema: Some(true),
+            has_checksum: false,
+            encrypted: false,
+            has_schema_hash: false,
+            has_schema_history: false,
+            payload_length: None,
+            created: None,
+            encoding: Encoding::default(),
+        }
+        .write(writer)?;
+        self.schema.serialize(&mut Serializer::new_raw(writer))?;
+        let mut serializer = Serializer {
+            writer,
+            version: self.version,
+            encoding: Encoding::default(),
+            interned_strings: HashMap::new(),
+            context: None,
+            progress: None,
+        };
+        self.value.serialize(&self.schema, &mut serializer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The value at the given path, see [DynValue::get]
+    pub fn get(&self, path: &str) -> Option<&DynValue> {
+        self.value.get(path)
+    }
+
+    /// Mutable version of [Document::get]
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut DynValue> {
+        self.value.get_mut(path)
+    }
+}
@@ -0,0 +1,533 @@
+//! Encryption of saved data, available with the `encryption` feature.
+//!
+//! [save_encrypted] and [load_encrypted] encrypt and authenticate the payload of a file with a
+//! caller-supplied key. [save_encrypted_file] and [load_encrypted_file] use the older format of
+//! [CryptoWriter] and [CryptoReader], with a key derived from a password.
+
+use crate::{Deserialize, Deserializer, LoadOptions, SaveOptions, SavefileError, Serialize, Serializer, WithSchema};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use ring::aead;
+use ring::aead::{BoundKey, Nonce, NonceSequence, OpeningKey, SealingKey, UnboundKey, AES_256_GCM};
+use ring::error::Unspecified;
+use ring::hkdf;
+#[cfg(feature = "fs")]
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Write};
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+#[derive(Debug)]
+struct RandomNonceSequence {
+    data1: u64,
+    data2: u32,
+}
+impl RandomNonceSequence {
+    pub fn new() -> RandomNonceSequence {
+        RandomNonceSequence {
+            data1: OsRng.next_u64(),
+            data2: OsRng.next_u32(),
+        }
+    }
+    pub fn serialize(&self, writer: &mut dyn Write) -> Result<(), SavefileError> {
+        writer.write_u64::<LittleEndian>(self.data1)?;
+        writer.write_u32::<LittleEndian>(self.data2)?;
+        Ok(())
+    }
+    pub fn deserialize(reader: &mut dyn Read) -> Result<RandomNonceSequence, SavefileError> {
+        Ok(RandomNonceSequence {
+            data1: reader.read_u64::<LittleEndian>()?,
+            data2: reader.read_u32::<LittleEndian>()?,
+        })
+    }
+}
+
+impl NonceSequence for RandomNonceSequence {
+    fn advance(&mut self) -> Result<Nonce, Unspecified> {
+        self.data2 = self.data2.wrapping_add(1);
+        if self.data2 == 0 {
+            self.data1 = self.data1.wrapping_add(1);
+        }
+        let mut bytes = [0u8; 12];
+        let bytes1: [u8; 8] = self.data1.to_le_bytes();
+        let bytes2: [u8; 4] = self.data2.to_le_bytes();
+        for i in 0..8 {
+            bytes[i] = bytes1[i];
+        }
+        for i in 0..4 {
+            bytes[i + 8] = bytes2[i];
+        }
+
+        Ok(Nonce::assume_unique_for_key(bytes))
+    }
+}
+
+/// A cryptographic stream wrapper.
+/// Wraps a plain dyn Write, and itself implements Write, encrypting
+/// all data written.
+pub struct CryptoWriter<'a> {
+    writer: &'a mut dyn Write,
+    buf: Vec<u8>,
+    sealkey: SealingKey<RandomNonceSequence>,
+    failed: bool,
+}
+
+/// A cryptographic stream wrapper.
+/// Wraps a plain dyn Read, and itself implements Read, decrypting
+/// and verifying all data read.
+pub struct CryptoReader<'a> {
+    reader: &'a mut dyn Read,
+    buf: Vec<u8>,
+    offset: usize,
+    openingkey: OpeningKey<RandomNonceSequence>,
+}
+
+impl<'a> CryptoReader<'a> {
+    /// Create a new CryptoReader, wrapping the given Read . Decrypts using the given
+    /// 32 byte cryptographic key.
+    /// Crypto is 256 bit AES GCM
+    pub fn new(reader: &'a mut dyn Read, key_bytes: [u8; 32]) -> Result<CryptoReader<'a>, SavefileError> {
+        let unboundkey = UnboundKey::new(&AES_256_GCM, &key_bytes).unwrap();
+
+        let nonce_sequence = RandomNonceSequence::deserialize(reader)?;
+        let openingkey = OpeningKey::new(unboundkey, nonce_sequence);
+
+        Ok(CryptoReader {
+            reader,
+            offset: 0,
+            buf: Vec::new(),
+            openingkey,
+        })
+    }
+}
+
+const CRYPTO_BUFSIZE: usize = 100_000;
+
+impl<'a> Drop for CryptoWriter<'a> {
+    fn drop(&mut self) {
+        self.flush().expect("The implicit flush in the Drop of CryptoWriter failed. This causes this panic. If you want to be able to handle this, make sure to call flush() manually. If a manual flush has failed, Drop won't panic.");
+    }
+}
+impl<'a> CryptoWriter<'a> {
+    /// Create a new CryptoWriter, wrapping the given Write . Encrypts using the given
+    /// 32 byte cryptographic key.
+    /// Crypto is 256 bit AES GCM
+    pub fn new(writer: &'a mut dyn Write, key_bytes: [u8; 32]) -> Result<CryptoWriter<'a>, SavefileError> {
+        let unboundkey = UnboundKey::new(&AES_256_GCM, &key_bytes).unwrap();
+        let nonce_sequence = RandomNonceSequence::new();
+        nonce_sequence.serialize(writer)?;
+        let sealkey = SealingKey::new(unboundkey, nonce_sequence);
+        Ok(CryptoWriter {
+            writer,
+            buf: Vec::new(),
+            sealkey,
+            failed: false,
+        })
+    }
+    /// Data is encrypted in chunks. Calling this unconditionally finalizes a chunk, actually emitting
+    /// data to the underlying dyn Write. When later reading data, an entire chunk must be read
+    /// from file before any plaintext is produced.
+    pub fn flush_final(mut self) -> Result<(), SavefileError> {
+        if self.failed {
+            panic!("Call to failed CryptoWriter");
+        }
+        self.flush()?;
+        Ok(())
+    }
+}
+impl<'a> Read for CryptoReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        loop {
+            if buf.len() <= self.buf.len() - self.offset {
+                buf.clone_from_slice(&self.buf[self.offset..self.offset + buf.len()]);
+                self.offset += buf.len();
+                return Ok(buf.len());
+            }
+
+            {
+                let oldlen = self.buf.len();
+                let newlen = self.buf.len() - self.offset;
+                self.buf.copy_within(self.offset..oldlen, 0);
+                self.buf.resize(newlen, 0);
+                self.offset = 0;
+            }
+            let mut sizebuf = [0; 8];
+            let mut sizebuf_bytes_read = 0;
+            loop {
+                match self.reader.read(&mut sizebuf[sizebuf_bytes_read..]) {
+                    Ok(gotsize) => {
+                        if gotsize == 0 {
+                            if sizebuf_bytes_read == 0 {
+                                let cur_content_size = self.buf.len() - self.offset;
+                                buf[0..cur_content_size]
+                                    .clone_from_slice(&self.buf[self.offset..self.offset + cur_content_size]);
+                                self.offset += cur_content_size;
+                                return Ok(cur_content_size);
+                            } else {
+                                return Err(Error::new(ErrorKind::UnexpectedEof, "Unexpected EOF"));
+                            }
+                        } else {
+                            sizebuf_bytes_read += gotsize;
+                            assert!(sizebuf_bytes_read <= 8);
+                        }
+                    }
+                    Err(err) => return Err(err),
+                }
+                if sizebuf_bytes_read == 8 {
+                    break;
+                }
+            }
+            use byteorder::ByteOrder;
+            let curlen = byteorder::LittleEndian::read_u64(&sizebuf) as usize;
+
+            if curlen > CRYPTO_BUFSIZE + 16 {
+                return Err(Error::new(ErrorKind::Other, "Cryptography error"));
+            }
+            let orglen = self.buf.len();
+            self.buf.resize(orglen + curlen, 0);
+
+            self.reader.read_exact(&mut self.buf[orglen..orglen + curlen])?;
+
+            match self
+                .openingkey
+                .open_in_place(aead::Aad::empty(), &mut self.buf[orglen..])
+            {
+                Ok(_) => {}
+                Err(_) => {
+                    return Err(Error::new(ErrorKind::Other, "Cryptography error"));
+                }
+            }
+            self.buf.resize(self.buf.len() - 16, 0);
+        }
+    }
+}
+impl<'a> Write for CryptoWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if self.failed {
+            panic!("Call to failed CryptoWriter");
+        }
+        self.buf.extend(buf);
+        if self.buf.len() > CRYPTO_BUFSIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Writes any non-written buffered bytes to the underlying stream.
+    /// If this fails, there is no recovery. The buffered data will have been
+    /// lost.
+    fn flush(&mut self) -> Result<(), Error> {
+        self.failed = true;
+        let mut offset = 0;
+
+        let mut tempbuf = Vec::new();
+        if self.buf.len() > CRYPTO_BUFSIZE {
+            tempbuf = Vec::<u8>::with_capacity(CRYPTO_BUFSIZE + 16);
+        }
+
+        while self.buf.len() > offset {
+            let curbuf;
+            if offset == 0 && self.buf.len() <= CRYPTO_BUFSIZE {
+                curbuf = &mut self.buf;
+            } else {
+                let chunksize = (self.buf.len() - offset).min(CRYPTO_BUFSIZE);
+                tempbuf.resize(chunksize, 0u8);
+                tempbuf.clone_from_slice(&self.buf[offset..offset + chunksize]);
+                curbuf = &mut tempbuf;
+            }
+            let expected_final_len = curbuf.len() as u64 + 16;
+            debug_assert!(expected_final_len <= CRYPTO_BUFSIZE as u64 + 16);
+
+            self.writer.write_u64::<LittleEndian>(expected_final_len)?; //16 for the tag
+            match self.sealkey.seal_in_place_append_tag(aead::Aad::empty(), curbuf) {
+                Ok(_) => {}
+                Err(_) => {
+                    return Err(Error::new(ErrorKind::Other, "Cryptography error"));
+                }
+            }
+            debug_assert!(curbuf.len() == expected_final_len as usize, "The size of the TAG generated by the AES 256 GCM in ring seems to have changed! This is very unexpected. File a bug on the savefile-crate");
+
+            self.writer.write_all(&curbuf[..])?;
+            self.writer.flush()?;
+            offset += curbuf.len() - 16;
+            curbuf.resize(curbuf.len() - 16, 0);
+        }
+        self.buf.clear();
+        self.failed = false;
+        Ok(())
+    }
+}
+
+/// Size of the plaintext chunks of an encrypted payload. Every chunk is sealed separately,
+/// so that the payload can be decrypted and verified without first reading it all into memory.
+const AEAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Size of the random salt stored after the header of an encrypted payload.
+const AEAD_SALT_LENGTH: usize = 32;
+
+/// Info string of the HKDF expansion of the file key.
+const AEAD_KEY_INFO: &[u8] = b"savefile encrypted payload";
+
+/// Build the nonce of a chunk of an encrypted payload.
+/// Each nonce consists of the big endian chunk counter and a byte which is 1 for the last
+/// chunk. This way, chunks cannot be reordered, and the payload cannot be truncated at a
+/// chunk boundary, without decryption failing. The nonces are the same in every file, which
+/// is safe since every file is encrypted with its own key, see [aead_key].
+fn aead_nonce(counter: u32, last: bool) -> Nonce {
+    let mut bytes = [0u8; aead::NONCE_LEN];
+    bytes[..4].copy_from_slice(&counter.to_be_bytes());
+    bytes[aead::NONCE_LEN - 1] = if last { 1 } else { 0 };
+    Nonce::assume_unique_for_key(bytes)
+}
+
+/// The key of a single file, derived from the given key and the random salt of the file
+/// using HKDF-SHA256. Unlike random nonces, which may collide after a few billion files,
+/// salts of 256 bits never collide in practice.
+fn aead_key(key: &[u8; 32], salt: &[u8; AEAD_SALT_LENGTH]) -> aead::LessSafeKey {
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(key);
+    let okm = prk
+        .expand(&[AEAD_KEY_INFO], &aead::CHACHA20_POLY1305)
+        .expect("HKDF-SHA256 can expand 32 byte keys");
+    aead::LessSafeKey::new(UnboundKey::from(okm))
+}
+
+/// A Write-wrapper encrypting everything written using ChaCha20-Poly1305.
+/// The data is written as a sequence of chunks, each prefixed by its little endian u32 length.
+/// All chunks but the last hold exactly [AEAD_CHUNK_SIZE] bytes of plaintext.
+/// [AeadWriter::finish] must be called after all data has been written.
+pub(crate) struct AeadWriter<'a> {
+    writer: &'a mut dyn Write,
+    key: aead::LessSafeKey,
+    aad: Vec<u8>,
+    counter: u32,
+    buf: Vec<u8>,
+}
+
+impl<'a> AeadWriter<'a> {
+    /// Generates a random salt and writes it to the writer.
+    /// `aad` is authenticated along with every chunk, but not encrypted.
+    pub(crate) fn new(
+        writer: &'a mut dyn Write,
+        key: &[u8; 32],
+        aad: Vec<u8>,
+    ) -> Result<AeadWriter<'a>, SavefileError> {
+        let mut salt = [0u8; AEAD_SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        writer.write_all(&salt)?;
+        Ok(AeadWriter {
+            writer,
+            key: aead_key(key, &salt),
+            aad,
+            counter: 0,
+            buf: Vec::with_capacity(AEAD_CHUNK_SIZE + aead::MAX_TAG_LEN),
+        })
+    }
+
+    fn seal_chunk(&mut self, last: bool) -> Result<(), Error> {
+        let nonce = aead_nonce(self.counter, last);
+        self.key
+            .seal_in_place_append_tag(nonce, aead::Aad::from(&self.aad), &mut self.buf)
+            .map_err(|_| Error::new(ErrorKind::Other, "Encryption failed"))?;
+        self.writer.write_u32::<LittleEndian>(self.buf.len() as u32)?;
+        self.writer.write_all(&self.buf)?;
+        self.buf.clear();
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "Too much data for a single encrypted payload"))?;
+        Ok(())
+    }
+
+    /// Write the last chunk. Without it, the payload can't be decrypted.
+    pub(crate) fn finish(mut self) -> Result<(), SavefileError> {
+        self.seal_chunk(true)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<'a> Write for AeadWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let len = buf.len().min(AEAD_CHUNK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() == AEAD_CHUNK_SIZE {
+            self.seal_chunk(false)?;
+        }
+        Ok(len)
+    }
+    /// Flushes the underlying writer. Buffered data is only written once a
+    /// complete chunk is available, or when the writer is finished.
+    fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()
+    }
+}
+
+/// A Read-wrapper decrypting and verifying data written by [AeadWriter].
+/// No plaintext is returned from a chunk before the whole chunk has been verified.
+pub(crate) struct AeadReader<'a> {
+    reader: &'a mut dyn Read,
+    key: aead::LessSafeKey,
+    aad: Vec<u8>,
+    counter: u32,
+    buf: Vec<u8>,
+    offset: usize,
+    done: bool,
+}
+
+impl<'a> AeadReader<'a> {
+    pub(crate) fn new(reader: &'a mut dyn Read, key: &[u8; 32], aad: Vec<u8>) -> Result<AeadReader<'a>, SavefileError> {
+        let mut salt = [0u8; AEAD_SALT_LENGTH];
+        reader.read_exact(&mut salt)?;
+        Ok(AeadReader {
+            reader,
+            key: aead_key(key, &salt),
+            aad,
+            counter: 0,
+            buf: Vec::new(),
+            offset: 0,
+            done: false,
+        })
+    }
+
+    fn open_chunk(&mut self) -> Result<(), Error> {
+        let len = self.reader.read_u32::<LittleEndian>()? as usize;
+        let tag_len = aead::CHACHA20_POLY1305.tag_len();
+        if len < tag_len || len > AEAD_CHUNK_SIZE + tag_len {
+            return Err(Error::new(ErrorKind::InvalidData, "Corrupt encrypted data"));
+        }
+        // Only the last chunk is shorter than the chunk size
+        let last = len < AEAD_CHUNK_SIZE + tag_len;
+        self.buf.resize(len, 0);
+        self.reader.read_exact(&mut self.buf)?;
+        let nonce = aead_nonce(self.counter, last);
+        let plaintext_len = self
+            .key
+            .open_in_place(nonce, aead::Aad::from(&self.aad), &mut self.buf)
+            .map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "Decryption failed. The key is wrong, or the data has been corrupted or tampered with.",
+                )
+            })?
+            .len();
+        self.buf.truncate(plaintext_len);
+        self.offset = 0;
+        self.counter = self.counter.wrapping_add(1);
+        self.done = last;
+        Ok(())
+    }
+
+    /// Read and verify the remaining chunks, so that a truncated payload is detected
+    /// even if the end of the data wasn't needed.
+    pub(crate) fn finish(&mut self) -> Result<(), SavefileError> {
+        while !self.done {
+            self.open_chunk()?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Read for AeadReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        while self.offset == self.buf.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.open_chunk()?;
+        }
+        let len = buf.len().min(self.buf.len() - self.offset);
+        buf[..len].copy_from_slice(&self.buf[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+/// Like [crate::save], except the payload is encrypted and authenticated using
+/// ChaCha20-Poly1305 with the given 256 bit key. The header is not encrypted, but
+/// it is authenticated, so the version and format flags can't be altered undetected.
+/// A random salt is generated for every call and stored after the header, and the payload
+/// is encrypted with a key derived from the given key and the salt, so the same key can
+/// safely be used for many files.
+///
+/// Use [crate::load_encrypted] with the same key to load the data.
+/// This is unrelated to [crate::save_encrypted_file], which derives its key from a password.
+pub fn save_encrypted<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    key: &[u8; 32],
+) -> Result<(), SavefileError> {
+    Serializer::save_impl(
+        writer,
+        version,
+        data,
+        SaveOptions {
+            encryption_key: Some(key),
+            ..SaveOptions::new(true)
+        },
+    )?;
+    Ok(())
+}
+
+/// Load data saved by [crate::save_encrypted], using the same key.
+/// Fails if the key is wrong, if the data has been tampered with or truncated,
+/// or if the data was not encrypted at all.
+pub fn load_encrypted<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+    key: &[u8; 32],
+) -> Result<T, SavefileError> {
+    Deserializer::load_impl::<T>(
+        reader,
+        version,
+        LoadOptions {
+            encryption_key: Some(key),
+            ..LoadOptions::new(true)
+        },
+    )
+}
+
+/// Like [crate::save_file], except encrypts the data with AES256, using the SHA256 hash
+/// of the password as key.
+#[cfg(feature = "fs")]
+pub fn save_encrypted_file<T: WithSchema + Serialize>(
+    filepath: impl AsRef<Path>,
+    version: u32,
+    data: &T,
+    password: &str,
+) -> Result<(), SavefileError> {
+    use ring::digest;
+    let actual = digest::digest(&digest::SHA256, password.as_bytes());
+    let mut key = [0u8; 32];
+    let password_hash = actual.as_ref();
+    assert_eq!(password_hash.len(), key.len(), "A SHA256 sum must be 32 bytes");
+    key.clone_from_slice(password_hash);
+
+    let mut f = File::create(filepath)?;
+    let mut writer = CryptoWriter::new(&mut f, key)?;
+
+    Serializer::save::<T>(&mut writer, version, data, true)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Like [crate::load_file], except it expects the file to be an encrypted file previously stored using
+/// [crate::save_encrypted_file].
+#[cfg(feature = "fs")]
+pub fn load_encrypted_file<T: WithSchema + Deserialize>(
+    filepath: impl AsRef<Path>,
+    version: u32,
+    password: &str,
+) -> Result<T, SavefileError> {
+    use ring::digest;
+    let actual = digest::digest(&digest::SHA256, password.as_bytes());
+    let mut key = [0u8; 32];
+    let password_hash = actual.as_ref();
+    assert_eq!(password_hash.len(), key.len(), "A SHA256 sum must be 32 bytes");
+    key.clone_from_slice(password_hash);
+
+    let mut f = File::open(filepath)?;
+    let mut reader = CryptoReader::new(&mut f, key).unwrap();
+    Deserializer::load::<T>(&mut reader, version)
+}
@@ -0,0 +1,487 @@
+//! C functions for reading savefile data, so that programs written in other languages can
+//! parse files written by Rust programs. The declarations are in `include/savefile.h`.
+//!
+//! A [SavefileReader] reads from a buffer holding an uncompressed, unencrypted file, such as one
+//! written by [crate::save]. The header is read first, then the schema (if any), and then the
+//! data, one primitive or length at a time, in the order given by the schema. Every read
+//! function returns 0 on success, and -1 on failure, in which case [savefile_reader_error]
+//! returns the error message.
+
+use crate::{CompressionMethod, Deserializer, Encoding, SavefileError, SavefileHeader, Schema};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+
+/// Kind of a struct schema node. Its children are the fields.
+pub const SAVEFILE_SCHEMA_STRUCT: u32 = 1;
+/// Kind of an enum schema node. Its children are the variants, and [savefile_schema_count] is
+/// the size of the discriminators in bytes, 1, 2 or 4.
+pub const SAVEFILE_SCHEMA_ENUM: u32 = 2;
+/// Kind of a primitive schema node, see [savefile_schema_primitive].
+pub const SAVEFILE_SCHEMA_PRIMITIVE: u32 = 3;
+/// Kind of a vector schema node: a length followed by that many items. The only child is the item.
+pub const SAVEFILE_SCHEMA_VECTOR: u32 = 4;
+/// Kind of a schema node of unknown contents.
+pub const SAVEFILE_SCHEMA_UNDEFINED: u32 = 5;
+/// Kind of a schema node without any data.
+pub const SAVEFILE_SCHEMA_ZERO_SIZE: u32 = 6;
+/// Kind of an option schema node: a u8 which is 1 if the only child follows.
+pub const SAVEFILE_SCHEMA_OPTION: u32 = 7;
+/// Kind of an array schema node: [savefile_schema_count] items. The only child is the item.
+pub const SAVEFILE_SCHEMA_ARRAY: u32 = 8;
+/// Kind of a bit-packed schema node. The only child is the packed type.
+pub const SAVEFILE_SCHEMA_BIT_PACKED: u32 = 9;
+/// Kind of an enum variant. Its children are the fields, and [savefile_schema_count] is its
+/// discriminator, which precedes the fields and has the size given by the enum.
+pub const SAVEFILE_SCHEMA_VARIANT: u32 = 10;
+
+/// Reads savefile data from a buffer. Created by [savefile_reader_new].
+pub struct SavefileReader {
+    data: *const u8,
+    len: usize,
+    position: usize,
+    file_version: u32,
+    encoding: Encoding,
+    has_checksum: bool,
+    /// The part of the header covered by the checksum of the schema, until it is verified
+    checksummed_header: Vec<u8>,
+    has_schema_history: bool,
+    interned_strings: Vec<String>,
+    string: String,
+    error: CString,
+}
+
+/// The header of a file, as returned by [savefile_read_header].
+#[repr(C)]
+pub struct SavefileHeaderInfo {
+    /// The version of the data in the file
+    pub version: u32,
+    /// True if the schema follows the header, see [savefile_read_schema]
+    pub has_schema: bool,
+    /// True if a hash of the schema follows the header, see [savefile_read_schema_hash]
+    pub has_schema_hash: bool,
+    /// True if the schema is followed by a checksum. It is verified by [savefile_read_schema]
+    /// and [savefile_read_schema_hash]. The data is followed by a checksum too, which isn't verified.
+    pub has_checksum: bool,
+}
+
+/// A schema returned by [savefile_read_schema], or one of its children.
+pub struct SavefileSchema {
+    kind: u32,
+    primitive: u32,
+    name: CString,
+    count: u64,
+    children: Vec<(CString, SavefileSchema)>,
+}
+
+fn c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', "")).unwrap_or_default()
+}
+
+impl SavefileSchema {
+    fn new(schema: &Schema) -> SavefileSchema {
+        let mut node = SavefileSchema {
+            kind: 0,
+            primitive: 0,
+            name: CString::default(),
+            count: 0,
+            children: Vec::new(),
+        };
+        let item = |schema: &Schema| vec![(CString::default(), SavefileSchema::new(schema))];
+        match schema {
+            Schema::Struct(schema_struct) => {
+                node.kind = SAVEFILE_SCHEMA_STRUCT;
+                node.name = c_string(&schema_struct.dbg_name);
+                node.children = schema_struct
+                    .fields
+                    .iter()
+                    .map(|field| (c_string(&field.name), SavefileSchema::new(&field.value)))
+                    .collect();
+            }
+            Schema::Enum(schema_enum) => {
+                node.kind = SAVEFILE_SCHEMA_ENUM;
+                node.name = c_string(&schema_enum.dbg_name);
+                node.count = schema_enum.discriminant_size as u64;
+                node.children = schema_enum
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        let fields = variant
+                            .fields
+                            .iter()
+                            .map(|field| (c_string(&field.name), SavefileSchema::new(&field.value)))
+                            .collect();
+                        let variant_node = SavefileSchema {
+                            kind: SAVEFILE_SCHEMA_VARIANT,
+                            primitive: 0,
+                            name: c_string(&variant.name),
+                            count: variant.discriminator as u64,
+                            children: fields,
+                        };
+                        (c_string(&variant.name), variant_node)
+                    })
+                    .collect();
+            }
+            Schema::Primitive(primitive) => {
+                node.kind = SAVEFILE_SCHEMA_PRIMITIVE;
+                node.primitive = primitive.to_u8() as u32;
+                node.name = c_string(primitive.name());
+            }
+            Schema::Vector(item_schema) => {
+                node.kind = SAVEFILE_SCHEMA_VECTOR;
+                node.children = item(item_schema);
+            }
+            Schema::Undefined => node.kind = SAVEFILE_SCHEMA_UNDEFINED,
+            Schema::ZeroSize => node.kind = SAVEFILE_SCHEMA_ZERO_SIZE,
+            Schema::SchemaOption(item_schema) => {
+                node.kind = SAVEFILE_SCHEMA_OPTION;
+                node.children = item(item_schema);
+            }
+            Schema::Array(array) => {
+                node.kind = SAVEFILE_SCHEMA_ARRAY;
+                node.count = array.count as u64;
+                node.children = item(&array.item_type);
+            }
+            Schema::BitPacked(item_schema) => {
+                node.kind = SAVEFILE_SCHEMA_BIT_PACKED;
+                node.children = item(item_schema);
+            }
+        }
+        node
+    }
+}
+
+impl SavefileReader {
+    /// Run `read` on the unread part of the buffer, and advance past what it read.
+    fn read<T>(
+        &mut self,
+        read: impl FnOnce(&mut Deserializer<&[u8]>) -> Result<T, SavefileError>,
+    ) -> Result<T, SavefileError> {
+        let data = if self.len == 0 {
+            &[][..]
+        } else {
+            unsafe { std::slice::from_raw_parts(self.data, self.len) }
+        };
+        let mut rest = &data[self.position..];
+        let mut deserializer = Deserializer::new_raw(&mut rest);
+        deserializer.file_version = self.file_version;
+        deserializer.memory_version = self.file_version;
+        deserializer.encoding = self.encoding;
+        deserializer.interned_strings = std::mem::take(&mut self.interned_strings);
+        let result = read(&mut deserializer);
+        self.interned_strings = std::mem::take(&mut deserializer.interned_strings);
+        self.position = self.len - rest.len();
+        result
+    }
+
+    /// Verify the checksum of everything read since `start`, if the file has checksums
+    fn verify_checksum(&mut self, start: usize) -> Result<(), SavefileError> {
+        if !self.has_checksum {
+            return Ok(());
+        }
+        let data = unsafe { std::slice::from_raw_parts(self.data, self.len) };
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&std::mem::take(&mut self.checksummed_header));
+        hasher.update(&data[start..self.position]);
+        let actual = hasher.finalize();
+        let expected = self.read(|deserializer| Ok(deserializer.reader.read_u32::<LittleEndian>()?))?;
+        if expected != actual {
+            return Err(SavefileError::ChecksumMismatch { expected, actual });
+        }
+        Ok(())
+    }
+
+    fn status(&mut self, result: Result<(), SavefileError>) -> c_int {
+        match result {
+            Ok(()) => 0,
+            Err(err) => {
+                self.error = c_string(&err.to_string());
+                -1
+            }
+        }
+    }
+}
+
+/// Create a reader of the `len` bytes at `data`. Free it with [savefile_reader_free].
+///
+/// # Safety
+/// `data` must point to `len` readable bytes, which must stay valid and unchanged until
+/// the reader is freed.
+#[no_mangle]
+pub unsafe extern "C" fn savefile_reader_new(data: *const u8, len: usize) -> *mut SavefileReader {
+    Box::into_raw(Box::new(SavefileReader {
+        data,
+        len,
+        position: 0,
+        file_version: 0,
+        encoding: Encoding::default(),
+        has_checksum: false,
+        checksummed_header: Vec::new(),
+        has_schema_history: false,
+        interned_strings: Vec::new(),
+        string: String::new(),
+        error: CString::default(),
+    }))
+}
+
+/// Free a reader created by [savefile_reader_new].
+///
+/// # Safety
+/// `reader` must have been returned by [savefile_reader_new], and not been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn savefile_reader_free(reader: *mut SavefileReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// The message of the last error, valid until the next failing call.
+///
+/// # Safety
+/// `reader` must be a reader returned by [savefile_reader_new].
+#[no_mangle]
+pub unsafe extern "C" fn savefile_reader_error(reader: *const SavefileReader) -> *const c_char {
+    (*reader).error.as_ptr()
+}
+
+/// The number of bytes read so far.
+///
+/// # Safety
+/// `reader` must be a reader returned by [savefile_reader_new].
+#[no_mangle]
+pub unsafe extern "C" fn savefile_reader_position(reader: *const SavefileReader) -> usize {
+    (*reader).position
+}
+
+/// Read the header of the file. This must be the first read. The following data is read
+/// with the version and encoding given by the header. Compressed and encrypted files,
+/// and files with version namespaces (see [crate::VersionNamespace]), are not supported.
+///
+/// # Safety
+/// `reader` must be a reader returned by [savefile_reader_new], and `header` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn savefile_read_header(reader: *mut SavefileReader, header: *mut SavefileHeaderInfo) -> c_int {
+    let reader = &mut *reader;
+    let result = reader
+        .read(|deserializer| SavefileHeader::read(&mut *deserializer.reader))
+        .and_then(|file_header| {
+            if file_header.encrypted || file_header.compression != CompressionMethod::None {
+                return Err(SavefileError::GeneralError {
+                    msg: "Compressed and encrypted files can't be read by the C interface".to_string(),
+                });
+            }
+            if file_header.format_version >= crate::NAMESPACES_FORMAT_VERSION {
+                return Err(SavefileError::GeneralError {
+                    msg: "Files with version namespaces can't be read by the C interface".to_string(),
+                });
+            }
+            Ok((file_header.authenticated_bytes()?, file_header))
+        });
+    match result {
+        Ok((checksummed_header, file_header)) => {
+            reader.checksummed_header = checksummed_header;
+            reader.file_version = file_header.version;
+            reader.encoding = file_header.encoding;
+            reader.has_checksum = file_header.has_checksum;
+            reader.has_schema_history = file_header.has_schema_history;
+            *header = SavefileHeaderInfo {
+                version: file_header.version,
+                has_schema: file_header.has_schema.unwrap_or(!file_header.has_schema_hash),
+                has_schema_hash: file_header.has_schema_hash,
+                has_checksum: file_header.has_checksum,
+            };
+            0
+        }
+        Err(err) => reader.status(Err(err)),
+    }
+}
+
+/// Read the schema following the header. Returns null on failure. Free the schema
+/// with [savefile_schema_free].
+///
+/// # Safety
+/// `reader` must be a reader returned by [savefile_reader_new].
+#[no_mangle]
+pub unsafe extern "C" fn savefile_read_schema(reader: *mut SavefileReader) -> *mut SavefileSchema {
+    let reader = &mut *reader;
+    let start = reader.position;
+    let (version, has_schema_history) = (reader.file_version, reader.has_schema_history);
+    let result = reader
+        .read(|deserializer| crate::read_stored_schema(&mut *deserializer.reader, version, has_schema_history))
+        .and_then(|schema| reader.verify_checksum(start).map(|()| schema));
+    match result {
+        Ok(schema) => Box::into_raw(Box::new(SavefileSchema::new(&schema))),
+        Err(err) => {
+            reader.status(Err(err));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Read the hash of the schema following the header, in files saved with a schema hash
+/// instead of a schema.
+///
+/// # Safety
+/// `reader` must be a reader returned by [savefile_reader_new], and `hash` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn savefile_read_schema_hash(reader: *mut SavefileReader, hash: *mut u64) -> c_int {
+    let reader = &mut *reader;
+    let start = reader.position;
+    let result = reader
+        .read(|deserializer| Ok(deserializer.reader.read_u64::<LittleEndian>()?))
+        .and_then(|value| reader.verify_checksum(start).map(|()| value));
+    let result = result.map(|value| *hash = value);
+    reader.status(result)
+}
+
+macro_rules! ffi_read {
+    ($($name:ident, $ty:ty, $method:ident;)*) => {
+        $(
+            #[doc = concat!("Read a `", stringify!($ty), "`.")]
+            ///
+            /// # Safety
+            /// `reader` must be a reader returned by [savefile_reader_new], and `value` a valid pointer.
+            #[no_mangle]
+            pub unsafe extern "C" fn $name(reader: *mut SavefileReader, value: *mut $ty) -> c_int {
+                let reader = &mut *reader;
+                let result = reader.read(|deserializer| deserializer.$method()).map(|read| *value = read);
+                reader.status(result)
+            }
+        )*
+    };
+}
+
+ffi_read! {
+    savefile_read_bool, bool, read_bool;
+    savefile_read_u8, u8, read_u8;
+    savefile_read_i8, i8, read_i8;
+    savefile_read_u16, u16, read_u16;
+    savefile_read_i16, i16, read_i16;
+    savefile_read_u32, u32, read_u32;
+    savefile_read_i32, i32, read_i32;
+    savefile_read_u64, u64, read_u64;
+    savefile_read_i64, i64, read_i64;
+    savefile_read_f32, f32, read_f32;
+    savefile_read_f64, f64, read_f64;
+}
+
+/// Read the length of a vector, string or map, as encoded by the file.
+///
+/// # Safety
+/// `reader` must be a reader returned by [savefile_reader_new], and `length` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn savefile_read_length(reader: *mut SavefileReader, length: *mut u64) -> c_int {
+    let reader = &mut *reader;
+    let result = reader
+        .read(|deserializer| deserializer.read_length())
+        .map(|read| *length = read);
+    reader.status(result)
+}
+
+/// Read a string. `data` is set to its UTF-8 bytes, which are not null terminated, and
+/// `len` to their number. The bytes are valid until the next call with the reader.
+///
+/// # Safety
+/// `reader` must be a reader returned by [savefile_reader_new], and `data` and `len` valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn savefile_read_string(
+    reader: *mut SavefileReader,
+    data: *mut *const u8,
+    len: *mut usize,
+) -> c_int {
+    let reader = &mut *reader;
+    let mut string = std::mem::take(&mut reader.string);
+    let result = reader.read(|deserializer| deserializer.read_string_into(&mut string));
+    reader.string = string;
+    if result.is_ok() {
+        *data = reader.string.as_ptr();
+        *len = reader.string.len();
+    }
+    reader.status(result)
+}
+
+/// Free a schema returned by [savefile_read_schema].
+///
+/// # Safety
+/// `schema` must have been returned by [savefile_read_schema], and not been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn savefile_schema_free(schema: *mut SavefileSchema) {
+    if !schema.is_null() {
+        drop(Box::from_raw(schema));
+    }
+}
+
+/// The kind of the schema node, one of the `SAVEFILE_SCHEMA_` constants.
+///
+/// # Safety
+/// `schema` must be a schema returned by [savefile_read_schema] or [savefile_schema_child].
+#[no_mangle]
+pub unsafe extern "C" fn savefile_schema_kind(schema: *const SavefileSchema) -> u32 {
+    (*schema).kind
+}
+
+/// The primitive type of a primitive schema node: 1 = i8, 2 = u8, 3 = i16, 4 = u16, 5 = i32,
+/// 6 = u32, 7 = i64, 8 = u64, 9 = string, 10 = f32, 11 = f64, 12 = bool, 13 = canary (a u32).
+/// 0 for other nodes.
+///
+/// # Safety
+/// `schema` must be a schema returned by [savefile_read_schema] or [savefile_schema_child].
+#[no_mangle]
+pub unsafe extern "C" fn savefile_schema_primitive(schema: *const SavefileSchema) -> u32 {
+    (*schema).primitive
+}
+
+/// The name of the type of a struct or enum, of a variant, or of a primitive. Empty for other nodes.
+///
+/// # Safety
+/// `schema` must be a schema returned by [savefile_read_schema] or [savefile_schema_child].
+#[no_mangle]
+pub unsafe extern "C" fn savefile_schema_name(schema: *const SavefileSchema) -> *const c_char {
+    (*schema).name.as_ptr()
+}
+
+/// The length of an array, the size of the discriminators of an enum, or the discriminator of
+/// a variant. 0 for other nodes.
+///
+/// # Safety
+/// `schema` must be a schema returned by [savefile_read_schema] or [savefile_schema_child].
+#[no_mangle]
+pub unsafe extern "C" fn savefile_schema_count(schema: *const SavefileSchema) -> u64 {
+    (*schema).count
+}
+
+/// The number of children of the schema node.
+///
+/// # Safety
+/// `schema` must be a schema returned by [savefile_read_schema] or [savefile_schema_child].
+#[no_mangle]
+pub unsafe extern "C" fn savefile_schema_child_count(schema: *const SavefileSchema) -> usize {
+    (*schema).children.len()
+}
+
+/// The child with the given index, or null if there is none. It is freed along with its root.
+///
+/// # Safety
+/// `schema` must be a schema returned by [savefile_read_schema] or [savefile_schema_child].
+#[no_mangle]
+pub unsafe extern "C" fn savefile_schema_child(schema: *const SavefileSchema, index: usize) -> *const SavefileSchema {
+    let schema = &*schema;
+    match schema.children.get(index) {
+        Some((_, child)) => child,
+        None => std::ptr::null(),
+    }
+}
+
+/// The name of the field or variant with the given index, or null if there is none.
+/// Empty for the items of vectors, arrays and options.
+///
+/// # Safety
+/// `schema` must be a schema returned by [savefile_read_schema] or [savefile_schema_child].
+#[no_mangle]
+pub unsafe extern "C" fn savefile_schema_child_name(schema: *const SavefileSchema, index: usize) -> *const c_char {
+    let schema = &*schema;
+    match schema.children.get(index) {
+        Some((name, _)) => name.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
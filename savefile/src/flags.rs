@@ -0,0 +1,148 @@
+//! Saving of flags types generated by the `bitflags!` macro of the bitflags crate.
+
+use crate::{Deserialize, Deserializer, SavefileError, Schema, Serialize, Serializer, WithSchema};
+use bitflags::parser::{to_writer, WriteHex};
+use bitflags::Flags;
+use std::io::{Read, Write};
+
+/// What to do when loading flags whose bits are not all defined by the flags type, for
+/// example because the file was saved by a newer version of the program, with more flags.
+/// Given to [crate::savefile_bitflags].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownBits {
+    /// Loading fails
+    Error,
+    /// The unknown bits are removed, keeping the flags which are defined
+    Truncate,
+}
+
+#[doc(hidden)]
+pub fn flags_schema<T: Flags>(version: u32) -> Schema
+where
+    T::Bits: WithSchema,
+{
+    T::Bits::schema(version)
+}
+
+#[doc(hidden)]
+pub fn serialize_flags<T: Flags>(flags: &T, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError>
+where
+    T::Bits: Serialize,
+{
+    flags.bits().serialize(serializer)
+}
+
+#[doc(hidden)]
+pub fn deserialize_flags<T: Flags>(
+    deserializer: &mut Deserializer<impl Read>,
+    unknown_bits: UnknownBits,
+) -> Result<T, SavefileError>
+where
+    T::Bits: Deserialize + WriteHex,
+{
+    let bits = T::Bits::deserialize(deserializer)?;
+    match unknown_bits {
+        UnknownBits::Truncate => Ok(T::from_bits_truncate(bits)),
+        UnknownBits::Error => T::from_bits(bits).ok_or_else(|| {
+            let unknown = T::from_bits_retain(bits).difference(T::from_bits_truncate(bits));
+            SavefileError::GeneralError {
+                msg: format!(
+                    "Corrupt file - {} has unknown bits {}",
+                    std::any::type_name::<T>(),
+                    introspect_flags(&unknown)
+                ),
+            }
+        }),
+    }
+}
+
+/// The flags in the text format of the bitflags crate, like `READ | WRITE | 0x40`
+#[doc(hidden)]
+pub fn introspect_flags<T: Flags>(flags: &T) -> String
+where
+    T::Bits: WriteHex,
+{
+    let mut text = String::new();
+    // Writing to a String never fails
+    let _ = to_writer(flags, &mut text);
+    text
+}
+
+/// Make a flags type generated by the `bitflags!` macro of the bitflags crate (version 2)
+/// serializable, by implementing [crate::WithSchema], [crate::Serialize], [crate::Deserialize],
+/// [crate::CurrentVersion] and [crate::Introspect] for it. Requires the `bitflags` feature.
+///
+/// The flags are saved as their bits, with the schema of the bits type, so a field can be
+/// changed from an integer to a flags type with the same bits without changing the schema.
+/// Loading flags with bits which are not defined by the type fails, unless
+/// [UnknownBits::Truncate] is given, in which case those bits are removed:
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate savefile_derive;
+/// use savefile::prelude::*;
+/// use savefile::{savefile_bitflags, UnknownBits};
+///
+/// bitflags::bitflags! {
+///     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///     pub struct Permissions: u8 {
+///         const READ = 1;
+///         const WRITE = 2;
+///     }
+/// }
+/// savefile_bitflags!(Permissions, UnknownBits::Truncate);
+///
+/// #[derive(Savefile)]
+/// struct Document {
+///     permissions: Permissions,
+/// }
+///
+/// # fn main() {
+/// let document = Document { permissions: Permissions::READ | Permissions::WRITE };
+/// let saved = save_to_mem(0, &document).unwrap();
+/// let loaded: Document = load_from_mem(&saved, 0).unwrap();
+/// assert_eq!(loaded.permissions, Permissions::READ | Permissions::WRITE);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! savefile_bitflags {
+    ($type:ty) => {
+        $crate::savefile_bitflags!($type, $crate::UnknownBits::Error);
+    };
+    ($type:ty, $unknown_bits:expr) => {
+        impl $crate::WithSchema for $type {
+            fn schema(version: u32) -> $crate::Schema {
+                $crate::flags_schema::<$type>(version)
+            }
+        }
+        impl $crate::CurrentVersion for $type {
+            const SAVEFILE_MAX_VERSION: u32 = 0;
+        }
+        impl $crate::Serialize for $type {
+            fn serialize(
+                &self,
+                serializer: &mut $crate::Serializer<impl ::std::io::Write>,
+            ) -> Result<(), $crate::SavefileError> {
+                $crate::serialize_flags(self, serializer)
+            }
+        }
+        impl $crate::Deserialize for $type {
+            fn deserialize(
+                deserializer: &mut $crate::Deserializer<impl ::std::io::Read>,
+            ) -> Result<Self, $crate::SavefileError> {
+                $crate::deserialize_flags(deserializer, $unknown_bits)
+            }
+        }
+        impl $crate::Introspect for $type {
+            fn introspect_value(&self) -> String {
+                $crate::introspect_flags(self)
+            }
+            fn introspect_child<'a>(&'a self, _index: usize) -> Option<Box<dyn $crate::IntrospectItem<'a> + 'a>> {
+                None
+            }
+            fn introspect_len(&self) -> usize {
+                0
+            }
+        }
+    };
+}
@@ -0,0 +1,157 @@
+//! Golden files, for testing that old saves can still be loaded.
+
+use crate::{
+    load_file, read_schema, save_file, save_file_atomic, schema_differences, schema_of, Deserialize, SavefileError,
+    SchemaOnly, Serialize, WithSchema,
+};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::BufReader;
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// The file name of the golden file of the given name and version
+fn golden_file_name(name: &str, version: u32) -> String {
+    format!("{}_v{}.savefile", name, version)
+}
+
+/// The version of a golden file of the given name, if `file_name` is one
+fn golden_file_version(name: &str, file_name: &str) -> Option<u32> {
+    file_name
+        .strip_prefix(name)?
+        .strip_prefix("_v")?
+        .strip_suffix(".savefile")?
+        .parse()
+        .ok()
+}
+
+/// Check that the files recorded for T in `dir` can still be loaded, and record a new one
+/// for the current version, if there is none.
+///
+/// The files are named `<name>_v<version>.savefile`, and are saved with their schema, by [crate::save_file].
+/// Each file is loaded as a T with the current version `version`. This fails if the schema of T
+/// changed incompatibly since the file was saved, such as if a field was added without a new version.
+/// The file of the current version must also contain `value`. To record a new value, delete the file.
+///
+/// The files are meant to be committed together with the tests using them, so that changes
+/// which break loading old saves are noticed.
+pub fn check_golden<T: WithSchema + Serialize + Deserialize + PartialEq + Debug>(
+    dir: impl AsRef<Path>,
+    name: &str,
+    version: u32,
+    value: &T,
+) -> Result<(), SavefileError> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    let current = dir.join(golden_file_name(name, version));
+    if !current.exists() {
+        save_file(&current, version, value)?;
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_version = match path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(|file_name| golden_file_version(name, file_name))
+        {
+            Some(file_version) => file_version,
+            None => continue,
+        };
+        let fail = |msg: String| SavefileError::GeneralError {
+            msg: format!("Golden file {}: {}", path.display(), msg),
+        };
+        let loaded = load_file::<T>(&path, version).map_err(|err| fail(format!("couldn't be loaded: {}", err)))?;
+        if file_version == version && &loaded != value {
+            return Err(fail(format!(
+                "contains {:?}, but {:?} was expected. Delete the file to record the new value.",
+                loaded, value
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Check that the schema of the given version of T hasn't changed. `expected` is either the
+/// [crate::Schema::stable_hash] of the schema, as 16 hexadecimal digits, or the path of a schema file.
+/// A schema file which doesn't exist is created, with the current schema, and should then be
+/// committed. It can be read by [crate::read_schema].
+///
+/// Once a version has been released, files saved with it exist, and its schema must stay the same
+/// for them to be loaded. The error lists the differences, if the expected schema is in a file.
+/// See [crate::assert_schema_stable], which panics instead of returning the error.
+pub fn check_schema_stable<T: WithSchema>(version: u32, expected: &str) -> Result<(), SavefileError> {
+    let schema = schema_of::<T>(version);
+    let hash = schema.stable_hash();
+    let changed = |details: String| SavefileError::IncompatibleSchema {
+        message: format!(
+            "The schema of version {} of {} has changed{}",
+            version,
+            std::any::type_name::<T>(),
+            details
+        ),
+    };
+    if expected.len() == 16 && expected.chars().all(|c| c.is_ascii_hexdigit()) {
+        if format!("{:016x}", hash) != expected.to_ascii_lowercase() {
+            return Err(changed(format!(", its hash is now {:016x}, not {}", hash, expected)));
+        }
+        return Ok(());
+    }
+    let path = Path::new(expected);
+    if !path.exists() {
+        return save_file_atomic(path, version, &SchemaOnly::<T>(PhantomData));
+    }
+    let (file_version, file_schema) = read_schema(&mut BufReader::new(File::open(path)?))?;
+    if file_version != version {
+        return Err(SavefileError::GeneralError {
+            msg: format!(
+                "The schema file {} is for version {}, not {}",
+                path.display(),
+                file_version,
+                version
+            ),
+        });
+    }
+    if file_schema.stable_hash() != hash {
+        let differences: Vec<String> = schema_differences(&schema, &file_schema)
+            .iter()
+            .map(|difference| difference.to_string())
+            .collect();
+        return Err(changed(format!(
+            " since {} was recorded: {}",
+            path.display(),
+            differences.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Panic if the schema of a released version of a type has changed, see [crate::check_schema_stable].
+/// The expected schema is given by its hash, as 16 hexadecimal digits, or by the path of a schema
+/// file, which is created if it doesn't exist. Use it in a test:
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate savefile_derive;
+/// # extern crate savefile;
+/// #[derive(Savefile)]
+/// struct Config {
+///     name: String,
+///     retries: u32,
+/// }
+///
+/// // In a #[test] function
+/// fn config_v0_is_stable() {
+///     savefile::assert_schema_stable!(Config, 0, "03dedf5e48515a3c");
+/// }
+/// # fn main() {
+/// #     config_v0_is_stable();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! assert_schema_stable {
+    ($t:ty, $version:expr, $expected:expr) => {
+        if let Err(err) = $crate::check_schema_stable::<$t>($version, $expected) {
+            panic!("{}", err);
+        }
+    };
+}
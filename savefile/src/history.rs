@@ -0,0 +1,494 @@
+//! Schemas of all versions of a type, with the versions in which each field exists.
+
+use crate::{
+    schema_of, Deserialize, Deserializer, Field, SavefileError, Schema, SchemaArray, SchemaEnum, SchemaPrimitive,
+    SchemaStruct, Serialize, Serializer, Variant, WithSchema,
+};
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// A field of a struct or enum variant in a [SchemaHistory], and the versions it exists in.
+/// A field whose type changed has one entry for each type, with the same name.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HistoryField {
+    /// Name of the field, or empty for the root of the history
+    pub name: String,
+    /// The first version in which the field exists
+    pub first_version: u32,
+    /// The last version in which the field exists
+    pub last_version: u32,
+    /// The schema of the field in these versions
+    pub value: HistoryNode,
+}
+
+/// An enum variant in a [SchemaHistory], and the versions it exists in.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HistoryVariant {
+    /// Name of the variant
+    pub name: String,
+    /// Discriminator of the variant
+    pub discriminator: u32,
+    /// The first version in which the variant exists
+    pub first_version: u32,
+    /// The last version in which the variant exists
+    pub last_version: u32,
+    /// The fields of the variant, in all versions
+    pub fields: Vec<HistoryField>,
+}
+
+/// The schema of a value in a range of versions. This is like [Schema], except that the
+/// fields of structs and enum variants are those of all the versions, each with the versions
+/// it exists in.
+#[derive(Debug, PartialEq, Clone)]
+pub enum HistoryNode {
+    /// A struct, see [Schema::Struct]
+    Struct {
+        /// Name of the struct
+        name: String,
+        /// The fields of the struct, in all versions
+        fields: Vec<HistoryField>,
+    },
+    /// An enum, see [Schema::Enum]
+    Enum {
+        /// Name of the enum
+        name: String,
+        /// The variants of the enum, in all versions
+        variants: Vec<HistoryVariant>,
+        /// The number of bytes of the discriminator, see [SchemaEnum::discriminant_size]
+        discriminant_size: u8,
+    },
+    /// A primitive, see [Schema::Primitive]
+    Primitive(SchemaPrimitive),
+    /// A vector, see [Schema::Vector]
+    Vector(Box<HistoryNode>),
+    /// A fixed size array, see [Schema::Array]
+    Array {
+        /// Number of items
+        count: usize,
+        /// The schema of the items
+        item: Box<HistoryNode>,
+    },
+    /// An option, see [Schema::SchemaOption]
+    SchemaOption(Box<HistoryNode>),
+    /// A bit-packed value, see [Schema::BitPacked]
+    BitPacked(Box<HistoryNode>),
+    /// A zero sized type, see [Schema::ZeroSize]
+    ZeroSize,
+    /// See [Schema::Undefined]
+    Undefined,
+}
+
+fn new_fields(fields: &[Field], version: u32) -> Vec<HistoryField> {
+    fields
+        .iter()
+        .map(|field| HistoryField {
+            name: field.name.clone(),
+            first_version: version,
+            last_version: version,
+            value: HistoryNode::new(&field.value, version),
+        })
+        .collect()
+}
+
+/// Add the fields of `version` to the fields of the earlier versions. Fields continuing
+/// from the previous version are extended, and other fields are inserted after the field
+/// preceding them in this version, so that the fields of every version keep their order.
+fn merge_fields(history: &mut Vec<HistoryField>, fields: &[Field], version: u32) {
+    let mut position = 0;
+    for field in fields {
+        let continued = history[position..].iter().position(|candidate| {
+            candidate.name == field.name
+                && candidate.last_version.checked_add(1) == Some(version)
+                && candidate.value.accepts(&field.value)
+        });
+        match continued {
+            Some(offset) => {
+                let existing = &mut history[position + offset];
+                existing.last_version = version;
+                existing.value.merge(&field.value, version);
+                position += offset + 1;
+            }
+            None => {
+                history.insert(
+                    position,
+                    HistoryField {
+                        name: field.name.clone(),
+                        first_version: version,
+                        last_version: version,
+                        value: HistoryNode::new(&field.value, version),
+                    },
+                );
+                position += 1;
+            }
+        }
+    }
+}
+
+fn fields_at(fields: &[HistoryField], version: u32) -> Vec<Field> {
+    fields
+        .iter()
+        .filter(|field| field.first_version <= version && version <= field.last_version)
+        .map(|field| Field {
+            name: field.name.clone(),
+            value: Arc::new(field.value.schema_at(version)),
+        })
+        .collect()
+}
+
+impl HistoryNode {
+    /// The history of a schema which only exists in the given version
+    fn new(schema: &Schema, version: u32) -> HistoryNode {
+        match schema {
+            Schema::Struct(schema_struct) => HistoryNode::Struct {
+                name: schema_struct.dbg_name.clone(),
+                fields: new_fields(&schema_struct.fields, version),
+            },
+            Schema::Enum(schema_enum) => HistoryNode::Enum {
+                name: schema_enum.dbg_name.clone(),
+                variants: schema_enum
+                    .variants
+                    .iter()
+                    .map(|variant| HistoryVariant {
+                        name: variant.name.clone(),
+                        discriminator: variant.discriminator,
+                        first_version: version,
+                        last_version: version,
+                        fields: new_fields(&variant.fields, version),
+                    })
+                    .collect(),
+                discriminant_size: schema_enum.discriminant_size,
+            },
+            Schema::Primitive(primitive) => HistoryNode::Primitive(*primitive),
+            Schema::Vector(item) => HistoryNode::Vector(Box::new(HistoryNode::new(item, version))),
+            Schema::Array(array) => HistoryNode::Array {
+                count: array.count,
+                item: Box::new(HistoryNode::new(&array.item_type, version)),
+            },
+            Schema::SchemaOption(item) => HistoryNode::SchemaOption(Box::new(HistoryNode::new(item, version))),
+            Schema::BitPacked(item) => HistoryNode::BitPacked(Box::new(HistoryNode::new(item, version))),
+            Schema::ZeroSize => HistoryNode::ZeroSize,
+            Schema::Undefined => HistoryNode::Undefined,
+        }
+    }
+
+    /// True if the schema can be merged into this history. Structs and enums accept schemas
+    /// of the same name, whatever their fields, since fields are versioned.
+    fn accepts(&self, schema: &Schema) -> bool {
+        match (self, schema) {
+            (HistoryNode::Struct { name, .. }, Schema::Struct(schema_struct)) => name == &schema_struct.dbg_name,
+            (
+                HistoryNode::Enum {
+                    name,
+                    variants,
+                    discriminant_size,
+                },
+                Schema::Enum(schema_enum),
+            ) => {
+                name == &schema_enum.dbg_name
+                    && *discriminant_size == schema_enum.discriminant_size
+                    && schema_enum.variants.iter().all(|variant| {
+                        variants
+                            .iter()
+                            .filter(|existing| existing.name == variant.name)
+                            .all(|existing| existing.discriminator == variant.discriminator)
+                    })
+            }
+            (HistoryNode::Primitive(a), Schema::Primitive(b)) => a == b,
+            (HistoryNode::Vector(a), Schema::Vector(b)) => a.accepts(b),
+            (HistoryNode::Array { count, item }, Schema::Array(array)) => {
+                *count == array.count && item.accepts(&array.item_type)
+            }
+            (HistoryNode::SchemaOption(a), Schema::SchemaOption(b)) => a.accepts(b),
+            (HistoryNode::BitPacked(a), Schema::BitPacked(b)) => a.accepts(b),
+            (HistoryNode::ZeroSize, Schema::ZeroSize) => true,
+            (HistoryNode::Undefined, Schema::Undefined) => true,
+            _ => false,
+        }
+    }
+
+    /// Add the schema of `version`, which must be accepted, to the history.
+    fn merge(&mut self, schema: &Schema, version: u32) {
+        match (self, schema) {
+            (HistoryNode::Struct { fields, .. }, Schema::Struct(schema_struct)) => {
+                merge_fields(fields, &schema_struct.fields, version)
+            }
+            (HistoryNode::Enum { variants, .. }, Schema::Enum(schema_enum)) => {
+                for variant in &schema_enum.variants {
+                    let continued = variants.iter_mut().find(|existing| {
+                        existing.name == variant.name && existing.last_version.checked_add(1) == Some(version)
+                    });
+                    match continued {
+                        Some(existing) => {
+                            existing.last_version = version;
+                            merge_fields(&mut existing.fields, &variant.fields, version);
+                        }
+                        None => variants.push(HistoryVariant {
+                            name: variant.name.clone(),
+                            discriminator: variant.discriminator,
+                            first_version: version,
+                            last_version: version,
+                            fields: new_fields(&variant.fields, version),
+                        }),
+                    }
+                }
+            }
+            (HistoryNode::Vector(item), Schema::Vector(schema_item))
+            | (HistoryNode::SchemaOption(item), Schema::SchemaOption(schema_item))
+            | (HistoryNode::BitPacked(item), Schema::BitPacked(schema_item)) => item.merge(schema_item, version),
+            (HistoryNode::Array { item, .. }, Schema::Array(array)) => item.merge(&array.item_type, version),
+            _ => {}
+        }
+    }
+
+    /// The schema in the given version, which must be one of the versions of this history
+    pub fn schema_at(&self, version: u32) -> Schema {
+        match self {
+            HistoryNode::Struct { name, fields } => Schema::Struct(SchemaStruct {
+                dbg_name: name.clone(),
+                fields: fields_at(fields, version),
+            }),
+            HistoryNode::Enum {
+                name,
+                variants,
+                discriminant_size,
+            } => Schema::Enum(SchemaEnum {
+                dbg_name: name.clone(),
+                variants: variants
+                    .iter()
+                    .filter(|variant| variant.first_version <= version && version <= variant.last_version)
+                    .map(|variant| Variant {
+                        name: variant.name.clone(),
+                        discriminator: variant.discriminator,
+                        fields: fields_at(&variant.fields, version),
+                    })
+                    .collect(),
+                discriminant_size: *discriminant_size,
+            }),
+            HistoryNode::Primitive(primitive) => Schema::Primitive(*primitive),
+            HistoryNode::Vector(item) => Schema::Vector(Arc::new(item.schema_at(version))),
+            HistoryNode::Array { count, item } => Schema::Array(SchemaArray {
+                count: *count,
+                item_type: Arc::new(item.schema_at(version)),
+            }),
+            HistoryNode::SchemaOption(item) => Schema::SchemaOption(Arc::new(item.schema_at(version))),
+            HistoryNode::BitPacked(item) => Schema::BitPacked(Arc::new(item.schema_at(version))),
+            HistoryNode::ZeroSize => Schema::ZeroSize,
+            HistoryNode::Undefined => Schema::Undefined,
+        }
+    }
+}
+
+/// The schemas of all versions of a type, from version 0 up to a current version. Each field
+/// of a struct or enum variant is stored once, with the versions it exists in, so the history
+/// is not much larger than a single schema.
+///
+/// Files saved by [crate::save_with_schema_history] contain the history instead of the schema
+/// of their version, so that tools can see how the data evolved, without the source code of
+/// older releases. Use [crate::read_schema_history] to read it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SchemaHistory {
+    /// The latest version of the history
+    pub current_version: u32,
+    /// The schema of the whole value. It only has more than one entry if the type of the
+    /// value changed, in which case each entry has the versions of one type, and an empty name.
+    pub root: Vec<HistoryField>,
+}
+
+impl SchemaHistory {
+    /// The history of T, from version 0 to `current_version`
+    pub fn of<T: WithSchema>(current_version: u32) -> SchemaHistory {
+        let mut history = SchemaHistory {
+            current_version: 0,
+            root: Vec::new(),
+        };
+        for version in 0..=current_version {
+            history.push(version, &schema_of::<T>(version));
+        }
+        history
+    }
+
+    /// Add the schema of the next version to the history. The version must be one more
+    /// than the current version, unless the history is empty.
+    pub fn push(&mut self, version: u32, schema: &Schema) {
+        self.current_version = version;
+        merge_fields(
+            &mut self.root,
+            &[Field {
+                name: String::new(),
+                value: Arc::new(schema.clone()),
+            }],
+            version,
+        );
+    }
+
+    /// The schema of the given version
+    pub fn schema(&self, version: u32) -> Result<Schema, SavefileError> {
+        self.root
+            .iter()
+            .find(|root| root.first_version <= version && version <= root.last_version)
+            .map(|root| root.value.schema_at(version))
+            .ok_or_else(|| SavefileError::WrongVersion {
+                msg: format!(
+                    "The schema history has no schema for version {}, only up to version {}",
+                    version, self.current_version
+                ),
+            })
+    }
+}
+
+fn serialize_fields(fields: &[HistoryField], serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+    serializer.write_length(fields.len() as u64)?;
+    for field in fields {
+        serializer.write_string(&field.name)?;
+        serializer.write_u32(field.first_version)?;
+        serializer.write_u32(field.last_version)?;
+        field.value.serialize(serializer)?;
+    }
+    Ok(())
+}
+
+fn deserialize_fields(deserializer: &mut Deserializer<impl Read>) -> Result<Vec<HistoryField>, SavefileError> {
+    let count = deserializer.read_length_usize()?;
+    let mut fields = Vec::new();
+    for _ in 0..count {
+        fields.push(HistoryField {
+            name: deserializer.read_string()?,
+            first_version: deserializer.read_u32()?,
+            last_version: deserializer.read_u32()?,
+            value: HistoryNode::deserialize(deserializer)?,
+        });
+    }
+    Ok(fields)
+}
+
+impl WithSchema for HistoryNode {
+    fn schema(_version: u32) -> Schema {
+        Schema::Undefined
+    }
+}
+
+impl Serialize for HistoryNode {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        match self {
+            HistoryNode::Struct { name, fields } => {
+                serializer.write_u8(1)?;
+                serializer.write_string(name)?;
+                serialize_fields(fields, serializer)
+            }
+            HistoryNode::Enum {
+                name,
+                variants,
+                discriminant_size,
+            } => {
+                // Like in schemas, only enums with wider discriminators than a u8 have their size
+                if *discriminant_size == 1 {
+                    serializer.write_u8(2)?;
+                } else {
+                    serializer.write_u8(10)?;
+                    serializer.write_u8(*discriminant_size)?;
+                }
+                serializer.write_string(name)?;
+                serializer.write_length(variants.len() as u64)?;
+                for variant in variants {
+                    serializer.write_string(&variant.name)?;
+                    serializer.write_discriminant(variant.discriminator, *discriminant_size)?;
+                    serializer.write_u32(variant.first_version)?;
+                    serializer.write_u32(variant.last_version)?;
+                    serialize_fields(&variant.fields, serializer)?;
+                }
+                Ok(())
+            }
+            HistoryNode::Primitive(primitive) => {
+                serializer.write_u8(3)?;
+                primitive.serialize(serializer)
+            }
+            HistoryNode::Vector(item) => {
+                serializer.write_u8(4)?;
+                item.serialize(serializer)
+            }
+            HistoryNode::Undefined => serializer.write_u8(5),
+            HistoryNode::ZeroSize => serializer.write_u8(6),
+            HistoryNode::SchemaOption(item) => {
+                serializer.write_u8(7)?;
+                item.serialize(serializer)
+            }
+            HistoryNode::Array { count, item } => {
+                serializer.write_u8(8)?;
+                serializer.write_length(*count as u64)?;
+                item.serialize(serializer)
+            }
+            HistoryNode::BitPacked(item) => {
+                serializer.write_u8(9)?;
+                item.serialize(serializer)
+            }
+        }
+    }
+}
+
+impl Deserialize for HistoryNode {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        Ok(match deserializer.read_u8()? {
+            1 => HistoryNode::Struct {
+                name: deserializer.read_string()?,
+                fields: deserialize_fields(deserializer)?,
+            },
+            tag @ (2 | 10) => {
+                let discriminant_size = if tag == 2 { 1 } else { deserializer.read_u8()? };
+                let name = deserializer.read_string()?;
+                let count = deserializer.read_length_usize()?;
+                let mut variants = Vec::new();
+                for _ in 0..count {
+                    variants.push(HistoryVariant {
+                        name: deserializer.read_string()?,
+                        discriminator: deserializer.read_discriminant(discriminant_size)?,
+                        first_version: deserializer.read_u32()?,
+                        last_version: deserializer.read_u32()?,
+                        fields: deserialize_fields(deserializer)?,
+                    });
+                }
+                HistoryNode::Enum {
+                    name,
+                    variants,
+                    discriminant_size,
+                }
+            }
+            3 => HistoryNode::Primitive(SchemaPrimitive::deserialize(deserializer)?),
+            4 => HistoryNode::Vector(Box::new(HistoryNode::deserialize(deserializer)?)),
+            5 => HistoryNode::Undefined,
+            6 => HistoryNode::ZeroSize,
+            7 => HistoryNode::SchemaOption(Box::new(HistoryNode::deserialize(deserializer)?)),
+            8 => HistoryNode::Array {
+                count: deserializer.read_length_usize()?,
+                item: Box::new(HistoryNode::deserialize(deserializer)?),
+            },
+            9 => HistoryNode::BitPacked(Box::new(HistoryNode::deserialize(deserializer)?)),
+            c => {
+                return Err(SavefileError::GeneralError {
+                    msg: format!("Corrupt schema history, schema variant {} encountered", c),
+                })
+            }
+        })
+    }
+}
+
+impl WithSchema for SchemaHistory {
+    fn schema(_version: u32) -> Schema {
+        Schema::Undefined
+    }
+}
+
+impl Serialize for SchemaHistory {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_u32(self.current_version)?;
+        serialize_fields(&self.root, serializer)
+    }
+}
+
+impl Deserialize for SchemaHistory {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        Ok(SchemaHistory {
+            current_version: deserializer.read_u32()?,
+            root: deserialize_fields(deserializer)?,
+        })
+    }
+}
@@ -0,0 +1,54 @@
+//! Functions for converting files to and from JSON in JavaScript, wrapping [Document].
+//!
+//! Build the crate for `wasm32-unknown-unknown` with the `js` feature and without default
+//! features, and generate the JavaScript bindings with `wasm-bindgen` (or `wasm-pack`):
+//!
+//! ```javascript
+//! import { toJson, fromJson, schemaOf } from "./savefile.js";
+//! const value = JSON.parse(toJson(bytes));
+//! value.players[0].name = "Alice";
+//! const saved = fromJson(bytes, JSON.stringify(value));
+//! ```
+//!
+//! Files are passed as `Uint8Array`, and errors are thrown as strings.
+
+use crate::{Document, DynValue, SavefileError};
+use wasm_bindgen::prelude::*;
+
+fn to_js_error(err: SavefileError) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+fn load_document(data: &[u8]) -> Result<Document, JsValue> {
+    let mut reader = data;
+    Document::load(&mut reader).map_err(to_js_error)
+}
+
+/// The data of a file saved with a schema, as JSON. See [crate::to_json].
+#[wasm_bindgen(js_name = toJson)]
+pub fn to_json(data: &[u8]) -> Result<String, JsValue> {
+    Ok(load_document(data)?.value.to_json())
+}
+
+/// A copy of the file `original`, with its data replaced by the given JSON. The schema and
+/// version of the original are kept, and the JSON must match the schema.
+#[wasm_bindgen(js_name = fromJson)]
+pub fn from_json(original: &[u8], json: &str) -> Result<Vec<u8>, JsValue> {
+    let mut document = load_document(original)?;
+    document.value = DynValue::from_json(&document.schema, json).map_err(to_js_error)?;
+    let mut bytes = Vec::new();
+    document.save(&mut bytes).map_err(to_js_error)?;
+    Ok(bytes)
+}
+
+/// The schema of a file saved with a schema, as JSON. See [crate::Schema::to_json].
+#[wasm_bindgen(js_name = schemaOf)]
+pub fn schema_of(data: &[u8]) -> Result<String, JsValue> {
+    Ok(load_document(data)?.schema.to_json())
+}
+
+/// The version of a file saved with a schema
+#[wasm_bindgen(js_name = versionOf)]
+pub fn version_of(data: &[u8]) -> Result<u32, JsValue> {
+    Ok(load_document(data)?.version)
+}
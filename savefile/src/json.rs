@@ -0,0 +1,677 @@
+//! Conversion of schemas and dynamic values to JSON, for consumers not written in rust.
+
+use crate::{Document, DynValue, Field, SavefileError, Schema, SchemaPrimitive};
+use indexmap::IndexMap;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::io::Read;
+
+/// Append `text` to `out` as a quoted JSON string.
+pub(crate) fn write_json_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(out, "\\u{:04x}", c as u32).unwrap();
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_json_fields(out: &mut String, fields: &[Field]) {
+    out.push('[');
+    for (i, field) in fields.iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+        out.push_str("{\"name\":");
+        write_json_string(out, &field.name);
+        out.push_str(",\"schema\":");
+        field.value.write_json(out);
+        out.push('}');
+    }
+    out.push(']');
+}
+
+fn write_json_schema_object(out: &mut String, fields: &[Field]) {
+    out.push_str("{\"type\":\"object\",\"properties\":{");
+    for (i, field) in fields.iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+        write_json_string(out, &field.name);
+        out.push(':');
+        field.value.write_json_schema(out);
+    }
+    out.push_str("},\"required\":[");
+    for (i, field) in fields.iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+        write_json_string(out, &field.name);
+    }
+    out.push_str("],\"additionalProperties\":false}");
+}
+
+impl SchemaPrimitive {
+    /// The JSON Schema of the JSON representation of a value of this type
+    fn json_schema(self) -> String {
+        let integer =
+            |min: i128, max: i128| format!("{{\"type\":\"integer\",\"minimum\":{},\"maximum\":{}}}", min, max);
+        match self {
+            SchemaPrimitive::schema_i8 => integer(i8::MIN.into(), i8::MAX.into()),
+            SchemaPrimitive::schema_u8 => integer(u8::MIN.into(), u8::MAX.into()),
+            SchemaPrimitive::schema_i16 => integer(i16::MIN.into(), i16::MAX.into()),
+            SchemaPrimitive::schema_u16 => integer(u16::MIN.into(), u16::MAX.into()),
+            SchemaPrimitive::schema_i32 => integer(i32::MIN.into(), i32::MAX.into()),
+            SchemaPrimitive::schema_u32 | SchemaPrimitive::schema_canary1 => integer(u32::MIN.into(), u32::MAX.into()),
+            SchemaPrimitive::schema_i64 => integer(i64::MIN.into(), i64::MAX.into()),
+            SchemaPrimitive::schema_u64 => integer(u64::MIN.into(), u64::MAX.into()),
+            SchemaPrimitive::schema_string => "{\"type\":\"string\"}".into(),
+            SchemaPrimitive::schema_f32 | SchemaPrimitive::schema_f64 => "{\"type\":\"number\"}".into(),
+            SchemaPrimitive::schema_bool => "{\"type\":\"boolean\"}".into(),
+        }
+    }
+}
+
+impl Schema {
+    /// Describe the schema as JSON. Every node is an object with a "kind" member,
+    /// which is one of "struct", "enum", "primitive", "vector", "array", "option",
+    /// "packed", "undefined" or "zerosize". Structs and enums have a "name", and their fields
+    /// are given as a list of objects with "name" and "schema". Enums have a list of
+    /// "variants", each with "name", "discriminator" and "fields", and enums with discriminators
+    /// wider than a u8 have their size in bytes as "discriminant_size". Primitives have a "type",
+    /// vectors, arrays, options and bit-packed values an "item", and arrays also a "count".
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) {
+        match *self {
+            Schema::Struct(ref schema_struct) => {
+                out.push_str("{\"kind\":\"struct\",\"name\":");
+                write_json_string(out, &schema_struct.dbg_name);
+                out.push_str(",\"fields\":");
+                write_json_fields(out, &schema_struct.fields);
+                out.push('}');
+            }
+            Schema::Enum(ref schema_enum) => {
+                out.push_str("{\"kind\":\"enum\",\"name\":");
+                write_json_string(out, &schema_enum.dbg_name);
+                if schema_enum.discriminant_size != 1 {
+                    write!(out, ",\"discriminant_size\":{}", schema_enum.discriminant_size).unwrap();
+                }
+                out.push_str(",\"variants\":[");
+                for (i, variant) in schema_enum.variants.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    out.push_str("{\"name\":");
+                    write_json_string(out, &variant.name);
+                    write!(out, ",\"discriminator\":{},\"fields\":", variant.discriminator).unwrap();
+                    write_json_fields(out, &variant.fields);
+                    out.push('}');
+                }
+                out.push_str("]}");
+            }
+            Schema::Primitive(primitive) => {
+                out.push_str("{\"kind\":\"primitive\",\"type\":");
+                write_json_string(out, primitive.name());
+                out.push('}');
+            }
+            Schema::Vector(ref item) => {
+                out.push_str("{\"kind\":\"vector\",\"item\":");
+                item.write_json(out);
+                out.push('}');
+            }
+            Schema::Array(ref array) => {
+                write!(out, "{{\"kind\":\"array\",\"count\":{},\"item\":", array.count).unwrap();
+                array.item_type.write_json(out);
+                out.push('}');
+            }
+            Schema::SchemaOption(ref item) => {
+                out.push_str("{\"kind\":\"option\",\"item\":");
+                item.write_json(out);
+                out.push('}');
+            }
+            Schema::Undefined => out.push_str("{\"kind\":\"undefined\"}"),
+            Schema::ZeroSize => out.push_str("{\"kind\":\"zerosize\"}"),
+            Schema::BitPacked(ref item) => {
+                out.push_str("{\"kind\":\"packed\",\"item\":");
+                item.write_json(out);
+                out.push('}');
+            }
+        }
+    }
+
+    /// Create a JSON Schema (draft 2020-12) for the JSON representation of values of this schema.
+    /// Structs are represented as objects, vectors and arrays as arrays, and options as
+    /// either null or the value. Enum variants without fields are represented as the
+    /// name of the variant, and other variants as an object with the variant name as
+    /// only key, holding an object with the fields.
+    pub fn to_json_schema(&self) -> String {
+        let mut out = String::from("{\"$schema\":\"https://json-schema.org/draft/2020-12/schema\",\"allOf\":[");
+        self.write_json_schema(&mut out);
+        out.push_str("]}");
+        out
+    }
+
+    fn write_json_schema(&self, out: &mut String) {
+        match *self {
+            Schema::Struct(ref schema_struct) => write_json_schema_object(out, &schema_struct.fields),
+            Schema::Enum(ref schema_enum) => {
+                out.push_str("{\"oneOf\":[");
+                for (i, variant) in schema_enum.variants.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    if variant.fields.is_empty() {
+                        out.push_str("{\"const\":");
+                        write_json_string(out, &variant.name);
+                        out.push('}');
+                    } else {
+                        out.push_str("{\"type\":\"object\",\"properties\":{");
+                        write_json_string(out, &variant.name);
+                        out.push(':');
+                        write_json_schema_object(out, &variant.fields);
+                        out.push_str("},\"required\":[");
+                        write_json_string(out, &variant.name);
+                        out.push_str("],\"additionalProperties\":false}");
+                    }
+                }
+                out.push_str("]}");
+            }
+            Schema::Primitive(primitive) => out.push_str(&primitive.json_schema()),
+            Schema::Vector(ref item) => {
+                out.push_str("{\"type\":\"array\",\"items\":");
+                item.write_json_schema(out);
+                out.push('}');
+            }
+            Schema::Array(ref array) => {
+                out.push_str("{\"type\":\"array\",\"items\":");
+                array.item_type.write_json_schema(out);
+                write!(out, ",\"minItems\":{0},\"maxItems\":{0}}}", array.count).unwrap();
+            }
+            Schema::SchemaOption(ref item) => {
+                out.push_str("{\"anyOf\":[{\"type\":\"null\"},");
+                item.write_json_schema(out);
+                out.push_str("]}");
+            }
+            Schema::Undefined => out.push_str("{}"),
+            Schema::ZeroSize => out.push_str("{\"type\":\"null\"}"),
+            // Packing doesn't change the JSON representation
+            Schema::BitPacked(ref item) => item.write_json_schema(out),
+        }
+    }
+}
+
+fn write_json_float(out: &mut String, value: f64) {
+    if value.is_finite() {
+        write!(out, "{}", value).unwrap();
+    } else {
+        // JSON has no representation of infinities and NaN
+        out.push_str("null");
+    }
+}
+
+fn write_json_value_object(out: &mut String, fields: &indexmap::IndexMap<String, DynValue>) {
+    out.push('{');
+    for (i, (name, value)) in fields.iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+        write_json_string(out, name);
+        out.push(':');
+        value.write_json(out);
+    }
+    out.push('}');
+}
+
+impl DynValue {
+    /// Convert the value to JSON, as described by [Schema::to_json_schema].
+    /// Infinite and NaN floating point values are represented as null.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) {
+        match self {
+            DynValue::I8(x) => write!(out, "{}", x).unwrap(),
+            DynValue::U8(x) => write!(out, "{}", x).unwrap(),
+            DynValue::I16(x) => write!(out, "{}", x).unwrap(),
+            DynValue::U16(x) => write!(out, "{}", x).unwrap(),
+            DynValue::I32(x) => write!(out, "{}", x).unwrap(),
+            DynValue::U32(x) => write!(out, "{}", x).unwrap(),
+            DynValue::I64(x) => write!(out, "{}", x).unwrap(),
+            DynValue::U64(x) => write!(out, "{}", x).unwrap(),
+            DynValue::F32(x) => write_json_float(out, *x as f64),
+            DynValue::F64(x) => write_json_float(out, *x),
+            DynValue::Bool(x) => write!(out, "{}", x).unwrap(),
+            DynValue::String(x) => write_json_string(out, x),
+            DynValue::Struct { fields, .. } => write_json_value_object(out, fields),
+            DynValue::Enum { variant, fields, .. } => {
+                if fields.is_empty() {
+                    write_json_string(out, variant);
+                } else {
+                    out.push('{');
+                    write_json_string(out, variant);
+                    out.push(':');
+                    write_json_value_object(out, fields);
+                    out.push('}');
+                }
+            }
+            DynValue::Vector(items) | DynValue::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    item.write_json(out);
+                }
+                out.push(']');
+            }
+            DynValue::Option(None) | DynValue::ZeroSize => out.push_str("null"),
+            DynValue::Option(Some(value)) => value.write_json(out),
+        }
+    }
+}
+
+/// A parsed JSON value
+enum JsonValue {
+    Null,
+    Bool(bool),
+    /// The number as written, so that integers of any size can be converted exactly
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// The maximum nesting of JSON arrays and objects, which protects the parser from
+/// overflowing its stack
+const MAX_JSON_DEPTH: usize = 512;
+
+fn json_error(msg: impl Into<String>) -> SavefileError {
+    SavefileError::GeneralError { msg: msg.into() }
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn unexpected(&self) -> SavefileError {
+        match self.text[self.pos..].chars().next() {
+            Some(c) => json_error(format!("Unexpected '{}' at offset {} of JSON", c, self.pos)),
+            None => json_error("Unexpected end of JSON"),
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), SavefileError> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, keyword: &str, value: JsonValue) -> Result<JsonValue, SavefileError> {
+        if !self.text[self.pos..].starts_with(keyword) {
+            return Err(self.unexpected());
+        }
+        self.pos += keyword.len();
+        Ok(value)
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<JsonValue, SavefileError> {
+        if depth > MAX_JSON_DEPTH {
+            return Err(json_error("JSON is nested too deeply"));
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.keyword("null", JsonValue::Null),
+            Some(b't') => self.keyword("true", JsonValue::Bool(true)),
+            Some(b'f') => self.keyword("false", JsonValue::Bool(false)),
+            Some(b'"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                loop {
+                    items.push(self.parse_value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(JsonValue::Array(items));
+                        }
+                        _ => return Err(self.unexpected()),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                let mut names = HashSet::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'"') {
+                        return Err(self.unexpected());
+                    }
+                    let name_pos = self.pos;
+                    let name = self.parse_string()?;
+                    if !names.insert(name.clone()) {
+                        return Err(json_error(format!(
+                            "Duplicate key {:?} at offset {} of JSON",
+                            name, name_pos
+                        )));
+                    }
+                    self.expect(b':')?;
+                    members.push((name, self.parse_value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(JsonValue::Object(members));
+                        }
+                        _ => return Err(self.unexpected()),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+                    self.pos += 1;
+                }
+                Ok(JsonValue::Number(self.text[start..self.pos].to_string()))
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, SavefileError> {
+        let hex = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| json_error("Unexpected end of JSON"))?;
+        let code = u32::from_str_radix(hex, 16)
+            .map_err(|_| json_error(format!("Bad escape sequence at offset {} of JSON", self.pos)))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    /// Parse a string, starting at its opening quote
+    fn parse_string(&mut self) -> Result<String, SavefileError> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let c = self.text[self.pos..]
+                .chars()
+                .next()
+                .ok_or_else(|| json_error("Unterminated string in JSON"))?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self.peek().ok_or_else(|| json_error("Unterminated string in JSON"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => {
+                            let mut code = self.parse_hex4()?;
+                            if (0xd800..0xdc00).contains(&code) && self.text[self.pos..].starts_with("\\u") {
+                                self.pos += 2;
+                                let low = self.parse_hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(json_error(format!(
+                                        "Unpaired surrogate before offset {} of JSON",
+                                        self.pos
+                                    )));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            out.push(std::char::from_u32(code).ok_or_else(|| {
+                                json_error(format!("Bad escape sequence before offset {} of JSON", self.pos))
+                            })?);
+                        }
+                        _ => {
+                            return Err(json_error(format!(
+                                "Bad escape sequence at offset {} of JSON",
+                                self.pos - 1
+                            )))
+                        }
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+}
+
+fn parse_json(text: &str) -> Result<JsonValue, SavefileError> {
+    let mut parser = JsonParser { text, pos: 0 };
+    let value = parser.parse_value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return Err(parser.unexpected());
+    }
+    Ok(value)
+}
+
+fn json_fields(fields: &[Field], json: JsonValue, what: &str) -> Result<IndexMap<String, DynValue>, SavefileError> {
+    let mut members = match json {
+        JsonValue::Object(members) => members,
+        _ => return Err(json_error(format!("Expected an object for {}", what))),
+    };
+    if let Some((name, _)) = members
+        .iter()
+        .find(|(name, _)| !fields.iter().any(|field| &field.name == name))
+    {
+        return Err(json_error(format!("{} has no field '{}'", what, name)));
+    }
+    let mut values = IndexMap::with_capacity(fields.len());
+    for field in fields {
+        let index = members
+            .iter()
+            .position(|(name, _)| name == &field.name)
+            .ok_or_else(|| json_error(format!("Field '{}' of {} is missing", field.name, what)))?;
+        let (_, member) = members.swap_remove(index);
+        values.insert(
+            field.name.clone(),
+            json_to_dyn(&field.value, member).map_err(|err| err.with_path_segment(&field.name))?,
+        );
+    }
+    Ok(values)
+}
+
+fn json_items(item_schema: &Schema, json: JsonValue) -> Result<Vec<DynValue>, SavefileError> {
+    match json {
+        JsonValue::Array(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| {
+                json_to_dyn(item_schema, item).map_err(|err| err.with_path_segment(&index.to_string()))
+            })
+            .collect(),
+        _ => Err(json_error("Expected an array")),
+    }
+}
+
+fn json_to_dyn(schema: &Schema, json: JsonValue) -> Result<DynValue, SavefileError> {
+    Ok(match schema {
+        Schema::Struct(schema_struct) => DynValue::Struct {
+            name: schema_struct.dbg_name.clone(),
+            fields: json_fields(&schema_struct.fields, json, &schema_struct.dbg_name)?,
+        },
+        Schema::Enum(schema_enum) => {
+            let (name, fields) = match json {
+                JsonValue::String(name) => (name, None),
+                JsonValue::Object(mut members) if members.len() == 1 => {
+                    let (name, fields) = members.pop().unwrap();
+                    (name, Some(fields))
+                }
+                _ => {
+                    return Err(json_error(format!(
+                        "Expected a variant name, or an object with a variant name as only key, for {}",
+                        schema_enum.dbg_name
+                    )))
+                }
+            };
+            let variant = schema_enum
+                .variants
+                .iter()
+                .find(|variant| variant.name == name)
+                .ok_or_else(|| json_error(format!("{} has no variant '{}'", schema_enum.dbg_name, name)))?;
+            let what = format!("{}::{}", schema_enum.dbg_name, variant.name);
+            DynValue::Enum {
+                variant: variant.name.clone(),
+                discriminator: variant.discriminator,
+                fields: match fields {
+                    Some(fields) => json_fields(&variant.fields, fields, &what)?,
+                    None => json_fields(&variant.fields, JsonValue::Object(Vec::new()), &what)?,
+                },
+            }
+        }
+        Schema::Primitive(primitive) => primitive_from_json(*primitive, json)?,
+        Schema::Vector(item) => DynValue::Vector(json_items(item, json)?),
+        Schema::Array(array) => {
+            let items = json_items(&array.item_type, json)?;
+            if items.len() != array.count {
+                return Err(json_error(format!(
+                    "Expected an array of {} items, but it has {}",
+                    array.count,
+                    items.len()
+                )));
+            }
+            DynValue::Array(items)
+        }
+        Schema::SchemaOption(item) => match json {
+            JsonValue::Null => DynValue::Option(None),
+            json => DynValue::Option(Some(Box::new(json_to_dyn(item, json)?))),
+        },
+        Schema::ZeroSize => match json {
+            JsonValue::Null => DynValue::ZeroSize,
+            _ => return Err(json_error("Expected null")),
+        },
+        Schema::BitPacked(item) => json_to_dyn(item, json)?,
+        Schema::Undefined => return Err(json_error("Values of undefined schema can't be converted from JSON")),
+    })
+}
+
+fn primitive_from_json(primitive: SchemaPrimitive, json: JsonValue) -> Result<DynValue, SavefileError> {
+    let integer = |json: &JsonValue, min: i128, max: i128| -> Result<i128, SavefileError> {
+        match json {
+            JsonValue::Number(text) => match text.parse::<i128>() {
+                Ok(x) if x >= min && x <= max => Ok(x),
+                _ => Err(json_error(format!(
+                    "Expected an integer from {} to {}, but got {}",
+                    min, max, text
+                ))),
+            },
+            _ => Err(json_error("Expected an integer")),
+        }
+    };
+    let float = |json: &JsonValue| -> Result<f64, SavefileError> {
+        match json {
+            JsonValue::Number(text) => text
+                .parse::<f64>()
+                .map_err(|_| json_error(format!("Expected a number, but got {}", text))),
+            // Infinities and NaN are written as null, and can't be told apart
+            JsonValue::Null => Ok(f64::NAN),
+            _ => Err(json_error("Expected a number")),
+        }
+    };
+    Ok(match primitive {
+        SchemaPrimitive::schema_i8 => DynValue::I8(integer(&json, i8::MIN.into(), i8::MAX.into())? as i8),
+        SchemaPrimitive::schema_u8 => DynValue::U8(integer(&json, u8::MIN.into(), u8::MAX.into())? as u8),
+        SchemaPrimitive::schema_i16 => DynValue::I16(integer(&json, i16::MIN.into(), i16::MAX.into())? as i16),
+        SchemaPrimitive::schema_u16 => DynValue::U16(integer(&json, u16::MIN.into(), u16::MAX.into())? as u16),
+        SchemaPrimitive::schema_i32 => DynValue::I32(integer(&json, i32::MIN.into(), i32::MAX.into())? as i32),
+        SchemaPrimitive::schema_u32 | SchemaPrimitive::schema_canary1 => {
+            DynValue::U32(integer(&json, u32::MIN.into(), u32::MAX.into())? as u32)
+        }
+        SchemaPrimitive::schema_i64 => DynValue::I64(integer(&json, i64::MIN.into(), i64::MAX.into())? as i64),
+        SchemaPrimitive::schema_u64 => DynValue::U64(integer(&json, u64::MIN.into(), u64::MAX.into())? as u64),
+        SchemaPrimitive::schema_f32 => DynValue::F32(float(&json)? as f32),
+        SchemaPrimitive::schema_f64 => DynValue::F64(float(&json)?),
+        SchemaPrimitive::schema_bool => match json {
+            JsonValue::Bool(x) => DynValue::Bool(x),
+            _ => return Err(json_error("Expected true or false")),
+        },
+        SchemaPrimitive::schema_string => match json {
+            JsonValue::String(x) => DynValue::String(x),
+            _ => return Err(json_error("Expected a string")),
+        },
+    })
+}
+
+impl DynValue {
+    /// Convert JSON in the representation described by [Schema::to_json_schema] to a value
+    /// of the given schema. Infinite and NaN floating point values, which are represented
+    /// as null, become NaN.
+    pub fn from_json(schema: &Schema, json: &str) -> Result<DynValue, SavefileError> {
+        json_to_dyn(schema, parse_json(json)?)
+    }
+}
+
+/// Convert the data of a file saved with a schema, such as by [crate::save], to JSON,
+/// as described by [Schema::to_json_schema]. The file can be recreated from the JSON,
+/// possibly after editing it, using [from_json] with the schema and version of the file,
+/// which can be found by loading it as a [Document].
+pub fn to_json(reader: &mut impl Read) -> Result<String, SavefileError> {
+    Ok(Document::load(reader)?.value.to_json())
+}
+
+/// Convert JSON created by [to_json] back to a file, which can be loaded as if it was saved
+/// by [crate::save] with the given version. `schema` is the schema of the data at that version.
+pub fn from_json(schema: &Schema, version: u32, json: &str) -> Result<Vec<u8>, SavefileError> {
+    let document = Document {
+        version,
+        schema: schema.clone(),
+        value: DynValue::from_json(schema, json)?,
+    };
+    let mut bytes = Vec::new();
+    document.save(&mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,328 @@
+//! Fields which are only deserialized when they are needed.
+
+use crate::namespace::{self, NamespaceVersions};
+use crate::{
+    packed_bytes, packed_run, read_vec_length, CurrentVersion, Deserialize, Deserializer, Encoding, Introspect, IntrospectItem,
+    SavefileError, Schema, Serialize, Serializer, WithSchema,
+};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// Reads through the value described by `schema`, without deserializing it.
+/// The value is read from the file in chunks as large as possible, for speed.
+pub(crate) fn skip_value(schema: &Schema, deserializer: &mut Deserializer<impl Read>) -> Result<(), SavefileError> {
+    if let Some(size) = schema.serialized_size() {
+        return skip_bytes(deserializer, size as u64);
+    }
+    match schema {
+        Schema::Struct(schema_struct) => skip_fields(&schema_struct.fields, deserializer)?,
+        Schema::Enum(schema_enum) => {
+            let discriminator = deserializer.read_discriminant(schema_enum.discriminant_size)?;
+            let variant = schema_enum
+                .variants
+                .iter()
+                .find(|variant| variant.discriminator == discriminator)
+                .ok_or_else(|| SavefileError::GeneralError {
+                    msg: format!(
+                        "Enum {} has no variant with discriminator {}",
+                        schema_enum.dbg_name, discriminator
+                    ),
+                })?;
+            skip_fields(&variant.fields, deserializer)?;
+        }
+        Schema::Primitive(_) if deserializer.encoding.intern_strings => {
+            // Interned strings must be read, since later strings may refer to them
+            deserializer.read_string()?;
+        }
+        Schema::Primitive(_) => {
+            // Strings are the only primitives without a fixed size
+            let length = deserializer.read_length_usize()?;
+            skip_bytes(deserializer, length as u64)?;
+        }
+        Schema::Vector(item) => match (read_vec_length(deserializer)?, item.serialized_size()) {
+            (Some(length), Some(size)) => skip_bytes(deserializer, (size as u64).saturating_mul(length as u64))?,
+            (Some(length), None) => {
+                for _ in 0..length {
+                    skip_value(item, deserializer)?;
+                }
+            }
+            (None, _) => loop {
+                let chunk_length = deserializer.read_length_usize()?;
+                if chunk_length == 0 {
+                    break;
+                }
+                for _ in 0..chunk_length {
+                    skip_value(item, deserializer)?;
+                }
+            },
+        },
+        Schema::Array(array) => {
+            for _ in 0..array.count {
+                skip_value(&array.item_type, deserializer)?;
+            }
+        }
+        Schema::SchemaOption(item) => {
+            if deserializer.read_u8()? != 0 {
+                skip_value(item, deserializer)?;
+            }
+        }
+        Schema::ZeroSize => {}
+        Schema::BitPacked(_) => {
+            let (_, width) = packed_run(std::iter::once(schema))?;
+            skip_bytes(deserializer, packed_bytes(width) as u64)?;
+        }
+        Schema::Undefined => {
+            return Err(SavefileError::GeneralError {
+                msg: "Values of undefined schema can't be skipped".into(),
+            })
+        }
+    }
+    Ok(())
+}
+
+fn skip_fields(fields: &[crate::Field], deserializer: &mut Deserializer<impl Read>) -> Result<(), SavefileError> {
+    let mut index = 0;
+    while index < fields.len() {
+        let field = &fields[index];
+        if let Schema::BitPacked(_) = *field.value {
+            // Consecutive bit-packed fields are stored together
+            let (count, width) = packed_run(fields[index..].iter().map(|field| &*field.value))?;
+            skip_bytes(deserializer, packed_bytes(width) as u64)?;
+            index += count;
+        } else {
+            skip_value(&field.value, deserializer).map_err(|err| err.with_path_segment(&field.name))?;
+            index += 1;
+        }
+    }
+    Ok(())
+}
+
+fn skip_bytes(deserializer: &mut Deserializer<impl Read>, count: u64) -> Result<(), SavefileError> {
+    let skipped = std::io::copy(&mut (&mut deserializer.reader).take(count), &mut std::io::sink())?;
+    if skipped != count {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(())
+}
+
+/// A Read-wrapper which keeps a copy of everything read.
+struct RecordingReader<'a, R: Read> {
+    reader: &'a mut R,
+    recorded: Vec<u8>,
+}
+
+impl<'a, R: Read> Read for RecordingReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.reader.read(buf)?;
+        self.recorded.extend_from_slice(&buf[..count]);
+        Ok(count)
+    }
+}
+
+enum LazyState<T> {
+    Loaded(T),
+    Unloaded {
+        bytes: Vec<u8>,
+        file_version: u32,
+        memory_version: u32,
+        encoding: Encoding,
+        /// The versions of the namespaces in the file the bytes were loaded from
+        namespaces: Option<NamespaceVersions>,
+    },
+}
+
+/// A field which isn't deserialized when the rest of the data is loaded. Instead, the
+/// serialized bytes are retained, and only deserialized when the value is first accessed.
+///
+/// This is useful when only some of the data in a file is usually needed, such as when
+/// listing saved games in a menu, which only needs the name and date of each save.
+/// Skipping the lazy value when loading is guided by its schema, which is much faster
+/// than deserializing it, especially for large vectors of plain values.
+///
+/// A `Lazy<T>` has the same schema as `T`, so a field of type T can be changed to
+/// `Lazy<T>` without affecting the file format. If the value hasn't been accessed,
+/// saving it again writes the retained bytes as is.
+///
+/// In files with interned strings (see [crate::Encoding::intern_strings]), the value is
+/// deserialized immediately, since its strings may refer to strings elsewhere in the file.
+pub struct Lazy<T> {
+    state: LazyState<T>,
+}
+
+impl<T> Lazy<T> {
+    /// Create a lazy value which is already loaded
+    pub fn new(value: T) -> Lazy<T> {
+        Lazy {
+            state: LazyState::Loaded(value),
+        }
+    }
+
+    /// True if the value has been deserialized
+    pub fn is_loaded(&self) -> bool {
+        matches!(self.state, LazyState::Loaded(_))
+    }
+}
+
+impl<T: Deserialize> Lazy<T> {
+    fn deserialize_retained(
+        bytes: &[u8],
+        file_version: u32,
+        memory_version: u32,
+        encoding: Encoding,
+        namespaces: &Option<NamespaceVersions>,
+    ) -> Result<T, SavefileError> {
+        let mut deserializer = Deserializer {
+            reader: &mut &bytes[..],
+            file_version,
+            memory_version,
+            encoding,
+            ephemeral_state: HashMap::new(),
+            interned_strings: Vec::new(),
+            scratch: Vec::new(),
+            unchecked_utf8: false,
+            context: None,
+            progress: None,
+            memory_budget: None,
+        };
+        namespace::with_file_versions(namespaces.clone(), || T::deserialize(&mut deserializer))
+    }
+
+    /// Deserialize the value, if that hasn't been done already, and return it.
+    pub fn get(&mut self) -> Result<&mut T, SavefileError> {
+        let value = match self.state {
+            LazyState::Unloaded {
+                ref bytes,
+                file_version,
+                memory_version,
+                encoding,
+                ref namespaces,
+            } => Some(Self::deserialize_retained(
+                bytes,
+                file_version,
+                memory_version,
+                encoding,
+                namespaces,
+            )?),
+            LazyState::Loaded(_) => None,
+        };
+        if let Some(value) = value {
+            self.state = LazyState::Loaded(value);
+        }
+        match self.state {
+            LazyState::Loaded(ref mut value) => Ok(value),
+            LazyState::Unloaded { .. } => unreachable!(),
+        }
+    }
+
+    /// Deserialize the value, if that hasn't been done already, and return it.
+    pub fn into_inner(self) -> Result<T, SavefileError> {
+        match self.state {
+            LazyState::Loaded(value) => Ok(value),
+            LazyState::Unloaded {
+                bytes,
+                file_version,
+                memory_version,
+                encoding,
+                namespaces,
+            } => Self::deserialize_retained(&bytes, file_version, memory_version, encoding, &namespaces),
+        }
+    }
+}
+
+impl<T: CurrentVersion> CurrentVersion for Lazy<T> {
+    const SAVEFILE_MAX_VERSION: u32 = T::SAVEFILE_MAX_VERSION;
+}
+
+impl<T: WithSchema> WithSchema for Lazy<T> {
+    fn schema(version: u32) -> Schema {
+        T::schema(version)
+    }
+}
+
+impl<T: WithSchema + Serialize + Deserialize> Serialize for Lazy<T> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        match self.state {
+            LazyState::Loaded(ref value) => value.serialize(serializer),
+            LazyState::Unloaded {
+                ref bytes,
+                file_version,
+                encoding,
+                ref namespaces,
+                ..
+            } if file_version == serializer.version
+                && encoding == serializer.encoding
+                && namespace::versions_unchanged(namespaces, || T::schema(file_version)) =>
+            {
+                serializer.write_bytes(bytes)
+            }
+            LazyState::Unloaded {
+                ref bytes,
+                file_version,
+                memory_version,
+                encoding,
+                ref namespaces,
+            } => {
+                // The retained bytes have an older version, older versions of namespaces
+                // or a different encoding, so they must be converted
+                Self::deserialize_retained(bytes, file_version, memory_version, encoding, namespaces)?
+                    .serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<T: WithSchema + Deserialize> Deserialize for Lazy<T> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        if deserializer.encoding.intern_strings {
+            // The strings of the value may refer to strings outside of it, so the retained
+            // bytes couldn't be deserialized on their own
+            return Ok(Lazy::new(T::deserialize(deserializer)?));
+        }
+        let schema = T::schema(deserializer.file_version);
+        let mut recording_reader = RecordingReader {
+            reader: &mut deserializer.reader,
+            recorded: Vec::new(),
+        };
+        skip_value(
+            &schema,
+            &mut Deserializer {
+                reader: &mut recording_reader,
+                file_version: deserializer.file_version,
+                memory_version: deserializer.memory_version,
+                encoding: deserializer.encoding,
+                ephemeral_state: HashMap::new(),
+                interned_strings: Vec::new(),
+                scratch: Vec::new(),
+                unchecked_utf8: false,
+                context: None,
+                progress: None,
+                memory_budget: None,
+            },
+        )?;
+        Ok(Lazy {
+            state: LazyState::Unloaded {
+                bytes: recording_reader.recorded,
+                file_version: deserializer.file_version,
+                memory_version: deserializer.memory_version,
+                encoding: deserializer.encoding,
+                namespaces: namespace::file_versions(),
+            },
+        })
+    }
+}
+
+impl<T: Introspect> Introspect for Lazy<T> {
+    fn introspect_value(&self) -> String {
+        match self.state {
+            LazyState::Loaded(ref value) => value.introspect_value(),
+            LazyState::Unloaded { .. } => "Lazy (not loaded)".to_string(),
+        }
+    }
+
+    fn introspect_child<'a>(&'a self, index: usize) -> Option<Box<dyn IntrospectItem<'a> + 'a>> {
+        match self.state {
+            LazyState::Loaded(ref value) => value.introspect_child(index),
+            LazyState::Unloaded { .. } => None,
+        }
+    }
+}
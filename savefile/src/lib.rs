@@ -1,6 +1,5 @@
 #![allow(incomplete_features)]
 #![recursion_limit = "256"]
-#![cfg_attr(feature = "nightly", feature(specialization))]
 #![deny(missing_docs)]
 #![deny(warnings)]
 
@@ -175,6 +174,9 @@ Removed fields must keep their deserialization type. This is easiest accomplishe
 using the `Removed<T>` type. `Removed<T>` uses zero space in RAM, but deserializes equivalently to T (with the
 result of the deserialization thrown away).
 
+To delete the old type altogether, use `RemovedOpaque<S>` instead. S only has to implement [WithSchema],
+giving the schema the old type had, and the value is skipped using that schema. See [RemovedOpaque].
+
 Savefile tries to validate that the `Removed<T>` type is used correctly. This validation is based on string
 matching, so it may trigger false positives for other types named Removed. Please avoid using a type with
 such a name. If this becomes a problem, please file an issue on github.
@@ -182,7 +184,8 @@ such a name. If this becomes a problem, please file an issue on github.
 Using the #\[savefile_versions] tag is critically important. If this is messed up, data corruption is likely.
 
 When a field is added, its type must implement the Default trait (unless the default_val or default_fn attributes
-are used).
+are used). When older files are loaded, the field gets its Default value, so a #\[savefile_versions] attribute
+is all that is needed to add a field such as a new counter or an optional setting.
 
 There also exists a savefile_default_val, a default_fn and a savefile_versions_as attribute. More about these below:
 
@@ -205,6 +208,95 @@ Rules for using the #\[savefile_versions] attribute:
     For example, if you remove a field in version 3, you should add a #\[savefile_versions="..2"] attribute.
  You may not change the type of a field in your structs, except when using the savefile_versions_as-macro.
 
+ ## Versioning enums
+
+ The fields of enum variants are versioned just like the fields of structs, using the same attributes.
+ A variant's fields can be added, removed using `Removed<T>`, or change type, without adding a new variant.
+ New variants can be added at the end of the enum, with a #\[savefile_versions = "N.."] attribute on the
+ variant. Variants can't be removed.
+
+ ```
+ # #[macro_use]
+ # extern crate savefile_derive;
+ # use savefile::prelude::*;
+
+ #[derive(Savefile)]
+ enum Event {
+     Dropped {
+         item: u32,
+         #[savefile_versions = "..0"]
+         count: Removed<u16>,
+         #[savefile_versions = "1.."]
+         #[savefile_default_val = "1"]
+         stack_size: u32,
+     },
+     #[savefile_versions = "1.."]
+     Equipped(u32),
+ }
+ # fn main() {}
+ ```
+
+ ## The savefile_enum_repr attribute
+
+ Each variant is saved with a discriminator, which is the index of the variant, as a u8. This limits
+ enums to 255 variants. Enums with more variants, such as large generated enums of opcodes or item kinds,
+ can have u16 or u32 discriminators, using #\[savefile_enum_repr(u16)] or #\[savefile_enum_repr(u32)].
+ The size of the discriminators is part of the schema, so loading a file saved with discriminators of
+ another size fails with an incompatible schema. Enums which derive ReprC must have a repr of the same
+ size as their discriminators.
+
+ ```
+ # #[macro_use]
+ # extern crate savefile_derive;
+ # use savefile::prelude::*;
+
+ #[derive(Savefile, Debug, PartialEq)]
+ #[savefile_enum_repr(u16)]
+ enum Opcode {
+     Nop,
+     Load(u32),
+     Store(u32),
+     // ... hundreds more
+ }
+
+ # fn main() {
+ let saved = save_to_mem(0, &Opcode::Load(7)).unwrap();
+ assert_eq!(load_from_mem::<Opcode>(&saved, 0).unwrap(), Opcode::Load(7));
+ # }
+ ```
+
+ ## The savefile_enum_as_int attribute
+
+ Enums without fields can instead be saved as their integer values, using #\[savefile_enum_as_int]. The
+ enum must have a repr of at most 32 bits, like #\[repr(u8)] or #\[repr(i16)], and each variant is saved
+ as its value in that type, which may be given explicitly, like `Wall = 7`. The discriminators in the
+ schema are then these values. Loading fails for values which are not variants of the enum.
+
+ Since the saved bytes are those of the enum in memory, such an enum can also derive ReprC, so that
+ vectors and arrays of it are copied as a whole, like for integers. The copied bytes are still
+ checked to be variants of the enum, before they are used.
+
+ ```
+ # #[macro_use]
+ # extern crate savefile_derive;
+ # use savefile::prelude::*;
+
+ #[derive(Savefile, ReprC, Clone, Copy, Debug, PartialEq)]
+ #[repr(u8)]
+ #[savefile_enum_as_int]
+ enum Tile {
+     Floor = 1,
+     Wall = 7,
+     Door = 20,
+ }
+
+ # fn main() {
+ let tiles = vec![Tile::Wall, Tile::Door, Tile::Floor];
+ let saved = save_to_mem(0, &tiles).unwrap();
+ assert_eq!(load_from_mem::<Vec<Tile>>(&saved, 0).unwrap(), tiles);
+ # }
+ ```
+
 
 
  ## The default_val attribute
@@ -287,6 +379,147 @@ Rules for using the #\[savefile_versions] attribute:
  savefile_ignore does not stop the generator from generating an implementation for [Introspect](crate::Introspect) for the given field. To stop
  this as well, also supply the attribute savefile_introspect_ignore .
 
+ ## The savefile_post_deserialize attribute
+
+ Putting the savefile_post_deserialize attribute on a struct or enum makes the derived deserializer call
+ [PostDeserialize::post_deserialize](crate::PostDeserialize::post_deserialize) on each value after it has
+ been constructed, with the version of the file. This can be used to recompute ignored fields, or to
+ normalize data loaded from older versions.
+
+ ```
+ # #[macro_use]
+ # extern crate savefile_derive;
+ # extern crate savefile;
+ use savefile::prelude::*;
+
+ #[derive(Savefile)]
+ #[savefile_post_deserialize]
+ struct Order {
+     items: Vec<u32>,
+     #[savefile_ignore]
+     total: u32,
+ }
+
+ impl PostDeserialize for Order {
+     fn post_deserialize(&mut self, _file_version: u32) {
+         self.total = self.items.iter().sum();
+     }
+ }
+ # fn main() {}
+ ```
+
+ ## The savefile_validate attribute
+
+ The savefile_validate attribute names a function which checks the invariants of a struct or enum.
+ The function has the signature `fn(&T) -> Result<(), String>`, and is called by the derived serializer
+ before a value is written, and by the derived deserializer after it has been loaded (and after any
+ post_deserialize hook). If it returns an error, saving or loading fails with
+ [SavefileError::Validation](crate::SavefileError::Validation), which contains the message and the path of
+ fields leading to the invalid value. Items of vectors are given by `*` in the path.
+
+ Types which also derive ReprC may be copied as raw bytes when they are items of vectors or arrays,
+ and such copies are not validated.
+
+ ```
+ # #[macro_use]
+ # extern crate savefile_derive;
+ # extern crate savefile;
+ use savefile::prelude::*;
+
+ fn check_range(range: &Range) -> Result<(), String> {
+     if range.start <= range.end {
+         Ok(())
+     } else {
+         Err(format!("{} is after {}", range.start, range.end))
+     }
+ }
+
+ #[derive(Savefile)]
+ #[savefile_validate = "check_range"]
+ struct Range {
+     start: u32,
+     end: u32,
+ }
+
+ # fn main() {
+ let mut data = Vec::new();
+ assert!(save(&mut data, 0, &Range { start: 3, end: 1 }).is_err());
+ # }
+ ```
+
+ ## The savefile_max_version attribute
+
+ The derive macro implements [CurrentVersion] for each type. Its constant `SAVEFILE_MAX_VERSION` is the
+ current version of the type, so it can be given to `save` and `load` instead of a version number kept by
+ hand, and [save_current] and [load_current] use it automatically.
+ Without an attribute, it is the lowest version which fits the #\[savefile_versions] attributes of the
+ fields and variants: the version of the most recently added field, or the version after the last
+ version of the most recently removed field. It is at least the current version of the type of each
+ field which is still saved, so a type containing a `Vec<Player>` is at least at version 1 below.
+ When the current version is higher than that, for example because a type with a hand-written
+ implementation changed, give it using the savefile_max_version attribute. It is then a compile
+ error for a field or variant to have versions beyond it, and the versions of the types of the
+ fields are not included.
+
+ ```
+ # #[macro_use]
+ # extern crate savefile_derive;
+ # extern crate savefile;
+ use savefile::prelude::*;
+
+ #[derive(Savefile)]
+ struct Player {
+     name: String,
+     #[savefile_versions = "0..0"]
+     strength: Removed<u32>,
+     #[savefile_versions = "1.."]
+     skills: Vec<String>,
+ }
+
+ #[derive(Savefile)]
+ struct Team {
+     players: Vec<Player>,
+ }
+
+ #[derive(Savefile)]
+ #[savefile_max_version = 3]
+ struct Settings {
+     #[savefile_versions = "2.."]
+     volume: u8,
+ }
+
+ # fn main() {
+ assert_eq!(Player::SAVEFILE_MAX_VERSION, 1);
+ assert_eq!(Team::SAVEFILE_MAX_VERSION, 1);
+ assert_eq!(Settings::SAVEFILE_MAX_VERSION, 3);
+ let mut data = Vec::new();
+ save_current(&mut data, &Settings { volume: 7 }).unwrap();
+ let settings: Settings = load_current(&mut &data[..]).unwrap();
+ assert_eq!(settings.volume, 7);
+ # }
+ ```
+
+ ```compile_fail
+ # #[macro_use]
+ # extern crate savefile_derive;
+ # extern crate savefile;
+ #[derive(Savefile)]
+ #[savefile_max_version = 1]
+ struct Settings {
+     #[savefile_versions = "2.."] // Error, the type is only at version 1
+     volume: u8,
+ }
+ # fn main() {}
+ ```
+
+ ## The savefile_namespace attribute
+
+ The types of a library are saved in files with the versions of the applications using them, so
+ a library can't use the savefile_versions attribute in the usual way. Instead, it can give its types
+ a version of their own, by declaring a [VersionNamespace] and naming it with
+ #\[savefile_namespace = "NAMESPACE"]. The versions of the fields of such types, and of the types
+ within them, then refer to the version of the namespace, which files record. See [VersionNamespace].
+
  ## The savefile_versions_as attribute
 
  The savefile_versions_as attribute can be used to support changing the type of a field.
@@ -376,6 +609,11 @@ Rules for using the #\[savefile_versions] attribute:
  really long, so we want to make sure that the overhead when serializing this is
  as low as possible.
 
+ Consecutive fields of fixed size primitive types, like `u32` and `f64`, are always written to the
+ file with one write, instead of one per field. The derive macro recognizes these fields by the name
+ of their type, so a type of your own with the name of a primitive type, like `u32`, can't be used as
+ the type of a field.
+
  Savefile has an unsafe trait [crate::ReprC] that you can implement for a type T. This instructs
  Savefile to optimize serialization of Vec<T> into being a very fast, raw memory copy.
 
@@ -392,7 +630,8 @@ Rules for using the #\[savefile_versions] attribute:
   that the #\[repr(C)] trait does not do this - it will include padding if needed for alignment
   reasons. You should not use #\[repr(packed)], since that may lead to unaligned struct fields.
   Instead, you should use #\[repr(C)] combined with manual padding, if necessary.
- If the type is an enum, it must be #\[repr(u8)] .
+ If the type is an enum, it must be #\[repr(u8)], or have the size given by the savefile_enum_repr attribute.
+ Enums with explicit discriminants, like `A = 5`, must have the savefile_enum_as_int attribute.
 
  For example, don't do:
  ```
@@ -433,6 +672,10 @@ Rules for using the #\[savefile_versions] attribute:
  detected in release builds. Serializing or deserializing each [crate::ReprC] struct at least once somewhere in your test suite
  is recommended.
 
+ The `#[derive(Savefile)]` macro automatically makes use of a `ReprC` implementation
+ for non-generic types. For generic types, or types with hand-written [crate::WithSchema] implementations,
+ override [crate::WithSchema::repr_c_layout] and return `IsReprC::of(version)` to enable the optimization.
+
 
  ```
  extern crate savefile;
@@ -504,6 +747,9 @@ Rules for using the #\[savefile_versions] attribute:
  to/from the binary stream. The Schema is meant as an extra sanity check, not as an exact format
  specification. The quality of this sanity check will depend on the implementation.
 
+ The [crate::Serializer] and [crate::Deserializer] are generic over the underlying writer and reader,
+ so the methods take `&mut Serializer<impl Write>` and `&mut Deserializer<impl Read>` respectively.
+
 
 
  ````rust
@@ -512,18 +758,19 @@ Rules for using the #\[savefile_versions] attribute:
      path: String,
  }
  use savefile::prelude::*;
+ use std::io::{Read, Write};
  impl WithSchema for MyPathBuf {
      fn schema(_version: u32) -> Schema {
          Schema::Primitive(SchemaPrimitive::schema_string)
      }
  }
  impl Serialize for MyPathBuf {
-     fn serialize<'a>(&self, serializer: &mut Serializer<'a>) -> Result<(), SavefileError> {
+     fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
          self.path.serialize(serializer)
      }
  }
  impl Deserialize for MyPathBuf {
-     fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
          Ok(MyPathBuf { path : String::deserialize(deserializer)? } )
      }
  }
@@ -687,6 +934,105 @@ Rules for using the #\[savefile_versions] attribute:
 
 /// The prelude contains all definitions thought to be needed by typical users of the library
 pub mod prelude;
+mod abi;
+mod archive;
+#[cfg(feature = "fs")]
+mod autosave;
+mod borrowed;
+mod columns;
+mod delta;
+mod dense_map;
+mod dynamic;
+#[cfg(feature = "encryption")]
+mod encryption;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "bitflags")]
+mod flags;
+#[cfg(feature = "fs")]
+mod golden;
+mod history;
+#[cfg(feature = "js")]
+pub mod js;
+mod json;
+mod lazy;
+mod migrator;
+mod namespace;
+#[cfg(feature = "memmap2")]
+mod mmap;
+mod packed_bools;
+mod packed_options;
+#[cfg(feature = "fs")]
+mod persistent;
+mod progress;
+mod proto;
+#[cfg(feature = "python")]
+pub mod python;
+mod random;
+mod registry;
+#[cfg(feature = "fs")]
+mod save_log;
+#[cfg(feature = "fs")]
+mod saved;
+mod seq;
+mod slice_reader;
+pub mod stream;
+mod string_block;
+mod tagged;
+#[cfg(feature = "tokio")]
+mod tokio_io;
+mod trait_object;
+mod vec_stream;
+pub use abi::{AbiCall, AbiConnection, AbiEntry, AbiExport, AbiMethod, AbiProvider};
+pub use archive::{ArchiveReader, ArchiveWriter, SaveArchive};
+#[cfg(feature = "fs")]
+pub use autosave::Autosaver;
+pub use borrowed::{load_borrowed, BorrowedDeserializer, DeserializeBorrowed};
+pub use columns::Columns;
+pub use delta::{apply_delta, save_delta};
+pub use dense_map::DenseMap;
+pub use dynamic::{diff_serialized, diff_values, Document, DynValue, ValueDifference};
+#[cfg(feature = "encryption")]
+pub use encryption::{load_encrypted, save_encrypted, CryptoReader, CryptoWriter};
+#[cfg(all(feature = "encryption", feature = "fs"))]
+pub use encryption::{load_encrypted_file, save_encrypted_file};
+#[cfg(feature = "bitflags")]
+pub use flags::{deserialize_flags, flags_schema, introspect_flags, serialize_flags, UnknownBits};
+#[cfg(feature = "fs")]
+pub use golden::{check_golden, check_schema_stable};
+pub use history::{HistoryField, HistoryNode, HistoryVariant, SchemaHistory};
+pub use json::{from_json, to_json};
+pub use lazy::Lazy;
+pub use migrator::Migrator;
+pub use namespace::{deserialize_in_namespace, serialize_in_namespace, VersionNamespace};
+#[cfg(feature = "memmap2")]
+pub use mmap::{load_file_mmap, MappedFile};
+pub use packed_bools::PackedBools;
+pub use packed_options::PackedOptions;
+#[cfg(feature = "fs")]
+pub use persistent::{Persistent, PersistentCommand};
+pub use progress::{CancellationToken, Progress, ProgressReport};
+use progress::{ProgressHooks, ProgressTracker};
+pub use random::{fuzz_corpus, mutate_bytes, random_value, roundtrip_check};
+#[cfg(feature = "quickcheck")]
+pub use random::ArbitraryValue;
+#[cfg(feature = "proptest")]
+pub use random::{type_strategy, value_strategy};
+pub use registry::SchemaRegistry;
+#[cfg(feature = "fs")]
+pub use save_log::{SaveLog, SaveLogReader};
+#[cfg(feature = "fs")]
+pub use saved::Saved;
+pub use seq::{deserialize_seq, serialize_iter, SeqDeserializer, SeqSerializer};
+pub use slice_reader::{SliceDeserializer, SliceReader};
+pub use string_block::StringBlock;
+pub use tagged::{load_tagged_any, save_tagged, TypeTags};
+pub use trait_object::{SavefileTraitObject, TypeRegistry};
+pub use vec_stream::{load_into_map, ChunkedVecWriter, MapReader, VecReader, VecWriter};
+#[cfg(feature = "tokio")]
+pub use tokio_io::{load_async, save_async};
+#[cfg(all(feature = "tokio", feature = "fs"))]
+pub use tokio_io::{load_file_async, save_file_async};
 extern crate alloc;
 extern crate arrayvec;
 extern crate byteorder;
@@ -694,27 +1040,48 @@ extern crate parking_lot;
 extern crate smallvec;
 use parking_lot::{Mutex, MutexGuard};
 use parking_lot::{RwLock, RwLockReadGuard};
+#[cfg(feature = "fs")]
 use std::fs::File;
 use std::io::Read;
+#[cfg(feature = "fs")]
+use std::io::{BufReader, BufWriter, Seek, SeekFrom};
 use std::borrow::Cow;
-use std::io::{Error, ErrorKind, Write};
+use std::io::{Error, Write};
 use std::sync::atomic::{
     AtomicBool, AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicIsize, AtomicU16, AtomicU32, AtomicU64, AtomicU8,
     AtomicUsize, Ordering,
 };
 
-use self::byteorder::LittleEndian;
+use self::byteorder::{BigEndian, LittleEndian};
 use std::collections::BinaryHeap;
 use std::collections::VecDeque;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
 #[allow(unused_imports)]
 use std::mem::MaybeUninit;
+use std::num::Wrapping;
 extern crate indexmap;
 use indexmap::IndexMap;
 use indexmap::IndexSet;
 extern crate bit_vec;
+#[cfg(feature = "bzip2")]
 extern crate bzip2;
+extern crate crc32fast;
+extern crate sha2;
+#[cfg(feature = "encryption")]
+extern crate rand;
+#[cfg(feature = "encryption")]
+extern crate ring;
+#[cfg(feature = "gzip")]
+extern crate flate2;
+#[cfg(feature = "lz4")]
+extern crate lz4_flex;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "bitflags")]
+extern crate bitflags;
 
 
 
@@ -772,6 +1139,125 @@ pub enum SavefileError {
     },
     /// A poisoned mutex was encountered when traversing the object being saved
     PoisonedMutex,
+    /// The checksum stored in the file does not match the data read. The file is corrupt.
+    ChecksumMismatch {
+        /// The checksum stored in the file
+        expected: u32,
+        /// The checksum of the data actually read
+        actual: u32,
+    },
+    /// Deserialization failed. This wraps the actual cause, adding information
+    /// about where in the data the failure occurred.
+    DeserializationFailed {
+        /// Number of bytes of the payload successfully read before the failure.
+        /// The payload is the data following the file header and schema.
+        /// None if the deserialization was not started through one of the load-functions.
+        offset: Option<u64>,
+        /// Path to the field being read, like `inventory/*/name`. Each
+        /// vector element is represented by a '*'. Empty if the failure
+        /// was not inside any field.
+        path: String,
+        /// The underlying error
+        cause: Box<SavefileError>,
+    },
+    /// A value was rejected by the function given in its `#[savefile_validate]` attribute,
+    /// either before it was saved, or after it was loaded.
+    Validation {
+        /// Path to the rejected value, like `inventory/*/name`, in the same form as for
+        /// [SavefileError::DeserializationFailed]. Empty if the saved or loaded value itself
+        /// was rejected.
+        path: String,
+        /// The message returned by the validation function
+        message: String,
+    },
+    /// The save or load was cancelled through the [CancellationToken] of its [Progress]
+    Cancelled,
+    /// An error of the application, returned by a custom implementation of Serialize or
+    /// Deserialize. See [SavefileError::custom] and [SavefileError::downcast_custom].
+    Custom(Box<dyn std::error::Error + Send + Sync>),
+    /// Loading needs more memory than the budget given to [crate::load_with_memory_budget].
+    /// This is usually wrapped in [SavefileError::DeserializationFailed], whose path says
+    /// which field was being loaded.
+    MemoryBudgetExceeded {
+        /// The memory budget, in bytes
+        budget: u64,
+        /// The memory needed so far, including the allocation which didn't fit, in bytes
+        needed: u64,
+    },
+}
+
+impl SavefileError {
+    /// Wrap an error of the application, to return it from a custom implementation of
+    /// Serialize or Deserialize. Anything which can be turned into a boxed error can be
+    /// given, including strings.
+    pub fn custom(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> SavefileError {
+        SavefileError::Custom(error.into())
+    }
+    /// The error of type E given to [SavefileError::custom], if this is such an error.
+    /// Errors wrapped by [SavefileError::DeserializationFailed] are looked for as well,
+    /// since errors returned while loading are wrapped with the path of the failing field.
+    pub fn downcast_custom<E: std::error::Error + 'static>(&self) -> Option<&E> {
+        match self {
+            SavefileError::Custom(error) => error.downcast_ref(),
+            SavefileError::DeserializationFailed { cause, .. } => cause.downcast_custom(),
+            _ => None,
+        }
+    }
+    /// Add the given path segment (typically a field name) in front of the
+    /// path of this error. Used by derived Deserialize-implementations to
+    /// report which field failed to deserialize.
+    pub fn with_path_segment(self, segment: &str) -> SavefileError {
+        match self {
+            SavefileError::Validation { .. } => self.with_validation_path_segment(segment),
+            SavefileError::Cancelled => self,
+            SavefileError::DeserializationFailed { offset, path, cause } => SavefileError::DeserializationFailed {
+                offset,
+                path: if path.is_empty() {
+                    segment.to_string()
+                } else {
+                    format!("{}/{}", segment, path)
+                },
+                cause,
+            },
+            other => SavefileError::DeserializationFailed {
+                offset: None,
+                path: segment.to_string(),
+                cause: Box::new(other),
+            },
+        }
+    }
+    /// Like [SavefileError::with_path_segment], except only [SavefileError::Validation] errors
+    /// are changed. Used by derived Serialize-implementations, to report which field was rejected.
+    pub fn with_validation_path_segment(self, segment: &str) -> SavefileError {
+        match self {
+            SavefileError::Validation { path, message } => SavefileError::Validation {
+                path: if path.is_empty() {
+                    segment.to_string()
+                } else {
+                    format!("{}/{}", segment, path)
+                },
+                message,
+            },
+            other => other,
+        }
+    }
+    fn with_offset(self, offset: u64) -> SavefileError {
+        match self {
+            // The value was read successfully, so the offset isn't interesting
+            SavefileError::Validation { .. } => self,
+            SavefileError::Cancelled => self,
+            SavefileError::DeserializationFailed { path, cause, .. } => SavefileError::DeserializationFailed {
+                offset: Some(offset),
+                path,
+                cause,
+            },
+            other => SavefileError::DeserializationFailed {
+                offset: Some(offset),
+                path: String::new(),
+                cause: Box::new(other),
+            },
+        }
+    }
 }
 
 impl Display for SavefileError {
@@ -810,12 +1296,56 @@ impl Display for SavefileError {
             SavefileError::PoisonedMutex => {
                 write!(f, "Poisoned mutex")
             }
+            SavefileError::ChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Checksum mismatch, the file is corrupt (expected {:08x}, got {:08x})",
+                    expected, actual
+                )
+            }
+            SavefileError::DeserializationFailed { offset, path, cause } => {
+                write!(f, "Deserialization failed")?;
+                if let Some(offset) = offset {
+                    write!(f, " at payload offset {}", offset)?;
+                }
+                if !path.is_empty() {
+                    write!(f, " in field [{}]", path)?;
+                }
+                write!(f, ": {}", cause)
+            }
+            SavefileError::Validation { path, message } => {
+                write!(f, "Validation failed")?;
+                if !path.is_empty() {
+                    write!(f, " in field [{}]", path)?;
+                }
+                write!(f, ": {}", message)
+            }
+            SavefileError::Cancelled => {
+                write!(f, "Cancelled")
+            }
+            SavefileError::Custom(error) => {
+                write!(f, "{}", error)
+            }
+            SavefileError::MemoryBudgetExceeded { budget, needed } => {
+                write!(
+                    f,
+                    "Memory budget of {} bytes exceeded, at least {} bytes are needed",
+                    budget, needed
+                )
+            }
         }
     }
 }
 
 impl std::error::Error for SavefileError {
-
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SavefileError::IOError { io_error } => Some(io_error),
+            SavefileError::DeserializationFailed { cause, .. } => Some(&**cause),
+            SavefileError::Custom(error) => Some(&**error),
+            _ => None,
+        }
+    }
 }
 
 
@@ -823,870 +1353,3217 @@ impl std::error::Error for SavefileError {
 /// Object to which serialized data is to be written.
 /// This is basically just a wrapped `std::io::Write` object
 /// and a file protocol version number.
-pub struct Serializer<'a> {
-    writer: &'a mut dyn Write,
+///
+/// The Serializer is generic over the writer, so that writing primitives
+/// does not need any dynamic dispatch. If a type-erased serializer is needed,
+/// use [DynSerializer], since `&mut dyn Write` also implements `Write`.
+pub struct Serializer<'a, W: Write> {
+    writer: &'a mut W,
     /// The version of the data structures in memory which are being serialized.
     pub version: u32,
+    /// How the data is encoded
+    pub encoding: Encoding,
+    /// The strings written so far, with their index, if strings are interned
+    interned_strings: HashMap<String, u64>,
+    /// Application state given to [crate::save_with_context], see [Serializer::context]
+    context: Option<&'a mut (dyn Any + 'static)>,
+    /// The progress given to [crate::save_with_progress], see [Serializer::enter_field]
+    progress: Option<ProgressTracker<'a>>,
 }
 
+/// A [Serializer] writing to a type-erased writer, see [SerializeDyn]
+pub type DynSerializer<'a, 'b> = Serializer<'a, &'b mut dyn Write>;
+
 /// Object from which bytes to be deserialized are read.
 /// This is basically just a wrapped `std::io::Read` object,
 /// the version number of the file being read, and the
 /// current version number of the data structures in memory.
-pub struct Deserializer<'a> {
-    reader: &'a mut dyn Read,
+///
+/// Like [Serializer], this is generic over the reader. Use [DynDeserializer]
+/// if a type-erased deserializer is needed.
+pub struct Deserializer<'a, R: Read> {
+    reader: &'a mut R,
     /// The version of the input file
     pub file_version: u32,
     /// The version of the data structures in memory
     pub memory_version: u32,
+    /// How the data in the file is encoded
+    pub encoding: Encoding,
     /// This contains ephemeral state that can be used to implement de-duplication of
     /// strings or possibly other situations where it is desired to deserialize DAGs.
     ephemeral_state: HashMap<TypeId, Box<dyn Any>>,
+    /// The strings read so far, if strings are interned
+    interned_strings: Vec<String>,
+    /// Reused buffer for reading strings
+    scratch: Vec<u8>,
+    /// If true, strings are assumed to be valid utf8, see [Deserializer::set_unchecked_utf8]
+    unchecked_utf8: bool,
+    /// Application state given to [crate::load_with_context], see [Deserializer::context]
+    context: Option<&'a mut (dyn Any + 'static)>,
+    /// The progress given to [crate::load_with_progress], see [Deserializer::enter_field]
+    progress: Option<ProgressTracker<'a>>,
+    /// The budget given to [crate::load_with_memory_budget], see [Deserializer::account_memory]
+    memory_budget: Option<MemoryBudget>,
+}
+
+/// The memory a [Deserializer] may allocate, and how much it has allocated
+#[derive(Debug, Clone, Copy)]
+struct MemoryBudget {
+    limit: u64,
+    used: u64,
+}
+
+/// Strings up to this length are read through [Deserializer]'s scratch buffer
+const SCRATCH_BUFFER_LIMIT: usize = 64 * 1024;
+
+/// Collections are created with room for at most this many items before they are read,
+/// and grow as needed after that. The length in the file can't be trusted, and creating
+/// a collection with room for an absurd number of items would abort the process.
+const MAX_PREALLOCATED_ITEMS: usize = 4096;
+
+/// A Read-wrapper keeping track of the number of bytes read,
+/// so that deserialization errors can report where they occurred.
+struct CountingReader<'a, R: Read> {
+    reader: &'a mut R,
+    count: &'a Cell<u64>,
+}
+
+impl<'a, R: Read> Read for CountingReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let got = self.reader.read(buf)?;
+        self.count.set(self.count.get() + got as u64);
+        Ok(got)
+    }
+    // Forwarded, so that the fast read_exact of readers like SliceReader is used
+    #[inline]
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.reader.read_exact(buf)?;
+        self.count.set(self.count.get() + buf.len() as u64);
+        Ok(())
+    }
 }
 
-impl<'a> Deserializer<'a> {
-    /// This function constructs a temporary state object of type R, and returns a mutable
-    /// reference to it. This object can be used to store data that needs to live for the entire
-    /// deserialization session. An example is de-duplicating Arc and other reference counted objects.
-    /// Out of the box, Arc<str> has this deduplication done for it.
-    /// The type T must be set to the type being deserialized, and is used as a key in a hashmap
-    /// separating the state for different types.
-    pub fn get_state<T: 'static, R: Default + 'static>(&mut self) -> &mut R {
-        let type_id = TypeId::of::<T>();
-        let the_any = self
-            .ephemeral_state
-            .entry(type_id)
-            .or_insert_with(|| Box::new(R::default()));
+const SAVEFILE_MAGIC: &[u8; 9] = b"savefile\0";
 
-        the_any.downcast_mut().unwrap()
-    }
-}
+/// The savefile format version written by this version of the crate.
+/// Version 0 files only had the data version and a compression flag in their header.
+const SAVEFILE_FORMAT_VERSION: u16 = 1;
 
-/// This is a marker trait for types which have an in-memory layout that is packed
-/// and therefore identical to the layout that savefile will use on disk.
-/// This means that types for which this trait is implemented can be serialized
-/// very quickly by just writing their raw bits to disc.
-///
-/// Rules to implement this trait:
-///
-/// * The type must be copy
-/// * The type must not contain any padding
-/// * The type must have a strictly deterministic memory layout (no field order randomization). This typically means repr(C)
-/// * All the constituent types of the type must also implement `ReprC` (correctly).
-pub unsafe trait ReprC: Copy {
-    /// This method returns true if the optimization is allowed
-    /// for the protocol version given as an argument.
-    /// This may return true if and only if the given protocol version
-    /// has a serialized format identical to the given protocol version.
-    fn repr_c_optimization_safe(version: u32) -> bool;
-}
+/// Format version of files whose payload starts with the versions of the namespaces of their
+/// types, see [VersionNamespace]. Only such files have this format version, so that older
+/// versions of savefile can still read the others.
+const NAMESPACES_FORMAT_VERSION: u16 = 2;
 
-impl From<std::io::Error> for SavefileError {
-    fn from(s: std::io::Error) -> SavefileError {
-        SavefileError::IOError { io_error: s }
-    }
-}
+/// Offset of the payload length in a header of the current format version.
+const PAYLOAD_LENGTH_OFFSET: u64 = 17;
 
-impl<T> From<std::sync::PoisonError<T>> for SavefileError {
-    fn from(_: std::sync::PoisonError<T>) -> SavefileError {
-        SavefileError::PoisonedMutex
-    }
-}
+/// Length of a header of the current format version.
+const HEADER_LENGTH: u64 = 33;
 
-impl From<std::string::FromUtf8Error> for SavefileError {
-    fn from(s: std::string::FromUtf8Error) -> SavefileError {
-        SavefileError::InvalidUtf8 { msg: s.to_string() }
-    }
-}
+/// Value used to signal that the payload length is not known.
+const UNKNOWN_PAYLOAD_LENGTH: u64 = std::u64::MAX;
 
-impl<T> From<arrayvec::CapacityError<T>> for SavefileError {
-    fn from(s: arrayvec::CapacityError<T>) -> SavefileError {
-        SavefileError::ArrayvecCapacityError { msg: s.to_string() }
-    }
+/// The compression methods which can be used for the payload of a file.
+/// Each requires enabling the cargo feature of the same name (`bzip2`, `gzip`,
+/// `zstd` or `lz4`). Only `bzip2` is enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
+    /// The payload is not compressed
+    None,
+    /// bzip2. Requires the `bzip2` feature, which is enabled by default.
+    Bzip2,
+    /// gzip. Requires the `gzip` feature.
+    Gzip,
+    /// zstd. Requires the `zstd` feature.
+    Zstd,
+    /// lz4, using the lz4 frame format. Requires the `lz4` feature.
+    Lz4,
 }
 
-impl WithSchema for PathBuf {
-    fn schema(_version: u32) -> Schema {
-        Schema::Primitive(SchemaPrimitive::schema_string)
+impl CompressionMethod {
+    fn to_u8(self) -> u8 {
+        match self {
+            CompressionMethod::None => 0,
+            CompressionMethod::Bzip2 => 1,
+            CompressionMethod::Gzip => 2,
+            CompressionMethod::Zstd => 3,
+            CompressionMethod::Lz4 => 4,
+        }
     }
-}
-impl Serialize for PathBuf {
-    fn serialize<'a>(&self, serializer: &mut Serializer<'a>) -> Result<(), SavefileError> {
-        let as_string: String = self.to_string_lossy().to_string();
-        as_string.serialize(serializer)
+    fn from_u8(value: u8) -> Result<CompressionMethod, SavefileError> {
+        Ok(match value {
+            0 => CompressionMethod::None,
+            1 => CompressionMethod::Bzip2,
+            2 => CompressionMethod::Gzip,
+            3 => CompressionMethod::Zstd,
+            4 => CompressionMethod::Lz4,
+            _ => {
+                return Err(SavefileError::GeneralError {
+                    msg: format!("Unknown compression method: {}", value),
+                })
+            }
+        })
     }
-}
-impl Deserialize for PathBuf {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        Ok(PathBuf::from(String::deserialize(deserializer)?))
+    #[allow(dead_code)]
+    fn not_enabled(self) -> SavefileError {
+        SavefileError::GeneralError {
+            msg: format!(
+                "Compression method {:?} is not available. Enable the corresponding feature of the savefile crate.",
+                self
+            ),
+        }
     }
 }
-impl Introspect for PathBuf {
-    fn introspect_value(&self) -> String {
-        self.to_string_lossy().to_string()
-    }
 
-    fn introspect_child<'a>(&'a self, _index: usize) -> Option<Box<dyn IntrospectItem<'a>>> {
-        None
+/// How the lengths of strings and collections are written, see [Encoding].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LengthEncoding {
+    /// Lengths are written as 8 byte integers. This is the default.
+    #[default]
+    Fixed64,
+    /// Lengths are written as 4 byte integers. Saving fails for strings and collections
+    /// with 2^32 - 1 or more elements.
+    Fixed32,
+    /// Lengths are written as variable length integers (unsigned LEB128), which take one byte
+    /// for lengths below 128, two bytes for lengths below 16384, and so on. Values of type usize
+    /// are still written as 8 byte integers, since their schema is that of a u64.
+    Varint,
+}
+
+/// The byte order of the integers and floats in the data, see [Encoding].
+/// The header of the file is always little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endianness {
+    /// Least significant byte first. This is the default.
+    #[default]
+    Little,
+    /// Most significant byte first, as used by many network protocols and some embedded targets.
+    Big,
+}
+
+impl Endianness {
+    /// The byte order of the machine the program runs on
+    pub fn native() -> Endianness {
+        if cfg!(target_endian = "big") {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        }
     }
 }
 
-impl<'a, T: 'a + WithSchema + ToOwned + ?Sized> WithSchema for Cow<'a, T> {
-    fn schema(version: u32) -> Schema {
-        T::schema(version)
-    }
+/// Details of how data is encoded, which aren't described by the schema.
+/// The encoding used is recorded in the header of a file, so loading detects it
+/// automatically. Files can be saved with a non-default encoding using [crate::save_with_encoding].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Encoding {
+    /// How the lengths of strings and collections are written.
+    /// Values of type usize are not affected, since their schema is that of a u64.
+    pub lengths: LengthEncoding,
+    /// The byte order of integers and floats, including lengths of 4 or 8 bytes.
+    pub endianness: Endianness,
+    /// If true, each distinct string is only written the first time it occurs. Later
+    /// occurrences refer to it by the order in which it first occurred. This makes files
+    /// with many repeated strings, such as identifiers, much smaller.
+    pub intern_strings: bool,
 }
-impl<'a, T: 'a + Serialize + ToOwned + ?Sized> Serialize for Cow<'a, T> {
-    fn serialize<'b>(&self, serializer: &mut Serializer<'b>) -> Result<(), SavefileError> {
-        (**self).serialize(serializer)
+
+impl Encoding {
+    /// True if values in memory have the same bytes as in the data, so values of
+    /// [ReprC] types can be copied directly. This is only the case if the endianness
+    /// is that of the machine.
+    pub fn allows_raw_copy(&self) -> bool {
+        self.endianness == Endianness::native()
     }
 }
-impl<'a, T: 'a + WithSchema + ToOwned + ?Sized> Deserialize for Cow<'a, T>
-where
-    T::Owned: Deserialize,
-{
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        Ok(Cow::Owned(<T as ToOwned>::Owned::deserialize(deserializer)?))
-    }
+
+/// Calls a byteorder method with the byte order of the given [Endianness]
+macro_rules! with_byte_order {
+    ($endianness:expr, $target:ident.$field:ident.$method:ident($($arg:expr),*)) => {
+        match $endianness {
+            Endianness::Little => $target.$field.$method::<LittleEndian>($($arg),*),
+            Endianness::Big => $target.$field.$method::<BigEndian>($($arg),*),
+        }
+    };
 }
-impl<'a, T: 'a + Introspect + ToOwned + ?Sized> Introspect for Cow<'a, T> {
-    fn introspect_value(&self) -> String {
-        (**self).introspect_value()
-    }
 
-    fn introspect_child<'b>(&'b self, index: usize) -> Option<Box<dyn IntrospectItem<'b> + 'b>> {
-        (**self).introspect_child(index)
+/// Header of a savefile file.
+/// Every file starts with the magic bytes `savefile\0`, followed by the information
+/// in this struct. Use [crate::read_header] to read it without loading the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavefileHeader {
+    /// Version of the savefile file format itself (not of the user data). This is 2 for files
+    /// recording the versions of [VersionNamespace]s, and 1 for other files.
+    pub format_version: u16,
+    /// The version of the user data structures in the file
+    pub version: u32,
+    /// How the payload is compressed
+    pub compression: CompressionMethod,
+    /// True if a schema precedes the data. This is None for files written
+    /// by older versions of savefile, which didn't record this.
+    pub has_schema: Option<bool>,
+    /// True if the schema and data are each followed by a CRC32 checksum,
+    /// which is verified when loading. The checksum of the schema also covers the
+    /// header, up to the payload length.
+    pub has_checksum: bool,
+    /// True if the payload is encrypted and authenticated, see [crate::save_encrypted].
+    pub encrypted: bool,
+    /// True if only a 64 bit hash of the schema precedes the data, instead of the
+    /// full schema. See [crate::save_with_schema_hash].
+    pub has_schema_hash: bool,
+    /// True if the schemas of all versions up to the version of the file precede the data,
+    /// instead of only the schema of that version. See [crate::save_with_schema_history].
+    pub has_schema_history: bool,
+    /// Number of bytes following the header, if known.
+    /// This is only known if the file was saved to a seekable destination or to memory.
+    pub payload_length: Option<u64>,
+    /// Point in time when the file was written, if known.
+    pub created: Option<std::time::SystemTime>,
+    /// How the data is encoded
+    pub encoding: Encoding,
+}
+
+/// The time to record in the header of a new file. The time is unknown on
+/// wasm32-unknown-unknown, where asking for it panics.
+fn creation_time() -> Option<std::time::SystemTime> {
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        None
+    } else {
+        Some(std::time::SystemTime::now())
     }
+}
 
-    fn introspect_len(&self) -> usize {
-        (**self).introspect_len()
+impl SavefileHeader {
+    fn write(&self, writer: &mut impl Write) -> Result<(), SavefileError> {
+        writer.write_all(SAVEFILE_MAGIC)?; //9
+        let format_version = if self.format_version >= NAMESPACES_FORMAT_VERSION {
+            NAMESPACES_FORMAT_VERSION
+        } else {
+            SAVEFILE_FORMAT_VERSION
+        };
+        writer.write_u16::<LittleEndian>(format_version)?; //11
+        writer.write_u32::<LittleEndian>(self.version)?; //15
+        writer.write_u8(self.compression.to_u8())?; //16
+        let mut flags = 0u8;
+        if self.has_schema == Some(true) {
+            flags |= 1;
+        }
+        if self.has_checksum {
+            flags |= 2;
+        }
+        if self.encrypted {
+            flags |= 4;
+        }
+        if self.has_schema_hash {
+            flags |= 8;
+        }
+        // A schema history is marked by both the schema and schema hash flags, so that
+        // older versions of savefile fail the hash check, instead of misreading the history.
+        if self.has_schema_history {
+            flags |= 1 | 8;
+        }
+        match self.encoding.lengths {
+            LengthEncoding::Fixed64 => {}
+            LengthEncoding::Varint => flags |= 16,
+            LengthEncoding::Fixed32 => flags |= 32,
+        }
+        if self.encoding.endianness == Endianness::Big {
+            flags |= 64;
+        }
+        if self.encoding.intern_strings {
+            flags |= 128;
+        }
+        writer.write_u8(flags)?; //17
+        writer.write_u64::<LittleEndian>(self.payload_length.unwrap_or(UNKNOWN_PAYLOAD_LENGTH))?; //25
+        let created = self
+            .created
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        writer.write_u64::<LittleEndian>(created)?; //33
+        Ok(())
     }
-}
 
-use ring::aead;
-use ring::aead::{BoundKey, Nonce, NonceSequence, OpeningKey, SealingKey, UnboundKey, AES_256_GCM};
-use ring::error::Unspecified;
-extern crate rand;
+    /// The part of the header which is authenticated when the payload is encrypted.
+    /// The payload length and creation time are not included, since the payload length
+    /// is only filled in after the payload has been written.
+    fn authenticated_bytes(&self) -> Result<Vec<u8>, SavefileError> {
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH as usize);
+        self.write(&mut bytes)?;
+        bytes.truncate(PAYLOAD_LENGTH_OFFSET as usize);
+        Ok(bytes)
+    }
 
-use byteorder::ReadBytesExt;
-use byteorder::WriteBytesExt;
-use rand::rngs::OsRng;
-use rand::RngCore;
+    fn read(reader: &mut impl Read) -> Result<SavefileHeader, SavefileError> {
+        let mut head: [u8; 9] = [0u8; 9];
+        reader.read_exact(&mut head)?;
 
-extern crate ring;
+        if &head != SAVEFILE_MAGIC {
+            return Err(SavefileError::GeneralError {msg: "File is not in new savefile-format. If you have a file in old format, contact crate author and we'll work something out! It is not the intention that binary compatibility will be broken any more in the future.".into()});
+        }
 
-#[derive(Debug)]
-struct RandomNonceSequence {
-    data1: u64,
-    data2: u32,
-}
-impl RandomNonceSequence {
-    pub fn new() -> RandomNonceSequence {
-        RandomNonceSequence {
-            data1: OsRng.next_u64(),
-            data2: OsRng.next_u32(),
+        let format_version = reader.read_u16::<LittleEndian>()?;
+        if format_version > NAMESPACES_FORMAT_VERSION {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "This file has been created by a later version of the savefile crate (file format version {}).",
+                    format_version
+                ),
+            });
         }
-    }
-    pub fn serialize(&self, writer: &mut dyn Write) -> Result<(), SavefileError> {
-        writer.write_u64::<LittleEndian>(self.data1)?;
-        writer.write_u32::<LittleEndian>(self.data2)?;
-        Ok(())
-    }
-    pub fn deserialize(reader: &mut dyn Read) -> Result<RandomNonceSequence, SavefileError> {
-        Ok(RandomNonceSequence {
-            data1: reader.read_u64::<LittleEndian>()?,
-            data2: reader.read_u32::<LittleEndian>()?,
+        let version = reader.read_u32::<LittleEndian>()?;
+        let compression = reader.read_u8()?;
+        if format_version == 0 {
+            return Ok(SavefileHeader {
+                format_version,
+                version,
+                compression: if compression != 0 {
+                    CompressionMethod::Bzip2
+                } else {
+                    CompressionMethod::None
+                },
+                has_schema: None,
+                has_checksum: false,
+                encrypted: false,
+                has_schema_hash: false,
+                has_schema_history: false,
+                payload_length: None,
+                created: None,
+                encoding: Encoding::default(),
+            });
+        }
+        let flags = reader.read_u8()?;
+        let payload_length = reader.read_u64::<LittleEndian>()?;
+        let created = reader.read_u64::<LittleEndian>()?;
+        Ok(SavefileHeader {
+            format_version,
+            version,
+            compression: CompressionMethod::from_u8(compression)?,
+            has_schema: Some(flags & 1 != 0),
+            has_checksum: flags & 2 != 0,
+            encrypted: flags & 4 != 0,
+            has_schema_hash: flags & 9 == 8,
+            has_schema_history: flags & 9 == 9,
+            payload_length: if payload_length == UNKNOWN_PAYLOAD_LENGTH {
+                None
+            } else {
+                Some(payload_length)
+            },
+            // A time too far in the future to be represented is treated as unknown
+            created: if created == 0 {
+                None
+            } else {
+                std::time::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(created))
+            },
+            encoding: Encoding {
+                lengths: if flags & 16 != 0 {
+                    LengthEncoding::Varint
+                } else if flags & 32 != 0 {
+                    LengthEncoding::Fixed32
+                } else {
+                    LengthEncoding::Fixed64
+                },
+                endianness: if flags & 64 != 0 {
+                    Endianness::Big
+                } else {
+                    Endianness::Little
+                },
+                intern_strings: flags & 128 != 0,
+            },
         })
     }
 }
 
-impl NonceSequence for RandomNonceSequence {
-    fn advance(&mut self) -> Result<Nonce, Unspecified> {
-        self.data2 = self.data2.wrapping_add(1);
-        if self.data2 == 0 {
-            self.data1 = self.data1.wrapping_add(1);
+/// Read the versions of the namespaces, and the schema or schema hash, preceding the data in a
+/// file, and unless `check_schema` is false, verify that the schema matches the in-memory schema
+/// of T for the versions of the file. Returns the versions of the namespaces, which must be
+/// active while the data is deserialized.
+fn check_file_schema<T: WithSchema, R: Read>(
+    reader: &mut ChecksumReader<R>,
+    header: &SavefileHeader,
+    has_schema: bool,
+    check_schema: bool,
+    check_schema_hash: bool,
+    external_schema: Option<ExternalSchema>,
+) -> Result<namespace::NamespaceVersions, SavefileError> {
+    let namespaces = namespace::read_namespace_versions(reader, header)?;
+    namespace::check_file_versions(&namespaces, || schema_of::<T>(header.version))?;
+    namespace::with_file_versions(Some(namespaces.clone()), || {
+        check_stored_schema::<T, R>(
+            reader,
+            header,
+            has_schema,
+            check_schema,
+            check_schema_hash,
+            external_schema,
+        )
+    })?;
+    Ok(namespaces)
+}
+
+fn check_stored_schema<T: WithSchema, R: Read>(
+    reader: &mut ChecksumReader<R>,
+    header: &SavefileHeader,
+    has_schema: bool,
+    check_schema: bool,
+    check_schema_hash: bool,
+    external_schema: Option<ExternalSchema>,
+) -> Result<(), SavefileError> {
+    let file_ver = header.version;
+    if external_schema.is_some() && !header.has_schema_hash && !has_schema {
+        return Err(SavefileError::GeneralError {
+            msg: "The data has no schema hash, so its schema can't be looked up".into(),
+        });
+    }
+    if header.has_schema_hash {
+        let file_hash = reader.read_u64::<LittleEndian>()?;
+        reader.verify()?;
+        if let Some(external_schema) = external_schema {
+            let external_schema = match external_schema {
+                ExternalSchema::Schema(schema) => schema,
+                ExternalSchema::Registry(registry) => registry.resolve(file_hash)?,
+            };
+            if file_hash != external_schema.stable_hash() {
+                return Err(SavefileError::IncompatibleSchema {
+                    message: format!(
+                        "Saved schema hash {:016x} differs from hash {:016x} of the schema file. The data was not saved with this schema file.",
+                        file_hash,
+                        external_schema.stable_hash()
+                    ),
+                });
+            }
+            let memory_schema = schema_of::<T>(file_ver);
+            if let Some(err) = diff_schema(&memory_schema, external_schema, ".".to_string()) {
+                return Err(SavefileError::IncompatibleSchema {
+                    message: format!(
+                        "Schema file differs from in-memory schema for version {}. Error: {}{}",
+                        file_ver,
+                        err,
+                        differing_subtrees(&memory_schema, external_schema)
+                    ),
+                });
+            }
+            return Ok(());
         }
-        use std::mem::transmute;
-        let mut bytes = [0u8; 12];
-        let bytes1: [u8; 8] = unsafe { transmute(self.data1.to_le()) };
-        let bytes2: [u8; 4] = unsafe { transmute(self.data2.to_le()) };
-        for i in 0..8 {
-            bytes[i] = bytes1[i];
+        let memory_hash = schema_of::<T>(file_ver).stable_hash();
+        if check_schema_hash && file_hash != memory_hash {
+            return Err(SavefileError::IncompatibleSchema {
+                message: format!(
+                    "Saved schema hash {:016x} differs from hash {:016x} of in-memory schema for version {}. The file does not contain the full schema, so no details are available.",
+                    file_hash, memory_hash, file_ver
+                ),
+            });
         }
-        for i in 0..4 {
-            bytes[i + 8] = bytes2[i];
+    } else if has_schema {
+        let file_schema = read_stored_schema(reader, file_ver, header.has_schema_history)?;
+        reader.verify()?;
+        let memory_schema = schema_of::<T>(file_ver);
+
+        if !check_schema {
+            // The caller asked us not to verify the schema
+        } else if let Some(err) = diff_schema(&memory_schema, &file_schema, ".".to_string()) {
+            return Err(SavefileError::IncompatibleSchema {
+                message: format!(
+                    "Saved schema differs from in-memory schema for version {}. Error: {}{}",
+                    file_ver,
+                    err,
+                    differing_subtrees(&memory_schema, &file_schema)
+                ),
+            });
         }
-
-        Ok(Nonce::assume_unique_for_key(bytes))
     }
+    Ok(())
 }
 
-/// A cryptographic stream wrapper.
-/// Wraps a plain dyn Write, and itself implements Write, encrypting
-/// all data written.
-pub struct CryptoWriter<'a> {
-    writer: &'a mut dyn Write,
-    buf: Vec<u8>,
-    sealkey: SealingKey<RandomNonceSequence>,
-    failed: bool,
+/// Read the schema preceding the data of a file, which is either the schema of the version of
+/// the file, or a [SchemaHistory] containing it. The schema itself is always written with the
+/// default encoding.
+fn read_stored_schema(reader: &mut impl Read, version: u32, has_schema_history: bool) -> Result<Schema, SavefileError> {
+    let mut schema_deserializer = Deserializer::new_raw(reader);
+    if has_schema_history {
+        SchemaHistory::deserialize(&mut schema_deserializer)?.schema(version)
+    } else {
+        Schema::deserialize(&mut schema_deserializer)
+    }
+}
+
+/// Read the header of a savefile file, without reading any of the data.
+/// This can be used to identify savefile files, and to find out which version
+/// they were written with. Afterwards, the reader is positioned at the start of the payload.
+pub fn read_header(reader: &mut impl Read) -> Result<SavefileHeader, SavefileError> {
+    SavefileHeader::read(reader)
+}
+
+/// Read the version and the schema of a savefile file, without knowing the type of the data.
+/// This can be used by tools which need to find out what a file contains.
+/// Fails if the file was saved without a full schema (for instance using
+/// [crate::save_noschema] or [crate::save_with_schema_hash]), or if it is encrypted.
+/// Compressed files are supported, if the compression method is enabled.
+pub fn read_schema(reader: &mut impl Read) -> Result<(u32, Schema), SavefileError> {
+    let header = SavefileHeader::read(reader)?;
+    if header.encrypted {
+        return Err(SavefileError::GeneralError {
+            msg: "The schema of an encrypted file can't be read.".into(),
+        });
+    }
+    if header.has_schema == Some(false) {
+        return Err(SavefileError::GeneralError {
+            msg: "The file does not contain a schema.".into(),
+        });
+    }
+    let mut decompressed = decompressing_reader(reader, header.compression)?;
+    let mut reader = ChecksumReader::new(&mut decompressed, &header)?;
+    namespace::read_namespace_versions(&mut reader, &header)?;
+    let schema = read_stored_schema(&mut reader, header.version, header.has_schema_history)?;
+    reader.verify()?;
+    Ok((header.version, schema))
+}
+
+/// Read the schema history of a file saved by [crate::save_with_schema_history].
+/// Returns None for files saved with only the schema of their version, and fails
+/// for the same files as [crate::read_schema].
+pub fn read_schema_history(reader: &mut impl Read) -> Result<Option<SchemaHistory>, SavefileError> {
+    let header = SavefileHeader::read(reader)?;
+    if header.encrypted {
+        return Err(SavefileError::GeneralError {
+            msg: "The schema of an encrypted file can't be read.".into(),
+        });
+    }
+    if header.has_schema == Some(false) {
+        return Err(SavefileError::GeneralError {
+            msg: "The file does not contain a schema.".into(),
+        });
+    }
+    if !header.has_schema_history {
+        return Ok(None);
+    }
+    let mut decompressed = decompressing_reader(reader, header.compression)?;
+    let mut reader = ChecksumReader::new(&mut decompressed, &header)?;
+    namespace::read_namespace_versions(&mut reader, &header)?;
+    let history = SchemaHistory::deserialize(&mut Deserializer::new_raw(&mut reader))?;
+    reader.verify()?;
+    Ok(Some(history))
+}
+
+/// A Write-wrapper keeping track of the number of bytes written.
+struct CountingWriter<'a, W: Write> {
+    writer: &'a mut W,
+    count: &'a Cell<u64>,
+}
+
+/// A Write-wrapper which optionally calculates a CRC32 checksum of everything written.
+struct ChecksumWriter<'a, W: Write> {
+    writer: &'a mut W,
+    hasher: Option<crc32fast::Hasher>,
+}
+
+impl<'a, W: Write> ChecksumWriter<'a, W> {
+    /// The first checksum also covers `header_bytes`, the authenticated bytes of the header,
+    /// which have already been written.
+    fn new(writer: &'a mut W, enabled: bool, header_bytes: &[u8]) -> ChecksumWriter<'a, W> {
+        ChecksumWriter {
+            writer,
+            hasher: if enabled {
+                Some(checksum_hasher(header_bytes))
+            } else {
+                None
+            },
+        }
+    }
+    /// Write the checksum of everything written so far, and start over.
+    fn write_checksum(&mut self) -> Result<(), SavefileError> {
+        if let Some(hasher) = self.hasher.replace(crc32fast::Hasher::new()) {
+            self.writer.write_u32::<LittleEndian>(hasher.finalize())?;
+        } else {
+            self.hasher = None;
+        }
+        Ok(())
+    }
 }
 
-/// A cryptographic stream wrapper.
-/// Wraps a plain dyn Read, and itself implements Read, decrypting
-/// and verifying all data read.
-pub struct CryptoReader<'a> {
-    reader: &'a mut dyn Read,
-    buf: Vec<u8>,
-    offset: usize,
-    openingkey: OpeningKey<RandomNonceSequence>,
+impl<'a, W: Write> Write for ChecksumWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let written = self.writer.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+    fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()
+    }
 }
 
-impl<'a> CryptoReader<'a> {
-    /// Create a new CryptoReader, wrapping the given Read . Decrypts using the given
-    /// 32 byte cryptographic key.
-    /// Crypto is 256 bit AES GCM
-    pub fn new(reader: &'a mut dyn Read, key_bytes: [u8; 32]) -> Result<CryptoReader<'a>, SavefileError> {
-        let unboundkey = UnboundKey::new(&AES_256_GCM, &key_bytes).unwrap();
+/// A hasher for the first checksum of a payload, which also covers the header
+fn checksum_hasher(header_bytes: &[u8]) -> crc32fast::Hasher {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(header_bytes);
+    hasher
+}
 
-        let nonce_sequence = RandomNonceSequence::deserialize(reader)?;
-        let openingkey = OpeningKey::new(unboundkey, nonce_sequence);
+/// A Read-wrapper which optionally calculates a CRC32 checksum of everything read.
+struct ChecksumReader<'a, R: Read> {
+    reader: &'a mut R,
+    hasher: Option<crc32fast::Hasher>,
+}
 
-        Ok(CryptoReader {
+impl<'a, R: Read> ChecksumReader<'a, R> {
+    /// A reader of the payload following the given header, which verifies checksums
+    /// if the header says there are any.
+    fn new(reader: &'a mut R, header: &SavefileHeader) -> Result<ChecksumReader<'a, R>, SavefileError> {
+        Ok(ChecksumReader {
             reader,
-            offset: 0,
-            buf: Vec::new(),
-            openingkey,
+            hasher: if header.has_checksum {
+                Some(checksum_hasher(&header.authenticated_bytes()?))
+            } else {
+                None
+            },
         })
     }
-}
-
-const CRYPTO_BUFSIZE: usize = 100_000;
-
-impl<'a> Drop for CryptoWriter<'a> {
-    fn drop(&mut self) {
-        self.flush().expect("The implicit flush in the Drop of CryptoWriter failed. This causes this panic. If you want to be able to handle this, make sure to call flush() manually. If a manual flush has failed, Drop won't panic.");
+    /// Read a checksum, and compare it with the checksum of everything read so far.
+    /// Then start over.
+    fn verify(&mut self) -> Result<(), SavefileError> {
+        if let Some(hasher) = self.hasher.replace(crc32fast::Hasher::new()) {
+            let actual = hasher.finalize();
+            let expected = self.reader.read_u32::<LittleEndian>()?;
+            if expected != actual {
+                return Err(SavefileError::ChecksumMismatch { expected, actual });
+            }
+        } else {
+            self.hasher = None;
+        }
+        Ok(())
     }
 }
-impl<'a> CryptoWriter<'a> {
-    /// Create a new CryptoWriter, wrapping the given Write . Encrypts using the given
-    /// 32 byte cryptographic key.
-    /// Crypto is 256 bit AES GCM
-    pub fn new(writer: &'a mut dyn Write, key_bytes: [u8; 32]) -> Result<CryptoWriter<'a>, SavefileError> {
-        let unboundkey = UnboundKey::new(&AES_256_GCM, &key_bytes).unwrap();
-        let nonce_sequence = RandomNonceSequence::new();
-        nonce_sequence.serialize(writer)?;
-        let sealkey = SealingKey::new(unboundkey, nonce_sequence);
-        Ok(CryptoWriter {
-            writer,
-            buf: Vec::new(),
-            sealkey,
-            failed: false,
-        })
+
+impl<'a, R: Read> Read for ChecksumReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let got = self.reader.read(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..got]);
+        }
+        Ok(got)
     }
-    /// Data is encrypted in chunks. Calling this unconditionally finalizes a chunk, actually emitting
-    /// data to the underlying dyn Write. When later reading data, an entire chunk must be read
-    /// from file before any plaintext is produced.
-    pub fn flush_final(mut self) -> Result<(), SavefileError> {
-        if self.failed {
-            panic!("Call to failed CryptoWriter");
+    #[inline]
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.reader.read_exact(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(buf);
         }
-        self.flush()?;
         Ok(())
     }
 }
-impl<'a> Read for CryptoReader<'a> {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        loop {
-            if buf.len() <= self.buf.len() - self.offset {
-                buf.clone_from_slice(&self.buf[self.offset..self.offset + buf.len()]);
-                self.offset += buf.len();
-                return Ok(buf.len());
-            }
 
-            {
-                let oldlen = self.buf.len();
-                let newlen = self.buf.len() - self.offset;
-                self.buf.copy_within(self.offset..oldlen, 0);
-                self.buf.resize(newlen, 0);
-                self.offset = 0;
-            }
-            let mut sizebuf = [0; 8];
-            let mut sizebuf_bytes_read = 0;
-            loop {
-                match self.reader.read(&mut sizebuf[sizebuf_bytes_read..]) {
-                    Ok(gotsize) => {
-                        if gotsize == 0 {
-                            if sizebuf_bytes_read == 0 {
-                                let cur_content_size = self.buf.len() - self.offset;
-                                buf[0..cur_content_size]
-                                    .clone_from_slice(&self.buf[self.offset..self.offset + cur_content_size]);
-                                self.offset += cur_content_size;
-                                return Ok(cur_content_size);
-                            } else {
-                                return Err(Error::new(ErrorKind::UnexpectedEof, "Unexpected EOF"));
-                            }
-                        } else {
-                            sizebuf_bytes_read += gotsize;
-                            assert!(sizebuf_bytes_read <= 8);
-                        }
-                    }
-                    Err(err) => return Err(err),
-                }
-                if sizebuf_bytes_read == 8 {
-                    break;
-                }
-            }
-            use byteorder::ByteOrder;
-            let curlen = byteorder::LittleEndian::read_u64(&sizebuf) as usize;
+impl<'a, W: Write> Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let written = self.writer.write(buf)?;
+        self.count.set(self.count.get() + written as u64);
+        Ok(written)
+    }
+    fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()
+    }
+}
 
-            if curlen > CRYPTO_BUFSIZE + 16 {
-                return Err(Error::new(ErrorKind::Other, "Cryptography error"));
-            }
-            let orglen = self.buf.len();
-            self.buf.resize(orglen + curlen, 0);
+impl<'a, R: Read> Deserializer<'a, R> {
+    /// This function constructs a temporary state object of type S, and returns a mutable
+    /// reference to it. This object can be used to store data that needs to live for the entire
+    /// deserialization session. An example is de-duplicating Arc and other reference counted objects.
+    /// Out of the box, Arc<str> has this deduplication done for it.
+    /// The type T must be set to the type being deserialized, and is used as a key in a hashmap
+    /// separating the state for different types.
+    pub fn get_state<T: 'static, S: Default + 'static>(&mut self) -> &mut S {
+        let type_id = TypeId::of::<T>();
+        let the_any = self
+            .ephemeral_state
+            .entry(type_id)
+            .or_insert_with(|| Box::new(S::default()));
 
-            self.reader.read_exact(&mut self.buf[orglen..orglen + curlen])?;
+        the_any.downcast_mut().unwrap()
+    }
 
-            match self
-                .openingkey
-                .open_in_place(aead::Aad::empty(), &mut self.buf[orglen..])
-            {
-                Ok(_) => {}
-                Err(_) => {
-                    return Err(Error::new(ErrorKind::Other, "Cryptography error"));
-                }
-            }
-            self.buf.resize(self.buf.len() - 16, 0);
-        }
+    /// The context given to [crate::load_with_context], if it is a C. Deserialize-implementations
+    /// can use it to resolve values against application state while loading, such as
+    /// looking up asset handles in a registry. Returns None if there is no context of type C.
+    pub fn context<C: Any>(&mut self) -> Option<&mut C> {
+        self.context.as_mut()?.downcast_mut()
     }
-}
-impl<'a> Write for CryptoWriter<'a> {
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
-        if self.failed {
-            panic!("Call to failed CryptoWriter");
+
+    /// Called by derived Deserialize-implementations before each field is read, and followed
+    /// by [Deserializer::leave_field] once it has been read. This reports progress and checks
+    /// for cancellation, when loading with [crate::load_with_progress].
+    pub fn enter_field(&mut self, name: &'static str) -> Result<(), SavefileError> {
+        match self.progress.as_mut() {
+            Some(progress) => progress.enter_field(name),
+            None => Ok(()),
         }
-        self.buf.extend(buf);
-        if self.buf.len() > CRYPTO_BUFSIZE {
-            self.flush()?;
+    }
+
+    /// Called after a field entered by [Deserializer::enter_field] has been read
+    pub fn leave_field(&mut self) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.leave_field();
         }
-        Ok(buf.len())
     }
 
-    /// Writes any non-written buffered bytes to the underlying stream.
-    /// If this fails, there is no recovery. The buffered data will have been
-    /// lost.
-    fn flush(&mut self) -> Result<(), Error> {
-        self.failed = true;
-        let mut offset = 0;
+    /// True if vectors and arrays of T are read by copying their memory, instead of item
+    /// by item, see [Serializer::uses_raw_copy]. This depends on the version of the file.
+    pub fn uses_raw_copy<T: WithSchema>(&self) -> bool {
+        T::repr_c_layout(self.file_version).is_yes() && self.encoding.allows_raw_copy()
+    }
 
-        let mut tempbuf = Vec::new();
-        if self.buf.len() > CRYPTO_BUFSIZE {
-            tempbuf = Vec::<u8>::with_capacity(CRYPTO_BUFSIZE + 16);
-        }
+    /// Check that the raw bytes of values of T read from the file are valid values,
+    /// before they are used as such, see [IsReprC::validate]
+    pub(crate) fn validate_raw<T: WithSchema>(&self, bytes: &[u8]) -> Result<(), SavefileError> {
+        T::repr_c_layout(self.file_version).validate(bytes)
+    }
 
-        while self.buf.len() > offset {
-            let curbuf;
-            if offset == 0 && self.buf.len() <= CRYPTO_BUFSIZE {
-                curbuf = &mut self.buf;
-            } else {
-                let chunksize = (self.buf.len() - offset).min(CRYPTO_BUFSIZE);
-                tempbuf.resize(chunksize, 0u8);
-                tempbuf.clone_from_slice(&self.buf[offset..offset + chunksize]);
-                curbuf = &mut tempbuf;
-            }
-            let expected_final_len = curbuf.len() as u64 + 16;
-            debug_assert!(expected_final_len <= CRYPTO_BUFSIZE as u64 + 16);
-
-            self.writer.write_u64::<LittleEndian>(expected_final_len)?; //16 for the tag
-            match self.sealkey.seal_in_place_append_tag(aead::Aad::empty(), curbuf) {
-                Ok(_) => {}
-                Err(_) => {
-                    return Err(Error::new(ErrorKind::Other, "Cryptography error"));
-                }
+    /// Count `bytes` of memory about to be allocated against the memory budget given to
+    /// [crate::load_with_memory_budget], failing with [SavefileError::MemoryBudgetExceeded]
+    /// if it doesn't fit. Vectors, strings and the standard collections call this before
+    /// allocating memory for their items. Deserialize-implementations which allocate large
+    /// buffers of their own should call it too. Without a budget, this does nothing.
+    pub fn account_memory(&mut self, bytes: u64) -> Result<(), SavefileError> {
+        if let Some(budget) = self.memory_budget.as_mut() {
+            let needed = budget.used.saturating_add(bytes);
+            if needed > budget.limit {
+                return Err(SavefileError::MemoryBudgetExceeded {
+                    budget: budget.limit,
+                    needed,
+                });
             }
-            debug_assert!(curbuf.len() == expected_final_len as usize, "The size of the TAG generated by the AES 256 GCM in ring seems to have changed! This is very unexpected. File a bug on the savefile-crate");
-
-            self.writer.write_all(&curbuf[..])?;
-            self.writer.flush()?;
-            offset += curbuf.len() - 16;
-            curbuf.resize(curbuf.len() - 16, 0);
+            budget.used = needed;
         }
-        self.buf.clear();
-        self.failed = false;
         Ok(())
     }
-}
 
-impl<'a> Serializer<'a> {
-    /// Writes a binary bool to the dyn Write
-    pub fn write_bool(&mut self, v: bool) -> Result<(), SavefileError> {
-        Ok(self.writer.write_u8(if v { 1 } else { 0 })?)
-    }
-    /// Writes a binary u8 to the dyn Write
-    pub fn write_u8(&mut self, v: u8) -> Result<(), SavefileError> {
-        Ok(self.writer.write_all(&[v])?)
-    }
-    /// Writes a binary i8 to the dyn Write
-    pub fn write_i8(&mut self, v: i8) -> Result<(), SavefileError> {
-        Ok(self.writer.write_i8(v)?)
+    /// Count the memory of `count` values of type T, see [Deserializer::account_memory]
+    fn account_items<T>(&mut self, count: usize) -> Result<(), SavefileError> {
+        self.account_memory((count as u64).saturating_mul(std::mem::size_of::<T>() as u64))
     }
+}
 
-    /// Writes a binary little endian u16 to the dyn Write
-    pub fn write_u16(&mut self, v: u16) -> Result<(), SavefileError> {
-        Ok(self.writer.write_u16::<LittleEndian>(v)?)
-    }
-    /// Writes a binary little endian i16 to the dyn Write
-    pub fn write_i16(&mut self, v: i16) -> Result<(), SavefileError> {
-        Ok(self.writer.write_i16::<LittleEndian>(v)?)
-    }
+/// This is a marker trait for types which have an in-memory layout that is packed
+/// and therefore identical to the layout that savefile will use on disk.
+/// This means that types for which this trait is implemented can be serialized
+/// very quickly by just writing their raw bits to disc.
+///
+/// Rules to implement this trait:
+///
+/// * The type must be copy
+/// * The type must not contain any padding
+/// * The type must have a strictly deterministic memory layout (no field order randomization). This typically means repr(C)
+/// * All the constituent types of the type must also implement `ReprC` (correctly).
+/// * If some bit patterns are not valid values of the type, they must be rejected by [ReprC::repr_c_validate]
+///
+/// The raw bits are only copied if the byte order of the data is that of the machine, so data
+/// is the same whether it was saved on a little endian or a big endian machine. Otherwise, the
+/// values are serialized one by one. See [Serializer::uses_raw_copy].
+pub unsafe trait ReprC: Copy {
+    /// This method returns true if the optimization is allowed
+    /// for the protocol version given as an argument.
+    /// This may return true if and only if the given protocol version
+    /// has a serialized format identical to the given protocol version.
+    fn repr_c_optimization_safe(version: u32) -> bool;
 
-    /// Writes a binary little endian u32 to the dyn Write
-    pub fn write_u32(&mut self, v: u32) -> Result<(), SavefileError> {
-        Ok(self.writer.write_u32::<LittleEndian>(v)?)
-    }
-    /// Writes a binary little endian i32 to the dyn Write
-    pub fn write_i32(&mut self, v: i32) -> Result<(), SavefileError> {
-        Ok(self.writer.write_i32::<LittleEndian>(v)?)
+    /// True if some bit patterns are not valid values of the type, like for enums and bools,
+    /// so that raw bytes read from a file must be checked by [ReprC::repr_c_validate] before
+    /// they are used as values. The default is false.
+    fn repr_c_needs_validation() -> bool {
+        false
     }
 
-    /// Writes a binary little endian f32 to the dyn Write
-    pub fn write_f32(&mut self, v: f32) -> Result<(), SavefileError> {
-        Ok(self.writer.write_f32::<LittleEndian>(v)?)
+    /// Checks that the raw bytes of any number of values, read from a file, are valid values
+    /// of the type. Only used if [ReprC::repr_c_needs_validation]. The default accepts all bytes.
+    fn repr_c_validate(_bytes: &[u8]) -> Result<(), SavefileError> {
+        Ok(())
     }
-    /// Writes a binary little endian f64 to the dyn Write
-    pub fn write_f64(&mut self, v: f64) -> Result<(), SavefileError> {
-        Ok(self.writer.write_f64::<LittleEndian>(v)?)
+}
+
+/// Checks raw bytes read from a file before they are used as values, see [ReprC::repr_c_validate]
+pub type ReprCValidator = fn(&[u8]) -> Result<(), SavefileError>;
+
+/// Proof that values of type T may be serialized and deserialized by
+/// simply copying their raw bytes, for some particular protocol version.
+///
+/// A value saying 'yes' can only be obtained for types implementing [ReprC],
+/// or by using unsafe code. This is what allows [WithSchema::repr_c_layout] to be
+/// a safe method, while still letting collections like `Vec<T>` rely on it.
+pub struct IsReprC<T> {
+    is_repr_c: bool,
+    validator: Option<ReprCValidator>,
+    phantom: PhantomData<T>,
+}
+
+impl<T> Clone for IsReprC<T> {
+    fn clone(&self) -> Self {
+        *self
     }
+}
+impl<T> Copy for IsReprC<T> {}
 
-    /// Writes a binary little endian u64 to the dyn Write
-    pub fn write_u64(&mut self, v: u64) -> Result<(), SavefileError> {
-        Ok(self.writer.write_u64::<LittleEndian>(v)?)
+impl<T> IsReprC<T> {
+    /// The raw-copy optimization may not be used for T.
+    pub fn no() -> IsReprC<T> {
+        IsReprC {
+            is_repr_c: false,
+            validator: None,
+            phantom: PhantomData,
+        }
     }
-    /// Writes a binary little endian i64 to the dyn Write
-    pub fn write_i64(&mut self, v: i64) -> Result<(), SavefileError> {
-        Ok(self.writer.write_i64::<LittleEndian>(v)?)
+    /// The raw-copy optimization may be used for T.
+    ///
+    /// # Safety
+    /// T must fulfill all the requirements of the [ReprC] trait, and all bit patterns
+    /// must be valid values of T.
+    pub unsafe fn yes() -> IsReprC<T> {
+        IsReprC {
+            is_repr_c: true,
+            validator: None,
+            phantom: PhantomData,
+        }
     }
-
-    /// Writes a binary little endian usize as u64 to the dyn Write
-    pub fn write_usize(&mut self, v: usize) -> Result<(), SavefileError> {
-        Ok(self.writer.write_u64::<LittleEndian>(v as u64)?)
+    /// The raw-copy optimization may be used for T, if the raw bytes read from a file are
+    /// first accepted by the validator. Without a validator, this is the same as [IsReprC::yes].
+    ///
+    /// # Safety
+    /// T must fulfill all the requirements of the [ReprC] trait, and all bit patterns
+    /// accepted by the validator must be valid values of T.
+    pub unsafe fn yes_validated(validator: Option<ReprCValidator>) -> IsReprC<T> {
+        IsReprC {
+            is_repr_c: true,
+            validator,
+            phantom: PhantomData,
+        }
     }
-    /// Writes a binary little endian isize as i64 to the dyn Write
-    pub fn write_isize(&mut self, v: isize) -> Result<(), SavefileError> {
-        Ok(self.writer.write_i64::<LittleEndian>(v as i64)?)
+    /// Returns true if the raw-copy optimization may be used for T.
+    pub fn is_yes(self) -> bool {
+        self.is_repr_c
     }
-    /// Writes a binary u8 array to the dyn Write
-    pub fn write_buf(&mut self, v: &[u8]) -> Result<(), SavefileError> {
-        Ok(self.writer.write_all(v)?)
+    /// The function checking raw bytes before they are used as values of T, if some bit
+    /// patterns are not valid values of T
+    pub fn validator(self) -> Option<ReprCValidator> {
+        self.validator
     }
-    /// Writes as a string as 64 bit length + utf8 data
-    pub fn write_string(&mut self, v: &str) -> Result<(), SavefileError> {
-        let asb = v.as_bytes();
-        self.write_usize(asb.len())?;
-        Ok(self.writer.write_all(asb)?)
+    /// Check that the raw bytes of any number of values read from a file are valid values of T
+    pub fn validate(self, bytes: &[u8]) -> Result<(), SavefileError> {
+        match self.validator {
+            Some(validator) => validator(bytes),
+            None => Ok(()),
+        }
     }
-    /// Writes a binary u8 array to the dyn Write. Synonym of write_buf.
-    pub fn write_bytes(&mut self, v: &[u8]) -> Result<(), SavefileError> {
-        Ok(self.writer.write_all(v)?)
+}
+
+impl<T: ReprC> IsReprC<T> {
+    /// Consult the [ReprC] implementation of T for the given version.
+    pub fn of(version: u32) -> IsReprC<T> {
+        IsReprC {
+            is_repr_c: T::repr_c_optimization_safe(version),
+            validator: if T::repr_c_needs_validation() {
+                Some(T::repr_c_validate)
+            } else {
+                None
+            },
+            phantom: PhantomData,
+        }
     }
+}
 
-    /// Creata a new serializer.
-    /// Don't use this function directly, use the [crate::save] function instead.
-    pub fn save<T: WithSchema + Serialize>(
-        writer: &mut dyn Write,
-        version: u32,
-        data: &T,
-        with_compression: bool,
-    ) -> Result<(), SavefileError> {
-        Ok(Self::save_impl(writer, version, data, true, with_compression)?)
+/// Checks that raw bytes are valid values of an enum without fields, given all its variants.
+/// Used by the [ReprC::repr_c_validate] generated by `#[derive(ReprC)]`.
+#[doc(hidden)]
+pub fn validate_repr_c_enum<T: ReprC>(bytes: &[u8], variants: &[T], name: &str) -> Result<(), SavefileError> {
+    let size = std::mem::size_of::<T>();
+    if size == 0 {
+        return Ok(());
     }
-    /// Creata a new serializer.
-    /// Don't use this function directly, use the [crate::save_noschema] function instead.
-    pub fn save_noschema<T: WithSchema + Serialize>(
-        writer: &mut dyn Write,
-        version: u32,
-        data: &T,
-    ) -> Result<(), SavefileError> {
-        Ok(Self::save_impl(writer, version, data, false, false)?)
+    // Safety: ReprC types have no padding, so all the bytes of the variants are initialized
+    let valid = unsafe { std::slice::from_raw_parts(variants.as_ptr() as *const u8, std::mem::size_of_val(variants)) };
+    let mut valid_bytes = [false; 256];
+    if size == 1 {
+        for &byte in valid {
+            valid_bytes[byte as usize] = true;
+        }
     }
-    fn save_impl<T: WithSchema + Serialize>(
-        writer: &mut dyn Write,
-        version: u32,
-        data: &T,
-        with_schema: bool,
-        with_compression: bool,
-    ) -> Result<(), SavefileError> {
-        let header = "savefile\0".to_string().into_bytes();
-
-        writer.write_all(&header)?; //9
-
-        writer.write_u16::<LittleEndian>(0 /*savefile format version*/)?;
-        writer.write_u32::<LittleEndian>(version)?;
-        // 9 + 2 + 4 = 15
+    for value in bytes.chunks_exact(size) {
+        let is_valid = if size == 1 {
+            valid_bytes[value[0] as usize]
+        } else {
+            valid.chunks_exact(size).any(|variant| variant == value)
+        };
+        if !is_valid {
+            return Err(SavefileError::GeneralError {
+                msg: format!("Corrupt file - unknown enum variant {:?} detected in {}.", value, name),
+            });
+        }
+    }
+    Ok(())
+}
 
-        {
-            let mut temp;
-            let writer: &mut dyn Write = if with_compression {
-                writer.write_u8(1)?; //15 + 1 = 16
-                temp = bzip2::write::BzEncoder::new(writer, Compression::Best);
-                &mut temp
-            } else {
-                writer.write_u8(0)?;
-                writer
-            };
+/// Helper used by the code generated by `#[derive(Savefile)]` to find out
+/// if a type implements [ReprC], without requiring specialization.
+/// A method call on `&ReprCProbe<T>` resolves to [ReprCProbeYes]
+/// if T is known to implement ReprC, and to [ReprCProbeNo] otherwise.
+#[doc(hidden)]
+pub struct ReprCProbe<T>(PhantomData<T>);
 
-            if with_schema {
-                let schema = T::schema(version);
-                let mut schema_serializer = Serializer::new_raw(writer);
-                schema.serialize(&mut schema_serializer)?;
-            }
+impl<T> ReprCProbe<T> {
+    #[doc(hidden)]
+    pub fn new() -> ReprCProbe<T> {
+        ReprCProbe(PhantomData)
+    }
+}
 
-            let mut serializer = Serializer { writer, version };
-            data.serialize(&mut serializer)?;
-            writer.flush()?;
-        }
+/// Expands to the [IsReprC] value for the given type, which must be concrete.
+/// Used by `#[derive(Savefile)]`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! repr_c_probe {
+    ($t:ty, $version:expr) => {{
+        #[allow(unused_imports)]
+        use $crate::{ReprCProbeNo, ReprCProbeYes};
+        (&$crate::ReprCProbe::<$t>::new()).repr_c_layout($version)
+    }};
+}
 
-        Ok(())
+#[doc(hidden)]
+pub trait ReprCProbeYes<T> {
+    fn repr_c_layout(&self, version: u32) -> IsReprC<T>;
+}
+impl<T: ReprC> ReprCProbeYes<T> for ReprCProbe<T> {
+    fn repr_c_layout(&self, version: u32) -> IsReprC<T> {
+        IsReprC::of(version)
     }
+}
 
-    /// Create a Serializer.
-    /// Don't use this method directly, use the [crate::save] function
-    /// instead.
-    pub fn new_raw(writer: &mut dyn Write) -> Serializer {
-        Serializer { writer, version: 0 }
+#[doc(hidden)]
+pub trait ReprCProbeNo<T> {
+    fn repr_c_layout(&self, version: u32) -> IsReprC<T>;
+}
+impl<'a, T> ReprCProbeNo<T> for &'a ReprCProbe<T> {
+    fn repr_c_layout(&self, _version: u32) -> IsReprC<T> {
+        IsReprC::no()
     }
 }
 
-impl<'a> Deserializer<'a> {
-    /// Reads a u8 and return true if equal to 1
-    pub fn read_bool(&mut self) -> Result<bool, SavefileError> {
-        Ok(self.reader.read_u8()? == 1)
+impl From<std::io::Error> for SavefileError {
+    fn from(s: std::io::Error) -> SavefileError {
+        SavefileError::IOError { io_error: s }
     }
-    /// Reads an u8
-    pub fn read_u8(&mut self) -> Result<u8, SavefileError> {
-        let mut buf = [0u8];
-        self.reader.read_exact(&mut buf)?;
-        Ok(buf[0])
+}
+
+impl<T> From<std::sync::PoisonError<T>> for SavefileError {
+    fn from(_: std::sync::PoisonError<T>) -> SavefileError {
+        SavefileError::PoisonedMutex
     }
-    /// Reads a little endian u16
-    pub fn read_u16(&mut self) -> Result<u16, SavefileError> {
-        Ok(self.reader.read_u16::<LittleEndian>()?)
+}
+
+impl From<std::str::Utf8Error> for SavefileError {
+    fn from(s: std::str::Utf8Error) -> SavefileError {
+        SavefileError::InvalidUtf8 { msg: s.to_string() }
     }
-    /// Reads a little endian u32
-    pub fn read_u32(&mut self) -> Result<u32, SavefileError> {
-        Ok(self.reader.read_u32::<LittleEndian>()?)
+}
+
+impl From<std::string::FromUtf8Error> for SavefileError {
+    fn from(s: std::string::FromUtf8Error) -> SavefileError {
+        SavefileError::InvalidUtf8 { msg: s.to_string() }
     }
-    /// Reads a little endian u64
-    pub fn read_u64(&mut self) -> Result<u64, SavefileError> {
-        Ok(self.reader.read_u64::<LittleEndian>()?)
+}
+
+impl<T> From<arrayvec::CapacityError<T>> for SavefileError {
+    fn from(s: arrayvec::CapacityError<T>) -> SavefileError {
+        SavefileError::ArrayvecCapacityError { msg: s.to_string() }
     }
+}
 
-    /// Reads an i8
-    pub fn read_i8(&mut self) -> Result<i8, SavefileError> {
-        Ok(self.reader.read_i8()?)
+impl WithSchema for PathBuf {
+    fn schema(_version: u32) -> Schema {
+        Schema::Primitive(SchemaPrimitive::schema_string)
     }
-    /// Reads a little endian i16
-    pub fn read_i16(&mut self) -> Result<i16, SavefileError> {
-        Ok(self.reader.read_i16::<LittleEndian>()?)
+}
+impl Serialize for PathBuf {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        let as_string: String = self.to_string_lossy().to_string();
+        as_string.serialize(serializer)
     }
-    /// Reads a little endian i32
-    pub fn read_i32(&mut self) -> Result<i32, SavefileError> {
-        Ok(self.reader.read_i32::<LittleEndian>()?)
+}
+impl Deserialize for PathBuf {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        Ok(PathBuf::from(String::deserialize(deserializer)?))
     }
-    /// Reads a little endian i64
-    pub fn read_i64(&mut self) -> Result<i64, SavefileError> {
-        Ok(self.reader.read_i64::<LittleEndian>()?)
+}
+impl Introspect for PathBuf {
+    fn introspect_value(&self) -> String {
+        self.to_string_lossy().to_string()
     }
-    /// Reads a little endian f32
-    pub fn read_f32(&mut self) -> Result<f32, SavefileError> {
-        Ok(self.reader.read_f32::<LittleEndian>()?)
+
+    fn introspect_child<'a>(&'a self, _index: usize) -> Option<Box<dyn IntrospectItem<'a>>> {
+        None
     }
-    /// Reads a little endian f64
-    pub fn read_f64(&mut self) -> Result<f64, SavefileError> {
-        Ok(self.reader.read_f64::<LittleEndian>()?)
+}
+
+impl<'a, T: 'a + WithSchema + ToOwned + ?Sized> WithSchema for Cow<'a, T> {
+    fn schema(version: u32) -> Schema {
+        T::schema(version)
     }
-    /// Reads an i64 into an isize. For 32 bit architectures, the function fails on overflow.
-    pub fn read_isize(&mut self) -> Result<isize, SavefileError> {
-        if let Ok(val) = TryFrom::try_from(self.reader.read_i64::<LittleEndian>()? as isize) {
-            Ok(val)
-        } else {
-            Err(SavefileError::SizeOverflow)
-        }
+}
+impl<'a, T: 'a + Serialize + ToOwned + ?Sized> Serialize for Cow<'a, T> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        (**self).serialize(serializer)
     }
-    /// Reads an u64 into an usize. For 32 bit architectures, the function fails on overflow.
-    pub fn read_usize(&mut self) -> Result<usize, SavefileError> {
-        if let Ok(val) = TryFrom::try_from(self.reader.read_u64::<LittleEndian>()? as usize) {
-            Ok(val)
-        } else {
-            Err(SavefileError::SizeOverflow)
-        }
+}
+impl<'a, T: 'a + WithSchema + ToOwned + ?Sized> Deserialize for Cow<'a, T>
+where
+    T::Owned: Deserialize,
+{
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        Ok(Cow::Owned(<T as ToOwned>::Owned::deserialize(deserializer)?))
     }
-    /// Reads a 64 bit length followed by an utf8 encoded string. Fails if data is not valid utf8
-    pub fn read_string(&mut self) -> Result<String, SavefileError> {
-        let l = self.read_usize()?;
-        #[cfg(feature = "size_sanity_checks")]
-        {
-            if l > 1_000_000 {
-                return Err(SavefileError::GeneralError {
-                    msg: format!("String too large"),
-                });
-            }
-        }
-        let mut v = Vec::with_capacity(l);
-        v.resize(l, 0); //TODO: Optimize this
-        self.reader.read_exact(&mut v)?;
-        Ok(String::from_utf8(v)?)
+}
+impl<'a, T: 'a + Introspect + ToOwned + ?Sized> Introspect for Cow<'a, T> {
+    fn introspect_value(&self) -> String {
+        (**self).introspect_value()
     }
 
-    /// Reads 'len' raw u8 bytes as a Vec<u8>
-    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, SavefileError> {
-        let mut v = Vec::with_capacity(len);
-        v.resize(len, 0); //TODO: Optimize this
-        self.reader.read_exact(&mut v)?;
-        Ok(v)
-    }
-    /// Reads raw u8 bytes into the given buffer. The buffer size must be
-    /// equal to the number of bytes desired to be read.
-    pub fn read_bytes_to_buf(&mut self, buf: &mut [u8]) -> Result<(), SavefileError> {
-        self.reader.read_exact(buf)?;
-        Ok(())
+    fn introspect_child<'b>(&'b self, index: usize) -> Option<Box<dyn IntrospectItem<'b> + 'b>> {
+        (**self).introspect_child(index)
     }
 
-    /// Deserialize an object of type T from the given reader.
-    /// Don't use this method directly, use the [crate::load] function
-    /// instead.
-    pub fn load<T: WithSchema + Deserialize>(reader: &mut dyn Read, version: u32) -> Result<T, SavefileError> {
-        Deserializer::load_impl::<T>(reader, version, true)
+    fn introspect_len(&self) -> usize {
+        (**self).introspect_len()
     }
+}
 
-    /// Deserialize an object of type T from the given reader.
-    /// Don't use this method directly, use the [crate::load_noschema] function
-    /// instead.
-    pub fn load_noschema<T: WithSchema + Deserialize>(reader: &mut dyn Read, version: u32) -> Result<T, SavefileError> {
-        Deserializer::load_impl::<T>(reader, version, false)
-    }
-    fn load_impl<T: WithSchema + Deserialize>(
-        reader: &mut dyn Read,
-        version: u32,
-        fetch_schema: bool,
-    ) -> Result<T, SavefileError> {
-        let mut head: [u8; 9] = [0u8; 9];
-        reader.read_exact(&mut head)?;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
 
-        if &head[..] != &("savefile\0".to_string().into_bytes())[..] {
-            return Err(SavefileError::GeneralError {msg: "File is not in new savefile-format. If you have a file in old format, contact crate author and we'll work something out! It is not the intention that binary compatibility will be broken any more in the future.".into()});
-        }
+/// A Write which may need to write trailing data after all data has been written,
+/// such as a compressor.
+trait FinishWrite: Write {
+    fn finish_write(&mut self) -> Result<(), SavefileError>;
+}
 
-        let savefile_lib_version = reader.read_u16::<LittleEndian>()?;
-        if savefile_lib_version != 0 {
-            return Err(SavefileError::GeneralError {msg: "This file has been created by an earlier, incompatible version of the savefile crate (0.5.0 or before).".into()});
-        }
-        let file_ver = reader.read_u32::<LittleEndian>()?;
+impl FinishWrite for &mut dyn Write {
+    fn finish_write(&mut self) -> Result<(), SavefileError> {
+        Ok(())
+    }
+}
 
-        if file_ver > version {
-            return Err(SavefileError::WrongVersion {
-                msg: format!(
-                    "File has later version ({}) than structs in memory ({}).",
-                    file_ver, version
-                ),
-            });
-        }
-        let with_compression = reader.read_u8()? != 0;
+#[cfg(feature = "bzip2")]
+impl<W: Write> FinishWrite for bzip2::write::BzEncoder<W> {
+    fn finish_write(&mut self) -> Result<(), SavefileError> {
+        Ok(self.try_finish()?)
+    }
+}
 
-        let mut temp;
-        let reader: &mut dyn Read = if with_compression {
-            temp = bzip2::read::BzDecoder::new(reader);
-            &mut temp
-        } else {
-            reader
-        };
+#[cfg(feature = "gzip")]
+impl<W: Write> FinishWrite for flate2::write::GzEncoder<W> {
+    fn finish_write(&mut self) -> Result<(), SavefileError> {
+        Ok(self.try_finish()?)
+    }
+}
 
-        if fetch_schema {
-            let mut schema_deserializer = Deserializer::new_raw(reader);
-            let memory_schema = T::schema(file_ver);
-            let file_schema = Schema::deserialize(&mut schema_deserializer)?;
+#[cfg(feature = "zstd")]
+impl<W: Write> FinishWrite for zstd::stream::write::Encoder<'static, W> {
+    fn finish_write(&mut self) -> Result<(), SavefileError> {
+        Ok(self.do_finish()?)
+    }
+}
 
-            if let Some(err) = diff_schema(&memory_schema, &file_schema, ".".to_string()) {
-                return Err(SavefileError::IncompatibleSchema {
-                    message: format!(
-                        "Saved schema differs from in-memory schema for version {}. Error: {}",
-                        file_ver, err
-                    ),
-                });
-            }
-        }
-        let mut deserializer = Deserializer {
-            reader,
-            file_version: file_ver,
-            memory_version: version,
-            ephemeral_state: HashMap::new(),
-        };
-        Ok(T::deserialize(&mut deserializer)?)
+#[cfg(feature = "lz4")]
+impl<W: Write> FinishWrite for lz4_flex::frame::FrameEncoder<W> {
+    fn finish_write(&mut self) -> Result<(), SavefileError> {
+        self.try_finish().map_err(|err| SavefileError::GeneralError {
+            msg: format!("lz4 compression failed: {}", err),
+        })
     }
+}
 
-    /// Create a Deserializer.
-    /// Don't use this method directly, use the [crate::load] function
-    /// instead.
-    pub fn new_raw(reader: &mut dyn Read) -> Deserializer {
-        Deserializer {
-            reader,
-            file_version: 0,
-            memory_version: 0,
-            ephemeral_state: HashMap::new(),
+/// Options controlling the format of a saved file.
+struct SaveOptions<'k> {
+    with_schema: bool,
+    compression: CompressionMethod,
+    with_checksum: bool,
+    with_schema_hash: bool,
+    with_schema_history: bool,
+    encryption_key: Option<&'k [u8; 32]>,
+    encoding: Encoding,
+    context: Option<&'k mut (dyn Any + 'static)>,
+    progress: Option<ProgressHooks<'k>>,
+    /// Record the current time in the header. Without it, the same data always gives
+    /// the same bytes.
+    with_creation_time: bool,
+}
+
+impl<'k> SaveOptions<'k> {
+    fn new(with_schema: bool) -> SaveOptions<'k> {
+        SaveOptions {
+            with_schema,
+            compression: CompressionMethod::None,
+            with_checksum: false,
+            with_schema_hash: false,
+            with_schema_history: false,
+            encryption_key: None,
+            encoding: Encoding::default(),
+            context: None,
+            progress: None,
+            with_creation_time: true,
         }
     }
 }
 
-/// Deserialize an instance of type T from the given `reader` .
-/// The current type of T in memory must be equal to `version`.
-/// The deserializer will use the actual protocol version in the
-/// file to do the deserialization.
-pub fn load<T: WithSchema + Deserialize>(reader: &mut dyn Read, version: u32) -> Result<T, SavefileError> {
-    Deserializer::load::<T>(reader, version)
+/// Options controlling how a file is loaded.
+struct LoadOptions<'k> {
+    /// If the file has a schema, check that it matches the type being loaded.
+    /// For files of older formats, which don't say if they have a schema, this also
+    /// says if there is one.
+    check_schema: bool,
+    encryption_key: Option<&'k [u8; 32]>,
+    unchecked_utf8: bool,
+    context: Option<&'k mut (dyn Any + 'static)>,
+    progress: Option<ProgressHooks<'k>>,
+    /// Fail if there are bytes left after the value, see [crate::load_exact]
+    exact: bool,
+    /// If the file has a schema hash, check that it matches the type being loaded.
+    /// This is done even when loading without a schema, see [crate::load_noschema].
+    check_schema_hash: bool,
+    /// The schema the data was saved with, if it is stored separately, see
+    /// [crate::load_with_external_schema]
+    external_schema: Option<ExternalSchema<'k>>,
+    /// See [crate::load_with_memory_budget]
+    memory_budget: Option<MemoryBudget>,
+}
+
+/// Where to find the schema of data which only has a schema hash
+enum ExternalSchema<'k> {
+    Schema(&'k Schema),
+    Registry(&'k mut SchemaRegistry),
+}
+
+impl<'k> LoadOptions<'k> {
+    fn new(check_schema: bool) -> LoadOptions<'k> {
+        LoadOptions {
+            check_schema,
+            encryption_key: None,
+            unchecked_utf8: false,
+            context: None,
+            progress: None,
+            exact: false,
+            check_schema_hash: true,
+            external_schema: None,
+            memory_budget: None,
+        }
+    }
 }
 
-/// Deserialize an instance of type T from the given u8 slice .
-/// The current type of T in memory must be equal to `version`.
-/// The deserializer will use the actual protocol version in the
-/// file to do the deserialization.
-pub fn load_from_mem<T: WithSchema + Deserialize>(input: &[u8], version: u32) -> Result<T, SavefileError> {
-    let mut input = input;
-    Deserializer::load::<T>(&mut input, version)
+/// Fail if anything can be read from the reader, which should be at the end of the data.
+fn check_at_end(reader: &mut impl Read) -> Result<(), SavefileError> {
+    let mut byte = [0u8];
+    if reader.read(&mut byte)? != 0 {
+        return Err(SavefileError::GeneralError {
+            msg: "There are bytes left after the loaded value".into(),
+        });
+    }
+    Ok(())
 }
 
-/// Write the given `data` to the `writer`.
-/// The current version of data must be `version`.
-pub fn save<T: WithSchema + Serialize>(writer: &mut dyn Write, version: u32, data: &T) -> Result<(), SavefileError> {
-    Serializer::save::<T>(writer, version, data, false)
+/// Encrypt and/or compress the payload, as described by the options.
+/// All such payloads are written through a `dyn Write`, so the serialization code is only
+/// instantiated once for all of them. The cost of the dynamic dispatch is small compared
+/// to the cost of compressing or encrypting the data.
+#[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+fn save_layered<T: WithSchema + Serialize>(
+    writer: &mut dyn Write,
+    version: u32,
+    data: &T,
+    namespaces: &[(String, u32)],
+    options: SaveOptions,
+    header_bytes: &[u8],
+) -> Result<(), SavefileError> {
+    #[cfg(feature = "encryption")]
+    if let Some(key) = options.encryption_key {
+        let mut encrypted = encryption::AeadWriter::new(writer, key, header_bytes.to_vec())?;
+        save_compressed_payload(&mut encrypted, version, data, namespaces, options, header_bytes)?;
+        return encrypted.finish();
+    }
+    save_compressed_payload(writer, version, data, namespaces, options, header_bytes)
 }
 
-/// Write the given `data` to the `writer`. Compresses data using 'snappy' compression format.
-/// The current version of data must be `version`.
-/// The resultant data can be loaded using the regular load-function (it autodetects if compressions was
-/// active or not).
-pub fn save_compressed<T: WithSchema + Serialize>(
+fn save_compressed_payload<T: WithSchema + Serialize>(
     writer: &mut dyn Write,
     version: u32,
     data: &T,
+    namespaces: &[(String, u32)],
+    options: SaveOptions,
+    header_bytes: &[u8],
 ) -> Result<(), SavefileError> {
-    Serializer::save::<T>(writer, version, data, true)
+    let mut compressed: Box<dyn FinishWrite + '_> = match options.compression {
+        CompressionMethod::None => Box::new(writer),
+        #[cfg(feature = "bzip2")]
+        CompressionMethod::Bzip2 => Box::new(bzip2::write::BzEncoder::new(writer, Compression::Best)),
+        #[cfg(feature = "gzip")]
+        CompressionMethod::Gzip => Box::new(flate2::write::GzEncoder::new(writer, flate2::Compression::default())),
+        #[cfg(feature = "zstd")]
+        CompressionMethod::Zstd => Box::new(zstd::stream::write::Encoder::new(writer, 0)?),
+        #[cfg(feature = "lz4")]
+        CompressionMethod::Lz4 => Box::new(lz4_flex::frame::FrameEncoder::new(writer)),
+        #[allow(unreachable_patterns)]
+        method => return Err(method.not_enabled()),
+    };
+    Serializer::save_payload(&mut compressed, version, data, namespaces, options, header_bytes)?;
+    compressed.finish_write()
+}
+
+/// Counterpart of [save_layered].
+fn load_layered<T: WithSchema + Deserialize>(
+    reader: &mut dyn Read,
+    header: &SavefileHeader,
+    version: u32,
+    options: LoadOptions,
+) -> Result<T, SavefileError> {
+    #[cfg(feature = "encryption")]
+    if let Some(key) = options.encryption_key {
+        let mut decrypted = encryption::AeadReader::new(reader, key, header.authenticated_bytes()?)?;
+        let result = load_decompressed_payload(&mut decrypted, header, version, options)?;
+        decrypted.finish()?;
+        return Ok(result);
+    }
+    load_decompressed_payload(reader, header, version, options)
+}
+
+fn load_decompressed_payload<T: WithSchema + Deserialize>(
+    reader: &mut dyn Read,
+    header: &SavefileHeader,
+    version: u32,
+    options: LoadOptions,
+) -> Result<T, SavefileError> {
+    let mut decompressed = decompressing_reader(reader, header.compression)?;
+    Deserializer::load_payload(&mut decompressed, header, version, options)
+}
+
+/// Read the payload of a file with checksums into memory, decompressed, and verify the
+/// checksums before any of the data is deserialized. Returns None if the payload length
+/// isn't known, in which case the checksums are verified while loading, and for files
+/// without checksums and encrypted files, whose payload is authenticated instead.
+fn read_verified_payload(reader: &mut impl Read, header: &SavefileHeader) -> Result<Option<Vec<u8>>, SavefileError> {
+    let payload_length = match header.payload_length {
+        Some(payload_length) if header.has_checksum && !header.encrypted => payload_length,
+        _ => return Ok(None),
+    };
+    let mut raw = Vec::new();
+    reader.take(payload_length).read_to_end(&mut raw)?;
+    if (raw.len() as u64) < payload_length {
+        return Err(SavefileError::ShortRead);
+    }
+    let payload = if header.compression == CompressionMethod::None {
+        raw
+    } else {
+        let mut payload = Vec::new();
+        decompressing_reader(&mut &raw[..], header.compression)?.read_to_end(&mut payload)?;
+        payload
+    };
+    verify_checksums(&payload, header)?;
+    Ok(Some(payload))
+}
+
+/// Verify the checksums of a decompressed payload, which ends with the checksum of the data.
+fn verify_checksums(payload: &[u8], header: &SavefileHeader) -> Result<(), SavefileError> {
+    let mut rest = payload;
+    let mut reader = ChecksumReader::new(&mut rest, header)?;
+    namespace::read_namespace_versions(&mut reader, header)?;
+    if header.has_schema_hash {
+        reader.read_u64::<LittleEndian>()?;
+        reader.verify()?;
+    } else if header.has_schema == Some(true) {
+        read_stored_schema(&mut reader, header.version, header.has_schema_history)?;
+        reader.verify()?;
+    }
+    let data_length = reader.reader.len().checked_sub(4).ok_or(SavefileError::ShortRead)?;
+    std::io::copy(&mut (&mut reader).take(data_length as u64), &mut std::io::sink())?;
+    reader.verify()
+}
+
+fn decompressing_reader<'r>(
+    reader: &'r mut dyn Read,
+    compression: CompressionMethod,
+) -> Result<Box<dyn Read + 'r>, SavefileError> {
+    Ok(match compression {
+        CompressionMethod::None => Box::new(reader),
+        #[cfg(feature = "bzip2")]
+        CompressionMethod::Bzip2 => Box::new(bzip2::read::BzDecoder::new(reader)),
+        #[cfg(feature = "gzip")]
+        CompressionMethod::Gzip => Box::new(flate2::read::GzDecoder::new(reader)),
+        #[cfg(feature = "zstd")]
+        CompressionMethod::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        #[cfg(feature = "lz4")]
+        CompressionMethod::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(reader)),
+        #[allow(unreachable_patterns)]
+        method => return Err(method.not_enabled()),
+    })
+}
+
+impl<'a, W: Write> Serializer<'a, W> {
+    /// Writes a binary bool to the writer
+    pub fn write_bool(&mut self, v: bool) -> Result<(), SavefileError> {
+        Ok(self.writer.write_u8(if v { 1 } else { 0 })?)
+    }
+    /// Writes a binary u8 to the writer
+    pub fn write_u8(&mut self, v: u8) -> Result<(), SavefileError> {
+        Ok(self.writer.write_all(&[v])?)
+    }
+    /// Writes a binary i8 to the writer
+    pub fn write_i8(&mut self, v: i8) -> Result<(), SavefileError> {
+        Ok(self.writer.write_i8(v)?)
+    }
+
+    /// Writes a binary u16 to the writer, in the byte order of the serializer
+    pub fn write_u16(&mut self, v: u16) -> Result<(), SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.writer.write_u16(v))?)
+    }
+    /// Writes a binary i16 to the writer, in the byte order of the serializer
+    pub fn write_i16(&mut self, v: i16) -> Result<(), SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.writer.write_i16(v))?)
+    }
+
+    /// Writes a binary u32 to the writer, in the byte order of the serializer
+    pub fn write_u32(&mut self, v: u32) -> Result<(), SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.writer.write_u32(v))?)
+    }
+    /// Writes a binary i32 to the writer, in the byte order of the serializer
+    pub fn write_i32(&mut self, v: i32) -> Result<(), SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.writer.write_i32(v))?)
+    }
+
+    /// Writes a binary f32 to the writer, in the byte order of the serializer
+    pub fn write_f32(&mut self, v: f32) -> Result<(), SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.writer.write_f32(v))?)
+    }
+    /// Writes a binary f64 to the writer, in the byte order of the serializer
+    pub fn write_f64(&mut self, v: f64) -> Result<(), SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.writer.write_f64(v))?)
+    }
+
+    /// Writes a binary u64 to the writer, in the byte order of the serializer
+    pub fn write_u64(&mut self, v: u64) -> Result<(), SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.writer.write_u64(v))?)
+    }
+    /// Writes a binary i64 to the writer, in the byte order of the serializer
+    pub fn write_i64(&mut self, v: i64) -> Result<(), SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.writer.write_i64(v))?)
+    }
+
+    /// Writes a usize as u64 to the writer, in the byte order of the serializer, whatever the
+    /// length encoding, since the schema of usize values is that of a u64.
+    /// Lengths of strings and collections are written by [Serializer::write_length].
+    pub fn write_usize(&mut self, v: usize) -> Result<(), SavefileError> {
+        self.write_u64(v as u64)
+    }
+    fn write_varint(&mut self, v: u64) -> Result<(), SavefileError> {
+        let mut v = v;
+        while v >= 0x80 {
+            self.writer.write_u8((v as u8) | 0x80)?;
+            v >>= 7;
+        }
+        Ok(self.writer.write_u8(v as u8)?)
+    }
+    /// Writes the length of a string or collection, as described by the length encoding
+    /// of the serializer. This is an 8 byte integer, unless a different
+    /// [LengthEncoding] has been chosen.
+    pub fn write_length(&mut self, v: u64) -> Result<(), SavefileError> {
+        match self.encoding.lengths {
+            LengthEncoding::Fixed64 => self.write_u64(v),
+            // The marker of chunked vectors is the largest length in either width
+            LengthEncoding::Fixed32 if v == CHUNKED_VEC_LENGTH => self.write_u32(u32::MAX),
+            LengthEncoding::Fixed32 if v >= u64::from(u32::MAX) => Err(SavefileError::GeneralError {
+                msg: format!("Length {} is too large to be written as a 32 bit length", v),
+            }),
+            LengthEncoding::Fixed32 => self.write_u32(v as u32),
+            LengthEncoding::Varint => self.write_varint(v),
+        }
+    }
+    /// Writes a binary isize as i64 to the writer
+    pub fn write_isize(&mut self, v: isize) -> Result<(), SavefileError> {
+        self.write_i64(v as i64)
+    }
+    /// Writes a group of bit-packed values, in as few bytes as possible, least significant
+    /// byte first. See [BitPacked].
+    pub fn write_packed(&mut self, packed: &PackedBits) -> Result<(), SavefileError> {
+        let bytes = packed.bits.to_le_bytes();
+        Ok(self.writer.write_all(&bytes[..packed_bytes(packed.width)])?)
+    }
+    /// Writes the discriminator of an enum variant, as a u8, u16 or u32 for a `discriminant_size`
+    /// of 1, 2 or 4 bytes, see [SchemaEnum::discriminant_size]. Fails if it doesn't fit.
+    pub fn write_discriminant(&mut self, discriminator: u32, discriminant_size: u8) -> Result<(), SavefileError> {
+        let too_large = || SavefileError::GeneralError {
+            msg: format!(
+                "Discriminator {} does not fit in {} bytes",
+                discriminator, discriminant_size
+            ),
+        };
+        match discriminant_size {
+            1 => self.write_u8(u8::try_from(discriminator).map_err(|_| too_large())?),
+            2 => self.write_u16(u16::try_from(discriminator).map_err(|_| too_large())?),
+            4 => self.write_u32(discriminator),
+            _ => Err(too_large()),
+        }
+    }
+    /// Writes a binary u8 array to the writer
+    pub fn write_buf(&mut self, v: &[u8]) -> Result<(), SavefileError> {
+        Ok(self.writer.write_all(v)?)
+    }
+    /// Writes as a string as 64 bit length + utf8 data.
+    /// If strings are interned, the string is preceded by a zero the first time it is written,
+    /// and later written as one more than its index among the strings written so far.
+    pub fn write_string(&mut self, v: &str) -> Result<(), SavefileError> {
+        if self.encoding.intern_strings {
+            if let Some(&index) = self.interned_strings.get(v) {
+                return self.write_length(index + 1);
+            }
+            let index = self.interned_strings.len() as u64;
+            self.interned_strings.insert(v.to_string(), index);
+            self.write_length(0)?;
+        }
+        let asb = v.as_bytes();
+        self.write_length(asb.len() as u64)?;
+        Ok(self.writer.write_all(asb)?)
+    }
+    /// Writes a binary u8 array to the writer. Synonym of write_buf.
+    pub fn write_bytes(&mut self, v: &[u8]) -> Result<(), SavefileError> {
+        Ok(self.writer.write_all(v)?)
+    }
+
+    /// Writes the items as a sequence, which can be deserialized as a `Vec<T>`.
+    /// Unlike when serializing a Vec, the number of items doesn't have to be known in advance,
+    /// since the items are written in chunks, each preceded by its number of items.
+    /// Files containing such sequences can't be read by savefile versions older than 0.9.
+    pub fn write_chunked<T: Serialize>(&mut self, items: impl IntoIterator<Item = T>) -> Result<(), SavefileError> {
+        self.write_length(CHUNKED_VEC_LENGTH)?;
+        let mut chunk = VecChunk::new();
+        for item in items {
+            chunk.push(&item, self)?;
+        }
+        chunk.finish(self)
+    }
+
+    /// Start writing a sequence of items one at a time, which can be deserialized as a `Vec<T>`.
+    /// If `len` is given, it is written in front of the items, and exactly that many items must
+    /// be pushed. Otherwise the items are written in chunks, see [SeqSerializer].
+    pub fn serialize_seq<T: Serialize>(&mut self, len: Option<usize>) -> Result<SeqSerializer<'_, 'a, W, T>, SavefileError> {
+        SeqSerializer::new(self, len)
+    }
+
+    /// Creata a new serializer.
+    /// Don't use this function directly, use the [crate::save] function instead.
+    pub fn save<T: WithSchema + Serialize>(
+        writer: &mut W,
+        version: u32,
+        data: &T,
+        with_compression: bool,
+    ) -> Result<(), SavefileError> {
+        let compression = if with_compression {
+            CompressionMethod::Bzip2
+        } else {
+            CompressionMethod::None
+        };
+        Self::save_impl(
+            writer,
+            version,
+            data,
+            SaveOptions {
+                compression,
+                ..SaveOptions::new(true)
+            },
+        )?;
+        Ok(())
+    }
+    /// Creata a new serializer.
+    /// Don't use this function directly, use the [crate::save_noschema] function instead.
+    pub fn save_noschema<T: WithSchema + Serialize>(
+        writer: &mut W,
+        version: u32,
+        data: &T,
+    ) -> Result<(), SavefileError> {
+        Self::save_impl(
+            writer,
+            version,
+            data,
+            SaveOptions {
+                with_schema_hash: true,
+                ..SaveOptions::new(false)
+            },
+        )?;
+        Ok(())
+    }
+    /// Returns the number of bytes written after the header.
+    fn save_impl<T: WithSchema + Serialize>(
+        writer: &mut W,
+        version: u32,
+        data: &T,
+        options: SaveOptions,
+    ) -> Result<u64, SavefileError> {
+        let namespaces = namespace::namespaces_of(|| schema_of::<T>(version))?;
+        let header = SavefileHeader {
+            format_version: namespace::format_version(&namespaces),
+            version,
+            compression: options.compression,
+            has_schema: Some(options.with_schema),
+            has_checksum: options.with_checksum,
+            encrypted: options.encryption_key.is_some(),
+            has_schema_hash: options.with_schema_hash,
+            has_schema_history: options.with_schema_history,
+            payload_length: None,
+            created: if options.with_creation_time {
+                creation_time()
+            } else {
+                None
+            },
+            encoding: options.encoding,
+        };
+        header.write(writer)?;
+        let header_bytes = header.authenticated_bytes()?;
+
+        let count = Cell::new(0);
+        let mut counting_writer = CountingWriter { writer, count: &count };
+        // Types in namespaces are saved in the current versions of the namespaces, also if
+        // this is done while loading a file
+        namespace::with_file_versions(None, || {
+            if options.compression == CompressionMethod::None && options.encryption_key.is_none() {
+                Serializer::save_payload(&mut counting_writer, version, data, &namespaces, options, &header_bytes)
+            } else {
+                save_layered(&mut counting_writer, version, data, &namespaces, options, &header_bytes)
+            }
+        })?;
+        counting_writer.flush()?;
+        Ok(count.get())
+    }
+
+    fn save_payload<T: WithSchema + Serialize>(
+        writer: &mut W,
+        version: u32,
+        data: &T,
+        namespaces: &[(String, u32)],
+        options: SaveOptions,
+        header_bytes: &[u8],
+    ) -> Result<(), SavefileError> {
+        let mut writer = ChecksumWriter::new(writer, options.with_checksum, header_bytes);
+        if !namespaces.is_empty() {
+            namespace::write_namespace_versions(&mut writer, namespaces)?;
+        }
+        if options.with_schema_history {
+            let history = SchemaHistory::of::<T>(version);
+            history.serialize(&mut Serializer::new_raw(&mut writer))?;
+            writer.write_checksum()?;
+        } else if options.with_schema {
+            let schema = schema_of::<T>(version);
+            let mut schema_serializer = Serializer::new_raw(&mut writer);
+            schema.serialize(&mut schema_serializer)?;
+            writer.write_checksum()?;
+        } else if options.with_schema_hash {
+            writer.write_u64::<LittleEndian>(schema_of::<T>(version).stable_hash())?;
+            writer.write_checksum()?;
+        }
+
+        let written = Cell::new(0);
+        let mut serializer = Serializer {
+            writer: &mut CountingWriter {
+                writer: &mut writer,
+                count: &written,
+            },
+            version,
+            encoding: options.encoding,
+            interned_strings: HashMap::new(),
+            context: options.context,
+            progress: options.progress.map(|hooks| ProgressTracker::new(hooks, &written)),
+        };
+        data.serialize(&mut serializer)?;
+        if let Some(progress) = serializer.progress.as_mut() {
+            progress.finish();
+        }
+        writer.write_checksum()?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The context given to [crate::save_with_context], if it is a C. Serialize-implementations
+    /// can use it to look up application state while saving. Returns None if there is no
+    /// context of type C.
+    pub fn context<C: Any>(&mut self) -> Option<&mut C> {
+        self.context.as_mut()?.downcast_mut()
+    }
+
+    /// Called by derived Serialize-implementations before each field is written, and followed
+    /// by [Serializer::leave_field] once it has been written. This reports progress and checks
+    /// for cancellation, when saving with [crate::save_with_progress].
+    pub fn enter_field(&mut self, name: &'static str) -> Result<(), SavefileError> {
+        match self.progress.as_mut() {
+            Some(progress) => progress.enter_field(name),
+            None => Ok(()),
+        }
+    }
+
+    /// Called after a field entered by [Serializer::enter_field] has been written
+    pub fn leave_field(&mut self) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress.leave_field();
+        }
+    }
+
+    /// True if vectors, slices and arrays of T are written by copying their memory, instead
+    /// of item by item. This is the case if T is [ReprC] for the version being written, and
+    /// the byte order of the data is that of the machine. Otherwise, such as when big endian
+    /// data is written on a little endian machine, or the other way around, each item is
+    /// serialized in the byte order of the data, so the result is the same on any machine.
+    pub fn uses_raw_copy<T: WithSchema>(&self) -> bool {
+        T::repr_c_layout(self.version).is_yes() && self.encoding.allows_raw_copy()
+    }
+
+    /// Create a Serializer.
+    /// Don't use this method directly, use the [crate::save] function
+    /// instead.
+    pub fn new_raw(writer: &mut W) -> Serializer<'_, W> {
+        Serializer {
+            writer,
+            version: 0,
+            encoding: Encoding::default(),
+            interned_strings: HashMap::new(),
+            context: None,
+            progress: None,
+        }
+    }
+}
+
+impl<'a, R: Read> Deserializer<'a, R> {
+    /// Reads a u8 and return true if equal to 1
+    pub fn read_bool(&mut self) -> Result<bool, SavefileError> {
+        Ok(self.reader.read_u8()? == 1)
+    }
+    /// Reads an u8
+    pub fn read_u8(&mut self) -> Result<u8, SavefileError> {
+        let mut buf = [0u8];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+    /// Reads a u16, in the byte order of the file
+    pub fn read_u16(&mut self) -> Result<u16, SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.reader.read_u16())?)
+    }
+    /// Reads a u32, in the byte order of the file
+    pub fn read_u32(&mut self) -> Result<u32, SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.reader.read_u32())?)
+    }
+    /// Reads a u64, in the byte order of the file
+    pub fn read_u64(&mut self) -> Result<u64, SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.reader.read_u64())?)
+    }
+
+    /// Reads an i8
+    pub fn read_i8(&mut self) -> Result<i8, SavefileError> {
+        Ok(self.reader.read_i8()?)
+    }
+    /// Reads a i16, in the byte order of the file
+    pub fn read_i16(&mut self) -> Result<i16, SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.reader.read_i16())?)
+    }
+    /// Reads a i32, in the byte order of the file
+    pub fn read_i32(&mut self) -> Result<i32, SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.reader.read_i32())?)
+    }
+    /// Reads a i64, in the byte order of the file
+    pub fn read_i64(&mut self) -> Result<i64, SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.reader.read_i64())?)
+    }
+    /// Reads a f32, in the byte order of the file
+    pub fn read_f32(&mut self) -> Result<f32, SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.reader.read_f32())?)
+    }
+    /// Reads a f64, in the byte order of the file
+    pub fn read_f64(&mut self) -> Result<f64, SavefileError> {
+        Ok(with_byte_order!(self.encoding.endianness, self.reader.read_f64())?)
+    }
+    /// Reads an i64 into an isize. For 32 bit architectures, the function fails on overflow.
+    pub fn read_isize(&mut self) -> Result<isize, SavefileError> {
+        isize::try_from(self.read_i64()?).map_err(|_| SavefileError::SizeOverflow)
+    }
+    /// Reads a group of bit-packed values with the given total width, written by
+    /// [Serializer::write_packed].
+    pub fn read_packed(&mut self, width: u32) -> Result<PackedBits, SavefileError> {
+        let mut packed = PackedBits::new();
+        if width > 64 {
+            return Err(SavefileError::GeneralError {
+                msg: format!("Corrupt file - {} bit-packed bits is more than the maximum of 64", width),
+            });
+        }
+        let mut bytes = [0u8; 8];
+        self.reader.read_exact(&mut bytes[..packed_bytes(width)])?;
+        packed.push_bits(u64::from_le_bytes(bytes), width)?;
+        Ok(packed)
+    }
+    /// Reads the discriminator of an enum variant, written by [Serializer::write_discriminant]
+    pub fn read_discriminant(&mut self, discriminant_size: u8) -> Result<u32, SavefileError> {
+        match discriminant_size {
+            1 => Ok(u32::from(self.read_u8()?)),
+            2 => Ok(u32::from(self.read_u16()?)),
+            4 => self.read_u32(),
+            _ => Err(SavefileError::GeneralError {
+                msg: format!("Corrupt schema - discriminator of {} bytes", discriminant_size),
+            }),
+        }
+    }
+    /// Reads an u64 into an usize, written by [Serializer::write_usize].
+    /// For 32 bit architectures, the function fails on overflow.
+    pub fn read_usize(&mut self) -> Result<usize, SavefileError> {
+        usize::try_from(self.read_u64()?).map_err(|_| SavefileError::SizeOverflow)
+    }
+    /// Reads the length of a string or collection into an usize, see [Deserializer::read_length].
+    /// For 32 bit architectures, the function fails on overflow.
+    pub fn read_length_usize(&mut self) -> Result<usize, SavefileError> {
+        usize::try_from(self.read_length()?).map_err(|_| SavefileError::SizeOverflow)
+    }
+    /// Reads the length of a string or collection, as described by the length encoding of the file.
+    pub fn read_length(&mut self) -> Result<u64, SavefileError> {
+        match self.encoding.lengths {
+            LengthEncoding::Fixed64 => self.read_u64(),
+            LengthEncoding::Fixed32 => match self.read_u32()? {
+                u32::MAX => Ok(CHUNKED_VEC_LENGTH),
+                length => Ok(u64::from(length)),
+            },
+            LengthEncoding::Varint => self.read_varint(),
+        }
+    }
+    fn read_varint(&mut self) -> Result<u64, SavefileError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.reader.read_u8()?;
+            if shift == 63 && byte > 1 {
+                break;
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SavefileError::GeneralError {
+            msg: "Corrupt file - variable length integer is too large".into(),
+        })
+    }
+    /// Reads a 64 bit length followed by an utf8 encoded string. Fails if data is not valid utf8.
+    /// Interned strings are resolved, see [Serializer::write_string].
+    pub fn read_string(&mut self) -> Result<String, SavefileError> {
+        let mut string = String::new();
+        self.read_string_into(&mut string)?;
+        Ok(string)
+    }
+
+    /// Like [Deserializer::read_string], but reading into an existing string, replacing its
+    /// contents. This reuses the allocation of the string, if it is large enough.
+    /// If an error is returned, the string is left empty.
+    pub fn read_string_into(&mut self, string: &mut String) -> Result<(), SavefileError> {
+        string.clear();
+        if self.encoding.intern_strings {
+            let reference = self.read_length()?;
+            if reference != 0 {
+                let interned_len = self
+                    .interned_strings
+                    .get((reference - 1) as usize)
+                    .ok_or_else(|| SavefileError::GeneralError {
+                        msg: format!("Corrupt file - reference to unknown interned string {}", reference),
+                    })?
+                    .len();
+                self.account_memory(interned_len as u64)?;
+                string.push_str(&self.interned_strings[(reference - 1) as usize]);
+                return Ok(());
+            }
+            self.read_raw_string_into(string)?;
+            self.interned_strings.push(string.clone());
+            return Ok(());
+        }
+        self.read_raw_string_into(string)
+    }
+
+    fn read_raw_string_into(&mut self, string: &mut String) -> Result<(), SavefileError> {
+        let l = self.read_length_usize()?;
+        self.account_memory(l as u64)?;
+        #[cfg(feature = "size_sanity_checks")]
+        {
+            if l > 1_000_000 {
+                return Err(SavefileError::GeneralError {
+                    msg: format!("String too large"),
+                });
+            }
+        }
+        if l <= SCRATCH_BUFFER_LIMIT {
+            // Small strings are read into the scratch buffer, which is already initialized,
+            // and validated there, so the string is only allocated once, without zero-filling.
+            if self.scratch.len() < l {
+                self.scratch.resize(l, 0);
+            }
+            self.reader.read_exact(&mut self.scratch[..l])?;
+            if self.unchecked_utf8 {
+                // Safety: the caller of set_unchecked_utf8 guarantees that strings are valid utf8
+                string.push_str(unsafe { std::str::from_utf8_unchecked(&self.scratch[..l]) });
+            } else {
+                string.push_str(std::str::from_utf8(&self.scratch[..l])?);
+            }
+            return Ok(());
+        }
+        let mut v = std::mem::take(string).into_bytes();
+        self.read_bytes_into(&mut v, l)?;
+        *string = if self.unchecked_utf8 {
+            // Safety: the caller of set_unchecked_utf8 guarantees that strings are valid utf8
+            unsafe { String::from_utf8_unchecked(v) }
+        } else {
+            String::from_utf8(v)?
+        };
+        Ok(())
+    }
+
+    /// Skip validation of the utf8 data of strings, which makes loading text heavy data faster.
+    ///
+    /// # Safety
+    /// All strings read must be valid utf8, otherwise the behavior is undefined. This is the case
+    /// for files written by savefile, which haven't been changed since, for example because
+    /// they were written by the application itself and are verified by a checksum.
+    pub unsafe fn set_unchecked_utf8(&mut self, unchecked_utf8: bool) {
+        self.unchecked_utf8 = unchecked_utf8;
+    }
+
+    /// Appends exactly `len` bytes to `v`, reading into its spare capacity. Memory is only
+    /// allocated as the bytes are read, see [reserve_for_items], so a corrupt or hostile
+    /// length fails when the data ends, instead of first making a huge allocation.
+    fn read_bytes_into(&mut self, v: &mut Vec<u8>, len: usize) -> Result<(), SavefileError> {
+        let end = v.len().checked_add(len).ok_or(SavefileError::SizeOverflow)?;
+        while v.len() < end {
+            reserve_for_items(v, end);
+            let chunk = (v.capacity() - v.len()).min(end - v.len());
+            let read = (&mut self.reader).take(chunk as u64).read_to_end(v)?;
+            if read != chunk {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads 'len' raw u8 bytes as a Vec<u8>
+    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, SavefileError> {
+        let mut v = Vec::new();
+        self.read_bytes_into(&mut v, len)?;
+        Ok(v)
+    }
+    /// Reads raw u8 bytes into the given buffer. The buffer size must be
+    /// equal to the number of bytes desired to be read.
+    pub fn read_bytes_to_buf(&mut self, buf: &mut [u8]) -> Result<(), SavefileError> {
+        self.reader.read_exact(buf)?;
+        Ok(())
+    }
+    /// Reads past one value with the given schema, without deserializing it. The schema
+    /// must be the schema the value was saved with, for the version of the file, such as
+    /// the schema read by [crate::read_schema]. Values of fixed size are skipped in one go.
+    /// Fails for [Schema::Undefined], and for enums with unknown discriminators.
+    pub fn skip_value(&mut self, schema: &Schema) -> Result<(), SavefileError> {
+        lazy::skip_value(schema, self)
+    }
+
+    /// Deserialize an object of type T from the given reader.
+    /// Don't use this method directly, use the [crate::load] function
+    /// instead.
+    pub fn load<T: WithSchema + Deserialize>(reader: &mut R, version: u32) -> Result<T, SavefileError> {
+        Deserializer::load_impl::<T>(reader, version, LoadOptions::new(true))
+    }
+
+    /// Deserialize an object of type T from the given reader.
+    /// Don't use this method directly, use the [crate::load_noschema] function
+    /// instead.
+    pub fn load_noschema<T: WithSchema + Deserialize>(reader: &mut R, version: u32) -> Result<T, SavefileError> {
+        Deserializer::load_impl::<T>(reader, version, LoadOptions::new(false))
+    }
+    fn load_impl<T: WithSchema + Deserialize>(
+        reader: &mut R,
+        version: u32,
+        options: LoadOptions,
+    ) -> Result<T, SavefileError> {
+        let header = Self::read_checked_header(reader, version, options.encryption_key)?;
+        if let Some(payload) = read_verified_payload(reader, &header)? {
+            return Deserializer::load_payload(&mut &payload[..], &header, version, options);
+        }
+        if header.compression == CompressionMethod::None && !header.encrypted {
+            return Self::load_payload(reader, &header, version, options);
+        }
+        load_layered(reader, &header, version, options)
+    }
+
+    /// Read the header, and check that the file can be loaded with the given version and key.
+    fn read_checked_header(
+        reader: &mut R,
+        version: u32,
+        encryption_key: Option<&[u8; 32]>,
+    ) -> Result<SavefileHeader, SavefileError> {
+        let header = SavefileHeader::read(reader)?;
+        let file_ver = header.version;
+
+        if file_ver > version {
+            return Err(SavefileError::WrongVersion {
+                msg: format!(
+                    "File has later version ({}) than structs in memory ({}).",
+                    file_ver, version
+                ),
+            });
+        }
+        if header.encrypted && encryption_key.is_none() {
+            return Err(SavefileError::GeneralError {
+                msg: if cfg!(feature = "encryption") {
+                    "File is encrypted. Use load_encrypted to load it.".into()
+                } else {
+                    "File is encrypted. Enable the encryption feature, and use load_encrypted to load it.".into()
+                },
+            });
+        }
+        if !header.encrypted && encryption_key.is_some() {
+            return Err(SavefileError::GeneralError {
+                msg: "File is not encrypted.".into(),
+            });
+        }
+        Ok(header)
+    }
+
+    /// Deserialize into an existing value, using [Deserialize::deserialize_in_place].
+    /// Don't use this method directly, use the [crate::load_in_place] function
+    /// instead.
+    pub fn load_in_place<T: WithSchema + Deserialize>(
+        reader: &mut R,
+        version: u32,
+        value: &mut T,
+    ) -> Result<(), SavefileError> {
+        let header = Self::read_checked_header(reader, version, None)?;
+        if let Some(payload) = read_verified_payload(reader, &header)? {
+            return Deserializer::load_payload_in_place(&mut &payload[..], &header, version, value);
+        }
+        if header.compression != CompressionMethod::None {
+            // The decompressed payload is in memory anyway, so there is little to gain
+            *value = load_layered(reader, &header, version, LoadOptions::new(true))?;
+            return Ok(());
+        }
+        Deserializer::load_payload_in_place(reader, &header, version, value)
+    }
+
+    fn load_payload_in_place<T: WithSchema + Deserialize>(
+        reader: &mut R,
+        header: &SavefileHeader,
+        version: u32,
+        value: &mut T,
+    ) -> Result<(), SavefileError> {
+        let has_schema = header.has_schema.unwrap_or(true);
+        let mut reader = ChecksumReader::new(reader, header)?;
+        let namespaces = check_file_schema::<T, _>(&mut reader, header, has_schema, true, true, None)?;
+        let count = Cell::new(0);
+        let mut counting_reader = CountingReader {
+            reader: &mut reader,
+            count: &count,
+        };
+        let mut deserializer = Deserializer {
+            reader: &mut counting_reader,
+            file_version: header.version,
+            memory_version: version,
+            encoding: header.encoding,
+            ephemeral_state: HashMap::new(),
+            interned_strings: Vec::new(),
+            scratch: Vec::new(),
+            unchecked_utf8: false,
+            context: None,
+            progress: None,
+            memory_budget: None,
+        };
+        let result = namespace::with_file_versions(Some(namespaces), || value.deserialize_in_place(&mut deserializer));
+        let offset = count.get();
+        result.map_err(|err| err.with_offset(offset))?;
+        reader.verify()
+    }
+
+    fn load_payload<T: WithSchema + Deserialize>(
+        reader: &mut R,
+        header: &SavefileHeader,
+        version: u32,
+        options: LoadOptions,
+    ) -> Result<T, SavefileError> {
+        let file_ver = header.version;
+        // Files of older formats don't say if they have a schema, so we have to trust the caller.
+        let has_schema = header.has_schema.unwrap_or(options.check_schema);
+        let mut reader = ChecksumReader::new(reader, header)?;
+        let namespaces = check_file_schema::<T, _>(
+            &mut reader,
+            header,
+            has_schema,
+            options.check_schema,
+            options.check_schema_hash,
+            options.external_schema,
+        )?;
+        let count = Cell::new(0);
+        let mut counting_reader = CountingReader {
+            reader: &mut reader,
+            count: &count,
+        };
+        let mut deserializer = Deserializer {
+            reader: &mut counting_reader,
+            file_version: file_ver,
+            memory_version: version,
+            encoding: header.encoding,
+            ephemeral_state: HashMap::new(),
+            interned_strings: Vec::new(),
+            scratch: Vec::new(),
+            unchecked_utf8: options.unchecked_utf8,
+            context: options.context,
+            progress: options.progress.map(|hooks| ProgressTracker::new(hooks, &count)),
+            memory_budget: options.memory_budget,
+        };
+        let result = namespace::with_file_versions(Some(namespaces), || T::deserialize(&mut deserializer));
+        if let (Ok(_), Some(progress)) = (&result, deserializer.progress.as_mut()) {
+            progress.finish();
+        }
+        let offset = count.get();
+        let result = result.map_err(|err| err.with_offset(offset))?;
+        reader.verify()?;
+        if options.exact {
+            check_at_end(&mut reader)?;
+        }
+        Ok(result)
+    }
+
+    /// Like [Deserializer::load_impl], but also checking that nothing follows the payload,
+    /// and that the payload has the length given in the header, if any.
+    fn load_exact<T: WithSchema + Deserialize>(reader: &mut R, version: u32) -> Result<T, SavefileError> {
+        let header = Self::read_checked_header(reader, version, None)?;
+        let count = Cell::new(0);
+        let mut counting_reader = CountingReader { reader, count: &count };
+        let options = LoadOptions {
+            exact: true,
+            ..LoadOptions::new(true)
+        };
+        let result = if let Some(payload) = read_verified_payload(&mut counting_reader, &header)? {
+            Deserializer::load_payload(&mut &payload[..], &header, version, options)?
+        } else if header.compression == CompressionMethod::None && !header.encrypted {
+            Deserializer::load_payload(&mut counting_reader, &header, version, options)?
+        } else {
+            load_layered(&mut counting_reader, &header, version, options)?
+        };
+        check_at_end(&mut counting_reader)?;
+        match header.payload_length {
+            Some(payload_length) if payload_length != count.get() => Err(SavefileError::GeneralError {
+                msg: format!(
+                    "The payload is {} bytes, but the header says it is {} bytes",
+                    count.get(),
+                    payload_length
+                ),
+            }),
+            _ => Ok(result),
+        }
+    }
+
+    /// Create a Deserializer.
+    /// Don't use this method directly, use the [crate::load] function
+    /// instead.
+    pub fn new_raw(reader: &mut R) -> Deserializer<'_, R> {
+        Deserializer {
+            reader,
+            file_version: 0,
+            memory_version: 0,
+            encoding: Encoding::default(),
+            ephemeral_state: HashMap::new(),
+            interned_strings: Vec::new(),
+            scratch: Vec::new(),
+            unchecked_utf8: false,
+            context: None,
+            progress: None,
+            memory_budget: None,
+        }
+    }
+}
+
+/// Deserialize an instance of type T from the given `reader` .
+/// The current type of T in memory must be equal to `version`.
+/// The deserializer will use the actual protocol version in the
+/// file to do the deserialization.
+pub fn load<T: WithSchema + Deserialize>(reader: &mut impl Read, version: u32) -> Result<T, SavefileError> {
+    Deserializer::load::<T>(reader, version)
+}
+
+/// Like [crate::load], but using the current version of T, given by [CurrentVersion].
+pub fn load_current<T: WithSchema + Deserialize + CurrentVersion>(reader: &mut impl Read) -> Result<T, SavefileError> {
+    Deserializer::load::<T>(reader, T::SAVEFILE_MAX_VERSION)
+}
+
+/// Like [crate::load], but `context` is available to the Deserialize-implementations of the
+/// loaded types, through [Deserializer::context]. This lets them resolve things like asset
+/// handles against a registry owned by the application, while loading.
+pub fn load_with_context<T: WithSchema + Deserialize, C: Any>(
+    reader: &mut impl Read,
+    version: u32,
+    context: &mut C,
+) -> Result<T, SavefileError> {
+    Deserializer::load_impl::<T>(
+        reader,
+        version,
+        LoadOptions {
+            context: Some(context),
+            ..LoadOptions::new(true)
+        },
+    )
+}
+
+/// Like [crate::load], but reporting the progress as the data is read, and failing with
+/// [SavefileError::Cancelled] if the [CancellationToken] of the progress is cancelled.
+/// See [Progress].
+pub fn load_with_progress<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+    progress: &mut Progress,
+) -> Result<T, SavefileError> {
+    Deserializer::load_impl::<T>(
+        reader,
+        version,
+        LoadOptions {
+            progress: Some(progress.hooks()),
+            ..LoadOptions::new(true)
+        },
+    )
+}
+
+/// Like [crate::load], but without validating the utf8 data of strings,
+/// see [Deserializer::set_unchecked_utf8].
+///
+/// # Safety
+/// All strings in the file must be valid utf8, otherwise the behavior is undefined.
+/// Use this only for files which are known to have been written by savefile, such as
+/// checksummed files written by the application itself.
+pub unsafe fn load_unchecked_utf8<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+) -> Result<T, SavefileError> {
+    Deserializer::load_impl::<T>(
+        reader,
+        version,
+        LoadOptions {
+            unchecked_utf8: true,
+            ..LoadOptions::new(true)
+        },
+    )
+}
+
+/// Like [crate::load], but fails with [SavefileError::MemoryBudgetExceeded] when the loaded
+/// value would need more than `budget` bytes of memory, instead of allocating it. This protects
+/// servers loading untrusted files, whose lengths can make them allocate huge amounts of memory.
+/// The error is wrapped in [SavefileError::DeserializationFailed], whose path names the field
+/// which didn't fit.
+///
+/// The memory of vectors, strings, boxed slices, and the maps, sets and other collections of
+/// the standard library is counted, without the overhead of the allocator and of hash tables.
+/// Custom Deserialize-implementations are included if they call [Deserializer::account_memory].
+pub fn load_with_memory_budget<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+    budget: u64,
+) -> Result<T, SavefileError> {
+    Deserializer::load_impl::<T>(
+        reader,
+        version,
+        LoadOptions {
+            memory_budget: Some(MemoryBudget { limit: budget, used: 0 }),
+            ..LoadOptions::new(true)
+        },
+    )
+}
+
+/// Deserialize an instance of type T from the given u8 slice .
+/// The current type of T in memory must be equal to `version`.
+/// The deserializer will use the actual protocol version in the
+/// file to do the deserialization.
+pub fn load_from_mem<T: WithSchema + Deserialize>(input: &[u8], version: u32) -> Result<T, SavefileError> {
+    Deserializer::load::<T>(&mut SliceReader::new(input), version)
+}
+
+/// Like [crate::load], but fails if there are any bytes left in the reader after the loaded
+/// value, or if the length of the data differs from the length recorded in the header, when
+/// there is one. This catches loading data as a type with fewer fields than the saved type,
+/// and files with garbage at the end, which [crate::load] ignores. The reader must end
+/// where the saved data ends.
+pub fn load_exact<T: WithSchema + Deserialize>(reader: &mut impl Read, version: u32) -> Result<T, SavefileError> {
+    Deserializer::load_exact::<T>(reader, version)
+}
+
+/// Like [crate::load], except the data is deserialized into an existing value, which
+/// makes it possible to reuse the memory allocated by it, such as the buffers of
+/// vectors and strings. See [Deserialize::deserialize_in_place].
+/// If loading fails, `value` is left in an unspecified, but valid, state.
+pub fn load_in_place<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+    value: &mut T,
+) -> Result<(), SavefileError> {
+    Deserializer::load_in_place(reader, version, value)
+}
+
+/// Write the given `data` to the `writer`.
+/// The current version of data must be `version`.
+pub fn save<T: WithSchema + Serialize>(writer: &mut impl Write, version: u32, data: &T) -> Result<(), SavefileError> {
+    Serializer::save::<T>(writer, version, data, false)
+}
+
+/// Like [crate::save], but using the current version of T, given by [CurrentVersion].
+pub fn save_current<T: WithSchema + Serialize + CurrentVersion>(
+    writer: &mut impl Write,
+    data: &T,
+) -> Result<(), SavefileError> {
+    Serializer::save::<T>(writer, T::SAVEFILE_MAX_VERSION, data, false)
+}
+
+/// Write the given `data` to the `writer`. Compresses data using the bzip2 compression format.
+/// The current version of data must be `version`.
+/// The resultant data can be loaded using the regular load-function (it autodetects if compressions was
+/// active or not).
+pub fn save_compressed<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    Serializer::save::<T>(writer, version, data, true)
+}
+
+/// Like [crate::save], except a CRC32 checksum of the schema and of the data
+/// is stored as well. The checksum of the schema also covers the header, except for the
+/// payload length and creation time. The checksums are verified automatically when loading,
+/// before the data is deserialized, and a mismatch results in [SavefileError::ChecksumMismatch].
+/// The data is serialized to memory first, so the header can record the length of the payload.
+pub fn save_checksummed<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    let mut bytes = Vec::new();
+    let payload_length = Serializer::save_impl(
+        &mut bytes,
+        version,
+        data,
+        SaveOptions {
+            with_checksum: true,
+            ..SaveOptions::new(true)
+        },
+    )?;
+    let offset = PAYLOAD_LENGTH_OFFSET as usize;
+    (&mut bytes[offset..offset + 8]).write_u64::<LittleEndian>(payload_length)?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Like [crate::save_compressed], but using the given compression method.
+/// Methods other than bzip2 require the corresponding cargo feature to be enabled.
+/// The regular load-functions detect the compression method automatically.
+pub fn save_compressed_with<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    method: CompressionMethod,
+) -> Result<(), SavefileError> {
+    Serializer::save_impl(
+        writer,
+        version,
+        data,
+        SaveOptions {
+            compression: method,
+            ..SaveOptions::new(true)
+        },
+    )?;
+    Ok(())
+}
+
+/// Like [crate::save], except only a 64 bit hash of the schema is written, instead of
+/// the full schema. This keeps small files small, while still catching most mistakes.
+/// When loading, the hash is compared to the hash of the in-memory schema for the
+/// version of the file. A mismatch gives [SavefileError::IncompatibleSchema], but since
+/// the full schema isn't available, the error can't say exactly what differs.
+/// See [crate::Schema::stable_hash].
+pub fn save_with_schema_hash<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    Serializer::save_impl(
+        writer,
+        version,
+        data,
+        SaveOptions {
+            with_schema_hash: true,
+            ..SaveOptions::new(false)
+        },
+    )?;
+    Ok(())
+}
+
+/// Like [crate::save], except the schemas of all versions from 0 up to `version` are written,
+/// instead of only the schema of `version`. Each field of each struct and enum variant is
+/// stored once, with the first and last version it exists in, see [SchemaHistory].
+/// Such files load like any other file with a schema, and [crate::read_schema_history] gives
+/// tools the whole history. Files saved like this can't be read by versions of savefile
+/// older than 0.9.
+pub fn save_with_schema_history<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    Serializer::save_impl(
+        writer,
+        version,
+        data,
+        SaveOptions {
+            with_schema_history: true,
+            ..SaveOptions::new(true)
+        },
+    )?;
+    Ok(())
+}
+
+/// The schema of T, without any data, to be saved to a schema file
+#[cfg_attr(not(feature = "fs"), allow(dead_code))]
+struct SchemaOnly<T>(PhantomData<T>);
+
+impl<T: WithSchema> WithSchema for SchemaOnly<T> {
+    fn schema(version: u32) -> Schema {
+        T::schema(version)
+    }
+}
+
+impl<T: WithSchema> Serialize for SchemaOnly<T> {
+    fn serialize(&self, _serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        Ok(())
+    }
+}
+
+/// Save the data to `data_path`, like [crate::save_with_schema_hash], and its full schema to
+/// `schema_path`, unless that file already exists. This is for applications which save many
+/// small files of the same type, where the schema would be much larger than the data.
+/// All of them can share a single schema file. Load the data with [crate::load_with_schema_file].
+///
+/// If the schema file exists, it must contain the schema of T of the given version. Use a
+/// different schema file for each version. The schema file is a savefile file without data,
+/// so [crate::read_schema] can read it.
+#[cfg(feature = "fs")]
+pub fn save_with_schema_file<T: WithSchema + Serialize>(
+    data_path: impl AsRef<Path>,
+    schema_path: impl AsRef<Path>,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    let schema_path = schema_path.as_ref();
+    if schema_path.exists() {
+        let (schema_version, schema) = read_schema(&mut BufReader::new(File::open(schema_path)?))?;
+        if schema_version != version || schema.stable_hash() != schema_of::<T>(version).stable_hash() {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "The schema file {} has a different schema than version {} of the saved type",
+                    schema_path.display(),
+                    version
+                ),
+            });
+        }
+    } else {
+        save_file_atomic(schema_path, version, &SchemaOnly::<T>(PhantomData))?;
+    }
+    let mut f = BufWriter::with_capacity(DEFAULT_FILE_BUFFER_CAPACITY, File::create(data_path)?);
+    save_with_schema_hash(&mut f, version, data)
+}
+
+/// Load data saved by [crate::save_with_schema_file], using the schema in `schema_path`.
+/// The schema file is compared to the schema of T, in the same way the schema
+/// stored in a file is by [crate::load]. It is also checked that the data was saved
+/// with the schema in the schema file. When loading many files, read the schema file once
+/// with [crate::read_schema], and load the data with [crate::load_with_external_schema].
+#[cfg(feature = "fs")]
+pub fn load_with_schema_file<T: WithSchema + Deserialize>(
+    data_path: impl AsRef<Path>,
+    schema_path: impl AsRef<Path>,
+    version: u32,
+) -> Result<T, SavefileError> {
+    let (_, schema) = read_schema(&mut BufReader::new(File::open(schema_path)?))?;
+    let mut f = BufReader::with_capacity(DEFAULT_FILE_BUFFER_CAPACITY, File::open(data_path)?);
+    load_with_external_schema(&mut f, version, &schema)
+}
+
+/// Load data saved with a schema hash, such as by [crate::save_with_schema_file], using
+/// the given schema the data was saved with. See [crate::load_with_schema_file].
+pub fn load_with_external_schema<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+    schema: &Schema,
+) -> Result<T, SavefileError> {
+    Deserializer::load_impl::<T>(
+        reader,
+        version,
+        LoadOptions {
+            external_schema: Some(ExternalSchema::Schema(schema)),
+            ..LoadOptions::new(false)
+        },
+    )
+}
+
+/// Save the data with only the fingerprint of its schema, like [crate::save_with_schema_hash],
+/// and add the schema to the registry. Load the data with [crate::load_with_registry].
+pub fn save_with_registry<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    registry: &mut SchemaRegistry,
+) -> Result<(), SavefileError> {
+    registry.register_type::<T>(version)?;
+    save_with_schema_hash(writer, version, data)
+}
+
+/// Load data saved with a schema hash, such as by [crate::save_with_registry], looking up
+/// the schema it was saved with by its fingerprint in the registry. The schema is compared
+/// to the schema of T, in the same way the schema stored in a file is by [crate::load].
+/// Fails if the registry doesn't know the schema.
+pub fn load_with_registry<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+    registry: &mut SchemaRegistry,
+) -> Result<T, SavefileError> {
+    Deserializer::load_impl::<T>(
+        reader,
+        version,
+        LoadOptions {
+            external_schema: Some(ExternalSchema::Registry(registry)),
+            ..LoadOptions::new(false)
+        },
+    )
+}
+
+/// Like [crate::save], but `context` is available to the Serialize-implementations of the
+/// saved types, through [Serializer::context]. See [crate::load_with_context].
+pub fn save_with_context<T: WithSchema + Serialize, C: Any>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    context: &mut C,
+) -> Result<(), SavefileError> {
+    Serializer::save_impl(
+        writer,
+        version,
+        data,
+        SaveOptions {
+            context: Some(context),
+            ..SaveOptions::new(true)
+        },
+    )?;
+    Ok(())
+}
+
+/// Like [crate::save], but reporting the progress as the data is written, and failing with
+/// [SavefileError::Cancelled] if the [CancellationToken] of the progress is cancelled.
+/// Whatever was written before the cancellation is left in the writer. To not leave a partial
+/// file behind, save to a temporary file and rename it when done, as [crate::save_file_atomic] does.
+/// See [Progress].
+pub fn save_with_progress<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    progress: &mut Progress,
+) -> Result<(), SavefileError> {
+    Serializer::save_impl(
+        writer,
+        version,
+        data,
+        SaveOptions {
+            progress: Some(progress.hooks()),
+            ..SaveOptions::new(true)
+        },
+    )?;
+    Ok(())
+}
+
+/// Like [crate::save], but using the given encoding for the data. For example,
+/// [LengthEncoding::Varint] makes files with many short strings or vectors much smaller.
+/// The encoding is recorded in the header, so the regular load-functions detect it
+/// automatically. Files saved with a non-default encoding can't be read by versions of
+/// savefile older than 0.9.
+pub fn save_with_encoding<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    encoding: Encoding,
+) -> Result<(), SavefileError> {
+    Serializer::save_impl(
+        writer,
+        version,
+        data,
+        SaveOptions {
+            encoding,
+            ..SaveOptions::new(true)
+        },
+    )?;
+    Ok(())
+}
+
+/// Like [crate::save], but without recording the creation time in the header, which
+/// is then 0. The same data then always gives the same bytes, which is useful for build
+/// artifacts, content-addressed storage and comparing files.
+pub fn save_deterministic<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    Serializer::save_impl(
+        writer,
+        version,
+        data,
+        SaveOptions {
+            with_creation_time: false,
+            ..SaveOptions::new(true)
+        },
+    )?;
+    Ok(())
+}
+
+/// A Write which discards everything written to it, and only counts the number of bytes.
+/// It can be used to find the size of serialized data, without storing it anywhere.
+/// For the size of a whole file, use [crate::serialized_size].
+#[derive(Debug, Default)]
+pub struct SizeCounter {
+    count: u64,
+}
+
+impl SizeCounter {
+    /// Create a counter which hasn't counted anything yet
+    pub fn new() -> SizeCounter {
+        SizeCounter::default()
+    }
+
+    /// The number of bytes written so far
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl Write for SizeCounter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.count += buf.len() as u64;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The number of bytes [crate::save_to_mem] produces for the given data, including the
+/// header and schema. The data is serialized, but not stored anywhere, so this is useful
+/// to preallocate buffers or check size limits before actually saving.
+/// The current version of data must be `version`.
+pub fn serialized_size<T: WithSchema + Serialize>(version: u32, data: &T) -> Result<u64, SavefileError> {
+    let mut counter = SizeCounter::new();
+    Serializer::save_impl(&mut counter, version, data, SaveOptions::new(true))?;
+    Ok(counter.count())
+}
+
+/// A Write which feeds everything written to it to a SHA-256 hasher.
+struct HashingWriter {
+    context: sha2::Sha256,
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.context.update(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Calculate the SHA-256 hash of the serialized form of the data, for deduplication or
+/// integrity checks. The data is serialized straight into the hasher, so no buffer holding
+/// all of it is needed. Only the data itself is hashed, not the header or schema.
+/// The current version of data must be `version`.
+///
+/// Equal values have equal hashes as long as they are serialized identically. This is not
+/// the case for HashMap and HashSet, whose items are serialized in an arbitrary order.
+/// Use BTreeMap, BTreeSet or IndexMap in values which are to be hashed.
+pub fn hash_value<T: WithSchema + Serialize>(version: u32, data: &T) -> Result<[u8; 32], SavefileError> {
+    let mut hashing_writer = HashingWriter {
+        context: sha2::Sha256::new(),
+    };
+    data.serialize(&mut Serializer {
+        writer: &mut hashing_writer,
+        version,
+        encoding: Encoding::default(),
+        interned_strings: HashMap::new(),
+        context: None,
+        progress: None,
+    })?;
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&hashing_writer.context.finalize());
+    Ok(hash)
+}
+
+/// Serialize the given data and return as a Vec<u8>
+/// The current version of data must be `version`. No creation time is recorded in the
+/// header, so the same data always gives the same bytes, see [crate::save_deterministic].
+pub fn save_to_mem<T: WithSchema + Serialize>(version: u32, data: &T) -> Result<Vec<u8>, SavefileError> {
+    let mut retval = Vec::new();
+    let options = SaveOptions {
+        with_creation_time: false,
+        ..SaveOptions::new(true)
+    };
+    let payload_length = Serializer::save_impl(&mut retval, version, data, options)?;
+    let offset = PAYLOAD_LENGTH_OFFSET as usize;
+    (&mut retval[offset..offset + 8]).write_u64::<LittleEndian>(payload_length)?;
+    Ok(retval)
+}
+
+/// Serialize the given data into a newly allocated Vec<u8>.
+/// This is convenient when the payload is to be sent over a channel
+/// or network socket. The current version of data must be `version`.
+/// This is the same as [crate::save_to_mem].
+pub fn save_to_vec<T: WithSchema + Serialize>(version: u32, data: &T) -> Result<Vec<u8>, SavefileError> {
+    save_to_mem(version, data)
+}
+
+/// Deserialize an instance of type T from a byte slice, typically
+/// one produced by [crate::save_to_vec].
+/// The current type of T in memory must be equal to `version`.
+/// This is the same as [crate::load_from_mem].
+pub fn load_from_slice<T: WithSchema + Deserialize>(input: &[u8], version: u32) -> Result<T, SavefileError> {
+    load_from_mem(input, version)
+}
+
+/// Like [crate::load] , but used to open files saved without schema,
+/// by one of the _noschema versions of the save functions.
+/// The hash of the schema stored in such files is compared to the hash of the schema of T,
+/// so that loading a file as the wrong type fails, instead of giving garbage.
+/// Files saved by versions of savefile older than 0.9 don't have such a hash.
+pub fn load_noschema<T: WithSchema + Deserialize>(reader: &mut impl Read, version: u32) -> Result<T, SavefileError> {
+    Deserializer::load_noschema::<T>(reader, version)
+}
+
+/// Like [crate::load_noschema], except the hash of the schema is not checked. This can be
+/// used to load files whose type has been changed in ways which don't affect how it is
+/// serialized, without changing the version, such as by renaming fields.
+pub fn load_noschema_unverified<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+) -> Result<T, SavefileError> {
+    Deserializer::load_impl::<T>(
+        reader,
+        version,
+        LoadOptions {
+            check_schema_hash: false,
+            ..LoadOptions::new(false)
+        },
+    )
+}
+
+/// Write the given `data` to the `writer`.
+/// The current version of data must be `version`.
+/// Do this write without writing any schema to disk.
+/// As long as all the serializers and deserializers
+/// are correctly written, the schema is not necessary.
+/// Omitting the schema saves some space in the saved file,
+/// but means that any mistake in implementation of the
+/// Serialize or Deserialize traits will cause hard-to-troubleshoot
+/// data corruption instead of a nice error message.
+///
+/// Only a 64 bit hash of the schema is written, as by [crate::save_with_schema_hash], which
+/// [crate::load_noschema] checks. This means files saved like this can't be read by versions
+/// of savefile older than 0.9.
+pub fn save_noschema<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    Serializer::save_noschema::<T>(writer, version, data)
+}
+
+/// The size of the buffer used by the file-based load and save functions,
+/// unless a different capacity is given.
+pub const DEFAULT_FILE_BUFFER_CAPACITY: usize = 64 * 1024;
+
+/// Like [crate::load] , except it deserializes from the given file in the filesystem.
+/// This is a pure convenience function. The file is read through a buffer
+/// of [crate::DEFAULT_FILE_BUFFER_CAPACITY] bytes.
+#[cfg(feature = "fs")]
+pub fn load_file<T: WithSchema + Deserialize>(filepath: impl AsRef<Path>, version: u32) -> Result<T, SavefileError> {
+    load_file_with_capacity(filepath, version, DEFAULT_FILE_BUFFER_CAPACITY)
+}
+
+/// Like [crate::load_file] , except the file is read through a buffer with
+/// the given capacity, in bytes.
+#[cfg(feature = "fs")]
+pub fn load_file_with_capacity<T: WithSchema + Deserialize>(
+    filepath: impl AsRef<Path>,
+    version: u32,
+    capacity: usize,
+) -> Result<T, SavefileError> {
+    load_from_with_capacity(&mut File::open(filepath)?, version, capacity)
+}
+
+/// Like [crate::load_exact] , except it deserializes from the given file in the filesystem.
+/// The file must contain nothing but the saved data.
+#[cfg(feature = "fs")]
+pub fn load_file_exact<T: WithSchema + Deserialize>(filepath: impl AsRef<Path>, version: u32) -> Result<T, SavefileError> {
+    let mut f = BufReader::with_capacity(DEFAULT_FILE_BUFFER_CAPACITY, File::open(filepath)?);
+    Deserializer::load_exact::<T>(&mut f, version)
+}
+
+/// Like [crate::load_file] , except it reads from an already opened file.
+/// Reading starts at the current position of the file. Note that because
+/// the file is read through a buffer, the position of the file afterwards
+/// may be past the end of the loaded data.
+#[cfg(feature = "fs")]
+pub fn load_from<T: WithSchema + Deserialize>(file: &mut File, version: u32) -> Result<T, SavefileError> {
+    load_from_with_capacity(file, version, DEFAULT_FILE_BUFFER_CAPACITY)
+}
+
+#[cfg(feature = "fs")]
+fn load_from_with_capacity<T: WithSchema + Deserialize>(
+    file: &mut File,
+    version: u32,
+    capacity: usize,
+) -> Result<T, SavefileError> {
+    let mut f = BufReader::with_capacity(capacity, file);
+    Deserializer::load::<T>(&mut f, version)
+}
+
+/// Like [crate::save] , except it opens a file on the filesystem and writes
+/// the data to it. This is a pure convenience function. The file is written through
+/// a buffer of [crate::DEFAULT_FILE_BUFFER_CAPACITY] bytes.
+#[cfg(feature = "fs")]
+pub fn save_file<T: WithSchema + Serialize>(filepath: impl AsRef<Path>, version: u32, data: &T) -> Result<(), SavefileError> {
+    save_file_with_capacity(filepath, version, data, DEFAULT_FILE_BUFFER_CAPACITY)
+}
+
+/// Like [crate::save_file] , except the file is written through a buffer with
+/// the given capacity, in bytes.
+#[cfg(feature = "fs")]
+pub fn save_file_with_capacity<T: WithSchema + Serialize>(
+    filepath: impl AsRef<Path>,
+    version: u32,
+    data: &T,
+    capacity: usize,
+) -> Result<(), SavefileError> {
+    save_to_with_capacity(&mut File::create(filepath)?, version, data, capacity)
+}
+
+/// Like [crate::save_file] , except it writes to an already opened file.
+/// Writing starts at the current position of the file.
+#[cfg(feature = "fs")]
+pub fn save_to<T: WithSchema + Serialize>(file: &mut File, version: u32, data: &T) -> Result<(), SavefileError> {
+    save_to_with_capacity(file, version, data, DEFAULT_FILE_BUFFER_CAPACITY)
+}
+
+#[cfg(feature = "fs")]
+fn save_to_with_capacity<T: WithSchema + Serialize>(
+    file: &mut File,
+    version: u32,
+    data: &T,
+    capacity: usize,
+) -> Result<(), SavefileError> {
+    let start = file.stream_position()?;
+    let payload_length = {
+        let mut f = BufWriter::with_capacity(capacity, &mut *file);
+        Serializer::save_impl(&mut f, version, data, SaveOptions::new(true))?
+    };
+    // Now that the payload has been written, we know its length
+    file.seek(SeekFrom::Start(start + PAYLOAD_LENGTH_OFFSET))?;
+    file.write_u64::<LittleEndian>(payload_length)?;
+    file.seek(SeekFrom::Start(start + HEADER_LENGTH + payload_length))?;
+    Ok(())
+}
+
+/// Like [crate::save_file] , except the file is replaced atomically.
+/// The data is first written to a temporary file in the same directory,
+/// which is synced to disk and then renamed over the destination. This means
+/// that a crash or power loss in the middle of saving never leaves a
+/// partially written file behind - either the old or the new contents survive.
+#[cfg(feature = "fs")]
+pub fn save_file_atomic<T: WithSchema + Serialize>(filepath: impl AsRef<Path>, version: u32, data: &T) -> Result<(), SavefileError> {
+    save_file_atomic_with_backups(filepath, version, data, 0)
+}
+
+/// Like [crate::save_file_atomic] , except up to `backups` previous versions of the file
+/// are kept. The most recent previous version is named `<filepath>.1`, the one before that
+/// `<filepath>.2`, and so on. Older files are deleted.
+#[cfg(feature = "fs")]
+pub fn save_file_atomic_with_backups<T: WithSchema + Serialize>(
+    filepath: impl AsRef<Path>,
+    version: u32,
+    data: &T,
+    backups: usize,
+) -> Result<(), SavefileError> {
+    write_file_atomic(filepath.as_ref(), backups, |f| {
+        save_to_with_capacity(f, version, data, DEFAULT_FILE_BUFFER_CAPACITY)
+    })
+}
+
+/// Replace the file at `path` atomically with the contents written by `write`,
+/// keeping `backups` previous versions. See [crate::save_file_atomic_with_backups].
+#[cfg(feature = "fs")]
+fn write_file_atomic(
+    path: &Path,
+    backups: usize,
+    write: impl FnOnce(&mut File) -> Result<(), SavefileError>,
+) -> Result<(), SavefileError> {
+    let file_name = path.file_name().ok_or_else(|| SavefileError::GeneralError {
+        msg: format!("Not a valid file path: {}", path.display()),
+    })?;
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    // The counter keeps concurrent saves of the same file within this process apart
+    static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = dir.join(temp_name);
+
+    let result = (|| -> Result<(), SavefileError> {
+        let mut f = File::create(&temp_path)?;
+        write(&mut f)?;
+        f.sync_all()?;
+        Ok(())
+    })();
+    if let Err(err) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(err);
+    }
+
+    if backups > 0 && path.exists() {
+        let backup_path = |index: usize| {
+            let mut backup = path.as_os_str().to_os_string();
+            backup.push(format!(".{}", index));
+            PathBuf::from(backup)
+        };
+        let _ = std::fs::remove_file(backup_path(backups));
+        for index in (1..backups).rev() {
+            let from = backup_path(index);
+            if from.exists() {
+                std::fs::rename(&from, backup_path(index + 1))?;
+            }
+        }
+        // Hard-linking means the destination never disappears, even briefly.
+        if std::fs::hard_link(path, backup_path(1)).is_err() {
+            std::fs::copy(path, backup_path(1))?;
+        }
+    }
+
+    std::fs::rename(&temp_path, path)?;
+
+    #[cfg(unix)]
+    {
+        // Make sure the rename itself is durable
+        File::open(&dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Like [crate::load_noschema] , except it deserializes from the given file in the filesystem.
+/// This is a pure convenience function.
+#[cfg(feature = "fs")]
+pub fn load_file_noschema<T: WithSchema + Deserialize>(filepath: impl AsRef<Path>, version: u32) -> Result<T, SavefileError> {
+    let mut f = BufReader::with_capacity(DEFAULT_FILE_BUFFER_CAPACITY, File::open(filepath)?);
+    Deserializer::load_noschema::<T>(&mut f, version)
+}
+
+/// Like [crate::save_noschema] , except it opens a file on the filesystem and writes
+/// the data to it. This is a pure convenience function.
+#[cfg(feature = "fs")]
+pub fn save_file_noschema<T: WithSchema + Serialize>(
+    filepath: impl AsRef<Path>,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    let mut f = BufWriter::with_capacity(DEFAULT_FILE_BUFFER_CAPACITY, File::create(filepath)?);
+    Serializer::save_noschema::<T>(&mut f, version, data)
+}
+
+/// This trait must be implemented by all data structures you wish to be able to save.
+/// It must encode the schema for the datastructure when saved using the given version number.
+/// When files are saved, the schema is encoded into the file.
+/// when loading, the schema is inspected to make sure that the load will safely succeed.
+/// This is only for increased safety, the file format does not in fact use the schema for any other
+/// purpose, the design is schema-less at the core, the schema is just an added layer of safety (which
+/// can be disabled).
+pub trait WithSchema {
+    /// Returns a representation of the schema used by this Serialize implementation for the given version.
+    fn schema(version: u32) -> Schema;
+
+    /// Tells if this type can be serialized by just copying its raw bytes,
+    /// for the given version. Collections like `Vec<T>` use this to write and read
+    /// all their elements at once.
+    ///
+    /// The default implementation says 'no'. `#[derive(Savefile)]` overrides this
+    /// for types which also implement [ReprC].
+    fn repr_c_layout(_version: u32) -> IsReprC<Self>
+    where
+        Self: Sized,
+    {
+        IsReprC::no()
+    }
+
+    /// Returns a cache in which the schemas of this type can be kept, so that they
+    /// do not have to be rebuilt every time a value is saved or loaded. See [schema_of].
+    ///
+    /// The default implementation returns None, meaning the schema is rebuilt each
+    /// time. `#[derive(Savefile)]` overrides this for types without generic parameters.
+    /// A cache must never be shared between types with different schemas.
+    fn schema_cache() -> Option<&'static SchemaCache> {
+        None
+    }
+}
+
+/// The current version of a type. `#[derive(Savefile)]` implements this, using the
+/// savefile_max_version attribute of the type, or else the versions of its fields and the
+/// current versions of the types of its fields.
+/// Used by [crate::save_current] and [crate::load_current], so that the version doesn't
+/// have to be given at each call.
+///
+/// Types without versions of their own, like the primitives, have version 0, and containers
+/// have the version of their contents. The versions of fields whose types don't implement
+/// this, like types with hand-written [WithSchema] implementations, and of fields whose types
+/// are generic parameters or contain the type itself, are not included. Implement this for
+/// such types, or give the version with the savefile_max_version attribute.
+pub trait CurrentVersion {
+    /// The version in which values of this type are saved
+    const SAVEFILE_MAX_VERSION: u32;
+}
+
+/// The larger of two versions, usable in constants. Used by derived implementations of
+/// [CurrentVersion].
+#[doc(hidden)]
+pub const fn max_version(a: u32, b: u32) -> u32 {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// Expands to the current version of the given type, if it implements [CurrentVersion], and
+/// otherwise to 0. Like [repr_c_probe], this only works for concrete types: for generic
+/// parameters, it is always 0. Used by `#[derive(Savefile)]`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! current_version_probe {
+    ($t:ty) => {{
+        #[allow(unused_imports)]
+        use $crate::CurrentVersionProbeNo;
+        <$crate::CurrentVersionProbe<$t>>::SAVEFILE_MAX_VERSION
+    }};
+}
+
+#[doc(hidden)]
+pub struct CurrentVersionProbe<T: ?Sized>(PhantomData<T>);
+
+impl<T: CurrentVersion + ?Sized> CurrentVersionProbe<T> {
+    pub const SAVEFILE_MAX_VERSION: u32 = T::SAVEFILE_MAX_VERSION;
+}
+
+#[doc(hidden)]
+pub trait CurrentVersionProbeNo {
+    const SAVEFILE_MAX_VERSION: u32 = 0;
+}
+
+impl<T: ?Sized> CurrentVersionProbeNo for CurrentVersionProbe<T> {}
+
+macro_rules! unversioned {
+    ($($t:ty),*) => {
+        $(
+            impl CurrentVersion for $t {
+                const SAVEFILE_MAX_VERSION: u32 = 0;
+            }
+        )*
+    };
+}
+
+unversioned!(bool, u8, i8, u16, i16, u32, i32, u64, i64, usize, isize, f32, f64, ());
+unversioned!(AtomicBool, AtomicU8, AtomicI8, AtomicU16, AtomicI16, AtomicU32, AtomicI32, AtomicU64, AtomicI64);
+unversioned!(AtomicUsize, AtomicIsize, String, str, PathBuf, bit_vec::BitVec, Canary1);
+unversioned!(Schema, SchemaPrimitive, SchemaStruct, SchemaEnum, SchemaArray, Field, Variant);
+
+macro_rules! versioned_as_contents {
+    ($($t:ident),*) => {
+        $(
+            impl<T: CurrentVersion + ?Sized> CurrentVersion for $t<T> {
+                const SAVEFILE_MAX_VERSION: u32 = T::SAVEFILE_MAX_VERSION;
+            }
+        )*
+    };
+}
+
+versioned_as_contents!(Box, Rc, Arc, RefCell, Mutex, RwLock);
+
+macro_rules! versioned_as_items {
+    ($($t:ident),*) => {
+        $(
+            impl<T: CurrentVersion> CurrentVersion for $t<T> {
+                const SAVEFILE_MAX_VERSION: u32 = T::SAVEFILE_MAX_VERSION;
+            }
+        )*
+    };
+}
+
+versioned_as_items!(Vec, VecDeque, BinaryHeap, Option, Cell, Wrapping, Range, SchemaOnly);
+
+impl<T: CurrentVersion> CurrentVersion for std::sync::Mutex<T> {
+    const SAVEFILE_MAX_VERSION: u32 = T::SAVEFILE_MAX_VERSION;
+}
+
+impl<T: CurrentVersion> CurrentVersion for [T] {
+    const SAVEFILE_MAX_VERSION: u32 = T::SAVEFILE_MAX_VERSION;
+}
+
+impl<T: CurrentVersion, const N: usize> CurrentVersion for [T; N] {
+    const SAVEFILE_MAX_VERSION: u32 = T::SAVEFILE_MAX_VERSION;
+}
+
+impl<T: CurrentVersion + ?Sized> CurrentVersion for &T {
+    const SAVEFILE_MAX_VERSION: u32 = T::SAVEFILE_MAX_VERSION;
+}
+
+impl<'a, T: CurrentVersion + ToOwned + ?Sized> CurrentVersion for Cow<'a, T> {
+    const SAVEFILE_MAX_VERSION: u32 = T::SAVEFILE_MAX_VERSION;
+}
+
+impl<K: CurrentVersion, S> CurrentVersion for HashSet<K, S> {
+    const SAVEFILE_MAX_VERSION: u32 = K::SAVEFILE_MAX_VERSION;
+}
+
+impl<K: CurrentVersion, S> CurrentVersion for IndexSet<K, S> {
+    const SAVEFILE_MAX_VERSION: u32 = K::SAVEFILE_MAX_VERSION;
+}
+
+impl<K: CurrentVersion, V: CurrentVersion, S> CurrentVersion for HashMap<K, V, S> {
+    const SAVEFILE_MAX_VERSION: u32 = max_version(K::SAVEFILE_MAX_VERSION, V::SAVEFILE_MAX_VERSION);
+}
+
+impl<K: CurrentVersion, V: CurrentVersion, S> CurrentVersion for IndexMap<K, V, S> {
+    const SAVEFILE_MAX_VERSION: u32 = max_version(K::SAVEFILE_MAX_VERSION, V::SAVEFILE_MAX_VERSION);
+}
+
+impl<K: CurrentVersion, V: CurrentVersion> CurrentVersion for BTreeMap<K, V> {
+    const SAVEFILE_MAX_VERSION: u32 = max_version(K::SAVEFILE_MAX_VERSION, V::SAVEFILE_MAX_VERSION);
+}
+
+impl<T1: CurrentVersion> CurrentVersion for (T1,) {
+    const SAVEFILE_MAX_VERSION: u32 = T1::SAVEFILE_MAX_VERSION;
+}
+
+impl<T1: CurrentVersion, T2: CurrentVersion> CurrentVersion for (T1, T2) {
+    const SAVEFILE_MAX_VERSION: u32 = max_version(T1::SAVEFILE_MAX_VERSION, T2::SAVEFILE_MAX_VERSION);
+}
+
+impl<T1: CurrentVersion, T2: CurrentVersion, T3: CurrentVersion> CurrentVersion for (T1, T2, T3) {
+    const SAVEFILE_MAX_VERSION: u32 = max_version(
+        max_version(T1::SAVEFILE_MAX_VERSION, T2::SAVEFILE_MAX_VERSION),
+        T3::SAVEFILE_MAX_VERSION,
+    );
+}
+
+impl<T: smallvec::Array> CurrentVersion for smallvec::SmallVec<T>
+where
+    T::Item: CurrentVersion,
+{
+    const SAVEFILE_MAX_VERSION: u32 = T::Item::SAVEFILE_MAX_VERSION;
 }
 
-/// Serialize the given data and return as a Vec<u8>
-/// The current version of data must be `version`.
-pub fn save_to_mem<T: WithSchema + Serialize>(version: u32, data: &T) -> Result<Vec<u8>, SavefileError> {
-    let mut retval = Vec::new();
-    Serializer::save::<T>(&mut retval, version, data, false)?;
-    Ok(retval)
+impl<T: arrayvec::Array> CurrentVersion for arrayvec::ArrayVec<T>
+where
+    T::Item: CurrentVersion,
+{
+    const SAVEFILE_MAX_VERSION: u32 = T::Item::SAVEFILE_MAX_VERSION;
 }
 
-/// Like [crate::load] , but used to open files saved without schema,
-/// by one of the _noschema versions of the save functions.
-pub fn load_noschema<T: WithSchema + Deserialize>(reader: &mut dyn Read, version: u32) -> Result<T, SavefileError> {
-    Deserializer::load_noschema::<T>(reader, version)
+impl<T: arrayvec::Array<Item = u8> + Copy> CurrentVersion for arrayvec::ArrayString<T> {
+    const SAVEFILE_MAX_VERSION: u32 = 0;
 }
 
-/// Write the given `data` to the `writer`.
-/// The current version of data must be `version`.
-/// Do this write without writing any schema to disk.
-/// As long as all the serializers and deserializers
-/// are correctly written, the schema is not necessary.
-/// Omitting the schema saves some space in the saved file,
-/// but means that any mistake in implementation of the
-/// Serialize or Deserialize traits will cause hard-to-troubleshoot
-/// data corruption instead of a nice error message.
-pub fn save_noschema<T: WithSchema + Serialize>(
-    writer: &mut dyn Write,
-    version: u32,
-    data: &T,
-) -> Result<(), SavefileError> {
-    Serializer::save_noschema::<T>(writer, version, data)
+impl<T: ?Sized> CurrentVersion for PhantomData<T> {
+    const SAVEFILE_MAX_VERSION: u32 = 0;
 }
 
-/// Like [crate::load] , except it deserializes from the given file in the filesystem.
-/// This is a pure convenience function.
-pub fn load_file<T: WithSchema + Deserialize>(filepath: &str, version: u32) -> Result<T, SavefileError> {
-    let mut f = File::open(filepath)?;
-    Deserializer::load::<T>(&mut f, version)
+// Removed fields are no longer saved, so their types don't affect the current version
+impl<T> CurrentVersion for Removed<T> {
+    const SAVEFILE_MAX_VERSION: u32 = 0;
 }
 
-/// Like [crate::save] , except it opens a file on the filesystem and writes
-/// the data to it. This is a pure convenience function.
-pub fn save_file<T: WithSchema + Serialize>(filepath: &str, version: u32, data: &T) -> Result<(), SavefileError> {
-    let mut f = File::create(filepath)?;
-    Serializer::save::<T>(&mut f, version, data, false)
+impl<S> CurrentVersion for RemovedOpaque<S> {
+    const SAVEFILE_MAX_VERSION: u32 = 0;
 }
 
-/// Like [crate::load_noschema] , except it deserializes from the given file in the filesystem.
-/// This is a pure convenience function.
-pub fn load_file_noschema<T: WithSchema + Deserialize>(filepath: &str, version: u32) -> Result<T, SavefileError> {
-    let mut f = File::open(filepath)?;
-    Deserializer::load_noschema::<T>(&mut f, version)
+/// Schemas of a type, for each version they have been requested for. Usually
+/// a static, returned by [WithSchema::schema_cache]. Schemas containing types in a
+/// [VersionNamespace] are cached for each version of the namespace as well.
+pub struct SchemaCache {
+    schemas: Mutex<Vec<CachedSchema>>,
 }
 
-/// Like [crate::save_noschema] , except it opens a file on the filesystem and writes
-/// the data to it. This is a pure convenience function.
-pub fn save_file_noschema<T: WithSchema + Serialize>(
-    filepath: &str,
+/// A schema in a [SchemaCache], with the version of the namespaces it uses
+struct CachedSchema {
     version: u32,
-    data: &T,
-) -> Result<(), SavefileError> {
-    let mut f = File::create(filepath)?;
-    Serializer::save_noschema::<T>(&mut f, version, data)
+    namespaces: namespace::UsedNamespaces,
+    schema: Arc<Schema>,
 }
 
-/// Like [crate::save_file], except encrypts the data with AES256, using the SHA256 hash
-/// of the password as key.
-pub fn save_encrypted_file<T: WithSchema + Serialize>(
-    filepath: &str,
-    version: u32,
-    data: &T,
-    password: &str,
-) -> Result<(), SavefileError> {
-    use ring::digest;
-    let actual = digest::digest(&digest::SHA256, password.as_bytes());
-    let mut key = [0u8; 32];
-    let password_hash = actual.as_ref();
-    assert_eq!(password_hash.len(), key.len(), "A SHA256 sum must be 32 bytes");
-    key.clone_from_slice(password_hash);
-
-    let mut f = File::create(filepath)?;
-    let mut writer = CryptoWriter::new(&mut f, key)?;
-
-    Serializer::save::<T>(&mut writer, version, data, true)?;
-    writer.flush()?;
-    Ok(())
+impl CachedSchema {
+    fn matches(&self, version: u32) -> bool {
+        self.version == version && namespace::same_versions(&self.namespaces)
+    }
 }
 
-/// Like [crate::load_file], except it expects the file to be an encrypted file previously stored using
-/// [crate::save_encrypted_file].
-pub fn load_encrypted_file<T: WithSchema + Deserialize>(
-    filepath: &str,
-    version: u32,
-    password: &str,
-) -> Result<T, SavefileError> {
-    use ring::digest;
-    let actual = digest::digest(&digest::SHA256, password.as_bytes());
-    let mut key = [0u8; 32];
-    let password_hash = actual.as_ref();
-    assert_eq!(password_hash.len(), key.len(), "A SHA256 sum must be 32 bytes");
-    key.clone_from_slice(password_hash);
+impl SchemaCache {
+    /// Create an empty cache.
+    pub const fn new() -> SchemaCache {
+        SchemaCache {
+            schemas: parking_lot::const_mutex(Vec::new()),
+        }
+    }
 
-    let mut f = File::open(filepath)?;
-    let mut reader = CryptoReader::new(&mut f, key).unwrap();
-    Deserializer::load::<T>(&mut reader, version)
+    /// Return the cached schema for the version, or build and cache it using `build`.
+    pub fn get_or_insert_with(&self, version: u32, build: impl FnOnce() -> Schema) -> Arc<Schema> {
+        {
+            let schemas = self.schemas.lock();
+            if let Some(cached) = schemas.iter().find(|cached| cached.matches(version)) {
+                namespace::record_used(&cached.namespaces);
+                return cached.schema.clone();
+            }
+        }
+        // Build without holding the lock, since building may need the caches of other types
+        let (schema, namespaces) = namespace::recording_used(build);
+        let schema = Arc::new(schema);
+        let mut schemas = self.schemas.lock();
+        if let Some(cached) = schemas.iter().find(|cached| cached.matches(version)) {
+            return cached.schema.clone();
+        }
+        schemas.push(CachedSchema {
+            version,
+            namespaces,
+            schema: schema.clone(),
+        });
+        schema
+    }
 }
 
-/// This trait must be implemented by all data structures you wish to be able to save.
-/// It must encode the schema for the datastructure when saved using the given version number.
-/// When files are saved, the schema is encoded into the file.
-/// when loading, the schema is inspected to make sure that the load will safely succeed.
-/// This is only for increased safety, the file format does not in fact use the schema for any other
-/// purpose, the design is schema-less at the core, the schema is just an added layer of safety (which
-/// can be disabled).
-pub trait WithSchema {
-    /// Returns a representation of the schema used by this Serialize implementation for the given version.
-    fn schema(version: u32) -> Schema;
+impl Default for SchemaCache {
+    fn default() -> SchemaCache {
+        SchemaCache::new()
+    }
+}
+
+/// Returns the schema of T for the given version. If T has a [SchemaCache],
+/// the schema is built only the first time, and shared after that.
+pub fn schema_of<T: WithSchema>(version: u32) -> Arc<Schema> {
+    match T::schema_cache() {
+        Some(cache) => cache.get_or_insert_with(version, || T::schema(version)),
+        None => Arc::new(T::schema(version)),
+    }
 }
 
 /// This trait must be implemented for all data structures you wish to be
@@ -1701,7 +4578,7 @@ pub trait WithSchema {
 /// and the use #\[derive(Serialize)]
 pub trait Serialize: WithSchema {
     /// Serialize self into the given serializer.
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError>; //TODO: Do error handling
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError>; //TODO: Do error handling
 }
 
 /// A child of an object implementing Introspect. Is a key-value pair. The only reason this is not
@@ -1765,26 +4642,175 @@ pub trait Introspect {
 /// and the use #\[derive(Deserialize)]
 pub trait Deserialize: WithSchema + Sized {
     /// Deserialize and return an instance of Self from the given deserializer.
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError>; //TODO: Do error handling
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError>; //TODO: Do error handling
+
+    /// Deserialize into an existing instance of Self, replacing its value. Implementations
+    /// can reuse memory allocated by the existing value, which avoids allocations when the
+    /// same value is loaded over and over. The default implementation deserializes a new
+    /// value and assigns it. If an error is returned, self is left in an unspecified, but
+    /// valid, state.
+    fn deserialize_in_place(&mut self, deserializer: &mut Deserializer<impl Read>) -> Result<(), SavefileError> {
+        *self = Self::deserialize(deserializer)?;
+        Ok(())
+    }
+}
+
+/// A [Deserializer] reading from a type-erased reader, see [DeserializeDyn]
+pub type DynDeserializer<'a, 'b> = Deserializer<'a, &'b mut dyn Read>;
+
+/// An object-safe version of [Serialize], implemented for all types implementing it.
+/// This allows values of different types to be serialized through `&dyn SerializeDyn`,
+/// at the cost of a virtual call per write.
+pub trait SerializeDyn {
+    /// Serialize self into the given type-erased serializer.
+    fn serialize_dyn(&self, serializer: &mut DynSerializer) -> Result<(), SavefileError>;
+}
+
+impl<T: Serialize + ?Sized> SerializeDyn for T {
+    fn serialize_dyn(&self, serializer: &mut DynSerializer) -> Result<(), SavefileError> {
+        self.serialize(serializer)
+    }
+}
+
+/// An object-safe version of [Deserialize], implemented for all types implementing it.
+/// Since a trait object can't be returned by value, this deserializes into an existing value,
+/// like [Deserialize::deserialize_in_place].
+pub trait DeserializeDyn {
+    /// Deserialize into self from the given type-erased deserializer, replacing its value.
+    fn deserialize_in_place_dyn(&mut self, deserializer: &mut DynDeserializer) -> Result<(), SavefileError>;
+}
+
+impl<T: Deserialize> DeserializeDyn for T {
+    fn deserialize_in_place_dyn(&mut self, deserializer: &mut DynDeserializer) -> Result<(), SavefileError> {
+        self.deserialize_in_place(deserializer)
+    }
+}
+
+/// A hook called after a value has been deserialized, for types deriving Savefile with the
+/// `#[savefile_post_deserialize]` attribute. See the crate documentation.
+///
+/// The hook isn't called for values loaded as raw memory, which types implementing [ReprC]
+/// may be, such as the items of vectors of them.
+pub trait PostDeserialize {
+    /// Called with the newly deserialized value, and the version of the file it was read from.
+    fn post_deserialize(&mut self, file_version: u32);
+}
+
+/// Types which can be stored in a few bits, in fields marked `#[savefile_packed]`.
+/// Consecutive such fields are stored together, in as few bytes as possible,
+/// which makes large arrays of structs with many flags much smaller.
+///
+/// This is implemented for bool, and `#[derive(Savefile)]` implements it for enums
+/// whose variants have no fields. Such enums use as many bits as needed for their
+/// largest discriminator, so adding variants later makes them use more bits.
+pub trait BitPacked: WithSchema + Sized {
+    /// The number of bits used in the given version. This must agree with the schema,
+    /// see [Schema::BitPacked].
+    fn bit_width(version: u32) -> u32;
+    /// The value as bits. Only the lowest `bit_width` bits may be set.
+    fn to_bits(&self) -> u64;
+    /// Create a value from the bits returned by `to_bits`
+    fn from_bits(bits: u64) -> Result<Self, SavefileError>;
+}
+
+impl BitPacked for bool {
+    fn bit_width(_version: u32) -> u32 {
+        1
+    }
+    fn to_bits(&self) -> u64 {
+        *self as u64
+    }
+    fn from_bits(bits: u64) -> Result<Self, SavefileError> {
+        Ok(bits != 0)
+    }
+}
+
+/// A group of bit-packed values, see [BitPacked]. Values are added at increasingly
+/// significant bits, and taken in the same order. Used by the code generated by
+/// `#[derive(Savefile)]`.
+#[derive(Debug, Default)]
+pub struct PackedBits {
+    bits: u64,
+    width: u32,
+}
+
+impl PackedBits {
+    /// Create an empty group
+    pub fn new() -> PackedBits {
+        PackedBits::default()
+    }
+
+    /// The number of bits in the group
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Add the lowest `width` bits of `bits`. Fails if the group would have more than 64 bits.
+    pub fn push_bits(&mut self, bits: u64, width: u32) -> Result<(), SavefileError> {
+        if self.width + width > 64 {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "Consecutive bit-packed fields use {} bits, but at most 64 are allowed",
+                    self.width + width
+                ),
+            });
+        }
+        if width > 0 {
+            self.bits |= (bits & mask_bits(width)) << self.width;
+            self.width += width;
+        }
+        Ok(())
+    }
+
+    /// Take the next `width` bits. Missing bits are zero.
+    pub fn pop_bits(&mut self, width: u32) -> u64 {
+        let width = width.min(64);
+        let bits = self.bits & mask_bits(width);
+        self.bits = self.bits.checked_shr(width).unwrap_or(0);
+        self.width = self.width.saturating_sub(width);
+        bits
+    }
+
+    /// Add a value, using the number of bits of the given version. Fails if the value
+    /// doesn't fit, which happens for enum variants which don't exist in that version.
+    pub fn push<T: BitPacked>(&mut self, value: &T, version: u32) -> Result<(), SavefileError> {
+        let bits = value.to_bits();
+        let width = T::bit_width(version);
+        if bits & !mask_bits(width) != 0 {
+            return Err(SavefileError::GeneralError {
+                msg: format!("The value {} does not fit in {} bits in version {}", bits, width, version),
+            });
+        }
+        self.push_bits(bits, width)
+    }
+
+    /// Take the next value, using the number of bits of the given version
+    pub fn pop<T: BitPacked>(&mut self, version: u32) -> Result<T, SavefileError> {
+        T::from_bits(self.pop_bits(T::bit_width(version)))
+    }
+}
+
+fn mask_bits(width: u32) -> u64 {
+    u64::MAX.checked_shr(64 - width).unwrap_or(0)
 }
 
 /// A field is serialized according to its value.
 /// The name is just for diagnostics.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Field {
     /// Field name
     pub name: String,
     /// Field type
-    pub value: Box<Schema>,
+    pub value: Arc<Schema>,
 }
 
 /// An array is serialized by serializing its items one by one,
 /// without any padding.
 /// The dbg_name is just for diagnostics.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct SchemaArray {
     /// Type of array elements
-    pub item_type: Box<Schema>,
+    pub item_type: Arc<Schema>,
     /// Length of array
     pub count: usize,
 }
@@ -1798,7 +4824,7 @@ impl SchemaArray {
 /// A struct is serialized by serializing its fields one by one,
 /// without any padding.
 /// The dbg_name is just for diagnostics.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct SchemaStruct {
     /// Diagnostic value
     pub dbg_name: String,
@@ -1815,28 +4841,85 @@ fn maybe_add(a: Option<usize>, b: Option<usize>) -> Option<usize> {
 }
 impl SchemaStruct {
     fn serialized_size(&self) -> Option<usize> {
-        self.fields
-            .iter()
-            .fold(Some(0usize), |prev, x| maybe_add(prev, x.value.serialized_size()))
+        fields_serialized_size(&self.fields)
+    }
+}
+
+/// The number of bits used by a bit-packed value with the given schema, which is the
+/// schema inside [Schema::BitPacked]. Enums use as many bits as their largest discriminator.
+pub(crate) fn packed_bit_width(schema: &Schema) -> Result<u32, SavefileError> {
+    match *schema {
+        Schema::Primitive(SchemaPrimitive::schema_bool) => Ok(1),
+        Schema::Enum(ref schema_enum) if schema_enum.variants.iter().all(|variant| variant.fields.is_empty()) => {
+            let max = schema_enum
+                .variants
+                .iter()
+                .map(|variant| variant.discriminator)
+                .max()
+                .unwrap_or(0);
+            Ok(32 - max.leading_zeros())
+        }
+        _ => Err(SavefileError::GeneralError {
+            msg: format!("Only bools and enums without fields can be bit-packed, not {}", schema),
+        }),
+    }
+}
+
+/// The number of bit-packed values at the start of `schemas`, which are stored together,
+/// and their total width in bits.
+pub(crate) fn packed_run<'a>(schemas: impl IntoIterator<Item = &'a Schema>) -> Result<(usize, u32), SavefileError> {
+    let mut count = 0;
+    let mut width = 0;
+    for schema in schemas {
+        match *schema {
+            Schema::BitPacked(ref item) => width += packed_bit_width(item)?,
+            _ => break,
+        }
+        count += 1;
+    }
+    if width > 64 {
+        return Err(SavefileError::GeneralError {
+            msg: format!("Consecutive bit-packed fields use {} bits, but at most 64 are allowed", width),
+        });
+    }
+    Ok((count, width))
+}
+
+/// The number of bytes used by bit-packed values with the given total width
+pub(crate) fn packed_bytes(width: u32) -> usize {
+    width.div_ceil(8) as usize
+}
+
+fn fields_serialized_size(fields: &[Field]) -> Option<usize> {
+    let mut size = Some(0usize);
+    let mut index = 0;
+    while index < fields.len() {
+        if let Schema::BitPacked(_) = *fields[index].value {
+            let (count, width) = packed_run(fields[index..].iter().map(|field| &*field.value)).ok()?;
+            size = maybe_add(size, Some(packed_bytes(width)));
+            index += count;
+        } else {
+            size = maybe_add(size, fields[index].value.serialized_size());
+            index += 1;
+        }
     }
+    size
 }
 
 /// An enum variant is serialized as its fields, one by one,
 /// without any padding.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Variant {
     /// Name of variant
     pub name: String,
     /// Discriminator in binary file-format
-    pub discriminator: u8,
+    pub discriminator: u32,
     /// Fields of variant
     pub fields: Vec<Field>,
 }
 impl Variant {
     fn serialized_size(&self) -> Option<usize> {
-        self.fields
-            .iter()
-            .fold(Some(0usize), |prev, x| maybe_add(prev, x.value.serialized_size()))
+        fields_serialized_size(&self.fields)
     }
 }
 
@@ -1844,12 +4927,15 @@ impl Variant {
 /// followed by all the field for that variant.
 /// The name of each variant, as well as its order in
 /// the enum (the discriminator), is significant.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct SchemaEnum {
     /// Diagnostic name
     pub dbg_name: String,
     /// Variants of enum
     pub variants: Vec<Variant>,
+    /// The number of bytes of the discriminator, 1, 2 or 4, see the
+    /// `savefile_enum_repr` attribute
+    pub discriminant_size: u8,
 }
 
 fn maybe_max(a: Option<usize>, b: Option<usize>) -> Option<usize> {
@@ -1862,7 +4948,7 @@ fn maybe_max(a: Option<usize>, b: Option<usize>) -> Option<usize> {
 }
 impl SchemaEnum {
     fn serialized_size(&self) -> Option<usize> {
-        let discr_size = 1usize; //Discriminator is always 1 byte
+        let discr_size = self.discriminant_size as usize;
         self.variants
             .iter()
             .fold(Some(discr_size), |prev, x| maybe_max(prev, x.serialized_size()))
@@ -1904,6 +4990,24 @@ pub enum SchemaPrimitive {
     schema_canary1,
 }
 impl SchemaPrimitive {
+    /// The discriminator of the primitive in the serialized schema
+    fn to_u8(self) -> u8 {
+        match self {
+            SchemaPrimitive::schema_i8 => 1,
+            SchemaPrimitive::schema_u8 => 2,
+            SchemaPrimitive::schema_i16 => 3,
+            SchemaPrimitive::schema_u16 => 4,
+            SchemaPrimitive::schema_i32 => 5,
+            SchemaPrimitive::schema_u32 => 6,
+            SchemaPrimitive::schema_i64 => 7,
+            SchemaPrimitive::schema_u64 => 8,
+            SchemaPrimitive::schema_string => 9,
+            SchemaPrimitive::schema_f32 => 10,
+            SchemaPrimitive::schema_f64 => 11,
+            SchemaPrimitive::schema_bool => 12,
+            SchemaPrimitive::schema_canary1 => 13,
+        }
+    }
     fn name(&self) -> &'static str {
         match *self {
             SchemaPrimitive::schema_i8 => "i8",
@@ -1939,25 +5043,13 @@ impl SchemaPrimitive {
     }
 }
 
-fn diff_primitive(a: SchemaPrimitive, b: SchemaPrimitive, path: &str) -> Option<String> {
-    if a != b {
-        return Some(format!(
-            "At location [{}]: Application protocol has datatype {}, but disk format has {}",
-            path,
-            a.name(),
-            b.name()
-        ));
-    }
-    None
-}
-
 /// The schema represents the save file format
 /// of your data structure. It is an AST (Abstract Syntax Tree)
 /// for consisting of various types of nodes in the savefile
 /// format. Custom Serialize-implementations cannot add new types to
 /// this tree, but must reuse these existing ones.
 /// See the various enum variants for more information:
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Schema {
     /// Represents a struct. Custom implementations of Serialize may use this
     /// format are encouraged to use this format.
@@ -1967,15 +5059,19 @@ pub enum Schema {
     /// Represents a primitive: Any of the various integer types (u8, i8, u16, i16 etc...), or String
     Primitive(SchemaPrimitive),
     /// A Vector of arbitrary nodes, all of the given type
-    Vector(Box<Schema>),
+    Vector(Arc<Schema>),
     /// An array of N arbitrary nodes, all of the given type
     Array(SchemaArray),
     /// An Option variable instance of the given type.
-    SchemaOption(Box<Schema>),
+    SchemaOption(Arc<Schema>),
     /// Basically a dummy value, the Schema nodes themselves report this schema if queried.
     Undefined,
     /// A zero-sized type. I.e, there is no data to serialize or deserialize.
     ZeroSize,
+    /// A bool or fieldless enum stored in as few bits as possible, see [BitPacked].
+    /// Consecutive bit-packed fields of a struct or enum variant are stored together,
+    /// in the smallest possible number of bytes.
+    BitPacked(Arc<Schema>),
 }
 
 impl Schema {
@@ -1985,7 +5081,7 @@ impl Schema {
             dbg_name: "1-Tuple".to_string(),
             fields: vec![Field {
                 name: "0".to_string(),
-                value: Box::new(T1::schema(version)),
+                value: schema_of::<T1>(version),
             }],
         })
     }
@@ -1997,11 +5093,11 @@ impl Schema {
             fields: vec![
                 Field {
                     name: "0".to_string(),
-                    value: Box::new(T1::schema(version)),
+                    value: schema_of::<T1>(version),
                 },
                 Field {
                     name: "1".to_string(),
-                    value: Box::new(T2::schema(version)),
+                    value: schema_of::<T2>(version),
                 },
             ],
         })
@@ -2013,15 +5109,15 @@ impl Schema {
             fields: vec![
                 Field {
                     name: "0".to_string(),
-                    value: Box::new(T1::schema(version)),
+                    value: schema_of::<T1>(version),
                 },
                 Field {
                     name: "1".to_string(),
-                    value: Box::new(T2::schema(version)),
+                    value: schema_of::<T2>(version),
                 },
                 Field {
                     name: "2".to_string(),
-                    value: Box::new(T3::schema(version)),
+                    value: schema_of::<T3>(version),
                 },
             ],
         })
@@ -2033,23 +5129,89 @@ impl Schema {
             fields: vec![
                 Field {
                     name: "0".to_string(),
-                    value: Box::new(T1::schema(version)),
+                    value: schema_of::<T1>(version),
                 },
                 Field {
                     name: "1".to_string(),
-                    value: Box::new(T2::schema(version)),
+                    value: schema_of::<T2>(version),
                 },
                 Field {
                     name: "2".to_string(),
-                    value: Box::new(T3::schema(version)),
+                    value: schema_of::<T3>(version),
                 },
                 Field {
                     name: "3".to_string(),
-                    value: Box::new(T4::schema(version)),
+                    value: schema_of::<T4>(version),
                 },
             ],
         })
     }
+    /// A 64 bit hash of the schema, which is stable across platforms and versions of rust.
+    /// Only the properties which are checked when comparing schemas are included. For
+    /// instance, the names of struct fields don't affect the hash, but the names of enum
+    /// variants do.
+    pub fn stable_hash(&self) -> u64 {
+        let mut context = sha2::Sha256::new();
+        self.hash_into(&mut context);
+        let digest = context.finalize();
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn hash_into(&self, context: &mut sha2::Sha256) {
+        fn hash_fields(fields: &[Field], context: &mut sha2::Sha256) {
+            context.update((fields.len() as u64).to_le_bytes());
+            for field in fields {
+                field.value.hash_into(context);
+            }
+        }
+        match *self {
+            Schema::Struct(ref schema_struct) => {
+                context.update([1]);
+                hash_fields(&schema_struct.fields, context);
+            }
+            Schema::Enum(ref schema_enum) => {
+                // Enums with wider discriminators hash differently, keeping the hash of the others
+                if schema_enum.discriminant_size == 1 {
+                    context.update([2]);
+                } else {
+                    context.update([10, schema_enum.discriminant_size]);
+                }
+                context.update((schema_enum.variants.len() as u64).to_le_bytes());
+                for variant in &schema_enum.variants {
+                    context.update((variant.name.len() as u64).to_le_bytes());
+                    context.update(variant.name.as_bytes());
+                    let discriminator = variant.discriminator.to_le_bytes();
+                    context.update(&discriminator[..schema_enum.discriminant_size as usize]);
+                    hash_fields(&variant.fields, context);
+                }
+            }
+            Schema::Primitive(primitive) => {
+                context.update([3, primitive.to_u8()]);
+            }
+            Schema::Vector(ref item) => {
+                context.update([4]);
+                item.hash_into(context);
+            }
+            Schema::Array(ref array) => {
+                context.update([5]);
+                context.update((array.count as u64).to_le_bytes());
+                array.item_type.hash_into(context);
+            }
+            Schema::SchemaOption(ref item) => {
+                context.update([6]);
+                item.hash_into(context);
+            }
+            Schema::Undefined => context.update([7]),
+            Schema::ZeroSize => context.update([8]),
+            Schema::BitPacked(ref item) => {
+                context.update([9]);
+                item.hash_into(context);
+            }
+        }
+    }
+
     /// Size
     pub fn serialized_size(&self) -> Option<usize> {
         match *self {
@@ -2061,106 +5223,521 @@ impl Schema {
             Schema::SchemaOption(ref _content) => None,
             Schema::Undefined => None,
             Schema::ZeroSize => Some(0),
+            // A bit-packed value on its own takes whole bytes
+            Schema::BitPacked(_) => packed_run(std::iter::once(self)).ok().map(|(_, width)| packed_bytes(width)),
+        }
+    }
+}
+
+impl Schema {
+    fn fmt_indented(&self, f: &mut Formatter<'_>, indent: usize) -> std::fmt::Result {
+        match *self {
+            Schema::Struct(ref schema_struct) => {
+                write!(f, "struct {} ", schema_struct.dbg_name)?;
+                fmt_fields(&schema_struct.fields, f, indent)
+            }
+            Schema::Enum(ref schema_enum) => {
+                writeln!(f, "enum {} {{", schema_enum.dbg_name)?;
+                for variant in &schema_enum.variants {
+                    write!(f, "{:w$}{} = {}", "", variant.name, variant.discriminator, w = (indent + 1) * 4)?;
+                    if !variant.fields.is_empty() {
+                        write!(f, " ")?;
+                        fmt_fields(&variant.fields, f, indent + 1)?;
+                    }
+                    writeln!(f)?;
+                }
+                write!(f, "{:w$}}}", "", w = indent * 4)
+            }
+            Schema::Primitive(primitive) => write!(f, "{}", primitive.name()),
+            Schema::Vector(ref item) => {
+                write!(f, "Vec<")?;
+                item.fmt_indented(f, indent)?;
+                write!(f, ">")
+            }
+            Schema::Array(ref array) => {
+                write!(f, "[")?;
+                array.item_type.fmt_indented(f, indent)?;
+                write!(f, "; {}]", array.count)
+            }
+            Schema::SchemaOption(ref item) => {
+                write!(f, "Option<")?;
+                item.fmt_indented(f, indent)?;
+                write!(f, ">")
+            }
+            Schema::Undefined => write!(f, "<undefined>"),
+            Schema::ZeroSize => write!(f, "()"),
+            Schema::BitPacked(ref item) => {
+                write!(f, "packed ")?;
+                item.fmt_indented(f, indent)
+            }
+        }
+    }
+}
+
+fn fmt_fields(fields: &[Field], f: &mut Formatter<'_>, indent: usize) -> std::fmt::Result {
+    writeln!(f, "{{")?;
+    for field in fields {
+        write!(f, "{:w$}{}: ", "", field.name, w = (indent + 1) * 4)?;
+        field.value.fmt_indented(f, indent + 1)?;
+        writeln!(f)?;
+    }
+    write!(f, "{:w$}}}", "", w = indent * 4)
+}
+
+/// Renders the schema as an indented tree, with one line per field or enum variant.
+/// This is meant for humans, for instance when investigating why a file can't be loaded.
+impl Display for Schema {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// The way in which two schemas differ, see [SchemaDifference].
+/// "memory" refers to the schema of the data structures in memory, and "file"
+/// to the schema of the file being loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaDifferenceKind {
+    /// The nodes are of different kinds, for instance a struct in memory, but a vector in the file.
+    /// The kinds are given as lower case names, such as "struct" or "vector".
+    KindMismatch {
+        /// Kind of the node in memory
+        memory: &'static str,
+        /// Kind of the node in the file
+        file: &'static str,
+    },
+    /// The in-memory schema contains [Schema::Undefined], which can't be compared.
+    Undefined,
+    /// Different primitive types
+    PrimitiveMismatch {
+        /// Type in memory
+        memory: SchemaPrimitive,
+        /// Type in the file
+        file: SchemaPrimitive,
+    },
+    /// Arrays have different lengths
+    ArrayLengthMismatch {
+        /// Length in memory
+        memory: usize,
+        /// Length in the file
+        file: usize,
+    },
+    /// Structs have different numbers of fields
+    StructFieldCountMismatch {
+        /// Name of the struct in memory
+        memory_name: String,
+        /// Name of the struct in the file
+        file_name: String,
+        /// Number of fields in memory
+        memory: usize,
+        /// Number of fields in the file
+        file: usize,
+    },
+    /// Enums have different numbers of variants
+    VariantCountMismatch {
+        /// Number of variants in memory
+        memory: usize,
+        /// Number of variants in the file
+        file: usize,
+    },
+    /// An enum variant has different names
+    VariantNameMismatch {
+        /// Index of the variant
+        index: usize,
+        /// Name in memory
+        memory: String,
+        /// Name in the file
+        file: String,
+    },
+    /// An enum variant has different discriminators
+    VariantDiscriminatorMismatch {
+        /// Index of the variant
+        index: usize,
+        /// Discriminator in memory
+        memory: u32,
+        /// Discriminator in the file
+        file: u32,
+    },
+    /// Enums have discriminators of different sizes
+    DiscriminantSizeMismatch {
+        /// Size of the discriminator in memory, in bytes
+        memory: u8,
+        /// Size of the discriminator in the file, in bytes
+        file: u8,
+    },
+    /// Enum variants have different numbers of fields
+    VariantFieldCountMismatch {
+        /// Number of fields in memory
+        memory: usize,
+        /// Number of fields in the file
+        file: usize,
+    },
+    /// A field present in two versions has changed position relative to the
+    /// other fields. Only reported by [crate::verify_version_compatibility].
+    FieldMoved {
+        /// Name of the field
+        name: String,
+    },
+}
+
+/// A difference between two schemas, as returned by [crate::schema_differences].
+/// The Display implementation produces the same message as used in
+/// [SavefileError::IncompatibleSchema].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaDifference {
+    /// Location of the difference, such as `./MyStruct/my_field`. Vectors are
+    /// denoted by `*`, options by `?` and arrays by `[N]`.
+    pub path: String,
+    /// What differs
+    pub kind: SchemaDifferenceKind,
+}
+
+impl Display for SchemaDifference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let path = &self.path;
+        match &self.kind {
+            SchemaDifferenceKind::KindMismatch { memory, file } => write!(
+                f,
+                "At location [{}]: In memory schema: {}, file schema: {}",
+                path, memory, file
+            ),
+            SchemaDifferenceKind::Undefined => write!(f, "At location [{}]: Undefined schema encountered.", path),
+            SchemaDifferenceKind::PrimitiveMismatch { memory, file } => write!(
+                f,
+                "At location [{}]: Application protocol has datatype {}, but disk format has {}",
+                path,
+                memory.name(),
+                file.name()
+            ),
+            SchemaDifferenceKind::ArrayLengthMismatch { memory, file } => write!(
+                f,
+                "At location [{}]: In memory array has length {}, but disk format length {}.",
+                path, memory, file
+            ),
+            SchemaDifferenceKind::StructFieldCountMismatch {
+                memory_name,
+                file_name,
+                memory,
+                file,
+            } => write!(
+                f,
+                "At location [{}]: In memory struct (struct {}) has {} fields, disk format (struct {}) has {} fields.",
+                path, memory_name, memory, file_name, file
+            ),
+            SchemaDifferenceKind::VariantCountMismatch { memory, file } => write!(
+                f,
+                "At location [{}]: In memory enum has {} variants, but disk format has {} variants.",
+                path, memory, file
+            ),
+            SchemaDifferenceKind::VariantNameMismatch { index, memory, file } => write!(
+                f,
+                "At location [{}]: Enum variant #{} in memory is called {}, but in disk format it is called {}",
+                path, index, memory, file
+            ),
+            SchemaDifferenceKind::VariantDiscriminatorMismatch { index, memory, file } => write!(
+                f,
+                "At location [{}]: Enum variant #{} in memory has discriminator {}, but in disk format it has {}",
+                path, index, memory, file
+            ),
+            SchemaDifferenceKind::DiscriminantSizeMismatch { memory, file } => write!(
+                f,
+                "At location [{}]: In memory enum has a discriminator of {} bytes, but disk format has {} bytes.",
+                path, memory, file
+            ),
+            SchemaDifferenceKind::VariantFieldCountMismatch { memory, file } => write!(
+                f,
+                "At location [{}]: In memory enum has {} fields, disk format has {} fields.",
+                path, memory, file
+            ),
+            SchemaDifferenceKind::FieldMoved { name } => write!(
+                f,
+                "At location [{}]: Field {} has moved relative to the other fields. Fields present in several versions must keep their order.",
+                path, name
+            ),
         }
     }
 }
 
-fn diff_vector(a: &Schema, b: &Schema, path: String) -> Option<String> {
-    diff_schema(a, b, path + "/*")
+/// Find all differences between the schema `memory`, of the data structures in memory,
+/// and the schema `file`, read from a file. An empty result means the schemas are
+/// compatible. Once a difference has been found, the nodes below it are not compared,
+/// but comparison continues with the following fields and variants.
+pub fn schema_differences(memory: &Schema, file: &Schema) -> Vec<SchemaDifference> {
+    let mut out = Vec::new();
+    diff_schema_into(memory, file, ".".to_string(), &mut out);
+    out
+}
+
+/// Check that the schemas of T for all versions from `old_version` to `new_version` only
+/// differ in ways allowed by the versioning support of savefile. Consecutive versions
+/// are compared. Fields may be added or removed (using `#[savefile_versions]` and [Removed]),
+/// but fields present in both versions must keep their order and type, and enums must keep
+/// their variants.
+///
+/// This is meant to be used in tests, to catch a refactoring which would break loading
+/// files saved by earlier versions of the application. All problems found are reported
+/// in the [SavefileError::IncompatibleSchema] returned. Note that changing the type of a field
+/// is reported, even if it is done intentionally using `#[savefile_versions_as]`.
+pub fn verify_version_compatibility<T: WithSchema>(old_version: u32, new_version: u32) -> Result<(), SavefileError> {
+    let mut messages = Vec::new();
+    let mut old_schema = T::schema(old_version);
+    for version in old_version.saturating_add(1)..=new_version {
+        let new_schema = T::schema(version);
+        let mut out = Vec::new();
+        diff_versions_into(&old_schema, &new_schema, ".".to_string(), &mut out);
+        for difference in out {
+            messages.push(format!("Version {} to {}: {}", version - 1, version, difference));
+        }
+        old_schema = new_schema;
+    }
+    if messages.is_empty() {
+        Ok(())
+    } else {
+        Err(SavefileError::IncompatibleSchema {
+            message: messages.join("\n"),
+        })
+    }
+}
+
+/// Compare the schemas of two consecutive versions of a type. Unlike [diff_schema_into],
+/// fields of structs and enum variants are matched by name, so that they can be
+/// added and removed.
+fn diff_versions_into(old: &Schema, new: &Schema, path: String, out: &mut Vec<SchemaDifference>) {
+    match (old, new) {
+        (Schema::Struct(old), Schema::Struct(new)) => {
+            diff_fields_by_name(&old.fields, &new.fields, &(path + "/" + &new.dbg_name), out)
+        }
+        (Schema::Enum(old), Schema::Enum(new)) => {
+            let path = path + &new.dbg_name;
+            if old.discriminant_size != new.discriminant_size {
+                out.push(SchemaDifference {
+                    path,
+                    kind: SchemaDifferenceKind::DiscriminantSizeMismatch {
+                        memory: new.discriminant_size,
+                        file: old.discriminant_size,
+                    },
+                });
+                return;
+            }
+            if old.variants.len() != new.variants.len() {
+                out.push(SchemaDifference {
+                    path,
+                    kind: SchemaDifferenceKind::VariantCountMismatch {
+                        memory: new.variants.len(),
+                        file: old.variants.len(),
+                    },
+                });
+                return;
+            }
+            for (index, (old_variant, new_variant)) in old.variants.iter().zip(new.variants.iter()).enumerate() {
+                if old_variant.name != new_variant.name {
+                    out.push(SchemaDifference {
+                        path: path.clone(),
+                        kind: SchemaDifferenceKind::VariantNameMismatch {
+                            index,
+                            memory: new_variant.name.clone(),
+                            file: old_variant.name.clone(),
+                        },
+                    });
+                } else if old_variant.discriminator != new_variant.discriminator {
+                    out.push(SchemaDifference {
+                        path: path.clone(),
+                        kind: SchemaDifferenceKind::VariantDiscriminatorMismatch {
+                            index,
+                            memory: new_variant.discriminator,
+                            file: old_variant.discriminator,
+                        },
+                    });
+                } else {
+                    diff_fields_by_name(
+                        &old_variant.fields,
+                        &new_variant.fields,
+                        &(path.clone() + "/" + &new_variant.name),
+                        out,
+                    );
+                }
+            }
+        }
+        (Schema::Vector(old), Schema::Vector(new)) => diff_versions_into(old, new, path + "/*", out),
+        (Schema::SchemaOption(old), Schema::SchemaOption(new)) => diff_versions_into(old, new, path + "/?", out),
+        (Schema::Array(old_array), Schema::Array(new_array)) if old_array.count == new_array.count => diff_versions_into(
+            &old_array.item_type,
+            &new_array.item_type,
+            format!("{}/[{}]", path, new_array.count),
+            out,
+        ),
+        _ => diff_schema_into(new, old, path, out),
+    }
+}
+
+fn diff_fields_by_name(old: &[Field], new: &[Field], path: &str, out: &mut Vec<SchemaDifference>) {
+    let mut previous_index = None;
+    for new_field in new {
+        if let Some(index) = old.iter().position(|old_field| old_field.name == new_field.name) {
+            if matches!(previous_index, Some(previous) if index < previous) {
+                out.push(SchemaDifference {
+                    path: path.to_string(),
+                    kind: SchemaDifferenceKind::FieldMoved {
+                        name: new_field.name.clone(),
+                    },
+                });
+            }
+            previous_index = Some(index);
+            diff_versions_into(&old[index].value, &new_field.value, path.to_string() + "/" + &new_field.name, out);
+        }
+    }
+}
+
+fn diff_primitive(a: SchemaPrimitive, b: SchemaPrimitive, path: String, out: &mut Vec<SchemaDifference>) {
+    if a != b {
+        out.push(SchemaDifference {
+            path,
+            kind: SchemaDifferenceKind::PrimitiveMismatch { memory: a, file: b },
+        });
+    }
+}
+
+fn diff_vector(a: &Schema, b: &Schema, path: String, out: &mut Vec<SchemaDifference>) {
+    diff_schema_into(a, b, path + "/*", out)
 }
 
-fn diff_array(a: &SchemaArray, b: &SchemaArray, path: String) -> Option<String> {
+fn diff_array(a: &SchemaArray, b: &SchemaArray, path: String, out: &mut Vec<SchemaDifference>) {
     if a.count != b.count {
-        return Some(format!(
-            "At location [{}]: In memory array has length {}, but disk format length {}.",
-            path, a.count, b.count
-        ));
+        out.push(SchemaDifference {
+            path,
+            kind: SchemaDifferenceKind::ArrayLengthMismatch {
+                memory: a.count,
+                file: b.count,
+            },
+        });
+        return;
     }
 
-    diff_schema(&a.item_type, &b.item_type, format!("{}/[{}]", path, a.count))
+    diff_schema_into(&a.item_type, &b.item_type, format!("{}/[{}]", path, a.count), out)
 }
 
-fn diff_option(a: &Schema, b: &Schema, path: String) -> Option<String> {
-    diff_schema(a, b, path + "/?")
+fn diff_option(a: &Schema, b: &Schema, path: String, out: &mut Vec<SchemaDifference>) {
+    diff_schema_into(a, b, path + "/?", out)
 }
 
-fn diff_enum(a: &SchemaEnum, b: &SchemaEnum, path: String) -> Option<String> {
-    let path = (path + &b.dbg_name).to_string();
+fn diff_enum(a: &SchemaEnum, b: &SchemaEnum, path: String, out: &mut Vec<SchemaDifference>) {
+    let path = path + &b.dbg_name;
+    if a.discriminant_size != b.discriminant_size {
+        out.push(SchemaDifference {
+            path,
+            kind: SchemaDifferenceKind::DiscriminantSizeMismatch {
+                memory: a.discriminant_size,
+                file: b.discriminant_size,
+            },
+        });
+        return;
+    }
     if a.variants.len() != b.variants.len() {
-        return Some(format!(
-            "At location [{}]: In memory enum has {} variants, but disk format has {} variants.",
+        out.push(SchemaDifference {
             path,
-            a.variants.len(),
-            b.variants.len()
-        ));
+            kind: SchemaDifferenceKind::VariantCountMismatch {
+                memory: a.variants.len(),
+                file: b.variants.len(),
+            },
+        });
+        return;
     }
     for i in 0..a.variants.len() {
         if a.variants[i].name != b.variants[i].name {
-            return Some(format!(
-                "At location [{}]: Enum variant #{} in memory is called {}, but in disk format it is called {}",
-                &path, i, a.variants[i].name, b.variants[i].name
-            ));
+            out.push(SchemaDifference {
+                path: path.clone(),
+                kind: SchemaDifferenceKind::VariantNameMismatch {
+                    index: i,
+                    memory: a.variants[i].name.clone(),
+                    file: b.variants[i].name.clone(),
+                },
+            });
+            continue;
         }
         if a.variants[i].discriminator != b.variants[i].discriminator {
-            return Some(format!(
-                "At location [{}]: Enum variant #{} in memory has discriminator {}, but in disk format it has {}",
-                &path, i, a.variants[i].discriminator, b.variants[i].discriminator
-            ));
+            out.push(SchemaDifference {
+                path: path.clone(),
+                kind: SchemaDifferenceKind::VariantDiscriminatorMismatch {
+                    index: i,
+                    memory: a.variants[i].discriminator,
+                    file: b.variants[i].discriminator,
+                },
+            });
+            continue;
         }
-        let r = diff_fields(
-            &a.variants[i].fields,
-            &b.variants[i].fields,
-            &(path.to_string() + "/" + &b.variants[i].name).to_string(),
-            "enum",
-            "",
-            "",
-        );
-        if let Some(err) = r {
-            return Some(err);
+        let variant_path = path.clone() + "/" + &b.variants[i].name;
+        if a.variants[i].fields.len() != b.variants[i].fields.len() {
+            out.push(SchemaDifference {
+                path: variant_path,
+                kind: SchemaDifferenceKind::VariantFieldCountMismatch {
+                    memory: a.variants[i].fields.len(),
+                    file: b.variants[i].fields.len(),
+                },
+            });
+            continue;
         }
+        diff_fields(&a.variants[i].fields, &b.variants[i].fields, &variant_path, out);
     }
-    None
-}
-fn diff_struct(a: &SchemaStruct, b: &SchemaStruct, path: String) -> Option<String> {
-    diff_fields(
-        &a.fields,
-        &b.fields,
-        &(path + "/" + &b.dbg_name).to_string(),
-        "struct",
-        &(" (struct ".to_string() + &a.dbg_name + ")"),
-        &(" (struct ".to_string() + &b.dbg_name + ")"),
-    )
 }
-fn diff_fields(
-    a: &[Field],
-    b: &[Field],
-    path: &str,
-    structuretype: &str,
-    extra_a: &str,
-    extra_b: &str,
-) -> Option<String> {
-    if a.len() != b.len() {
-        return Some(format!(
-            "At location [{}]: In memory {}{} has {} fields, disk format{} has {} fields.",
+fn diff_struct(a: &SchemaStruct, b: &SchemaStruct, path: String, out: &mut Vec<SchemaDifference>) {
+    let path = path + "/" + &b.dbg_name;
+    if a.fields.len() != b.fields.len() {
+        out.push(SchemaDifference {
             path,
-            structuretype,
-            extra_a,
-            a.len(),
-            extra_b,
-            b.len()
-        ));
+            kind: SchemaDifferenceKind::StructFieldCountMismatch {
+                memory_name: a.dbg_name.clone(),
+                file_name: b.dbg_name.clone(),
+                memory: a.fields.len(),
+                file: b.fields.len(),
+            },
+        });
+        return;
     }
+    diff_fields(&a.fields, &b.fields, &path, out)
+}
+/// Compare fields pairwise. The number of fields must be equal.
+fn diff_fields(a: &[Field], b: &[Field], path: &str, out: &mut Vec<SchemaDifference>) {
     for i in 0..a.len() {
-        let r = diff_schema(
-            &a[i].value,
-            &b[i].value,
-            (path.to_string() + "/" + &b[i].name).to_string(),
-        );
-        if let Some(err) = r {
-            return Some(err);
+        diff_schema_into(&a[i].value, &b[i].value, path.to_string() + "/" + &b[i].name, out);
+    }
+}
+
+/// The subtrees of the schemas in which the first difference between them lies, rendered
+/// with [Schema]'s Display, to be appended to the message of [SavefileError::IncompatibleSchema].
+fn differing_subtrees(memory: &Schema, file: &Schema) -> String {
+    let children: Vec<(&Schema, &Schema)> = match (memory, file) {
+        (Schema::Struct(a), Schema::Struct(b)) if a.fields.len() == b.fields.len() => a
+            .fields
+            .iter()
+            .zip(&b.fields)
+            .map(|(a, b)| (&*a.value, &*b.value))
+            .collect(),
+        (Schema::Enum(a), Schema::Enum(b))
+            if a.discriminant_size == b.discriminant_size
+                && a.variants.len() == b.variants.len()
+                && a.variants.iter().zip(&b.variants).all(|(a, b)| {
+                    a.name == b.name && a.discriminator == b.discriminator && a.fields.len() == b.fields.len()
+                }) =>
+        {
+            a.variants
+                .iter()
+                .zip(&b.variants)
+                .flat_map(|(a, b)| a.fields.iter().zip(&b.fields))
+                .map(|(a, b)| (&*a.value, &*b.value))
+                .collect()
         }
+        (Schema::Vector(a), Schema::Vector(b))
+        | (Schema::SchemaOption(a), Schema::SchemaOption(b))
+        | (Schema::BitPacked(a), Schema::BitPacked(b)) => vec![(&**a, &**b)],
+        (Schema::Array(a), Schema::Array(b)) if a.count == b.count => vec![(&*a.item_type, &*b.item_type)],
+        _ => Vec::new(),
+    };
+    match children
+        .into_iter()
+        .find(|(a, b)| diff_schema(a, b, String::new()).is_some())
+    {
+        Some((a, b)) => differing_subtrees(a, b),
+        None => format!("\nIn memory:\n{}\nIn file:\n{}", memory, file),
     }
-    None
 }
 
 /// Return a (kind of) human-readable description of the difference
@@ -2168,30 +5745,38 @@ fn diff_fields(
 /// schema (used in memory).
 /// Returns None if both schemas are equivalent
 fn diff_schema(a: &Schema, b: &Schema, path: String) -> Option<String> {
+    let mut out = Vec::new();
+    diff_schema_into(a, b, path, &mut out);
+    out.first().map(|difference| difference.to_string())
+}
+
+fn diff_schema_into(a: &Schema, b: &Schema, path: String, out: &mut Vec<SchemaDifference>) {
     let (atype, btype) = match *a {
         Schema::Struct(ref xa) => match *b {
-            Schema::Struct(ref xb) => return diff_struct(xa, xb, path),
+            Schema::Struct(ref xb) => return diff_struct(xa, xb, path, out),
             Schema::Enum(_) => ("struct", "enum"),
             Schema::Primitive(_) => ("struct", "primitive"),
             Schema::Vector(_) => ("struct", "vector"),
             Schema::SchemaOption(_) => ("struct", "option"),
             Schema::Undefined => ("struct", "undefined"),
             Schema::ZeroSize => ("struct", "zerosize"),
+            Schema::BitPacked(_) => ("struct", "packed"),
             Schema::Array(_) => ("struct", "array"),
         },
         Schema::Enum(ref xa) => match *b {
-            Schema::Enum(ref xb) => return diff_enum(xa, xb, path),
+            Schema::Enum(ref xb) => return diff_enum(xa, xb, path, out),
             Schema::Struct(_) => ("enum", "struct"),
             Schema::Primitive(_) => ("enum", "primitive"),
             Schema::Vector(_) => ("enum", "vector"),
             Schema::SchemaOption(_) => ("enum", "option"),
             Schema::Undefined => ("enum", "undefined"),
             Schema::ZeroSize => ("enum", "zerosize"),
+            Schema::BitPacked(_) => ("enum", "packed"),
             Schema::Array(_) => ("enum", "array"),
         },
         Schema::Primitive(ref xa) => match *b {
             Schema::Primitive(ref xb) => {
-                return diff_primitive(*xa, *xb, &path);
+                return diff_primitive(*xa, *xb, path, out);
             }
             Schema::Struct(_) => ("primitive", "struct"),
             Schema::Enum(_) => ("primitive", "enum"),
@@ -2199,11 +5784,12 @@ fn diff_schema(a: &Schema, b: &Schema, path: String) -> Option<String> {
             Schema::SchemaOption(_) => ("primitive", "option"),
             Schema::Undefined => ("primitive", "undefined"),
             Schema::ZeroSize => ("primitive", "zerosize"),
+            Schema::BitPacked(_) => ("primitive", "packed"),
             Schema::Array(_) => ("primitive", "array"),
         },
         Schema::SchemaOption(ref xa) => match *b {
             Schema::SchemaOption(ref xb) => {
-                return diff_option(xa, xb, path);
+                return diff_option(xa, xb, path, out);
             }
             Schema::Struct(_) => ("option", "struct"),
             Schema::Enum(_) => ("option", "enum"),
@@ -2211,11 +5797,12 @@ fn diff_schema(a: &Schema, b: &Schema, path: String) -> Option<String> {
             Schema::Vector(_) => ("option", "vector"),
             Schema::Undefined => ("option", "undefined"),
             Schema::ZeroSize => ("option", "zerosize"),
+            Schema::BitPacked(_) => ("option", "packed"),
             Schema::Array(_) => ("option", "array"),
         },
         Schema::Vector(ref xa) => match *b {
             Schema::Vector(ref xb) => {
-                return diff_vector(xa, xb, path);
+                return diff_vector(xa, xb, path, out);
             }
             Schema::Struct(_) => ("vector", "struct"),
             Schema::Enum(_) => ("vector", "enum"),
@@ -2223,14 +5810,19 @@ fn diff_schema(a: &Schema, b: &Schema, path: String) -> Option<String> {
             Schema::SchemaOption(_) => ("vector", "option"),
             Schema::Undefined => ("vector", "undefined"),
             Schema::ZeroSize => ("vector", "zerosize"),
+            Schema::BitPacked(_) => ("vector", "packed"),
             Schema::Array(_) => ("vector", "array"),
         },
         Schema::Undefined => {
-            return Some(format!("At location [{}]: Undefined schema encountered.", path));
+            out.push(SchemaDifference {
+                path,
+                kind: SchemaDifferenceKind::Undefined,
+            });
+            return;
         }
         Schema::ZeroSize => match *b {
             Schema::ZeroSize => {
-                return None;
+                return;
             }
             Schema::Vector(_) => ("zerosize", "vector"),
             Schema::Struct(_) => ("zerosize", "struct"),
@@ -2238,6 +5830,7 @@ fn diff_schema(a: &Schema, b: &Schema, path: String) -> Option<String> {
             Schema::SchemaOption(_) => ("zerosize", "option"),
             Schema::Primitive(_) => ("zerosize", "primitive"),
             Schema::Undefined => ("zerosize", "undefined"),
+            Schema::BitPacked(_) => ("zerosize", "packed"),
             Schema::Array(_) => ("zerosize", "array"),
         },
         Schema::Array(ref xa) => match *b {
@@ -2248,13 +5841,28 @@ fn diff_schema(a: &Schema, b: &Schema, path: String) -> Option<String> {
             Schema::SchemaOption(_) => ("array", "option"),
             Schema::Undefined => ("array", "undefined"),
             Schema::ZeroSize => ("array", "zerosize"),
-            Schema::Array(ref xb) => return diff_array(xa, xb, path),
+            Schema::BitPacked(_) => ("array", "packed"),
+            Schema::Array(ref xb) => return diff_array(xa, xb, path, out),
+        },
+        Schema::BitPacked(ref xa) => match *b {
+            Schema::BitPacked(ref xb) => return diff_schema_into(xa, xb, path, out),
+            Schema::Vector(_) => ("packed", "vector"),
+            Schema::Struct(_) => ("packed", "struct"),
+            Schema::Enum(_) => ("packed", "enum"),
+            Schema::Primitive(_) => ("packed", "primitive"),
+            Schema::SchemaOption(_) => ("packed", "option"),
+            Schema::Undefined => ("packed", "undefined"),
+            Schema::ZeroSize => ("packed", "zerosize"),
+            Schema::Array(_) => ("packed", "array"),
         },
     };
-    Some(format!(
-        "At location [{}]: In memory schema: {}, file schema: {}",
-        path, atype, btype
-    ))
+    out.push(SchemaDifference {
+        path,
+        kind: SchemaDifferenceKind::KindMismatch {
+            memory: atype,
+            file: btype,
+        },
+    });
 }
 
 impl WithSchema for Field {
@@ -2264,16 +5872,16 @@ impl WithSchema for Field {
 }
 
 impl Serialize for Field {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_string(&self.name)?;
         self.value.serialize(serializer)
     }
 }
 impl Deserialize for Field {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(Field {
             name: deserializer.read_string()?,
-            value: Box::new(Schema::deserialize(deserializer)?),
+            value: Arc::new(Schema::deserialize(deserializer)?),
         })
     }
 }
@@ -2282,30 +5890,36 @@ impl WithSchema for Variant {
         Schema::Undefined
     }
 }
-impl Serialize for Variant {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+impl Variant {
+    /// Serialize the variant of an enum whose discriminator has `discriminant_size` bytes
+    fn serialize_sized(
+        &self,
+        discriminant_size: u8,
+        serializer: &mut Serializer<impl Write>,
+    ) -> Result<(), SavefileError> {
         serializer.write_string(&self.name)?;
-        serializer.write_u8(self.discriminator)?;
-        serializer.write_usize(self.fields.len())?;
+        serializer.write_discriminant(self.discriminator, discriminant_size)?;
+        serializer.write_length(self.fields.len() as u64)?;
         for field in &self.fields {
             field.serialize(serializer)?;
         }
         Ok(())
     }
-}
 
-impl Deserialize for Variant {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize_sized(
+        discriminant_size: u8,
+        deserializer: &mut Deserializer<impl Read>,
+    ) -> Result<Self, SavefileError> {
         Ok(Variant {
             name: deserializer.read_string()?,
-            discriminator: deserializer.read_u8()?,
+            discriminator: deserializer.read_discriminant(discriminant_size)?,
             fields: {
-                let l = deserializer.read_usize()?;
+                let l = deserializer.read_length_usize()?;
                 let mut ret = Vec::new();
                 for _ in 0..l {
                     ret.push(Field {
                         name: deserializer.read_string()?,
-                        value: Box::new(Schema::deserialize(deserializer)?),
+                        value: Arc::new(Schema::deserialize(deserializer)?),
                     });
                 }
                 ret
@@ -2313,17 +5927,27 @@ impl Deserialize for Variant {
         })
     }
 }
+impl Serialize for Variant {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        self.serialize_sized(1, serializer)
+    }
+}
+impl Deserialize for Variant {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        Variant::deserialize_sized(1, deserializer)
+    }
+}
 impl Serialize for SchemaArray {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        serializer.write_usize(self.count)?;
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_length(self.count as u64)?;
         self.item_type.serialize(serializer)?;
         Ok(())
     }
 }
 impl Deserialize for SchemaArray {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        let count = deserializer.read_usize()?;
-        let item_type = Box::new(Schema::deserialize(deserializer)?);
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let count = deserializer.read_length_usize()?;
+        let item_type = Arc::new(Schema::deserialize(deserializer)?);
         Ok(SchemaArray { count, item_type })
     }
 }
@@ -2339,9 +5963,9 @@ impl WithSchema for SchemaStruct {
     }
 }
 impl Serialize for SchemaStruct {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_string(&self.dbg_name)?;
-        serializer.write_usize(self.fields.len())?;
+        serializer.write_length(self.fields.len() as u64)?;
         for field in &self.fields {
             field.serialize(serializer)?;
         }
@@ -2349,9 +5973,9 @@ impl Serialize for SchemaStruct {
     }
 }
 impl Deserialize for SchemaStruct {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let dbg_name = deserializer.read_string()?;
-        let l = deserializer.read_usize()?;
+        let l = deserializer.read_length_usize()?;
         Ok(SchemaStruct {
             dbg_name,
             fields: {
@@ -2371,27 +5995,12 @@ impl WithSchema for SchemaPrimitive {
     }
 }
 impl Serialize for SchemaPrimitive {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        let discr = match *self {
-            SchemaPrimitive::schema_i8 => 1,
-            SchemaPrimitive::schema_u8 => 2,
-            SchemaPrimitive::schema_i16 => 3,
-            SchemaPrimitive::schema_u16 => 4,
-            SchemaPrimitive::schema_i32 => 5,
-            SchemaPrimitive::schema_u32 => 6,
-            SchemaPrimitive::schema_i64 => 7,
-            SchemaPrimitive::schema_u64 => 8,
-            SchemaPrimitive::schema_string => 9,
-            SchemaPrimitive::schema_f32 => 10,
-            SchemaPrimitive::schema_f64 => 11,
-            SchemaPrimitive::schema_bool => 12,
-            SchemaPrimitive::schema_canary1 => 13,
-        };
-        serializer.write_u8(discr)
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_u8(self.to_u8())
     }
 }
 impl Deserialize for SchemaPrimitive {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let var = match deserializer.read_u8()? {
             1 => SchemaPrimitive::schema_i8,
             2 => SchemaPrimitive::schema_u8,
@@ -2422,30 +6031,42 @@ impl WithSchema for SchemaEnum {
     }
 }
 
+/// The size of the discriminator of enums is only written for enums with wider discriminators
+/// than a u8, whose schemas have their own discriminator in [Schema::serialize]. This keeps
+/// other schemas readable by older versions of savefile.
 impl Serialize for SchemaEnum {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_string(&self.dbg_name)?;
-        serializer.write_usize(self.variants.len())?;
+        serializer.write_length(self.variants.len() as u64)?;
         for var in &self.variants {
-            var.serialize(serializer)?;
+            var.serialize_sized(self.discriminant_size, serializer)?;
         }
         Ok(())
     }
 }
-impl Deserialize for SchemaEnum {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+impl SchemaEnum {
+    fn deserialize_sized(
+        discriminant_size: u8,
+        deserializer: &mut Deserializer<impl Read>,
+    ) -> Result<Self, SavefileError> {
         let dbg_name = deserializer.read_string()?;
-        let l = deserializer.read_usize()?;
+        let l = deserializer.read_length_usize()?;
         let mut ret = Vec::new();
         for _ in 0..l {
-            ret.push(Variant::deserialize(deserializer)?);
+            ret.push(Variant::deserialize_sized(discriminant_size, deserializer)?);
         }
         Ok(SchemaEnum {
             dbg_name,
             variants: ret,
+            discriminant_size,
         })
     }
 }
+impl Deserialize for SchemaEnum {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        SchemaEnum::deserialize_sized(1, deserializer)
+    }
+}
 
 impl WithSchema for Schema {
     fn schema(_version: u32) -> Schema {
@@ -2453,16 +6074,21 @@ impl WithSchema for Schema {
     }
 }
 impl Serialize for Schema {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         match *self {
             Schema::Struct(ref schema_struct) => {
                 serializer.write_u8(1)?;
                 schema_struct.serialize(serializer)
             }
-            Schema::Enum(ref schema_enum) => {
+            Schema::Enum(ref schema_enum) if schema_enum.discriminant_size == 1 => {
                 serializer.write_u8(2)?;
                 schema_enum.serialize(serializer)
             }
+            Schema::Enum(ref schema_enum) => {
+                serializer.write_u8(10)?;
+                serializer.write_u8(schema_enum.discriminant_size)?;
+                schema_enum.serialize(serializer)
+            }
             Schema::Primitive(ref schema_prim) => {
                 serializer.write_u8(3)?;
                 schema_prim.serialize(serializer)
@@ -2481,21 +6107,38 @@ impl Serialize for Schema {
                 serializer.write_u8(8)?;
                 array.serialize(serializer)
             }
+            Schema::BitPacked(ref content) => {
+                serializer.write_u8(9)?;
+                content.serialize(serializer)
+            }
         }
     }
 }
 
 impl Deserialize for Schema {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let schema = match deserializer.read_u8()? {
             1 => Schema::Struct(SchemaStruct::deserialize(deserializer)?),
             2 => Schema::Enum(SchemaEnum::deserialize(deserializer)?),
             3 => Schema::Primitive(SchemaPrimitive::deserialize(deserializer)?),
-            4 => Schema::Vector(Box::new(Schema::deserialize(deserializer)?)),
+            4 => Schema::Vector(Arc::new(Schema::deserialize(deserializer)?)),
             5 => Schema::Undefined,
             6 => Schema::ZeroSize,
-            7 => Schema::SchemaOption(Box::new(Schema::deserialize(deserializer)?)),
+            7 => Schema::SchemaOption(Arc::new(Schema::deserialize(deserializer)?)),
             8 => Schema::Array(SchemaArray::deserialize(deserializer)?),
+            9 => Schema::BitPacked(Arc::new(Schema::deserialize(deserializer)?)),
+            10 => {
+                let discriminant_size = deserializer.read_u8()?;
+                if discriminant_size != 2 && discriminant_size != 4 {
+                    return Err(SavefileError::GeneralError {
+                        msg: format!(
+                            "Corrupt schema, discriminator of {} bytes encountered",
+                            discriminant_size
+                        ),
+                    });
+                }
+                Schema::Enum(SchemaEnum::deserialize_sized(discriminant_size, deserializer)?)
+            }
             c => {
                 return Err(SavefileError::GeneralError {
                     msg: format!("Corrupt schema, schema variant {} encountered", c),
@@ -2518,20 +6161,24 @@ impl Introspect for String {
         self.to_string()
     }
 
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_>>> {
         None
     }
 }
 impl Serialize for String {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_string(self)
     }
 }
 
 impl Deserialize for String {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<String, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<String, SavefileError> {
         deserializer.read_string()
     }
+
+    fn deserialize_in_place(&mut self, deserializer: &mut Deserializer<impl Read>) -> Result<(), SavefileError> {
+        deserializer.read_string_into(self)
+    }
 }
 
 /// Type of single child of introspector for Mutex
@@ -2554,7 +6201,7 @@ impl<T: Introspect> Introspect for Mutex<T> {
         format!("Mutex<{}>", std::any::type_name::<T>())
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if index == 0 {
             Some(Box::new(IntrospectItemMutex { g: self.lock() }))
         } else {
@@ -2583,7 +6230,7 @@ impl<T: Introspect> Introspect for std::sync::Mutex<T> {
         format!("Mutex<{}>", std::any::type_name::<T>())
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         match self.lock() {
             Ok(item) => {
                 if index == 0 {
@@ -2604,14 +6251,14 @@ impl<T: WithSchema> WithSchema for std::sync::Mutex<T> {
 }
 
 impl<T: Serialize> Serialize for std::sync::Mutex<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         let data = self.lock()?;
         data.serialize(serializer)
     }
 }
 
 impl<T: Deserialize> Deserialize for std::sync::Mutex<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<std::sync::Mutex<T>, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<std::sync::Mutex<T>, SavefileError> {
         Ok(std::sync::Mutex::new(T::deserialize(deserializer)?))
     }
 }
@@ -2623,14 +6270,14 @@ impl<T: WithSchema> WithSchema for Mutex<T> {
 }
 
 impl<T: Serialize> Serialize for Mutex<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         let data = self.lock();
         data.serialize(serializer)
     }
 }
 
 impl<T: Deserialize> Deserialize for Mutex<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Mutex<T>, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Mutex<T>, SavefileError> {
         Ok(Mutex::new(T::deserialize(deserializer)?))
     }
 }
@@ -2658,7 +6305,7 @@ impl<T: Introspect> Introspect for RefCell<T> {
         )
     }
 
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         // Introspect not supported
         None
     }
@@ -2674,7 +6321,7 @@ impl<T: Introspect> Introspect for Rc<T> {
         format!("Rc({})", self.deref().introspect_value())
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         self.deref().introspect_child(index)
     }
 
@@ -2688,7 +6335,7 @@ impl<T: Introspect> Introspect for Arc<T> {
         format!("Arc({})", self.deref().introspect_value())
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         self.deref().introspect_child(index)
     }
 
@@ -2700,7 +6347,7 @@ impl<T: Introspect> Introspect for RwLock<T> {
     fn introspect_value(&self) -> String {
         format!("RwLock<{}>", std::any::type_name::<T>())
     }
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if index == 0 {
             Some(Box::new(IntrospectItemRwLock { g: self.read() }))
         } else {
@@ -2720,14 +6367,14 @@ impl<T: WithSchema> WithSchema for RwLock<T> {
 }
 
 impl<T: Serialize> Serialize for RwLock<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         let data = self.read();
         data.serialize(serializer)
     }
 }
 
 impl<T: Deserialize> Deserialize for RwLock<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<RwLock<T>, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<RwLock<T>, SavefileError> {
         Ok(RwLock::new(T::deserialize(deserializer)?))
     }
 }
@@ -2755,37 +6402,12 @@ pub fn introspect_item<'a>(key: String, val: &'a dyn Introspect) -> Box<dyn Intr
 
 
 
-#[cfg(not(feature = "nightly"))]
 impl<K: Introspect + Eq + Hash, V: Introspect, S: ::std::hash::BuildHasher> Introspect for HashMap<K, V, S> {
     fn introspect_value(&self) -> String {
         format!("HashMap<{},{}>", std::any::type_name::<K>(), std::any::type_name::<V>())
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
-        let bucket = index / 2;
-        let off = index % 2;
-        if let Some((key, val)) = self.iter().skip(bucket).next() {
-            if off == 0 {
-                Some(introspect_item(format!("Key #{}", index), key))
-            } else {
-                Some(introspect_item(format!("Value #{}", index), val))
-            }
-        } else {
-            None
-        }
-    }
-    fn introspect_len(&self) -> usize {
-        self.len()
-    }
-}
-
-#[cfg(feature = "nightly")]
-impl<K: Introspect + Eq + Hash, V: Introspect, S: ::std::hash::BuildHasher> Introspect for HashMap<K, V, S> {
-    default fn introspect_value(&self) -> String {
-        format!("HashMap<{},{}>", std::any::type_name::<K>(), std::any::type_name::<V>())
-    }
-
-    default fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         let bucket = index / 2;
         let off = index % 2;
         if let Some((key, val)) = self.iter().skip(bucket).next() {
@@ -2798,27 +6420,6 @@ impl<K: Introspect + Eq + Hash, V: Introspect, S: ::std::hash::BuildHasher> Intr
             None
         }
     }
-    default fn introspect_len(&self) -> usize {
-        self.len()
-    }
-}
-
-#[cfg(feature = "nightly")]
-impl<K: Introspect + Eq + Hash, V: Introspect, S: ::std::hash::BuildHasher> Introspect for HashMap<K, V, S>
-where
-    K: ToString,
-{
-    fn introspect_value(&self) -> String {
-        format!("HashMap<{},{}>", std::any::type_name::<K>(), std::any::type_name::<V>())
-    }
-
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
-        if let Some((key, val)) = self.iter().skip(index).next() {
-            Some(introspect_item(key.to_string(), val))
-        } else {
-            None
-        }
-    }
     fn introspect_len(&self) -> usize {
         self.len()
     }
@@ -2829,7 +6430,7 @@ impl<K: Introspect + Eq + Hash, S: ::std::hash::BuildHasher> Introspect for Hash
         format!("HashSet<{}>", std::any::type_name::<K>())
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if let Some(key) = self.iter().skip(index).next() {
             Some(introspect_item(format!("#{}", index), key))
         } else {
@@ -2848,7 +6449,7 @@ impl<K: Introspect, V: Introspect> Introspect for BTreeMap<K, V> {
 
     // This has very bad performance. But with the model behind Savefile Introspect it
     // is presently hard to do much better
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         let bucket = index / 2;
         let off = index % 2;
         if let Some((key, val)) = self.iter().skip(bucket).next() {
@@ -2867,53 +6468,126 @@ impl<K: Introspect, V: Introspect> Introspect for BTreeMap<K, V> {
 }
 impl<K: WithSchema, V: WithSchema> WithSchema for BTreeMap<K, V> {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(Schema::Struct(SchemaStruct {
+        Schema::Vector(Arc::new(Schema::Struct(SchemaStruct {
             dbg_name: "KeyValuePair".to_string(),
             fields: vec![
                 Field {
                     name: "key".to_string(),
-                    value: Box::new(K::schema(version)),
+                    value: schema_of::<K>(version),
                 },
                 Field {
                     name: "value".to_string(),
-                    value: Box::new(V::schema(version)),
+                    value: schema_of::<V>(version),
                 },
             ],
         })))
     }
 }
-impl<K: Serialize, V: Serialize> Serialize for BTreeMap<K, V> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        self.len().serialize(serializer)?;
-        for (k, v) in self {
+/// Serialize the keys and values of a map. If they are [ReprC], they are copied to a
+/// buffer, which is written in large chunks.
+fn serialize_pairs<'a, K: Serialize + 'a, V: Serialize + 'a>(
+    pairs: impl Iterator<Item = (&'a K, &'a V)>,
+    serializer: &mut Serializer<impl Write>,
+) -> Result<(), SavefileError> {
+    if !(serializer.uses_raw_copy::<K>() && serializer.uses_raw_copy::<V>()) {
+        for (k, v) in pairs {
             k.serialize(serializer)?;
             v.serialize(serializer)?;
         }
-        Ok(())
+        return Ok(());
+    }
+    let mut buffer = Vec::with_capacity(VEC_CHUNK_BYTES);
+    for (k, v) in pairs {
+        // Safety: K and V are ReprC, so they contain no padding, and all their bytes are initialized
+        unsafe {
+            buffer.extend_from_slice(std::slice::from_raw_parts(
+                k as *const K as *const u8,
+                std::mem::size_of::<K>(),
+            ));
+            buffer.extend_from_slice(std::slice::from_raw_parts(
+                v as *const V as *const u8,
+                std::mem::size_of::<V>(),
+            ));
+        }
+        if buffer.len() >= VEC_CHUNK_BYTES {
+            serializer.write_buf(&buffer)?;
+            buffer.clear();
+        }
+    }
+    serializer.write_buf(&buffer)
+}
+
+/// Deserialize `count` keys and values of a map, serialized by [serialize_pairs], and give
+/// them to `insert`. If they are [ReprC], they are read in large chunks and copied out.
+fn deserialize_pairs<K: Deserialize, V: Deserialize>(
+    count: usize,
+    deserializer: &mut Deserializer<impl Read>,
+    mut insert: impl FnMut(K, V),
+) -> Result<(), SavefileError> {
+    if !(deserializer.uses_raw_copy::<K>() && deserializer.uses_raw_copy::<V>()) {
+        for _ in 0..count {
+            insert(K::deserialize(deserializer)?, V::deserialize(deserializer)?);
+        }
+        return Ok(());
+    }
+    let pair_size = std::mem::size_of::<K>() + std::mem::size_of::<V>();
+    let chunk_size = (VEC_CHUNK_BYTES / pair_size.max(1)).max(1);
+    let key_layout = K::repr_c_layout(deserializer.file_version);
+    let value_layout = V::repr_c_layout(deserializer.file_version);
+    let mut buffer = Vec::new();
+    let mut remaining = count;
+    while remaining > 0 {
+        let chunk = remaining.min(chunk_size);
+        buffer.resize(chunk * pair_size, 0);
+        deserializer.reader.read_exact(&mut buffer)?;
+        if key_layout.validator().is_some() || value_layout.validator().is_some() {
+            for pair in buffer.chunks_exact(pair_size.max(1)) {
+                let (key, value) = pair.split_at(std::mem::size_of::<K>());
+                key_layout.validate(key)?;
+                value_layout.validate(value)?;
+            }
+        }
+        for pair in 0..chunk {
+            // Safety: K and V are ReprC, and the bytes have been validated as values of them
+            unsafe {
+                let ptr = buffer.as_ptr().add(pair * pair_size);
+                insert(
+                    std::ptr::read_unaligned(ptr as *const K),
+                    std::ptr::read_unaligned(ptr.add(std::mem::size_of::<K>()) as *const V),
+                );
+            }
+        }
+        remaining -= chunk;
+    }
+    Ok(())
+}
+
+impl<K: Serialize, V: Serialize> Serialize for BTreeMap<K, V> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        self.len().serialize(serializer)?;
+        serialize_pairs(self.iter(), serializer)
     }
 }
 impl<K: Deserialize + Ord, V: Deserialize> Deserialize for BTreeMap<K, V> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let mut ret = BTreeMap::new();
         let count = <usize as Deserialize>::deserialize(deserializer)?;
-        for _ in 0..count {
-            ret.insert(
-                <_ as Deserialize>::deserialize(deserializer)?,
-                <_ as Deserialize>::deserialize(deserializer)?,
-            );
-        }
+        deserializer.account_items::<(K, V)>(count)?;
+        deserialize_pairs(count, deserializer, |k, v| {
+            ret.insert(k, v);
+        })?;
         Ok(ret)
     }
 }
 
 impl<K:WithSchema> WithSchema for HashSet<K> {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(K::schema(version)))
+        Schema::Vector(schema_of::<K>(version))
     }
 }
 impl<K:Serialize> Serialize for HashSet<K> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        serializer.write_usize(self.len())?;
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_length(self.len() as u64)?;
         for item in self {
             item.serialize(serializer)?;
         }
@@ -2921,9 +6595,10 @@ impl<K:Serialize> Serialize for HashSet<K> {
     }
 }
 impl<K:Deserialize+Eq+Hash> Deserialize for HashSet<K> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        let cnt = deserializer.read_usize()?;
-        let mut ret = HashSet::with_capacity(cnt);
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let cnt = deserializer.read_length_usize()?;
+        deserializer.account_items::<K>(cnt)?;
+        let mut ret = HashSet::with_capacity(cnt.min(MAX_PREALLOCATED_ITEMS));
         for _ in 0..cnt {
             ret.insert(<_ as Deserialize>::deserialize(deserializer)?);
         }
@@ -2933,16 +6608,16 @@ impl<K:Deserialize+Eq+Hash> Deserialize for HashSet<K> {
 
 impl<K: WithSchema + Eq + Hash, V: WithSchema, S: ::std::hash::BuildHasher> WithSchema for HashMap<K, V, S> {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(Schema::Struct(SchemaStruct {
+        Schema::Vector(Arc::new(Schema::Struct(SchemaStruct {
             dbg_name: "KeyValuePair".to_string(),
             fields: vec![
                 Field {
                     name: "key".to_string(),
-                    value: Box::new(K::schema(version)),
+                    value: schema_of::<K>(version),
                 },
                 Field {
                     name: "value".to_string(),
-                    value: Box::new(V::schema(version)),
+                    value: schema_of::<V>(version),
                 },
             ],
         })))
@@ -2950,46 +6625,52 @@ impl<K: WithSchema + Eq + Hash, V: WithSchema, S: ::std::hash::BuildHasher> With
 }
 
 impl<K: Serialize + Eq + Hash, V: Serialize, S: ::std::hash::BuildHasher> Serialize for HashMap<K, V, S> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        serializer.write_usize(self.len())?;
-        for (k, v) in self.iter() {
-            k.serialize(serializer)?;
-            v.serialize(serializer)?;
-        }
-        Ok(())
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_length(self.len() as u64)?;
+        serialize_pairs(self.iter(), serializer)
     }
 }
 
 impl<K: Deserialize + Eq + Hash, V: Deserialize> Deserialize for HashMap<K, V> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        let l = deserializer.read_usize()?;
-        let mut ret = HashMap::with_capacity(l);
-        for _ in 0..l {
-            ret.insert(K::deserialize(deserializer)?, V::deserialize(deserializer)?);
-        }
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let l = deserializer.read_length_usize()?;
+        deserializer.account_items::<(K, V)>(l)?;
+        let mut ret = HashMap::with_capacity(l.min(MAX_PREALLOCATED_ITEMS));
+        deserialize_pairs(l, deserializer, |k, v| {
+            ret.insert(k, v);
+        })?;
         Ok(ret)
     }
+
+    fn deserialize_in_place(&mut self, deserializer: &mut Deserializer<impl Read>) -> Result<(), SavefileError> {
+        let l = deserializer.read_length_usize()?;
+        deserializer.account_items::<(K, V)>(l)?;
+        self.clear();
+        self.reserve(l.min(MAX_PREALLOCATED_ITEMS));
+        deserialize_pairs(l, deserializer, |k, v| {
+            self.insert(k, v);
+        })
+    }
 }
 
 impl<K: WithSchema + Eq + Hash, V: WithSchema, S: ::std::hash::BuildHasher> WithSchema for IndexMap<K, V, S> {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(Schema::Struct(SchemaStruct {
+        Schema::Vector(Arc::new(Schema::Struct(SchemaStruct {
             dbg_name: "KeyValuePair".to_string(),
             fields: vec![
                 Field {
                     name: "key".to_string(),
-                    value: Box::new(K::schema(version)),
+                    value: schema_of::<K>(version),
                 },
                 Field {
                     name: "value".to_string(),
-                    value: Box::new(V::schema(version)),
+                    value: schema_of::<V>(version),
                 },
             ],
         })))
     }
 }
 
-#[cfg(not(feature = "nightly"))]
 impl<K: Introspect + Eq + Hash, V: Introspect, S: ::std::hash::BuildHasher> Introspect for IndexMap<K, V, S> {
     fn introspect_value(&self) -> String {
         format!(
@@ -2999,7 +6680,7 @@ impl<K: Introspect + Eq + Hash, V: Introspect, S: ::std::hash::BuildHasher> Intr
         )
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         let bucket = index / 2;
         let off = index % 2;
         if let Some((k, v)) = self.get_index(bucket) {
@@ -3018,78 +6699,21 @@ impl<K: Introspect + Eq + Hash, V: Introspect, S: ::std::hash::BuildHasher> Intr
     }
 }
 
-#[cfg(feature = "nightly")]
-impl<K: Introspect + Eq + Hash, V: Introspect, S: ::std::hash::BuildHasher> Introspect for IndexMap<K, V, S> {
-    default fn introspect_value(&self) -> String {
-        format!(
-            "IndexMap<{},{}>",
-            std::any::type_name::<K>(),
-            std::any::type_name::<V>()
-        )
-    }
-
-    default fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
-        let bucket = index / 2;
-        let off = index % 2;
-        if let Some((k, v)) = self.get_index(bucket) {
-            if off == 0 {
-                Some(introspect_item(format!("Key #{}", bucket), k))
-            } else {
-                Some(introspect_item(format!("Value #{}", bucket), v))
-            }
-        } else {
-            None
-        }
-    }
-
-    default fn introspect_len(&self) -> usize {
-        self.len()
-    }
-}
-
-#[cfg(feature = "nightly")]
-impl<K: Introspect + Eq + Hash, V: Introspect, S: ::std::hash::BuildHasher> Introspect for IndexMap<K, V, S>
-where
-    K: ToString,
-{
-    fn introspect_value(&self) -> String {
-        format!(
-            "IndexMap<{},{}>",
-            std::any::type_name::<K>(),
-            std::any::type_name::<V>()
-        )
-    }
-
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
-        if let Some((k, v)) = self.get_index(index) {
-            Some(introspect_item(k.to_string(), v))
-        } else {
-            None
-        }
-    }
-
-    fn introspect_len(&self) -> usize {
-        self.len()
-    }
-}
 impl<K: Serialize + Eq + Hash, V: Serialize, S: ::std::hash::BuildHasher> Serialize for IndexMap<K, V, S> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        serializer.write_usize(self.len())?;
-        for (k, v) in self.iter() {
-            k.serialize(serializer)?;
-            v.serialize(serializer)?;
-        }
-        Ok(())
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_length(self.len() as u64)?;
+        serialize_pairs(self.iter(), serializer)
     }
 }
 
 impl<K: Deserialize + Eq + Hash, V: Deserialize> Deserialize for IndexMap<K, V> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        let l = deserializer.read_usize()?;
-        let mut ret = IndexMap::with_capacity(l);
-        for _ in 0..l {
-            ret.insert(K::deserialize(deserializer)?, V::deserialize(deserializer)?);
-        }
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let l = deserializer.read_length_usize()?;
+        deserializer.account_items::<(K, V)>(l)?;
+        let mut ret = IndexMap::with_capacity(l.min(MAX_PREALLOCATED_ITEMS));
+        deserialize_pairs(l, deserializer, |k, v| {
+            ret.insert(k, v);
+        })?;
         Ok(ret)
     }
 }
@@ -3099,7 +6723,7 @@ impl<K: Introspect + Eq + Hash, S: ::std::hash::BuildHasher> Introspect for Inde
         format!("IndexSet<{}>", std::any::type_name::<K>())
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if let Some(val) = self.get_index(index) {
             Some(introspect_item(format!("#{}", index), val))
         } else {
@@ -3114,19 +6738,19 @@ impl<K: Introspect + Eq + Hash, S: ::std::hash::BuildHasher> Introspect for Inde
 
 impl<K: WithSchema + Eq + Hash, S: ::std::hash::BuildHasher> WithSchema for IndexSet<K, S> {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(Schema::Struct(SchemaStruct {
+        Schema::Vector(Arc::new(Schema::Struct(SchemaStruct {
             dbg_name: "Key".to_string(),
             fields: vec![Field {
                 name: "key".to_string(),
-                value: Box::new(K::schema(version)),
+                value: schema_of::<K>(version),
             }],
         })))
     }
 }
 
 impl<K: Serialize + Eq + Hash, S: ::std::hash::BuildHasher> Serialize for IndexSet<K, S> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        serializer.write_usize(self.len())?;
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_length(self.len() as u64)?;
         for k in self.iter() {
             k.serialize(serializer)?;
         }
@@ -3135,9 +6759,10 @@ impl<K: Serialize + Eq + Hash, S: ::std::hash::BuildHasher> Serialize for IndexS
 }
 
 impl<K: Deserialize + Eq + Hash> Deserialize for IndexSet<K> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        let l = deserializer.read_usize()?;
-        let mut ret = IndexSet::with_capacity(l);
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let l = deserializer.read_length_usize()?;
+        deserializer.account_items::<K>(l)?;
+        let mut ret = IndexSet::with_capacity(l.min(MAX_PREALLOCATED_ITEMS));
         for _ in 0..l {
             ret.insert(K::deserialize(deserializer)?);
         }
@@ -3170,17 +6795,17 @@ impl<T: Introspect> Introspect for Removed<T> {
         format!("Removed<{}>", std::any::type_name::<T>())
     }
 
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
 impl<T: WithSchema> Serialize for Removed<T> {
-    fn serialize(&self, _serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, _serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         panic!("Something is wrong with version-specification of fields - there was an attempt to actually serialize a removed field!");
     }
 }
 impl<T: WithSchema + Deserialize> Deserialize for Removed<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         T::deserialize(deserializer)?;
         Ok(Removed {
             phantom: std::marker::PhantomData,
@@ -3188,12 +6813,105 @@ impl<T: WithSchema + Deserialize> Deserialize for Removed<T> {
     }
 }
 
+/// Like [Removed], except the value is skipped using only its schema, instead of being
+/// deserialized. S only needs to implement [WithSchema], so the old type, and everything it uses,
+/// can be deleted. S is typically a unit struct whose schema is the schema the old type had:
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate savefile_derive;
+/// # extern crate savefile;
+/// use savefile::prelude::*;
+///
+/// /// Stands in for the deleted `Stats` struct, which had two u32 fields
+/// struct OldStats;
+///
+/// impl WithSchema for OldStats {
+///     fn schema(version: u32) -> Schema {
+///         Schema::Struct(SchemaStruct {
+///             dbg_name: "Stats".into(),
+///             fields: vec![
+///                 Field { name: "hp".into(), value: schema_of::<u32>(version) },
+///                 Field { name: "mana".into(), value: schema_of::<u32>(version) },
+///             ],
+///         })
+///     }
+/// }
+///
+/// #[derive(Savefile)]
+/// struct Player {
+///     name: String,
+///     #[savefile_versions = "..0"]
+///     stats: RemovedOpaque<OldStats>,
+/// }
+/// # fn main() {}
+/// ```
+///
+/// The schema of a struct can be printed with `{:?}`, by an older release which still has it.
+pub struct RemovedOpaque<S> {
+    phantom: std::marker::PhantomData<fn() -> S>,
+}
+
+impl<S> RemovedOpaque<S> {
+    /// Helper to create an instance of RemovedOpaque<S>. RemovedOpaque<S> has no data.
+    pub fn new() -> RemovedOpaque<S> {
+        RemovedOpaque {
+            phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<S> Default for RemovedOpaque<S> {
+    fn default() -> RemovedOpaque<S> {
+        RemovedOpaque::new()
+    }
+}
+
+impl<S> std::fmt::Debug for RemovedOpaque<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RemovedOpaque<{}>", std::any::type_name::<S>())
+    }
+}
+
+impl<S> PartialEq for RemovedOpaque<S> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<S: WithSchema> WithSchema for RemovedOpaque<S> {
+    fn schema(version: u32) -> Schema {
+        <S>::schema(version)
+    }
+}
+
+impl<S> Introspect for RemovedOpaque<S> {
+    fn introspect_value(&self) -> String {
+        format!("RemovedOpaque<{}>", std::any::type_name::<S>())
+    }
+
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        None
+    }
+}
+impl<S: WithSchema> Serialize for RemovedOpaque<S> {
+    fn serialize(&self, _serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        panic!("Something is wrong with version-specification of fields - there was an attempt to actually serialize a removed field!");
+    }
+}
+impl<S: WithSchema> Deserialize for RemovedOpaque<S> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        deserializer.skip_value(&S::schema(deserializer.file_version))?;
+        Ok(RemovedOpaque::new())
+    }
+}
+
 impl<T> Introspect for PhantomData<T> {
     fn introspect_value(&self) -> String {
         "PhantomData".to_string()
     }
 
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -3204,12 +6922,12 @@ impl<T> WithSchema for std::marker::PhantomData<T> {
 }
 
 impl<T> Serialize for std::marker::PhantomData<T> {
-    fn serialize(&self, _serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, _serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         Ok(())
     }
 }
 impl<T> Deserialize for std::marker::PhantomData<T> {
-    fn deserialize(_deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(_deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(std::marker::PhantomData)
     }
 }
@@ -3218,7 +6936,7 @@ impl<T: Introspect> Introspect for Box<T> {
     fn introspect_value(&self) -> String {
         self.deref().introspect_value()
     }
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         self.deref().introspect_child(index)
     }
     fn introspect_len(&self) -> usize {
@@ -3234,7 +6952,7 @@ impl<T: Introspect> Introspect for Option<T> {
         }
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if let Some(cont) = self {
             cont.introspect_child(index)
         } else {
@@ -3252,12 +6970,12 @@ impl<T: Introspect> Introspect for Option<T> {
 
 impl<T: WithSchema> WithSchema for Option<T> {
     fn schema(version: u32) -> Schema {
-        Schema::SchemaOption(Box::new(T::schema(version)))
+        Schema::SchemaOption(schema_of::<T>(version))
     }
 }
 
 impl<T: Serialize> Serialize for Option<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         match self {
             &Some(ref x) => {
                 serializer.write_bool(true)?;
@@ -3268,7 +6986,7 @@ impl<T: Serialize> Serialize for Option<T> {
     }
 }
 impl<T: Deserialize> Deserialize for Option<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let issome = deserializer.read_bool()?;
         if issome {
             Ok(Some(T::deserialize(deserializer)?))
@@ -3285,15 +7003,15 @@ impl WithSchema for bit_vec::BitVec {
             fields: vec![
                 Field {
                     name: "num_bits".to_string(),
-                    value: Box::new(usize::schema(version)),
+                    value: schema_of::<usize>(version),
                 },
                 Field {
                     name: "num_bytes".to_string(),
-                    value: Box::new(usize::schema(version)),
+                    value: schema_of::<usize>(version),
                 },
                 Field {
                     name: "buffer".to_string(),
-                    value: Box::new(Schema::Vector(Box::new(u8::schema(version)))),
+                    value: Arc::new(Schema::Vector(schema_of::<u8>(version))),
                 },
             ],
         })
@@ -3301,11 +7019,11 @@ impl WithSchema for bit_vec::BitVec {
 }
 
 impl Serialize for bit_vec::BitVec {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         let l = self.len();
-        serializer.write_usize(l)?;
+        serializer.write_length(l as u64)?;
         let bytes = self.to_bytes();
-        serializer.write_usize(bytes.len())?;
+        serializer.write_length(bytes.len() as u64)?;
         serializer.write_bytes(&bytes)?;
         Ok(())
     }
@@ -3323,14 +7041,14 @@ impl Introspect for bit_vec::BitVec {
         ret
     }
 
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
 impl Deserialize for bit_vec::BitVec {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        let numbits = deserializer.read_usize()?;
-        let numbytes = deserializer.read_usize()?;
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let numbits = deserializer.read_length_usize()?;
+        let numbytes = deserializer.read_length_usize()?;
         let bytes = deserializer.read_bytes(numbytes)?;
         let mut ret = bit_vec::BitVec::from_bytes(&bytes);
         ret.truncate(numbits);
@@ -3360,13 +7078,13 @@ impl<T: Introspect> Introspect for BinaryHeap<T> {
 
 impl<T: WithSchema> WithSchema for BinaryHeap<T> {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(T::schema(version)))
+        Schema::Vector(schema_of::<T>(version))
     }
 }
 impl<T: Serialize + Ord> Serialize for BinaryHeap<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         let l = self.len();
-        serializer.write_usize(l)?;
+        serializer.write_length(l as u64)?;
         for item in self.iter() {
             item.serialize(serializer)?
         }
@@ -3374,9 +7092,10 @@ impl<T: Serialize + Ord> Serialize for BinaryHeap<T> {
     }
 }
 impl<T: Deserialize + Ord> Deserialize for BinaryHeap<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        let l = deserializer.read_usize()?;
-        let mut ret = BinaryHeap::with_capacity(l);
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let l = deserializer.read_length_usize()?;
+        deserializer.account_items::<T>(l)?;
+        let mut ret = BinaryHeap::with_capacity(l.min(MAX_PREALLOCATED_ITEMS));
         for _ in 0..l {
             ret.push(T::deserialize(deserializer)?);
         }
@@ -3392,7 +7111,7 @@ where
         format!("SmallVec<{}>", std::any::type_name::<T>())
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if let Some(val) = self.get(index) {
             Some(introspect_item(index.to_string(), val))
         } else {
@@ -3410,7 +7129,7 @@ where
     T::Item: WithSchema,
 {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(T::Item::schema(version)))
+        Schema::Vector(schema_of::<T::Item>(version))
     }
 }
 
@@ -3418,9 +7137,9 @@ impl<T: smallvec::Array> Serialize for smallvec::SmallVec<T>
 where
     T::Item: Serialize,
 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         let l = self.len();
-        serializer.write_usize(l)?;
+        serializer.write_length(l as u64)?;
         for item in self.iter() {
             item.serialize(serializer)?
         }
@@ -3431,9 +7150,10 @@ impl<T: smallvec::Array> Deserialize for smallvec::SmallVec<T>
 where
     T::Item: Deserialize,
 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        let l = deserializer.read_usize()?;
-        let mut ret = Self::with_capacity(l);
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let l = deserializer.read_length_usize()?;
+        deserializer.account_items::<T::Item>(l)?;
+        let mut ret = Self::with_capacity(l.min(MAX_PREALLOCATED_ITEMS));
         for _ in 0..l {
             ret.push(T::Item::deserialize(deserializer)?);
         }
@@ -3441,18 +7161,18 @@ where
     }
 }
 
-fn regular_serialize_vec<T: Serialize>(item: &[T], serializer: &mut Serializer) -> Result<(), SavefileError> {
+fn regular_serialize_vec<T: Serialize>(item: &[T], serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
     let l = item.len();
-    serializer.write_usize(l)?;
+    serializer.write_length(l as u64)?;
     for item in item.iter() {
-        item.serialize(serializer)?
+        item.serialize(serializer).map_err(|err| err.with_validation_path_segment("*"))?
     }
     Ok(())
 }
 
 impl<T: WithSchema> WithSchema for Arc<[T]> {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(T::schema(version)))
+        Schema::Vector(schema_of::<T>(version))
     }
 }
 impl<T: Introspect> Introspect for Arc<[T]> {
@@ -3460,7 +7180,7 @@ impl<T: Introspect> Introspect for Arc<[T]> {
         return "Arc[]".to_string();
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if index >= self.len() {
             return None;
         }
@@ -3489,12 +7209,12 @@ impl Introspect for Arc<str> {
     }
 }
 impl Serialize for Arc<str> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_string(&*self)
     }
 }
 impl Deserialize for Arc<str> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let s = deserializer.read_string()?;
 
         let state = deserializer.get_state::<Arc<str>, HashMap<String, Arc<str>>>();
@@ -3508,45 +7228,21 @@ impl Deserialize for Arc<str> {
     }
 }
 
-#[cfg(feature = "nightly")]
-impl<T: Serialize> Serialize for Arc<[T]> {
-    default fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        regular_serialize_vec(self, serializer)
-    }
-}
-#[cfg(not(feature = "nightly"))]
-impl<T: Serialize> Serialize for Arc<[T]> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        regular_serialize_vec(self, serializer)
-    }
-}
-#[cfg(feature = "nightly")]
-impl<T: Serialize + ReprC> Serialize for Arc<[T]> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        unsafe {
-            if !T::repr_c_optimization_safe(serializer.version) {
-                regular_serialize_vec(&*self, serializer)
-            } else {
-                let l = self.len();
-                serializer.write_usize(l)?;
-                serializer.write_buf(std::slice::from_raw_parts(
-                    (*self).as_ptr() as *const u8,
-                    std::mem::size_of::<T>() * l,
-                ))
-            }
-        }
-    }
-}
-
+impl<T: Serialize> Serialize for Arc<[T]> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_slice(&*self, serializer)
+    }
+}
+
 impl<T: Deserialize> Deserialize for Arc<[T]> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(Vec::<T>::deserialize(deserializer)?.into())
     }
 }
 
 impl<T: WithSchema> WithSchema for Vec<T> {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(T::schema(version)))
+        Schema::Vector(schema_of::<T>(version))
     }
 }
 
@@ -3555,7 +7251,7 @@ impl<T: Introspect> Introspect for Vec<T> {
         return "vec[]".to_string();
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if index >= self.len() {
             return None;
         }
@@ -3566,39 +7262,149 @@ impl<T: Introspect> Introspect for Vec<T> {
     }
 }
 
-#[cfg(feature = "nightly")]
 impl<T: Serialize> Serialize for Vec<T> {
-    default fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        regular_serialize_vec(self, serializer)
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_slice(self, serializer)
     }
 }
-#[cfg(not(feature = "nightly"))]
-impl<T: Serialize> Serialize for Vec<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        regular_serialize_vec(self, serializer)
+
+/// Serialize a slice, writing all its elements in one go if T is ReprC.
+fn serialize_slice<T: Serialize>(items: &[T], serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+    if !serializer.uses_raw_copy::<T>() {
+        regular_serialize_vec(items, serializer)
+    } else {
+        let l = items.len();
+        serializer.write_length(l as u64)?;
+        serializer.write_buf(unsafe {
+            std::slice::from_raw_parts(items.as_ptr() as *const u8, std::mem::size_of::<T>() * l)
+        })
     }
 }
-#[cfg(feature = "nightly")]
-impl<T: Serialize + ReprC> Serialize for Vec<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        unsafe {
-            if !T::repr_c_optimization_safe(serializer.version) {
-                regular_serialize_vec(self, serializer)
-            } else {
-                let l = self.len();
-                serializer.write_usize(l)?;
-                serializer.write_buf(std::slice::from_raw_parts(
-                    self.as_ptr() as *const u8,
-                    std::mem::size_of::<T>() * l,
-                ))
+
+/// Written instead of the number of items of a vector, to signal that the items follow in
+/// chunks, each preceded by its number of items, and terminated by an empty chunk.
+/// This makes it possible to write sequences whose length isn't known in advance,
+/// see [Serializer::write_chunked].
+const CHUNKED_VEC_LENGTH: u64 = u64::MAX;
+
+/// Chunks of the chunked vector encoding are written when they reach this many bytes
+const VEC_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Items of a vector in the chunked encoding, collected until the chunk is large enough to be written.
+struct VecChunk {
+    buffer: Vec<u8>,
+    count: usize,
+}
+
+impl VecChunk {
+    fn new() -> VecChunk {
+        VecChunk {
+            buffer: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Add the item to the chunk, writing the chunk first if it is full
+    fn push<T: Serialize>(&mut self, item: &T, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        if self.buffer.len() >= VEC_CHUNK_BYTES {
+            self.write(serializer)?;
+        }
+        let mut item_serializer = Serializer {
+            writer: &mut self.buffer,
+            version: serializer.version,
+            encoding: serializer.encoding,
+            interned_strings: std::mem::take(&mut serializer.interned_strings),
+            context: serializer.context.as_deref_mut(),
+            progress: None,
+        };
+        let result = item.serialize(&mut item_serializer);
+        serializer.interned_strings = item_serializer.interned_strings;
+        result?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn write(&mut self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        if self.count > 0 {
+            serializer.write_length(self.count as u64)?;
+            serializer.write_buf(&self.buffer)?;
+            self.buffer.clear();
+            self.count = 0;
+        }
+        Ok(())
+    }
+
+    /// Write the remaining items, followed by the terminating empty chunk
+    fn finish(&mut self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        self.write(serializer)?;
+        serializer.write_length(0)
+    }
+}
+
+/// Read the number of items of a vector. None means the items use the chunked encoding.
+fn read_vec_length(deserializer: &mut Deserializer<impl Read>) -> Result<Option<usize>, SavefileError> {
+    let length = deserializer.read_length()?;
+    if length == CHUNKED_VEC_LENGTH {
+        return Ok(None);
+    }
+    let length = usize::try_from(length).map_err(|_| SavefileError::SizeOverflow)?;
+    Ok(Some(length))
+}
+
+/// Call `read_item` once for each item of a vector, whose items may use the chunked encoding.
+fn read_vec_items<R: Read>(
+    deserializer: &mut Deserializer<R>,
+    mut read_item: impl FnMut(&mut Deserializer<R>) -> Result<(), SavefileError>,
+) -> Result<(), SavefileError> {
+    match read_vec_length(deserializer)? {
+        Some(length) => {
+            for _ in 0..length {
+                read_item(deserializer)?;
             }
         }
+        None => loop {
+            let chunk_length = deserializer.read_length_usize()?;
+            if chunk_length == 0 {
+                break;
+            }
+            for _ in 0..chunk_length {
+                read_item(deserializer)?;
+            }
+        },
     }
+    Ok(())
 }
 
-fn regular_deserialize_vec<T: Deserialize>(deserializer: &mut Deserializer) -> Result<Vec<T>, SavefileError> {
-    let l = deserializer.read_usize()?;
+fn deserialize_vec_chunks<T: Deserialize>(deserializer: &mut Deserializer<impl Read>) -> Result<Vec<T>, SavefileError> {
+    let mut ret = Vec::new();
+    loop {
+        let chunk_length = deserializer.read_length_usize()?;
+        if chunk_length == 0 {
+            return Ok(ret);
+        }
+        #[cfg(feature = "size_sanity_checks")]
+        {
+            if ret.len().saturating_add(chunk_length) > 1_000_000 {
+                return Err(SavefileError::GeneralError {
+                    msg: format!("Too many items in Vec: {}", ret.len().saturating_add(chunk_length)),
+                });
+            }
+        }
+        deserializer.account_items::<T>(chunk_length)?;
+        let total = ret.len().saturating_add(chunk_length);
+        for _ in 0..chunk_length {
+            if ret.len() == ret.capacity() {
+                reserve_for_items(&mut ret, total);
+            }
+            ret.push(T::deserialize(deserializer).map_err(|err| err.with_path_segment("*"))?);
+        }
+    }
+}
 
+fn regular_deserialize_vec<T: Deserialize>(
+    deserializer: &mut Deserializer<impl Read>,
+    l: usize,
+) -> Result<Vec<T>, SavefileError> {
     #[cfg(feature = "size_sanity_checks")]
     {
         if l > 1_000_000 {
@@ -3607,64 +7413,124 @@ fn regular_deserialize_vec<T: Deserialize>(deserializer: &mut Deserializer) -> R
             });
         }
     }
-    let mut ret = Vec::with_capacity(l);
+    let mut ret = Vec::new();
     for _ in 0..l {
-        ret.push(T::deserialize(deserializer)?);
+        if ret.len() == ret.capacity() {
+            reserve_for_items(&mut ret, l);
+        }
+        ret.push(T::deserialize(deserializer).map_err(|err| err.with_path_segment("*"))?);
     }
     Ok(ret)
 }
 
-#[cfg(feature = "nightly")]
-impl<T: Deserialize> Deserialize for Vec<T> {
-    default fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        Ok(regular_deserialize_vec::<T>(deserializer)?)
-    }
+/// Vectors being deserialized allocate memory for at most this many bytes of items at a time,
+/// see [reserve_for_items]
+const ALLOCATION_CHUNK_BYTES: usize = 16 * 1024 * 1024;
+
+/// Make room in `items` for more of the `total` items being read into it, at least for the next
+/// one, and for no more than `total`. The first allocation is for at most [ALLOCATION_CHUNK_BYTES]
+/// of items, and the capacity then at most doubles each time. Memory is thus only allocated
+/// as items are read, so a corrupt or hostile length fails when the data ends, instead of
+/// first making a huge allocation, while the items are still only moved a few times.
+fn reserve_for_items<T>(items: &mut Vec<T>, total: usize) {
+    let chunk_items = (ALLOCATION_CHUNK_BYTES / std::mem::size_of::<T>().max(1)).max(1);
+    let remaining = total.saturating_sub(items.len());
+    items.reserve_exact(remaining.min(items.capacity().max(chunk_items)));
 }
 
-#[cfg(not(feature = "nightly"))]
-impl<T: Deserialize> Deserialize for Vec<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        Ok(regular_deserialize_vec::<T>(deserializer)?)
+/// Read `count` items of type T as their raw bytes, appending them to `items`. Memory is
+/// only allocated for the items as their bytes are read, see [reserve_for_items]. If the data ends in
+/// the middle of the items, an error is returned, and the items read so far are kept.
+///
+/// The bytes are validated before they are used as items, see [IsReprC::validate].
+///
+/// Safety: T must be [ReprC], and the reader must be in the byte order of the machine,
+/// see [Deserializer::uses_raw_copy].
+unsafe fn read_raw_items<T: WithSchema>(
+    deserializer: &mut Deserializer<impl Read>,
+    items: &mut Vec<T>,
+    count: usize,
+) -> Result<(), SavefileError> {
+    let item_size = std::mem::size_of::<T>();
+    let new_len = items.len().checked_add(count).ok_or(SavefileError::SizeOverflow)?;
+    match new_len.checked_mul(item_size) {
+        Some(bytes) if bytes <= isize::MAX as usize => {}
+        _ => return Err(SavefileError::MemoryAllocationLayoutError),
+    }
+    if item_size == 0 {
+        // Zero-sized items have no bytes in the data, and vectors of them never allocate
+        items.set_len(new_len);
+        return Ok(());
+    }
+    while items.len() < new_len {
+        if items.len() == items.capacity() {
+            reserve_for_items(items, new_len);
+        }
+        let chunk = items.capacity().min(new_len) - items.len();
+        let start = items.as_mut_ptr().add(items.len()) as *mut u8;
+        // Zeroed first, so that the reader is never given uninitialized memory
+        std::ptr::write_bytes(start, 0, chunk * item_size);
+        let bytes = std::slice::from_raw_parts_mut(start, chunk * item_size);
+        deserializer.reader.read_exact(bytes)?;
+        deserializer.validate_raw::<T>(bytes)?;
+        items.set_len(items.len() + chunk);
     }
+    Ok(())
 }
 
-#[cfg(feature = "nightly")]
-impl<T: Deserialize + ReprC> Deserialize for Vec<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        if !T::repr_c_optimization_safe(deserializer.file_version) {
-            Ok(regular_deserialize_vec::<T>(deserializer)?)
+impl<T: Deserialize> Deserialize for Vec<T> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let num_elems = match read_vec_length(deserializer)? {
+            Some(num_elems) => num_elems,
+            None => return deserialize_vec_chunks(deserializer),
+        };
+        deserializer.account_items::<T>(num_elems)?;
+        if !deserializer.uses_raw_copy::<T>() {
+            Ok(regular_deserialize_vec::<T>(deserializer, num_elems)?)
         } else {
-            use std::mem;
+            let mut ret = Vec::new();
+            // Safety: uses_raw_copy says T is ReprC
+            unsafe { read_raw_items(deserializer, &mut ret, num_elems)? };
+            Ok(ret)
+        }
+    }
 
-            let align = mem::align_of::<T>();
-            let elem_size = mem::size_of::<T>();
-            let num_elems = deserializer.read_usize()?;
-            if num_elems == 0 {
-                return Ok(Vec::new());
+    fn deserialize_in_place(&mut self, deserializer: &mut Deserializer<impl Read>) -> Result<(), SavefileError> {
+        let num_elems = match read_vec_length(deserializer)? {
+            Some(num_elems) => num_elems,
+            None => {
+                *self = deserialize_vec_chunks(deserializer)?;
+                return Ok(());
             }
-            let num_bytes = elem_size * num_elems;
-            let layout = if let Ok(layout) = std::alloc::Layout::from_size_align(num_bytes, align) {
-                Ok(layout)
-            } else {
-                Err(SavefileError::MemoryAllocationLayoutError)
-            }?;
-            let ptr = unsafe { std::alloc::alloc(layout.clone()) };
-
-            {
-                let slice = unsafe { std::slice::from_raw_parts_mut(ptr as *mut u8, num_bytes) };
-                match deserializer.reader.read_exact(slice) {
-                    Ok(()) => Ok(()),
-                    Err(err) => {
-                        unsafe {
-                            std::alloc::dealloc(ptr, layout);
-                        }
-                        Err(err)
-                    }
-                }?;
+        };
+        deserializer.account_items::<T>(num_elems)?;
+        #[cfg(feature = "size_sanity_checks")]
+        {
+            if num_elems > 1_000_000 {
+                return Err(SavefileError::GeneralError {
+                    msg: format!("Too many items in Vec: {}", num_elems),
+                });
             }
-            let ret = unsafe { Vec::from_raw_parts(ptr as *mut T, num_elems, num_elems) };
-            Ok(ret)
         }
+        if !deserializer.uses_raw_copy::<T>() {
+            // Existing items are deserialized in place, so their allocations are reused too
+            self.truncate(num_elems);
+            for item in self.iter_mut() {
+                item.deserialize_in_place(deserializer)
+                    .map_err(|err| err.with_path_segment("*"))?;
+            }
+            while self.len() < num_elems {
+                if self.len() == self.capacity() {
+                    reserve_for_items(self, num_elems);
+                }
+                self.push(T::deserialize(deserializer).map_err(|err| err.with_path_segment("*"))?);
+            }
+        } else {
+            self.clear();
+            // Safety: uses_raw_copy says T is ReprC
+            unsafe { read_raw_items(deserializer, self, num_elems)? };
+        }
+        Ok(())
     }
 }
 
@@ -3673,7 +7539,7 @@ impl<T: Introspect> Introspect for VecDeque<T> {
         format!("VecDeque<{}>", std::any::type_name::<T>())
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if let Some(val) = self.get(index) {
             Some(introspect_item(index.to_string(), val))
         } else {
@@ -3688,37 +7554,38 @@ impl<T: Introspect> Introspect for VecDeque<T> {
 
 impl<T: WithSchema> WithSchema for VecDeque<T> {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(T::schema(version)))
+        Schema::Vector(schema_of::<T>(version))
     }
 }
 
 impl<T: Serialize> Serialize for VecDeque<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         regular_serialize_vecdeque::<T>(self, serializer)
     }
 }
 
 impl<T: Deserialize> Deserialize for VecDeque<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(regular_deserialize_vecdeque::<T>(deserializer)?)
     }
 }
 
 fn regular_serialize_vecdeque<T: Serialize>(
     item: &VecDeque<T>,
-    serializer: &mut Serializer,
+    serializer: &mut Serializer<impl Write>,
 ) -> Result<(), SavefileError> {
     let l = item.len();
-    serializer.write_usize(l)?;
+    serializer.write_length(l as u64)?;
     for item in item.iter() {
         item.serialize(serializer)?
     }
     Ok(())
 }
 
-fn regular_deserialize_vecdeque<T: Deserialize>(deserializer: &mut Deserializer) -> Result<VecDeque<T>, SavefileError> {
-    let l = deserializer.read_usize()?;
-    let mut ret = VecDeque::with_capacity(l);
+fn regular_deserialize_vecdeque<T: Deserialize>(deserializer: &mut Deserializer<impl Read>) -> Result<VecDeque<T>, SavefileError> {
+    let l = deserializer.read_length_usize()?;
+    deserializer.account_items::<T>(l)?;
+    let mut ret = VecDeque::with_capacity(l.min(MAX_PREALLOCATED_ITEMS));
     for _ in 0..l {
         ret.push_back(T::deserialize(deserializer)?);
     }
@@ -3729,6 +7596,17 @@ unsafe impl ReprC for bool {
     fn repr_c_optimization_safe(_version: u32) -> bool {
         true
     }
+    fn repr_c_needs_validation() -> bool {
+        true
+    }
+    fn repr_c_validate(bytes: &[u8]) -> Result<(), SavefileError> {
+        match bytes.iter().find(|&&byte| byte > 1) {
+            Some(byte) => Err(SavefileError::GeneralError {
+                msg: format!("Corrupt file - {} is not a valid bool", byte),
+            }),
+            None => Ok(()),
+        }
+    }
 } //It isn't really guaranteed that bool is an u8 or i8 where false = 0 and true = 1. But it's true in practice. And the breakage would be hard to measure if this were ever changed, so a change is unlikely.
 unsafe impl ReprC for u8 {
     fn repr_c_optimization_safe(_version: u32) -> bool {
@@ -3782,12 +7660,14 @@ unsafe impl ReprC for f64 {
 }
 unsafe impl ReprC for usize {
     fn repr_c_optimization_safe(_version: u32) -> bool {
-        true
+        // Saved as 64 bits, so only a raw copy on 64 bit machines
+        std::mem::size_of::<usize>() == 8
     }
 }
 unsafe impl ReprC for isize {
     fn repr_c_optimization_safe(_version: u32) -> bool {
-        true
+        // Saved as 64 bits, so only a raw copy on 64 bit machines
+        std::mem::size_of::<isize>() == 8
     }
 }
 unsafe impl ReprC for () {
@@ -3795,15 +7675,95 @@ unsafe impl ReprC for () {
         true
     }
 }
+unsafe impl<T: ReprC, const N: usize> ReprC for [T; N] {
+    fn repr_c_optimization_safe(version: u32) -> bool {
+        T::repr_c_optimization_safe(version)
+    }
+    fn repr_c_needs_validation() -> bool {
+        T::repr_c_needs_validation()
+    }
+    fn repr_c_validate(bytes: &[u8]) -> Result<(), SavefileError> {
+        T::repr_c_validate(bytes)
+    }
+}
+unsafe impl<T: ReprC> ReprC for Wrapping<T> {
+    fn repr_c_optimization_safe(version: u32) -> bool {
+        T::repr_c_optimization_safe(version)
+    }
+    fn repr_c_needs_validation() -> bool {
+        T::repr_c_needs_validation()
+    }
+    fn repr_c_validate(bytes: &[u8]) -> Result<(), SavefileError> {
+        T::repr_c_validate(bytes)
+    }
+}
+
+/// True if the fields of a tuple, given as offset and size, are laid out in order
+/// without any padding, so the tuple has the same bytes in memory as when serialized.
+/// The layout of tuples is not specified by rust, so this must be checked.
+fn is_packed_in_order(fields: &[(usize, usize)], size: usize) -> bool {
+    let mut end = 0;
+    for &(offset, field_size) in fields {
+        if offset != end {
+            return false;
+        }
+        end += field_size;
+    }
+    end == size
+}
+
+/// A raw-copy proof for a tuple type, if all its fields have one and it has no padding
+macro_rules! tuple_repr_c_layout {
+    ($version:expr, $tuple:ty, $($t:ident $index:tt),*) => {{
+        // Fields needing validation are not supported, so such tuples are read field by field
+        if $($t::repr_c_layout($version).is_yes() && $t::repr_c_layout($version).validator().is_none() &&)*
+            is_packed_in_order(
+                &[$((std::mem::offset_of!($tuple, $index), std::mem::size_of::<$t>())),*],
+                std::mem::size_of::<$tuple>(),
+            )
+        {
+            // Safety: the fields can be raw-copied, and are laid out like when serialized
+            unsafe { IsReprC::yes() }
+        } else {
+            IsReprC::no()
+        }
+    }};
+}
+
+unsafe impl<T1: ReprC + WithSchema> ReprC for (T1,) {
+    fn repr_c_optimization_safe(version: u32) -> bool {
+        <(T1,)>::repr_c_layout(version).is_yes()
+    }
+}
+unsafe impl<T1: ReprC + WithSchema, T2: ReprC + WithSchema> ReprC for (T1, T2) {
+    fn repr_c_optimization_safe(version: u32) -> bool {
+        <(T1, T2)>::repr_c_layout(version).is_yes()
+    }
+}
+unsafe impl<T1: ReprC + WithSchema, T2: ReprC + WithSchema, T3: ReprC + WithSchema> ReprC for (T1, T2, T3) {
+    fn repr_c_optimization_safe(version: u32) -> bool {
+        <(T1, T2, T3)>::repr_c_layout(version).is_yes()
+    }
+}
 
 
 impl<T: WithSchema, const N: usize> WithSchema for [T; N] {
     fn schema(version: u32) -> Schema {
         Schema::Array(SchemaArray {
-            item_type: Box::new(T::schema(version)),
+            item_type: schema_of::<T>(version),
             count: N,
         })
     }
+    fn repr_c_layout(version: u32) -> IsReprC<Self> {
+        let item = T::repr_c_layout(version);
+        if item.is_yes() {
+            // Safety: the items of an array follow each other without padding, so the
+            // bytes of arrays are validated like the bytes of any number of items
+            unsafe { IsReprC::yes_validated(item.validator()) }
+        } else {
+            IsReprC::no()
+        }
+    }
 }
 
 impl<T: Introspect, const N: usize> Introspect for [T; N] {
@@ -3811,7 +7771,7 @@ impl<T: Introspect, const N: usize> Introspect for [T; N] {
         format!("[{}; {}]", std::any::type_name::<T>(), N)
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if index >= self.len() {
             None
         } else {
@@ -3820,105 +7780,45 @@ impl<T: Introspect, const N: usize> Introspect for [T; N] {
     }
 }
 
-#[cfg(feature = "nightly")]
-impl<T: Serialize, const N: usize> Serialize for [T; N] {
-    default fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        for item in self.iter() {
-            item.serialize(serializer)?
-        }
-        Ok(())
-    }
-}
-#[cfg(not(feature = "nightly"))]
 impl<T: Serialize, const N: usize> Serialize for [T; N] {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        for item in self.iter() {
-            item.serialize(serializer)?
-        }
-        Ok(())
-    }
-}
-#[cfg(not(feature = "nightly"))]
-impl<T: Deserialize, const N: usize> Deserialize for [T; N] {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        let mut data: [MaybeUninit<T>; N] = unsafe {
-            MaybeUninit::uninit().assume_init() //This seems strange, but is correct according to rust docs: https://doc.rust-lang.org/std/mem/union.MaybeUninit.html
-        };
-        for idx in 0..N {
-            data[idx] = MaybeUninit::new(T::deserialize(deserializer)?); //This leaks on panic, but we shouldn't panic and at least it isn't UB!
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        if !serializer.uses_raw_copy::<T>() {
+            for item in self.iter() {
+                item.serialize(serializer)?
+            }
+            Ok(())
+        } else {
+            serializer.write_buf(unsafe {
+                std::slice::from_raw_parts(self.as_ptr() as *const u8, std::mem::size_of::<T>() * N)
+            })
         }
-        let ptr = &mut data as *mut _ as *mut [T; N];
-        let res = unsafe { ptr.read() };
-        core::mem::forget(data);
-        Ok(res)
     }
 }
-#[cfg(feature = "nightly")]
+
 impl<T: Deserialize, const N: usize> Deserialize for [T; N] {
-    default fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let mut data: [MaybeUninit<T>; N] = unsafe {
             MaybeUninit::uninit().assume_init() //This seems strange, but is correct according to rust docs: https://doc.rust-lang.org/std/mem/union.MaybeUninit.html
         };
-        for idx in 0..N {
-            data[idx] = MaybeUninit::new(T::deserialize(deserializer)?); //This leaks on panic, but we shouldn't panic and at least it isn't UB!
-        }
-        let ptr = &mut data as *mut _ as *mut [T; N];
-        let res = unsafe { ptr.read() };
-        core::mem::forget(data);
-        Ok(res)
-    }
-}
-
-#[cfg(feature = "nightly")]
-impl<T: Serialize + ReprC, const N: usize> Serialize for [T; N] {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        unsafe {
-            if !T::repr_c_optimization_safe(serializer.version) {
-                for item in self.iter() {
-                    item.serialize(serializer)?
-                }
-                Ok(())
-            } else {
-                serializer.write_buf(std::slice::from_raw_parts(
-                    self.as_ptr() as *const u8,
-                    std::mem::size_of::<T>() * N,
-                ))
-            }
-        }
-    }
-}
-
-#[cfg(feature = "nightly")]
-impl<T: Deserialize + ReprC, const N: usize> Deserialize for [T; N] {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
-        if !T::repr_c_optimization_safe(deserializer.file_version) {
-            let mut data: [MaybeUninit<T>; N] = unsafe {
-                MaybeUninit::uninit().assume_init() //This seems strange, but is correct according to rust docs: https://doc.rust-lang.org/std/mem/union.MaybeUninit.html
-            };
+        if !deserializer.uses_raw_copy::<T>() {
             for idx in 0..N {
                 data[idx] = MaybeUninit::new(T::deserialize(deserializer)?); //This leaks on panic, but we shouldn't panic and at least it isn't UB!
             }
-            let ptr = &mut data as *mut _ as *mut [T; N];
-            let res = unsafe { ptr.read() };
-            core::mem::forget(data);
-            Ok(res)
         } else {
-            let mut data: [MaybeUninit<T>; N] = unsafe {
-                MaybeUninit::uninit().assume_init() //This seems strange, but is correct according to rust docs: https://doc.rust-lang.org/std/mem/union.MaybeUninit.html
+            let ptr = data.as_mut_ptr() as *mut u8;
+            let num_bytes: usize = std::mem::size_of::<T>() * N;
+            // Zeroed first, so that the reader is never given uninitialized memory
+            let slice = unsafe {
+                std::ptr::write_bytes(ptr, 0, num_bytes);
+                std::slice::from_raw_parts_mut(ptr, num_bytes)
             };
-
-            {
-                let ptr = data.as_mut_ptr();
-                let num_bytes: usize = std::mem::size_of::<T>() * N;
-                let slice: &mut [MaybeUninit<u8>] =
-                    unsafe { std::slice::from_raw_parts_mut(ptr as *mut MaybeUninit<u8>, num_bytes) };
-                deserializer.reader.read_exact(unsafe { std::mem::transmute(slice) })?;
-            }
-            let ptr = &mut data as *mut _ as *mut [T; N];
-            let res = unsafe { ptr.read() };
-            core::mem::forget(data);
-            Ok(res)
+            deserializer.reader.read_exact(slice)?;
+            deserializer.validate_raw::<T>(slice)?;
         }
+        let ptr = &mut data as *mut _ as *mut [T; N];
+        let res = unsafe { ptr.read() };
+        core::mem::forget(data);
+        Ok(res)
     }
 }
 
@@ -3928,14 +7828,14 @@ impl<T1: WithSchema> WithSchema for Range<T1> {
     }
 }
 impl<T1: Serialize> Serialize for Range<T1> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         self.start.serialize(serializer)?;
         self.end.serialize(serializer)?;
         Ok(())
     }
 }
 impl<T1: Deserialize> Deserialize for Range<T1> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(T1::deserialize(deserializer)?..T1::deserialize(deserializer)?)
     }
 }
@@ -3944,7 +7844,7 @@ impl<T1: Introspect> Introspect for Range<T1> {
         return "Range".to_string();
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if index == 0 {
             return Some(introspect_item("start".to_string(), &self.start));
         }
@@ -3959,16 +7859,19 @@ impl<T1: WithSchema, T2: WithSchema, T3: WithSchema> WithSchema for (T1, T2, T3)
     fn schema(version: u32) -> Schema {
         Schema::new_tuple3::<T1, T2, T3>(version)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<Self> {
+        tuple_repr_c_layout!(version, (T1, T2, T3), T1 0, T2 1, T3 2)
+    }
 }
 impl<T1: Serialize, T2: Serialize, T3: Serialize> Serialize for (T1, T2, T3) {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         self.0.serialize(serializer)?;
         self.1.serialize(serializer)?;
         self.2.serialize(serializer)
     }
 }
 impl<T1: Deserialize, T2: Deserialize, T3: Deserialize> Deserialize for (T1, T2, T3) {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok((
             T1::deserialize(deserializer)?,
             T2::deserialize(deserializer)?,
@@ -3981,15 +7884,18 @@ impl<T1: WithSchema, T2: WithSchema> WithSchema for (T1, T2) {
     fn schema(version: u32) -> Schema {
         Schema::new_tuple2::<T1, T2>(version)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<Self> {
+        tuple_repr_c_layout!(version, (T1, T2), T1 0, T2 1)
+    }
 }
 impl<T1: Serialize, T2: Serialize> Serialize for (T1, T2) {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         self.0.serialize(serializer)?;
         self.1.serialize(serializer)
     }
 }
 impl<T1: Deserialize, T2: Deserialize> Deserialize for (T1, T2) {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok((T1::deserialize(deserializer)?, T2::deserialize(deserializer)?))
     }
 }
@@ -3998,14 +7904,17 @@ impl<T1: WithSchema> WithSchema for (T1,) {
     fn schema(version: u32) -> Schema {
         Schema::new_tuple1::<T1>(version)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<Self> {
+        tuple_repr_c_layout!(version, (T1,), T1 0)
+    }
 }
 impl<T1: Serialize> Serialize for (T1,) {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         self.0.serialize(serializer)
     }
 }
 impl<T1: Deserialize> Deserialize for (T1,) {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok((T1::deserialize(deserializer)?,))
     }
 }
@@ -4016,12 +7925,12 @@ impl<T: arrayvec::Array<Item = u8> + Copy> WithSchema for arrayvec::ArrayString<
     }
 }
 impl<T: arrayvec::Array<Item = u8> + Copy> Serialize for arrayvec::ArrayString<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_string(self.as_str())
     }
 }
 impl<T: arrayvec::Array<Item = u8> + Copy> Deserialize for arrayvec::ArrayString<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let s = deserializer.read_string()?;
         Ok(arrayvec::ArrayString::from(&s)?)
     }
@@ -4039,7 +7948,7 @@ impl<T: arrayvec::Array<Item = u8> + Copy> Introspect for arrayvec::ArrayString<
 
 impl<V: WithSchema, T: arrayvec::Array<Item = V>> WithSchema for arrayvec::ArrayVec<T> {
     fn schema(version: u32) -> Schema {
-        Schema::Vector(Box::new(V::schema(version)))
+        Schema::Vector(schema_of::<V>(version))
     }
 }
 
@@ -4061,79 +7970,33 @@ impl<V: Introspect + 'static, T: arrayvec::Array<Item = V>> Introspect for array
         self.len()
     }
 }
-#[cfg(feature = "nightly")]
-impl<V: Serialize, T: arrayvec::Array<Item = V>> Serialize for arrayvec::ArrayVec<T> {
-    default fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        regular_serialize_vec(self, serializer)
-    }
-}
-
-#[cfg(not(feature = "nightly"))]
 impl<V: Serialize, T: arrayvec::Array<Item = V>> Serialize for arrayvec::ArrayVec<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        regular_serialize_vec(self, serializer)
-    }
-}
-
-#[cfg(feature = "nightly")]
-impl<V: Serialize + ReprC, T: arrayvec::Array<Item = V>> Serialize for arrayvec::ArrayVec<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        unsafe {
-            if !V::repr_c_optimization_safe(serializer.version) {
-                regular_serialize_vec(self, serializer)
-            } else {
-                let l = self.len();
-                serializer.write_usize(l)?;
-                serializer.write_buf(std::slice::from_raw_parts(
-                    self.as_ptr() as *const u8,
-                    std::mem::size_of::<V>() * l,
-                ))
-            }
-        }
-    }
-}
-#[cfg(feature = "nightly")]
-impl<V: Deserialize, T: arrayvec::Array<Item = V>> Deserialize for arrayvec::ArrayVec<T> {
-    default fn deserialize(deserializer: &mut Deserializer) -> Result<arrayvec::ArrayVec<T>, SavefileError> {
-        let mut ret = arrayvec::ArrayVec::new();
-        let l = deserializer.read_usize()?;
-        for _ in 0..l {
-            ret.push(V::deserialize(deserializer)?);
-        }
-        Ok(ret)
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_slice(self, serializer)
     }
 }
 
-#[cfg(not(feature = "nightly"))]
 impl<V: Deserialize, T: arrayvec::Array<Item = V>> Deserialize for arrayvec::ArrayVec<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<arrayvec::ArrayVec<T>, SavefileError> {
-        let mut ret = arrayvec::ArrayVec::new();
-        let l = deserializer.read_usize()?;
-        for _ in 0..l {
-            ret.push(V::deserialize(deserializer)?);
-        }
-        Ok(ret)
-    }
-}
-
-#[cfg(feature = "nightly")]
-impl<V: Deserialize + ReprC, T: arrayvec::Array<Item = V>> Deserialize for arrayvec::ArrayVec<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<arrayvec::ArrayVec<T>, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<arrayvec::ArrayVec<T>, SavefileError> {
         let mut ret = arrayvec::ArrayVec::new();
-        let l = deserializer.read_usize()?;
+        let l = deserializer.read_length_usize()?;
         if l > ret.capacity() {
             return Err(SavefileError::ArrayvecCapacityError {
                 msg: format!("ArrayVec with capacity {} can't hold {} items", ret.capacity(), l),
             });
         }
-        if !V::repr_c_optimization_safe(deserializer.memory_version) {
+        if !deserializer.uses_raw_copy::<V>() {
             for _ in 0..l {
                 ret.push(V::deserialize(deserializer)?);
             }
         } else {
             unsafe {
-                let bytebuf = std::slice::from_raw_parts_mut(ret.as_mut_ptr() as *mut u8, std::mem::size_of::<V>() * l);
+                let num_bytes = std::mem::size_of::<V>() * l;
+                // Zeroed first, so that the reader is never given uninitialized memory
+                std::ptr::write_bytes(ret.as_mut_ptr() as *mut u8, 0, num_bytes);
+                let bytebuf = std::slice::from_raw_parts_mut(ret.as_mut_ptr() as *mut u8, num_bytes);
                 deserializer.reader.read_exact(bytebuf)?; //We 'leak' ReprC objects here on error, but the idea is they are drop-less anyway, so this has no effect
+                deserializer.validate_raw::<V>(bytebuf)?;
                 ret.set_len(l);
             }
         }
@@ -4148,12 +8011,12 @@ impl<T: WithSchema> WithSchema for Box<T> {
     }
 }
 impl<T: Serialize> Serialize for Box<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         self.deref().serialize(serializer)
     }
 }
 impl<T: Deserialize> Deserialize for Box<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(Box::new(T::deserialize(deserializer)?))
     }
 }
@@ -4166,12 +8029,12 @@ impl<T: WithSchema> WithSchema for Rc<T> {
     }
 }
 impl<T: Serialize> Serialize for Rc<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         self.deref().serialize(serializer)
     }
 }
 impl<T: Deserialize> Deserialize for Rc<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(Rc::new(T::deserialize(deserializer)?))
     }
 }
@@ -4182,16 +8045,17 @@ impl<T: WithSchema> WithSchema for Arc<T> {
     }
 }
 impl<T: Serialize> Serialize for Arc<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         self.deref().serialize(serializer)
     }
 }
 impl<T: Deserialize> Deserialize for Arc<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(Arc::new(T::deserialize(deserializer)?))
     }
 }
 
+#[cfg(feature = "bzip2")]
 use bzip2::Compression;
 use std::any::{Any, TypeId};
 use std::cell::Cell;
@@ -4199,7 +8063,10 @@ use std::cell::RefCell;
 use std::convert::TryFrom;
 use std::fmt::{Debug, Display, Formatter};
 use std::marker::PhantomData;
+#[cfg(feature = "fs")]
+use std::path::Path;
 use std::path::PathBuf;
+use sha2::Digest;
 use std::sync::Arc;
 
 impl<T: WithSchema> WithSchema for RefCell<T> {
@@ -4208,40 +8075,94 @@ impl<T: WithSchema> WithSchema for RefCell<T> {
     }
 }
 impl<T: Serialize> Serialize for RefCell<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         self.borrow().serialize(serializer)
     }
 }
 impl<T: Deserialize> Deserialize for RefCell<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(RefCell::new(T::deserialize(deserializer)?))
     }
 }
 
+impl<T: WithSchema> WithSchema for Wrapping<T> {
+    fn schema(version: u32) -> Schema {
+        T::schema(version)
+    }
+    fn repr_c_layout(version: u32) -> IsReprC<Self> {
+        let inner = T::repr_c_layout(version);
+        if inner.is_yes() {
+            // Safety: Wrapping is repr(transparent)
+            unsafe { IsReprC::yes_validated(inner.validator()) }
+        } else {
+            IsReprC::no()
+        }
+    }
+}
+impl<T: Serialize> Serialize for Wrapping<T> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        self.0.serialize(serializer)
+    }
+}
+impl<T: Deserialize> Deserialize for Wrapping<T> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        Ok(Wrapping(T::deserialize(deserializer)?))
+    }
+}
+impl<T: Introspect> Introspect for Wrapping<T> {
+    fn introspect_value(&self) -> String {
+        self.0.introspect_value()
+    }
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        self.0.introspect_child(index)
+    }
+    fn introspect_len(&self) -> usize {
+        self.0.introspect_len()
+    }
+}
+
 impl<T: WithSchema> WithSchema for Cell<T> {
     fn schema(version: u32) -> Schema {
         T::schema(version)
     }
 }
 impl<T: Serialize + Copy> Serialize for Cell<T> {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         let t: T = self.get();
         t.serialize(serializer)
     }
 }
 impl<T: Deserialize> Deserialize for Cell<T> {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(Cell::new(T::deserialize(deserializer)?))
     }
 }
 
+impl<T: Introspect + Copy> Introspect for Cell<T> {
+    fn introspect_value(&self) -> String {
+        format!("Cell({} (deep introspect not supported))", self.get().introspect_value())
+    }
+
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        // The value can only be copied out of the cell, not borrowed
+        None
+    }
+
+    fn introspect_len(&self) -> usize {
+        0
+    }
+}
+
 impl WithSchema for () {
     fn schema(_version: u32) -> Schema {
         Schema::ZeroSize
     }
+    fn repr_c_layout(version: u32) -> IsReprC<()> {
+        IsReprC::of(version)
+    }
 }
 impl Serialize for () {
-    fn serialize(&self, _serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, _serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         Ok(())
     }
 }
@@ -4249,12 +8170,12 @@ impl Introspect for () {
     fn introspect_value(&self) -> String {
         "()".to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
 impl Deserialize for () {
-    fn deserialize(_deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(_deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(())
     }
 }
@@ -4264,7 +8185,7 @@ impl<T: Introspect> Introspect for (T,) {
         return "1-tuple".to_string();
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if index == 0 {
             return Some(introspect_item("0".to_string(), &self.0));
         }
@@ -4277,7 +8198,7 @@ impl<T1: Introspect, T2: Introspect> Introspect for (T1, T2) {
         return "2-tuple".to_string();
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if index == 0 {
             return Some(introspect_item("0".to_string(), &self.0));
         }
@@ -4292,7 +8213,7 @@ impl<T1: Introspect, T2: Introspect, T3: Introspect> Introspect for (T1, T2, T3)
         return "3-tuple".to_string();
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if index == 0 {
             return Some(introspect_item("0".to_string(), &self.0));
         }
@@ -4310,7 +8231,7 @@ impl<T1: Introspect, T2: Introspect, T3: Introspect, T4: Introspect> Introspect
         return "4-tuple".to_string();
     }
 
-    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         if index == 0 {
             return Some(introspect_item("0".to_string(), &self.0));
         }
@@ -4331,7 +8252,7 @@ impl Introspect for AtomicBool {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4339,7 +8260,7 @@ impl Introspect for AtomicU8 {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4347,7 +8268,7 @@ impl Introspect for AtomicI8 {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4355,7 +8276,7 @@ impl Introspect for AtomicU16 {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4363,7 +8284,7 @@ impl Introspect for AtomicI16 {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4371,7 +8292,7 @@ impl Introspect for AtomicU32 {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4379,7 +8300,7 @@ impl Introspect for AtomicI32 {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4387,7 +8308,7 @@ impl Introspect for AtomicU64 {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4395,7 +8316,7 @@ impl Introspect for AtomicI64 {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4403,7 +8324,7 @@ impl Introspect for AtomicUsize {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4411,7 +8332,7 @@ impl Introspect for AtomicIsize {
     fn introspect_value(&self) -> String {
         self.load(Ordering::SeqCst).to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4463,20 +8384,12 @@ impl WithSchema for AtomicI64 {
 }
 impl WithSchema for AtomicUsize {
     fn schema(_version: u32) -> Schema {
-        match std::mem::size_of::<usize>() {
-            4 => Schema::Primitive(SchemaPrimitive::schema_u32),
-            8 => Schema::Primitive(SchemaPrimitive::schema_u64),
-            _ => panic!("Size of usize was neither 32 bit nor 64 bit. This is not supported by the savefile crate."),
-        }
+        Schema::Primitive(SchemaPrimitive::schema_u64)
     }
 }
 impl WithSchema for AtomicIsize {
     fn schema(_version: u32) -> Schema {
-        match std::mem::size_of::<isize>() {
-            4 => Schema::Primitive(SchemaPrimitive::schema_i32),
-            8 => Schema::Primitive(SchemaPrimitive::schema_i64),
-            _ => panic!("Size of isize was neither 32 bit nor 64 bit. This is not supported by the savefile crate."),
-        }
+        Schema::Primitive(SchemaPrimitive::schema_i64)
     }
 }
 
@@ -4484,81 +8397,114 @@ impl WithSchema for bool {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_bool)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<bool> {
+        IsReprC::of(version)
+    }
 }
 impl WithSchema for u8 {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_u8)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<u8> {
+        IsReprC::of(version)
+    }
 }
 impl WithSchema for i8 {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_i8)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<i8> {
+        IsReprC::of(version)
+    }
 }
 impl WithSchema for u16 {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_u16)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<u16> {
+        IsReprC::of(version)
+    }
 }
 impl WithSchema for i16 {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_i16)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<i16> {
+        IsReprC::of(version)
+    }
 }
 impl WithSchema for u32 {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_u32)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<u32> {
+        IsReprC::of(version)
+    }
 }
 impl WithSchema for i32 {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_i32)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<i32> {
+        IsReprC::of(version)
+    }
 }
 impl WithSchema for u64 {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_u64)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<u64> {
+        IsReprC::of(version)
+    }
 }
 impl WithSchema for i64 {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_i64)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<i64> {
+        IsReprC::of(version)
+    }
 }
+// usize and isize are always saved as 64 bit values, so files are portable between
+// 32 and 64 bit machines. Loading a value which doesn't fit fails with SizeOverflow.
 impl WithSchema for usize {
     fn schema(_version: u32) -> Schema {
-        match std::mem::size_of::<usize>() {
-            4 => Schema::Primitive(SchemaPrimitive::schema_u32),
-            8 => Schema::Primitive(SchemaPrimitive::schema_u64),
-            _ => panic!("Size of usize was neither 32 bit nor 64 bit. This is not supported by the savefile crate."),
-        }
+        Schema::Primitive(SchemaPrimitive::schema_u64)
+    }
+    fn repr_c_layout(version: u32) -> IsReprC<usize> {
+        IsReprC::of(version)
     }
 }
 impl WithSchema for isize {
     fn schema(_version: u32) -> Schema {
-        match std::mem::size_of::<isize>() {
-            4 => Schema::Primitive(SchemaPrimitive::schema_i32),
-            8 => Schema::Primitive(SchemaPrimitive::schema_i64),
-            _ => panic!("Size of isize was neither 32 bit nor 64 bit. This is not supported by the savefile crate."),
-        }
+        Schema::Primitive(SchemaPrimitive::schema_i64)
+    }
+    fn repr_c_layout(version: u32) -> IsReprC<isize> {
+        IsReprC::of(version)
     }
 }
 impl WithSchema for f32 {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_f32)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<f32> {
+        IsReprC::of(version)
+    }
 }
 impl WithSchema for f64 {
     fn schema(_version: u32) -> Schema {
         Schema::Primitive(SchemaPrimitive::schema_f64)
     }
+    fn repr_c_layout(version: u32) -> IsReprC<f64> {
+        IsReprC::of(version)
+    }
 }
 
 impl Introspect for bool {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4566,7 +8512,7 @@ impl Introspect for u8 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4574,7 +8520,7 @@ impl Introspect for u16 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4582,7 +8528,7 @@ impl Introspect for u32 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4590,7 +8536,7 @@ impl Introspect for u64 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4598,7 +8544,7 @@ impl Introspect for u128 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4606,7 +8552,7 @@ impl Introspect for i8 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4614,7 +8560,7 @@ impl Introspect for i16 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4622,7 +8568,7 @@ impl Introspect for i32 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4630,7 +8576,7 @@ impl Introspect for i64 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4638,7 +8584,7 @@ impl Introspect for i128 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4646,7 +8592,7 @@ impl Introspect for f32 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4654,7 +8600,7 @@ impl Introspect for f64 {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4662,7 +8608,7 @@ impl Introspect for usize {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
@@ -4670,259 +8616,259 @@ impl Introspect for isize {
     fn introspect_value(&self) -> String {
         self.to_string()
     }
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
 
 impl Serialize for u8 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_u8(*self)
     }
 }
 impl Deserialize for u8 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_u8()
     }
 }
 impl Serialize for bool {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_bool(*self)
     }
 }
 impl Deserialize for bool {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_bool()
     }
 }
 
 impl Serialize for f32 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_f32(*self)
     }
 }
 impl Deserialize for f32 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_f32()
     }
 }
 
 impl Serialize for f64 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_f64(*self)
     }
 }
 impl Deserialize for f64 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_f64()
     }
 }
 
 impl Serialize for i8 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_i8(*self)
     }
 }
 impl Deserialize for i8 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_i8()
     }
 }
 impl Serialize for u16 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_u16(*self)
     }
 }
 impl Deserialize for u16 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_u16()
     }
 }
 impl Serialize for i16 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_i16(*self)
     }
 }
 impl Deserialize for i16 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_i16()
     }
 }
 
 impl Serialize for u32 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_u32(*self)
     }
 }
 impl Deserialize for u32 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_u32()
     }
 }
 impl Serialize for i32 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_i32(*self)
     }
 }
 impl Deserialize for i32 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_i32()
     }
 }
 
 impl Serialize for u64 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_u64(*self)
     }
 }
 impl Deserialize for u64 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_u64()
     }
 }
 impl Serialize for i64 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_i64(*self)
     }
 }
 impl Deserialize for i64 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_i64()
     }
 }
 
 impl Serialize for usize {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_usize(*self)
     }
 }
 impl Deserialize for usize {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_usize()
     }
 }
 impl Serialize for isize {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_isize(*self)
     }
 }
 impl Deserialize for isize {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         deserializer.read_isize()
     }
 }
 
 impl Serialize for AtomicBool {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_bool(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicBool {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicBool::new(deserializer.read_bool()?))
     }
 }
 
 impl Serialize for AtomicU8 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_u8(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicU8 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicU8::new(deserializer.read_u8()?))
     }
 }
 impl Serialize for AtomicI8 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_i8(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicI8 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicI8::new(deserializer.read_i8()?))
     }
 }
 impl Serialize for AtomicU16 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_u16(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicU16 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicU16::new(deserializer.read_u16()?))
     }
 }
 impl Serialize for AtomicI16 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_i16(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicI16 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicI16::new(deserializer.read_i16()?))
     }
 }
 
 impl Serialize for AtomicU32 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_u32(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicU32 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicU32::new(deserializer.read_u32()?))
     }
 }
 impl Serialize for AtomicI32 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_i32(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicI32 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicI32::new(deserializer.read_i32()?))
     }
 }
 
 impl Serialize for AtomicU64 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_u64(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicU64 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicU64::new(deserializer.read_u64()?))
     }
 }
 impl Serialize for AtomicI64 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_i64(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicI64 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicI64::new(deserializer.read_i64()?))
     }
 }
 
 impl Serialize for AtomicUsize {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_usize(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicUsize {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicUsize::new(deserializer.read_usize()?))
     }
 }
 impl Serialize for AtomicIsize {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
         serializer.write_isize(self.load(Ordering::SeqCst))
     }
 }
 impl Deserialize for AtomicIsize {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         Ok(AtomicIsize::new(deserializer.read_isize()?))
     }
 }
@@ -4943,19 +8889,22 @@ impl Introspect for Canary1 {
         "Canary1".to_string()
     }
 
-    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem + '_>> {
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
         None
     }
 }
 
+/// The value a [Canary1] is serialized as
+const CANARY1_VALUE: u32 = 0x47566843;
+
 impl Deserialize for Canary1 {
-    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let magic = deserializer.read_u32()?;
-        if magic != 0x47566843 {
+        if magic != CANARY1_VALUE {
             return Err(SavefileError::GeneralError {
                 msg: format!(
                     "Encountered bad magic value when deserializing Canary1. Expected {} but got {}",
-                    0x47566843, magic
+                    CANARY1_VALUE, magic
                 ),
             });
         }
@@ -4964,8 +8913,8 @@ impl Deserialize for Canary1 {
 }
 
 impl Serialize for Canary1 {
-    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
-        serializer.write_u32(0x47566843)
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_u32(CANARY1_VALUE)
     }
 }
 
@@ -5204,21 +9153,6 @@ impl Display for IntrospectedElementKey {
     }
 }
 
-struct OuterIntrospectItem<'a> {
-    key: String,
-    val: &'a dyn Introspect,
-}
-
-impl<'a> IntrospectItem<'a> for OuterIntrospectItem<'a> {
-    fn key(&self) -> &str {
-        &self.key
-    }
-
-    fn val(&self) -> &dyn Introspect {
-        self.val
-    }
-}
-
 impl Introspector {
     /// Returns a new Introspector with no limit to the number of fields introspected per level
     pub fn new() -> Introspector {
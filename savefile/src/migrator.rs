@@ -0,0 +1,164 @@
+//! Loading of files saved by older versions of an application, whose data
+//! structures were too different to be handled by the `#[savefile_versions]` attribute.
+
+use crate::{Deserialize, Deserializer, SavefileError, SavefileHeader, WithSchema};
+use std::any::Any;
+use std::io::Read;
+use std::marker::PhantomData;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+type LoadFn = Box<dyn Fn(&mut dyn Read) -> Result<Box<dyn Any>, SavefileError>>;
+type UpgradeFn = Box<dyn Fn(Box<dyn Any>) -> Result<Box<dyn Any>, SavefileError>>;
+
+struct MigrationStep {
+    max_version: u32,
+    load: LoadFn,
+    upgrade: UpgradeFn,
+}
+
+/// Loads files saved using historical data structures, and upgrades them to the current one.
+///
+/// Each historical type is registered along with the last file version which was saved
+/// using it, and a function converting it to the next type. When loading, the version of
+/// the file determines which type it is deserialized as, and then the upgrade functions
+/// are applied in order until the current type `T` is reached.
+///
+/// Upgrades must be registered in order of increasing versions, and the result type of each
+/// upgrade must be the historical type of the next upgrade (or `T`, for the last one).
+///
+/// ```
+/// extern crate savefile;
+/// #[macro_use]
+/// extern crate savefile_derive;
+/// use savefile::prelude::*;
+///
+/// #[derive(Savefile)]
+/// struct OldPlayer {
+///     name: String,
+///     x: f32,
+///     y: f32,
+/// }
+/// #[derive(Savefile)]
+/// struct Player {
+///     name: String,
+///     position: (f32, f32),
+/// }
+///
+/// fn main() {
+///     let mut data = Vec::new();
+///     save(&mut data, 0, &OldPlayer { name: "Bob".into(), x: 1.0, y: 2.0 }).unwrap();
+///
+///     let migrator = Migrator::<Player>::new(1).with_upgrade(0, |old: OldPlayer| Player {
+///         name: old.name,
+///         position: (old.x, old.y),
+///     });
+///     let player = migrator.load(&mut &data[..]).unwrap();
+///     assert_eq!(player.position, (1.0, 2.0));
+/// }
+/// ```
+pub struct Migrator<T> {
+    version: u32,
+    steps: Vec<MigrationStep>,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: WithSchema + Deserialize + 'static> Migrator<T> {
+    /// Create a migrator for the type T, whose current version is `version`.
+    pub fn new(version: u32) -> Migrator<T> {
+        Migrator {
+            version,
+            steps: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Register the historical type `Old`, which was used for files with versions up to
+    /// and including `max_version`, and newer than the `max_version` of the previous upgrade.
+    /// `upgrade` converts it to the type used by the following versions.
+    ///
+    /// Panics if `max_version` isn't larger than that of the previously registered upgrade,
+    /// or isn't smaller than the version of T.
+    pub fn with_upgrade<Old, New>(mut self, max_version: u32, upgrade: impl Fn(Old) -> New + 'static) -> Migrator<T>
+    where
+        Old: WithSchema + Deserialize + 'static,
+        New: 'static,
+    {
+        if let Some(previous) = self.steps.last() {
+            assert!(
+                max_version > previous.max_version,
+                "Upgrades must be registered in order of increasing versions"
+            );
+        }
+        assert!(
+            max_version < self.version,
+            "Upgrades must be for versions earlier than the current version"
+        );
+        self.steps.push(MigrationStep {
+            max_version,
+            load: Box::new(move |reader| {
+                let old: Old = Deserializer::load(&mut &mut *reader, max_version)?;
+                Ok(Box::new(old))
+            }),
+            upgrade: Box::new(move |value| match value.downcast::<Old>() {
+                Ok(old) => Ok(Box::new(upgrade(*old))),
+                Err(_) => Err(SavefileError::GeneralError {
+                    msg: format!(
+                        "The upgrade before the one for version {} doesn't produce a {}",
+                        max_version,
+                        std::any::type_name::<Old>()
+                    ),
+                }),
+            }),
+        });
+        self
+    }
+
+    /// Load data saved by any of the registered historical types, or by T itself.
+    pub fn load(&self, reader: &mut impl Read) -> Result<T, SavefileError> {
+        // The header is read twice, once here to find the version, and again when loading.
+        let mut recording = RecordingReader {
+            reader,
+            recorded: Vec::new(),
+        };
+        let header = SavefileHeader::read(&mut recording)?;
+        let recorded = recording.recorded;
+        let mut reader = (&recorded[..]).chain(reader);
+
+        let first_step = match self.steps.iter().position(|step| header.version <= step.max_version) {
+            Some(first_step) => first_step,
+            None => return Deserializer::load::<T>(&mut reader, self.version),
+        };
+        let mut value = (self.steps[first_step].load)(&mut reader)?;
+        for step in &self.steps[first_step..] {
+            value = (step.upgrade)(value)?;
+        }
+        match value.downcast::<T>() {
+            Ok(value) => Ok(*value),
+            Err(_) => Err(SavefileError::GeneralError {
+                msg: format!("The last upgrade doesn't produce a {}", std::any::type_name::<T>()),
+            }),
+        }
+    }
+
+    /// Like [Migrator::load], except it reads the given file.
+    #[cfg(feature = "fs")]
+    pub fn load_file(&self, path: impl AsRef<Path>) -> Result<T, SavefileError> {
+        let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+        self.load(&mut file)
+    }
+}
+
+/// A Read-wrapper keeping a copy of everything read.
+struct RecordingReader<'a, R: Read> {
+    reader: &'a mut R,
+    recorded: Vec<u8>,
+}
+
+impl<'a, R: Read> Read for RecordingReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let got = self.reader.read(buf)?;
+        self.recorded.extend_from_slice(&buf[..got]);
+        Ok(got)
+    }
+}
@@ -0,0 +1,51 @@
+//! Loading of memory-mapped files.
+
+use crate::{load_borrowed, Deserialize, DeserializeBorrowed, Deserializer, SavefileError, WithSchema};
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+/// A file mapped into memory, from which values can be loaded without reading the
+/// whole file first. Values loaded by [MappedFile::load] can borrow strings and slices
+/// directly from the mapped file, see [crate::DeserializeBorrowed]. Only the parts of the
+/// file which are actually accessed are read from disk, so even very large files open
+/// almost instantly.
+///
+/// The file must not be modified while it is mapped, neither by this process nor by any
+/// other. Doing so makes the mapped memory change underneath the loaded values.
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    /// Map the given file into memory
+    pub fn open(filepath: impl AsRef<Path>) -> Result<MappedFile, SavefileError> {
+        let file = File::open(filepath)?;
+        // Safety: The caller is responsible for not modifying the file, as documented above
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(MappedFile { map })
+    }
+
+    /// The contents of the file
+    pub fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Load the value in the file, like [crate::load_borrowed]. Strings and slices in the
+    /// value can borrow from the mapped file. The file must not be compressed or encrypted.
+    /// The current version of T in memory must be `version`.
+    pub fn load<'a, T: WithSchema + DeserializeBorrowed<'a>>(&'a self, version: u32) -> Result<T, SavefileError> {
+        load_borrowed(&self.map, version)
+    }
+}
+
+/// Like [crate::load_file], except the file is mapped into memory instead of being read
+/// through a buffer. To load values which borrow from the file, use [MappedFile] instead.
+/// The current version of T in memory must be `version`.
+pub fn load_file_mmap<T: WithSchema + Deserialize>(
+    filepath: impl AsRef<Path>,
+    version: u32,
+) -> Result<T, SavefileError> {
+    let mapped = MappedFile::open(filepath)?;
+    Deserializer::load::<T>(&mut mapped.bytes(), version)
+}
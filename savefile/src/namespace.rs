@@ -0,0 +1,330 @@
+//! Versions of groups of types which evolve independently of the files they are saved in,
+//! such as the types of a library. See [VersionNamespace].
+
+use crate::{
+    Deserialize, Deserializer, SavefileError, SavefileHeader, Serialize, Serializer, NAMESPACES_FORMAT_VERSION,
+    SAVEFILE_FORMAT_VERSION,
+};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// A version number shared by a group of types, such as the types of a library, which is
+/// separate from the version given when saving and loading. This lets a library change its
+/// types without the applications using them having to change their versions, and lets
+/// applications change their versions without affecting the library.
+///
+/// Types are placed in a namespace with the `savefile_namespace` attribute, naming a
+/// constant of this type. The `savefile_versions` attributes of their fields, and those of
+/// any types within them which are not in a namespace of their own, then refer to the
+/// version of the namespace. Files record the versions of the namespaces of the types they
+/// contain, and when loading, each namespace gets the version it had when the file was
+/// saved. Files which don't record a namespace, such as files saved before its types were
+/// placed in it, are loaded with version 0 of it.
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate savefile_derive;
+/// use savefile::prelude::*;
+///
+/// // In the library
+/// pub const GEOMETRY: VersionNamespace = VersionNamespace::new("geometry", 1);
+///
+/// #[derive(Savefile, Debug, PartialEq)]
+/// #[savefile_namespace = "GEOMETRY"]
+/// pub struct Point {
+///     x: f32,
+///     y: f32,
+///     #[savefile_versions = "1.."]
+///     z: f32,
+/// }
+///
+/// // In the application
+/// #[derive(Savefile, Debug, PartialEq)]
+/// struct Drawing {
+///     name: String,
+///     points: Vec<Point>,
+/// }
+///
+/// # fn main() {
+/// let drawing = Drawing {
+///     name: "line".to_string(),
+///     points: vec![Point { x: 1.0, y: 2.0, z: 3.0 }],
+/// };
+/// let saved = save_to_mem(5, &drawing).unwrap();
+/// let loaded: Drawing = load_from_mem(&saved, 5).unwrap();
+/// assert_eq!(loaded, drawing);
+/// # }
+/// ```
+///
+/// The namespaces are recorded by the save functions, [crate::SaveArchive] and
+/// [crate::VecWriter]. Files containing namespaces can't be loaded by versions of savefile
+/// older than 0.9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionNamespace {
+    name: &'static str,
+    version: u32,
+}
+
+/// The versions of the namespaces recorded in a file, by name
+pub(crate) type NamespaceVersions = Arc<BTreeMap<String, u32>>;
+
+/// The namespaces used, with the version each was used in
+pub(crate) type UsedNamespaces = Vec<(VersionNamespace, u32)>;
+
+#[derive(Default)]
+struct NamespaceState {
+    /// The versions of the namespaces in the file being loaded, or None when not loading
+    loading: Option<NamespaceVersions>,
+    /// For each schema being built, the namespaces it has used so far
+    recording: Vec<UsedNamespaces>,
+}
+
+impl NamespaceState {
+    fn version_of(&self, namespace: &VersionNamespace) -> u32 {
+        match self.loading {
+            Some(ref versions) => versions.get(namespace.name).copied().unwrap_or(0),
+            None => namespace.version,
+        }
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<NamespaceState> = RefCell::new(NamespaceState::default());
+}
+
+impl VersionNamespace {
+    /// A namespace with the given name, and the current version of its types. The name is
+    /// saved in files, so it must not change, and it must not be used by any other namespace.
+    pub const fn new(name: &'static str, version: u32) -> VersionNamespace {
+        VersionNamespace { name, version }
+    }
+
+    /// The name of the namespace
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The current version of the types in the namespace, in which they are saved
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The version the types in the namespace have right now: while a file is being loaded,
+    /// this is the version of the namespace in the file, otherwise it is the current version.
+    /// Implementations of [crate::WithSchema] and [crate::Deserialize] for types in the
+    /// namespace use this, instead of the version they are given.
+    pub fn active_version(&self) -> u32 {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let version = state.version_of(self);
+            if let Some(used) = state.recording.last_mut() {
+                if !used.contains(&(*self, version)) {
+                    used.push((*self, version));
+                }
+            }
+            version
+        })
+    }
+}
+
+/// Serialize a value of a type in the namespace, with the version of the serializer set to
+/// the current version of the namespace. Used by derived implementations of Serialize.
+#[doc(hidden)]
+pub fn serialize_in_namespace<'a, W: Write>(
+    namespace: &VersionNamespace,
+    serializer: &mut Serializer<'a, W>,
+    serialize: impl FnOnce(&mut Serializer<'a, W>) -> Result<(), SavefileError>,
+) -> Result<(), SavefileError> {
+    let outer_version = std::mem::replace(&mut serializer.version, namespace.version);
+    let result = serialize(serializer);
+    serializer.version = outer_version;
+    result
+}
+
+/// Deserialize a value of a type in the namespace, with the versions of the deserializer set
+/// to those of the namespace. Used by derived implementations of Deserialize.
+#[doc(hidden)]
+pub fn deserialize_in_namespace<'a, R: Read, T>(
+    namespace: &VersionNamespace,
+    deserializer: &mut Deserializer<'a, R>,
+    deserialize: impl FnOnce(&mut Deserializer<'a, R>) -> Result<T, SavefileError>,
+) -> Result<T, SavefileError> {
+    let file_version = namespace.active_version();
+    check_version(namespace, file_version)?;
+    let outer_versions = (deserializer.file_version, deserializer.memory_version);
+    deserializer.file_version = file_version;
+    deserializer.memory_version = namespace.version;
+    let result = deserialize(deserializer);
+    deserializer.file_version = outer_versions.0;
+    deserializer.memory_version = outer_versions.1;
+    result
+}
+
+fn check_version(namespace: &VersionNamespace, file_version: u32) -> Result<(), SavefileError> {
+    if file_version > namespace.version {
+        return Err(SavefileError::WrongVersion {
+            msg: format!(
+                "File has later version ({}) of namespace {} than structs in memory ({}).",
+                file_version, namespace.name, namespace.version
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Check that the file doesn't have later versions of the namespaces used by the schema built
+/// by `build` than those in memory
+pub(crate) fn check_file_versions<S>(
+    versions: &NamespaceVersions,
+    build: impl FnOnce() -> S,
+) -> Result<(), SavefileError> {
+    if versions.is_empty() {
+        return Ok(());
+    }
+    let (_, used) = with_file_versions(Some(versions.clone()), || recording_used(build));
+    for (namespace, file_version) in used {
+        check_version(&namespace, file_version)?;
+    }
+    Ok(())
+}
+
+/// Restores the versions of the file being loaded before [with_file_versions], also on panic
+struct LoadingGuard(Option<NamespaceVersions>);
+
+impl Drop for LoadingGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        STATE.with(|state| state.borrow_mut().loading = previous);
+    }
+}
+
+/// Run `load` with the versions of the namespaces in the file being loaded. With None, the
+/// current versions of the namespaces are used, as when saving.
+pub(crate) fn with_file_versions<R>(versions: Option<NamespaceVersions>, load: impl FnOnce() -> R) -> R {
+    let previous = STATE.with(|state| std::mem::replace(&mut state.borrow_mut().loading, versions));
+    let _guard = LoadingGuard(previous);
+    load()
+}
+
+/// The versions of the namespaces in the file being loaded, if any
+pub(crate) fn file_versions() -> Option<NamespaceVersions> {
+    STATE.with(|state| state.borrow().loading.clone())
+}
+
+/// Removes the frame pushed by [recording_used], if building the schema panics
+struct RecordingGuard;
+
+impl Drop for RecordingGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            STATE.with(|state| state.borrow_mut().recording.pop());
+        }
+    }
+}
+
+/// Build a schema, returning the namespaces used by it. They are also recorded as used by
+/// the schema being built around it, if any.
+pub(crate) fn recording_used<S>(build: impl FnOnce() -> S) -> (S, UsedNamespaces) {
+    STATE.with(|state| state.borrow_mut().recording.push(Vec::new()));
+    let guard = RecordingGuard;
+    let schema = build();
+    std::mem::forget(guard);
+    let used = STATE
+        .with(|state| state.borrow_mut().recording.pop())
+        .unwrap_or_default();
+    record_used(&used);
+    (schema, used)
+}
+
+/// Record namespaces as used by the schema being built, when a cached schema using them
+/// is reused
+pub(crate) fn record_used(used: &[(VersionNamespace, u32)]) {
+    if used.is_empty() {
+        return;
+    }
+    STATE.with(|state| {
+        if let Some(recording) = state.borrow_mut().recording.last_mut() {
+            for namespace in used {
+                if !recording.contains(namespace) {
+                    recording.push(*namespace);
+                }
+            }
+        }
+    });
+}
+
+/// True if the namespaces have the same versions now as when they were used
+pub(crate) fn same_versions(used: &[(VersionNamespace, u32)]) -> bool {
+    used.is_empty()
+        || STATE.with(|state| {
+            let state = state.borrow();
+            used.iter()
+                .all(|(namespace, version)| state.version_of(namespace) == *version)
+        })
+}
+
+/// True if the schema built by `build` uses the same versions of namespaces in the file the
+/// versions are from, as it does now. Data loaded from such a file can then be saved as is.
+pub(crate) fn versions_unchanged<S>(versions: &Option<NamespaceVersions>, build: impl FnOnce() -> S) -> bool {
+    match versions {
+        None => true,
+        Some(versions) => {
+            let (_, used) = with_file_versions(Some(versions.clone()), || recording_used(build));
+            with_file_versions(None, || same_versions(&used))
+        }
+    }
+}
+
+/// The names and current versions of the namespaces used by the schema built by `build`,
+/// which are recorded in files saved with that schema
+pub(crate) fn namespaces_of<S>(build: impl FnOnce() -> S) -> Result<Vec<(String, u32)>, SavefileError> {
+    let (_, used) = with_file_versions(None, || recording_used(build));
+    let mut versions: BTreeMap<String, u32> = BTreeMap::new();
+    for (namespace, version) in used {
+        match versions.insert(namespace.name.to_string(), version) {
+            Some(other) if other != version => {
+                return Err(SavefileError::GeneralError {
+                    msg: format!(
+                        "The version namespace {} has two different versions, {} and {}",
+                        namespace.name, other, version
+                    ),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(versions.into_iter().collect())
+}
+
+/// The format version of a file recording the given namespaces
+pub(crate) fn format_version(namespaces: &[(String, u32)]) -> u16 {
+    if namespaces.is_empty() {
+        SAVEFILE_FORMAT_VERSION
+    } else {
+        NAMESPACES_FORMAT_VERSION
+    }
+}
+
+/// Write the versions of the namespaces, at the start of the payload of a file with
+/// [NAMESPACES_FORMAT_VERSION]. They are always written with the default encoding.
+pub(crate) fn write_namespace_versions(
+    writer: &mut impl Write,
+    namespaces: &[(String, u32)],
+) -> Result<(), SavefileError> {
+    namespaces.to_vec().serialize(&mut Serializer::new_raw(writer))
+}
+
+/// Read the versions of the namespaces at the start of the payload, if the format of the
+/// file has them
+pub(crate) fn read_namespace_versions(
+    reader: &mut impl Read,
+    header: &SavefileHeader,
+) -> Result<NamespaceVersions, SavefileError> {
+    if header.format_version < NAMESPACES_FORMAT_VERSION {
+        return Ok(NamespaceVersions::default());
+    }
+    let namespaces = Vec::<(String, u32)>::deserialize(&mut Deserializer::new_raw(reader))?;
+    Ok(Arc::new(namespaces.into_iter().collect()))
+}
@@ -0,0 +1,195 @@
+//! Vectors of bools saved with one bit per bool, instead of one byte.
+
+use crate::{
+    schema_of, CurrentVersion, Deserialize, Deserializer, Field, Introspect, IntrospectItem, SavefileError, Schema, SchemaStruct,
+    Serialize, Serializer, WithSchema,
+};
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+
+/// A vector of bools, `Vec<bool>` or `Box<[bool]>`, which is saved with 8 bools per byte, making
+/// it 8 times smaller than when saved as a `Vec<bool>`. The bools are stored from the least
+/// significant bit of each byte, like fields marked `#[savefile_packed]`. A `PackedBools<&[bool]>`
+/// can be saved too, for saving a slice without copying it.
+///
+/// The vector is used through [Deref] and [DerefMut]. In the schema, it is a struct with the
+/// number of bools `len` and the vector of bytes `bits`, so it doesn't have the schema of a
+/// `Vec<bool>`. Existing files can still be loaded, by changing the type of a field from
+/// `Vec<bool>` to `PackedBools<Vec<bool>>` in a new version:
+///
+/// ```
+/// # #[macro_use]
+/// # extern crate savefile_derive;
+/// use savefile::prelude::*;
+///
+/// #[derive(Savefile)]
+/// struct Level {
+///     #[savefile_versions_as = "0..0:Vec<bool>"]
+///     #[savefile_versions = "1.."]
+///     walls: PackedBools<Vec<bool>>,
+/// }
+///
+/// # fn main() {
+/// let walls: Vec<bool> = (0..1000).map(|i| i % 3 == 0).collect();
+/// let saved = save_to_mem(1, &Level { walls: PackedBools(walls.clone()) }).unwrap();
+/// let loaded: Level = load_from_mem(&saved, 1).unwrap();
+/// assert_eq!(*loaded.walls, walls);
+/// # }
+/// ```
+///
+/// Loading fails if the number of bytes doesn't match the number of bools, or if the unused
+/// bits of the last byte are not zero.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PackedBools<V>(pub V);
+
+impl<V> PackedBools<V> {
+    /// The vector
+    pub fn into_inner(self) -> V {
+        self.0
+    }
+}
+
+impl<V> Deref for PackedBools<V> {
+    type Target = V;
+    fn deref(&self) -> &V {
+        &self.0
+    }
+}
+
+impl<V> DerefMut for PackedBools<V> {
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.0
+    }
+}
+
+impl From<Vec<bool>> for PackedBools<Vec<bool>> {
+    fn from(bools: Vec<bool>) -> Self {
+        PackedBools(bools)
+    }
+}
+
+impl From<Box<[bool]>> for PackedBools<Box<[bool]>> {
+    fn from(bools: Box<[bool]>) -> Self {
+        PackedBools(bools)
+    }
+}
+
+fn packed_bools_schema(version: u32) -> Schema {
+    Schema::Struct(SchemaStruct {
+        dbg_name: "PackedBools".to_string(),
+        fields: vec![
+            Field {
+                name: "len".to_string(),
+                value: schema_of::<u64>(version),
+            },
+            Field {
+                name: "bits".to_string(),
+                value: schema_of::<Vec<u8>>(version),
+            },
+        ],
+    })
+}
+
+fn serialize_bools(bools: &[bool], serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+    serialize_bits(bools.len(), bools.iter().copied(), serializer)
+}
+
+/// Serialize `len` bools like a [PackedBools] containing them
+pub(crate) fn serialize_bits(
+    len: usize,
+    bools: impl Iterator<Item = bool>,
+    serializer: &mut Serializer<impl Write>,
+) -> Result<(), SavefileError> {
+    serializer.write_u64(len as u64)?;
+    let mut bits = vec![0u8; len.div_ceil(8)];
+    for (index, value) in bools.enumerate() {
+        bits[index / 8] |= (value as u8) << (index % 8);
+    }
+    bits.serialize(serializer)
+}
+
+/// Deserialize the bools of a [PackedBools]
+pub(crate) fn deserialize_bools(deserializer: &mut Deserializer<impl Read>) -> Result<Vec<bool>, SavefileError> {
+    let len = deserializer.read_u64()?;
+    let bits = Vec::<u8>::deserialize(deserializer).map_err(|err| err.with_path_segment("bits"))?;
+    if len.div_ceil(8) != bits.len() as u64 {
+        return Err(SavefileError::GeneralError {
+            msg: format!("{} bools can't be stored in {} bytes", len, bits.len()),
+        });
+    }
+    if len % 8 != 0 && bits[bits.len() - 1] >> (len % 8) != 0 {
+        return Err(SavefileError::GeneralError {
+            msg: "The unused bits of the last byte of the bools are not zero".into(),
+        });
+    }
+    let len = usize::try_from(len).map_err(|_| SavefileError::SizeOverflow)?;
+    deserializer.account_items::<bool>(len)?;
+    Ok((0..len)
+        .map(|index| bits[index / 8] & (1 << (index % 8)) != 0)
+        .collect())
+}
+
+impl WithSchema for PackedBools<Vec<bool>> {
+    fn schema(version: u32) -> Schema {
+        packed_bools_schema(version)
+    }
+}
+
+impl Serialize for PackedBools<Vec<bool>> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_bools(&self.0, serializer)
+    }
+}
+
+impl Deserialize for PackedBools<Vec<bool>> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        Ok(PackedBools(deserialize_bools(deserializer)?))
+    }
+}
+
+impl WithSchema for PackedBools<Box<[bool]>> {
+    fn schema(version: u32) -> Schema {
+        packed_bools_schema(version)
+    }
+}
+
+impl Serialize for PackedBools<Box<[bool]>> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_bools(&self.0, serializer)
+    }
+}
+
+impl Deserialize for PackedBools<Box<[bool]>> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        Ok(PackedBools(deserialize_bools(deserializer)?.into_boxed_slice()))
+    }
+}
+
+impl WithSchema for PackedBools<&[bool]> {
+    fn schema(version: u32) -> Schema {
+        packed_bools_schema(version)
+    }
+}
+
+impl Serialize for PackedBools<&[bool]> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_bools(self.0, serializer)
+    }
+}
+
+impl<V: Introspect> Introspect for PackedBools<V> {
+    fn introspect_value(&self) -> String {
+        self.0.introspect_value()
+    }
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        self.0.introspect_child(index)
+    }
+    fn introspect_len(&self) -> usize {
+        self.0.introspect_len()
+    }
+}
+
+impl<V: CurrentVersion> CurrentVersion for PackedBools<V> {
+    const SAVEFILE_MAX_VERSION: u32 = V::SAVEFILE_MAX_VERSION;
+}
@@ -0,0 +1,117 @@
+//! Vectors of options saved as a bitmap of which items are present, followed by the present values.
+
+use crate::dense_map::serialize_run;
+use crate::packed_bools::{deserialize_bools, serialize_bits};
+use crate::{
+    schema_of, CurrentVersion, Deserialize, Deserializer, Field, Introspect, IntrospectItem, PackedBools, SavefileError, Schema,
+    SchemaStruct, Serialize, Serializer, WithSchema,
+};
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+
+/// A vector of options, `Vec<Option<T>>`, which is saved as one bit per item, telling if it is
+/// present, followed by a vector of the present values. Compared to a `Vec<Option<T>>`, which
+/// has a byte in front of each value, this is much smaller for sparse vectors, such as columns
+/// of attributes which most rows don't have. When T is [crate::ReprC], the values are written
+/// and read in large chunks.
+///
+/// The vector is used through [Deref] and [DerefMut]. In the schema, it is a struct with the
+/// [PackedBools] `present`, and the vector `values`. Loading fails if the number of values
+/// differs from the number of items which are present. Fields can be changed from a
+/// `Vec<Option<T>>` to a `PackedOptions<Vec<Option<T>>>` in a new version, using
+/// `savefile_versions_as`, like for [PackedBools].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PackedOptions<V>(pub V);
+
+impl<V> PackedOptions<V> {
+    /// The vector
+    pub fn into_inner(self) -> V {
+        self.0
+    }
+}
+
+impl<V> Deref for PackedOptions<V> {
+    type Target = V;
+    fn deref(&self) -> &V {
+        &self.0
+    }
+}
+
+impl<V> DerefMut for PackedOptions<V> {
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.0
+    }
+}
+
+impl<T> From<Vec<Option<T>>> for PackedOptions<Vec<Option<T>>> {
+    fn from(options: Vec<Option<T>>) -> Self {
+        PackedOptions(options)
+    }
+}
+
+impl<T: WithSchema> WithSchema for PackedOptions<Vec<Option<T>>> {
+    fn schema(version: u32) -> Schema {
+        Schema::Struct(SchemaStruct {
+            dbg_name: "PackedOptions".to_string(),
+            fields: vec![
+                Field {
+                    name: "present".to_string(),
+                    value: schema_of::<PackedBools<Vec<bool>>>(version),
+                },
+                Field {
+                    name: "values".to_string(),
+                    value: schema_of::<Vec<T>>(version),
+                },
+            ],
+        })
+    }
+}
+
+impl<T: Serialize> Serialize for PackedOptions<Vec<Option<T>>> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_bits(self.0.len(), self.0.iter().map(Option::is_some), serializer)?;
+        let present = self.0.iter().filter(|option| option.is_some()).count();
+        serialize_run(present, self.0.iter().flatten(), serializer)
+    }
+}
+
+impl<T: Deserialize> Deserialize for PackedOptions<Vec<Option<T>>> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let present = deserialize_bools(deserializer).map_err(|err| err.with_path_segment("present"))?;
+        let values = Vec::<T>::deserialize(deserializer).map_err(|err| err.with_path_segment("values"))?;
+        let present_count = present.iter().filter(|present| **present).count();
+        if present_count != values.len() {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "{} items are present, but there are {} values",
+                    present_count,
+                    values.len()
+                ),
+            });
+        }
+        deserializer.account_items::<Option<T>>(present.len())?;
+        let mut values = values.into_iter();
+        Ok(PackedOptions(
+            present
+                .into_iter()
+                .map(|present| if present { values.next() } else { None })
+                .collect(),
+        ))
+    }
+}
+
+impl<V: Introspect> Introspect for PackedOptions<V> {
+    fn introspect_value(&self) -> String {
+        self.0.introspect_value()
+    }
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        self.0.introspect_child(index)
+    }
+    fn introspect_len(&self) -> usize {
+        self.0.introspect_len()
+    }
+}
+
+impl<V: CurrentVersion> CurrentVersion for PackedOptions<V> {
+    const SAVEFILE_MAX_VERSION: u32 = V::SAVEFILE_MAX_VERSION;
+}
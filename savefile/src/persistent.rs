@@ -0,0 +1,163 @@
+//! Persistence of application state as a snapshot plus a journal of the commands
+//! executed since the snapshot was taken.
+
+use crate::{
+    load_file, save_file_atomic, Deserialize, SaveLog, SavefileError, Schema, Serialize, Serializer, WithSchema,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A command which modifies state of type T, which is journaled by [Persistent].
+/// Applying a command must be deterministic, since the journaled commands are
+/// applied again to the snapshot when the state is loaded.
+pub trait PersistentCommand<T>: WithSchema + Serialize + Deserialize {
+    /// Apply the command to the state
+    fn apply(&self, state: &mut T);
+}
+
+/// Number of journaled commands after which [Persistent] compacts by default
+const DEFAULT_COMPACT_AFTER: u64 = 1000;
+
+/// The snapshot file contains the number of commands applied to the state, followed by the state.
+/// This makes it possible to skip commands in the journal which are already part of the snapshot,
+/// if the application crashed after writing a snapshot, but before truncating the journal.
+struct Snapshot<'a, T> {
+    sequence: u64,
+    state: &'a T,
+}
+
+impl<'a, T: WithSchema> WithSchema for Snapshot<'a, T> {
+    fn schema(version: u32) -> Schema {
+        Schema::new_tuple2::<u64, T>(version)
+    }
+}
+
+impl<'a, T: Serialize> Serialize for Snapshot<'a, T> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        self.sequence.serialize(serializer)?;
+        self.state.serialize(serializer)
+    }
+}
+
+/// State of type T, which is persisted as a snapshot file plus an append-only journal
+/// of the commands of type C executed since the snapshot was saved.
+///
+/// Executing a command only appends it to the journal, which is much cheaper than saving
+/// the whole state. Every so often, the journal is compacted, by saving a new snapshot
+/// and emptying the journal. The journal is stored next to the snapshot, with `.journal`
+/// appended to the file name. Both are saved with the same version.
+pub struct Persistent<T, C> {
+    state: T,
+    version: u32,
+    snapshot_path: PathBuf,
+    journal_path: PathBuf,
+    journal: SaveLog<(u64, C)>,
+    sequence: u64,
+    journaled: u64,
+    compact_after: u64,
+}
+
+impl<T: WithSchema + Serialize + Deserialize, C: PersistentCommand<T>> Persistent<T, C> {
+    /// Load the state persisted at the given path, by loading the snapshot and applying the
+    /// journaled commands to it. If there is no snapshot, the journaled commands are applied
+    /// to the state returned by `initial`. The current version of T and C must be `version`.
+    /// If any commands were journaled, a new snapshot is saved right away.
+    pub fn open(
+        path: impl AsRef<Path>,
+        version: u32,
+        initial: impl FnOnce() -> T,
+    ) -> Result<Persistent<T, C>, SavefileError> {
+        let snapshot_path = path.as_ref().to_path_buf();
+        let mut journal_path = snapshot_path.clone().into_os_string();
+        journal_path.push(".journal");
+        let journal_path = PathBuf::from(journal_path);
+
+        let (mut sequence, mut state) = if snapshot_path.exists() {
+            load_file::<(u64, T)>(&snapshot_path, version)?
+        } else {
+            (0, initial())
+        };
+        let mut replayed = 0;
+        if journal_path.exists() {
+            for record in SaveLog::<(u64, C)>::read(&journal_path, version)? {
+                let (command_sequence, command) = record?;
+                if command_sequence < sequence {
+                    continue;
+                }
+                command.apply(&mut state);
+                sequence = command_sequence + 1;
+                replayed += 1;
+            }
+        }
+        let journal = if replayed > 0 {
+            // Compact right away, so the journal can be appended to even if it had an older version
+            let snapshot = Snapshot {
+                sequence,
+                state: &state,
+            };
+            save_file_atomic(&snapshot_path, version, &snapshot)?;
+            SaveLog::create(&journal_path, version)?
+        } else {
+            match SaveLog::open(&journal_path, version) {
+                Ok(journal) => journal,
+                // The journal only has commands which are already part of the snapshot
+                Err(SavefileError::WrongVersion { .. }) => SaveLog::create(&journal_path, version)?,
+                Err(err) => return Err(err),
+            }
+        };
+        Ok(Persistent {
+            state,
+            version,
+            snapshot_path,
+            journal_path,
+            journal,
+            sequence,
+            journaled: 0,
+            compact_after: DEFAULT_COMPACT_AFTER,
+        })
+    }
+
+    /// The current state
+    pub fn state(&self) -> &T {
+        &self.state
+    }
+
+    /// Set the number of journaled commands after which the journal is compacted.
+    /// The default is 1000.
+    pub fn set_compact_after(&mut self, commands: u64) {
+        self.compact_after = commands.max(1);
+    }
+
+    /// Journal the command, and then apply it to the state.
+    /// Compacts the journal if it has become long enough.
+    pub fn execute(&mut self, command: C) -> Result<(), SavefileError> {
+        let record = (self.sequence, command);
+        self.journal.append(&record)?;
+        record.1.apply(&mut self.state);
+        self.sequence += 1;
+        self.journaled += 1;
+        if self.journaled >= self.compact_after {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Save a snapshot of the current state and empty the journal.
+    /// Both are replaced atomically, so a crash while compacting leaves a snapshot and a
+    /// journal which can be opened, from before or after compacting.
+    pub fn compact(&mut self) -> Result<(), SavefileError> {
+        let snapshot = Snapshot {
+            sequence: self.sequence,
+            state: &self.state,
+        };
+        save_file_atomic(&self.snapshot_path, self.version, &snapshot)?;
+        self.journal = SaveLog::create(&self.journal_path, self.version)?;
+        self.journaled = 0;
+        Ok(())
+    }
+
+    /// Make sure all journaled commands have reached the storage device.
+    pub fn sync(&self) -> Result<(), SavefileError> {
+        self.journal.sync()
+    }
+}
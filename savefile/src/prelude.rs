@@ -1,7 +1,30 @@
 pub use {
-    super::introspect_item, super::load, super::load_encrypted_file, super::load_file, super::load_file_noschema, super::load_from_mem, super::load_noschema, super::save,
-    super::save_encrypted_file, super::save_file, super::save_file_noschema, super::save_noschema, super::save_to_mem, super::Canary1, super::CryptoReader,
-    super::CryptoWriter, super::Deserialize, super::Deserializer, super::Field, super::Introspect, super::IntrospectItem, super::IntrospectedElementKey,
-    super::IntrospectionResult, super::Introspector, super::IntrospectorNavCommand, super::Removed, super::ReprC, super::SavefileError, super::Schema, super::SchemaEnum,
-    super::SchemaPrimitive, super::SchemaStruct, super::Serialize, super::Serializer, super::Variant, super::WithSchema,
+    super::apply_delta, super::deserialize_seq, super::diff_serialized, super::diff_values, super::from_json, super::fuzz_corpus, super::hash_value, super::introspect_item, super::load, super::load_borrowed, super::load_current, super::load_exact, super::load_from_mem, super::load_unchecked_utf8, super::load_with_context, super::load_with_memory_budget, super::load_with_external_schema, super::load_with_progress, super::load_with_registry, super::load_from_slice, super::load_in_place, super::load_into_map, super::load_noschema, super::load_noschema_unverified, super::load_tagged_any, super::mutate_bytes, super::random_value, super::read_header, super::read_schema, super::read_schema_history, super::roundtrip_check, super::save,
+    super::save_checksummed, super::save_compressed, super::save_compressed_with, super::save_current, super::save_delta, super::save_deterministic, super::save_noschema, super::save_tagged, super::save_to_mem, super::save_to_vec, super::save_with_context, super::save_with_progress, super::save_with_registry, super::save_with_encoding, super::save_with_schema_hash, super::save_with_schema_history, super::schema_differences, super::schema_of, super::serialize_iter, super::serialized_size, super::to_json, super::verify_version_compatibility, super::AbiConnection, super::AbiExport, super::AbiMethod, super::AbiProvider, super::ArchiveReader, super::ArchiveWriter, super::BitPacked, super::BorrowedDeserializer, super::CancellationToken, super::Canary1, super::Columns, super::ChunkedVecWriter, super::CompressionMethod, super::CurrentVersion, 
+    super::Deserialize, super::DeserializeBorrowed, super::DeserializeDyn, super::DenseMap, super::Deserializer, super::SeqDeserializer, super::Document, super::DynDeserializer, super::DynSerializer, super::DynValue, super::Encoding, super::Endianness, super::Field, super::HistoryField, super::HistoryNode, super::HistoryVariant, super::Introspect, super::IntrospectItem, super::IntrospectedElementKey,
+    super::IntrospectionResult, super::Introspector, super::IntrospectorNavCommand, super::IsReprC, super::Lazy, super::LengthEncoding, super::MapReader, super::Migrator, super::PackedBits, super::PackedBools, super::PackedOptions, super::PostDeserialize, super::Progress, super::ProgressReport, super::Removed, super::RemovedOpaque, super::ReprC, super::SaveArchive, super::SavefileError, super::SavefileHeader, super::SavefileTraitObject, super::Schema, super::SchemaCache, super::SchemaEnum, super::SchemaHistory, super::SchemaRegistry,
+    super::SchemaDifference, super::SchemaDifferenceKind, super::SchemaPrimitive, super::SchemaStruct, super::SeqSerializer, super::Serialize, super::SerializeDyn, super::Serializer, super::SizeCounter, super::SliceDeserializer, super::SliceReader, super::StringBlock, super::TypeRegistry, super::TypeTags, super::ValueDifference, super::Variant, super::VecReader, super::VecWriter, super::VersionNamespace, super::WithSchema,
 };
+#[cfg(feature = "fs")]
+pub use super::{
+    check_golden, check_schema_stable, load_file, load_file_exact, load_file_noschema, load_file_with_capacity,
+    load_from, load_with_schema_file, save_file, save_file_atomic, save_file_atomic_with_backups, save_file_noschema,
+    save_file_with_capacity, save_to, save_with_schema_file, Autosaver, Persistent, PersistentCommand, SaveLog,
+    SaveLogReader, Saved,
+};
+#[cfg(feature = "encryption")]
+pub use super::{load_encrypted, save_encrypted, CryptoReader, CryptoWriter};
+#[cfg(all(feature = "encryption", feature = "fs"))]
+pub use super::{load_encrypted_file, save_encrypted_file};
+#[cfg(feature = "tokio")]
+pub use super::{load_async, save_async};
+#[cfg(all(feature = "tokio", feature = "fs"))]
+pub use super::{load_file_async, save_file_async};
+#[cfg(feature = "proptest")]
+pub use super::{type_strategy, value_strategy};
+#[cfg(feature = "quickcheck")]
+pub use super::ArbitraryValue;
+#[cfg(feature = "memmap2")]
+pub use super::{load_file_mmap, MappedFile};
+#[cfg(feature = "bitflags")]
+pub use super::UnknownBits;
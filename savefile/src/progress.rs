@@ -0,0 +1,165 @@
+//! Progress reporting and cancellation of long saves and loads.
+
+use crate::SavefileError;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Within a top-level field, progress is reported each time a field is reached after
+/// at least this many bytes have been processed since the last report.
+const REPORT_INTERVAL: u64 = 64 * 1024;
+
+/// The callback of a [Progress]
+type ProgressCallback<'a> = dyn FnMut(ProgressReport) + 'a;
+
+/// Lets a save or load in progress be cancelled, typically from another thread.
+/// Clones of a token share the same state, so a clone can be given to a [Progress],
+/// while the original is kept to cancel it.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// A token which hasn't been cancelled
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel the saves and loads using this token. They fail with [SavefileError::Cancelled]
+    /// when they reach the next field.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// True if [CancellationToken::cancel] has been called
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// How far a save or load has come, given to the callback of a [Progress].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressReport<'a> {
+    /// The number of bytes of data written or read so far, not counting the header and
+    /// schema. For compressed files, this is the size before compression.
+    pub bytes: u64,
+    /// The top-level field being saved or loaded, or empty when the save or load is done.
+    /// Fields of unnamed structs are given by their index.
+    pub field: &'a str,
+}
+
+/// Progress reporting and cancellation, for [crate::save_with_progress] and
+/// [crate::load_with_progress].
+///
+/// The callback is called each time a field of the saved or loaded value is reached,
+/// and then at least every 64 KiB inside the field. It is called one last time with an
+/// empty field, when everything has been saved or loaded. The cancellation token is
+/// checked at every field of every type deriving Savefile.
+#[derive(Default)]
+pub struct Progress<'a> {
+    callback: Option<Box<ProgressCallback<'a>>>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl<'a> Progress<'a> {
+    /// Progress without a callback or cancellation token
+    pub fn new() -> Progress<'a> {
+        Progress::default()
+    }
+
+    /// Call the given function with the progress, as it is made
+    pub fn with_callback(self, callback: impl FnMut(ProgressReport) + 'a) -> Progress<'a> {
+        Progress {
+            callback: Some(Box::new(callback)),
+            ..self
+        }
+    }
+
+    /// Fail with [SavefileError::Cancelled] once the token has been cancelled
+    pub fn with_cancellation(self, token: CancellationToken) -> Progress<'a> {
+        Progress {
+            cancellation: Some(token),
+            ..self
+        }
+    }
+
+    pub(crate) fn hooks(&mut self) -> ProgressHooks<'_> {
+        ProgressHooks {
+            callback: match self.callback {
+                Some(ref mut callback) => Some(&mut **callback),
+                None => None,
+            },
+            cancellation: self.cancellation.as_ref(),
+        }
+    }
+}
+
+/// The parts of a [Progress] used by one save or load
+pub(crate) struct ProgressHooks<'a> {
+    callback: Option<&'a mut ProgressCallback<'a>>,
+    cancellation: Option<&'a CancellationToken>,
+}
+
+/// Keeps track of the fields reached by a [crate::Serializer] or [crate::Deserializer],
+/// and calls the hooks. The bytes are counted by the writer or reader.
+pub(crate) struct ProgressTracker<'a> {
+    callback: Option<&'a mut ProgressCallback<'a>>,
+    cancellation: Option<&'a CancellationToken>,
+    bytes: &'a Cell<u64>,
+    /// The number of fields which have been entered, but not left
+    depth: usize,
+    /// The top-level field entered last
+    field: &'static str,
+    /// The bytes at the last report
+    reported: u64,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub(crate) fn new<'h: 'a>(hooks: ProgressHooks<'h>, bytes: &'a Cell<u64>) -> ProgressTracker<'a> {
+        ProgressTracker {
+            callback: match hooks.callback {
+                Some(callback) => Some(callback),
+                None => None,
+            },
+            cancellation: hooks.cancellation,
+            bytes,
+            depth: 0,
+            field: "",
+            reported: 0,
+        }
+    }
+
+    fn report(&mut self, field: &str) {
+        self.reported = self.bytes.get();
+        if let Some(callback) = self.callback.as_mut() {
+            callback(ProgressReport {
+                bytes: self.reported,
+                field,
+            });
+        }
+    }
+
+    pub(crate) fn enter_field(&mut self, name: &'static str) -> Result<(), SavefileError> {
+        if self.cancellation.is_some_and(CancellationToken::is_cancelled) {
+            return Err(SavefileError::Cancelled);
+        }
+        self.depth += 1;
+        if self.depth == 1 {
+            self.field = name;
+            self.report(name);
+        } else if self.bytes.get() - self.reported >= REPORT_INTERVAL {
+            self.report(self.field);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn leave_field(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    /// Report that everything has been saved or loaded
+    pub(crate) fn finish(&mut self) {
+        self.report("");
+    }
+}
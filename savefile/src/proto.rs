@@ -0,0 +1,346 @@
+//! Export of schemas as protocol buffer definitions, and of values in the protocol buffer
+//! encoding, for consumers using protobuf tooling.
+
+use crate::{DynValue, Field, SavefileError, Schema, SchemaPrimitive, SchemaStruct};
+use indexmap::IndexMap;
+use std::collections::HashMap;
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LEN: u64 = 2;
+const WIRE_FIXED32: u64 = 5;
+
+/// A valid protobuf identifier for a rust name. Characters which can't be used are
+/// replaced by '_', and names not starting with a letter get the prefix.
+fn identifier(name: &str, prefix: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("{}{}", prefix, name)
+    }
+}
+
+fn primitive_type(primitive: SchemaPrimitive) -> &'static str {
+    match primitive {
+        SchemaPrimitive::schema_i8 | SchemaPrimitive::schema_i16 | SchemaPrimitive::schema_i32 => "sint32",
+        SchemaPrimitive::schema_u8
+        | SchemaPrimitive::schema_u16
+        | SchemaPrimitive::schema_u32
+        | SchemaPrimitive::schema_canary1 => "uint32",
+        SchemaPrimitive::schema_i64 => "sint64",
+        SchemaPrimitive::schema_u64 => "uint64",
+        SchemaPrimitive::schema_string => "string",
+        SchemaPrimitive::schema_f32 => "float",
+        SchemaPrimitive::schema_f64 => "double",
+        SchemaPrimitive::schema_bool => "bool",
+    }
+}
+
+/// True if the root schema is represented by a message of its own, instead of
+/// the field `value` of a message called Root.
+fn is_message(schema: &Schema) -> bool {
+    match schema {
+        Schema::Struct(_) | Schema::Enum(_) => true,
+        Schema::BitPacked(item) => is_message(item),
+        _ => false,
+    }
+}
+
+fn undefined_error() -> SavefileError {
+    SavefileError::GeneralError {
+        msg: "Undefined schemas can't be represented in protocol buffers".into(),
+    }
+}
+
+struct ProtoBuilder {
+    /// Definitions of the messages, in the order they were created
+    messages: Vec<String>,
+    /// The schema each message name was defined for, so that each schema is only defined once,
+    /// and different schemas of the same name get different names
+    names: HashMap<String, Schema>,
+}
+
+impl ProtoBuilder {
+    /// The name of the message representing `schema`, defining it if needed
+    fn message(
+        &mut self,
+        wanted: &str,
+        schema: &Schema,
+        define: impl FnOnce(&mut ProtoBuilder, &str) -> Result<String, SavefileError>,
+    ) -> Result<String, SavefileError> {
+        let wanted = identifier(wanted, "T");
+        let mut name = wanted.clone();
+        let mut suffix = 1;
+        while let Some(existing) = self.names.get(&name) {
+            if existing == schema {
+                return Ok(name);
+            }
+            suffix += 1;
+            name = format!("{}{}", wanted, suffix);
+        }
+        self.names.insert(name.clone(), schema.clone());
+        let definition = define(self, &name)?;
+        self.messages.push(definition);
+        Ok(name)
+    }
+
+    fn struct_message(&mut self, name: &str, fields: &[Field]) -> Result<String, SavefileError> {
+        let mut definition = format!("message {} {{\n", name);
+        for (index, field) in fields.iter().enumerate() {
+            definition.push_str(&self.field_line(&field.value, &identifier(&field.name, "field_"), index + 1, name)?);
+        }
+        definition.push_str("}\n");
+        Ok(definition)
+    }
+
+    /// The declaration of a field of the message `message`
+    fn field_line(
+        &mut self,
+        schema: &Schema,
+        name: &str,
+        number: usize,
+        message: &str,
+    ) -> Result<String, SavefileError> {
+        let (label, item) = match schema {
+            Schema::Vector(item) => ("repeated ", &**item),
+            Schema::Array(array) => ("repeated ", &*array.item_type),
+            Schema::SchemaOption(item) => ("optional ", &**item),
+            Schema::BitPacked(item) => return self.field_line(item, name, number, message),
+            _ => ("", schema),
+        };
+        let item_type = self.value_type(item, &format!("{}_{}", message, name))?;
+        Ok(format!("  {}{} {} = {};\n", label, item_type, name, number))
+    }
+
+    /// The type of a single value of the schema. Values which can't be a single field,
+    /// such as vectors in vectors, are wrapped in a message named after `context`.
+    fn value_type(&mut self, schema: &Schema, context: &str) -> Result<String, SavefileError> {
+        match schema {
+            Schema::Primitive(primitive) => Ok(primitive_type(*primitive).into()),
+            Schema::Struct(schema_struct) => self.message(&schema_struct.dbg_name, schema, |builder, name| {
+                builder.struct_message(name, &schema_struct.fields)
+            }),
+            Schema::Enum(schema_enum) => self.message(&schema_enum.dbg_name, schema, |builder, name| {
+                let mut definition = format!("message {} {{\n  oneof variant {{\n", name);
+                for (index, variant) in schema_enum.variants.iter().enumerate() {
+                    let variant_schema = Schema::Struct(SchemaStruct {
+                        dbg_name: variant.name.clone(),
+                        fields: variant.fields.clone(),
+                    });
+                    let variant_type = builder.message(
+                        &format!("{}_{}", name, variant.name),
+                        &variant_schema,
+                        |builder, name| builder.struct_message(name, &variant.fields),
+                    )?;
+                    definition.push_str(&format!(
+                        "    {} {} = {};\n",
+                        variant_type,
+                        identifier(&variant.name, "variant_"),
+                        index + 1
+                    ));
+                }
+                definition.push_str("  }\n}\n");
+                Ok(definition)
+            }),
+            Schema::ZeroSize => self.message("Unit", schema, |_, name| Ok(format!("message {} {{\n}}\n", name))),
+            Schema::BitPacked(item) => self.value_type(item, context),
+            Schema::Vector(_) | Schema::Array(_) | Schema::SchemaOption(_) => {
+                self.message(&format!("{}_Value", context), schema, |builder, name| {
+                    Ok(format!(
+                        "message {} {{\n{}}}\n",
+                        name,
+                        builder.field_line(schema, "value", 1, name)?
+                    ))
+                })
+            }
+            Schema::Undefined => Err(undefined_error()),
+        }
+    }
+}
+
+impl Schema {
+    /// Create a protocol buffer definition (proto3) of the messages representing values of
+    /// this schema, in the given package. Values can be encoded as these messages using
+    /// [DynValue::to_protobuf], and read with code generated from the definition.
+    ///
+    /// Structs become messages, and enums messages with a `oneof` holding a message for the
+    /// fields of each variant. Vectors and arrays become repeated fields, and options optional
+    /// fields. Values which can't be the type of a field, such as options of vectors, are wrapped
+    /// in a message with the value as its field `value`. Fields are numbered in order, from 1.
+    /// Signed integers are encoded as `sint32` or `sint64`. If the schema is not a struct or an
+    /// enum, the value is the field `value` of a message called Root. The name of the root
+    /// message is the last message of the definition.
+    pub fn to_proto(&self, package: &str) -> Result<String, SavefileError> {
+        let mut builder = ProtoBuilder {
+            messages: Vec::new(),
+            names: HashMap::new(),
+        };
+        if is_message(self) {
+            builder.value_type(self, "Root")?;
+        } else {
+            builder.message("Root", self, |builder, name| {
+                Ok(format!(
+                    "message {} {{\n{}}}\n",
+                    name,
+                    builder.field_line(self, "value", 1, name)?
+                ))
+            })?;
+        }
+        Ok(format!(
+            "syntax = \"proto3\";\n\npackage {};\n\n{}",
+            package,
+            builder.messages.join("\n")
+        ))
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_tag(out: &mut Vec<u8>, number: usize, wire_type: u64) {
+    write_varint(out, (number as u64) << 3 | wire_type);
+}
+
+fn write_len(out: &mut Vec<u8>, number: usize, bytes: &[u8]) {
+    write_tag(out, number, WIRE_LEN);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn mismatch(schema: &Schema) -> SavefileError {
+    SavefileError::GeneralError {
+        msg: format!("Value doesn't match schema {:?}", schema),
+    }
+}
+
+fn encode_fields(
+    fields: &[Field],
+    values: &IndexMap<String, DynValue>,
+    out: &mut Vec<u8>,
+) -> Result<(), SavefileError> {
+    for (index, field) in fields.iter().enumerate() {
+        let value = values.get(&field.name).ok_or_else(|| SavefileError::GeneralError {
+            msg: format!("Value has no field '{}'", field.name),
+        })?;
+        encode_field(index + 1, &field.value, value, out).map_err(|err| err.with_path_segment(&field.name))?;
+    }
+    Ok(())
+}
+
+/// Encode the contents of the message representing the value
+fn encode_message(schema: &Schema, value: &DynValue, out: &mut Vec<u8>) -> Result<(), SavefileError> {
+    match (schema, value) {
+        (Schema::Struct(schema_struct), DynValue::Struct { fields, .. }) => {
+            encode_fields(&schema_struct.fields, fields, out)
+        }
+        (Schema::Enum(schema_enum), DynValue::Enum { variant, fields, .. }) => {
+            let (index, schema_variant) = schema_enum
+                .variants
+                .iter()
+                .enumerate()
+                .find(|(_, candidate)| &candidate.name == variant)
+                .ok_or_else(|| mismatch(schema))?;
+            let mut variant_bytes = Vec::new();
+            encode_fields(&schema_variant.fields, fields, &mut variant_bytes)?;
+            write_len(out, index + 1, &variant_bytes);
+            Ok(())
+        }
+        (Schema::ZeroSize, DynValue::ZeroSize) => Ok(()),
+        (Schema::BitPacked(item), value) => encode_message(item, value, out),
+        // Wrapper messages
+        (Schema::Vector(_) | Schema::Array(_) | Schema::SchemaOption(_), value) => encode_field(1, schema, value, out),
+        _ => Err(mismatch(schema)),
+    }
+}
+
+/// Encode the field with the given number, as declared by [ProtoBuilder::field_line]
+fn encode_field(number: usize, schema: &Schema, value: &DynValue, out: &mut Vec<u8>) -> Result<(), SavefileError> {
+    match (schema, value) {
+        (Schema::Vector(item), DynValue::Vector(items)) => {
+            for item_value in items {
+                encode_value(number, item, item_value, out)?;
+            }
+            Ok(())
+        }
+        (Schema::Array(array), DynValue::Array(items)) => {
+            for item_value in items {
+                encode_value(number, &array.item_type, item_value, out)?;
+            }
+            Ok(())
+        }
+        (Schema::SchemaOption(item), DynValue::Option(item_value)) => match item_value {
+            Some(item_value) => encode_value(number, item, item_value, out),
+            None => Ok(()),
+        },
+        (Schema::BitPacked(item), value) => encode_field(number, item, value, out),
+        (schema, value) => encode_value(number, schema, value, out),
+    }
+}
+
+/// Encode a single value of the type given by [ProtoBuilder::value_type]
+fn encode_value(number: usize, schema: &Schema, value: &DynValue, out: &mut Vec<u8>) -> Result<(), SavefileError> {
+    let signed = |out: &mut Vec<u8>, x: i64| {
+        write_tag(out, number, WIRE_VARINT);
+        write_varint(out, ((x << 1) ^ (x >> 63)) as u64);
+    };
+    let unsigned = |out: &mut Vec<u8>, x: u64| {
+        write_tag(out, number, WIRE_VARINT);
+        write_varint(out, x);
+    };
+    match (schema, value) {
+        (Schema::Primitive(SchemaPrimitive::schema_i8), DynValue::I8(x)) => signed(out, (*x).into()),
+        (Schema::Primitive(SchemaPrimitive::schema_i16), DynValue::I16(x)) => signed(out, (*x).into()),
+        (Schema::Primitive(SchemaPrimitive::schema_i32), DynValue::I32(x)) => signed(out, (*x).into()),
+        (Schema::Primitive(SchemaPrimitive::schema_i64), DynValue::I64(x)) => signed(out, *x),
+        (Schema::Primitive(SchemaPrimitive::schema_u8), DynValue::U8(x)) => unsigned(out, (*x).into()),
+        (Schema::Primitive(SchemaPrimitive::schema_u16), DynValue::U16(x)) => unsigned(out, (*x).into()),
+        (Schema::Primitive(SchemaPrimitive::schema_u32 | SchemaPrimitive::schema_canary1), DynValue::U32(x)) => {
+            unsigned(out, (*x).into())
+        }
+        (Schema::Primitive(SchemaPrimitive::schema_u64), DynValue::U64(x)) => unsigned(out, *x),
+        (Schema::Primitive(SchemaPrimitive::schema_bool), DynValue::Bool(x)) => unsigned(out, *x as u64),
+        (Schema::Primitive(SchemaPrimitive::schema_f32), DynValue::F32(x)) => {
+            write_tag(out, number, WIRE_FIXED32);
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        (Schema::Primitive(SchemaPrimitive::schema_f64), DynValue::F64(x)) => {
+            write_tag(out, number, WIRE_FIXED64);
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        (Schema::Primitive(SchemaPrimitive::schema_string), DynValue::String(x)) => {
+            write_len(out, number, x.as_bytes())
+        }
+        (Schema::Primitive(_), _) => return Err(mismatch(schema)),
+        (Schema::BitPacked(item), value) => return encode_value(number, item, value, out),
+        (Schema::Undefined, _) => return Err(undefined_error()),
+        (schema, value) => {
+            let mut message = Vec::new();
+            encode_message(schema, value, &mut message)?;
+            write_len(out, number, &message);
+        }
+    }
+    Ok(())
+}
+
+impl DynValue {
+    /// Encode the value as the root message of the protocol buffer definition created
+    /// by [Schema::to_proto] for the schema of the value.
+    pub fn to_protobuf(&self, schema: &Schema) -> Result<Vec<u8>, SavefileError> {
+        let mut out = Vec::new();
+        if is_message(schema) {
+            encode_message(schema, self, &mut out)?;
+        } else {
+            encode_field(1, schema, self, &mut out)?;
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,280 @@
+//! A Python module for inspecting and modifying files, wrapping [Document].
+//!
+//! Build the crate as a cdylib with the `python-extension` feature, for example with
+//! `cargo rustc --release --features python-extension --crate-type cdylib`, and rename the library to
+//! `savefile.so` (`savefile.pyd` on Windows) to import it as `savefile`:
+//!
+//! ```python
+//! import savefile
+//! doc = savefile.Document.load_file("save.bin")
+//! doc.set("players/0/name", "Alice")
+//! doc.save_file("save.bin")
+//! ```
+//!
+//! Values are converted to Python as follows: integers and floats to `int` and `float`,
+//! strings to `str`, structs to a `dict` of their fields, enums to a `dict` with the keys
+//! `variant` and `fields`, vectors and arrays to a `list`, and absent options and zero-sized
+//! values to `None`. When values are set, they are converted back as described by the schema
+//! of the file. A fieldless enum variant may then also be given as a `str` with its name.
+
+// The code generated by pymethods converts the PyErr of every PyResult into a PyErr
+#![allow(clippy::useless_conversion)]
+
+use crate::{Document, DynValue, SavefileError, Schema, SchemaPrimitive};
+use indexmap::IndexMap;
+use pyo3::exceptions::{PyKeyError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyString};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+fn to_python_error(err: SavefileError) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+fn to_python(py: Python<'_>, value: &DynValue) -> PyObject {
+    let fields_to_python = |fields: &IndexMap<String, DynValue>| {
+        let dict = PyDict::new_bound(py);
+        for (name, value) in fields {
+            dict.set_item(name, to_python(py, value))
+                .expect("string keys can always be inserted");
+        }
+        dict
+    };
+    match value {
+        DynValue::I8(x) => x.into_py(py),
+        DynValue::U8(x) => x.into_py(py),
+        DynValue::I16(x) => x.into_py(py),
+        DynValue::U16(x) => x.into_py(py),
+        DynValue::I32(x) => x.into_py(py),
+        DynValue::U32(x) => x.into_py(py),
+        DynValue::I64(x) => x.into_py(py),
+        DynValue::U64(x) => x.into_py(py),
+        DynValue::F32(x) => x.into_py(py),
+        DynValue::F64(x) => x.into_py(py),
+        DynValue::Bool(x) => x.into_py(py),
+        DynValue::String(x) => x.into_py(py),
+        DynValue::Struct { fields, .. } => fields_to_python(fields).into_py(py),
+        DynValue::Enum { variant, fields, .. } => {
+            let dict = PyDict::new_bound(py);
+            dict.set_item("variant", variant)
+                .expect("string keys can always be inserted");
+            dict.set_item("fields", fields_to_python(fields))
+                .expect("string keys can always be inserted");
+            dict.into_py(py)
+        }
+        DynValue::Vector(items) | DynValue::Array(items) => {
+            PyList::new_bound(py, items.iter().map(|item| to_python(py, item))).into_py(py)
+        }
+        DynValue::Option(Some(value)) => to_python(py, value),
+        DynValue::Option(None) | DynValue::ZeroSize => py.None(),
+    }
+}
+
+fn fields_from_python(fields: &[crate::Field], dict: &Bound<'_, PyDict>) -> PyResult<IndexMap<String, DynValue>> {
+    let mut values = IndexMap::with_capacity(fields.len());
+    for field in fields {
+        let value = dict
+            .get_item(&field.name)?
+            .ok_or_else(|| PyKeyError::new_err(format!("Missing field {}", field.name)))?;
+        values.insert(field.name.clone(), from_python(&field.value, &value)?);
+    }
+    Ok(values)
+}
+
+fn from_python(schema: &Schema, obj: &Bound<'_, PyAny>) -> PyResult<DynValue> {
+    Ok(match schema {
+        Schema::Struct(schema_struct) => DynValue::Struct {
+            name: schema_struct.dbg_name.clone(),
+            fields: fields_from_python(&schema_struct.fields, obj.downcast::<PyDict>()?)?,
+        },
+        Schema::Enum(schema_enum) => {
+            let (name, fields) = if let Ok(name) = obj.downcast::<PyString>() {
+                (name.extract::<String>()?, None)
+            } else {
+                let dict = obj.downcast::<PyDict>()?;
+                let name = dict
+                    .get_item("variant")?
+                    .ok_or_else(|| PyKeyError::new_err("Missing enum variant"))?
+                    .extract::<String>()?;
+                (name, dict.get_item("fields")?)
+            };
+            let variant = schema_enum
+                .variants
+                .iter()
+                .find(|variant| variant.name == name)
+                .ok_or_else(|| {
+                    PyValueError::new_err(format!("Enum {} has no variant {}", schema_enum.dbg_name, name))
+                })?;
+            let fields = match fields {
+                Some(fields) => fields_from_python(&variant.fields, fields.downcast::<PyDict>()?)?,
+                None if variant.fields.is_empty() => IndexMap::new(),
+                None => return Err(PyKeyError::new_err(format!("Missing fields of variant {}", name))),
+            };
+            DynValue::Enum {
+                variant: variant.name.clone(),
+                discriminator: variant.discriminator,
+                fields,
+            }
+        }
+        Schema::Primitive(primitive) => match primitive {
+            SchemaPrimitive::schema_i8 => DynValue::I8(obj.extract()?),
+            SchemaPrimitive::schema_u8 => DynValue::U8(obj.extract()?),
+            SchemaPrimitive::schema_i16 => DynValue::I16(obj.extract()?),
+            SchemaPrimitive::schema_u16 => DynValue::U16(obj.extract()?),
+            SchemaPrimitive::schema_i32 => DynValue::I32(obj.extract()?),
+            SchemaPrimitive::schema_u32 | SchemaPrimitive::schema_canary1 => DynValue::U32(obj.extract()?),
+            SchemaPrimitive::schema_i64 => DynValue::I64(obj.extract()?),
+            SchemaPrimitive::schema_u64 => DynValue::U64(obj.extract()?),
+            SchemaPrimitive::schema_string => DynValue::String(obj.extract()?),
+            SchemaPrimitive::schema_f32 => DynValue::F32(obj.extract()?),
+            SchemaPrimitive::schema_f64 => DynValue::F64(obj.extract()?),
+            SchemaPrimitive::schema_bool => DynValue::Bool(obj.extract()?),
+        },
+        Schema::Vector(item) => DynValue::Vector(items_from_python(item, obj)?),
+        Schema::Array(array) => {
+            let items = items_from_python(&array.item_type, obj)?;
+            if items.len() != array.count {
+                return Err(PyValueError::new_err(format!(
+                    "Expected {} items, got {}",
+                    array.count,
+                    items.len()
+                )));
+            }
+            DynValue::Array(items)
+        }
+        Schema::SchemaOption(item) => {
+            if obj.is_none() {
+                DynValue::Option(None)
+            } else {
+                DynValue::Option(Some(Box::new(from_python(item, obj)?)))
+            }
+        }
+        Schema::ZeroSize => DynValue::ZeroSize,
+        Schema::BitPacked(item) => from_python(item, obj)?,
+        Schema::Undefined => return Err(PyTypeError::new_err("Values of undefined schema can't be set")),
+    })
+}
+
+fn items_from_python(item: &Schema, obj: &Bound<'_, PyAny>) -> PyResult<Vec<DynValue>> {
+    if obj.is_instance_of::<PyString>() {
+        return Err(PyTypeError::new_err("Expected a sequence, got a str"));
+    }
+    obj.iter()?.map(|value| from_python(item, &value?)).collect()
+}
+
+/// The schema of the child of the given value with the given key, see [DynValue::child]
+fn child_schema<'a>(schema: &'a Schema, value: &DynValue, key: &str) -> Option<&'a Schema> {
+    match (schema, value) {
+        (Schema::Struct(schema_struct), _) => schema_struct
+            .fields
+            .iter()
+            .find(|field| field.name == key)
+            .map(|field| &*field.value),
+        (Schema::Enum(schema_enum), DynValue::Enum { variant, .. }) => schema_enum
+            .variants
+            .iter()
+            .find(|candidate| &candidate.name == variant)?
+            .fields
+            .iter()
+            .find(|field| field.name == key)
+            .map(|field| &*field.value),
+        (Schema::Vector(item), _) => Some(item),
+        (Schema::Array(array), _) => Some(&array.item_type),
+        (Schema::SchemaOption(item), DynValue::Option(Some(value))) => child_schema(item, value, key),
+        _ => None,
+    }
+}
+
+/// A file loaded with its schema, which can be inspected, modified and saved again.
+#[pyclass(name = "Document", module = "savefile")]
+pub struct PyDocument {
+    document: Document,
+}
+
+#[pymethods]
+impl PyDocument {
+    /// Load a document from the bytes of a file saved with a schema.
+    #[staticmethod]
+    fn load(data: &[u8]) -> PyResult<PyDocument> {
+        let mut reader = data;
+        let document = Document::load(&mut reader).map_err(to_python_error)?;
+        Ok(PyDocument { document })
+    }
+
+    /// Load a document from a file saved with a schema.
+    #[staticmethod]
+    fn load_file(path: PathBuf) -> PyResult<PyDocument> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let document = Document::load(&mut reader).map_err(to_python_error)?;
+        Ok(PyDocument { document })
+    }
+
+    /// The bytes of the document, saved with its schema and without compression.
+    fn save<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut data = Vec::new();
+        self.document.save(&mut data).map_err(to_python_error)?;
+        Ok(PyBytes::new_bound(py, &data))
+    }
+
+    /// Save the document to a file, with its schema and without compression.
+    fn save_file(&self, path: PathBuf) -> PyResult<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.document.save(&mut writer).map_err(to_python_error)
+    }
+
+    /// The version of the data
+    #[getter]
+    fn version(&self) -> u32 {
+        self.document.version
+    }
+
+    /// The schema of the data, as text
+    #[getter]
+    fn schema(&self) -> String {
+        self.document.schema.to_string()
+    }
+
+    /// The data
+    #[getter]
+    fn value(&self, py: Python<'_>) -> PyObject {
+        to_python(py, &self.document.value)
+    }
+
+    #[setter]
+    fn set_value(&mut self, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.document.value = from_python(&self.document.schema, value)?;
+        Ok(())
+    }
+
+    /// The value at the given path, such as `players/0/name`. Raises KeyError if there is none.
+    fn get(&self, py: Python<'_>, path: &str) -> PyResult<PyObject> {
+        let value = self
+            .document
+            .get(path)
+            .ok_or_else(|| PyKeyError::new_err(path.to_string()))?;
+        Ok(to_python(py, value))
+    }
+
+    /// Replace the value at the given path, converting it as described by the schema.
+    /// Raises KeyError if there is no value at the path.
+    fn set(&mut self, path: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let missing = || PyKeyError::new_err(path.to_string());
+        let mut schema = &self.document.schema;
+        let mut current = &self.document.value;
+        for key in path.split('/').filter(|key| !key.is_empty()) {
+            schema = child_schema(schema, current, key).ok_or_else(missing)?;
+            current = current.child(key).ok_or_else(missing)?;
+        }
+        let value = from_python(schema, value)?;
+        *self.document.get_mut(path).ok_or_else(missing)? = value;
+        Ok(())
+    }
+}
+
+/// The `savefile` Python module
+#[pymodule]
+fn savefile(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyDocument>()
+}
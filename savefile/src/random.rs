@@ -0,0 +1,419 @@
+//! Random values of any type with a schema, for testing serialization.
+
+#[cfg(feature = "quickcheck")]
+use crate::CurrentVersion;
+use crate::{
+    schema_of, Deserialize, Deserializer, Document, DynValue, Encoding, SavefileError, Schema, SchemaPrimitive,
+    Serialize, Serializer, WithSchema, HEADER_LENGTH,
+};
+use indexmap::IndexMap;
+#[cfg(feature = "proptest")]
+use proptest::prelude::{any, BoxedStrategy, Just, Strategy};
+#[cfg(feature = "proptest")]
+use proptest::strategy::Union;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+/// The maximum number of items in generated vectors
+const MAX_RANDOM_ITEMS: usize = 4;
+
+/// The maximum number of characters in generated strings
+const MAX_RANDOM_CHARS: usize = 16;
+
+/// The maximum number of mutations applied by [mutate_bytes]
+const MAX_MUTATIONS: usize = 3;
+
+fn random_fields(fields: &[crate::Field], rng: &mut impl Rng) -> Result<IndexMap<String, DynValue>, SavefileError> {
+    let mut values = IndexMap::with_capacity(fields.len());
+    for field in fields {
+        values.insert(
+            field.name.clone(),
+            generate(&field.value, rng).map_err(|err| err.with_path_segment(&field.name))?,
+        );
+    }
+    Ok(values)
+}
+
+/// A random value matching the schema, generated from the given seed. The same seed
+/// always gives the same value. Vectors have at most 4 items, and strings
+/// at most 16 characters. Floats are never NaN. Fails for [Schema::Undefined].
+///
+/// Serializing the value with the schema gives data which any type with that schema
+/// can load, unless the type rejects some values its schema allows, as `char` does.
+pub fn random_value(schema: &Schema, seed: u64) -> Result<DynValue, SavefileError> {
+    generate(schema, &mut StdRng::seed_from_u64(seed))
+}
+
+fn generate(schema: &Schema, rng: &mut impl Rng) -> Result<DynValue, SavefileError> {
+    Ok(match schema {
+        Schema::Struct(schema_struct) => DynValue::Struct {
+            name: schema_struct.dbg_name.clone(),
+            fields: random_fields(&schema_struct.fields, rng)?,
+        },
+        Schema::Enum(schema_enum) => {
+            if schema_enum.variants.is_empty() {
+                return Err(SavefileError::GeneralError {
+                    msg: format!("Enum {} has no variants, so it has no values", schema_enum.dbg_name),
+                });
+            }
+            let variant = &schema_enum.variants[rng.gen_range(0, schema_enum.variants.len())];
+            DynValue::Enum {
+                variant: variant.name.clone(),
+                discriminator: variant.discriminator,
+                fields: random_fields(&variant.fields, rng)?,
+            }
+        }
+        Schema::Primitive(primitive) => match primitive {
+            SchemaPrimitive::schema_i8 => DynValue::I8(rng.gen()),
+            SchemaPrimitive::schema_u8 => DynValue::U8(rng.gen()),
+            SchemaPrimitive::schema_i16 => DynValue::I16(rng.gen()),
+            SchemaPrimitive::schema_u16 => DynValue::U16(rng.gen()),
+            SchemaPrimitive::schema_i32 => DynValue::I32(rng.gen()),
+            SchemaPrimitive::schema_u32 => DynValue::U32(rng.gen()),
+            SchemaPrimitive::schema_i64 => DynValue::I64(rng.gen()),
+            SchemaPrimitive::schema_u64 => DynValue::U64(rng.gen()),
+            SchemaPrimitive::schema_string => {
+                let len = rng.gen_range(0, MAX_RANDOM_CHARS + 1);
+                DynValue::String((0..len).map(|_| rng.gen::<char>()).collect())
+            }
+            SchemaPrimitive::schema_f32 => {
+                let x = f32::from_bits(rng.gen());
+                DynValue::F32(if x.is_nan() { 0.0 } else { x })
+            }
+            SchemaPrimitive::schema_f64 => {
+                let x = f64::from_bits(rng.gen());
+                DynValue::F64(if x.is_nan() { 0.0 } else { x })
+            }
+            SchemaPrimitive::schema_bool => DynValue::Bool(rng.gen()),
+            // The canary is checked when loading, so it must have its only valid value
+            SchemaPrimitive::schema_canary1 => DynValue::U32(crate::CANARY1_VALUE),
+        },
+        Schema::Vector(item) => {
+            let len = rng.gen_range(0, MAX_RANDOM_ITEMS + 1);
+            DynValue::Vector((0..len).map(|_| generate(item, rng)).collect::<Result<_, _>>()?)
+        }
+        Schema::Array(array) => DynValue::Array(
+            (0..array.count)
+                .map(|_| generate(&array.item_type, rng))
+                .collect::<Result<_, _>>()?,
+        ),
+        Schema::SchemaOption(item) => {
+            if rng.gen() {
+                DynValue::Option(Some(Box::new(generate(item, rng)?)))
+            } else {
+                DynValue::Option(None)
+            }
+        }
+        Schema::ZeroSize => DynValue::ZeroSize,
+        Schema::BitPacked(item) => generate(item, rng)?,
+        Schema::Undefined => {
+            return Err(SavefileError::GeneralError {
+                msg: "Values of undefined schema can't be generated".into(),
+            })
+        }
+    })
+}
+
+/// Deserialize a T from data without a header, saved with the given version
+fn load_raw<T: Deserialize>(bytes: &[u8], version: u32) -> Result<T, SavefileError> {
+    let mut reader = bytes;
+    let mut deserializer = Deserializer::new_raw(&mut reader);
+    deserializer.file_version = version;
+    deserializer.memory_version = version;
+    T::deserialize(&mut deserializer)
+}
+
+fn raw_serializer(writer: &mut Vec<u8>, version: u32) -> Serializer<'_, Vec<u8>> {
+    Serializer {
+        writer,
+        version,
+        encoding: Encoding::default(),
+        interned_strings: HashMap::new(),
+        context: None,
+        progress: None,
+    }
+}
+
+/// The value, which has the given schema, loaded as a T
+fn value_as<T: Deserialize>(value: &DynValue, schema: &Schema, version: u32) -> Result<T, SavefileError> {
+    let mut bytes = Vec::new();
+    value.serialize(schema, &mut raw_serializer(&mut bytes, version))?;
+    load_raw(&bytes, version)
+}
+
+/// Check that `count` random values of T survive being saved and loaded again.
+/// Each value is generated from the schema of T at the given version, serialized, and
+/// loaded as a T. That T is then serialized and loaded again, and the two must be equal.
+/// The current version of T in memory must be `version`.
+///
+/// The values are generated by [random_value] from the seeds 0 to `count` - 1, and the
+/// error says which seed failed, so failures can be reproduced.
+///
+/// Some types reject values their schema allows, such as a [crate::DenseMap] with unsorted
+/// keys. Such values aren't values of T, so they are skipped. The check fails if all of them
+/// are, as is likely for [crate::PackedBools] and [crate::StringBlock], whose lengths must
+/// match their data. These types can't be checked this way. Types which accept only a few of
+/// their values, such as enums with `savefile_enum_as_int`, whose discriminants are random
+/// integers, are only checked with those few.
+pub fn roundtrip_check<T: WithSchema + Serialize + Deserialize + PartialEq + Debug>(
+    version: u32,
+    count: u64,
+) -> Result<(), SavefileError> {
+    let schema = schema_of::<T>(version);
+    let mut rejected = None;
+    let mut checked = 0;
+    for seed in 0..count {
+        let fail = |what: &str, err: String| SavefileError::GeneralError {
+            msg: format!("Random value with seed {} {}: {}", seed, what, err),
+        };
+        let value = random_value(&schema, seed)?;
+        let first: T = match value_as(&value, &schema, version) {
+            Ok(first) => first,
+            Err(err) => {
+                rejected = Some(fail("couldn't be loaded", err.to_string()));
+                continue;
+            }
+        };
+        checked += 1;
+        let mut resaved = Vec::new();
+        first.serialize(&mut raw_serializer(&mut resaved, version))?;
+        let second = load_raw::<T>(&resaved, version)
+            .map_err(|err| fail("couldn't be loaded after saving it again", err.to_string()))?;
+        if first != second {
+            return Err(fail(
+                "changed when saved and loaded again",
+                format!("{:?} became {:?}", first, second),
+            ));
+        }
+    }
+    match rejected {
+        Some(err) if checked == 0 => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// A proptest strategy for values matching the schema, like those of [random_value].
+/// The values shrink towards shorter vectors and strings, absent options, earlier
+/// variants and smaller numbers. Fails for [Schema::Undefined].
+#[cfg(feature = "proptest")]
+pub fn value_strategy(schema: &Schema) -> Result<BoxedStrategy<DynValue>, SavefileError> {
+    let fields_strategy =
+        |fields: &[crate::Field]| -> Result<BoxedStrategy<IndexMap<String, DynValue>>, SavefileError> {
+            let names: Vec<String> = fields.iter().map(|field| field.name.clone()).collect();
+            let values = fields
+                .iter()
+                .map(|field| value_strategy(&field.value).map_err(|err| err.with_path_segment(&field.name)))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(values
+                .prop_map(move |values| names.iter().cloned().zip(values).collect())
+                .boxed())
+        };
+    let items = |item: &Schema, len: std::ops::RangeInclusive<usize>| -> Result<_, SavefileError> {
+        Ok(proptest::collection::vec(value_strategy(item)?, len))
+    };
+    Ok(match schema {
+        Schema::Struct(schema_struct) => {
+            let name = schema_struct.dbg_name.clone();
+            fields_strategy(&schema_struct.fields)?
+                .prop_map(move |fields| DynValue::Struct {
+                    name: name.clone(),
+                    fields,
+                })
+                .boxed()
+        }
+        Schema::Enum(schema_enum) => {
+            if schema_enum.variants.is_empty() {
+                return Err(SavefileError::GeneralError {
+                    msg: format!("Enum {} has no variants, so it has no values", schema_enum.dbg_name),
+                });
+            }
+            let variants = schema_enum
+                .variants
+                .iter()
+                .map(|variant| {
+                    let (name, discriminator) = (variant.name.clone(), variant.discriminator);
+                    Ok(fields_strategy(&variant.fields)?
+                        .prop_map(move |fields| DynValue::Enum {
+                            variant: name.clone(),
+                            discriminator,
+                            fields,
+                        })
+                        .boxed())
+                })
+                .collect::<Result<Vec<_>, SavefileError>>()?;
+            Union::new(variants).boxed()
+        }
+        Schema::Primitive(primitive) => match primitive {
+            SchemaPrimitive::schema_i8 => any::<i8>().prop_map(DynValue::I8).boxed(),
+            SchemaPrimitive::schema_u8 => any::<u8>().prop_map(DynValue::U8).boxed(),
+            SchemaPrimitive::schema_i16 => any::<i16>().prop_map(DynValue::I16).boxed(),
+            SchemaPrimitive::schema_u16 => any::<u16>().prop_map(DynValue::U16).boxed(),
+            SchemaPrimitive::schema_i32 => any::<i32>().prop_map(DynValue::I32).boxed(),
+            SchemaPrimitive::schema_u32 => any::<u32>().prop_map(DynValue::U32).boxed(),
+            SchemaPrimitive::schema_i64 => any::<i64>().prop_map(DynValue::I64).boxed(),
+            SchemaPrimitive::schema_u64 => any::<u64>().prop_map(DynValue::U64).boxed(),
+            SchemaPrimitive::schema_string => proptest::collection::vec(any::<char>(), 0..=MAX_RANDOM_CHARS)
+                .prop_map(|chars| DynValue::String(chars.into_iter().collect()))
+                .boxed(),
+            SchemaPrimitive::schema_f32 => any::<f32>()
+                .prop_map(|x| DynValue::F32(if x.is_nan() { 0.0 } else { x }))
+                .boxed(),
+            SchemaPrimitive::schema_f64 => any::<f64>()
+                .prop_map(|x| DynValue::F64(if x.is_nan() { 0.0 } else { x }))
+                .boxed(),
+            SchemaPrimitive::schema_bool => any::<bool>().prop_map(DynValue::Bool).boxed(),
+            SchemaPrimitive::schema_canary1 => Just(DynValue::U32(crate::CANARY1_VALUE)).boxed(),
+        },
+        Schema::Vector(item) => items(item, 0..=MAX_RANDOM_ITEMS)?.prop_map(DynValue::Vector).boxed(),
+        Schema::Array(array) => items(&array.item_type, array.count..=array.count)?
+            .prop_map(DynValue::Array)
+            .boxed(),
+        Schema::SchemaOption(item) => proptest::option::of(value_strategy(item)?)
+            .prop_map(|value| DynValue::Option(value.map(Box::new)))
+            .boxed(),
+        Schema::ZeroSize => Just(DynValue::ZeroSize).boxed(),
+        Schema::BitPacked(item) => value_strategy(item)?,
+        Schema::Undefined => {
+            return Err(SavefileError::GeneralError {
+                msg: "Values of undefined schema can't be generated".into(),
+            })
+        }
+    })
+}
+
+/// A proptest strategy for values of T, generated by [value_strategy] from the schema of T
+/// at the given version. Values which T rejects are filtered out, see [roundtrip_check].
+/// The current version of T in memory must be `version`.
+///
+/// ```
+/// # #[macro_use] extern crate savefile_derive;
+/// use proptest::prelude::*;
+/// use savefile::type_strategy;
+///
+/// #[derive(Savefile, Debug, PartialEq)]
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// proptest! {
+///     fn points_roundtrip(point in type_strategy::<Point>(0).unwrap()) {
+///         let bytes = savefile::save_to_mem(0, &point).unwrap();
+///         prop_assert_eq!(savefile::load_from_mem::<Point>(&bytes, 0).unwrap(), point);
+///     }
+/// }
+/// # fn main() { points_roundtrip(); }
+/// ```
+#[cfg(feature = "proptest")]
+pub fn type_strategy<T: WithSchema + Deserialize + Debug + 'static>(
+    version: u32,
+) -> Result<BoxedStrategy<T>, SavefileError> {
+    let schema = schema_of::<T>(version);
+    Ok(value_strategy(&schema)?
+        .prop_filter_map("rejected by Deserialize", move |value| {
+            value_as(&value, &schema, version).ok()
+        })
+        .boxed())
+}
+
+/// The number of random values [ArbitraryValue] tries, before giving up on finding one
+/// which T accepts
+#[cfg(feature = "quickcheck")]
+const MAX_ARBITRARY_ATTEMPTS: usize = 1000;
+
+/// A value of T generated from the schema of its current version, for quickcheck.
+/// It implements `quickcheck::Arbitrary` by generating values like [random_value], from
+/// seeds given by quickcheck. Values which T rejects are skipped, see [roundtrip_check].
+/// The values aren't shrunk, since a smaller seed doesn't give a smaller value.
+#[cfg(feature = "quickcheck")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArbitraryValue<T>(pub T);
+
+#[cfg(feature = "quickcheck")]
+impl<T: WithSchema + Deserialize + CurrentVersion + Clone + 'static> quickcheck::Arbitrary for ArbitraryValue<T> {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        let version = T::SAVEFILE_MAX_VERSION;
+        let schema = schema_of::<T>(version);
+        let mut last_error = None;
+        for _ in 0..MAX_ARBITRARY_ATTEMPTS {
+            let seed = <u64 as quickcheck::Arbitrary>::arbitrary(g);
+            let value = random_value(&schema, seed).unwrap_or_else(|err| panic!("{}", err));
+            match value_as(&value, &schema, version) {
+                Ok(value) => return ArbitraryValue(value),
+                Err(err) => last_error = Some(err),
+            }
+        }
+        panic!(
+            "None of {} random values could be loaded as {}. The last error was: {}",
+            MAX_ARBITRARY_ATTEMPTS,
+            std::any::type_name::<T>(),
+            last_error.expect("at least one value was tried")
+        );
+    }
+}
+
+/// Apply a few random mutations to the bytes, generated from the given seed.
+/// Bits are flipped, bytes replaced by 0 or 255, 8 bytes replaced by a huge length,
+/// and ranges removed, duplicated or cut off at the end. The header of a file is
+/// mutated less often than the rest, so that most mutated files get past it.
+pub fn mutate_bytes(bytes: &[u8], seed: u64) -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut bytes = bytes.to_vec();
+    for _ in 0..rng.gen_range(1, MAX_MUTATIONS + 1) {
+        if bytes.is_empty() {
+            break;
+        }
+        let start = if bytes.len() as u64 > HEADER_LENGTH && rng.gen_range(0, 4) != 0 {
+            HEADER_LENGTH as usize
+        } else {
+            0
+        };
+        let pos = rng.gen_range(start, bytes.len());
+        let end = rng.gen_range(pos, bytes.len()) + 1;
+        match rng.gen_range(0, 6) {
+            0 => bytes[pos] ^= 1 << rng.gen_range(0, 8),
+            1 => bytes[pos] = if rng.gen() { 0 } else { 255 },
+            2 => {
+                let huge = if rng.gen() { u64::MAX } else { 1 << 40 };
+                for (byte, huge_byte) in bytes[pos..].iter_mut().zip(huge.to_le_bytes().iter()) {
+                    *byte = *huge_byte;
+                }
+            }
+            3 => {
+                bytes.drain(pos..end);
+            }
+            4 => {
+                let range = bytes[pos..end].to_vec();
+                bytes.splice(end..end, range);
+            }
+            _ => bytes.truncate(pos),
+        }
+    }
+    bytes
+}
+
+/// Files for seeding fuzz targets which load data of the given schema, such as with
+/// [crate::load_from_mem]. The first `count` files are saved with the schema, as by
+/// [crate::save], each containing a value generated by [random_value] from the seeds `seed` up to
+/// `seed + count`, wrapping around after `u64::MAX`. They are followed by `count` copies of them
+/// changed by [mutate_bytes].
+pub fn fuzz_corpus(schema: &Schema, version: u32, count: u64, seed: u64) -> Result<Vec<Vec<u8>>, SavefileError> {
+    let mut corpus = Vec::with_capacity(2 * count as usize);
+    for value_seed in (0..count).map(|index| seed.wrapping_add(index)) {
+        let document = Document {
+            version,
+            schema: schema.clone(),
+            value: random_value(schema, value_seed)?,
+        };
+        let mut bytes = Vec::new();
+        document.save(&mut bytes)?;
+        corpus.push(bytes);
+    }
+    for index in 0..count as usize {
+        let mutated = mutate_bytes(&corpus[index], seed.wrapping_add(index as u64));
+        corpus.push(mutated);
+    }
+    Ok(corpus)
+}
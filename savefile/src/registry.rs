@@ -0,0 +1,144 @@
+//! Schemas of saved data, looked up by the schema hash stored in the data.
+
+#[cfg(feature = "fs")]
+use crate::{
+    read_schema, write_file_atomic, CompressionMethod, Encoding, SavefileHeader, Serialize, Serializer,
+    SAVEFILE_FORMAT_VERSION,
+};
+use crate::{schema_of, SavefileError, Schema, WithSchema};
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::io::BufReader;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+
+/// Known schemas, keyed by their fingerprint, which is the hash returned by
+/// [Schema::stable_hash]. Data saved by [crate::save_with_registry] contains only the
+/// fingerprint of its schema, and [crate::load_with_registry] finds the full schema here,
+/// to check it against the type being loaded.
+///
+/// The registry can have a cache directory, in which each registered schema is saved to
+/// a file named by its fingerprint. These are schema files, which [crate::read_schema] can
+/// read. Schemas which aren't in memory are looked up there, so data saved by one process
+/// can be loaded by another, as long as they share the directory. This needs the `fs` feature.
+#[derive(Debug, Default)]
+pub struct SchemaRegistry {
+    schemas: HashMap<u64, Schema>,
+    #[cfg(feature = "fs")]
+    cache_dir: Option<PathBuf>,
+}
+
+impl SchemaRegistry {
+    /// An empty registry without a cache directory
+    pub fn new() -> SchemaRegistry {
+        SchemaRegistry::default()
+    }
+
+    /// A registry using the given cache directory, which must exist. Schemas are only read
+    /// from the directory when they are needed.
+    #[cfg(feature = "fs")]
+    pub fn with_cache_dir(cache_dir: impl AsRef<Path>) -> SchemaRegistry {
+        SchemaRegistry {
+            schemas: HashMap::new(),
+            cache_dir: Some(cache_dir.as_ref().to_path_buf()),
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    fn cache_path(&self, fingerprint: u64) -> Option<PathBuf> {
+        self.cache_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{:016x}.schema", fingerprint)))
+    }
+
+    /// Add the schema to the registry, and to the cache directory if it isn't there already.
+    /// Returns the fingerprint of the schema.
+    pub fn register(&mut self, schema: Schema) -> Result<u64, SavefileError> {
+        let fingerprint = schema.stable_hash();
+        #[cfg(feature = "fs")]
+        if let Some(path) = self.cache_path(fingerprint) {
+            if !path.exists() {
+                write_file_atomic(&path, 0, |file| {
+                    SavefileHeader {
+                        format_version: SAVEFILE_FORMAT_VERSION,
+                        version: 0,
+                        compression: CompressionMethod::None,
+                        has_schema: Some(true),
+                        has_checksum: false,
+                        encrypted: false,
+                        has_schema_hash: false,
+                        has_schema_history: false,
+                        payload_length: None,
+                        created: crate::creation_time(),
+                        encoding: Encoding::default(),
+                    }
+                    .write(file)?;
+                    schema.serialize(&mut Serializer::new_raw(file))
+                })?;
+            }
+        }
+        self.schemas.insert(fingerprint, schema);
+        Ok(fingerprint)
+    }
+
+    /// Add the schema of the given version of T, see [SchemaRegistry::register]
+    pub fn register_type<T: WithSchema>(&mut self, version: u32) -> Result<u64, SavefileError> {
+        let fingerprint = schema_of::<T>(version).stable_hash();
+        if !self.schemas.contains_key(&fingerprint) {
+            self.register(Schema::clone(&schema_of::<T>(version)))?;
+        }
+        Ok(fingerprint)
+    }
+
+    /// The schema with the given fingerprint, if it has been registered or loaded from
+    /// the cache directory. The cache directory isn't searched by this method.
+    pub fn get(&self, fingerprint: u64) -> Option<&Schema> {
+        self.schemas.get(&fingerprint)
+    }
+
+    /// The schema with the given fingerprint, loaded from the cache directory if it hasn't
+    /// been registered. Fails if the schema isn't known.
+    pub fn resolve(&mut self, fingerprint: u64) -> Result<&Schema, SavefileError> {
+        if !self.schemas.contains_key(&fingerprint) {
+            let schema = self.read_cached(fingerprint)?;
+            self.schemas.insert(fingerprint, schema);
+        }
+        Ok(&self.schemas[&fingerprint])
+    }
+
+    /// The schema with the given fingerprint, from the cache directory
+    #[cfg(feature = "fs")]
+    fn read_cached(&self, fingerprint: u64) -> Result<Schema, SavefileError> {
+        let path = match self.cache_path(fingerprint) {
+            Some(path) if path.exists() => path,
+            _ => return Err(not_registered(fingerprint)),
+        };
+        let (_, schema) = read_schema(&mut BufReader::new(File::open(&path)?))?;
+        if schema.stable_hash() != fingerprint {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "The schema in {} has fingerprint {:016x}, not the one in its name",
+                    path.display(),
+                    schema.stable_hash()
+                ),
+            });
+        }
+        Ok(schema)
+    }
+
+    #[cfg(not(feature = "fs"))]
+    fn read_cached(&self, fingerprint: u64) -> Result<Schema, SavefileError> {
+        Err(not_registered(fingerprint))
+    }
+}
+
+fn not_registered(fingerprint: u64) -> SavefileError {
+    SavefileError::GeneralError {
+        msg: format!(
+            "The schema with fingerprint {:016x} is not in the registry",
+            fingerprint
+        ),
+    }
+}
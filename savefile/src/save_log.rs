@@ -0,0 +1,295 @@
+//! Append-only files of records, for event journals and similar.
+
+use crate::namespace::{self, NamespaceVersions};
+use crate::{
+    diff_schema, differing_subtrees, schema_of, CompressionMethod, Deserialize, Deserializer, Encoding, SavefileError,
+    SavefileHeader, Schema, Serialize, Serializer, WithSchema,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// Length of the length and checksum preceding each record
+const RECORD_HEADER_LENGTH: u64 = 8;
+
+/// An append-only file of records of type T.
+///
+/// The file starts with a regular savefile header, the versions of the namespaces used by T (see
+/// [crate::VersionNamespace]) and the schema of T, followed by the records.
+/// Each record is preceded by its length and CRC32 checksum. If the application crashed while
+/// appending a record, the incomplete record at the end of the file is detected and ignored
+/// when reading, and removed when the log is opened for appending again.
+///
+/// All records in a log have the same version, and the same versions of namespaces. Records can
+/// be read by newer versions of T, but only appended by the versions the log was created with.
+pub struct SaveLog<T> {
+    file: File,
+    version: u32,
+    phantom: PhantomData<fn(&T)>,
+}
+
+/// Iterator over the records of a [SaveLog], created by [SaveLog::read].
+pub struct SaveLogReader<T> {
+    reader: BufReader<File>,
+    file_version: u32,
+    memory_version: u32,
+    namespaces: NamespaceVersions,
+    done: bool,
+    phantom: PhantomData<fn() -> T>,
+}
+
+/// Read the header and schema of the log, returning the version of the log and the versions of
+/// its namespaces.
+fn read_log_header<T: WithSchema>(
+    reader: &mut impl Read,
+    version: u32,
+) -> Result<(u32, NamespaceVersions), SavefileError> {
+    let header = SavefileHeader::read(reader)?;
+    if header.version > version {
+        return Err(SavefileError::WrongVersion {
+            msg: format!(
+                "Log has later version ({}) than structs in memory ({}).",
+                header.version, version
+            ),
+        });
+    }
+    if header.has_schema != Some(true)
+        || header.compression != CompressionMethod::None
+        || header.has_schema_history
+        || header.encrypted
+        || header.encoding != Encoding::default()
+    {
+        return Err(SavefileError::GeneralError {
+            msg: "File is not a savefile log".into(),
+        });
+    }
+    let namespaces = namespace::read_namespace_versions(reader, &header)?;
+    namespace::check_file_versions(&namespaces, || schema_of::<T>(header.version))?;
+    let file_schema = Schema::deserialize(&mut Deserializer::new_raw(reader))?;
+    let memory_schema = namespace::with_file_versions(Some(namespaces.clone()), || schema_of::<T>(header.version));
+    if let Some(err) = diff_schema(&memory_schema, &file_schema, ".".to_string()) {
+        return Err(SavefileError::IncompatibleSchema {
+            message: format!(
+                "Saved schema differs from in-memory schema for version {}. Error: {}{}",
+                header.version,
+                err,
+                differing_subtrees(&memory_schema, &file_schema)
+            ),
+        });
+    }
+    Ok((header.version, namespaces))
+}
+
+/// Read until the buffer is full or the end of the file is reached, returning the number of
+/// bytes read. Unlike [Read::read_exact], this tells a short read at the end of the file,
+/// which is left by an interrupted write, from other errors.
+fn read_until_end(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize, SavefileError> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(read)
+}
+
+/// Read the next record. Returns None at the end of the log, or if the last record is incomplete
+/// because the end of the file was reached while reading it.
+fn read_record(reader: &mut BufReader<File>) -> Result<Option<Vec<u8>>, SavefileError> {
+    let mut record_header = [0u8; RECORD_HEADER_LENGTH as usize];
+    if read_until_end(reader, &mut record_header)? < record_header.len() {
+        return Ok(None);
+    }
+    let length = (&record_header[0..4]).read_u32::<LittleEndian>()? as u64;
+    let expected = (&record_header[4..8]).read_u32::<LittleEndian>()?;
+    // The length may be corrupt, so it isn't used to preallocate
+    let mut payload = Vec::new();
+    // read_to_end only stops early at the end of the file, and fails on other errors
+    if reader.take(length).read_to_end(&mut payload)? as u64 != length {
+        return Ok(None);
+    }
+    let actual = crc32fast::hash(&payload);
+    if actual != expected {
+        if reader.fill_buf()?.is_empty() {
+            // The last write was interrupted
+            return Ok(None);
+        }
+        return Err(SavefileError::ChecksumMismatch { expected, actual });
+    }
+    Ok(Some(payload))
+}
+
+impl<T: WithSchema + Serialize> SaveLog<T> {
+    /// Create a new, empty, log at the given path. Any existing file is replaced atomically,
+    /// as by [crate::save_file_atomic], so that a crash leaves either the old file or the new log.
+    /// The current version of T in memory must be `version`.
+    pub fn create(path: impl AsRef<Path>, version: u32) -> Result<SaveLog<T>, SavefileError> {
+        let path = path.as_ref();
+        let mut head = Vec::new();
+        let namespaces = namespace::namespaces_of(|| schema_of::<T>(version))?;
+        SavefileHeader {
+            format_version: namespace::format_version(&namespaces),
+            version,
+            compression: CompressionMethod::None,
+            has_schema: Some(true),
+            has_checksum: false,
+            encrypted: false,
+            has_schema_hash: false,
+            has_schema_history: false,
+            payload_length: None,
+            created: crate::creation_time(),
+            encoding: Encoding::default(),
+        }
+        .write(&mut head)?;
+        if !namespaces.is_empty() {
+            namespace::write_namespace_versions(&mut head, &namespaces)?;
+        }
+        namespace::with_file_versions(None, || schema_of::<T>(version))
+            .serialize(&mut Serializer::new_raw(&mut head))?;
+        crate::write_file_atomic(path, 0, |file| Ok(file.write_all(&head)?))?;
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(SaveLog {
+            file,
+            version,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Open the log at the given path for appending, creating it if it doesn't exist.
+    /// An incomplete record at the end of the log, left by an interrupted [SaveLog::append],
+    /// is removed. The log must have been created with the same version as `version`, and with
+    /// the current versions of the namespaces used by T.
+    pub fn open(path: impl AsRef<Path>, version: u32) -> Result<SaveLog<T>, SavefileError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Self::create(path, version);
+        }
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut reader = BufReader::new(file);
+        let (file_version, file_namespaces) = read_log_header::<T>(&mut reader, version)?;
+        if file_version != version {
+            return Err(SavefileError::WrongVersion {
+                msg: format!(
+                    "Log has version {}, so records of version {} can't be appended to it.",
+                    file_version, version
+                ),
+            });
+        }
+        let namespaces = namespace::namespaces_of(|| schema_of::<T>(version))?;
+        if !file_namespaces
+            .iter()
+            .map(|(name, version)| (name.clone(), *version))
+            .eq(namespaces)
+        {
+            return Err(SavefileError::WrongVersion {
+                msg: "Log has other versions of namespaces than those in memory, so records can't be appended to it."
+                    .into(),
+            });
+        }
+        let mut end = reader.stream_position()?;
+        while let Some(payload) = read_record(&mut reader)? {
+            end += RECORD_HEADER_LENGTH + payload.len() as u64;
+        }
+        let mut file = reader.into_inner();
+        file.set_len(end)?;
+        file.seek(SeekFrom::Start(end))?;
+        Ok(SaveLog {
+            file,
+            version,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Append a record to the end of the log.
+    pub fn append(&mut self, data: &T) -> Result<(), SavefileError> {
+        let mut record = vec![0u8; RECORD_HEADER_LENGTH as usize];
+        namespace::with_file_versions(None, || {
+            data.serialize(&mut Serializer {
+                writer: &mut record,
+                version: self.version,
+                encoding: Encoding::default(),
+                interned_strings: HashMap::new(),
+                context: None,
+                progress: None,
+            })
+        })?;
+        let length = record.len() as u64 - RECORD_HEADER_LENGTH;
+        if length > u32::MAX as u64 {
+            return Err(SavefileError::GeneralError {
+                msg: format!("Record of {} bytes is too large for a log", length),
+            });
+        }
+        let checksum = crc32fast::hash(&record[RECORD_HEADER_LENGTH as usize..]);
+        (&mut record[0..4]).write_u32::<LittleEndian>(length as u32)?;
+        (&mut record[4..8]).write_u32::<LittleEndian>(checksum)?;
+        self.file.write_all(&record)?;
+        Ok(())
+    }
+
+    /// Make sure all appended records have reached the storage device.
+    pub fn sync(&self) -> Result<(), SavefileError> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+impl<T: WithSchema + Deserialize> SaveLog<T> {
+    /// Read the records of the log at the given path, in the order they were appended.
+    /// The current version of T in memory must be `version`.
+    pub fn read(path: impl AsRef<Path>, version: u32) -> Result<SaveLogReader<T>, SavefileError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let (file_version, namespaces) = read_log_header::<T>(&mut reader, version)?;
+        Ok(SaveLogReader {
+            reader,
+            file_version,
+            memory_version: version,
+            namespaces,
+            done: false,
+            phantom: PhantomData,
+        })
+    }
+}
+
+impl<T: WithSchema + Deserialize> Iterator for SaveLogReader<T> {
+    type Item = Result<T, SavefileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let payload = match read_record(&mut self.reader) {
+            Ok(Some(payload)) => payload,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(err) => {
+                self.done = true;
+                return Some(Err(err));
+            }
+        };
+        let mut deserializer = Deserializer {
+            reader: &mut &payload[..],
+            file_version: self.file_version,
+            memory_version: self.memory_version,
+            encoding: Encoding::default(),
+            ephemeral_state: HashMap::new(),
+            interned_strings: Vec::new(),
+            scratch: Vec::new(),
+            unchecked_utf8: false,
+            context: None,
+            progress: None,
+            memory_budget: None,
+        };
+        Some(namespace::with_file_versions(Some(self.namespaces.clone()), || {
+            T::deserialize(&mut deserializer)
+        }))
+    }
+}
@@ -0,0 +1,102 @@
+//! A value which keeps track of whether it has changed since it was last saved.
+
+use crate::{load_file, save_file_atomic, Deserialize, SavefileError, Serialize, WithSchema};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+/// A value of type T which keeps track of whether it has been changed since it was saved
+/// or loaded, so it is only saved again when needed. This is useful for settings files and
+/// other small state, which is rarely changed but often checked.
+///
+/// The value is considered changed each time it is borrowed mutably, through [DerefMut],
+/// even if nothing is actually modified.
+#[derive(Debug, Clone)]
+pub struct Saved<T> {
+    value: T,
+    dirty: bool,
+}
+
+impl<T> Saved<T> {
+    /// A value which hasn't been saved yet, so it is dirty
+    pub fn new(value: T) -> Saved<T> {
+        Saved { value, dirty: true }
+    }
+
+    /// True if the value has been borrowed mutably since it was last saved or loaded
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Make the value be saved by the next [Saved::flush_if_dirty]
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Consider the value saved, such as after saving it by other means
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+
+    /// The value
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T: WithSchema + Deserialize> Saved<T> {
+    /// Load the value from the given file, see [crate::load_file]. The loaded value is not dirty.
+    pub fn load(path: impl AsRef<Path>, version: u32) -> Result<Saved<T>, SavefileError> {
+        Ok(Saved {
+            value: load_file(path, version)?,
+            dirty: false,
+        })
+    }
+
+    /// Load the value from the given file, or, if there is no such file, use the value
+    /// returned by `initial`, which is dirty.
+    pub fn load_or_else(
+        path: impl AsRef<Path>,
+        version: u32,
+        initial: impl FnOnce() -> T,
+    ) -> Result<Saved<T>, SavefileError> {
+        if path.as_ref().exists() {
+            Saved::load(path, version)
+        } else {
+            Ok(Saved::new(initial()))
+        }
+    }
+}
+
+impl<T: WithSchema + Serialize> Saved<T> {
+    /// Save the value to the given file if it is dirty, replacing the file atomically, as by
+    /// [crate::save_file_atomic]. Returns true if the value was saved. If saving fails,
+    /// the value stays dirty.
+    pub fn flush_if_dirty(&mut self, path: impl AsRef<Path>, version: u32) -> Result<bool, SavefileError> {
+        if !self.dirty {
+            return Ok(false);
+        }
+        save_file_atomic(path, version, &self.value)?;
+        self.dirty = false;
+        Ok(true)
+    }
+}
+
+impl<T: Default> Default for Saved<T> {
+    fn default() -> Saved<T> {
+        Saved::new(T::default())
+    }
+}
+
+impl<T> Deref for Saved<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Saved<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.value
+    }
+}
@@ -0,0 +1,215 @@
+//! Serializing and deserializing the items of a vector one at a time, without collecting them into a vector.
+
+use crate::{
+    read_vec_length, Deserialize, Deserializer, SavefileError, Serialize, Serializer, VecChunk, CHUNKED_VEC_LENGTH,
+};
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+/// Writes the items of a `Vec<T>` one at a time, as they are produced, for example by a database
+/// cursor. Create it with [Serializer::serialize_seq], add the items with [SeqSerializer::push],
+/// and then call [SeqSerializer::end]. The items can be deserialized as a `Vec<T>`, so this can
+/// be used in the Serialize-implementation of a type whose schema is a vector.
+///
+/// If the number of items is known in advance, it is written in front of them, just like for a
+/// `Vec<T>`, and it is an error to push a different number of items. Otherwise the items are
+/// written in chunks, as by [Serializer::write_chunked]. If the SeqSerializer is dropped
+/// without calling end, the written data is incomplete.
+pub struct SeqSerializer<'s, 'a, W: Write, T> {
+    serializer: &'s mut Serializer<'a, W>,
+    len: Option<usize>,
+    count: usize,
+    chunk: VecChunk,
+    phantom: PhantomData<fn(&T)>,
+}
+
+impl<'s, 'a, W: Write, T: Serialize> SeqSerializer<'s, 'a, W, T> {
+    pub(crate) fn new(
+        serializer: &'s mut Serializer<'a, W>,
+        len: Option<usize>,
+    ) -> Result<SeqSerializer<'s, 'a, W, T>, SavefileError> {
+        match len {
+            Some(len) => serializer.write_length(len as u64)?,
+            None => serializer.write_length(CHUNKED_VEC_LENGTH)?,
+        }
+        Ok(SeqSerializer {
+            serializer,
+            len,
+            count: 0,
+            chunk: VecChunk::new(),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Write an item. Without a known number of items, items are buffered until a whole chunk can be written.
+    pub fn push(&mut self, item: &T) -> Result<(), SavefileError> {
+        match self.len {
+            Some(len) if self.count == len => {
+                return Err(SavefileError::GeneralError {
+                    msg: format!("More items than the {} items the sequence was started with", len),
+                });
+            }
+            Some(_) => item
+                .serialize(self.serializer)
+                .map_err(|err| err.with_validation_path_segment("*"))?,
+            None => self.chunk.push(item, self.serializer)?,
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// The number of items pushed so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Finish the sequence. Fails if fewer items were pushed than the sequence was started with.
+    pub fn end(mut self) -> Result<(), SavefileError> {
+        match self.len {
+            Some(len) if self.count != len => Err(SavefileError::GeneralError {
+                msg: format!("Only {} of the {} items of the sequence were written", self.count, len),
+            }),
+            Some(_) => Ok(()),
+            None => self.chunk.finish(self.serializer),
+        }
+    }
+}
+
+/// Serialize the items produced by an iterator, so they can be deserialized as a `Vec<T>`.
+/// If `len_hint` is given, it must be the exact number of items, see [SeqSerializer].
+///
+/// ```
+/// use savefile::prelude::*;
+///
+/// let mut data = Vec::new();
+/// let mut serializer = Serializer::new_raw(&mut data);
+/// serialize_iter(&mut serializer, Some(3), (1..=3u32).map(|x| x * 10)).unwrap();
+/// serialize_iter(&mut serializer, None, "a b c".split(' ').map(str::to_string)).unwrap();
+///
+/// let mut reader = &data[..];
+/// let mut deserializer = Deserializer::new_raw(&mut reader);
+/// assert_eq!(Vec::<u32>::deserialize(&mut deserializer).unwrap(), vec![10, 20, 30]);
+/// assert_eq!(Vec::<String>::deserialize(&mut deserializer).unwrap(), vec!["a", "b", "c"]);
+/// ```
+pub fn serialize_iter<T: Serialize>(
+    serializer: &mut Serializer<impl Write>,
+    len_hint: Option<usize>,
+    items: impl IntoIterator<Item = T>,
+) -> Result<(), SavefileError> {
+    let mut seq = serializer.serialize_seq(len_hint)?;
+    for item in items {
+        seq.push(&item)?;
+    }
+    seq.end()
+}
+
+/// Reads the items of a `Vec<T>` one at a time, so they can be filtered, aggregated or forwarded
+/// without collecting all of them, see [deserialize_seq]. Both the plain and the chunked encoding
+/// of vectors can be read.
+///
+/// The deserializer is positioned after the vector once all items have been read. After an
+/// error, no more items are returned.
+pub struct SeqDeserializer<'d, 'a, R: Read, T> {
+    deserializer: &'d mut Deserializer<'a, R>,
+    /// Number of items left in the current chunk, or in the whole vector if it isn't chunked
+    remaining: usize,
+    chunked: bool,
+    done: bool,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<'d, 'a, R: Read, T: Deserialize> SeqDeserializer<'d, 'a, R, T> {
+    /// The number of items not yet read, or None if the vector is chunked, in which case
+    /// the number of items isn't known in advance.
+    pub fn remaining(&self) -> Option<usize> {
+        if self.chunked && !self.done {
+            None
+        } else {
+            Some(self.remaining)
+        }
+    }
+
+    /// Read the remaining items and discard them, leaving the deserializer after the vector
+    pub fn finish(self) -> Result<(), SavefileError> {
+        for item in self {
+            item?;
+        }
+        Ok(())
+    }
+
+    fn read_item(&mut self) -> Result<Option<T>, SavefileError> {
+        if self.remaining == 0 {
+            // Only chunked vectors get here, at the start of each chunk
+            match self.deserializer.read_length_usize()? {
+                0 => return Ok(None),
+                chunk_length => self.remaining = chunk_length,
+            }
+        }
+        let item = T::deserialize(self.deserializer).map_err(|err| err.with_path_segment("*"))?;
+        self.remaining -= 1;
+        self.done = self.remaining == 0 && !self.chunked;
+        Ok(Some(item))
+    }
+}
+
+impl<'d, 'a, R: Read, T: Deserialize> Iterator for SeqDeserializer<'d, 'a, R, T> {
+    type Item = Result<T, SavefileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_item() {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            // Nothing sensible can be read after an error
+            Err(err) => {
+                self.done = true;
+                self.remaining = 0;
+                Some(Err(err))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.remaining() {
+            Some(remaining) => (0, Some(remaining)),
+            None => (0, None),
+        }
+    }
+}
+
+/// Start reading a `Vec<T>` one item at a time. The number of items is read immediately,
+/// and the items as the returned [SeqDeserializer] is iterated.
+///
+/// ```
+/// use savefile::prelude::*;
+///
+/// let mut data = Vec::new();
+/// (0..1000u64).collect::<Vec<_>>().serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+/// true.serialize(&mut Serializer::new_raw(&mut data)).unwrap();
+///
+/// let mut reader = &data[..];
+/// let mut deserializer = Deserializer::new_raw(&mut reader);
+/// let mut sum = 0;
+/// for item in deserialize_seq::<u64, _>(&mut deserializer).unwrap() {
+///     sum += item.unwrap();
+/// }
+/// assert_eq!(sum, 499500);
+/// assert_eq!(bool::deserialize(&mut deserializer).unwrap(), true);
+/// ```
+pub fn deserialize_seq<'d, 'a, T: Deserialize, R: Read>(
+    deserializer: &'d mut Deserializer<'a, R>,
+) -> Result<SeqDeserializer<'d, 'a, R, T>, SavefileError> {
+    let length = read_vec_length(deserializer)?;
+    Ok(SeqDeserializer {
+        deserializer,
+        remaining: length.unwrap_or(0),
+        chunked: length.is_none(),
+        done: length == Some(0),
+        phantom: PhantomData,
+    })
+}
@@ -0,0 +1,108 @@
+//! Reading from an in-memory buffer, without the overhead of a general reader.
+
+use crate::Deserializer;
+use std::io::Read;
+use std::marker::PhantomData;
+
+/// A reader of a byte slice, which [crate::load_from_mem] and [crate::load_from_slice] use to
+/// load values. Each read only compares the number of bytes wanted with the number left, and
+/// copies them, which is usually inlined into the Deserialize-implementations. This makes
+/// loading values with many small fields, such as integers, considerably faster than through a
+/// `&[u8]` borrowed by a [Deserializer].
+///
+/// A failed read leaves the reader where it was.
+#[derive(Debug, Clone)]
+pub struct SliceReader<'a> {
+    pos: *const u8,
+    end: *const u8,
+    data: PhantomData<&'a [u8]>,
+}
+
+// Safety: The reader is just a shared borrow of a byte slice
+unsafe impl<'a> Send for SliceReader<'a> {}
+unsafe impl<'a> Sync for SliceReader<'a> {}
+
+impl<'a> SliceReader<'a> {
+    /// A reader starting at the beginning of `data`
+    pub fn new(data: &'a [u8]) -> SliceReader<'a> {
+        let range = data.as_ptr_range();
+        SliceReader {
+            pos: range.start,
+            end: range.end,
+            data: PhantomData,
+        }
+    }
+
+    /// The part of the slice which hasn't been read yet
+    pub fn remaining(&self) -> &'a [u8] {
+        // Safety: pos and end are within the same slice, which is borrowed for 'a
+        unsafe { std::slice::from_raw_parts(self.pos, self.len()) }
+    }
+
+    /// The number of bytes which haven't been read yet
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end as usize - self.pos as usize
+    }
+
+    /// True if all of the slice has been read
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.end
+    }
+
+    /// Move past `len` bytes, returning a pointer to the first of them, if there are that many left
+    #[inline]
+    fn advance(&mut self, len: usize) -> Option<*const u8> {
+        if len > self.len() {
+            return None;
+        }
+        let start = self.pos;
+        // Safety: There are at least len bytes left, so this stays within the slice
+        self.pos = unsafe { self.pos.add(len) };
+        Some(start)
+    }
+}
+
+impl<'a> Read for SliceReader<'a> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.len());
+        if let Some(start) = self.advance(len) {
+            // Safety: advance checked that the len bytes at start are within the slice
+            unsafe { std::ptr::copy_nonoverlapping(start, buf.as_mut_ptr(), len) };
+        }
+        Ok(len)
+    }
+
+    #[inline]
+    fn read_exact(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        match self.advance(buf.len()) {
+            Some(start) => {
+                // Safety: advance checked that the bytes at start are within the slice
+                unsafe { std::ptr::copy_nonoverlapping(start, buf.as_mut_ptr(), buf.len()) };
+                Ok(())
+            }
+            None => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)),
+        }
+    }
+}
+
+/// A [Deserializer] reading from an in-memory buffer, through a [SliceReader]. Files in memory
+/// are loaded this way by [crate::load_from_mem], this is for reading values without a header.
+///
+/// ```
+/// use savefile::prelude::*;
+///
+/// let mut data = Vec::new();
+/// let mut serializer = Serializer::new_raw(&mut data);
+/// 7u32.serialize(&mut serializer).unwrap();
+/// "seven".to_string().serialize(&mut serializer).unwrap();
+///
+/// let mut reader = SliceReader::new(&data);
+/// let mut deserializer = SliceDeserializer::new_raw(&mut reader);
+/// assert_eq!(u32::deserialize(&mut deserializer).unwrap(), 7);
+/// assert_eq!(String::deserialize(&mut deserializer).unwrap(), "seven");
+/// assert!(reader.is_empty());
+/// ```
+pub type SliceDeserializer<'a, 'b> = Deserializer<'b, SliceReader<'a>>;
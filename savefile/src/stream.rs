@@ -0,0 +1,234 @@
+//! Framing of savefile payloads sent over byte streams, such as a `TcpStream`.
+//!
+//! Each message is sent as a frame consisting of the little endian u32 length of the
+//! payload, a flag byte, the payload itself (as written by [crate::save_to_mem]) and,
+//! if the flag byte says so, a CRC32 checksum of the payload. The receiver reads
+//! exactly one frame per message, so many messages can be sent over the same stream.
+//!
+//! Before exchanging messages, peers built with different versions of the message types
+//! can use [handshake] to agree on a version which both of them understand.
+//!
+//! [RpcClient] and [serve] call the methods of an [AbiProvider] at the other end of a stream,
+//! checking the schemas of the methods when connecting, like [crate::AbiConnection] does for
+//! providers in other libraries.
+
+use crate::abi::{call_result, dispatch, MethodTable, DESCRIBE_METHOD};
+use crate::{
+    diff_schema, load_noschema, save_noschema, save_to_mem, AbiMethod, AbiProvider, Deserialize, Deserializer,
+    SavefileError, Schema, Serialize, WithSchema,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+/// Flag signalling that the payload is followed by a checksum
+const FLAG_CHECKSUM: u8 = 1;
+
+fn write_frame(writer: &mut impl Write, payload: &[u8], flags: u8) -> Result<(), SavefileError> {
+    if payload.len() > u32::MAX as usize {
+        return Err(SavefileError::GeneralError {
+            msg: format!("Message of {} bytes is too large to be sent", payload.len()),
+        });
+    }
+    writer.write_u32::<LittleEndian>(payload.len() as u32)?;
+    writer.write_u8(flags)?;
+    writer.write_all(payload)?;
+    if flags & FLAG_CHECKSUM != 0 {
+        writer.write_u32::<LittleEndian>(crc32fast::hash(payload))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_frame(reader: &mut impl Read) -> Result<Vec<u8>, SavefileError> {
+    read_frame_or_end(reader)?.ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into())
+}
+
+/// Read a frame, or return None if the stream ended before it began
+fn read_frame_or_end(reader: &mut impl Read) -> Result<Option<Vec<u8>>, SavefileError> {
+    let mut length_bytes = [0u8; 4];
+    let mut filled = 0;
+    while filled < length_bytes.len() {
+        match reader.read(&mut length_bytes[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            Ok(count) => filled += count,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let length = u32::from_le_bytes(length_bytes) as u64;
+    let flags = reader.read_u8()?;
+    if flags & !FLAG_CHECKSUM != 0 {
+        return Err(SavefileError::GeneralError {
+            msg: format!("Unknown message flags: {}", flags),
+        });
+    }
+    // The length may be corrupt, so it isn't used to preallocate
+    let mut payload = Vec::new();
+    if reader.take(length).read_to_end(&mut payload)? as u64 != length {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    if flags & FLAG_CHECKSUM != 0 {
+        let expected = reader.read_u32::<LittleEndian>()?;
+        let actual = crc32fast::hash(&payload);
+        if expected != actual {
+            return Err(SavefileError::ChecksumMismatch { expected, actual });
+        }
+    }
+    Ok(Some(payload))
+}
+
+/// Send `data` over the stream as a single message. Use [recv_message] to receive it.
+/// The writer is flushed afterwards.
+pub fn send_message<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    write_frame(writer, &save_to_mem(version, data)?, 0)
+}
+
+/// Like [send_message], except a CRC32 checksum of the message is sent along with it,
+/// and verified by [recv_message].
+pub fn send_message_checksummed<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    write_frame(writer, &save_to_mem(version, data)?, FLAG_CHECKSUM)
+}
+
+/// Receive a single message sent by [send_message] or [send_message_checksummed].
+/// Blocks until the whole message has been received.
+/// The current version of T in memory must be `version`.
+pub fn recv_message<T: WithSchema + Deserialize>(reader: &mut impl Read, version: u32) -> Result<T, SavefileError> {
+    let payload = read_frame(reader)?;
+    Deserializer::load::<T>(&mut &payload[..], version)
+}
+
+/// Agree with the peer at the other end of the stream on a version of T to communicate with.
+/// Both peers call this function with the range of versions of T they support. The peers
+/// exchange their ranges, pick the highest version supported by both, and then exchange
+/// their schemas of T at that version, to make sure that they actually agree on its layout.
+///
+/// On success, the agreed version is returned. Messages should then be sent with
+/// [send_message] using the agreed version, and received with [recv_message] using
+/// the current version of T. Note that derived implementations of [Serialize] can't
+/// serialize versions older than the newest field added or removed. Both peers write
+/// before reading, so the stream must be able to buffer a schema's worth of data, as
+/// sockets do.
+pub fn handshake<T: WithSchema>(
+    stream: &mut (impl Read + Write),
+    min_version: u32,
+    max_version: u32,
+) -> Result<u32, SavefileError> {
+    let mut range = Vec::new();
+    save_noschema(&mut range, 0, &(min_version, max_version))?;
+    write_frame(stream, &range, 0)?;
+    let (peer_min_version, peer_max_version): (u32, u32) = load_noschema(&mut &read_frame(stream)?[..], 0)?;
+
+    let version = max_version.min(peer_max_version);
+    if version < min_version.max(peer_min_version) {
+        return Err(SavefileError::IncompatibleSchema {
+            message: format!(
+                "No common version: versions {} to {} are supported, but the peer supports versions {} to {}",
+                min_version, max_version, peer_min_version, peer_max_version
+            ),
+        });
+    }
+
+    let schema = T::schema(version);
+    let mut schema_bytes = Vec::new();
+    save_noschema(&mut schema_bytes, 0, &schema)?;
+    write_frame(stream, &schema_bytes, 0)?;
+    let peer_schema: Schema = load_noschema(&mut &read_frame(stream)?[..], 0)?;
+    if let Some(message) = diff_schema(&schema, &peer_schema, "".to_string()) {
+        return Err(SavefileError::IncompatibleSchema {
+            message: format!("The peer's schema of version {} differs: {}", version, message),
+        });
+    }
+    Ok(version)
+}
+
+fn write_request(writer: &mut impl Write, method: u32, version: u32, arguments: &[u8]) -> Result<(), SavefileError> {
+    let mut request = Vec::with_capacity(arguments.len() + 8);
+    request.write_u32::<LittleEndian>(method)?;
+    request.write_u32::<LittleEndian>(version)?;
+    request.extend_from_slice(arguments);
+    write_frame(writer, &request, 0)
+}
+
+/// Serve calls from an [RpcClient] at the other end of the stream, until the client
+/// closes the stream. Errors and panics of the methods are sent to the client, and
+/// don't stop the server.
+pub fn serve<P: AbiProvider>(stream: &mut (impl Read + Write), provider: &mut P) -> Result<(), SavefileError> {
+    while let Some(request) = read_frame_or_end(stream)? {
+        let mut reader = &request[..];
+        let method = reader.read_u32::<LittleEndian>()?;
+        let version = reader.read_u32::<LittleEndian>()?;
+        let (status, data) = dispatch(provider, method, version, reader);
+        let mut response = Vec::with_capacity(data.len() + 4);
+        response.write_u32::<LittleEndian>(status)?;
+        response.extend_from_slice(&data);
+        write_frame(stream, &response, 0)?;
+    }
+    Ok(())
+}
+
+/// Calls the methods of a provider served by [serve] at the other end of a stream.
+pub struct RpcClient<S: Read + Write> {
+    stream: S,
+    methods: MethodTable,
+}
+
+impl<S: Read + Write> RpcClient<S> {
+    /// Connect to the server at the other end of the stream. `version` is the current version
+    /// of the argument and return types of the client, and `expected` returns the methods the
+    /// client wants to call, using the given version of their types, like [AbiProvider::methods].
+    ///
+    /// Returns an error if the server has a method with the same name as an expected method,
+    /// but different argument or return types. Expected methods which the server doesn't have
+    /// return an error when called.
+    pub fn connect(
+        mut stream: S,
+        version: u32,
+        expected: impl FnOnce(u32) -> Vec<AbiMethod>,
+    ) -> Result<RpcClient<S>, SavefileError> {
+        let description = Self::raw_call(&mut stream, version, DESCRIBE_METHOD, &[])?;
+        let methods = MethodTable::from_description(&description, version, expected)?;
+        Ok(RpcClient { stream, methods })
+    }
+
+    /// The version used for the arguments and return values of calls,
+    /// the oldest of the versions of the client and the server.
+    pub fn version(&self) -> u32 {
+        self.methods.version()
+    }
+
+    /// Returns true if the server has a method with the given name.
+    pub fn has_method(&self, name: &str) -> bool {
+        self.methods.has_method(name)
+    }
+
+    /// Call the method with the given name, and wait for its result. `A` are the arguments,
+    /// usually a tuple, and `R` the return value, as for [AbiMethod::new].
+    pub fn call<A: Serialize, R: Deserialize>(&mut self, name: &str, arguments: &A) -> Result<R, SavefileError> {
+        let method = self.methods.index(name)?;
+        let arguments = self.methods.serialize_arguments(arguments)?;
+        let result = Self::raw_call(&mut self.stream, self.methods.version(), method, &arguments)?;
+        self.methods.deserialize_result(&result)
+    }
+
+    /// Returns the stream, closing the connection.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn raw_call(stream: &mut S, version: u32, method: u32, arguments: &[u8]) -> Result<Vec<u8>, SavefileError> {
+        write_request(stream, method, version, arguments)?;
+        let response = read_frame(stream)?;
+        let mut reader = &response[..];
+        let status = reader.read_u32::<LittleEndian>()?;
+        call_result(status, reader.to_vec())
+    }
+}
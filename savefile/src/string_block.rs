@@ -0,0 +1,174 @@
+//! Vectors of strings saved as all the lengths, followed by all the bytes, instead of string by string.
+
+use crate::{
+    schema_of, CurrentVersion, Deserialize, Deserializer, Field, Introspect, IntrospectItem, SavefileError, Schema, SchemaStruct,
+    Serialize, Serializer, WithSchema,
+};
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut, Range};
+
+/// A vector of strings, `Vec<String>` or `Vec<Vec<u8>>`, which is saved as a vector of the
+/// lengths of all the strings, followed by one block with the bytes of all of them. Loading
+/// then takes two large reads, instead of two small reads per string, and utf8 is validated
+/// once for the whole block. This is much faster for vectors with millions of short strings,
+/// such as the lines of a dialogue database.
+///
+/// The vector is used through [Deref] and [DerefMut]. In the schema, it is a struct with the
+/// vectors `lengths` and `data`. Loading fails if the lengths don't add up to the size of the
+/// data. Strings are not interned, even if the serializer interns strings.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StringBlock<V>(pub V);
+
+impl<V> StringBlock<V> {
+    /// The vector
+    pub fn into_inner(self) -> V {
+        self.0
+    }
+}
+
+impl<V> Deref for StringBlock<V> {
+    type Target = V;
+    fn deref(&self) -> &V {
+        &self.0
+    }
+}
+
+impl<V> DerefMut for StringBlock<V> {
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.0
+    }
+}
+
+fn string_block_schema(version: u32) -> Schema {
+    Schema::Struct(SchemaStruct {
+        dbg_name: "StringBlock".to_string(),
+        fields: vec![
+            Field {
+                name: "lengths".to_string(),
+                value: schema_of::<Vec<u64>>(version),
+            },
+            Field {
+                name: "data".to_string(),
+                value: schema_of::<Vec<u8>>(version),
+            },
+        ],
+    })
+}
+
+/// Serialize the lengths of the strings, and then their bytes, like a `Vec<u8>` containing all of them
+fn serialize_block<'a>(
+    strings: impl Iterator<Item = &'a [u8]> + Clone,
+    serializer: &mut Serializer<impl Write>,
+) -> Result<(), SavefileError> {
+    let lengths: Vec<u64> = strings.clone().map(|string| string.len() as u64).collect();
+    lengths.serialize(serializer)?;
+    serializer.write_length(strings.clone().map(|string| string.len() as u64).sum())?;
+    for string in strings {
+        serializer.write_buf(string)?;
+    }
+    Ok(())
+}
+
+/// Deserialize the lengths and data saved by [serialize_block]. Returns the data, which is all the
+/// strings concatenated, and the range of each string within it.
+fn deserialize_block(
+    deserializer: &mut Deserializer<impl Read>,
+) -> Result<(Vec<u8>, Vec<Range<usize>>), SavefileError> {
+    let lengths = Vec::<u64>::deserialize(deserializer).map_err(|err| err.with_path_segment("lengths"))?;
+    let data = Vec::<u8>::deserialize(deserializer).map_err(|err| err.with_path_segment("data"))?;
+    let mut ranges = Vec::with_capacity(lengths.len());
+    let mut start = 0usize;
+    for length in lengths {
+        let end = usize::try_from(length)
+            .ok()
+            .and_then(|length| start.checked_add(length))
+            .filter(|end| *end <= data.len())
+            .ok_or_else(|| SavefileError::GeneralError {
+                msg: format!("The strings are longer than their {} bytes of data", data.len()),
+            })?;
+        ranges.push(start..end);
+        start = end;
+    }
+    if start != data.len() {
+        return Err(SavefileError::GeneralError {
+            msg: format!("The strings have {} bytes, but {} bytes of data", start, data.len()),
+        });
+    }
+    // The strings are copied out of the data, so it is needed twice
+    deserializer.account_memory(data.len() as u64)?;
+    Ok((data, ranges))
+}
+
+impl WithSchema for StringBlock<Vec<String>> {
+    fn schema(version: u32) -> Schema {
+        string_block_schema(version)
+    }
+}
+
+impl Serialize for StringBlock<Vec<String>> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_block(self.0.iter().map(|string| string.as_bytes()), serializer)
+    }
+}
+
+impl Deserialize for StringBlock<Vec<String>> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let (data, ranges) = deserialize_block(deserializer)?;
+        deserializer.account_items::<String>(ranges.len())?;
+        let text = if deserializer.unchecked_utf8 {
+            // Safety: the caller of set_unchecked_utf8 guarantees that strings are valid utf8
+            unsafe { std::str::from_utf8_unchecked(&data) }
+        } else {
+            let text = std::str::from_utf8(&data)?;
+            // Valid utf8 split at character boundaries gives valid utf8 pieces
+            if ranges.iter().any(|range| !text.is_char_boundary(range.start)) {
+                return Err(SavefileError::GeneralError {
+                    msg: "A string of the block is not valid utf8".into(),
+                });
+            }
+            text
+        };
+        Ok(StringBlock(
+            ranges.into_iter().map(|range| text[range].to_string()).collect(),
+        ))
+    }
+}
+
+impl WithSchema for StringBlock<Vec<Vec<u8>>> {
+    fn schema(version: u32) -> Schema {
+        string_block_schema(version)
+    }
+}
+
+impl Serialize for StringBlock<Vec<Vec<u8>>> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serialize_block(self.0.iter().map(|bytes| bytes.as_slice()), serializer)
+    }
+}
+
+impl Deserialize for StringBlock<Vec<Vec<u8>>> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let (data, ranges) = deserialize_block(deserializer)?;
+        deserializer.account_items::<Vec<u8>>(ranges.len())?;
+        Ok(StringBlock(
+            ranges.into_iter().map(|range| data[range].to_vec()).collect(),
+        ))
+    }
+}
+
+impl<V: Introspect> Introspect for StringBlock<V> {
+    fn introspect_value(&self) -> String {
+        self.0.introspect_value()
+    }
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        self.0.introspect_child(index)
+    }
+    fn introspect_len(&self) -> usize {
+        self.0.introspect_len()
+    }
+}
+
+impl<V: CurrentVersion> CurrentVersion for StringBlock<V> {
+    const SAVEFILE_MAX_VERSION: u32 = V::SAVEFILE_MAX_VERSION;
+}
@@ -0,0 +1,132 @@
+//! Objects of different types written one after another to the same stream, each preceded
+//! by a tag saying what type it is.
+
+use crate::{save, Deserialize, Deserializer, SavefileError, Serialize, WithSchema};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+type LoadFn = Box<dyn Fn(&mut dyn Read) -> Result<Box<dyn Any>, SavefileError> + Send + Sync>;
+
+struct TaggedType {
+    version: u32,
+    load: LoadFn,
+}
+
+/// The types which can be saved by [save_tagged] and loaded by [load_tagged_any], and the
+/// tags identifying them in the stream.
+///
+/// The writer and the reader of a stream must register the same types with the same tags.
+/// Each object is saved with its schema, as by [crate::save], so a type which has different
+/// fields at the two ends is detected when loading.
+///
+/// ```
+/// extern crate savefile;
+/// #[macro_use]
+/// extern crate savefile_derive;
+/// use savefile::prelude::*;
+///
+/// #[derive(Savefile, Debug, PartialEq)]
+/// struct Ping {
+///     sequence: u32,
+/// }
+/// #[derive(Savefile, Debug, PartialEq)]
+/// struct Status {
+///     message: String,
+/// }
+///
+/// fn main() {
+///     let tags = TypeTags::new().with_type::<Ping>(1, 0).with_type::<Status>(2, 0);
+///     let mut pipe = Vec::new();
+///     save_tagged(&mut pipe, &tags, &Ping { sequence: 7 }).unwrap();
+///     save_tagged(&mut pipe, &tags, &Status { message: "ok".into() }).unwrap();
+///
+///     let mut reader = &pipe[..];
+///     while let Some(object) = load_tagged_any(&mut reader, &tags).unwrap() {
+///         if let Some(ping) = object.downcast_ref::<Ping>() {
+///             assert_eq!(ping.sequence, 7);
+///         } else if let Some(status) = object.downcast_ref::<Status>() {
+///             assert_eq!(status.message, "ok");
+///         }
+///     }
+/// }
+/// ```
+#[derive(Default)]
+pub struct TypeTags {
+    types: HashMap<u32, TaggedType>,
+    tags: HashMap<TypeId, u32>,
+}
+
+impl TypeTags {
+    /// No registered types
+    pub fn new() -> TypeTags {
+        TypeTags::default()
+    }
+
+    /// Register T with the given tag. Objects of type T are saved and loaded with the given
+    /// version, which must be the current version of T.
+    ///
+    /// Panics if the tag or the type has already been registered.
+    pub fn with_type<T: WithSchema + Serialize + Deserialize + 'static>(mut self, tag: u32, version: u32) -> TypeTags {
+        assert!(!self.types.contains_key(&tag), "The type tag {} is already in use", tag);
+        assert!(
+            self.tags.insert(TypeId::of::<T>(), tag).is_none(),
+            "The type {} has already been registered",
+            std::any::type_name::<T>()
+        );
+        self.types.insert(
+            tag,
+            TaggedType {
+                version,
+                load: Box::new(move |reader| {
+                    let value: T = Deserializer::load(&mut &mut *reader, version)?;
+                    Ok(Box::new(value))
+                }),
+            },
+        );
+        self
+    }
+
+    /// The tag of T, if it has been registered
+    pub fn tag_of<T: 'static>(&self) -> Option<u32> {
+        self.tags.get(&TypeId::of::<T>()).copied()
+    }
+}
+
+/// Save `data` to the writer, preceded by the tag of its type. Any number of objects
+/// of registered types can be saved to the same writer, and loaded by [load_tagged_any].
+/// Fails if the type of `data` hasn't been registered.
+pub fn save_tagged<T: WithSchema + Serialize + 'static>(
+    writer: &mut impl Write,
+    tags: &TypeTags,
+    data: &T,
+) -> Result<(), SavefileError> {
+    let tag = tags.tag_of::<T>().ok_or_else(|| SavefileError::GeneralError {
+        msg: format!("The type {} has no type tag", std::any::type_name::<T>()),
+    })?;
+    writer.write_u32::<LittleEndian>(tag)?;
+    save(writer, tags.types[&tag].version, data)
+}
+
+/// Load the next object saved by [save_tagged], as the type registered for its tag.
+/// Use [Any::downcast] or [Any::downcast_ref] to find out which type it is. Returns None
+/// if the reader is at its end.
+pub fn load_tagged_any(reader: &mut impl Read, tags: &TypeTags) -> Result<Option<Box<dyn Any>>, SavefileError> {
+    let mut tag_bytes = [0u8; 4];
+    let mut filled = 0;
+    while filled < tag_bytes.len() {
+        match reader.read(&mut tag_bytes[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            Ok(count) => filled += count,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let tag = u32::from_le_bytes(tag_bytes);
+    let tagged_type = tags.types.get(&tag).ok_or_else(|| SavefileError::GeneralError {
+        msg: format!("Unknown type tag {}", tag),
+    })?;
+    Ok(Some((tagged_type.load)(reader)?))
+}
@@ -0,0 +1,77 @@
+//! Async versions of the load and save functions, for use with tokio.
+//!
+//! The data is serialized to, and deserialized from, memory. Only the I/O is asynchronous.
+//! Since files saved to memory record the length of their payload in the header, several
+//! values can be written to the same stream one after another, and read back one at a time.
+
+use crate::{save_to_mem, Deserialize, Deserializer, SavefileError, SavefileHeader, Serialize, WithSchema, HEADER_LENGTH};
+#[cfg(feature = "fs")]
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Length of a header of file format version 0
+const FORMAT_0_HEADER_LENGTH: usize = 16;
+
+/// Like [crate::save], except it writes to an AsyncWrite.
+pub async fn save_async<T: WithSchema + Serialize>(
+    writer: &mut (impl AsyncWrite + Unpin),
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    let bytes = save_to_mem(version, data)?;
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Like [crate::load], except it reads from an AsyncRead.
+/// If the length of the payload is recorded in the header, as it is for data written by
+/// [crate::save_async], exactly one value is read from the reader. Otherwise, the
+/// reader is read until its end.
+pub async fn load_async<T: WithSchema + Deserialize>(
+    reader: &mut (impl AsyncRead + Unpin),
+    version: u32,
+) -> Result<T, SavefileError> {
+    let mut bytes = vec![0u8; FORMAT_0_HEADER_LENGTH];
+    reader.read_exact(&mut bytes).await?;
+    if bytes[9..11] != [0, 0] {
+        bytes.resize(HEADER_LENGTH as usize, 0);
+        reader.read_exact(&mut bytes[FORMAT_0_HEADER_LENGTH..]).await?;
+    }
+    let header = SavefileHeader::read(&mut &bytes[..])?;
+    match header.payload_length {
+        Some(payload_length) => {
+            // The length may be corrupt, so it isn't used to preallocate
+            let read = (&mut *reader).take(payload_length).read_to_end(&mut bytes).await?;
+            if (read as u64) < payload_length {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+        None => {
+            reader.read_to_end(&mut bytes).await?;
+        }
+    }
+    Deserializer::load::<T>(&mut &bytes[..], version)
+}
+
+/// Like [crate::save_file], except the file is written asynchronously.
+#[cfg(feature = "fs")]
+pub async fn save_file_async<T: WithSchema + Serialize>(
+    filepath: impl AsRef<Path>,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    let bytes = save_to_mem(version, data)?;
+    tokio::fs::write(filepath, bytes).await?;
+    Ok(())
+}
+
+/// Like [crate::load_file], except the file is read asynchronously.
+#[cfg(feature = "fs")]
+pub async fn load_file_async<T: WithSchema + Deserialize>(
+    filepath: impl AsRef<Path>,
+    version: u32,
+) -> Result<T, SavefileError> {
+    let bytes = tokio::fs::read(filepath).await?;
+    Deserializer::load::<T>(&mut &bytes[..], version)
+}
@@ -0,0 +1,347 @@
+//! Serialization of boxed trait objects, such as `Box<dyn Component>`, by looking up the
+//! concrete type of each object in a registry.
+
+use crate::{
+    schema_of, Deserialize, Deserializer, Field, Introspect, SavefileError, Schema, SchemaStruct, Serialize,
+    Serializer, WithSchema,
+};
+use std::any::TypeId;
+use std::io::{Read, Write};
+
+/// Deserializes a registered type, and converts it to a boxed trait object
+type DeserializeFn<T> = fn(&mut Deserializer<&[u8]>) -> Result<Box<T>, SavefileError>;
+
+/// A type registered in a [TypeRegistry], with functions to handle it as a `T`
+struct RegisteredType<T: ?Sized + 'static> {
+    name: &'static str,
+    type_id: TypeId,
+    schema_hash: fn(u32) -> u64,
+    serialize: fn(&T, &mut Serializer<Vec<u8>>) -> Result<(), SavefileError>,
+    deserialize: DeserializeFn<T>,
+    introspect: fn(&T) -> &dyn Introspect,
+}
+
+impl<T: ?Sized> Clone for RegisteredType<T> {
+    fn clone(&self) -> RegisteredType<T> {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for RegisteredType<T> {}
+
+/// A trait whose boxed trait objects can be saved and loaded. Implemented for `dyn Trait` by
+/// [crate::savefile_trait_object], which also implements the savefile traits for `Box<dyn Trait>`.
+pub trait SavefileTraitObject: 'static {
+    /// The concrete types which can be saved as this trait
+    fn savefile_registry() -> &'static TypeRegistry<Self>;
+}
+
+/// The concrete types which can be saved and loaded as boxed trait objects of type `Box<T>`,
+/// where T is `dyn Trait`, with a stable name for each.
+///
+/// Each object is saved as the name of its type, the hash of the schema of the type, and the
+/// data of the object. When loading, the name says which type to load the object as, and
+/// the schema hash is compared to the schema of that type, see [crate::Schema::stable_hash].
+/// The names must not change, or files saved with the old names can't be loaded.
+///
+/// Use [crate::savefile_trait_object] to create the registry of a trait, and
+/// [crate::register_savefile_type] to add types to it. All types must be registered before
+/// objects of them are saved or loaded, typically at the start of the program.
+///
+/// ```
+/// extern crate savefile;
+/// #[macro_use]
+/// extern crate savefile_derive;
+/// use savefile::prelude::*;
+/// use std::any::Any;
+///
+/// trait Component: Any {
+///     fn weight(&self) -> u32;
+/// }
+///
+/// #[derive(Savefile)]
+/// struct Wheel {
+///     diameter: u32,
+/// }
+/// impl Component for Wheel {
+///     fn weight(&self) -> u32 {
+///         self.diameter * 2
+///     }
+/// }
+///
+/// #[derive(Savefile)]
+/// struct Car {
+///     parts: Vec<Box<dyn Component>>,
+/// }
+///
+/// savefile::savefile_trait_object!(Component);
+///
+/// fn main() {
+///     savefile::register_savefile_type!(Component, Wheel, "wheel");
+///     let car = Car {
+///         parts: vec![Box::new(Wheel { diameter: 16 })],
+///     };
+///     let loaded: Car = load_from_mem(&save_to_mem(0, &car).unwrap(), 0).unwrap();
+///     assert_eq!(loaded.parts[0].weight(), 32);
+/// }
+/// ```
+pub struct TypeRegistry<T: ?Sized + 'static> {
+    types: parking_lot::Mutex<Vec<RegisteredType<T>>>,
+    type_id_of: fn(&T) -> TypeId,
+}
+
+impl<T: ?Sized + 'static> TypeRegistry<T> {
+    /// An empty registry. Use [crate::savefile_trait_object] instead of calling this directly.
+    ///
+    /// # Safety
+    /// `type_id_of` must return the [TypeId] of the concrete type of the object, since
+    /// objects are cast to the registered type with that id.
+    pub const unsafe fn new(type_id_of: fn(&T) -> TypeId) -> TypeRegistry<T> {
+        TypeRegistry {
+            types: parking_lot::const_mutex(Vec::new()),
+            type_id_of,
+        }
+    }
+
+    /// Register the type C, with the given name. `deserialize` deserializes a C, and converts
+    /// it to a boxed trait object. Use [crate::register_savefile_type] instead of calling this
+    /// directly. Registering the same type with the same name again has no effect.
+    ///
+    /// Panics if the name is used by another type, or if the type has another name.
+    pub fn register<C: WithSchema + Serialize + Introspect + 'static>(
+        &self,
+        name: &'static str,
+        deserialize: DeserializeFn<T>,
+    ) {
+        let mut types = self.types.lock();
+        let type_id = TypeId::of::<C>();
+        if let Some(existing) = types.iter().find(|t| t.name == name || t.type_id == type_id) {
+            assert!(
+                existing.name == name && existing.type_id == type_id,
+                "Can't register {} as {:?}, since {:?} is already registered for {}",
+                std::any::type_name::<C>(),
+                name,
+                existing.name,
+                if existing.type_id == type_id {
+                    "it"
+                } else {
+                    "another type"
+                }
+            );
+            return;
+        }
+        // Only called with objects whose concrete type is C, see `find_type`
+        fn downcast<T: ?Sized, C>(object: &T) -> &C {
+            unsafe { &*(object as *const T as *const C) }
+        }
+        types.push(RegisteredType {
+            name,
+            type_id,
+            schema_hash: |version| schema_of::<C>(version).stable_hash(),
+            serialize: |object, serializer| downcast::<T, C>(object).serialize(serializer),
+            deserialize,
+            introspect: |object| downcast::<T, C>(object),
+        });
+    }
+
+    /// The registered type of the object, copied, so that the lock isn't held when it is used
+    fn find_type(&self, object: &T) -> Result<RegisteredType<T>, SavefileError> {
+        let type_id = (self.type_id_of)(object);
+        match self.types.lock().iter().find(|t| t.type_id == type_id) {
+            Some(registered) => Ok(*registered),
+            None => Err(SavefileError::GeneralError {
+                msg: format!(
+                    "The type of the object hasn't been registered for {}",
+                    std::any::type_name::<T>()
+                ),
+            }),
+        }
+    }
+
+    /// The name the concrete type of the object was registered with, if it was registered
+    pub fn name_of(&self, object: &T) -> Option<&'static str> {
+        self.find_type(object).ok().map(|registered| registered.name)
+    }
+
+    /// The schema of `Box<T>`, which is the same for all registered types
+    pub fn schema(&self, version: u32) -> Schema {
+        Schema::Struct(SchemaStruct {
+            dbg_name: format!("Box<{}>", std::any::type_name::<T>()),
+            fields: vec![
+                Field {
+                    name: "type_name".to_string(),
+                    value: schema_of::<String>(version),
+                },
+                Field {
+                    name: "schema_hash".to_string(),
+                    value: schema_of::<u64>(version),
+                },
+                Field {
+                    name: "data".to_string(),
+                    value: schema_of::<Vec<u8>>(version),
+                },
+            ],
+        })
+    }
+
+    /// Serialize the object as its registered type
+    pub fn serialize(&self, object: &T, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        let registered = self.find_type(object)?;
+        let mut data = Vec::new();
+        let mut object_serializer = Serializer {
+            writer: &mut data,
+            version: serializer.version,
+            encoding: serializer.encoding,
+            interned_strings: std::mem::take(&mut serializer.interned_strings),
+            context: serializer.context.as_deref_mut(),
+            progress: None,
+        };
+        let result = (registered.serialize)(object, &mut object_serializer);
+        serializer.interned_strings = std::mem::take(&mut object_serializer.interned_strings);
+        result?;
+        serializer.write_string(registered.name)?;
+        (registered.schema_hash)(serializer.version).serialize(serializer)?;
+        data.serialize(serializer)
+    }
+
+    /// Deserialize an object saved by [TypeRegistry::serialize], as the type registered with
+    /// the saved name.
+    pub fn deserialize(&self, deserializer: &mut Deserializer<impl Read>) -> Result<Box<T>, SavefileError> {
+        let name = String::deserialize(deserializer)?;
+        let schema_hash = u64::deserialize(deserializer)?;
+        let data = Vec::<u8>::deserialize(deserializer)?;
+        let registered = match self.types.lock().iter().find(|t| t.name == name) {
+            Some(registered) => *registered,
+            None => {
+                return Err(SavefileError::GeneralError {
+                    msg: format!(
+                        "No type named {:?} has been registered for {}",
+                        name,
+                        std::any::type_name::<T>()
+                    ),
+                })
+            }
+        };
+        let memory_hash = (registered.schema_hash)(deserializer.file_version);
+        if schema_hash != memory_hash {
+            return Err(SavefileError::IncompatibleSchema {
+                message: format!(
+                    "Saved schema hash {:016x} of {:?} differs from hash {:016x} of in-memory schema for version {}",
+                    schema_hash, name, memory_hash, deserializer.file_version
+                ),
+            });
+        }
+        let mut reader = &data[..];
+        let mut object_deserializer = Deserializer {
+            reader: &mut reader,
+            file_version: deserializer.file_version,
+            memory_version: deserializer.memory_version,
+            encoding: deserializer.encoding,
+            ephemeral_state: std::mem::take(&mut deserializer.ephemeral_state),
+            interned_strings: std::mem::take(&mut deserializer.interned_strings),
+            scratch: std::mem::take(&mut deserializer.scratch),
+            unchecked_utf8: deserializer.unchecked_utf8,
+            context: deserializer.context.as_deref_mut(),
+            progress: None,
+            memory_budget: deserializer.memory_budget,
+        };
+        let result = (registered.deserialize)(&mut object_deserializer);
+        deserializer.memory_budget = object_deserializer.memory_budget;
+        deserializer.ephemeral_state = std::mem::take(&mut object_deserializer.ephemeral_state);
+        deserializer.interned_strings = std::mem::take(&mut object_deserializer.interned_strings);
+        deserializer.scratch = std::mem::take(&mut object_deserializer.scratch);
+        let object = result?;
+        if !reader.is_empty() {
+            return Err(SavefileError::GeneralError {
+                msg: format!("There are bytes left after the object of type {:?}", name),
+            });
+        }
+        Ok(object)
+    }
+
+    /// The registered type of the object, as [Introspect], or None if it isn't registered
+    pub fn introspect<'a>(&self, object: &'a T) -> Option<&'a dyn Introspect> {
+        self.find_type(object)
+            .ok()
+            .map(|registered| (registered.introspect)(object))
+    }
+}
+
+/// Make boxed trait objects of the given trait, `Box<dyn Trait>`, serializable, by implementing
+/// [SavefileTraitObject] for `dyn Trait`, and the savefile traits for `Box<dyn Trait>`.
+/// The trait must have [std::any::Any] as a supertrait. Use [crate::register_savefile_type]
+/// to register the types which can be saved. See [TypeRegistry].
+#[macro_export]
+macro_rules! savefile_trait_object {
+    ($trait:path) => {
+        impl $crate::SavefileTraitObject for dyn $trait {
+            fn savefile_registry() -> &'static $crate::TypeRegistry<dyn $trait> {
+                // Safety: the trait has Any as a supertrait, so type_id gives the concrete type
+                static REGISTRY: $crate::TypeRegistry<dyn $trait> =
+                    unsafe { $crate::TypeRegistry::new(|object| <dyn $trait as ::std::any::Any>::type_id(object)) };
+                &REGISTRY
+            }
+        }
+        // The registered types are only known at run time, so they don't affect the current
+        // version of types containing the trait objects
+        impl $crate::CurrentVersion for dyn $trait {
+            const SAVEFILE_MAX_VERSION: u32 = 0;
+        }
+        impl $crate::WithSchema for Box<dyn $trait> {
+            fn schema(version: u32) -> $crate::Schema {
+                <dyn $trait as $crate::SavefileTraitObject>::savefile_registry().schema(version)
+            }
+        }
+        impl $crate::Serialize for Box<dyn $trait> {
+            fn serialize(
+                &self,
+                serializer: &mut $crate::Serializer<impl ::std::io::Write>,
+            ) -> Result<(), $crate::SavefileError> {
+                <dyn $trait as $crate::SavefileTraitObject>::savefile_registry().serialize(&**self, serializer)
+            }
+        }
+        impl $crate::Deserialize for Box<dyn $trait> {
+            fn deserialize(
+                deserializer: &mut $crate::Deserializer<impl ::std::io::Read>,
+            ) -> Result<Self, $crate::SavefileError> {
+                <dyn $trait as $crate::SavefileTraitObject>::savefile_registry().deserialize(deserializer)
+            }
+        }
+        impl $crate::Introspect for Box<dyn $trait> {
+            fn introspect_value(&self) -> String {
+                let registry = <dyn $trait as $crate::SavefileTraitObject>::savefile_registry();
+                match registry.introspect(&**self) {
+                    Some(object) => object.introspect_value(),
+                    None => "<unregistered type>".to_string(),
+                }
+            }
+            fn introspect_child<'a>(&'a self, index: usize) -> Option<Box<dyn $crate::IntrospectItem<'a> + 'a>> {
+                <dyn $trait as $crate::SavefileTraitObject>::savefile_registry()
+                    .introspect(&**self)?
+                    .introspect_child(index)
+            }
+            fn introspect_len(&self) -> usize {
+                match <dyn $trait as $crate::SavefileTraitObject>::savefile_registry().introspect(&**self) {
+                    Some(object) => object.introspect_len(),
+                    None => 0,
+                }
+            }
+        }
+    };
+}
+
+/// Register a type which implements the trait, so that `Box<dyn Trait>` objects of it can be
+/// saved and loaded. The name is saved with each object, and must not change.
+/// The trait must have been made serializable with [crate::savefile_trait_object].
+/// See [TypeRegistry] for an example.
+#[macro_export]
+macro_rules! register_savefile_type {
+    ($trait:path, $type:ty, $name:expr) => {
+        <dyn $trait as $crate::SavefileTraitObject>::savefile_registry().register::<$type>(
+            $name,
+            |deserializer| -> Result<Box<dyn $trait>, $crate::SavefileError> {
+                Ok(Box::new(<$type as $crate::Deserialize>::deserialize(deserializer)?))
+            },
+        )
+    };
+}
@@ -0,0 +1,427 @@
+//! Writing and reading files containing a `Vec<T>` one item at a time, for
+//! collections which are too large to be kept in memory. Files containing maps
+//! can be read one entry at a time.
+
+use crate::namespace::{self, NamespaceVersions};
+use crate::{
+    check_file_schema, schema_of, ChecksumReader, CompressionMethod, Deserialize, Deserializer, Encoding, Field,
+    SavefileError, SavefileHeader, Schema, SchemaStruct, Serialize, Serializer, VecChunk, WithSchema,
+    CHUNKED_VEC_LENGTH, HEADER_LENGTH, PAYLOAD_LENGTH_OFFSET,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+
+/// Writes a file containing a `Vec<T>`, one item at a time. The file can be loaded
+/// as a `Vec<T>` by [crate::load], or read one item at a time by [VecReader].
+///
+/// The number of items is written in front of them, so the writer must be seekable,
+/// to fill it in when [VecWriter::finish] is called. If the VecWriter is dropped
+/// without calling finish, the file claims to contain no items.
+pub struct VecWriter<W: Write + Seek, T> {
+    writer: W,
+    version: u32,
+    start: u64,
+    length_offset: u64,
+    count: u64,
+    phantom: PhantomData<fn(&T)>,
+}
+
+/// Write the header of a file containing a `Vec<T>`, and the schema.
+fn write_vec_header<T: WithSchema>(writer: &mut impl Write, version: u32) -> Result<(), SavefileError> {
+    let namespaces = namespace::namespaces_of(|| schema_of::<Vec<T>>(version))?;
+    SavefileHeader {
+        format_version: namespace::format_version(&namespaces),
+        version,
+        compression: CompressionMethod::None,
+        has_schema: Some(true),
+        has_checksum: false,
+        encrypted: false,
+        has_schema_hash: false,
+        has_schema_history: false,
+        payload_length: None,
+        created: crate::creation_time(),
+        encoding: Encoding::default(),
+    }
+    .write(writer)?;
+    if !namespaces.is_empty() {
+        namespace::write_namespace_versions(writer, &namespaces)?;
+    }
+    namespace::with_file_versions(None, || <Vec<T>>::schema(version)).serialize(&mut Serializer::new_raw(writer))
+}
+
+impl<W: Write + Seek, T: WithSchema + Serialize> VecWriter<W, T> {
+    /// Write the header and schema of the file.
+    /// The current version of T in memory must be `version`.
+    pub fn new(mut writer: W, version: u32) -> Result<VecWriter<W, T>, SavefileError> {
+        let start = writer.stream_position()?;
+        write_vec_header::<T>(&mut writer, version)?;
+        let length_offset = writer.stream_position()?;
+        writer.write_u64::<LittleEndian>(0)?;
+        Ok(VecWriter {
+            writer,
+            version,
+            start,
+            length_offset,
+            count: 0,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Write an item
+    pub fn push(&mut self, item: &T) -> Result<(), SavefileError> {
+        item.serialize(&mut Serializer {
+            writer: &mut self.writer,
+            version: self.version,
+            encoding: Encoding::default(),
+            interned_strings: HashMap::new(),
+            context: None,
+            progress: None,
+        })?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// The number of items written so far
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    /// True if no items have been written
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Fill in the number of items and the payload length, and return the writer.
+    pub fn finish(mut self) -> Result<W, SavefileError> {
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.length_offset))?;
+        self.writer.write_u64::<LittleEndian>(self.count)?;
+        self.writer.seek(SeekFrom::Start(self.start + PAYLOAD_LENGTH_OFFSET))?;
+        self.writer
+            .write_u64::<LittleEndian>(end - self.start - HEADER_LENGTH)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Like [VecWriter], except the writer doesn't have to be seekable, such as when writing
+/// to a pipe or socket. Instead of writing the number of items in front of them, the items
+/// are written in chunks, as done by [crate::Serializer::write_chunked].
+pub struct ChunkedVecWriter<W: Write, T> {
+    writer: W,
+    version: u32,
+    chunk: VecChunk,
+    phantom: PhantomData<fn(&T)>,
+}
+
+impl<W: Write, T: WithSchema + Serialize> ChunkedVecWriter<W, T> {
+    /// Write the header and schema of the file.
+    /// The current version of T in memory must be `version`.
+    pub fn new(mut writer: W, version: u32) -> Result<ChunkedVecWriter<W, T>, SavefileError> {
+        write_vec_header::<T>(&mut writer, version)?;
+        writer.write_u64::<LittleEndian>(CHUNKED_VEC_LENGTH)?;
+        Ok(ChunkedVecWriter {
+            writer,
+            version,
+            chunk: VecChunk::new(),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Write an item. Items are buffered until a whole chunk can be written.
+    pub fn push(&mut self, item: &T) -> Result<(), SavefileError> {
+        self.chunk.push(
+            item,
+            &mut Serializer {
+                writer: &mut self.writer,
+                version: self.version,
+                encoding: Encoding::default(),
+                interned_strings: HashMap::new(),
+                context: None,
+                progress: None,
+            },
+        )
+    }
+
+    /// Write the remaining items and the end of the sequence, and return the writer.
+    pub fn finish(mut self) -> Result<W, SavefileError> {
+        self.chunk.finish(&mut Serializer {
+            writer: &mut self.writer,
+            version: self.version,
+            encoding: Encoding::default(),
+            interned_strings: HashMap::new(),
+            context: None,
+            progress: None,
+        })?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads a file containing a `Vec<T>` one item at a time, as an iterator.
+/// This works for files written by [VecWriter], as well as by [crate::save], as long as
+/// they aren't compressed, encrypted or checksummed.
+pub struct VecReader<R: Read, T> {
+    reader: R,
+    file_version: u32,
+    memory_version: u32,
+    encoding: Encoding,
+    /// The versions of the namespaces in the file, see [crate::VersionNamespace]
+    namespaces: NamespaceVersions,
+    /// The strings read so far, if strings are interned
+    interned_strings: Vec<String>,
+    /// The scratch buffer of the deserializer, reused for all items
+    scratch: Vec<u8>,
+    /// Number of items left in the current chunk, or in the whole vector if it isn't chunked
+    remaining: u64,
+    chunked: bool,
+    done: bool,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<R: Read, T: WithSchema + Deserialize> VecReader<R, T> {
+    /// Read the header and schema of the file, and verify the schema.
+    /// The current version of T in memory must be `version`.
+    pub fn new(mut reader: R, version: u32) -> Result<VecReader<R, T>, SavefileError> {
+        let header = SavefileHeader::read(&mut reader)?;
+        if header.version > version {
+            return Err(SavefileError::WrongVersion {
+                msg: format!(
+                    "File has later version ({}) than structs in memory ({}).",
+                    header.version, version
+                ),
+            });
+        }
+        if header.compression != CompressionMethod::None || header.encrypted || header.has_checksum {
+            return Err(SavefileError::GeneralError {
+                msg: "Compressed, encrypted or checksummed files can't be read one item at a time".into(),
+            });
+        }
+        let namespaces = check_file_schema::<Vec<T>, _>(
+            &mut ChecksumReader::new(&mut reader, &header)?,
+            &header,
+            header.has_schema.unwrap_or(true),
+            true,
+            true,
+            None,
+        )?;
+        let length = Deserializer {
+            reader: &mut reader,
+            file_version: header.version,
+            memory_version: version,
+            encoding: header.encoding,
+            ephemeral_state: HashMap::new(),
+            interned_strings: Vec::new(),
+            scratch: Vec::new(),
+            unchecked_utf8: false,
+            context: None,
+            progress: None,
+            memory_budget: None,
+        }
+        .read_length()?;
+        let chunked = length == CHUNKED_VEC_LENGTH;
+        Ok(VecReader {
+            reader,
+            file_version: header.version,
+            memory_version: version,
+            encoding: header.encoding,
+            namespaces,
+            interned_strings: Vec::new(),
+            scratch: Vec::new(),
+            remaining: if chunked { 0 } else { length },
+            chunked,
+            done: !chunked && length == 0,
+            phantom: PhantomData,
+        })
+    }
+
+    /// The number of items not yet read, or None if the file was written by a
+    /// [ChunkedVecWriter], in which case the number of items isn't known in advance.
+    pub fn remaining(&self) -> Option<u64> {
+        if self.chunked && !self.done {
+            None
+        } else {
+            Some(self.remaining)
+        }
+    }
+}
+
+impl<R: Read, T: WithSchema + Deserialize> Iterator for VecReader<R, T> {
+    type Item = Result<T, SavefileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut deserializer = Deserializer {
+            reader: &mut self.reader,
+            file_version: self.file_version,
+            memory_version: self.memory_version,
+            encoding: self.encoding,
+            ephemeral_state: HashMap::new(),
+            interned_strings: std::mem::take(&mut self.interned_strings),
+            scratch: std::mem::take(&mut self.scratch),
+            unchecked_utf8: false,
+            context: None,
+            progress: None,
+            memory_budget: None,
+        };
+        if self.remaining == 0 {
+            // Only chunked vectors get here, at the start of each chunk
+            match deserializer.read_length() {
+                Ok(0) => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(chunk_length) => self.remaining = chunk_length,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        let result = namespace::with_file_versions(Some(self.namespaces.clone()), || T::deserialize(&mut deserializer));
+        self.interned_strings = deserializer.interned_strings;
+        self.scratch = deserializer.scratch;
+        match result {
+            Ok(_) => {
+                self.remaining -= 1;
+                self.done = self.remaining == 0 && !self.chunked;
+            }
+            // Nothing sensible can be read after an error
+            Err(_) => {
+                self.done = true;
+                self.remaining = 0;
+            }
+        }
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.remaining() {
+            Some(remaining) => (0, Some(remaining.min(usize::MAX as u64) as usize)),
+            None => (0, None),
+        }
+    }
+}
+
+/// One entry of a map, as saved by the implementations of Serialize for maps
+struct KeyValuePair<K, V>(K, V);
+
+impl<K: WithSchema, V: WithSchema> WithSchema for KeyValuePair<K, V> {
+    fn schema(version: u32) -> Schema {
+        Schema::Struct(SchemaStruct {
+            dbg_name: "KeyValuePair".to_string(),
+            fields: vec![
+                Field {
+                    name: "key".to_string(),
+                    value: schema_of::<K>(version),
+                },
+                Field {
+                    name: "value".to_string(),
+                    value: schema_of::<V>(version),
+                },
+            ],
+        })
+    }
+}
+
+impl<K: Deserialize, V: Deserialize> Deserialize for KeyValuePair<K, V> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let key = K::deserialize(deserializer)?;
+        Ok(KeyValuePair(key, V::deserialize(deserializer)?))
+    }
+}
+
+/// Reads a file containing a map, such as a `HashMap<K, V>` or `BTreeMap<K, V>`, one entry at a
+/// time, without collecting the entries into a map. The same kinds of files can be read as
+/// by [VecReader].
+pub struct MapReader<R: Read, K, V> {
+    entries: VecReader<R, KeyValuePair<K, V>>,
+}
+
+impl<R: Read, K: WithSchema + Deserialize, V: WithSchema + Deserialize> MapReader<R, K, V> {
+    /// Read the header and schema of the file, and verify the schema.
+    /// The current version of K and V in memory must be `version`.
+    pub fn new(reader: R, version: u32) -> Result<MapReader<R, K, V>, SavefileError> {
+        Ok(MapReader {
+            entries: VecReader::new(reader, version)?,
+        })
+    }
+
+    /// The number of entries not yet read, see [VecReader::remaining]
+    pub fn remaining(&self) -> Option<u64> {
+        self.entries.remaining()
+    }
+}
+
+impl<R: Read, K: WithSchema + Deserialize, V: WithSchema + Deserialize> Iterator for MapReader<R, K, V> {
+    type Item = Result<(K, V), SavefileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries
+            .next()
+            .map(|entry| entry.map(|KeyValuePair(key, value)| (key, value)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+/// Read the entries of a file containing a map into an existing map, one entry at a time, such as
+/// to layer the data of a mod over the data of the base game. Entries whose key is not in `map`
+/// are inserted. For the other entries, `resolve` is called with the key, the value in the map and
+/// the loaded value, and decides what the value should be, or fails loading. Entries are merged
+/// as they are read, so if loading fails, `map` contains the entries read before the failure.
+/// The file is read by a [MapReader].
+///
+/// ```
+/// use savefile::prelude::*;
+/// use std::collections::HashMap;
+///
+/// let mut items: HashMap<String, u32> = HashMap::new();
+/// items.insert("sword".to_string(), 10);
+/// items.insert("shield".to_string(), 5);
+///
+/// let mut mod_items = HashMap::new();
+/// mod_items.insert("sword".to_string(), 25u32);
+/// mod_items.insert("bow".to_string(), 15u32);
+/// let mod_file = save_to_mem(0, &mod_items).unwrap();
+///
+/// // Let the mod replace the values of the base game
+/// load_into_map(&mut &mod_file[..], 0, &mut items, |_key, existing, loaded| {
+///     *existing = loaded;
+///     Ok(())
+/// })
+/// .unwrap();
+/// assert_eq!(items["sword"], 25);
+/// assert_eq!(items["shield"], 5);
+/// assert_eq!(items["bow"], 15);
+/// ```
+pub fn load_into_map<K, V, S>(
+    reader: &mut impl Read,
+    version: u32,
+    map: &mut HashMap<K, V, S>,
+    mut resolve: impl FnMut(&K, &mut V, V) -> Result<(), SavefileError>,
+) -> Result<(), SavefileError>
+where
+    K: WithSchema + Deserialize + Eq + Hash,
+    V: WithSchema + Deserialize,
+    S: BuildHasher,
+{
+    for entry in MapReader::<_, K, V>::new(reader, version)? {
+        let (key, value) = entry?;
+        match map.get_mut(&key) {
+            Some(existing) => resolve(&key, existing, value)?,
+            None => {
+                map.insert(key, value);
+            }
+        }
+    }
+    Ok(())
+}